@@ -0,0 +1,72 @@
+use serde::Serialize;
+use crate::log::{self, PathSegment};
+
+/// The version of the JSON documents `jimmy api` writes; it changes whenever a field is removed or
+/// changes meaning, but not when one is added
+pub const API_VERSION: u32 = 1;
+
+/// A warning about the configuration, or the reason it was refused
+#[derive(Debug, Serialize)]
+pub struct Problem
+{
+    pub message: String,
+    /// The property it's about, such as `partitions[1].raid_profile`, if it's known
+    pub path: Option<String>,
+}
+
+impl From<log::Logged> for Problem
+{
+    fn from(logged: log::Logged) -> Self
+    {
+        Self { path: path(&logged.context), message: logged.message }
+    }
+}
+
+/// Return the path to a property, written as in JavaScript, or `None` for the whole configuration
+fn path(segments: &[PathSegment]) -> Option<String>
+{
+    if segments.is_empty() {
+        return None;
+    }
+    let identifier = |key: &str| key.starts_with(|c: char| c.is_ascii_alphabetic() || c == '_')
+        && key.chars().all(|c| c.is_ascii_alphanumeric() || c == '_');
+    let mut path = String::new();
+    for segment in segments {
+        match segment {
+            PathSegment::Key(key) if identifier(key) => {
+                if !path.is_empty() {
+                    path.push('.');
+                }
+                path.push_str(key);
+            },
+            PathSegment::Key(key) => path += &format!("[{}]", serde_json::to_string(key).unwrap()),
+            PathSegment::Index(i) => path += &format!("[{}]", i),
+        }
+    }
+    Some(path)
+}
+
+/// Read a configuration given as JSON and return the document that describes it: whether it's
+/// `valid`, with its `warnings` and its `errors`, and with `plan`, the plan of the installation,
+/// or `null` if there's none. Nothing but the document is written to stdout
+pub fn respond(input: &str, plan: bool, allow_missing_env: bool) -> serde_json::Value
+{
+    log::collect_warnings();
+    let options = match serde_json::from_str::<serde_json::Value>(input) {
+        Ok(config) => crate::migrate_config(serde_yaml::to_value(&config).unwrap())
+            .and_then(|yaml| crate::options_from_config(yaml, allow_missing_env))
+            .map_err(|errors| errors.into_iter().map(Problem::from).collect()),
+        Err(e) => Err(vec![Problem { message: format!("the input isn't valid JSON: {}", e), path: None }]),
+    };
+    let warnings: Vec<Problem> = log::collected_warnings().into_iter().map(Problem::from).collect();
+    let mut response = serde_json::json!({
+        "api_version": API_VERSION,
+        "valid": options.is_ok(),
+        "warnings": warnings,
+        "errors": options.as_ref().err().map(Vec::as_slice).unwrap_or_default(),
+    });
+    if plan {
+        response["plan"] = options.map(|o| o.plan()).unwrap_or(serde_json::Value::Null);
+    }
+    response
+}
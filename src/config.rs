@@ -0,0 +1,65 @@
+use std::path::PathBuf;
+
+/// The parts of the environment that decide where jimmy looks for a configuration file, when it
+/// isn't given one
+#[derive(Debug, Clone)]
+pub struct SearchEnv
+{
+    /// The directory jimmy is ran from
+    pub cwd: PathBuf,
+    /// The value of `XDG_CONFIG_HOME`, if it's set
+    pub xdg_config_home: Option<String>,
+    /// The value of `HOME`, if it's set
+    pub home: Option<String>,
+}
+
+impl SearchEnv
+{
+    /// Take a snapshot of the environment jimmy is running in
+    pub fn current() -> Self
+    {
+        Self {
+            cwd: std::env::current_dir().unwrap_or_default(),
+            xdg_config_home: std::env::var("XDG_CONFIG_HOME").ok(),
+            home: std::env::var("HOME").ok(),
+        }
+    }
+}
+
+/// The configuration file that's used by every user of the machine, if they have none of their own
+const SYSTEM_CONFIG: &str = "/etc/jimmy/config.yaml";
+
+/// Return where the configuration file of the user goes: `$XDG_CONFIG_HOME/jimmy/config.yaml`, or
+/// `~/.config/jimmy/config.yaml` if `XDG_CONFIG_HOME` isn't set. As the XDG Base Directory
+/// Specification says, a relative `XDG_CONFIG_HOME` is ignored. Return `None` if neither variable
+/// gives a directory
+pub fn user_config_path(env: &SearchEnv) -> Option<PathBuf>
+{
+    let absolute = |dir: &&String| dir.starts_with('/');
+    let config_home = match env.xdg_config_home.as_ref().filter(absolute) {
+        Some(config_home) => PathBuf::from(config_home),
+        None => PathBuf::from(env.home.as_ref().filter(absolute)?).join(".config"),
+    };
+    Some(config_home.join("jimmy").join("config.yaml"))
+}
+
+/// Return the places where jimmy looks for a configuration file, in order: `jimmy.yaml` in the
+/// current directory, the configuration file of the user, and the one of the whole system
+pub fn search_paths(env: &SearchEnv) -> Vec<PathBuf>
+{
+    let mut paths = vec![env.cwd.join("jimmy.yaml")];
+    paths.extend(user_config_path(env));
+    paths.push(PathBuf::from(SYSTEM_CONFIG));
+    paths
+}
+
+/// Return the first of the search paths that's a file or, if there's none, all the paths that
+/// were searched
+pub fn find_config(env: &SearchEnv) -> Result<PathBuf, Vec<PathBuf>>
+{
+    let paths = search_paths(env);
+    match paths.iter().find(|p| p.is_file()) {
+        Some(path) => Ok(path.clone()),
+        None => Err(paths),
+    }
+}
@@ -1,4 +1,9 @@
-use serde::Deserialize;
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
+use serde::{Deserialize, Deserializer};
+use serde::de::{self, SeqAccess, Visitor};
+use regex::Regex;
+use crate::log::{self, Errors, PathSegment, error, refusal, refuse, warning};
 
 /// *Potentially* valid installation options. Everything is wrapped in `Option<T>` because serde
 /// would error if the property isn't found.
@@ -6,150 +11,3264 @@ use serde::Deserialize;
 pub struct ParsedInstallOptions
 {
     pub hostname: Option<String>,
-    pub region: Option<String>,
-    pub city: Option<String>,
+    pub timezone: Option<String>,
+    #[serde(default, deserialize_with = "deserialize_names")]
     pub locales: Option<Vec<String>>,
     pub kernel: Option<String>,
-    pub extra: Option<String>,
+    #[serde(default, deserialize_with = "deserialize_names")]
+    pub packages: Option<Vec<String>>,
     pub bootloader: Option<String>,
+    pub chroot_backend: Option<String>,
+    pub disk: Option<String>,
     pub partitions: Option<Vec<ParsedPartition>>,
     pub users: Option<Vec<ParsedUser>>,
+    pub activate_swap: Option<bool>,
+    pub firmware_packages: Option<StringOrList>,
+    pub timings: Option<bool>,
+    pub fstab_extra: Option<Vec<ParsedFstabEntry>>,
+    pub issue: Option<String>,
+    pub motd: Option<String>,
+    pub pretty_name: Option<String>,
+    pub parallel_format: Option<bool>,
+    pub default_mount_options: Option<BTreeMap<String, String>>,
+    pub strict: Option<bool>,
+    pub language: Option<String>,
+    pub retries: Option<u32>,
+    pub retry_delay: Option<u32>,
+    pub pacstrap_args: Option<Vec<String>>,
+    pub default_editor: Option<String>,
+    pub first_boot: Option<Vec<String>>,
+    pub mkinitcpio_hooks: Option<Vec<String>>,
+    pub disks: Option<BTreeMap<String, ParsedDisk>>,
+    pub discoverable_partitions: Option<bool>,
+    pub offline: Option<bool>,
+    pub initramfs_generator: Option<String>,
+    pub arch: Option<String>,
+    pub boot_entry_label: Option<String>,
+    pub keep_existing_entries: Option<bool>,
+    pub systemd_boot_update: Option<String>,
+    pub esp_sync_hook: Option<bool>,
+    pub maintenance: Option<ParsedMaintenance>,
+    pub hardening: Option<ParsedHardening>,
+    pub kernel_params: Option<StringOrList>,
+    pub grub: Option<ParsedGrub>,
+    pub systemd_boot: Option<ParsedBootEntry>,
+    pub efistub: Option<ParsedBootEntry>,
+    pub report: Option<String>,
+    pub directories: Option<Vec<ParsedDirectory>>,
+    pub snapshot_date: Option<String>,
+    pub package_pins: Option<BTreeMap<String, String>>,
+    pub cleanup: Option<String>,
+    pub root_password_policy: Option<String>,
+    pub root_password_hash: Option<String>,
+    pub root_password_attempts: Option<u32>,
+    pub skel: Option<String>,
+    pub keep_resolv_conf: Option<bool>,
+    pub min_iso_version: Option<String>,
+    pub keymap: Option<String>,
+    pub hardware_clock: Option<String>,
+    #[serde(default, deserialize_with = "deserialize_names")]
+    pub allow_lints: Option<Vec<String>>,
+    #[serde(default, deserialize_with = "deserialize_names")]
+    pub phases: Option<Vec<String>>,
+    pub power: Option<String>,
+    pub laptop: Option<BoolOrAuto>,
+    pub image: Option<ParsedDiskImage>,
+    pub previous_mounts: Option<String>,
+    pub default_target: Option<String>,
+    pub bootstrap: Option<String>,
+    pub tarball: Option<ParsedTarball>,
+    pub mount_root: Option<String>,
+}
+
+/// *Potentially* valid partition options. Everything is wrapped in `Option<T>` because serde would
+/// error if the property isn't found.
+#[derive(Deserialize, Debug, Clone)]
+pub struct ParsedPartition
+{
+    pub format: Option<String>,
+    pub disk: Option<String>,
+    pub size: Option<String>,
+    pub mount: Option<String>,
+    pub mkfs_args: Option<StringOrList>,
+    pub swap_priority: Option<i32>,
+    pub activate_swap: Option<bool>,
+    pub mount_options: Option<String>,
+    pub encryption: Option<ParsedEncryption>,
+    pub type_guid: Option<String>,
+    pub unmounted: Option<bool>,
+    pub esp: Option<bool>,
+    pub raid_profile: Option<String>,
+    pub members: Option<Vec<ParsedPartition>>,
+    pub post_format: Option<Vec<String>>,
+    pub reuse: Option<bool>,
+}
+
+/// *Potentially* valid encryption options of a partition. Everything is wrapped in `Option<T>`
+/// because serde would error if the property isn't found.
+#[derive(Deserialize, Debug, Clone)]
+pub struct ParsedEncryption
+{
+    pub tpm2: Option<bool>,
+    pub pcrs: Option<Vec<u32>>,
+    pub keyfile: Option<String>,
+}
+
+/// *Potentially* valid maintenance options of the installed system. Everything is wrapped in
+/// `Option<T>` because serde would error if the property isn't found.
+#[derive(Deserialize, Debug, Clone, Default)]
+pub struct ParsedMaintenance
+{
+    pub paccache: Option<bool>,
+    pub orphan_cleanup: Option<bool>,
+    pub mirrorlist_update: Option<String>,
+    pub mirrorlist_countries: Option<Vec<String>>,
+}
+
+/// *Potentially* valid hardening options of the installed system. Everything is wrapped in
+/// `Option<T>` because serde would error if the property isn't found.
+#[derive(Deserialize, Debug, Clone, Default)]
+pub struct ParsedHardening
+{
+    pub kernel_lockdown: Option<bool>,
+    pub disable_coredumps: Option<bool>,
+    pub restrict_dmesg: Option<bool>,
+    pub hidepid: Option<bool>,
+}
+
+/// *Potentially* valid options of GRUB. Everything is wrapped in `Option<T>` because serde would
+/// error if the property isn't found.
+#[derive(Deserialize, Debug, Clone, Default)]
+pub struct ParsedGrub
+{
+    pub cmdline_linux: Option<StringOrList>,
+    pub cmdline_linux_default: Option<StringOrList>,
+}
+
+/// *Potentially* valid options of the boot entry of systemd-boot or efistub. Everything is wrapped
+/// in `Option<T>` because serde would error if the property isn't found.
+#[derive(Deserialize, Debug, Clone, Default)]
+pub struct ParsedBootEntry
+{
+    pub extra_params: Option<StringOrList>,
+}
+
+/// *Potentially* valid information about a disk. Everything is wrapped in `Option<T>` because
+/// serde would error if the property isn't found.
+#[derive(Deserialize, Debug, Clone)]
+pub struct ParsedDisk
+{
+    pub size: Option<String>,
+    pub min_remaining: Option<String>,
+    pub pre_format: Option<Vec<String>>,
+    pub reserve_end: Option<String>,
+}
+
+/// *Potentially* valid options of the disk image files installed onto. Everything is wrapped in
+/// `Option<T>` because serde would error if the property isn't found.
+#[derive(Deserialize, Debug, Clone)]
+pub struct ParsedDiskImage
+{
+    pub size: Option<String>,
+    pub create: Option<bool>,
+    pub allow_real_disks: Option<bool>,
+}
+
+/// *Potentially* valid options of the bootstrap tarball the system is extracted from. Everything is
+/// wrapped in `Option<T>` because serde would error if the property isn't found.
+#[derive(Deserialize, Debug, Clone)]
+pub struct ParsedTarball
+{
+    pub source: Option<String>,
+    pub sha256: Option<String>,
+}
+
+/// A property that can be written either as a single string or as a list of strings
+#[derive(Deserialize, Debug, Clone)]
+#[serde(untagged)]
+pub enum StringOrList
+{
+    One(String),
+    Many(Vec<String>),
+}
+
+impl StringOrList
+{
+    /// Turn the property into a list of words; a single string is split on whitespace
+    pub fn into_words(self) -> Vec<String>
+    {
+        match self {
+            StringOrList::One(s) => s.split_whitespace().map(|w| w.to_string()).collect(),
+            StringOrList::Many(l) => l,
+        }
+    }
+}
+
+/// Split a string of names separated by commas or whitespace, e.g. `wheel,video audio`, leaving
+/// out the empty ones
+fn split_names(names: &str) -> impl Iterator<Item = String> + '_
+{
+    names.split(|c: char| c == ',' || c.is_whitespace())
+        .filter(|n| !n.is_empty())
+        .map(String::from)
+}
+
+/// Deserialize a property that lists names, such as groups or packages. It can be written as a
+/// sequence, as a string of names separated by commas, or as one separated by whitespace; the
+/// items of a sequence are split the same way, and empty names are left out. A property without
+/// a value is an empty list
+fn deserialize_names<'de, D>(deserializer: D) -> Result<Option<Vec<String>>, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    struct Names;
+
+    impl<'de> Visitor<'de> for Names
+    {
+        type Value = Vec<String>;
+
+        fn expecting(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result
+        {
+            f.write_str("a list of names, or a string of names separated by commas or spaces")
+        }
+
+        fn visit_str<E: de::Error>(self, names: &str) -> Result<Self::Value, E>
+        {
+            Ok(split_names(names).collect())
+        }
+
+        fn visit_unit<E: de::Error>(self) -> Result<Self::Value, E>
+        {
+            Ok(Vec::new())
+        }
+
+        fn visit_seq<A: SeqAccess<'de>>(self, mut seq: A) -> Result<Self::Value, A::Error>
+        {
+            let mut names = Vec::new();
+            while let Some(item) = seq.next_element::<String>()? {
+                names.extend(split_names(&item));
+            }
+            Ok(names)
+        }
+    }
+
+    deserializer.deserialize_any(Names).map(Some)
+}
+
+/// *Potentially* valid fstab entry. Everything is wrapped in `Option<T>` because serde would error
+/// if the property isn't found.
+#[derive(Deserialize, Debug, Clone)]
+pub struct ParsedFstabEntry
+{
+    pub fs: Option<String>,
+    pub dir: Option<String>,
+    #[serde(rename = "type")]
+    pub fstype: Option<String>,
+    pub options: Option<String>,
+    pub dump: Option<u32>,
+    pub pass: Option<u32>,
+    /// The whole line, as it would appear in the fstab file; used instead of the other fields
+    pub raw: Option<String>,
+}
+
+/// *Potentially* valid user. Everything is wrapped in `Option<T>` because serde would error if the
+/// property isn't found.
+#[derive(Deserialize, Debug, Clone)]
+pub struct ParsedUser
+{
+    pub name: Option<String>,
+    #[serde(default, deserialize_with = "deserialize_names")]
+    pub groups: Option<Vec<String>>,
+    pub shell: Option<String>,
+    pub locale: Option<String>,
+    pub home_encryption: Option<bool>,
+    pub skip_skel: Option<bool>,
+    pub linger: Option<bool>,
+    #[serde(default, deserialize_with = "deserialize_names")]
+    pub user_services: Option<Vec<String>>,
+}
+
+/// *Potentially* valid directory to create on the installed system. Everything is wrapped in
+/// `Option<T>` because serde would error if the property isn't found.
+#[derive(Deserialize, Debug, Clone)]
+pub struct ParsedDirectory
+{
+    pub path: Option<String>,
+    pub mode: Option<Mode>,
+    pub owner: Option<String>,
+    pub group: Option<String>,
+}
+
+/// A file mode, which can be written as a string (`"0750"`) or as a number (`750`); either way,
+/// its digits are read as octal ones. An unquoted `0o750` is already turned into another number
+/// by the YAML parser, so it has to be quoted
+#[derive(Deserialize, Debug, Clone)]
+#[serde(untagged)]
+pub enum Mode
+{
+    Text(String),
+    Number(u64),
+}
+
+/// A property that's either `true`, `false`, or the word `auto` for jimmy to work it out
+#[derive(Deserialize, Debug, Clone)]
+#[serde(untagged)]
+pub enum BoolOrAuto
+{
+    Flag(bool),
+    Word(String),
+}
+
+/// Only the Latest or the LTS kernel can be installed
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Kernel {
+    Latest,
+    Lts,
+}
+
+impl Kernel
+{
+    /// Every kernel that can be installed
+    pub const ALL: &'static [Kernel] = &[Kernel::Latest, Kernel::Lts];
+
+    /// Return the name used for the `kernel` property
+    pub fn name(&self) -> &'static str
+    {
+        match self {
+            Kernel::Latest => "latest",
+            Kernel::Lts => "lts",
+        }
+    }
+
+    /// Return the name of the package the kernel is installed from, on the given architecture
+    pub fn package(&self, arch: Architecture) -> &'static str
+    {
+        match (self, arch) {
+            (Kernel::Latest, Architecture::X86_64) => "linux",
+            (Kernel::Lts, Architecture::X86_64) => "linux-lts",
+            // Arch Linux ARM only packages the mainline kernel
+            (_, Architecture::Aarch64) => "linux-aarch64",
+        }
+    }
+
+    /// Return the name of the kernel image in /boot, on the given architecture
+    pub fn image(&self, arch: Architecture) -> &'static str
+    {
+        match (self, arch) {
+            (Kernel::Latest, Architecture::X86_64) => "vmlinuz-linux",
+            (Kernel::Lts, Architecture::X86_64) => "vmlinuz-linux-lts",
+            (_, Architecture::Aarch64) => "Image",
+        }
+    }
+
+    /// Return the name of the initramfs image in /boot
+    pub fn initramfs(&self) -> &'static str
+    {
+        match self {
+            Kernel::Latest => "initramfs-linux.img",
+            Kernel::Lts => "initramfs-linux-lts.img",
+        }
+    }
+}
+
+/// The processor architectures a system can be installed for
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Architecture {
+    X86_64,
+    Aarch64,
+}
+
+impl Architecture
+{
+    /// Every architecture a system can be installed for
+    pub const ALL: &'static [Architecture] = &[Architecture::X86_64, Architecture::Aarch64];
+
+    /// Return the name used for the `arch` property, which is also what `uname -m` prints
+    pub fn name(&self) -> &'static str
+    {
+        match self {
+            Architecture::X86_64 => "x86_64",
+            Architecture::Aarch64 => "aarch64",
+        }
+    }
+
+    /// Return the platform `grub-install` is given as its target
+    pub fn grub_target(&self) -> &'static str
+    {
+        match self {
+            Architecture::X86_64 => "x86_64-efi",
+            Architecture::Aarch64 => "arm64-efi",
+        }
+    }
+
+    /// Return what the names of the EFI programs built for the architecture end with, such as
+    /// `grubx64.efi`
+    pub fn efi_suffix(&self) -> &'static str
+    {
+        match self {
+            Architecture::X86_64 => "x64",
+            Architecture::Aarch64 => "aa64",
+        }
+    }
+
+    /// Return where the bootstrap tarball of the latest release is downloaded from, for
+    /// `bootstrap: tarball`; Arch Linux ARM has no bootstrap tarball, but its root filesystem
+    /// tarball does the same
+    pub fn bootstrap_tarball(&self) -> &'static str
+    {
+        match self {
+            Architecture::X86_64 => "https://geo.mirror.pkgbuild.com/iso/latest/archlinux-bootstrap-x86_64.tar.zst",
+            Architecture::Aarch64 => "https://os.archlinuxarm.org/os/ArchLinuxARM-aarch64-latest.tar.gz",
+        }
+    }
+
+    /// Return the name, in `PARTITION_TYPES`, of the type of the root partition
+    pub fn root_partition_type(&self) -> &'static str
+    {
+        match self {
+            Architecture::X86_64 => "linux-root-x86-64",
+            Architecture::Aarch64 => "linux-root-arm64",
+        }
+    }
+}
+
+/// The bootloaders that jimmy knows how to set up
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Bootloader {
+    Grub,
+    Efistub,
+    SystemdBoot,
+}
+
+impl Bootloader
+{
+    /// Every bootloader that can be set up
+    pub const ALL: &'static [Bootloader] = &[Bootloader::Grub, Bootloader::Efistub, Bootloader::SystemdBoot];
+
+    /// Return the name used for the `bootloader` property
+    pub fn name(&self) -> &'static str
+    {
+        match self {
+            Bootloader::Grub => "grub",
+            Bootloader::Efistub => "efistub",
+            Bootloader::SystemdBoot => "systemd-boot",
+        }
+    }
+}
+
+/// The partitions that validation found the bootloader needs, by their index in the list of
+/// partitions
+#[derive(Debug, Clone, Copy)]
+pub struct BootPartitions
+{
+    pub root: usize,
+    pub esp: usize,
+}
+
+/// The languages that the status messages of the generated scripts can be printed in
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Language {
+    English,
+    Spanish,
+    German,
+}
+
+impl Language
+{
+    /// Every language there are status messages for
+    pub const ALL: &'static [Language] = &[Language::English, Language::Spanish, Language::German];
+
+    /// Return the code used for the `language` property
+    pub fn code(&self) -> &'static str
+    {
+        match self {
+            Language::English => "en",
+            Language::Spanish => "es",
+            Language::German => "de",
+        }
+    }
+}
+
+/// The version of the configuration file format understood by this version of jimmy
+pub const CONFIG_VERSION: u32 = 2;
+
+/// Every way jimmy knows of running the configuration script inside the target system
+pub const CHROOT_BACKENDS: &[&str] = &["arch-chroot", "nspawn"];
+
+/// Every program that jimmy knows how to create the initramfs with
+pub const INITRAMFS_GENERATORS: &[&str] = &["mkinitcpio", "dracut"];
+
+/// How often the mirrorlist of the installed system can be updated with reflector
+pub const MIRRORLIST_UPDATES: &[&str] = &["daily", "weekly", "monthly"];
+
+/// Which of the files jimmy leaves on the installed system are kept once it's installed: all of
+/// them, only the report, or none
+pub const CLEANUP_POLICIES: &[&str] = &["keep-everything", "keep-report-only", "remove-all"];
+
+/// What the hardware clock keeps: UTC, as Linux expects, or local time, as Windows does
+pub const HARDWARE_CLOCKS: &[&str] = &["utc", "localtime"];
+
+/// The daemons that manage the power settings of a laptop, or `none` for neither; they change the
+/// same settings, so only one of them is installed
+pub const POWER_DAEMONS: &[&str] = &["tlp", "power-profiles-daemon", "none"];
+
+/// What the script does when it finds filesystems mounted under `mount_root` before partitioning, as
+/// an earlier attempt that failed leaves them: stop, telling how to unmount them, or unmount
+/// them and go on
+pub const PREVIOUS_MOUNTS: &[&str] = &["abort", "unmount"];
+
+/// How the base system is put onto `mount_root`: installed with pacstrap, or extracted from a bootstrap
+/// tarball, in which pacman then installs the packages
+pub const BOOTSTRAPS: &[&str] = &["pacstrap", "tarball"];
+
+/// The systemd targets the installed system can boot into by default: with a display manager,
+/// on a console, or with only what it takes to repair it
+pub const DEFAULT_TARGETS: &[&str] = &["graphical", "multi-user", "rescue"];
+
+/// Packages and groups that install a desktop environment, or the core of one
+pub const DESKTOPS: &[&str] = &[
+    "plasma", "plasma-meta", "plasma-desktop", "gnome", "gnome-shell", "xfce4", "cinnamon", "mate",
+    "lxqt", "budgie-desktop", "deepin", "enlightenment",
+];
+
+/// Packages that install a display manager
+pub const DISPLAY_MANAGERS: &[&str] = &["sddm", "gdm", "lightdm", "lxdm", "ly", "greetd"];
+
+/// How systemd-boot is updated on the EFI system partition after systemd is upgraded: by
+/// `systemd-boot-update.service` on the next boot, or by a pacman hook right away
+pub const SYSTEMD_BOOT_UPDATES: &[&str] = &["service", "hook"];
+
+/// How the password of root is set: asked for until it's typed right, asked for a few times before
+/// root is locked, locked straight away, or set to a hash from the configuration file
+pub const ROOT_PASSWORD_POLICIES: &[&str] = &["prompt", "prompt-with-fallback", "locked", "hash"];
+
+/// The phases of the installation, in the order they're ran in, each with the phase it can't be
+/// ran without: the disks are partitioned, formatted and mounted, the base system is installed,
+/// its filesystem table is written, it's configured inside arch-chroot, and it's unmounted
+pub const PHASES: &[(&str, Option<&str>)] = &[
+    ("disks", None),
+    ("pacstrap", Some("disks")),
+    ("fstab", Some("pacstrap")),
+    ("chroot", Some("fstab")),
+    ("unmount", Some("disks")),
+];
+
+/// Every program that jimmy knows how to use for managing the network on the target system
+pub const NETWORK_BACKENDS: &[&str] = &["networkmanager"];
+
+/// Which firmware packages get installed along with the kernel
+#[derive(Debug)]
+pub enum Firmware {
+    /// Just `linux-firmware`
+    Default,
+    /// No firmware at all, e.g. for virtual machines
+    None,
+    /// The given packages, instead of `linux-firmware`
+    Packages(Vec<String>),
+}
+
+impl From<Option<StringOrList>> for Firmware
+{
+    fn from(raw: Option<StringOrList>) -> Self
+    {
+        let packages = raw.map(StringOrList::into_words).unwrap_or_default();
+        match packages.iter().map(|p| p.as_str()).collect::<Vec<&str>>()[..] {
+            [] | ["default"] => Firmware::Default,
+            ["none"] => Firmware::None,
+            _ => Firmware::Packages(packages),
+        }
+    }
+}
+
+/// Struct that contains the minimum needed to create a functioning Arch installation
+#[derive(Debug)]
+pub struct InstallOptions
+{
+    pub hostname: String,
+    /// The path of the timezone's file under /usr/share/zoneinfo, e.g. `Europe/London`
+    pub timezone: String,
+    pub locales: Vec<String>,
+    pub kernel: Kernel,
+    /// The processor architecture of the target system
+    pub arch: Architecture,
+    pub firmware: Firmware,
+    pub extra: String,
+    pub bootloader: Bootloader,
+    /// Where the root and the EFI system partitions are in `partitions`
+    pub boot: BootPartitions,
+    /// How the configuration script is ran inside the target system: `arch-chroot` or `nspawn`
+    pub chroot_backend: String,
+    pub partitions: Vec<Partition>,
+    pub users: Vec<User>,
+    /// Entries added to the fstab file besides those created by `genfstab`
+    pub fstab_extra: Vec<FstabEntry>,
+    /// Contents of /etc/issue, printed before the login prompt
+    pub issue: Option<String>,
+    /// Contents of /etc/motd, printed after logging in
+    pub motd: Option<String>,
+    /// The name of the operating system in /etc/os-release
+    pub pretty_name: Option<String>,
+    /// Whether partitions on different disks are formatted at the same time
+    pub parallel_format: bool,
+    /// Whether the script reports how long each step of the installation took
+    pub timings: bool,
+    /// The language of the status messages printed by the script
+    pub language: Language,
+    /// How many times a failed `pacstrap` is retried
+    pub retries: u32,
+    /// How many seconds to wait before retrying a failed `pacstrap`
+    pub retry_delay: u32,
+    /// The arguments given to pacman through `pacstrap`, before the packages
+    pub pacstrap_args: Vec<String>,
+    /// The program set as `EDITOR` in /etc/environment
+    pub default_editor: Option<String>,
+    /// Commands that run the first time the installed system boots, once the network is up
+    pub first_boot: Vec<String>,
+    /// The hooks of the initramfs, if the default ones of `mkinitcpio` aren't enough
+    pub mkinitcpio_hooks: Option<Vec<String>>,
+    /// The hash of the configuration file the options were read from, recorded in the script
+    pub config_hash: String,
+    /// The disks whose size was declared, to check that their partitions fit on them
+    pub disks: BTreeMap<String, Disk>,
+    /// Commands ran before each disk is partitioned, with `$DEVICE` set to the file of the disk
+    pub pre_format: BTreeMap<String, Vec<String>>,
+    /// The space left free at the end of the disks that have it, after the partition that takes
+    /// the rest of the disk
+    pub reserve_end: BTreeMap<String, Size>,
+    /// Whether the live system has no network, so missing programs can't be installed on it
+    pub offline: bool,
+    /// The program that creates the initramfs
+    pub initramfs_generator: String,
+    /// The label of the boot entry created for efistub, and of those of the secondary EFI system
+    /// partitions
+    pub boot_entry_label: String,
+    /// Whether the boot entries with the same label as the new one are kept, instead of deleted
+    pub keep_existing_entries: bool,
+    /// How systemd-boot is updated on the ESP when systemd is upgraded
+    pub systemd_boot_update: String,
+    /// Whether a pacman hook copies the EFI system partition onto the secondary ones after every
+    /// transaction, and not only during the installation
+    pub esp_sync_hook: bool,
+    /// What the installed system does by itself to keep in shape
+    pub maintenance: Maintenance,
+    /// The basic hardening measures the installed system is set up with
+    pub hardening: Hardening,
+    /// The parameters of the kernel command line given in the configuration file
+    pub kernel_params: KernelParams,
+    /// Where the JSON report of the installation is written on the installed system, if anywhere
+    pub report: Option<String>,
+    /// The directories created on the installed system, after the users
+    pub directories: Vec<Directory>,
+    /// The day of the Arch Linux Archive the packages are installed from, e.g. `2024-11-01`,
+    /// instead of the mirrors of the live system
+    pub snapshot_date: Option<String>,
+    /// The packages installed at a given version from the Arch Linux Archive, and kept at it
+    pub package_pins: BTreeMap<String, String>,
+    /// Which of the files jimmy leaves on the installed system are kept once the installation
+    /// succeeded; one of `CLEANUP_POLICIES`
+    pub cleanup: String,
+    /// How the password of root is set; one of `ROOT_PASSWORD_POLICIES`
+    pub root_password_policy: String,
+    /// The hashed password of root, with `root_password_policy: hash`
+    pub root_password_hash: Option<String>,
+    /// How many times the password of root is asked for before root is locked, with
+    /// `root_password_policy: prompt-with-fallback`
+    pub root_password_attempts: u32,
+    /// The directory of the live system whose files are copied into /etc/skel on the installed
+    /// system, before the users are created
+    pub skel: Option<String>,
+    /// Whether a working resolv.conf of the live system is put on the installed system while the
+    /// arch-chroot script runs; by default, only when something in it needs the network
+    pub keep_resolv_conf: Option<bool>,
+    /// The oldest release of the live ISO the script may run on, e.g. `2024.01.01`, on top of the
+    /// ones the features of the configuration need
+    pub min_iso_version: Option<String>,
+    /// The keymap of the console of the installed system, e.g. `de-latin1`, also loaded on the
+    /// live system before anything is typed; the default US one if `None`
+    pub keymap: Option<String>,
+    /// Whether the hardware clock keeps UTC or local time; one of `HARDWARE_CLOCKS`
+    pub hardware_clock: String,
+    /// The lints of `jimmy validate --lint` that aren't ran on this configuration
+    pub allow_lints: Vec<String>,
+    /// The phases of the installation the script carries out, in the order of `PHASES`
+    pub phases: Vec<String>,
+    /// The daemon that manages the power settings on laptops; one of `POWER_DAEMONS`
+    pub power: String,
+    /// Whether the machine is a laptop, so that the power daemon is installed; if `None`, the
+    /// script finds out from the chassis type the firmware reports
+    pub laptop: Option<bool>,
+    /// How the disks that are files are made into devices, if there are any
+    pub image: Option<DiskImage>,
+    /// What the script does with the filesystems left mounted under `mount_root`; one of
+    /// `PREVIOUS_MOUNTS`
+    pub previous_mounts: String,
+    /// The systemd target the installed system boots into, without its `.target`; one of
+    /// `DEFAULT_TARGETS`. If `None`, it's graphical when a desktop or a display manager is
+    /// installed, and multi-user otherwise
+    pub default_target: Option<String>,
+    /// One of `BOOTSTRAPS`
+    pub bootstrap: String,
+    /// The tarball the base system is extracted from, with `bootstrap: tarball` only
+    pub tarball: Option<Tarball>,
+    /// The directory of the live system the new system is mounted on while it's installed,
+    /// without a trailing slash; `/mnt` unless it's set
+    pub mount_root: String,
+}
+
+/// The bootstrap tarball the base system is extracted from, which is downloaded if it's given by
+/// a URL
+#[derive(Debug, Clone)]
+pub struct Tarball
+{
+    /// A URL with `http://` or `https://`, or the path to a file on the live system
+    pub source: String,
+    /// The SHA-256 checksum the tarball is checked against; without it, its signature is
+    /// checked, from the file next to it with `.sig` appended to its name
+    pub sha256: Option<String>,
+}
+
+impl Tarball
+{
+    /// Return whether the tarball is downloaded
+    pub fn is_url(&self) -> bool
+    {
+        self.source.starts_with("http://") || self.source.starts_with("https://")
+    }
+
+    /// Return how many leading directories are stripped from the paths of the tarball: the
+    /// bootstrap tarballs of Arch Linux keep everything in `root.<arch>`, while those of Arch
+    /// Linux ARM are the root filesystem itself
+    pub fn strip_components(&self) -> u32
+    {
+        let name = self.source.rsplit('/').next().unwrap_or_default();
+        match name.starts_with("archlinux-bootstrap-") {
+            true => 1,
+            false => 0,
+        }
+    }
+}
+
+/// How the disks that are files, rather than devices, are installed onto: the script attaches each
+/// of them to a loop device, which stands for it until the end of the installation
+#[derive(Debug, Clone)]
+pub struct DiskImage
+{
+    /// The size of the files, which their partitions are checked against
+    pub size: Option<Size>,
+    /// Whether the script creates the files, as sparse files of `size`, instead of using
+    /// existing ones
+    pub create: bool,
+    /// Whether real disks may be partitioned along with the files
+    pub allow_real_disks: bool,
+}
+
+/// The smallest EFI system partition that's guaranteed to be formatted as FAT32 on 4K-sector
+/// drives, as recommended by the Arch wiki
+const ESP_MIN_SIZE_MIB: u64 = 260;
+
+/// The units a size can be given in, as they're written after the number, along with how many
+/// bytes they stand for. Units without a `B` are binary, like `fdisk` takes them; units with a `B`
+/// are decimal, like disks are sold in. The first spelling of every unit is the one sizes are
+/// written back with
+const SIZE_UNITS: &[(&[&str], u64)] = &[
+    (&["T", "TiB"], 1 << 40),
+    (&["TB"], 1_000_000_000_000),
+    (&["G", "GiB"], 1 << 30),
+    (&["GB"], 1_000_000_000),
+    (&["M", "MiB"], 1 << 20),
+    (&["MB"], 1_000_000),
+    (&["K", "KiB"], 1 << 10),
+    (&["KB"], 1_000),
+];
+
+/// A size, as it's given to partitions and disks in the configuration file (e.g. `500M`, `1GiB`,
+/// `2GB`)
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub struct Size
+{
+    pub bytes: u64,
+}
+
+impl Size
+{
+    /// Read a size written as a number followed by one of the `SIZE_UNITS`. Return `None` if the
+    /// size can't be understood, or doesn't fit in 64 bits
+    pub fn parse(size: &str) -> Option<Size>
+    {
+        let split = size.find(|c: char| !c.is_ascii_digit()).unwrap_or(size.len());
+        let (number, unit) = size.split_at(split);
+        let number: u64 = number.parse().ok()?;
+        let (_, bytes) = SIZE_UNITS.iter().find(|(names, _)| names.contains(&unit))?;
+        Some(Size { bytes: number.checked_mul(*bytes)? })
+    }
+
+    /// The size in MiB, rounding down
+    pub fn mib(&self) -> u64
+    {
+        self.bytes >> 20
+    }
+}
+
+impl std::fmt::Display for Size
+{
+    /// Write the size with the largest unit it's a whole number of, which `fdisk` understands as
+    /// well, so that reading it back gives the same size
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result
+    {
+        // every size that was read is a whole number of one of the units, but 0 is of all of them
+        let (names, bytes) = SIZE_UNITS.iter()
+            .find(|(_, bytes)| self.bytes.is_multiple_of(*bytes))
+            .unwrap_or(&SIZE_UNITS[SIZE_UNITS.len() - 1]);
+        write!(f, "{}{}", self.bytes / bytes, names[0])
+    }
+}
+
+/// Read the `size` of a partition. Refuse if it can't be understood
+fn parse_partition_size(size: &str) -> Result<Size, Errors>
+{
+    Size::parse(size).ok_or_else(|| refusal!("invalid size: \"{}\" (expected a number followed by one of: {})",
+        size, SIZE_UNITS.iter().flat_map(|(names, _)| names.iter().copied()).collect::<Vec<&str>>().join(", ")))
+}
+
+/// Format a size in MiB the way it'd be written in the configuration file
+pub fn format_mib(mib: u64) -> String
+{
+    if mib >= 1 << 10 && mib.is_multiple_of(1 << 10) {
+        format!("{}G", mib >> 10)
+    } else if mib >= 1 << 10 {
+        format!("{:.1}G", mib as f64 / (1 << 10) as f64)
+    } else {
+        format!("{}M", mib)
+    }
+}
+
+/// A disk whose size was declared in the configuration file
+#[derive(Debug)]
+pub struct Disk
+{
+    /// The size of the disk, as it was declared
+    pub size: String,
+    /// The MiB that the partitions can use
+    pub usable_mib: u64,
+    /// The MiB left for the partition without a `size`, if the disk has one
+    pub remaining_mib: Option<u64>,
+    /// The least space the partition without a `size` is expected to get, as it was declared or
+    /// defaulted to
+    pub min_remaining: String,
+    /// The space left free at the end of the disk, if any
+    pub reserve_end: Option<Size>,
+}
+
+/// The MiB that GPT and `fdisk` keep for themselves on every disk: the first partition starts
+/// after the first MiB, which holds the partition table, and the backup of the table at the end
+/// of the disk takes up the last, partial MiB
+pub const GPT_OVERHEAD_MIB: u64 = 2;
+
+/// The size under which the partition that takes the rest of a disk is probably too small, unless
+/// the disk's `min_remaining` says otherwise
+const DEFAULT_MIN_REMAINING: &str = "8G";
+
+/// The directory the new system is mounted on while it's installed, unless `mount_root` is set
+const DEFAULT_MOUNT_ROOT: &str = "/mnt";
+
+/// The directories of the live system whose filesystems are the kernel's, which nothing can be
+/// mounted under
+const VIRTUAL_FILESYSTEMS: &[&str] = &["/dev", "/proc", "/sys"];
+
+/// Return the `mount_root` without its trailing slashes; refuse if it's not an absolute path that
+/// the script can create and mount the new system on. Its name goes unquoted into the commands,
+/// so it's kept to characters that the shell doesn't treat specially
+fn parse_mount_root(raw: &str) -> Result<String, Errors>
+{
+    let root = raw.trim_end_matches('/');
+    let valid = Regex::new(r"^(/[A-Za-z0-9._-]+)+$").unwrap();
+    if !valid.is_match(root) || root.split('/').any(|c| c == "." || c == "..") {
+        refuse!("invalid mount_root: \"{}\" (expected an absolute path other than /, of letters, digits, '.', '_' and '-', such as /mnt or /target)", raw)
+    }
+    if let Some(dir) = VIRTUAL_FILESYSTEMS.iter().find(|d| root == **d || root.starts_with(&format!("{}/", d))) {
+        refuse!("invalid mount_root: \"{}\" (expected a directory outside of {}, whose filesystems are the kernel's)", raw, dir)
+    }
+    Ok(root.to_string())
+}
+
+/// Check that the partitions on a disk whose size was declared fit on it, along with the space
+/// reserved at its end, and work out how much space is left for the partition without a `size`.
+/// Refuse if they don't fit; warn if what's left is less than the disk's `min_remaining`
+fn plan_disk(name: &str, raw: ParsedDisk, partitions: &[Partition], reserve_end: Option<Size>) -> Result<Disk, Errors>
+{
+    let size = raw.size.ok_or_else(|| refusal!("disk {} is declared without a `size`", name))?;
+    let bytes = Size::parse(&size).map(|s| s.bytes)
+        .ok_or_else(|| refusal!("invalid size for disk {}: \"{}\" (expected e.g. '500G' or '512GB')", name, size))?;
+    let usable_mib = (bytes >> 20).checked_sub(GPT_OVERHEAD_MIB)
+        .ok_or_else(|| refusal!("disk {} is too small to hold a partition table: \"{}\"", name, size))?;
+    let min_remaining = raw.min_remaining.unwrap_or_else(|| DEFAULT_MIN_REMAINING.to_string());
+    let min_remaining_mib = Size::parse(&min_remaining).map(|s| s.mib())
+        .ok_or_else(|| refusal!("invalid min_remaining for disk {}: \"{}\"", name, min_remaining))?;
+
+    // the unused pass reports the disks without partitions, once everything is planned
+    let on_disk: Vec<&Partition> = partitions.iter().filter(|p| p.disk == name).collect();
+    let unsized_partitions: Vec<&&Partition> = on_disk.iter().filter(|p| p.size.is_none()).collect();
+    if unsized_partitions.len() > 1 {
+        refuse!("disk {} has {} partitions without a `size`, but only one of them can take the rest of the disk",
+            name, unsized_partitions.len());
+    }
+    let mut used_mib = 0;
+    for size in on_disk.iter().filter_map(|p| p.size) {
+        // every partition starts on a MiB boundary, so the space after a size that isn't a whole
+        // number of MiB can't be used
+        used_mib += (size.bytes + (1 << 20) - 1) >> 20;
+    }
+    if used_mib > usable_mib {
+        refuse!("the partitions on disk {} need {}, but only {} of its {} can be partitioned; make them smaller",
+            name, format_mib(used_mib), format_mib(usable_mib), size);
+    }
+    let reserved_mib = reserve_end.map_or(0, |r| (r.bytes + (1 << 20) - 1) >> 20);
+    if used_mib + reserved_mib > usable_mib {
+        refuse!("the partitions on disk {} need {}, and {} is reserved at its end, but only {} of its {} can be partitioned; make them smaller, or reserve less",
+            name, format_mib(used_mib), reserve_end.unwrap(), format_mib(usable_mib), size);
+    }
+    let remaining_mib = match unsized_partitions.first() {
+        None => None,
+        Some(p) => {
+            let remaining_mib = usable_mib - used_mib - reserved_mib;
+            let what = if p.mount.is_empty() { format!("the {} partition", p.format) } else { format!("the partition mounted at {}", p.mount) };
+            if remaining_mib == 0 {
+                refuse!("{} takes the rest of disk {}, but there's no space left on it", what, name);
+            } else if remaining_mib < min_remaining_mib {
+                warning!("{} takes the rest of disk {}, which is only {} (less than {})",
+                    what, name, format_mib(remaining_mib), min_remaining);
+            }
+            Some(remaining_mib)
+        },
+    };
+    Ok(Disk { size, usable_mib, remaining_mib, min_remaining, reserve_end })
+}
+
+/// Refuse if the partitions don't meet the needs of the bootloader: all of them need a root
+/// partition and an EFI system partition that firmware can read, mounted at `/boot` or `/efi`.
+/// systemd-boot also needs the kernels on such a partition. Return where the root and the EFI
+/// system partitions are
+fn validate_bootloader(bootloader: Bootloader, partitions: &[Partition]) -> Result<BootPartitions, Errors>
+{
+    let root = partitions.iter().position(|p| p.mount == "/")
+        .ok_or_else(|| refusal!("{} requires a root partition; add a partition with `mount: /`", bootloader.name()))?;
+    let esp_index = esp_index(partitions)
+        .ok_or_else(|| refusal!(
+            "{} requires an EFI system partition; add a `fat32` partition with `mount: /boot`",
+            bootloader.name()
+        ))?;
+    let esp = &partitions[esp_index];
+    if esp.format != "fat32" {
+        refuse!("{} requires the partition mounted at {} to be an EFI system partition; change its `format` from '{}' to 'fat32'",
+            bootloader.name(), esp.mount, esp.format);
+    }
+    if let Some(size) = esp.size {
+        if size.mib() < ESP_MIN_SIZE_MIB {
+            refuse!("the EFI system partition mounted at {} is too small; change its `size` from '{}' to at least '{}M'",
+                esp.mount, size, ESP_MIN_SIZE_MIB);
+        }
+    }
+    if bootloader == Bootloader::SystemdBoot && esp.mount == "/efi" {
+        // systemd-boot can only read the kernels if they're on a partition the firmware can read
+        match find_xbootldr(bootloader, partitions) {
+            None => refuse!("systemd-boot with the EFI system partition mounted at /efi requires an XBOOTLDR partition for the kernels; add a `fat32` partition with `mount: /boot`"),
+            Some(xbootldr) if xbootldr.format != "fat32" =>
+                refuse!("systemd-boot requires the XBOOTLDR partition mounted at /boot to be readable by the firmware; change its `format` from '{}' to 'fat32'",
+                    xbootldr.format),
+            _ => (),
+        }
+    }
+    Ok(BootPartitions { root, esp: esp_index })
+}
+
+/// The packages that only exist for x86-64: the microcode updates of its processors
+const X86_64_ONLY_PACKAGES: &[&str] = &["intel-ucode", "amd-ucode"];
+
+/// Refuse if something in the configuration isn't supported on the architecture. Everything is
+/// supported on x86-64; on aarch64, only the mainline kernel is packaged, the kernel images are
+/// installed straight into /boot and there's no microcode
+fn validate_arch(arch: Architecture, kernel: Kernel, bootloader: Bootloader, initramfs_generator: &str, extra: &str) -> Result<(), Errors>
+{
+    if arch == Architecture::X86_64 {
+        return Ok(());
+    }
+    if kernel != Kernel::Latest {
+        refuse!("`kernel: {}` isn't supported on {}, where only the mainline kernel is packaged; use `kernel: latest`",
+            kernel.name(), arch.name());
+    }
+    if bootloader == Bootloader::SystemdBoot {
+        refuse!("`bootloader: systemd-boot` isn't supported on {} yet; use grub or efistub", arch.name());
+    }
+    // the pacman hooks that call dracut find the kernels under /usr/lib/modules, where those of
+    // Arch Linux ARM aren't
+    if initramfs_generator == "dracut" {
+        refuse!("`initramfs_generator: dracut` isn't supported on {} yet; use mkinitcpio", arch.name());
+    }
+    if let Some(package) = extra.split_whitespace().find(|p| X86_64_ONLY_PACKAGES.contains(p)) {
+        refuse!("the package '{}' in `packages` only exists for x86_64; remove it, since there's no microcode on {}",
+            package, arch.name());
+    }
+    Ok(())
+}
+
+/// How much space, in MiB, the files that a package puts in /boot take: the kernel, along with
+/// its initramfs and fallback initramfs, or the microcode images
+const BOOT_SPACE_MIB: &[(&str, u64)] = &[
+    ("linux", 150),
+    ("linux-lts", 130),
+    ("linux-aarch64", 120),
+    ("intel-ucode", 10),
+    ("amd-ucode", 10),
+];
+
+/// Check that the partition that's going to hold the kernels has room for them. Refuse if it's
+/// smaller than the estimate, and warn if it leaves less than half of it free, since `mkinitcpio`
+/// needs room while it rebuilds the images. Refuse if the kernels end up on a partition the
+/// bootloader can't read
+fn validate_boot_space(bootloader: Bootloader, kernel: &str, extra: &str, partitions: &[Partition]) -> Result<(), Errors>
+{
+    let boot = match partitions.iter().find(|p| p.mount == "/boot") {
+        Some(boot) => boot,
+        None => {
+            // the kernels are on the root partition, which only GRUB can read
+            if bootloader == Bootloader::Efistub {
+                refuse!("efistub loads the kernels straight from the EFI system partition, but they'd be on the root partition; mount the EFI system partition at /boot instead");
+            }
+            return Ok(());
+        },
+    };
+    let required: u64 = BOOT_SPACE_MIB.iter()
+        .filter(|(package, _)| *package == kernel || extra.split_whitespace().any(|p| p == *package))
+        .map(|(_, mib)| mib)
+        .sum();
+    let size = match boot.size {
+        Some(size) => size,
+        // it takes the rest of the disk
+        None => return Ok(()),
+    };
+    if size.mib() < required {
+        refuse!("the partition mounted at /boot is too small for the kernel (about {}M with its initramfs images and microcode); change its `size` from '{}' to at least '{}M'",
+            required, size, required);
+    } else if size.mib() < required + required / 2 {
+        warning!("the partition mounted at /boot ({}) has little room to spare for the kernel (about {}M); rebuilding the initramfs may run out of space",
+            size, required);
+    }
+    Ok(())
+}
+
+/// What has to be read from a filesystem for the system to boot
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum BootRead
+{
+    /// The kernels, by the bootloader, from the partition mounted at `/boot`, or from the root
+    /// partition if there's none
+    Kernels,
+    /// The root filesystem, by the initramfs
+    Root,
+}
+
+/// What a filesystem is on, as far as reading it while booting goes
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum BootStack
+{
+    /// Straight on its partition
+    Plain,
+    /// On a LUKS container, as `encryption` creates them
+    Luks,
+    /// Spread over its partition and its btrfs `members`
+    BtrfsMembers,
+}
+
+/// A combination of a bootloader with a filesystem that it, or the initramfs it starts, has to
+/// read, which is known not to boot, or to boot unreliably. The formats are those of
+/// `FILESYSTEMS`; `None` and an empty list stand for
+/// all of them. In `why` and `workaround`, `{mount}` and `{format}` are replaced by those of the
+/// filesystem
+struct BootCompatibility
+{
+    bootloader: Option<Bootloader>,
+    reads: BootRead,
+    formats: &'static [&'static str],
+    stack: Option<BootStack>,
+    /// Whether the system doesn't boot at all, rather than only unreliably
+    broken: bool,
+    why: &'static str,
+    workaround: &'static str,
+}
+
+/// The combinations of bootloaders and filesystems that are known not to boot, or to boot
+/// unreliably; all the others are expected to boot
+const BOOT_MATRIX: &[BootCompatibility] = &[
+    BootCompatibility {
+        bootloader: None,
+        reads: BootRead::Root,
+        formats: &["fat32", "ntfs", "exfat"],
+        stack: None,
+        broken: true,
+        why: "the root filesystem would be {format}, which has no owners, permissions or symbolic links, so the base system can't run from it",
+        workaround: "format the partition mounted at / as ext4 or btrfs",
+    },
+    BootCompatibility {
+        // efistub and systemd-boot already need the kernels on a FAT32 partition, which can't be
+        // encrypted
+        bootloader: Some(Bootloader::Grub),
+        reads: BootRead::Kernels,
+        formats: &[],
+        stack: Some(BootStack::Luks),
+        broken: true,
+        why: "the kernels would be on the encrypted partition mounted at {mount}, and GRUB can't unlock the LUKS2 containers cryptsetup creates",
+        workaround: "put /boot on an unencrypted ext4 or fat32 partition",
+    },
+    BootCompatibility {
+        bootloader: Some(Bootloader::Grub),
+        reads: BootRead::Kernels,
+        formats: &["btrfs"],
+        stack: Some(BootStack::BtrfsMembers),
+        broken: false,
+        why: "GRUB would read the kernels from the btrfs filesystem mounted at {mount}, which is spread over several devices, and can't once one of them is missing",
+        workaround: "put /boot on a partition of its own, such as the EFI system partition",
+    },
+    BootCompatibility {
+        bootloader: Some(Bootloader::Grub),
+        reads: BootRead::Kernels,
+        formats: &["ntfs", "exfat"],
+        stack: None,
+        broken: false,
+        why: "the kernels would be on the {format} partition mounted at {mount}, which is meant for sharing files with Windows, and which GRUB and the updates of the kernel are hardly ever used with",
+        workaround: "format the partition mounted at {mount} as ext4 or fat32",
+    },
+];
+
+/// Refuse if the bootloader can't boot what the configuration installs, according to
+/// `BOOT_MATRIX`, and warn about, or refuse if `strict` is set, what it may not boot reliably. An
+/// initramfs started by busybox also needs the `btrfs` hook for a root filesystem spread over
+/// several devices
+fn validate_boot_matrix(bootloader: Bootloader, partitions: &[Partition], mkinitcpio_hooks: Option<&[String]>, strict: bool) -> Result<(), Errors>
+{
+    let root = partitions.iter().find(|p| p.mount == "/").ok_or_else(|| refusal!("no root partition"))?;
+    let kernels = partitions.iter().find(|p| p.mount == "/boot").unwrap_or(root);
+    let stack = |p: &Partition| match (&p.encryption, partitions.iter().any(|m| m.member_of.as_deref() == Some(p.mount.as_str()))) {
+        (Some(_), _) => BootStack::Luks,
+        (None, true) => BootStack::BtrfsMembers,
+        (None, false) => BootStack::Plain,
+    };
+    for row in BOOT_MATRIX {
+        assert!(row.formats.iter().all(|f| filesystem(f).is_some()), "BOOT_MATRIX has an unknown format");
+        let read = match row.reads {
+            BootRead::Kernels => kernels,
+            BootRead::Root => root,
+        };
+        if row.bootloader.is_some_and(|b| b != bootloader)
+            || !(row.formats.is_empty() || row.formats.contains(&read.format.as_str()))
+            || row.stack.is_some_and(|s| s != stack(read))
+        {
+            continue;
+        }
+        let explain = |text: &str| text.replace("{mount}", &read.mount).replace("{format}", &read.format);
+        if row.broken {
+            refuse!("`bootloader: {}` can't boot this system: {}; {}", bootloader.name(), explain(row.why), explain(row.workaround));
+        }
+        let msg = format!("`bootloader: {}` may not boot this system reliably: {}; {}", bootloader.name(), explain(row.why), explain(row.workaround));
+        if strict {
+            refuse!("{}", msg);
+        }
+        warning!("{}", msg);
+    }
+    if let Some(hooks) = mkinitcpio_hooks {
+        if stack(root) == BootStack::BtrfsMembers && hook_flavor(hooks) == HookFlavor::Busybox && !hooks.iter().any(|h| h == "btrfs") {
+            refuse!("the root filesystem is spread over several devices, which an initramfs started by busybox only finds all of with the 'btrfs' hook; add it to mkinitcpio_hooks, after 'udev'")
+        }
+    }
+    Ok(())
+}
+
+/// Return the bootstrap tarball of `raw`, the latest one of `arch` unless it has a `source`. On
+/// other architectures than x86_64, the tarball has to have a `sha256`, since its signature is made
+/// with the key of Arch Linux ARM, which the keyring of the live system doesn't have
+fn parse_tarball(raw: ParsedTarball, arch: Architecture) -> Result<Tarball, Errors>
+{
+    let source = raw.source.unwrap_or_else(|| arch.bootstrap_tarball().to_string());
+    if !source.starts_with("http://") && !source.starts_with("https://") && !source.starts_with('/') {
+        refuse!("invalid source of tarball: \"{}\" (expected a URL starting with http:// or https://, or an absolute path)", source)
+    }
+    let sha256 = raw.sha256.map(|sum| sum.to_lowercase());
+    if let Some(sum) = sha256.as_deref().filter(|s| s.len() != 64 || !s.chars().all(|c| c.is_ascii_hexdigit())) {
+        refuse!("invalid sha256 of tarball: \"{}\" (expected 64 hexadecimal digits)", sum)
+    }
+    if arch != Architecture::X86_64 && sha256.is_none() {
+        refuse!("the tarball of {} needs a `sha256` under `tarball`: it's signed by Arch Linux ARM, whose key the live system doesn't have to verify it with",
+            arch.name())
+    }
+    Ok(Tarball { source, sha256 })
+}
+
+/// Read the options of the disk image files. Refuse if the size can't be understood, or if the
+/// files are to be created without one
+fn parse_disk_image(raw: ParsedDiskImage) -> Result<DiskImage, Errors>
+{
+    let size = raw.size.map(|size| Size::parse(&size)
+        .ok_or_else(|| refusal!("invalid size of image: \"{}\" (expected e.g. '20G')", size))).transpose()?;
+    let create = raw.create.unwrap_or(false);
+    if create && size.is_none() {
+        refuse!("`image` has `create: true`, but no `size` to create the files with")
+    }
+    Ok(DiskImage { size, create, allow_real_disks: raw.allow_real_disks.unwrap_or(false) })
+}
+
+/// Refuse if the disks that are files can't be installed onto: without `image`, along with real
+/// disks unless `allow_real_disks` says so, or in a way that names their partitions by the loop
+/// devices they're on while the script runs, which are gone once the image boots elsewhere
+fn validate_disk_images(image: Option<&DiskImage>, bootloader: Bootloader, partitions: &[Partition], busybox: bool) -> Result<(), Errors>
+{
+    let mut images: Vec<&str> = Vec::new();
+    let mut real: Vec<&str> = Vec::new();
+    for disk in partitions.iter().map(|p| p.disk.as_str()) {
+        let list = if is_image_disk(disk) { &mut images } else { &mut real };
+        if !list.contains(&disk) {
+            list.push(disk);
+        }
+    }
+    let image = match (image, images.first()) {
+        (None, None) => return Ok(()),
+        (Some(_), None) => {
+            warning!("`image` is set, but none of the disks is a file; it's going to be ignored");
+            return Ok(());
+        },
+        (None, Some(disk)) => refuse!("disk \"{}\" isn't a device under /dev; use e.g. \"/dev/sda\", or set `image` to install onto a disk image file, \
+            e.g. `image: {{ size: 20G, create: true }}`", disk),
+        (Some(image), Some(_)) => image,
+    };
+    if let (false, Some(disk)) = (image.allow_real_disks, real.first()) {
+        refuse!("the disk image {} would be installed along with the real disk {}; set `allow_real_disks: true` under `image` if that's what's meant",
+            images[0], disk)
+    }
+    if bootloader != Bootloader::Grub {
+        refuse!("`bootloader: {}` can't boot from a disk image: its boot entry would find the root partition by the loop device it's on while installing; \
+            use `bootloader: grub`, which finds it by UUID", bootloader.name())
+    }
+    let root = partitions.iter().find(|p| p.mount == "/").ok_or_else(|| refusal!("no root partition"))?;
+    if root.encryption.is_some() && busybox && is_image_disk(&root.disk) {
+        refuse!("the encrypted root partition on {} would be unlocked by an initramfs started by busybox, which finds it by the loop device it's on while installing; \
+            use the 'systemd' and 'sd-encrypt' hooks in mkinitcpio_hooks", root.disk)
+    }
+    if let Some(secondary) = secondary_esps(partitions).first() {
+        refuse!("the secondary EFI system partition on {} would get a boot entry in the firmware of the machine installing the disk image, rather than the one it boots on; \
+            remove `esp: true` from the partitions that aren't mounted", secondary.disk)
+    }
+    Ok(())
+}
+
+/// Return the partition that should be used as the EFI system partition: the one mounted at
+/// `/efi` if there's one, otherwise the one mounted at `/boot`
+pub fn find_esp(partitions: &[Partition]) -> Option<&Partition>
+{
+    esp_index(partitions).map(|i| &partitions[i])
+}
+
+/// Return the index of the partition `find_esp` returns
+fn esp_index(partitions: &[Partition]) -> Option<usize>
+{
+    partitions.iter().position(|p| p.mount == "/efi")
+        .or_else(|| partitions.iter().position(|p| p.mount == "/boot"))
+}
+
+/// Return the EFI system partitions that are kept in sync with the primary one, the one
+/// `find_esp` returns: those marked `esp: true` that aren't mounted
+pub fn secondary_esps(partitions: &[Partition]) -> Vec<&Partition>
+{
+    partitions.iter().filter(|p| p.esp && p.mount.is_empty()).collect()
+}
+
+/// Refuse if the partitions marked `esp: true` don't leave exactly one primary EFI system
+/// partition, the one that's mounted, or if a secondary one is smaller than it. Warn about the
+/// secondary ones that wouldn't let the machine boot once the disk of the primary one is gone
+fn validate_secondary_esps(bootloader: Bootloader, chroot_backend: &str, partitions: &[Partition], boot: BootPartitions) -> Result<(), Errors>
+{
+    let primary = &partitions[boot.esp];
+    if let Some(other) = partitions.iter().find(|p| p.esp && !p.mount.is_empty() && !std::ptr::eq(*p, primary)) {
+        refuse!("the partition mounted at {} is marked `esp: true`, but the primary EFI system partition is the one mounted at {}; \
+            there can only be one, so remove the mount point of the others to keep them in sync with it",
+            other.mount, primary.mount)
+    }
+    let secondaries = secondary_esps(partitions);
+    if secondaries.is_empty() {
+        return Ok(());
+    }
+    if chroot_backend == "nspawn" {
+        refuse!("the secondary EFI system partitions are mounted from inside the installed system, which systemd-nspawn doesn't give access to the disks; use `chroot_backend: arch-chroot`")
+    }
+    for secondary in &secondaries {
+        if secondary.disk == primary.disk {
+            warning!("the secondary EFI system partition on {} is on the same disk as the primary one, so the machine can't boot from it if the disk fails",
+                secondary.disk);
+        }
+        if let (Some(size), Some(primary_size)) = (secondary.size, primary.size) {
+            if size.mib() < primary_size.mib() {
+                refuse!("the secondary EFI system partition on {} is smaller than the primary one; change its `size` from '{}' to at least '{}'",
+                    secondary.disk, size, primary_size);
+            }
+        }
+    }
+    if bootloader == Bootloader::Grub && primary.mount == "/boot" {
+        warning!("GRUB on the secondary EFI system partitions reads its configuration from the primary one; mount the primary one at /efi instead, for GRUB to read it from the root partition");
+    } else if let Some(xbootldr) = find_xbootldr(bootloader, partitions) {
+        warning!("the kernels are on the XBOOTLDR partition mounted at {}, which isn't copied onto the secondary EFI system partitions",
+            xbootldr.mount);
+    }
+    Ok(())
+}
+
+/// Return the partition where systemd-boot expects to find the kernels, if it's not the EFI system
+/// partition: when the latter is mounted at `/efi`, it's the one mounted at `/boot`
+pub fn find_xbootldr(bootloader: Bootloader, partitions: &[Partition]) -> Option<&Partition>
+{
+    if bootloader != Bootloader::SystemdBoot || find_esp(partitions)?.mount != "/efi" {
+        return None;
+    }
+    partitions.iter().find(|p| p.mount == "/boot")
+}
+
+/// The GUID of the "Linux extended boot" (XBOOTLDR) partition type
+const XBOOTLDR: &str = "BC13C2FF-59E6-4262-A352-B275FD6F7172";
+
+/// The partition types that can be given by name in the `type_guid` property of a partition
+pub const PARTITION_TYPES: &[(&str, &str)] = &[
+    ("esp", "C12A7328-F81F-11D2-BA4B-00A0C93EC93B"),
+    ("xbootldr", XBOOTLDR),
+    ("swap", "0657FD6D-A4AB-43C4-84E5-0933C84B4F4F"),
+    ("linux", "0FC63DAF-8483-4772-8E79-3D69D8477DE4"),
+    ("linux-home", "933AC7E1-2EB4-4F13-B844-0E14E2AEF915"),
+    ("linux-srv", "3B8F8425-20E0-4F3B-907F-1A25A76F98E8"),
+    ("linux-var", "4D21B016-B534-45C2-A9FB-5C16E091FD2D"),
+    ("linux-root-x86-64", "4F68BCE3-E8CD-4DB1-96E7-FBCAF984B709"),
+    ("linux-root-arm64", "B921B045-1DF0-41C3-AF44-4C6F280D3FAE"),
+];
+
+/// The partition types that the Discoverable Partitions Specification gives to partitions mounted
+/// at a few places, so that systemd-gpt-auto-generator can mount them without an fstab entry. The
+/// root partition's type depends on the architecture
+const DISCOVERABLE_PARTITIONS: &[(&str, &str)] = &[
+    ("/home", "linux-home"),
+    ("/srv", "linux-srv"),
+    ("/var", "linux-var"),
+];
+
+/// Return the GUID of a partition type given either by one of the names in `PARTITION_TYPES` or as
+/// a GUID. `fdisk` takes GUIDs for every type, so they're what ends up in the script. Refuse if
+/// it's neither
+fn partition_type_guid(type_guid: &str) -> Result<String, Errors>
+{
+    if let Some((_, guid)) = PARTITION_TYPES.iter().find(|(name, _)| *name == type_guid) {
+        return Ok(guid.to_string());
+    }
+    let guid = Regex::new(r"^[[:xdigit:]]{8}-[[:xdigit:]]{4}-[[:xdigit:]]{4}-[[:xdigit:]]{4}-[[:xdigit:]]{12}$").unwrap();
+    if !guid.is_match(type_guid) {
+        refuse!("invalid type_guid: \"{}\" (expected a GUID, or one of: {})",
+            type_guid, PARTITION_TYPES.iter().map(|(name, _)| *name).collect::<Vec<&str>>().join(", "))
+    }
+    Ok(type_guid.to_uppercase())
+}
+
+/// Warn about partition types that don't go with the format of the partition: swap space that's
+/// not of the swap type and vice versa, and EFI system or XBOOTLDR partitions that the firmware
+/// can't read
+fn check_partition_type(guid: &str, format: &str)
+{
+    let name = PARTITION_TYPES.iter().find(|(_, g)| *g == guid).map(|(name, _)| *name);
+    let mismatch = match name {
+        Some("swap") => format != "swap",
+        Some("esp") | Some("xbootldr") => format != "fat32",
+        _ => format == "swap",
+    };
+    if mismatch {
+        warning!("type_guid '{}' doesn't match the partition's '{}' format", name.unwrap_or(guid), format);
+    }
+}
+
+/// Packages and disk names that only make sense inside a virtual machine
+const VIRTUALIZATION_INDICATORS: &[&str] = &[
+    "qemu-guest-agent",
+    "spice-vdagent",
+    "virtualbox-guest-utils",
+    "open-vm-tools",
+    "hyperv",
+    "/dev/vd",
+    "/dev/xvd",
+];
+
+/// Warn if no firmware is going to be installed, but nothing in the configuration suggests that
+/// the target is a virtual machine, which is the only place where that's a good idea
+fn validate_firmware(firmware: &Firmware, extra: &str, partitions: &[Partition])
+{
+    if !matches!(firmware, Firmware::None) {
+        return;
+    }
+    let is_vm = VIRTUALIZATION_INDICATORS.iter().any(|indicator| {
+        extra.split_whitespace().any(|package| package == *indicator)
+            || partitions.iter().any(|p| p.disk.starts_with(indicator))
+    });
+    if !is_vm {
+        warning!("no firmware packages are going to be installed, but the target doesn't seem to be a virtual machine");
+    }
+}
+
+/// Some of the `packages` that contradict something else the configuration file selects, all for
+/// the same reason, so that the installation succeeds but doesn't work as intended
+struct PackageConflict
+{
+    /// The names of the packages; a trailing `*` matches every package that starts with the rest
+    packages: &'static [&'static str],
+    /// Return the property the package contradicts, as written in the configuration file, if it's
+    /// selected
+    feature: fn(&InstallOptions) -> Option<String>,
+    /// Why the two don't go together, and what to do instead
+    reason: &'static str,
+}
+
+/// Return the feature that packages with kernel modules of their own conflict with, since Arch
+/// Linux doesn't sign those modules
+fn kernel_lockdown(options: &InstallOptions) -> Option<String>
+{
+    options.hardening.kernel_lockdown.then(|| "`kernel_lockdown` under `hardening`".to_string())
+}
+
+/// Return the power daemon that other programs managing the power settings conflict with
+fn power_daemon(options: &InstallOptions) -> Option<String>
+{
+    (options.power != "none").then(|| format!("`power: {}`", options.power))
+}
+
+/// Every known conflict between the `packages` and the rest of the configuration
+const PACKAGE_CONFLICTS: &[PackageConflict] = &[
+    PackageConflict {
+        packages: &["connman*", "netctl", "wicd*"],
+        feature: |_| Some("the networkmanager network backend".to_string()),
+        reason: "both manage the network interfaces and fight over them; remove the package",
+    },
+    PackageConflict {
+        packages: &["dhcpcd"],
+        feature: |_| Some("the networkmanager network backend".to_string()),
+        reason: "NetworkManager runs its own DHCP client, and the two fight over the leases if dhcpcd is enabled; remove the package",
+    },
+    PackageConflict {
+        packages: &["grub"],
+        feature: |o| (o.bootloader != Bootloader::Grub).then(|| format!("`bootloader: {}`", o.bootloader.name())),
+        reason: "GRUB gets installed but is never set up; remove the package, or use `bootloader: grub`",
+    },
+    PackageConflict {
+        packages: &["refind", "syslinux"],
+        feature: |o| Some(format!("`bootloader: {}`", o.bootloader.name())),
+        reason: "jimmy only sets up one bootloader; remove the package",
+    },
+    PackageConflict {
+        packages: &["linux"],
+        feature: |o| (o.kernel != Kernel::Latest).then(|| format!("`kernel: {}`", o.kernel.name())),
+        reason: "a second kernel gets installed, but the bootloader only boots the one given by `kernel`",
+    },
+    PackageConflict {
+        packages: &["linux-lts"],
+        feature: |o| (o.kernel != Kernel::Lts).then(|| format!("`kernel: {}`", o.kernel.name())),
+        reason: "a second kernel gets installed, but the bootloader only boots the one given by `kernel`",
+    },
+    PackageConflict {
+        packages: &["nvidia"],
+        feature: |o| (o.kernel != Kernel::Latest).then(|| format!("`kernel: {}`", o.kernel.name())),
+        reason: "the package only has modules for the `linux` kernel; use nvidia-lts or nvidia-dkms",
+    },
+    PackageConflict {
+        packages: &["nvidia-open"],
+        feature: |o| (o.kernel != Kernel::Latest).then(|| format!("`kernel: {}`", o.kernel.name())),
+        reason: "the package only has modules for the `linux` kernel; use nvidia-open-dkms",
+    },
+    PackageConflict {
+        packages: &["nvidia-lts"],
+        feature: |o| (o.kernel != Kernel::Lts).then(|| format!("`kernel: {}`", o.kernel.name())),
+        reason: "the package only has modules for the `linux-lts` kernel; use nvidia or nvidia-dkms",
+    },
+    PackageConflict {
+        packages: &["nvidia", "nvidia-lts", "nvidia-open", "nvidia-dkms", "nvidia-open-dkms"],
+        feature: kernel_lockdown,
+        reason: "the locked down kernel only loads signed modules, and those of the package aren't; remove it, or turn off kernel_lockdown",
+    },
+    PackageConflict {
+        packages: &["linux-firmware*"],
+        feature: |o| matches!(o.firmware, Firmware::None).then(|| "`firmware_packages: none`".to_string()),
+        reason: "firmware gets installed anyway; remove the package, or list it in `firmware_packages`",
+    },
+    PackageConflict {
+        packages: &["tlp"],
+        feature: |o| (o.power == "power-profiles-daemon").then(|| "`power: power-profiles-daemon`".to_string()),
+        reason: "both change the same power settings and undo each other's; remove the package, or use `power: tlp`",
+    },
+    PackageConflict {
+        packages: &["power-profiles-daemon"],
+        feature: |o| (o.power == "tlp").then(|| "`power: tlp`".to_string()),
+        reason: "both change the same power settings and undo each other's; remove the package, or use `power: power-profiles-daemon`",
+    },
+    PackageConflict {
+        packages: &["gnome"],
+        feature: |o| (o.power == "tlp").then(|| "`power: tlp`".to_string()),
+        reason: "GNOME comes with power-profiles-daemon, which undoes the power settings of TLP; use `power: power-profiles-daemon`",
+    },
+    PackageConflict {
+        packages: &["laptop-mode-tools", "auto-cpufreq"],
+        feature: power_daemon,
+        reason: "both change the same power settings and undo each other's; remove the package",
+    },
+];
+
+/// Determine if a package name matches a pattern, where a trailing `*` matches anything
+fn package_matches(pattern: &str, package: &str) -> bool
+{
+    match pattern.strip_suffix('*') {
+        Some(prefix) => package.starts_with(prefix),
+        None => package == pattern,
+    }
+}
+
+/// Warn about, or refuse if `strict` is set, a swap partition with a mount point, which would be
+/// ignored since swap is activated instead of mounted; `number` is the place of the partition
+/// under `partitions`, starting at 1. Return the partition without its mount point
+fn validate_swap_mount(number: usize, raw: ParsedPartition, strict: bool) -> Result<ParsedPartition, Errors>
+{
+    match (raw.format.as_deref(), raw.mount.as_deref()) {
+        (Some("swap"), Some(mount)) if !mount.is_empty() => {
+            let msg = format!("partition {} under `partitions` (swap on {}) has `mount: {}`, but swap isn't mounted; remove the mount point, or use a format that can be mounted",
+                number, raw.disk.as_deref().unwrap_or("an unspecified disk"), mount);
+            if strict {
+                refuse!("{}", msg);
+            }
+            warning!("{}; it's going to be ignored", msg);
+            Ok(ParsedPartition { mount: None, ..raw })
+        },
+        _ => Ok(raw),
+    }
+}
+
+/// Return the partition along with the `members` of its btrfs filesystem, which come right after
+/// it. Members only say which disk they're on and how big they are: they're formatted and mounted
+/// along with the partition, so refuse if they have a format or a mount point of their own, or if
+/// two devices of the filesystem share a disk
+fn with_members(raw: ParsedPartition) -> Result<Vec<Partition>, Errors>
+{
+    let members = raw.members.clone().unwrap_or_default();
+    if !members.is_empty() && raw.format.as_deref() != Some("btrfs") {
+        refuse!("members are only used with `format: btrfs`, not '{}'", raw.format.as_deref().unwrap_or("ext4"))
+    }
+    let partition = Partition::try_from(ParsedPartition { members: None, ..raw })?;
+    let devices = members.len() + 1;
+    let min_devices = RAID_PROFILES.iter()
+        .find(|(name, _)| Some(*name) == partition.raid_profile.as_deref())
+        .map(|(_, min)| *min)
+        .unwrap_or(1);
+    if devices < min_devices {
+        refuse!("`raid_profile: {}` needs at least {} devices, but the btrfs filesystem mounted at {} has {}; add `members` on other disks",
+            partition.raid_profile.as_deref().unwrap_or_default(), min_devices, partition.mount, devices)
+    }
+    if members.is_empty() {
+        return Ok(vec![partition]);
+    }
+    if partition.raid_profile.is_none() {
+        refuse!("the btrfs filesystem mounted at {} has members, but no `raid_profile` (expected one of: {})",
+            partition.mount, RAID_PROFILES.iter().map(|(name, _)| *name).collect::<Vec<&str>>().join(", "))
+    }
+    if partition.mount.is_empty() {
+        refuse!("a btrfs filesystem with members must be mounted; add `mount:` to the partition on {}", partition.disk)
+    }
+    if partition.encryption.is_some() {
+        refuse!("the btrfs filesystem mounted at {} has members, which can't be encrypted yet; remove its `encryption`", partition.mount)
+    }
+
+    let mut disks = vec![partition.disk.clone()];
+    let mut partitions = vec![];
+    for member in members {
+        let disk = normalize_disk(&member.disk.clone().ok_or_else(|| {
+            refusal!("a member of the btrfs filesystem mounted at {} has no `disk`; members go on disks of their own", partition.mount)
+        })?)?;
+        let own = [
+            ("format", member.format.is_some()),
+            ("mount", member.mount.is_some()),
+            ("mkfs_args", member.mkfs_args.is_some()),
+            ("mount_options", member.mount_options.is_some()),
+            ("encryption", member.encryption.is_some()),
+            ("unmounted", member.unmounted.is_some()),
+            ("esp", member.esp.is_some()),
+            ("raid_profile", member.raid_profile.is_some()),
+            ("members", member.members.is_some()),
+            ("post_format", member.post_format.is_some()),
+            ("reuse", member.reuse.is_some()),
+        ];
+        if let Some((property, _)) = own.iter().find(|(_, given)| *given) {
+            refuse!("the member on {} of the btrfs filesystem mounted at {} has `{}`, but members are formatted and mounted along with the filesystem; remove it",
+                disk, partition.mount, property)
+        }
+        if disks.contains(&disk) {
+            refuse!("the btrfs filesystem mounted at {} has more than one device on {}; put each member on a disk of its own", partition.mount, disk)
+        }
+        disks.push(disk.clone());
+        let fdisk_type = member.type_guid.as_deref().map(partition_type_guid).transpose()?;
+        if let Some(guid) = &fdisk_type {
+            check_partition_type(guid, "btrfs");
+        }
+        partitions.push(Partition {
+            format: "btrfs".to_string(),
+            disk,
+            size: member.size.as_deref().map(parse_partition_size).transpose()?,
+            mount: String::new(),
+            mkfs_args: vec![],
+            swap_priority: None,
+            activate_swap: true,
+            fdisk_type,
+            mount_options: String::new(),
+            encryption: None,
+            esp: false,
+            unmounted: false,
+            raid_profile: None,
+            member_of: Some(partition.mount.clone()),
+            post_format: vec![],
+            reuse: false,
+        });
+    }
+    partitions.insert(0, partition);
+    Ok(partitions)
+}
+
+/// Warn about, or refuse if `strict` is set, the `packages` that contradict other properties
+fn validate_extra(options: &InstallOptions, strict: bool) -> Result<(), Errors>
+{
+    for package in options.extra.split_whitespace() {
+        for conflict in PACKAGE_CONFLICTS.iter().filter(|c| c.packages.iter().any(|p| package_matches(p, package))) {
+            if let Some(feature) = (conflict.feature)(options) {
+                let msg = format!("package '{}' conflicts with {}: {}", package, feature, conflict.reason);
+                if strict {
+                    refuse!("{}", msg);
+                }
+                warning!("{}", msg);
+            }
+        }
+    }
+    Ok(())
+}
+
+/// The editors jimmy knows the packages of, by the name of their program
+pub const KNOWN_EDITORS: &[(&str, &str)] = &[
+    ("vim", "vim"),
+    ("nvim", "neovim"),
+    ("nano", "nano"),
+    ("emacs", "emacs"),
+];
+
+/// Return the package that provides an editor, if it's one of the known ones. The editor may be
+/// given as a path, such as `/usr/bin/vim`
+pub fn editor_package(editor: &str) -> Option<&'static str>
+{
+    let program = editor.rsplit('/').next().unwrap_or(editor);
+    KNOWN_EDITORS.iter().find(|(p, _)| *p == program).map(|(_, package)| *package)
+}
+
+/// Refuse if the filesystem holding /home can't store the home directories of systemd-homed, which
+/// are LUKS images that need to be allocated with `fallocate`
+fn validate_home_encryption(user: &str, partitions: &[Partition]) -> Result<(), Errors>
+{
+    let home = partitions.iter()
+        .find(|p| p.mount == "/home")
+        .or_else(|| partitions.iter().find(|p| p.mount == "/"));
+    if let Some(home) = home.filter(|p| p.format != "ext4") {
+        refuse!("user '{}' has `home_encryption`, which stores the home directory in an encrypted image on /home; the partition mounted at {} needs to be 'ext4', not '{}'",
+            user, home.mount, home.format);
+    }
+    Ok(())
+}
+
+/// The timezone of the installed system when the configuration file gives none
+pub const DEFAULT_TIMEZONE: &str = "UTC";
+
+/// Refuse if the `snapshot_date` isn't a day of the calendar written as `YYYY-MM-DD`
+fn validate_snapshot_date(date: &str) -> Result<(), Errors>
+{
+    let invalid = || refusal!("invalid snapshot_date: \"{}\" (expected a date such as 2024-11-01)", date);
+    let c = Regex::new(r"^(\d{4})-(\d{2})-(\d{2})$").unwrap().captures(date).ok_or_else(invalid)?;
+    let (year, month, day): (u32, u32, u32) = (c[1].parse().unwrap(), c[2].parse().unwrap(), c[3].parse().unwrap());
+    let leap = year % 4 == 0 && (year % 100 != 0 || year % 400 == 0);
+    let days = match month {
+        1 | 3 | 5 | 7 | 8 | 10 | 12 => 31,
+        4 | 6 | 9 | 11 => 30,
+        2 if leap => 29,
+        2 => 28,
+        _ => return Err(invalid()),
+    };
+    if !(1..=days).contains(&day) {
+        return Err(invalid());
+    }
+    Ok(())
+}
+
+/// Return the commands of a disk or partition given under `property`, refusing if one of them has
+/// a NUL character, which can't be put in the script
+fn device_cmds(property: &str, cmds: Option<Vec<String>>) -> Result<Vec<String>, Errors>
+{
+    let cmds = cmds.unwrap_or_default();
+    if let Some(cmd) = cmds.iter().find(|c| c.contains('\0')) {
+        refuse!("{} command contains a NUL character: {:?}", property, cmd)
+    }
+    Ok(cmds)
+}
+
+/// Add an error to `errors` if the `min_iso_version` isn't a release of the live ISO, written as
+/// `YYYY.MM.DD`
+fn validate_min_iso_version(version: &str, errors: &mut Errors)
+{
+    let valid = Regex::new(r"^\d{4}\.(\d{2})\.(\d{2})$").unwrap().captures(version).is_some_and(|c| {
+        (1..=12).contains(&c[1].parse::<u32>().unwrap()) && (1..=31).contains(&c[2].parse::<u32>().unwrap())
+    });
+    if !valid {
+        error!(errors, "invalid min_iso_version: \"{}\" (expected a release of the ISO such as 2024.01.01)", version)
+    }
+}
+
+/// Add an error to `errors` if the `keymap` can't be the name of a keymap, such as `de-latin1` or
+/// `fr-bepo`
+fn validate_keymap(keymap: &str, errors: &mut Errors)
+{
+    if !Regex::new(r"^[A-Za-z0-9][A-Za-z0-9._+-]*$").unwrap().is_match(keymap) {
+        error!(errors, "invalid keymap: \"{}\" (expected the name of a keymap such as de-latin1, as listed by `localectl list-keymaps`)", keymap)
+    }
+}
+
+/// Return a locale as /etc/locale.gen spells it, warning about what's changed: one without a
+/// charset, like `en_US`, gets `.UTF-8`, and the charset `utf8`, in any case and with or without
+/// its hyphen, is spelled `UTF-8`. Refuse if it has characters no locale has; warn about, or refuse
+/// if `strict` is set, one that still isn't of the form `ll_CC.CHARSET[@modifier]`. `option` is
+/// where it was given, such as `locales`
+fn normalize_locale(option: &str, raw: &str, strict: bool) -> Result<String, Errors>
+{
+    if raw.is_empty() || !raw.chars().all(|c| c.is_ascii_alphanumeric() || "_.@-".contains(c)) {
+        refuse!("invalid {}: \"{}\" (expected a locale such as en_US.UTF-8)", option, raw)
+    }
+    let (name, modifier) = match raw.split_once('@') {
+        Some((name, modifier)) => (name, format!("@{}", modifier)),
+        None => (raw, String::new()),
+    };
+    let locale = match name.split_once('.') {
+        None => {
+            let locale = format!("{}.UTF-8{}", name, modifier);
+            warning!("{} has '{}', which has no charset; using '{}'", option, raw, locale);
+            locale
+        },
+        Some((name, charset)) if charset != "UTF-8" && charset.replace('-', "").eq_ignore_ascii_case("utf8") => {
+            let locale = format!("{}.UTF-8{}", name, modifier);
+            warning!("{} has '{}', which /etc/locale.gen spells '{}'; using that", option, raw, locale);
+            locale
+        },
+        Some(_) => raw.to_string(),
+    };
+    if !Regex::new(r"^([a-z]{2,3}_[A-Z]{2}|C)\.[A-Z0-9][A-Z0-9-]*(@[a-z]+)?$").unwrap().is_match(&locale) {
+        let msg = format!("{} has '{}', which isn't of the form ll_CC.CHARSET[@modifier], such as en_US.UTF-8 or sr_RS.UTF-8@latin, so locale-gen won't find it in /etc/locale.gen",
+            option, locale);
+        if strict {
+            refuse!("{}", msg);
+        }
+        warning!("{}", msg);
+    }
+    Ok(locale)
+}
+
+/// Determine if a string is the name of a package, as pacman allows them: lowercase letters,
+/// digits and `@._+-`, without a hyphen or a dot at the start
+fn is_package_name(name: &str) -> bool
+{
+    Regex::new(r"^[a-z0-9@_+][a-z0-9@._+-]*$").unwrap().is_match(name)
+}
+
+/// Refuse if one of the `packages` isn't the name of a package, pointing the pacman flags to
+/// `pacstrap_args`, and warn about the ones that look like versions
+fn validate_extra_names(extra: &[String]) -> Result<(), Errors>
+{
+    let version = Regex::new(r"^\d+([.:]\d+)+(-\d+)?$").unwrap();
+    for (i, package) in extra.iter().enumerate() {
+        if package.starts_with('-') {
+            let argument = extra.get(i + 1)
+                .filter(|a| !a.starts_with('-'))
+                .map(|a| format!(", along with '{}' if it's the flag's argument", a))
+                .unwrap_or_default();
+            refuse!("`packages` only lists packages, but it has the pacman flag '{}'; give it to `pacstrap_args` instead{}", package, argument)
+        }
+        if !is_package_name(package) {
+            refuse!("invalid package name in packages: \"{}\" (expected lowercase letters, digits and @._+-)", package)
+        }
+        if version.is_match(package) {
+            warning!("'{}' in `packages` looks like a version rather than a package; pin packages at a version with package_pins", package);
+        }
+    }
+    Ok(())
+}
+
+/// Refuse if a package pinned with `package_pins` doesn't have the name of a package, or if its
+/// version isn't a full one, with the release: `6.6.1.arch1-1`, or `1:2.3-4` with an epoch
+fn validate_package_pin(name: &str, version: &str) -> Result<(), Errors>
+{
+    if !is_package_name(name) {
+        refuse!("invalid package name in package_pins: \"{}\"", name)
+    }
+    if !Regex::new(r"^(\d+:)?[A-Za-z0-9._+~]+-\d+(\.\d+)?$").unwrap().is_match(version) {
+        refuse!("invalid version for package '{}' in package_pins: \"{}\" (expected the version and the release, e.g. '6.6.1.arch1-1')",
+            name, version)
+    }
+    Ok(())
+}
+
+/// Add an error to `errors` if the `root_password_hash` isn't a hash in the format of /etc/shadow,
+/// such as the ones made by `openssl passwd -6` or `mkpasswd`. The hash itself is left out of the
+/// message, since it may end up in a bug report
+fn validate_password_hash(hash: &str, errors: &mut Errors)
+{
+    if !Regex::new(r"^\$[0-9a-z]+\$[./0-9A-Za-z$=,+-]+$").unwrap().is_match(hash) {
+        error!(errors, "invalid root_password_hash (expected a hash such as the ones `openssl passwd -6` or `mkpasswd` make, starting with e.g. '$6$' or '$y$')")
+    }
+}
+
+/// Return the timezone, as the path of its file under /usr/share/zoneinfo: `Europe/London`, or
+/// `UTC` for those that aren't in a region. Without one, it's `DEFAULT_TIMEZONE`. Add an error to
+/// `errors` if there's no such timezone
+fn validate_timezone(timezone: Option<String>, errors: &mut Errors) -> String
+{
+    let timezone = match timezone.map(|tz| tz.trim_matches('/').to_string()).filter(|tz| !tz.is_empty()) {
+        None => {
+            warning!("timezone not specified; defaulting to '{}'", DEFAULT_TIMEZONE);
+            return DEFAULT_TIMEZONE.to_string();
+        },
+        Some(timezone) => timezone,
+    };
+    let is_valid_part = |p: &str| !p.is_empty() && p != "." && p != "..";
+    if !timezone.split('/').all(is_valid_part) || !crate::is_file(&format!("/usr/share/zoneinfo/{}", timezone)) {
+        error!(errors, "invalid timezone: \"{}\" (expected the path of a file under /usr/share/zoneinfo, e.g. Europe/London)", timezone)
+    }
+    timezone
+}
+
+/// Run `f` as reading the top-level property `name`, so that what it warns about or refuses is
+/// said to be about it
+fn property<T>(name: &str, f: impl FnOnce() -> T) -> T
+{
+    log::within(&[PathSegment::Key(name.to_string())], f)
+}
+
+impl TryFrom<ParsedInstallOptions> for InstallOptions
+{
+    type Error = Errors;
+
+    /// Create a new instance of `InstallOptions` from an instance of `ParsedInstallOptions`, or
+    /// return every error that was found in it
+    fn try_from(raw: ParsedInstallOptions) -> Result<Self, Errors>
+    {
+        let mut errors = Vec::new();
+        match Self::from_parsed(raw, &mut errors) {
+            Ok(_) if !errors.is_empty() => Err(errors),
+            Ok(options) => Ok(options),
+            Err(refusal) => {
+                errors.extend(refusal);
+                Err(errors)
+            },
+        }
+    }
+}
+
+impl InstallOptions
+{
+    /// Return the partition mounted at `/`
+    pub fn root(&self) -> &Partition
+    {
+        &self.partitions[self.boot.root]
+    }
+
+    /// Return the primary EFI system partition, the one the bootloader is installed onto
+    pub fn esp(&self) -> &Partition
+    {
+        &self.partitions[self.boot.esp]
+    }
+
+    /// Read the installation options, adding to `errors` what the rest can still be read without,
+    /// and returning what they're refused for as soon as it's found
+    fn from_parsed(raw: ParsedInstallOptions, errors: &mut Errors) -> Result<Self, Errors>
+    {
+        let kernel_name = raw.kernel.unwrap_or_default();
+        let kernel = Kernel::ALL.iter().copied()
+            .find(|k| k.name() == kernel_name)
+            .unwrap_or(Kernel::Lts); // assume LTS kernel at all times
+        let arch = match raw.arch.as_deref() {
+            None => Architecture::X86_64,
+            Some(name) => Architecture::ALL.iter().copied()
+                .find(|a| a.name() == name)
+                .ok_or_else(|| property("arch", || refusal!("invalid arch: \"{}\" (expected one of: {})",
+                    name, Architecture::ALL.iter().map(Architecture::name).collect::<Vec<&str>>().join(", "))))?,
+        };
+        let strict = raw.strict.unwrap_or(false);
+        let locales =
+            if let Some(l) = raw.locales {
+                match l[..] {
+                    [] => {
+                        warning!("locales not specified; defaulting to 'en_US.UTF-8'");
+                        vec!["en_US.UTF-8".to_string()]
+                    }
+                    _ => l
+                }
+            } else {
+                warning!("locales not specified; defaulting to 'en_US.UTF-8'");
+                vec!["en_US.UTF-8".to_string()]
+            };
+        let mut locales: Vec<String> = locales.iter().map(|l| normalize_locale("locales", l, strict)).collect::<Result<_, _>>()?;
+        // `en_US` and `en_US.UTF-8` are the same locale once normalized
+        let mut seen = vec![];
+        locales.retain(|l| if seen.contains(l) { false } else { seen.push(l.clone()); true });
+
+        let timezone = property("timezone", || validate_timezone(raw.timezone, errors));
+
+        let bootloader_name = raw.bootloader.ok_or_else(|| refusal!("no bootloader specified"))?;
+        let bootloader = Bootloader::ALL.iter().copied()
+            .find(|b| b.name() == bootloader_name)
+            .ok_or_else(|| property("bootloader", || refusal!("invalid bootloader: \"{}\" (expected one of: {})",
+                bootloader_name, Bootloader::ALL.iter().map(Bootloader::name).collect::<Vec<&str>>().join(", "))))?;
+        let chroot_backend = raw.chroot_backend.unwrap_or_else(|| "arch-chroot".to_string());
+        if !CHROOT_BACKENDS.contains(&chroot_backend.as_str()) {
+            property("chroot_backend", || error!(errors, "invalid chroot_backend: \"{}\" (expected one of: {})", chroot_backend, CHROOT_BACKENDS.join(", ")));
+        }
+        // turn every `ParsedPartition` into a proper `Partition`, letting the global options
+        // apply to those partitions that don't override them
+        let mut partitions: Vec<Partition> = raw.partitions.ok_or_else(|| refusal!("no partitions specified"))?
+            .into_iter()
+            .map(|p| ParsedPartition {
+                activate_swap: p.activate_swap.or(raw.activate_swap),
+                disk: p.disk.or_else(|| raw.disk.clone()),
+                ..p
+            })
+            .enumerate()
+            .map(|(i, p)| log::within(&[PathSegment::Key("partitions".to_string()), PathSegment::Index(i)], || {
+                validate_swap_mount(i + 1, p, strict).and_then(with_members)
+            }))
+            .collect::<Result<Vec<Vec<Partition>>, Errors>>()?
+            .into_iter()
+            .flatten()
+            .collect();
+        let boot = validate_bootloader(bootloader, &partitions)?;
+        validate_secondary_esps(bootloader, &chroot_backend, &partitions, boot)?;
+        validate_encryption(&partitions)?;
+        let initramfs_generator = raw.initramfs_generator.unwrap_or_else(|| "mkinitcpio".to_string());
+        if !INITRAMFS_GENERATORS.contains(&initramfs_generator.as_str()) {
+            property("initramfs_generator", || error!(errors, "invalid initramfs_generator: \"{}\" (expected one of: {})", initramfs_generator, INITRAMFS_GENERATORS.join(", ")));
+        }
+        let extra = raw.packages.unwrap_or_default();
+        validate_extra_names(&extra)?;
+        let extra = extra.join(" ");
+        let pacstrap_args = raw.pacstrap_args.unwrap_or_default();
+        if let Some(arg) = pacstrap_args.iter().find(|a| a.is_empty() || a.contains(['\n', '\0'])) {
+            property("pacstrap_args", || error!(errors, "pacstrap_args must be single lines, and not empty: {:?}", arg));
+        }
+        validate_arch(arch, kernel, bootloader, &initramfs_generator, &extra)?;
+        let maintenance = Maintenance::try_from(raw.maintenance.unwrap_or_default())?;
+        let hardening = Hardening::from(raw.hardening.unwrap_or_default());
+        let kernel_params = KernelParams::new(raw.kernel_params, raw.grub, raw.systemd_boot, raw.efistub, bootloader)?;
+        if arch != Architecture::X86_64 && maintenance.mirrorlist_update.is_some() {
+            refuse!("mirrorlist_update isn't supported on {}: reflector only knows the mirrors of Arch Linux, not those of Arch Linux ARM", arch.name());
+        }
+        let min_iso_version = raw.min_iso_version;
+        if let Some(version) = &min_iso_version {
+            property("min_iso_version", || validate_min_iso_version(version, errors));
+        }
+        if let Some(keymap) = &raw.keymap {
+            property("keymap", || validate_keymap(keymap, errors));
+        }
+        let allow_lints = raw.allow_lints.unwrap_or_default();
+        if let Some(name) = allow_lints.iter().find(|l| !crate::lint::is_lint(l)) {
+            property("allow_lints", || error!(errors, "invalid allow_lints: \"{}\" (expected one of: {})", name,
+                crate::lint::LINTS.iter().map(|l| l.name).collect::<Vec<&str>>().join(", ")));
+        }
+        let phases = raw.phases.unwrap_or_else(|| PHASES.iter().map(|(phase, _)| phase.to_string()).collect());
+        validate_phases(&phases)?;
+        let phases = PHASES.iter()
+            .map(|(phase, _)| phase.to_string())
+            .filter(|phase| phases.contains(phase))
+            .collect();
+        let hardware_clock = raw.hardware_clock.unwrap_or_else(|| "utc".to_string());
+        if !HARDWARE_CLOCKS.contains(&hardware_clock.as_str()) {
+            property("hardware_clock", || error!(errors, "invalid hardware_clock: \"{}\" (expected one of: {})", hardware_clock, HARDWARE_CLOCKS.join(", ")));
+        }
+        let power = raw.power.unwrap_or_else(|| "none".to_string());
+        if !POWER_DAEMONS.contains(&power.as_str()) {
+            property("power", || error!(errors, "invalid power: \"{}\" (expected one of: {})", power, POWER_DAEMONS.join(", ")));
+        }
+        let laptop = match &raw.laptop {
+            None => None,
+            Some(BoolOrAuto::Flag(laptop)) => Some(*laptop),
+            Some(BoolOrAuto::Word(word)) if word == "auto" => None,
+            Some(BoolOrAuto::Word(word)) => {
+                property("laptop", || error!(errors, "invalid laptop: \"{}\" (expected one of: auto, true, false)", word));
+                None
+            },
+        };
+        if raw.laptop.is_some() && power == "none" {
+            warning!("laptop is only used along with `power`, which is `none`; it's going to be ignored");
+        }
+        let previous_mounts = raw.previous_mounts.unwrap_or_else(|| "abort".to_string());
+        if !PREVIOUS_MOUNTS.contains(&previous_mounts.as_str()) {
+            property("previous_mounts", || error!(errors, "invalid previous_mounts: \"{}\" (expected one of: {})", previous_mounts, PREVIOUS_MOUNTS.join(", ")));
+        }
+        let mount_root = raw.mount_root.as_deref().map_or_else(|| Ok(DEFAULT_MOUNT_ROOT.to_string()), parse_mount_root)?;
+        let bootstrap = raw.bootstrap.unwrap_or_else(|| "pacstrap".to_string());
+        if !BOOTSTRAPS.contains(&bootstrap.as_str()) {
+            property("bootstrap", || error!(errors, "invalid bootstrap: \"{}\" (expected one of: {})", bootstrap, BOOTSTRAPS.join(", ")));
+        }
+        let tarball = match (bootstrap.as_str(), raw.tarball) {
+            ("tarball", raw) => Some(parse_tarball(raw.unwrap_or(ParsedTarball { source: None, sha256: None }), arch)?),
+            (_, Some(_)) => {
+                warning!("tarball is only used with `bootstrap: tarball`; it's going to be ignored");
+                None
+            },
+            (_, None) => None,
+        };
+        // systemctl spells them with `.target`, so they're taken either way
+        let default_target = raw.default_target.map(|t| t.strip_suffix(".target").map(String::from).unwrap_or(t));
+        if let Some(target) = default_target.as_deref().filter(|t| !DEFAULT_TARGETS.contains(t)) {
+            property("default_target", || error!(errors, "invalid default_target: \"{}\" (expected one of: {})", target, DEFAULT_TARGETS.join(", ")));
+        }
+        let snapshot_date = raw.snapshot_date;
+        let package_pins = raw.package_pins.unwrap_or_default();
+        if let Some(date) = &snapshot_date {
+            property("snapshot_date", || validate_snapshot_date(date))?;
+            if maintenance.mirrorlist_update.is_some() {
+                refuse!("snapshot_date and mirrorlist_update can't be used together, since reflector would replace the mirrorlist of the snapshot; remove one of the two")
+            }
+            warning!("the installed system gets its packages from the Arch Linux Archive as of {}, so it gets no security updates until /etc/pacman.d/mirrorlist lists current mirrors again", date);
+        }
+        for (name, version) in &package_pins {
+            validate_package_pin(name, version)?;
+        }
+        if arch != Architecture::X86_64 && (snapshot_date.is_some() || !package_pins.is_empty()) {
+            refuse!("snapshot_date and package_pins aren't supported on {}: the Arch Linux Archive only has packages for x86_64", arch.name());
+        }
+        let mkinitcpio_hooks = match initramfs_generator.as_str() {
+            "dracut" if raw.mkinitcpio_hooks.is_some() =>
+                refuse!("mkinitcpio_hooks can't be used with `initramfs_generator: dracut`; remove one of the two"),
+            "dracut" => None,
+            _ => validate_hooks(raw.mkinitcpio_hooks, &partitions, arch)?,
+        };
+        validate_boot_matrix(bootloader, &partitions, mkinitcpio_hooks.as_deref(), strict)?;
+        let image = raw.image.map(parse_disk_image).transpose()?;
+        let busybox = initramfs_generator != "dracut" && mkinitcpio_hooks.as_deref().map(hook_flavor).unwrap_or(HookFlavor::Busybox) == HookFlavor::Busybox;
+        validate_disk_images(image.as_ref(), bootloader, &partitions, busybox)?;
+        let xbootldr = find_xbootldr(bootloader, &partitions).map(|p| p.mount.clone());
+        // a `type_guid` given by hand takes precedence
+        for p in partitions.iter_mut().filter(|p| Some(&p.mount) == xbootldr.as_ref() && p.fdisk_type.is_none()) {
+            p.fdisk_type = Some(XBOOTLDR.to_string());
+        }
+        if raw.discoverable_partitions.unwrap_or(true) {
+            for p in partitions.iter_mut().filter(|p| p.fdisk_type.is_none()) {
+                // only Linux filesystems can be discovered
+                if filesystem(&p.format).is_some_and(|fs| fs.fdisk_type != "linux") {
+                    continue;
+                }
+                if p.mount == "/" {
+                    p.fdisk_type = Some(partition_type_guid(arch.root_partition_type())?);
+                } else if let Some((_, name)) = DISCOVERABLE_PARTITIONS.iter().find(|(mount, _)| *mount == p.mount) {
+                    p.fdisk_type = Some(partition_type_guid(name)?);
+                }
+            }
+        }
+        for (format, options) in raw.default_mount_options.unwrap_or_default() {
+            if filesystem(&format).is_none() {
+                warning!("default mount options given for unknown format '{}'; they're going to be ignored", format);
+            }
+            validate_mount_options(&options)?;
+            for p in partitions.iter_mut().filter(|p| p.format == format) {
+                p.mount_options = merge_mount_options(&options, &p.mount_options);
+            }
+        }
+        let mut raw_disks = BTreeMap::new();
+        for (name, disk) in raw.disks.unwrap_or_default() {
+            let name = normalize_disk(&name)?;
+            if raw_disks.insert(name.clone(), disk).is_some() {
+                refuse!("disk {} is declared more than once under `disks`", name);
+            }
+        }
+        merge_disk_spellings(&mut partitions, &mut raw_disks)?;
+        validate_reused(&partitions, &raw_disks, image.as_ref())?;
+        // the partitions of the files that are created have to fit in them, as on a declared disk
+        if let Some(size) = image.as_ref().and_then(|i| i.size) {
+            for disk in partitions.iter().map(|p| &p.disk).filter(|d| is_image_disk(d)) {
+                let declared = raw_disks.entry(disk.clone())
+                    .or_insert(ParsedDisk { size: None, min_remaining: None, pre_format: None, reserve_end: None });
+                match declared.size.as_deref().map(|s| (s, Size::parse(s))) {
+                    None => declared.size = Some(size.to_string()),
+                    Some((_, Some(declared))) if declared == size => (),
+                    Some((declared, _)) => refuse!("disk {} is declared under `disks` with a size of {}, but the size of `image` is {}; remove one of the two",
+                        disk, declared, size),
+                }
+            }
+        }
+        let mut pre_format = BTreeMap::new();
+        let mut reserve_end = BTreeMap::new();
+        // a disk may be declared only for its `pre_format` commands or its `reserve_end`, without
+        // its size being checked
+        let mut unplanned = vec![];
+        for (name, disk) in raw_disks.iter_mut() {
+            let cmds = device_cmds("pre_format", disk.pre_format.take())?;
+            let reserved = disk.reserve_end.take().map(|r| Size::parse(&r)
+                .ok_or_else(|| refusal!("invalid reserve_end for disk {}: \"{}\" (expected e.g. '10G')", name, r))).transpose()?;
+            if cmds.is_empty() && reserved.is_none() {
+                continue;
+            }
+            let planned = disk.size.is_some() || disk.min_remaining.is_some();
+            if !planned {
+                unplanned.push(name.clone());
+            }
+            let on_disk: Vec<&Partition> = partitions.iter().filter(|p| &p.disk == name).collect();
+            if !cmds.is_empty() {
+                if on_disk.is_empty() {
+                    warning!("disk {} has `pre_format` commands, but there are no partitions on it; they're going to be ignored", name);
+                } else {
+                    pre_format.insert(name.clone(), cmds);
+                }
+            }
+            if let Some(reserved) = reserved {
+                if on_disk.is_empty() {
+                    warning!("disk {} has `reserve_end`, but there are no partitions on it; it's going to be ignored", name);
+                } else if !planned && on_disk.iter().all(|p| p.size.is_some()) {
+                    warning!("disk {} has `reserve_end`, but none of its partitions takes the rest of it, so the end of the disk is left free anyway", name);
+                } else {
+                    reserve_end.insert(name.clone(), reserved);
+                }
+            }
+        }
+        raw_disks.retain(|name, _| !unplanned.contains(name));
+        let disks = raw_disks
+            .into_iter()
+            .map(|(name, disk)| {
+                let disk = log::within(&[PathSegment::Key("disks".to_string()), PathSegment::Key(name.clone())], || {
+                    plan_disk(&name, disk, &partitions, reserve_end.get(&name).copied())
+                })?;
+                Ok((name, disk))
+            })
+            .collect::<Result<_, Errors>>()?;
+        let firmware = Firmware::from(raw.firmware_packages);
+        validate_boot_space(bootloader, kernel.package(arch), &extra, &partitions)?;
+        validate_firmware(&firmware, &extra, &partitions);
+        if let Some(name) = raw.pretty_name.as_ref().filter(|n| n.contains('\n')) {
+            property("pretty_name", || error!(errors, "pretty_name must be a single line: {:?}", name));
+        }
+        let pretty_name = raw.pretty_name;
+        if let Some(editor) = &raw.default_editor {
+            if editor.is_empty() || editor.contains(|c: char| c.is_whitespace() || c == '\'' || c == '"') {
+                property("default_editor", || error!(errors, "default_editor must be the name of a program, without spaces or quotes: {:?}", editor));
+            }
+            if editor_package(editor).is_none() && !extra.split_whitespace().any(|p| p == editor) {
+                warning!("jimmy doesn't know which package provides the default editor '{}'; add it to `packages`", editor);
+            }
+        }
+        // turn every `ParsedUser` into a proper `User`
+        let users: Vec<User> = raw.users.unwrap_or_default().into_iter()
+            .map(|u| {
+                let u = User::try_from(u)?;
+                Ok(User {
+                    locale: u.locale.as_ref().map(|l| normalize_locale(&format!("the locale of user '{}'", u.name), l, strict)).transpose()?,
+                    ..u
+                })
+            })
+            .collect::<Result<_, Errors>>()?;
+        for user in &users {
+            if let Some(locale) = user.locale.as_ref().filter(|l| !locales.contains(l)) {
+                refuse!("user '{}' has the locale '{}', which isn't generated; add it to `locales`", user.name, locale);
+            }
+        }
+        if let Some(user) = users.iter().find(|u| u.home_encryption) {
+            validate_home_encryption(&user.name, &partitions)?;
+        }
+        for user in &users {
+            validate_user_services(user)?;
+        }
+        let first_boot = raw.first_boot.unwrap_or_default();
+        if let Some(cmd) = first_boot.iter().find(|c| c.contains('\0')) {
+            property("first_boot", || error!(errors, "first_boot command contains a NUL character: {:?}", cmd));
+        }
+        let has_secondary_esps = !secondary_esps(&partitions).is_empty();
+        if bootloader != Bootloader::Efistub && !has_secondary_esps {
+            if raw.boot_entry_label.is_some() {
+                warning!("boot_entry_label is only used with `bootloader: efistub` or secondary EFI system partitions; it's going to be ignored");
+            }
+            if raw.keep_existing_entries.is_some() {
+                warning!("keep_existing_entries is only used with `bootloader: efistub` or secondary EFI system partitions; it's going to be ignored");
+            }
+        }
+        if !has_secondary_esps && raw.esp_sync_hook.is_some() {
+            warning!("esp_sync_hook is only used with secondary EFI system partitions, marked `esp: true` and left unmounted; it's going to be ignored");
+        }
+        let esp_sync_hook = raw.esp_sync_hook.unwrap_or(false) && has_secondary_esps;
+        if bootloader != Bootloader::SystemdBoot && raw.systemd_boot_update.is_some() {
+            warning!("systemd_boot_update is only used with `bootloader: systemd-boot`; it's going to be ignored");
+        }
+        let systemd_boot_update = raw.systemd_boot_update.unwrap_or_else(|| "service".to_string());
+        if !SYSTEMD_BOOT_UPDATES.contains(&systemd_boot_update.as_str()) {
+            property("systemd_boot_update", || error!(errors, "invalid systemd_boot_update: \"{}\" (expected one of: {})", systemd_boot_update, SYSTEMD_BOOT_UPDATES.join(", ")));
+        }
+        let boot_entry_label = raw.boot_entry_label.unwrap_or_else(|| match kernel {
+            Kernel::Lts => "Arch Linux LTS".to_string(),
+            _ => "Arch Linux".to_string(),
+        });
+        if boot_entry_label.trim().is_empty() || boot_entry_label.contains(char::is_control) {
+            property("boot_entry_label", || error!(errors, "boot_entry_label must be a single line of text, without tabs: {:?}", boot_entry_label));
+        }
+        let timings = raw.timings.unwrap_or(true);
+        if let Some(path) = &raw.report {
+            if !path.starts_with('/') || path.ends_with('/') || path.contains(['\n', '\0']) {
+                property("report", || error!(errors, "report must be the absolute path of a file on the installed system: {:?}", path));
+            }
+            if !timings {
+                warning!("the report won't have the durations of the steps, since `timings` is off");
+            }
+        }
+        let directories: Vec<Directory> = raw.directories.unwrap_or_default().into_iter().map(Directory::try_from).collect::<Result<_, _>>()?;
+        for (i, directory) in directories.iter().enumerate() {
+            if directories[..i].iter().any(|d| d.path == directory.path) {
+                refuse!("directory {} is listed more than once under `directories`", directory.path)
+            }
+            let owner = directory.owner.as_deref().filter(|o| *o != "root");
+            if let Some(user) = owner.and_then(|o| users.iter().find(|u| u.name == o)) {
+                if user.home_encryption {
+                    refuse!("directory {} is owned by '{}', whose home_encryption means the user is only created on the first boot; use another owner",
+                        directory.path, user.name);
+                }
+            } else if let Some(owner) = owner {
+                warning!("directory {} is owned by '{}', who isn't one of the `users`; the user has to be created by a package", directory.path, owner);
+            }
+        }
+        let cleanup = raw.cleanup.unwrap_or_else(|| "keep-everything".to_string());
+        if !CLEANUP_POLICIES.contains(&cleanup.as_str()) {
+            property("cleanup", || error!(errors, "invalid cleanup: \"{}\" (expected one of: {})", cleanup, CLEANUP_POLICIES.join(", ")));
+        }
+        if cleanup == "remove-all" && raw.report.is_some() {
+            refuse!("a report is written, but `cleanup: remove-all` would remove it; use `cleanup: keep-report-only`")
+        }
+        let root_password_policy = raw.root_password_policy.unwrap_or_else(|| match raw.root_password_hash {
+            Some(_) => "hash".to_string(),
+            None => "prompt".to_string(),
+        });
+        if !ROOT_PASSWORD_POLICIES.contains(&root_password_policy.as_str()) {
+            return Err(property("root_password_policy", || refusal!("invalid root_password_policy: \"{}\" (expected one of: {})",
+                root_password_policy, ROOT_PASSWORD_POLICIES.join(", "))));
+        }
+        match (root_password_policy.as_str(), &raw.root_password_hash) {
+            ("hash", None) => refuse!("`root_password_policy: hash` needs a `root_password_hash`"),
+            ("hash", Some(hash)) => property("root_password_hash", || validate_password_hash(hash, errors)),
+            (policy, Some(_)) => refuse!("root_password_hash is only used with `root_password_policy: hash`, not `{}`", policy),
+            _ => (),
+        }
+        let root_password_attempts = raw.root_password_attempts.unwrap_or(3);
+        if raw.root_password_attempts.is_some() && root_password_policy != "prompt-with-fallback" {
+            warning!("root_password_attempts is only used with `root_password_policy: prompt-with-fallback`; it's going to be ignored");
+        } else if root_password_attempts == 0 {
+            property("root_password_attempts", || error!(errors, "root_password_attempts must be at least 1"));
+        }
+        if root_password_policy == "locked" && users.is_empty() {
+            warning!("root is locked and there are no `users`, so nobody can log in to the installed system");
+        }
+        let skel = raw.skel.map(|path| property("skel", || {
+            if !path.starts_with('/') || path.contains(['\n', '\0']) {
+                refuse!("skel must be the absolute path of a directory on the live system: {:?}", path)
+            }
+            let normalized = format!("/{}", path.split('/').filter(|c| !c.is_empty()).collect::<Vec<&str>>().join("/"));
+            if normalized == "/" {
+                refuse!("skel can't be the root directory of the live system")
+            }
+            Ok(normalized)
+        })).transpose()?;
+        if let Some(user) = users.iter().find(|u| u.skip_skel && u.home_encryption) {
+            refuse!("user '{}' has both skip_skel and home_encryption; systemd-homed always fills the home directory from /etc/skel", user.name)
+        }
+        let language = match raw.language {
+            None => Language::English,
+            Some(code) => Language::ALL.iter().copied()
+                .find(|l| l.code() == code)
+                .unwrap_or_else(|| {
+                    warning!("no status messages in language '{}'; defaulting to 'en'", code);
+                    Language::English
+                }),
+        };
+
+        let options = Self {
+            hostname: raw.hostname.ok_or_else(|| refusal!("hostname not specified"))?,
+            timezone,
+            locales,
+            kernel,
+            arch,
+            firmware,
+            extra,
+            bootloader,
+            boot,
+            chroot_backend,
+            partitions,
+            users,
+            timings,
+            fstab_extra: raw.fstab_extra.unwrap_or_default().into_iter().map(FstabEntry::try_from).collect::<Result<_, _>>()?,
+            issue: raw.issue,
+            motd: raw.motd,
+            pretty_name,
+            parallel_format: raw.parallel_format.unwrap_or(false),
+            language,
+            retries: raw.retries.unwrap_or(3),
+            pacstrap_args,
+            retry_delay: raw.retry_delay.unwrap_or(10),
+            default_editor: raw.default_editor,
+            first_boot,
+            mkinitcpio_hooks,
+            config_hash: String::new(),
+            disks,
+            pre_format,
+            reserve_end,
+            offline: raw.offline.unwrap_or(false),
+            initramfs_generator,
+            boot_entry_label,
+            esp_sync_hook,
+            keep_existing_entries: raw.keep_existing_entries.unwrap_or(false),
+            systemd_boot_update,
+            maintenance,
+            hardening,
+            kernel_params,
+            report: raw.report,
+            directories,
+            snapshot_date,
+            package_pins,
+            cleanup,
+            root_password_policy,
+            root_password_hash: raw.root_password_hash,
+            root_password_attempts,
+            skel,
+            keep_resolv_conf: raw.keep_resolv_conf,
+            min_iso_version,
+            keymap: raw.keymap,
+            hardware_clock,
+            allow_lints,
+            phases,
+            power,
+            laptop,
+            image,
+            previous_mounts,
+            default_target,
+            bootstrap,
+            tarball,
+            mount_root,
+        };
+        if options.hardening.hidepid && options.fstab_extra.iter().any(|e| e.dir.trim_end_matches('/') == "/proc") {
+            refuse!("`hidepid` under `hardening` writes the fstab entry for /proc, and fstab_extra has one already; remove one of them")
+        }
+        if options.hardware_clock == "localtime" && !options.hints_at_dual_boot() {
+            warning!("`hardware_clock: localtime` is only useful when the machine is shared with Windows, but nothing else in the configuration (os-prober, an ntfs partition, keep_existing_entries) hints at another system");
+        }
+        if options.keep_resolv_conf == Some(false) {
+            for feature in options.chroot_network_features() {
+                warning!("`keep_resolv_conf: false`, but {} needs the network inside arch-chroot, where names might not resolve", feature);
+            }
+        }
+        if options.default_target.as_deref() == Some("multi-user") {
+            if let Some(manager) = options.package_list().iter().find(|p| DISPLAY_MANAGERS.contains(&p.as_str())) {
+                warning!("`default_target: multi-user`, but the display manager {} is installed, and it only starts on graphical.target; use `default_target: graphical`", manager);
+            }
+        }
+        for message in crate::unused::unused(&options).messages() {
+            if strict {
+                refuse!("{}", message);
+            }
+            warning!("{}", message);
+        }
+        validate_extra(&options, strict)?;
+        Ok(options)
+    }
+}
+
+/// Struct that contains the minimum needed to create a partition on disk
+#[derive(Debug)]
+pub struct Partition
+{
+    pub format: String,
+    pub disk: String,
+    /// The size, or `None` for the partition that takes the rest of its disk
+    pub size: Option<Size>,
+    pub mount: String,
+    /// Extra arguments passed to the `mkfs` command, after the ones jimmy uses
+    pub mkfs_args: Vec<String>,
+    /// Priority given to `swapon`; only meaningful for swap partitions
+    pub swap_priority: Option<i32>,
+    /// Whether a swap partition is activated during the installation
+    pub activate_swap: bool,
+    /// The `fdisk` partition type, when it's not the one that goes with the format
+    pub fdisk_type: Option<String>,
+    /// Comma-separated options passed to `mount`
+    pub mount_options: String,
+    /// How the partition is encrypted with LUKS, if it is
+    pub encryption: Option<Encryption>,
+    /// Whether the partition is an EFI system partition; those that aren't mounted are kept in
+    /// sync with the one mounted at `/boot` or `/efi`
+    pub esp: bool,
+    /// Whether the partition is marked `unmounted: true`, to be left unmounted on purpose
+    pub unmounted: bool,
+    /// How btrfs spreads the data and metadata of the filesystem over its devices, if it's given
+    pub raid_profile: Option<String>,
+    /// The mount point of the btrfs filesystem this partition is a member of, if it's one; members
+    /// are created like any other partition, but they're formatted and mounted along with it
+    pub member_of: Option<String>,
+    /// Commands ran once the partition is formatted, before it's mounted, with `$DEVICE` set to
+    /// the file its filesystem is on
+    pub post_format: Vec<String>,
+    /// Whether the partition is marked `reuse: true`, to be kept as it is, with its data, and only
+    /// mounted; the partition table of its disk is kept along with it
+    pub reuse: bool,
 }
 
-/// *Potentially* valid partition options. Everything is wrapped in `Option<T>` because serde would
-/// error if the property isn't found.
-#[derive(Deserialize, Debug, Clone)]
-pub struct ParsedPartition
+/// The periodic cleanups and updates the installed system does by itself; all of them are off
+/// unless the configuration file turns them on
+#[derive(Debug, Default)]
+pub struct Maintenance
 {
-    pub format: Option<String>,
-    pub disk: Option<String>,
-    pub size: Option<String>,
-    pub mount: Option<String>,
+    /// Whether `paccache.timer` removes old versions of packages from the cache every week
+    pub paccache: bool,
+    /// Whether pacman lists the packages that nothing needs anymore after every transaction
+    pub orphan_cleanup: bool,
+    /// How often `reflector.timer` updates the mirrorlist, if at all
+    pub mirrorlist_update: Option<String>,
+    /// The countries whose mirrors reflector picks from; all of them if empty
+    pub mirrorlist_countries: Vec<String>,
 }
 
-/// *Potentially* valid user. Everything is wrapped in `Option<T>` because serde would error if the
-/// property isn't found.
-#[derive(Deserialize, Debug, Clone)]
-pub struct ParsedUser
+impl TryFrom<ParsedMaintenance> for Maintenance
 {
-    pub name: Option<String>,
-    pub groups: Option<Vec<String>>,
-    pub shell: Option<String>,
+    type Error = Errors;
+
+    fn try_from(raw: ParsedMaintenance) -> Result<Self, Errors>
+    {
+        if let Some(update) = raw.mirrorlist_update.as_ref().filter(|u| !MIRRORLIST_UPDATES.contains(&u.as_str())) {
+            refuse!("invalid mirrorlist_update: \"{}\" (expected one of: {})", update, MIRRORLIST_UPDATES.join(", "))
+        }
+        let countries = raw.mirrorlist_countries.unwrap_or_default();
+        if let Some(country) = countries.iter().find(|c| c.is_empty() || c.contains(|c: char| c == ',' || c.is_control())) {
+            refuse!("mirrorlist_countries must be names or codes of countries, without commas: {:?}", country)
+        }
+        if !countries.is_empty() && raw.mirrorlist_update.is_none() {
+            warning!("mirrorlist_countries is only used with `mirrorlist_update`; it's going to be ignored");
+        }
+        Ok(Self {
+            paccache: raw.paccache.unwrap_or(false),
+            orphan_cleanup: raw.orphan_cleanup.unwrap_or(false),
+            mirrorlist_update: raw.mirrorlist_update,
+            mirrorlist_countries: countries,
+        })
+    }
 }
 
-/// Only the Latest or the LTS kernel can be installed
-#[derive(Debug)]
-pub enum Kernel {
-    Latest,
-    Lts,
+/// The toggles under `hardening`, in the order they're applied in
+pub const HARDENING_TOGGLES: &[&str] = &["kernel_lockdown", "disable_coredumps", "restrict_dmesg", "hidepid"];
+
+/// Valid hardening options of the installed system
+#[derive(Debug, Clone, Default)]
+pub struct Hardening
+{
+    /// Whether the kernel is locked down, refusing what could change it while it runs
+    pub kernel_lockdown: bool,
+    /// Whether systemd-coredump throws away the core dumps of crashed programs
+    pub disable_coredumps: bool,
+    /// Whether only root can read the messages of the kernel
+    pub restrict_dmesg: bool,
+    /// Whether the processes of other users are hidden under /proc
+    pub hidepid: bool,
 }
 
-/// Struct that contains the minimum needed to create a functioning Arch installation
-#[derive(Debug)]
-pub struct InstallOptions
+impl From<ParsedHardening> for Hardening
 {
-    pub hostname: String,
-    pub region: String,
-    pub city: String,
-    pub locales: Vec<String>,
-    pub kernel: Kernel,
-    pub extra: String,
-    pub bootloader: String,
-    pub partitions: Vec<Partition>,
-    pub users: Vec<User>,
+    fn from(raw: ParsedHardening) -> Self
+    {
+        Self {
+            kernel_lockdown: raw.kernel_lockdown.unwrap_or(false),
+            disable_coredumps: raw.disable_coredumps.unwrap_or(false),
+            restrict_dmesg: raw.restrict_dmesg.unwrap_or(false),
+            hidepid: raw.hidepid.unwrap_or(false),
+        }
+    }
 }
 
-/// If the combination of region and timezone is valid, return true
-fn is_valid_zoneinfo(region: Option<String>, city: Option<String>) -> bool
+impl Hardening
 {
-    crate::is_file(&format!(
-        "/usr/share/zoneinfo/{}{}",
-        region.unwrap_or_default(),
-        if let Some(city) = city {
-            format!("/{}", city)
-        } else {
-            "".to_string()
+    /// Return the names of the toggles that are turned on, as they're written under `hardening`
+    pub fn enabled(&self) -> Vec<&'static str>
+    {
+        let on = [self.kernel_lockdown, self.disable_coredumps, self.restrict_dmesg, self.hidepid];
+        HARDENING_TOGGLES.iter().zip(on).filter(|(_, on)| *on).map(|(name, _)| *name).collect()
+    }
+}
+
+/// Check a selection of phases of the installation, or refuse if it has a phase that doesn't
+/// exist, none at all, or a phase without the one it can't be ran without
+pub fn validate_phases(phases: &[String]) -> Result<(), Errors>
+{
+    let names: Vec<&str> = PHASES.iter().map(|(phase, _)| *phase).collect();
+    if let Some(phase) = phases.iter().find(|p| !names.contains(&p.as_str())) {
+        refuse!("invalid phases: \"{}\" (expected one of: {})", phase, names.join(", "))
+    }
+    if phases.is_empty() {
+        refuse!("no phases of the installation are selected, so the script would do nothing")
+    }
+    for (phase, needs) in PHASES {
+        if let (true, Some(needs)) = (phases.iter().any(|p| p == phase), needs) {
+            if !phases.iter().any(|p| p == needs) {
+                refuse!("the phase {} needs the phase {}, which isn't selected", phase, needs)
+            }
+        }
+    }
+    Ok(())
+}
+
+/// The parameters of the kernel command line that jimmy sets by itself, and that the
+/// configuration file can't give
+pub const RESERVED_KERNEL_PARAMS: &[&str] = &["root", "cryptdevice", "rw", "ro", "initrd"];
+
+/// The parameters of the kernel command line given in the configuration file, beyond those that
+/// tell the initramfs where the root filesystem is
+#[derive(Debug, Default)]
+pub struct KernelParams
+{
+    /// The parameters of `kernel_params`, given whichever the bootloader is
+    pub shared: Vec<String>,
+    /// The parameters given only by the chosen bootloader, merged after the shared ones:
+    /// `grub.cmdline_linux`, `systemd_boot.extra_params` or `efistub.extra_params`
+    pub bootloader: Vec<String>,
+    /// What `GRUB_CMDLINE_LINUX_DEFAULT` is set to, if it's replaced; GRUB only gives it to the
+    /// normal boot entries, and not to the recovery ones
+    pub grub_default: Option<Vec<String>>,
+}
+
+/// Return the name of a kernel parameter, what's before its `=`
+pub fn kernel_param_name(param: &str) -> &str
+{
+    param.split_once('=').map(|(name, _)| name).unwrap_or(param)
+}
+
+/// Check the kernel parameters of `option`, or refuse if one of them can't be given: it has
+/// characters that would break the quoting of the commands that write it, or it's one jimmy sets
+/// by itself
+fn validate_kernel_params(option: &str, raw: Option<StringOrList>) -> Result<Vec<String>, Errors>
+{
+    let params = raw.map(StringOrList::into_words).unwrap_or_default();
+    for param in &params {
+        if param.is_empty() || param.contains(|c: char| c.is_whitespace() || c.is_control() || "'\"`\\|&$".contains(c)) {
+            refuse!("invalid {}: \"{}\" (expected a kernel parameter, such as quiet or console=ttyS0,115200, without quotes, backslashes, `|`, `&` or `$`)", option, param)
+        }
+        if RESERVED_KERNEL_PARAMS.contains(&kernel_param_name(param)) {
+            refuse!("{} can't have {}: jimmy sets the parameters {} by itself", option, param, RESERVED_KERNEL_PARAMS.join(", "))
+        }
+    }
+    Ok(params)
+}
+
+impl KernelParams
+{
+    /// Collect the parameters of the configuration file that the chosen bootloader gives, and
+    /// warn about the sections of the other bootloaders
+    fn new(
+        shared: Option<StringOrList>,
+        grub: Option<ParsedGrub>,
+        systemd_boot: Option<ParsedBootEntry>,
+        efistub: Option<ParsedBootEntry>,
+        bootloader: Bootloader,
+    ) -> Result<Self, Errors>
+    {
+        if grub.is_some() && bootloader != Bootloader::Grub {
+            warning!("grub is only used with `bootloader: grub`; it's going to be ignored");
+        }
+        if systemd_boot.is_some() && bootloader != Bootloader::SystemdBoot {
+            warning!("systemd_boot is only used with `bootloader: systemd-boot`; it's going to be ignored");
         }
-    ))
+        if efistub.is_some() && bootloader != Bootloader::Efistub {
+            warning!("efistub is only used with `bootloader: efistub`; it's going to be ignored");
+        }
+        let grub = grub.unwrap_or_default();
+        let shared = validate_kernel_params("kernel_params", shared)?;
+        let cmdline_linux = validate_kernel_params("grub.cmdline_linux", grub.cmdline_linux)?;
+        let cmdline_linux_default = grub.cmdline_linux_default
+            .map(|params| validate_kernel_params("grub.cmdline_linux_default", Some(params)))
+            .transpose()?;
+        let systemd_boot = validate_kernel_params("systemd_boot.extra_params", systemd_boot.and_then(|s| s.extra_params))?;
+        let efistub = validate_kernel_params("efistub.extra_params", efistub.and_then(|e| e.extra_params))?;
+        Ok(match bootloader {
+            Bootloader::Grub => Self { shared, bootloader: cmdline_linux, grub_default: cmdline_linux_default },
+            Bootloader::SystemdBoot => Self { shared, bootloader: systemd_boot, grub_default: None },
+            Bootloader::Efistub => Self { shared, bootloader: efistub, grub_default: None },
+        })
+    }
 }
 
-impl From<ParsedInstallOptions> for InstallOptions
+/// How a partition is encrypted with LUKS
+#[derive(Debug)]
+pub struct Encryption
+{
+    /// Whether a key sealed by the TPM2 chip is enrolled on the first boot, so that the partition
+    /// unlocks without asking for the passphrase
+    pub tpm2: bool,
+    /// The PCRs the TPM2 key is bound to
+    pub pcrs: Vec<u32>,
+    /// Where, on the root partition, the key that unlocks the partition on boot is stored
+    pub keyfile: Option<String>,
+}
+
+impl TryFrom<ParsedEncryption> for Encryption
 {
-    /// Create a new instance of `InstallOptions` from an instance of `ParsedInstallOptions`
-    fn from(raw: ParsedInstallOptions) -> Self
+    type Error = Errors;
+
+    fn try_from(raw: ParsedEncryption) -> Result<Self, Errors>
     {
-        let kernel = match raw.kernel.unwrap_or_default().as_str() {
-            "latest" => Kernel::Latest,
-            _ => Kernel::Lts, // assume LTS kernel at all times
+        let pcrs = raw.pcrs.unwrap_or_else(|| vec![7]);
+        if let Some(pcr) = pcrs.iter().find(|pcr| **pcr > 23) {
+            refuse!("TPM2 PCRs are numbered from 0 to 23, not {}", pcr)
+        }
+        if let Some(keyfile) = &raw.keyfile {
+            if !keyfile.starts_with('/') {
+                refuse!("keyfile is a relative path: \"{}\"", keyfile)
+            }
+            if keyfile.ends_with('/') || keyfile.contains(|c: char| c.is_whitespace() || c == '\'' || c == '"') {
+                refuse!("keyfile must be the path of a file, without spaces or quotes: {:?}", keyfile)
+            }
+        }
+        Ok(Self {
+            tpm2: raw.tpm2.unwrap_or(false),
+            pcrs,
+            keyfile: raw.keyfile,
+        })
+    }
+}
+
+/// The hooks of an initramfs that's started by systemd, as created by `mkinitcpio` for a system
+/// with an encrypted root partition
+pub const SYSTEMD_HOOKS: &[&str] = &[
+    "base", "systemd", "autodetect", "microcode", "modconf", "kms", "keyboard", "sd-vconsole",
+    "block", "sd-encrypt", "filesystems", "fsck",
+];
+
+/// The hooks that only work in an initramfs started by busybox, and the ones that only work in an
+/// initramfs started by systemd
+const BUSYBOX_ONLY_HOOKS: &[&str] = &["udev", "encrypt", "keymap", "consolefont", "usr", "resume", "shutdown"];
+const SYSTEMD_ONLY_HOOKS: &[&str] = &["systemd", "sd-encrypt", "sd-vconsole"];
+
+/// The two kinds of initramfs `mkinitcpio` can create; their hooks can't be mixed
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum HookFlavor {
+    Busybox,
+    Systemd,
+}
+
+/// Return the kind of initramfs a list of `mkinitcpio` hooks creates; `validate_hooks` refuses the
+/// lists that mix hooks of both kinds
+pub fn hook_flavor(hooks: &[String]) -> HookFlavor
+{
+    match hooks.iter().any(|h| SYSTEMD_ONLY_HOOKS.contains(&h.as_str())) {
+        true => HookFlavor::Systemd,
+        false => HookFlavor::Busybox,
+    }
+}
+
+/// Return the `mkinitcpio` hooks that the installed system should use, or `None` if the default
+/// ones do. Refuse if the hooks mix both kinds of initramfs, or if they can't unlock the encrypted
+/// root partition
+fn validate_hooks(hooks: Option<Vec<String>>, partitions: &[Partition], arch: Architecture) -> Result<Option<Vec<String>>, Errors>
+{
+    let root_encryption = partitions.iter()
+        .find(|p| p.mount == "/")
+        .and_then(|p| p.encryption.as_ref());
+    let hooks = match (hooks, root_encryption) {
+        (Some(hooks), _) => hooks,
+        (None, Some(_)) => return Ok(Some(SYSTEMD_HOOKS.iter()
+            // there's no microcode to load on other processors
+            .filter(|h| arch == Architecture::X86_64 || **h != "microcode")
+            .map(|h| h.to_string())
+            .collect())),
+        (None, None) => return Ok(None),
+    };
+    let busybox = hooks.iter().find(|h| BUSYBOX_ONLY_HOOKS.contains(&h.as_str()));
+    let systemd = hooks.iter().find(|h| SYSTEMD_ONLY_HOOKS.contains(&h.as_str()));
+    if let (Some(b), Some(s)) = (busybox, systemd) {
+        refuse!("mkinitcpio_hooks mixes the busybox hook '{}' with the systemd hook '{}'; use only one kind", b, s)
+    }
+    let flavor = hook_flavor(&hooks);
+    if let Some(encryption) = root_encryption {
+        let needed = match flavor {
+            HookFlavor::Busybox => "encrypt",
+            HookFlavor::Systemd => "sd-encrypt",
         };
-        let locales =
-            if let Some(l) = raw.locales {
-                match l[..] {
-                    [] => {
-                        eprintln!("warning: locales not specified; defaulting to 'en_US.UTF-8'");
-                        vec!["en_US.UTF-8".to_string()]
-                    }
-                    _ => l
-                }
-            } else {
-                eprintln!("warning: locales not specified; defaulting to 'en_US.UTF-8'");
-                vec!["en_US.UTF-8".to_string()]
-            };
+        if !hooks.iter().any(|h| h == needed) {
+            refuse!("the root partition is encrypted, so mkinitcpio_hooks needs the '{}' hook", needed)
+        }
+        if encryption.tpm2 && flavor == HookFlavor::Busybox {
+            refuse!("unlocking the root partition with the TPM2 chip needs an initramfs started by systemd; use the 'systemd' and 'sd-encrypt' hooks in mkinitcpio_hooks")
+        }
+    }
+    Ok(Some(hooks))
+}
 
-        if !is_valid_zoneinfo(raw.region.clone(), raw.city.clone()) {
-            panic!("invalid zoneinfo (region: '{:?}', city: '{:?}'", raw.region, raw.city);
+/// Refuse if the partitions can't be encrypted: only those with a mount point can, except swap and
+/// the fat32 ones the firmware reads; if the root partition is, the kernels need a /boot partition
+/// of their own, since bootloaders can't read them from an encrypted one. The keyfiles are checked
+/// by `validate_keyfile`
+fn validate_encryption(partitions: &[Partition]) -> Result<(), Errors>
+{
+    for p in partitions.iter().filter(|p| p.encryption.is_some()) {
+        if p.mount.is_empty() || p.format == "swap" {
+            refuse!("only partitions with a mount point can be encrypted, not the {} one on {}", p.format, p.disk)
         }
+        if p.format == "fat32" {
+            refuse!("the partition mounted at {} can't be encrypted, since the firmware needs to read it", p.mount)
+        }
+        if p.mount == "/" && !partitions.iter().any(|p| p.mount == "/boot") {
+            refuse!("the root partition is encrypted, so the kernels need a partition of their own; add a partition with `mount: /boot`")
+        }
+        if let Some(keyfile) = p.encryption.as_ref().and_then(|e| e.keyfile.as_ref()) {
+            validate_keyfile(keyfile, p, partitions)?;
+        }
+    }
+    Ok(())
+}
 
-        Self {
-            hostname: raw.hostname.expect("error: hostname not specified"),
-            region: raw.region.unwrap_or_default(),
-            city: raw.city.unwrap_or_default(),
-            locales,
-            kernel,
-            extra: raw.extra.unwrap_or_default(),
-            bootloader: raw.bootloader.expect("error: no bootloader specified"),
-            // turn every `ParsedPartition` into a proper `Partition`
-            partitions: raw.partitions.expect("error: no partitions specified")
-                            .into_iter().map(|p| p.into()).collect(),
-            // turn every `ParsedUser` into a proper `User`
-            users: raw.users.unwrap_or_default().into_iter().map(|u| u.into()).collect(),
+/// Check that the keyfile of an encrypted partition is stored on the root partition, which is
+/// unlocked before the others are
+fn validate_keyfile(keyfile: &str, partition: &Partition, partitions: &[Partition]) -> Result<(), Errors>
+{
+    if partition.mount == "/" {
+        refuse!("the root partition can't be unlocked with a keyfile, since the keyfile would be stored on it")
+    }
+    let root = partitions
+        .iter()
+        .find(|p| p.mount == "/")
+        .ok_or_else(|| refusal!("no root partition"))?;
+    if let Some(other) = partitions
+        .iter()
+        .filter(|p| p.mount != "/" && !p.mount.is_empty() && p.format != "swap")
+        .find(|p| keyfile.starts_with(&format!("{}/", p.mount)))
+    {
+        refuse!("the keyfile {} would be stored on the partition mounted at {}; it must be stored on the root partition",
+            keyfile, other.mount)
+    }
+    if root.encryption.is_none() {
+        warning!("the keyfile {} of the partition mounted at {} is stored on an unencrypted root partition; anyone with the disk can unlock it",
+            keyfile, partition.mount);
+    }
+    Ok(())
+}
+
+/// Return the path under `/dev/disk` of a disk given by a stable identifier (`by-id:...` or
+/// `wwn:...`), or `None` if the disk was given as a plain path
+pub fn stable_disk_path(disk: &str) -> Option<String>
+{
+    if let Some(id) = disk.strip_prefix("by-id:") {
+        Some(format!("/dev/disk/by-id/{}", id))
+    } else {
+        disk.strip_prefix("wwn:").map(|wwn| format!("/dev/disk/by-id/wwn-{}", wwn))
+    }
+}
+
+/// Return whether a disk is a file to install onto, such as `./test.img`, rather than a device:
+/// a path that isn't under /dev
+pub fn is_image_disk(disk: &str) -> bool
+{
+    stable_disk_path(disk).is_none() && !disk.starts_with("/dev/")
+}
+
+/// Return a disk as it should be used: with repeated and trailing slashes, and `.`, removed from
+/// paths.
+/// Refuse if it isn't a path or a stable identifier, or if it looks like a partition; relative
+/// paths are only ever disk images, which `validate_disk_images` checks
+pub fn normalize_disk(disk: &str) -> Result<String, Errors>
+{
+    if let Some(id) = disk.strip_prefix("by-id:").or_else(|| disk.strip_prefix("wwn:")) {
+        if id.is_empty() || id.contains('/') {
+            refuse!("invalid disk identifier: \"{}\"", disk)
+        }
+        if let Some(m) = Regex::new(r"-part\d+$").unwrap().find(id) {
+            refuse!("disk \"{}\" is a partition; use the identifier of the whole disk: \"{}\"",
+                disk, &disk[..disk.len() - m.as_str().len()])
+        }
+        // udev names the links of World Wide Names in lowercase, whatever case they're written in
+        if let Some(wwn) = disk.strip_prefix("wwn:") {
+            return Ok(format!("wwn:{}", wwn.to_lowercase()));
+        }
+        return Ok(disk.to_string());
+    }
+    let components = disk.split('/').filter(|c| !c.is_empty() && *c != ".").collect::<Vec<&str>>().join("/");
+    if components.is_empty() {
+        refuse!("invalid disk: \"{}\"", disk)
+    }
+    let normalized = match disk.starts_with('/') {
+        true => format!("/{}", components),
+        false => components,
+    };
+
+    // partitions of SCSI, virtio, Xen and IDE disks are numbered right after the disk's name,
+    // while disks whose names end in a digit separate them with a 'p'
+    let partition_patterns = [
+        r"^(/dev/(?:sd|vd|xvd|hd)[a-z]+)\d+$",
+        r"^(/dev/(?:nvme\d+n\d+|mmcblk\d+|loop\d+|md\d+))p\d+$",
+        r"^(/dev/disk/by-[a-z]+/.+)-part\d+$",
+    ];
+    for pattern in partition_patterns {
+        if let Some(c) = Regex::new(pattern).unwrap().captures(&normalized) {
+            refuse!("disk \"{}\" is a partition; use the whole disk: \"{}\"", disk, &c[1])
         }
     }
+    Ok(normalized)
 }
 
-/// Struct that contains the minimum needed to create a partition on disk
-#[derive(Debug)]
-pub struct Partition
+/// Return the directory under which the paths of disks are looked up when checking whether two of
+/// them are the same device: `/`, unless `JIMMY_DEVICE_ROOT` says otherwise
+fn device_root() -> PathBuf
 {
-    pub format: String,
-    pub disk: String,
-    pub size: String,
-    pub mount: String,
+    std::env::var_os("JIMMY_DEVICE_ROOT").map(PathBuf::from).unwrap_or_else(|| PathBuf::from("/"))
+}
+
+/// Return the device a disk is on the machine jimmy is running on, with every symbolic link
+/// resolved, or `None` if it doesn't exist there
+fn resolve_disk(disk: &str, root: &Path) -> Option<PathBuf>
+{
+    // disk images are files on the machine the script runs on, where relative paths start from
+    // the directory it's ran in
+    if is_image_disk(disk) {
+        return None;
+    }
+    let path = stable_disk_path(disk).unwrap_or_else(|| disk.to_string());
+    std::fs::canonicalize(root.join(path.trim_start_matches('/'))).ok()
+}
+
+/// Put the partitions of disks that are written in different ways, but are the same device on the
+/// machine jimmy is running on (e.g. a `by-id:` identifier and the `/dev/sdX` it links to), on the
+/// disk as it's first written, so that the disk is partitioned only once. Refuse if the
+/// partitions or the sizes declared under the different names can't go together
+fn merge_disk_spellings(partitions: &mut [Partition], disks: &mut BTreeMap<String, ParsedDisk>) -> Result<(), Errors>
+{
+    let root = device_root();
+    let mut names: Vec<String> = Vec::new();
+    for p in partitions.iter() {
+        if !names.contains(&p.disk) {
+            names.push(p.disk.clone());
+        }
+    }
+    let mut seen: Vec<(PathBuf, String)> = Vec::new();
+    for name in names {
+        let device = match resolve_disk(&name, &root) {
+            Some(device) => device,
+            None => continue,
+        };
+        let first = match seen.iter().find(|(d, _)| *d == device) {
+            Some((_, first)) => first.clone(),
+            None => {
+                seen.push((device, name));
+                continue;
+            }
+        };
+        let shown = Path::new("/").join(device.strip_prefix(&root).unwrap_or(&device));
+        if disks.contains_key(&first) && disks.contains_key(&name) {
+            refuse!("disks {} and {} are the same device ({}), but both are declared under `disks`; keep only one of them",
+                first, name, shown.display());
+        }
+        let unsized_partitions = partitions.iter()
+            .filter(|p| (p.disk == first || p.disk == name) && p.size.is_none() && !p.reuse)
+            .count();
+        if unsized_partitions > 1 {
+            refuse!("disks {} and {} are the same device ({}), and both have a partition without a `size`, but only one of them can take the rest of the disk",
+                first, name, shown.display());
+        }
+        warning!("disks {} and {} are the same device ({}); its partitions are all going to be made on {}",
+            first, name, shown.display(), first);
+        for p in partitions.iter_mut().filter(|p| p.disk == name) {
+            p.disk = first.clone();
+        }
+        if let Some(disk) = disks.remove(&name) {
+            disks.insert(first, disk);
+        }
+    }
+    Ok(())
 }
 
-impl From<ParsedPartition> for Partition
+/// Refuse if the `user_services` of a user aren't names of units, or if the user lingers or has
+/// units enabled but their home directory is encrypted, since systemd-homed only unlocks it when
+/// they log in
+fn validate_user_services(user: &User) -> Result<(), Errors>
 {
+    let unit = Regex::new(r"^[A-Za-z0-9:_.@-]+\.(service|socket|timer|path|target)$").unwrap();
+    if let Some(name) = user.user_services.iter().find(|u| !unit.is_match(u)) {
+        refuse!("invalid user_services of user '{}': \"{}\" (expected the name of a unit, such as podman.socket or backup.timer)",
+            user.name, name);
+    }
+    if user.home_encryption && (user.linger || !user.user_services.is_empty()) {
+        refuse!("user '{}' has `linger` or `user_services`, but their home directory is encrypted, and systemd-homed only unlocks it when they log in; \
+            remove `home_encryption`, or those", user.name);
+    }
+    Ok(())
+}
+
+/// Return an absolute path on the installed system without repeated or trailing slashes. Refuse if
+/// it's relative, has `.` or `..` in it, or is the root directory itself; `what` tells what the
+/// path is for
+fn normalize_target_path(what: &str, path: &str) -> Result<String, Errors>
+{
+    if !path.starts_with('/') {
+        refuse!("{} is a relative path: \"{}\"", what, path)
+    }
+    if path.contains(['\n', '\0']) {
+        refuse!("{} contains a newline or a NUL character: {:?}", what, path)
+    }
+    let components: Vec<&str> = path.split('/').filter(|c| !c.is_empty()).collect();
+    if components.iter().any(|c| *c == "." || *c == "..") {
+        refuse!("{} must not have `.` or `..` in it: \"{}\"", what, path)
+    }
+    if components.is_empty() {
+        refuse!("{} can't be the root directory", what)
+    }
+    Ok(format!("/{}", components.join("/")))
+}
+
+/// Return a file mode as the octal digits `install -m` and `chmod` take, e.g. `0750`. Refuse if
+/// it isn't made of three or four octal digits; `what` tells what the mode is for
+fn validate_mode(what: &str, mode: Mode) -> Result<String, Errors>
+{
+    let digits = match mode {
+        Mode::Text(digits) => digits,
+        Mode::Number(n) => n.to_string(),
+    };
+    let digits = digits.strip_prefix("0o").unwrap_or(&digits);
+    if !(3..=4).contains(&digits.len()) || !digits.chars().all(|c| ('0'..='7').contains(&c)) {
+        refuse!("invalid mode for {}: \"{}\" (expected three or four octal digits, e.g. '0750')", what, digits)
+    }
+    Ok(format!("{:0>4}", digits))
+}
+
+/// Refuse if a list of mount options couldn't be passed to `mount -o`
+fn validate_mount_options(options: &str) -> Result<(), Errors>
+{
+    if options.contains(char::is_whitespace) {
+        refuse!("mount options can't contain whitespace: \"{}\"", options)
+    }
+    Ok(())
+}
+
+/// Merge two comma-separated lists of mount options. An option from `specific` replaces the one
+/// with the same name from `defaults` (e.g. `compress=zstd:1` replaces `compress=zstd`), in place;
+/// the others are added at the end
+pub fn merge_mount_options(defaults: &str, specific: &str) -> String
+{
+    let name = |option: &str| option.split('=').next().unwrap_or_default().to_string();
+    let mut merged: Vec<&str> = Vec::new();
+    for option in defaults.split(',').chain(specific.split(',')).filter(|o| !o.is_empty()) {
+        match merged.iter().position(|o| name(o) == name(option)) {
+            Some(i) => merged[i] = option,
+            None => merged.push(option),
+        }
+    }
+    merged.join(",")
+}
+
+/// The range of priorities accepted by `swapon -p`
+const SWAP_PRIORITY_MIN: i32 = -1;
+const SWAP_PRIORITY_MAX: i32 = 32767;
+
+impl TryFrom<ParsedPartition> for Partition
+{
+    type Error = Errors;
+
     /// Create a new instance of `Partition` from an instance of `ParsedPartition`
-    fn from(raw: ParsedPartition) -> Self
+    fn try_from(raw: ParsedPartition) -> Result<Self, Errors>
     {
-        let format: String;
-        if raw.format.is_none() || raw.format.as_ref().unwrap() == "" {
-            eprintln!("warning: partition format not specified; defaulting to 'ext4'");
-            format = "ext4".to_string();
-        } else {
-            format = raw.format.unwrap();
+        let format = match raw.format.clone() {
+            Some(f) if !f.is_empty() => f,
+            _ => {
+                warning!("partition format not specified; defaulting to 'ext4'");
+                "ext4".to_string()
+            }
+        };
+        let mount = raw.mount.clone().unwrap_or_default();
+        let esp = raw.esp.unwrap_or(false);
+        if esp && format != "fat32" {
+            refuse!("a partition marked `esp: true` must be formatted as 'fat32', not '{}'", format)
         }
-        if raw.mount.is_none() || raw.mount.as_ref().unwrap() == "" {
-            eprintln!("warning: partition mount not specified; it's not going to be mounted");
-        } else if !raw.mount.as_ref().unwrap().starts_with("/") {
-            panic!("mount point is a relative path: \"{}\"", raw.mount.unwrap())
+        let unmounted = raw.unmounted.unwrap_or(false);
+        if !mount.is_empty() && !mount.starts_with('/') {
+            refuse!("mount point is a relative path: \"{}\"", mount)
         }
-        Self {
+        // those without a mount point that aren't marked `unmounted: true` are reported by the
+        // unused pass, which knows which of them are kept in sync or are members of another
+        if !mount.is_empty() && unmounted && format != "swap" {
+            refuse!("partition mounted at {} is also marked `unmounted: true`; remove one of the two", mount)
+        }
+        let reuse = raw.reuse.unwrap_or(false);
+        if reuse {
+            validate_reuse(&raw, &format, &mount)?;
+        }
+        let mkfs_args = raw.mkfs_args.map(StringOrList::into_words).unwrap_or_default();
+        if let Some(arg) = mkfs_args.iter().find(|a| a.contains(['\n', '\0'])) {
+            refuse!("mkfs argument contains a newline or a NUL character: {:?}", arg)
+        }
+        if let Some(priority) = raw.swap_priority {
+            if format != "swap" {
+                warning!("swap priority specified for a '{}' partition; it's going to be ignored", format);
+            } else if !(SWAP_PRIORITY_MIN..=SWAP_PRIORITY_MAX).contains(&priority) {
+                refuse!("swap priority must be between {} and {}, not {}",
+                    SWAP_PRIORITY_MIN, SWAP_PRIORITY_MAX, priority)
+            }
+        }
+        let mount_options = raw.mount_options.unwrap_or_default();
+        validate_mount_options(&mount_options)?;
+        let encryption = raw.encryption.map(Encryption::try_from).transpose()?;
+        let fdisk_type = raw.type_guid.as_deref().map(partition_type_guid).transpose()?;
+        if let Some(guid) = &fdisk_type {
+            check_partition_type(guid, &format);
+        }
+        if let Some(profile) = &raw.raid_profile {
+            log::within(&[PathSegment::Key("raid_profile".to_string())], || {
+                if format != "btrfs" {
+                    refuse!("raid_profile is only used with `format: btrfs`, not '{}'", format)
+                }
+                if !RAID_PROFILES.iter().any(|(name, _)| name == profile) {
+                    refuse!("invalid raid_profile: \"{}\" (expected one of: {})",
+                        profile, RAID_PROFILES.iter().map(|(name, _)| *name).collect::<Vec<&str>>().join(", "))
+                }
+                Ok(())
+            })?;
+        }
+        let disk = normalize_disk(&raw.disk.ok_or_else(|| refusal!("partition disk not specified, and there's no `disk` for every partition"))?)?;
+        Ok(Self {
             format,
-            disk: raw.disk.expect("error: partition disk not specified"),
-            size: raw.size.unwrap_or_else(|| "".to_string()),
-            mount: raw.mount.unwrap_or_else(|| "".to_string()),
+            disk,
+            size: raw.size.as_deref().map(parse_partition_size).transpose()?,
+            mount,
+            mkfs_args,
+            swap_priority: raw.swap_priority,
+            activate_swap: raw.activate_swap.unwrap_or(true),
+            fdisk_type,
+            mount_options,
+            encryption,
+            esp,
+            unmounted,
+            raid_profile: raw.raid_profile,
+            member_of: None,
+            post_format: device_cmds("post_format", raw.post_format)?,
+            reuse,
+        })
+    }
+}
+
+/// Check a partition marked `reuse: true`, before it's made into a `Partition`: it's kept as it
+/// is, so it can't be resized, formatted, encrypted or given another type, and it's mounted on
+/// the new system, whose root isn't reused. Its filesystem has to be one that the script can tell
+/// is on it
+fn validate_reuse(raw: &ParsedPartition, format: &str, mount: &str) -> Result<(), Errors>
+{
+    if raw.size.is_some() {
+        refuse!("a partition marked `reuse: true` can't be given a `size`, since it's kept as it is; remove it")
+    }
+    if mount.is_empty() {
+        refuse!("a partition marked `reuse: true` needs a mount point, since it's kept for the new system to use it")
+    }
+    if mount == "/" {
+        refuse!("the root partition can't be reused, since the new system is installed on it; remove `reuse: true`")
+    }
+    let set = [
+        ("mkfs_args", raw.mkfs_args.is_some()),
+        ("encryption", raw.encryption.is_some()),
+        ("type_guid", raw.type_guid.is_some()),
+        ("esp", raw.esp.is_some()),
+        ("raid_profile", raw.raid_profile.is_some()),
+        ("members", raw.members.is_some()),
+        ("post_format", raw.post_format.is_some()),
+    ];
+    if let Some((property, _)) = set.iter().find(|(_, given)| *given) {
+        refuse!("the partition mounted at {} is reused, so it's neither formatted nor changed; remove `{}`", mount, property)
+    }
+    if filesystem(format).is_none_or(|fs| fs.format == "swap") {
+        refuse!("invalid format for the reused partition mounted at {}: \"{}\" (expected one of: {})",
+            mount, format, FILESYSTEMS.iter().map(|fs| fs.format).filter(|f| *f != "swap").collect::<Vec<&str>>().join(", "))
+    }
+    Ok(())
+}
+
+/// Check the partitions marked `reuse: true` against the rest of their disks, whose partition
+/// tables are kept: the new partitions come before the reused ones, since they're created where
+/// the partitions they replace were, and the disk isn't declared under `disks`, whose checks and
+/// commands are about the whole of it, nor is it a disk image the script creates or checks
+fn validate_reused(partitions: &[Partition], disks: &BTreeMap<String, ParsedDisk>, image: Option<&DiskImage>) -> Result<(), Errors>
+{
+    for disk in partitions.iter().filter(|p| p.reuse).map(|p| &p.disk) {
+        let on_disk: Vec<&Partition> = partitions.iter().filter(|p| &p.disk == disk).collect();
+        let first = on_disk.iter().position(|p| p.reuse).unwrap();
+        if let Some(new) = on_disk.iter().skip(first).position(|p| !p.reuse) {
+            refuse!("partition {} on {} is new, but it comes after partition {}, which is reused; \
+                on a disk whose partition table is kept, the new partitions come first", first + new + 1, disk, first + 1)
+        }
+        if disks.contains_key(disk) {
+            refuse!("disk {} is declared under `disks`, but partition {} on it is reused, so its partition table is kept \
+                and the space left on it isn't known; remove it from `disks`", disk, first + 1)
+        }
+        // the size of a disk image is checked like the size of a declared disk
+        if is_image_disk(disk) && image.is_some_and(|i| i.create || i.size.is_some()) {
+            refuse!("partition {} on {} is reused, but `image` creates the disk image or checks its size; remove `create` and `size` under `image` to use the existing one",
+                first + 1, disk)
+        }
+    }
+    Ok(())
+}
+
+/// Everything needed to put a filesystem on a partition and use it on the installed system
+#[derive(Debug)]
+pub struct Filesystem
+{
+    /// The name used for the `format` property of a partition
+    pub format: &'static str,
+    /// The command that creates the filesystem, without the path to the partition
+    pub mkfs: &'static str,
+    /// The package that provides the `mkfs` command
+    pub mkfs_package: &'static str,
+    /// The `fdisk` partition type that should be used with the filesystem
+    pub fdisk_type: &'static str,
+    /// The type `blkid` finds on a partition holding the filesystem
+    pub blkid_type: &'static str,
+    /// Packages the target system needs in order to mount the filesystem
+    pub packages: &'static [&'static str],
+}
+
+/// The GUID of the "Microsoft basic data" partition type; `fdisk` doesn't have an alias for it
+const MICROSOFT_BASIC_DATA: &str = "EBD0A0A2-B9E5-4433-87C0-68B6B72699C7";
+
+/// All formats that jimmy knows how to create
+pub const FILESYSTEMS: &[Filesystem] = &[
+    Filesystem {
+        format: "ext2",
+        mkfs: "mkfs.ext2",
+        mkfs_package: "e2fsprogs",
+        fdisk_type: "linux",
+        blkid_type: "ext2",
+        packages: &[],
+    },
+    Filesystem {
+        format: "ext3",
+        mkfs: "mkfs.ext3",
+        mkfs_package: "e2fsprogs",
+        fdisk_type: "linux",
+        blkid_type: "ext3",
+        packages: &[],
+    },
+    Filesystem {
+        format: "ext4",
+        mkfs: "mkfs.ext4",
+        mkfs_package: "e2fsprogs",
+        fdisk_type: "linux",
+        blkid_type: "ext4",
+        packages: &[],
+    },
+    Filesystem {
+        format: "fat32",
+        mkfs: "mkfs.fat -F 32",
+        mkfs_package: "dosfstools",
+        fdisk_type: "uefi",
+        blkid_type: "vfat",
+        packages: &[],
+    },
+    Filesystem {
+        format: "swap",
+        mkfs: "mkswap",
+        mkfs_package: "util-linux",
+        fdisk_type: "swap",
+        blkid_type: "swap",
+        packages: &[],
+    },
+    Filesystem {
+        format: "btrfs",
+        mkfs: "mkfs.btrfs",
+        mkfs_package: "btrfs-progs",
+        fdisk_type: "linux",
+        blkid_type: "btrfs",
+        packages: &["btrfs-progs"],
+    },
+    Filesystem {
+        format: "ntfs",
+        mkfs: "mkfs.ntfs -Q",
+        mkfs_package: "ntfs-3g",
+        fdisk_type: MICROSOFT_BASIC_DATA,
+        blkid_type: "ntfs",
+        packages: &["ntfs-3g"],
+    },
+    Filesystem {
+        format: "exfat",
+        mkfs: "mkfs.exfat",
+        mkfs_package: "exfatprogs",
+        fdisk_type: MICROSOFT_BASIC_DATA,
+        blkid_type: "exfat",
+        packages: &["exfatprogs"],
+    },
+];
+
+/// Return the filesystem with the given format name, if jimmy knows about it
+pub fn filesystem(format: &str) -> Option<&'static Filesystem>
+{
+    FILESYSTEMS.iter().find(|fs| fs.format == format)
+}
+
+/// The profiles btrfs can spread the data and metadata of a filesystem over its devices with, and
+/// how many devices each of them needs at least
+pub const RAID_PROFILES: &[(&str, usize)] = &[("single", 1), ("raid1", 2), ("raid10", 2)];
+
+/// Struct that contains a single line of the fstab file
+#[derive(Debug, Clone)]
+pub struct FstabEntry
+{
+    pub fs: String,
+    pub dir: String,
+    pub fstype: String,
+    pub options: String,
+    pub dump: u32,
+    pub pass: u32,
+}
+
+impl TryFrom<ParsedFstabEntry> for FstabEntry
+{
+    type Error = Errors;
+
+    /// Create a new instance of `FstabEntry` from an instance of `ParsedFstabEntry`, refusing if
+    /// it wouldn't make a valid fstab line
+    fn try_from(raw: ParsedFstabEntry) -> Result<Self, Errors>
+    {
+        let entry = if let Some(line) = raw.raw {
+            let fields: Vec<&str> = line.split_whitespace().collect();
+            if !(4..=6).contains(&fields.len()) {
+                refuse!("fstab entry must have between 4 and 6 fields, but it has {}: \"{}\"", fields.len(), line)
+            }
+            let number = |i: usize| fields.get(i)
+                .map(|f| f.parse().map_err(|_| refusal!("fstab entry has a non-numeric field: \"{}\"", line)))
+                .unwrap_or(Ok(0));
+            Self {
+                fs: fields[0].to_string(),
+                dir: fields[1].to_string(),
+                fstype: fields[2].to_string(),
+                options: fields[3].to_string(),
+                dump: number(4)?,
+                pass: number(5)?,
+            }
+        } else {
+            Self {
+                fs: raw.fs.ok_or_else(|| refusal!("fstab entry has no `fs`"))?,
+                dir: raw.dir.ok_or_else(|| refusal!("fstab entry has no `dir`"))?,
+                fstype: raw.fstype.ok_or_else(|| refusal!("fstab entry has no `type`"))?,
+                options: raw.options.unwrap_or_else(|| "defaults".to_string()),
+                dump: raw.dump.unwrap_or(0),
+                pass: raw.pass.unwrap_or(0),
+            }
+        };
+        for field in [&entry.fs, &entry.dir, &entry.fstype, &entry.options] {
+            if field.is_empty() || field.contains(char::is_whitespace) {
+                refuse!("fstab entry fields can't be empty or contain whitespace (use \\040 for spaces): \"{}\"", field)
+            }
+        }
+        if entry.dir != "none" && !entry.dir.starts_with('/') {
+            refuse!("fstab entry mount point is a relative path: \"{}\"", entry.dir)
         }
+        Ok(entry)
     }
 }
 
@@ -160,25 +3279,125 @@ pub struct User
     pub name: String,
     pub groups: Vec<String>,
     pub shell: String,
+    /// The locale the user's sessions use, instead of the system's
+    pub locale: Option<String>,
+    /// Whether the home directory is an encrypted one, managed by systemd-homed
+    pub home_encryption: bool,
+    /// Whether the home directory is created empty, instead of with the files of /etc/skel
+    pub skip_skel: bool,
+    /// Whether the user's systemd instance runs from boot, instead of only while they're logged in
+    pub linger: bool,
+    /// The units of the user's systemd instance that are enabled
+    pub user_services: Vec<String>,
 }
 
-impl From<ParsedUser> for User
+impl TryFrom<ParsedUser> for User
 {
-    fn from(raw: ParsedUser) -> Self
+    type Error = Errors;
+
+    fn try_from(raw: ParsedUser) -> Result<Self, Errors>
     {
-        Self {
-            name: raw.name.expect("No username specified"),
+        Ok(Self {
+            name: raw.name.ok_or_else(|| refusal!("No username specified"))?,
             groups: raw.groups.unwrap_or_default(),
             shell: raw.shell.unwrap_or_default(),
+            locale: raw.locale,
+            home_encryption: raw.home_encryption.unwrap_or(false),
+            skip_skel: raw.skip_skel.unwrap_or(false),
+            linger: raw.linger.unwrap_or(false),
+            user_services: raw.user_services.unwrap_or_default(),
+        })
+    }
+}
+
+/// A directory created on the installed system, with the mode and owner it's given
+#[derive(Debug, Clone)]
+pub struct Directory
+{
+    pub path: String,
+    /// The mode, as four octal digits; `install`'s default when not given
+    pub mode: Option<String>,
+    pub owner: Option<String>,
+    pub group: Option<String>,
+}
+
+impl TryFrom<ParsedDirectory> for Directory
+{
+    type Error = Errors;
+
+    /// Create a new instance of `Directory` from an instance of `ParsedDirectory`, refusing if its
+    /// path, mode or owner can't be used
+    fn try_from(raw: ParsedDirectory) -> Result<Self, Errors>
+    {
+        let path = normalize_target_path("directory path", &raw.path.ok_or_else(|| refusal!("directory has no `path`"))?)?;
+        let mode = raw.mode.map(|m| validate_mode(&path, m)).transpose()?;
+        for name in [&raw.owner, &raw.group].into_iter().flatten() {
+            if name.is_empty() || name.contains(|c: char| c.is_whitespace() || c == ':' || c == '\'' || c == '"') {
+                refuse!("invalid owner or group of directory {}: {:?}", path, name)
+            }
         }
+        Ok(Self { path, mode, owner: raw.owner, group: raw.group })
     }
 }
 
+/// Return the hash of a parsed configuration file, as 16 hexadecimal digits. It's the 64-bit
+/// FNV-1a hash of the file written back as YAML, so it doesn't change between runs or versions
+/// of Rust, but it does change with the order of the properties
+pub fn config_hash(config: &serde_yaml::Value) -> String
+{
+    let hash = serde_yaml::to_string(config).unwrap()
+        .bytes()
+        .fold(0xcbf29ce484222325_u64, |hash, b| (hash ^ b as u64).wrapping_mul(0x100000001b3));
+    format!("{:016x}", hash)
+}
+
+/// Return a description of everything this version of jimmy supports, for programs that generate
+/// configuration files
+pub fn capabilities() -> serde_json::Value
+{
+    serde_json::json!({
+        "version": env!("CARGO_PKG_VERSION"),
+        "config_version": CONFIG_VERSION,
+        "api_version": crate::api::API_VERSION,
+        "formats": FILESYSTEMS.iter().map(|fs| serde_json::json!({
+            "name": fs.format,
+            "mkfs": fs.mkfs,
+            "packages": fs.packages,
+        })).collect::<Vec<serde_json::Value>>(),
+        "bootloaders": Bootloader::ALL.iter().map(Bootloader::name).collect::<Vec<&str>>(),
+        "chroot_backends": CHROOT_BACKENDS,
+        "initramfs_generators": INITRAMFS_GENERATORS,
+        "kernels": Kernel::ALL.iter().map(Kernel::name).collect::<Vec<&str>>(),
+        "architectures": Architecture::ALL.iter().map(Architecture::name).collect::<Vec<&str>>(),
+        "network_backends": NETWORK_BACKENDS,
+        "mirrorlist_updates": MIRRORLIST_UPDATES,
+        "cleanup_policies": CLEANUP_POLICIES,
+        "hardware_clocks": HARDWARE_CLOCKS,
+        "power_daemons": POWER_DAEMONS,
+        "previous_mounts": PREVIOUS_MOUNTS,
+        "default_targets": DEFAULT_TARGETS,
+        "bootstraps": BOOTSTRAPS,
+        "lints": crate::lint::LINTS.iter().map(|l| l.name).collect::<Vec<&str>>(),
+        "root_password_policies": ROOT_PASSWORD_POLICIES,
+        "hardening_toggles": HARDENING_TOGGLES,
+        "systemd_boot_updates": SYSTEMD_BOOT_UPDATES,
+        "languages": Language::ALL.iter().map(Language::code).collect::<Vec<&str>>(),
+        "raid_profiles": RAID_PROFILES.iter().map(|(name, _)| *name).collect::<Vec<&str>>(),
+        "partition_types": PARTITION_TYPES.iter().map(|(name, guid)| serde_json::json!({
+            "name": name,
+            "guid": guid,
+        })).collect::<Vec<serde_json::Value>>(),
+    })
+}
+
 pub fn sample_input_file() -> &'static str
 {
-r"# Basic arch installation; latest kernel with a single root partition, booted
-# with GRUB
-# It uses /dev/sda for its partition
+r"# Basic arch installation; latest kernel with a boot partition and a root
+# partition, booted with GRUB
+# It uses /dev/sda for its partitions
+
+# the version of the format this file follows
+version: 2
 
 hostname: archlinux
 
@@ -193,12 +3412,11 @@ users:
 
 # user preferences
 bootloader: grub
-extra: vim
+packages: vim
 
-# Timezone info, as per /usr/share/zoneinfo/*Region*/*City*
+# Timezone info, as per /usr/share/zoneinfo/*Region*/*City*; UTC if not given
 # For example purpoeses, use London, Europe
-region: Europe
-city: London
+timezone: Europe/London
 
 # List of locales to use and generate. By default, when nothing is specified,
 # 'en_US.UTF-8' is assumed.
@@ -208,13 +3426,20 @@ locales:
 # alternatively: `lts`
 kernel: latest
 
+# the disk of every partition that doesn't have a `disk` of its own
+disk: /dev/sda
+
 # you have to configure partitions manually
 partitions:
   # the name of the array serves no purpose other than readability
+  # the bootloader needs an EFI system partition, mounted at /boot or /efi
+  - boot:
+    format: fat32
+    mount: /boot
+    size: 500M
   - root:
     format: ext4
     mount: /
-    disk: /dev/sda
     # when there's no `size` property, it's assumed you want the remaining space
     # on the disk
 "
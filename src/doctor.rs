@@ -0,0 +1,350 @@
+use std::path::Path;
+use crate::data::{Architecture, format_mib};
+
+/// The vendors of processors that Arch Linux packages microcode updates for
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum CpuVendor {
+    Intel,
+    Amd,
+}
+
+impl CpuVendor
+{
+    /// Return the name of the vendor
+    pub fn name(&self) -> &'static str
+    {
+        match self {
+            CpuVendor::Intel => "Intel",
+            CpuVendor::Amd => "AMD",
+        }
+    }
+
+    /// Return the package with the microcode updates for the vendor's processors
+    pub fn microcode_package(&self) -> &'static str
+    {
+        match self {
+            CpuVendor::Intel => "intel-ucode",
+            CpuVendor::Amd => "amd-ucode",
+        }
+    }
+}
+
+/// The hypervisors a machine can be a virtual machine of
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Virtualization {
+    Qemu,
+    VirtualBox,
+    Vmware,
+    HyperV,
+    /// The processor says it runs under a hypervisor, but not which one
+    Unknown,
+}
+
+impl Virtualization
+{
+    /// Return the name of the hypervisor
+    pub fn name(&self) -> &'static str
+    {
+        match self {
+            Virtualization::Qemu => "qemu",
+            Virtualization::VirtualBox => "virtualbox",
+            Virtualization::Vmware => "vmware",
+            Virtualization::HyperV => "hyperv",
+            Virtualization::Unknown => "unknown hypervisor",
+        }
+    }
+
+    /// Return the package with the tools that integrate a guest with the hypervisor, if any
+    pub fn guest_package(&self) -> Option<&'static str>
+    {
+        match self {
+            Virtualization::Qemu => Some("qemu-guest-agent"),
+            Virtualization::VirtualBox => Some("virtualbox-guest-utils"),
+            Virtualization::Vmware => Some("open-vm-tools"),
+            Virtualization::HyperV => Some("hyperv"),
+            Virtualization::Unknown => None,
+        }
+    }
+}
+
+/// A disk found on the machine
+#[derive(Debug, Clone, PartialEq)]
+pub struct DetectedDisk
+{
+    /// The path of the disk, e.g. `/dev/sda`
+    pub path: String,
+    pub bytes: u64,
+    /// Whether the disk can be removed, such as a USB stick the live system may have booted from
+    pub removable: bool,
+    pub model: Option<String>,
+}
+
+impl DetectedDisk
+{
+    /// Return a description of the disk, such as `/dev/sda: 128G, QEMU HARDDISK`
+    pub fn describe(&self) -> String
+    {
+        let mut description = format!("{}: {}", self.path, format_mib(self.bytes >> 20));
+        if let Some(model) = &self.model {
+            description.push_str(&format!(", {}", model));
+        }
+        if self.removable {
+            description.push_str(", removable");
+        }
+        description
+    }
+}
+
+/// What jimmy could find out about the machine it's running on
+#[derive(Debug, Clone)]
+pub struct Machine
+{
+    pub arch: Architecture,
+    /// Whether the machine booted with UEFI, rather than BIOS
+    pub uefi: bool,
+    pub cpu_vendor: Option<CpuVendor>,
+    pub virtualization: Option<Virtualization>,
+    pub keymap: Option<String>,
+    /// The timezone of the live system, e.g. `Europe/London` or `UTC`
+    pub timezone: Option<String>,
+    pub disks: Vec<DetectedDisk>,
+}
+
+/// Return the architecture of the processors described by the contents of /proc/cpuinfo; only
+/// ARM processors have a `CPU implementer`
+pub fn detect_arch(cpuinfo: &str) -> Architecture
+{
+    if cpuinfo.lines().any(|l| l.starts_with("CPU implementer")) {
+        Architecture::Aarch64
+    } else {
+        Architecture::X86_64
+    }
+}
+
+/// Return the vendor of the processors described by the contents of /proc/cpuinfo, if it ships
+/// microcode updates
+pub fn detect_cpu_vendor(cpuinfo: &str) -> Option<CpuVendor>
+{
+    let vendor = cpuinfo.lines()
+        .find(|l| l.starts_with("vendor_id"))?
+        .split(':')
+        .nth(1)?
+        .trim();
+    match vendor {
+        "GenuineIntel" => Some(CpuVendor::Intel),
+        "AuthenticAMD" => Some(CpuVendor::Amd),
+        _ => None,
+    }
+}
+
+/// Return the hypervisor the machine runs under, if any, from the vendor and the name of the
+/// system given by its firmware (`/sys/class/dmi/id/sys_vendor` and `product_name`), and from the
+/// contents of /proc/cpuinfo
+pub fn detect_virtualization(sys_vendor: &str, product_name: &str, cpuinfo: &str) -> Option<Virtualization>
+{
+    let (vendor, product) = (sys_vendor.trim(), product_name.trim());
+    if vendor == "QEMU" || product.starts_with("KVM") || product.starts_with("Standard PC") {
+        Some(Virtualization::Qemu)
+    } else if vendor == "innotek GmbH" || product == "VirtualBox" {
+        Some(Virtualization::VirtualBox)
+    } else if vendor.starts_with("VMware") {
+        Some(Virtualization::Vmware)
+    } else if vendor == "Microsoft Corporation" && product == "Virtual Machine" {
+        Some(Virtualization::HyperV)
+    } else if cpuinfo.lines().any(|l| l.starts_with("flags") && l.split_whitespace().any(|f| f == "hypervisor")) {
+        Some(Virtualization::Unknown)
+    } else {
+        None
+    }
+}
+
+/// Return the keymap set in the contents of /etc/vconsole.conf, if any
+pub fn detect_keymap(vconsole: &str) -> Option<String>
+{
+    vconsole.lines()
+        .find_map(|l| l.strip_prefix("KEYMAP="))
+        .map(|k| k.trim().trim_matches('"').to_string())
+        .filter(|k| !k.is_empty())
+}
+
+/// Return the timezone that /etc/localtime points to, given the target of the link, such as
+/// `/usr/share/zoneinfo/Europe/London`
+pub fn detect_timezone(localtime: &Path) -> Option<String>
+{
+    let target = localtime.to_str()?;
+    let (_, zone) = target.split_once("zoneinfo/")?;
+    Some(zone.to_string()).filter(|z| !z.is_empty())
+}
+
+/// Return the disks listed under `sys_block` (normally /sys/block), along with their sizes, in
+/// order of their names. Loop devices, RAM disks, optical drives and the devices of the device
+/// mapper and software RAID aren't disks that can be partitioned
+pub fn detect_disks(sys_block: &Path) -> Vec<DetectedDisk>
+{
+    const NOT_DISKS: &[&str] = &["loop", "ram", "zram", "sr", "fd", "dm-", "md", "nbd"];
+    let read = |path: &Path| std::fs::read_to_string(path).ok().map(|s| s.trim().to_string());
+    let mut names: Vec<String> = std::fs::read_dir(sys_block)
+        .map(|entries| entries.filter_map(|e| e.ok()?.file_name().into_string().ok()).collect())
+        .unwrap_or_default();
+    names.retain(|name| !NOT_DISKS.iter().any(|prefix| name.starts_with(prefix)));
+    names.sort();
+    names.into_iter()
+        .filter_map(|name| {
+            let dir = sys_block.join(&name);
+            // the size is always in 512-byte sectors, whatever the sector size of the disk
+            let sectors: u64 = read(&dir.join("size"))?.parse().ok()?;
+            Some(DetectedDisk {
+                path: format!("/dev/{}", name),
+                bytes: sectors * 512,
+                removable: read(&dir.join("removable")).as_deref() == Some("1"),
+                model: read(&dir.join("device/model")).filter(|m| !m.is_empty()),
+            })
+        })
+        .filter(|d| d.bytes > 0)
+        .collect()
+}
+
+impl Machine
+{
+    /// Find out what the machine is like from the files under `root`, which is `/` for the
+    /// machine jimmy runs on: /proc, /sys and /etc
+    pub fn detect(root: &Path) -> Self
+    {
+        let read = |path: &str| std::fs::read_to_string(root.join(path)).unwrap_or_default();
+        let cpuinfo = read("proc/cpuinfo");
+        Self {
+            arch: detect_arch(&cpuinfo),
+            uefi: root.join("sys/firmware/efi").is_dir(),
+            cpu_vendor: detect_cpu_vendor(&cpuinfo),
+            virtualization: detect_virtualization(
+                &read("sys/class/dmi/id/sys_vendor"),
+                &read("sys/class/dmi/id/product_name"),
+                &cpuinfo,
+            ),
+            keymap: detect_keymap(&read("etc/vconsole.conf")),
+            timezone: std::fs::read_link(root.join("etc/localtime")).ok().and_then(|l| detect_timezone(&l)),
+            disks: detect_disks(&root.join("sys/block")),
+        }
+    }
+
+    /// Return the disk the draft installs onto: the biggest one that can't be removed, or the
+    /// biggest one if all can
+    pub fn target_disk(&self) -> Option<&DetectedDisk>
+    {
+        self.disks.iter()
+            .filter(|d| !d.removable)
+            .max_by_key(|d| d.bytes)
+            .or_else(|| self.disks.iter().max_by_key(|d| d.bytes))
+    }
+
+    /// Return a report of everything that was found out about the machine
+    pub fn report(&self) -> String
+    {
+        let mut lines = vec![
+            format!("architecture: {}", self.arch.name()),
+            format!("firmware: {}", if self.uefi { "UEFI" } else { "BIOS" }),
+            match self.cpu_vendor {
+                Some(vendor) => format!("cpu: {} (microcode: {})", vendor.name(), vendor.microcode_package()),
+                None => "cpu: no microcode updates".to_string(),
+            },
+            format!("virtualization: {}", self.virtualization.map(|v| v.name()).unwrap_or("none")),
+            format!("keymap: {}", self.keymap.as_deref().unwrap_or("unknown")),
+            format!("timezone: {}", self.timezone.as_deref().unwrap_or("unknown")),
+        ];
+        if self.disks.is_empty() {
+            lines.push("disks: none".to_string());
+        } else {
+            lines.push("disks:".to_string());
+            lines.extend(self.disks.iter().map(|d| format!("    {}", d.describe())));
+        }
+        lines.join("\n") + "\n"
+    }
+
+    /// Return a configuration file for the machine, with the values that were found out filled in
+    /// and comments on what's left to decide
+    pub fn draft(&self) -> String
+    {
+        let mut yaml = vec![
+            "# drafted by `jimmy doctor --draft` from the machine it ran on; check every value,".to_string(),
+            "# especially the partitions, before generating a script from it".to_string(),
+            String::new(),
+            "# the version of the format this file follows".to_string(),
+            format!("version: {}", crate::data::CONFIG_VERSION),
+            String::new(),
+            "hostname: archlinux".to_string(),
+            String::new(),
+        ];
+        if !self.uefi {
+            yaml.push("# warning: the machine booted with BIOS, but jimmy only installs systems that boot".to_string());
+            yaml.push("# with UEFI; boot the live system with UEFI, if the firmware can".to_string());
+        }
+        yaml.push("bootloader: grub".to_string());
+        if self.arch != Architecture::X86_64 {
+            yaml.push(format!("arch: {}", self.arch.name()));
+        }
+
+        let mut extra = Vec::new();
+        if let Some(vendor) = self.cpu_vendor.filter(|_| self.arch == Architecture::X86_64) {
+            yaml.push(format!("# microcode for the {} processor", vendor.name()));
+            extra.push(vendor.microcode_package());
+        }
+        if let Some(virtualization) = self.virtualization {
+            yaml.push(format!("# this is a virtual machine ({}), which needs no firmware", virtualization.name()));
+            extra.extend(virtualization.guest_package());
+        }
+        if !extra.is_empty() {
+            yaml.push(format!("packages: {}", yaml_scalar(&extra.join(" "))));
+        }
+        if self.virtualization.is_some() {
+            yaml.push("firmware_packages: none".to_string());
+        }
+        yaml.push(String::new());
+
+        yaml.push("# the timezone of the live system, as per /usr/share/zoneinfo/*Region*/*City*".to_string());
+        match &self.timezone {
+            Some(timezone) => yaml.push(format!("timezone: {}", yaml_scalar(timezone))),
+            None => {
+                yaml.push(format!("# the live system has none, so it's {} unless you set it, e.g.:", crate::data::DEFAULT_TIMEZONE));
+                yaml.push("# timezone: Europe/London".to_string());
+            },
+        }
+        yaml.push(String::new());
+        yaml.push("locales:".to_string());
+        yaml.push("  - en_US.UTF-8".to_string());
+        if let Some(keymap) = &self.keymap {
+            yaml.push(format!("keymap: {}", yaml_scalar(keymap)));
+        }
+        yaml.push(String::new());
+        yaml.push("kernel: latest".to_string());
+        yaml.push(String::new());
+
+        yaml.push("partitions:".to_string());
+        if self.disks.is_empty() {
+            yaml.push("  # no disks were found; fill in `disk` with the one to install onto".to_string());
+        } else {
+            yaml.push("  # the disks of the machine:".to_string());
+            yaml.extend(self.disks.iter().map(|d| format!("  #   {}", d.describe())));
+        }
+        let disk = self.target_disk().map(|d| d.path.as_str()).unwrap_or("/dev/sda");
+        yaml.extend([
+            "  - boot:".to_string(),
+            "    format: fat32".to_string(),
+            "    mount: /boot".to_string(),
+            format!("    disk: {}", disk),
+            "    size: 1G".to_string(),
+            "  - root:".to_string(),
+            "    format: ext4".to_string(),
+            "    mount: /".to_string(),
+            format!("    disk: {}", disk),
+            "    # the rest of the disk".to_string(),
+        ]);
+        yaml.join("\n") + "\n"
+    }
+}
+
+/// Return a string as a YAML scalar, quoted only if YAML would read it as something else
+fn yaml_scalar(value: &str) -> String
+{
+    let yaml = serde_yaml::to_string(value).unwrap();
+    yaml.strip_prefix("---\n").unwrap_or(&yaml).trim_end().to_string()
+}
@@ -1,5 +1,33 @@
-use crate::data::{InstallOptions, Partition, User, Kernel};
+use crate::data::{InstallOptions, Bootloader, Partition, User, Kernel, Firmware, FstabEntry, Filesystem, Language, Directory, Tarball};
+use crate::messages::message;
+use crate::data::{HookFlavor, hook_flavor};
+use crate::data::{Disk, Size, format_mib, CONFIG_VERSION, GPT_OVERHEAD_MIB, PHASES, DESKTOPS, DISPLAY_MANAGERS};
+use crate::data::{filesystem, editor_package, kernel_param_name, stable_disk_path, is_image_disk, find_xbootldr, secondary_esps};
+use crate::unused::{unused, Unused};
+use crate::shellgen::{shell_quote, heredoc_cmd, ShellCmd, indent, or_fail, require_command, confirm, until_success, retry_at_most, append_once, feed_lines};
 use regex::Regex;
+use serde::Serialize;
+
+/// The parameters `GRUB_CMDLINE_LINUX_DEFAULT` has in /etc/default/grub, unless
+/// `grub.cmdline_linux_default` replaces them
+const GRUB_CMDLINE_LINUX_DEFAULT: &[&str] = &["loglevel=3", "quiet"];
+
+/// Merge lists of kernel parameters, each given after the ones before it: a parameter replaces
+/// those of the earlier lists that have the same name, and is left out if it's already there, but
+/// a list can have several parameters with the same name, such as `console=`
+fn merge_kernel_params(lists: &[&[String]]) -> Vec<String>
+{
+    let mut merged: Vec<String> = Vec::new();
+    for list in lists {
+        merged.retain(|p| !list.iter().any(|l| kernel_param_name(l) == kernel_param_name(p)));
+        for param in list.iter() {
+            if !merged.contains(param) {
+                merged.push(param.clone());
+            }
+        }
+    }
+    merged
+}
 
 /// Take the second element of each of the tuples in the input only if they're Some()
 fn map_snd<A, B>(tuples: Vec<(A, Option<B>)>) -> Vec<B>
@@ -11,298 +39,3774 @@ fn map_snd<A, B>(tuples: Vec<(A, Option<B>)>) -> Vec<B>
         .collect()
 }
 
-/// Given a string and a list of commands, prepend an echo command printing the message to the list
-/// of commands
-#[allow(dead_code)]
-fn echo_status(msg: &str, cmds: &str) -> String
-{
-    format!("echo '{}'\n{}", msg, cmds)
-}
+/// Return where `path`, a path on the installed system, is on the live system while it's mounted
+/// on `root`. The root directory of the installed system keeps its trailing slash, as `/mnt/`
+fn target_path(root: &str, path: &str) -> String
+{
+    format!("{}{}", root, path)
+}
+
+/// Return a command that appends an entry to the fstab file of the target system mounted on
+/// `root`, for the filesystems `genfstab` can't pick up by itself because they aren't mounted. The
+/// entry identifies `device` by the UUID it has when the script runs, instead of its (unstable)
+/// path
+fn fstab_append_cmd(root: &str, device: &str, dir: &str, fstype: &str, options: &str, pass: u32) -> String
+{
+    let entry = FstabEntry {
+        fs: format!("UUID=$(blkid -s UUID -o value {})", device),
+        dir: dir.to_string(),
+        fstype: fstype.to_string(),
+        options: options.to_string(),
+        dump: 0,
+        pass,
+    };
+    format!("echo \"{}\" >> {}", entry.fstab_line(), target_path(root, "/etc/fstab"))
+}
+
+/// Return the name of the shell variable that holds the kernel name of a disk given by a stable
+/// identifier, or of the loop device a disk image is attached to, or `None` if the disk was given
+/// as the path of a device
+fn disk_variable(disk: &str) -> Option<String>
+{
+    if stable_disk_path(disk).is_none() && !is_image_disk(disk) {
+        return None;
+    }
+    Some(format!(
+        "JIMMY_DISK_{}",
+        disk.chars().map(|c| if c.is_ascii_alphanumeric() { c } else { '_' }).collect::<String>(),
+    ))
+}
+
+/// Return what the script should use to refer to a disk: its path, or the variable holding its
+/// kernel name if it's given by a stable identifier or is a disk image
+fn disk_device(disk: &str) -> String
+{
+    match disk_variable(disk) {
+        Some(var) => format!("${}", var),
+        None => disk.to_string(),
+    }
+}
+
+/// Return the shell code that runs the `cmds` given for a disk or a partition, with `$DEVICE` set
+/// to `device`, as the script refers to it. They run in a subshell, so that `$DEVICE` and
+/// whatever else they change don't leak into the rest of the script, and they're left as they
+/// were written, since they may hold here-documents; the script stops if one of them fails, naming
+/// `what` they were given for
+fn with_device(device: &str, cmds: &[String], what: &str) -> String
+{
+    // the status is checked afterwards rather than with `||`, which would turn `set -e` off
+    format!("(\nDEVICE={}\nexport DEVICE\nset -e\n{}\n)\nif [ $? -ne 0 ]; then\n    echo {} >&2\n    exit 1\nfi",
+        device,
+        cmds.join("\n"),
+        shell_quote(&format!("error: the {} failed", what)),
+    )
+}
+
+/// The shells the scripts can be written for, which their `#!` line names
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum ShellDialect
+{
+    #[default]
+    Sh,
+    Bash,
+}
+
+impl ShellDialect
+{
+    pub const ALL: &'static [ShellDialect] = &[ShellDialect::Sh, ShellDialect::Bash];
+
+    /// Return the name the dialect is given on the command line
+    pub fn name(&self) -> &'static str
+    {
+        match self {
+            ShellDialect::Sh => "sh",
+            ShellDialect::Bash => "bash",
+        }
+    }
+
+    /// Return the path of the shell that runs the scripts
+    fn interpreter(&self) -> &'static str
+    {
+        match self {
+            ShellDialect::Sh => "/bin/sh",
+            ShellDialect::Bash => "/bin/bash",
+        }
+    }
+}
+
+/// The width long commands are wrapped to, unless told otherwise
+pub const DEFAULT_WIDTH: usize = 80;
+
+/// How the scripts are written, beyond what the configuration file asks for. The scripts are made
+/// from the installation options and this alone, so that the same two always give the same
+/// script; the default is a script for sh, without colors, whose stderr isn't kept, with its long
+/// commands wrapped to `DEFAULT_WIDTH`
+#[derive(Debug, Clone)]
+pub struct RenderContext
+{
+    /// Whether the same configuration file always gives the same script, byte for byte: without
+    /// the time it was generated at, and with the packages sorted
+    pub reproducible: bool,
+    /// Whether the status messages are printed in bold
+    pub color: bool,
+    /// Where on the live system the installation script keeps everything it writes to stderr,
+    /// instead of a temporary file that's removed once the status is written
+    pub log_path: Option<String>,
+    /// The shell the scripts are written for
+    pub shell: ShellDialect,
+    /// How many columns the long commands are wrapped to, or 0 to leave them on a single line
+    pub width: usize,
+}
+
+impl Default for RenderContext
+{
+    fn default() -> Self
+    {
+        Self {
+            reproducible: false,
+            color: false,
+            log_path: None,
+            shell: ShellDialect::default(),
+            width: DEFAULT_WIDTH,
+        }
+    }
+}
+
+/// The files a partition is reached through. They're worked out in a single place, from the disk
+/// of the partition and its place on it, by `InstallOptions::map_partitions()`: the commands of a
+/// partition only ever use the files they're given, whatever device they're on
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct BlockDevice
+{
+    /// The number of the partition on its disk, starting at 1
+    pub number: u32,
+    /// The partition file, e.g. `/dev/sda1`, `/dev/nvme0n1p2` or `${JIMMY_DISK_..._PART}1` for
+    /// disks given by a stable identifier and disk images, whose variables are set by
+    /// `resolve_disks_cmds()`
+    pub partition: String,
+    /// The file that holds the filesystem: the device mapper entry of an encrypted partition, or
+    /// the partition file otherwise
+    pub filesystem: String,
+    /// The partition file that's used from inside the arch-chroot session, and on the installed
+    /// system. Disks given by a stable identifier keep using it, since the kernel names of the
+    /// disks may change between boots; disk images only have the loop device they're attached
+    /// to, whose variables are handed to the arch-chroot script
+    pub stable: String,
+}
+
+impl BlockDevice
+{
+    /// Return the files of a partition that's the `number`th on its disk, starting at 1
+    pub fn of_partition(partition: &Partition, number: u32) -> Self
+    {
+        let disk = &partition.disk;
+        let file = match disk_variable(disk) {
+            Some(var) => format!("${{{}_PART}}{}", var, number),
+            // when the name of the disk ends with a digit (e.g. NVME drives), the kernel puts a
+            // `p` between it and the number of the partition
+            None if disk.ends_with(|c: char| c.is_ascii_digit()) => format!("{}p{}", disk, number),
+            None => format!("{}{}", disk, number),
+        };
+        Self {
+            number,
+            filesystem: match partition.mapper_name() {
+                Some(name) => format!("/dev/mapper/{}", name),
+                None => file.clone(),
+            },
+            stable: match stable_disk_path(disk) {
+                Some(path) => format!("{}-part{}", path, number),
+                None => file.clone(),
+            },
+            partition: file,
+        }
+    }
+}
+
+/// Given a string and a list of commands, prepend an echo command printing the message to the list
+/// of commands; with `color`, the message is printed in bold
+fn echo_status(msg: &str, cmds: &str, color: bool) -> String
+{
+    format!("{}\n{}", status_line(msg, color), cmds)
+}
+
+/// Return the command that prints the last status message of the script, after an empty line
+fn done_line(msg: &str, color: bool) -> String
+{
+    match color {
+        true => format!("printf '\\n\\033[1m%s\\033[0m\\n' '{}'", msg),
+        false => format!("printf '\\n%s\\n' '{}'", msg),
+    }
+}
+
+/// Return the command that prints a status message, in bold with `color`
+fn status_line(msg: &str, color: bool) -> String
+{
+    match color {
+        true => format!("printf '\\033[1m%s\\033[0m\\n' '{}'", msg),
+        false => format!("echo '{}'", msg),
+    }
+}
+
+/// Format a point in time as an ISO 8601 timestamp in UTC, such as `2022-04-05T13:37:00Z`
+fn utc_timestamp(time: std::time::SystemTime) -> String
+{
+    let secs = time.duration_since(std::time::UNIX_EPOCH).map(|d| d.as_secs()).unwrap_or(0);
+    let (days, secs) = (secs / 86400, secs % 86400);
+    // turn the days since 1970-01-01 into a date; see http://howardhinnant.github.io/date_algorithms.html
+    let z = days as i64 + 719468;
+    let era = z.div_euclid(146097);
+    let doe = z.rem_euclid(146097);
+    let yoe = (doe - doe / 1460 + doe / 36524 - doe / 146096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = doy - (153 * mp + 2) / 5 + 1;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 };
+    let year = yoe + era * 400 + i64::from(month <= 2);
+    format!("{:04}-{:02}-{:02}T{:02}:{:02}:{:02}Z",
+        year, month, day, secs / 3600, secs % 3600 / 60, secs % 60)
+}
+
+/// A script that runs the first time the installed system boots, for the things that can't be
+/// done from inside the arch-chroot session, since nothing is running there
+struct FirstBootScript
+{
+    /// What the script does, used in its file name
+    name: &'static str,
+    script: String,
+    /// The units that have to be running for the script to work
+    after: &'static [&'static str],
+    /// Whether the script asks questions, which is done on tty1
+    interactive: bool,
+}
+
+/// The program that runs the first-boot scripts, in the order of their names. The scripts that
+/// succeed are removed; if one fails, the rest don't run, and the service stays enabled so that
+/// they're tried again on the next boot. Once every script succeeds, the service disables itself
+const FIRST_BOOT_RUNNER: &str = r#"#!/bin/sh
+# first-boot runner automatically generated by jimmy-rs
+for script in /usr/local/lib/jimmy/firstboot.d/*.sh; do
+    [ -e "$script" ] || continue
+    echo "running $script"
+    if ! "$script"; then
+        echo "error: $script failed; it is going to run again on the next boot" >&2
+        exit 1
+    fi
+    rm -f "$script"
+done
+systemctl disable jimmy-firstboot.service
+"#;
+
+/// Return the commands that install the first-boot scripts, numbered in the order they're given,
+/// along with the runner and the oneshot service that starts it before anyone can log in. The
+/// output of the scripts goes to the journal
+fn first_boot_cmds(scripts: &[FirstBootScript]) -> Vec<String>
+{
+    let mut after: Vec<&str> = Vec::new();
+    for unit in scripts.iter().flat_map(|s| s.after.iter()) {
+        if !after.contains(unit) {
+            after.push(unit);
+        }
+    }
+    let mut unit = vec![
+        "[Unit]".to_string(),
+        "Description=Finish the installation done by jimmy".to_string(),
+    ];
+    if !after.is_empty() {
+        unit.push(format!("Wants={}", after.join(" ")));
+        unit.push(format!("After={}", after.join(" ")));
+    }
+    unit.extend([
+        "Before=systemd-user-sessions.service getty@tty1.service".to_string(),
+        String::new(),
+        "[Service]".to_string(),
+        "Type=oneshot".to_string(),
+        "ExecStart=/usr/local/lib/jimmy/firstboot".to_string(),
+    ]);
+    if scripts.iter().any(|s| s.interactive) {
+        unit.extend([
+            "StandardInput=tty".to_string(),
+            "TTYPath=/dev/tty1".to_string(),
+            "StandardOutput=journal+console".to_string(),
+        ]);
+    } else {
+        unit.push("StandardOutput=journal".to_string());
+    }
+    unit.extend([
+        String::new(),
+        "[Install]".to_string(),
+        "WantedBy=multi-user.target".to_string(),
+    ]);
+
+    let mut cmds = vec!["mkdir -p /usr/local/lib/jimmy/firstboot.d".to_string()];
+    for (i, script) in scripts.iter().enumerate() {
+        let path = format!("/usr/local/lib/jimmy/firstboot.d/{:02}-{}.sh", (i + 1) * 10, script.name);
+        cmds.push(heredoc_cmd(&path, &format!(
+            "#!/bin/sh\n# first-boot script automatically generated by jimmy-rs\nset -e\n\n{}\n",
+            script.script,
+        ), false));
+        cmds.push(format!("chmod +x {}", path));
+    }
+    cmds.extend([
+        heredoc_cmd("/usr/local/lib/jimmy/firstboot", FIRST_BOOT_RUNNER, false),
+        "chmod +x /usr/local/lib/jimmy/firstboot".to_string(),
+        heredoc_cmd("/etc/systemd/system/jimmy-firstboot.service", &(unit.join("\n") + "\n"), false),
+    ]);
+    cmds
+}
+
+/// Shell code that prepares for timing the steps of the installation: it remembers when the
+/// installation started, creates the file in which the timings are collected, and defines the
+/// function that prints how long a step took
+const TIMINGS_SETUP: &str = r#"JIMMY_START=$(date +%s)
+JIMMY_TIMINGS=$(mktemp)
+jimmy_time() {
+    elapsed=$(( $(date +%s) - $2 ))
+    if [ "$elapsed" -ge 60 ]; then
+        printf '%-16s %dm%ds\n' "$1" $((elapsed / 60)) $((elapsed % 60))
+    else
+        printf '%-16s %ds\n' "$1" "$elapsed"
+    fi
+}"#;
+
+/// Shell code that prints the table of timings, and copies it onto the target system
+const TIMINGS_SUMMARY: &str = r#"jimmy_time 'total' "$JIMMY_START" >>"$JIMMY_TIMINGS"
+mkdir -p {root}/var/log/jimmy && cp "$JIMMY_TIMINGS" {root}/var/log/jimmy/timings.txt
+cat "$JIMMY_TIMINGS" && rm -f "$JIMMY_TIMINGS""#;
+
+/// Shell code that defines the function running the checks of the verification step: it prints
+/// whether the check passed, and remembers if any failed. The results are also written to the
+/// file named by `JIMMY_VERIFIED`, if it's set, for the report
+const VERIFY_SETUP: &str = r#"jimmy_failed=0
+jimmy_verify() {
+    if eval "$2" >/dev/null 2>&1; then
+        result=PASS
+    else
+        result=FAIL
+        jimmy_failed=1
+    fi
+    echo "$result: $1"
+    printf '%s\t%s\n' "$result" "$1" >>"${JIMMY_VERIFIED:-/dev/null}"
+}"#;
+
+/// Shell code that creates the file in which the verification step writes its results for the
+/// report
+const REPORT_SETUP: &str = "JIMMY_VERIFIED=$(mktemp)";
+
+/// Shell code that prints its argument as a JSON string: without the control characters, and with
+/// backslashes and double quotes escaped. It's the body of the functions that the report and the
+/// status file are written with
+const JSON_STRING: &str = r#"printf '"%s"' "$(printf '%s' "$1" | tr -d '\000-\037' | sed 's/\\/\\\\/g; s/"/\\"/g')""#;
+
+/// Shell code that writes the report of the installation, as a single line of JSON: the plan
+/// (`{plan}`, already in JSON) that jimmy made from the configuration file, how many seconds every
+/// step took, from the timings saved on the target system, the results of the verification step,
+/// and the product name of the machine along with the model, serial number and size of the disks
+/// (`{disks}`) that `lsblk` finds. `{dir}` is the directory of the report, and `{path}` the
+/// report itself
+const REPORT_CMDS: &str = r#"jimmy_json() {
+    {json_string}
+}
+mkdir -p {root}{dir}
+{
+    printf '{"plan":%s,"durations":{' {plan}
+    if [ -f {root}/var/log/jimmy/timings.txt ]; then
+        awk '{ t = $NF; $NF = ""; sub(/ +$/, ""); s = 0; if (t ~ /m/) { split(t, a, "m"); s = a[1] * 60; t = a[2] }; sub(/s$/, "", t); printf "%s\"%s\":%d", sep, $0, s + t; sep = "," }' {root}/var/log/jimmy/timings.txt
+    fi
+    printf '},"verification":['
+    sep=
+    while IFS="$(printf '\t')" read -r result check; do
+        if [ "$result" = PASS ]; then passed=true; else passed=false; fi
+        printf '%s{"check":%s,"passed":%s}' "$sep" "$(jimmy_json "$check")" "$passed"
+        sep=,
+    done <"$JIMMY_VERIFIED"
+    printf '],"warnings":['
+    sep=
+    if [ -f {root}/var/log/jimmy/warnings.txt ]; then
+        while read -r warning; do
+            printf '%s%s' "$sep" "$(jimmy_json "$warning")"
+            sep=,
+        done <{root}/var/log/jimmy/warnings.txt
+    fi
+    printf '],"hardware":{"product_name":%s,"disks":' "$(jimmy_json "$(cat /sys/class/dmi/id/product_name 2>/dev/null)")"
+    disks=$(lsblk --json --nodeps --bytes --output PATH,MODEL,SERIAL,WWN,SIZE {disks} 2>/dev/null | tr -d '\n' | sed 's/^{ *"blockdevices": *//; s/ *}$//')
+    printf '%s}}\n' "${disks:-[]}"
+} >{root}{path}
+rm -f "$JIMMY_VERIFIED""#;
+
+/// Where the status of the installation is written on the live system, however it ended
+const STATUS_PATH: &str = "/tmp/jimmy-status.json";
+
+/// How many of the last lines the script wrote to stderr are kept in the status
+const STATUS_STDERR_LINES: u32 = 50;
+
+/// Shell code that writes the status of the installation to `{path}` when the script exits, for
+/// whatever started it, and copies it into /var/lib/jimmy on the new system while it's mounted: a
+/// single line of JSON saying whether it succeeded, the step that was running (`JIMMY_STEP`), the
+/// exit status, when it started and ended, the subset of the plan of the report (`{plan}`) that
+/// identifies the installation, and the last `{lines}` lines written to stderr. stderr goes through
+/// `tee`, so that it's still shown while being saved, to the file `{stderr_setup}` names, which
+/// `{stderr_cleanup}` removes or keeps
+const STATUS_SETUP: &str = r#"# write how the installation ended to {path} when the script exits
+JIMMY_STATUS_START=$(date -u +%Y-%m-%dT%H:%M:%SZ)
+JIMMY_STEP=
+{stderr_setup}
+jimmy_fifo=$(mktemp -u)
+mkfifo "$jimmy_fifo"
+tee -a "$JIMMY_STDERR" <"$jimmy_fifo" >&2 &
+jimmy_tee=$!
+exec 9>&2 2>"$jimmy_fifo"
+rm -f "$jimmy_fifo"
+jimmy_status_json() {
+    {json_string}
+}
+jimmy_status() {
+    # let tee write down what's left in the pipe, unless something still running holds it open;
+    # POSIX sleep only takes whole seconds
+    exec 2>&9 9>&-
+    jimmy_wait=0
+    while kill -0 "$jimmy_tee" 2>/dev/null && [ "$jimmy_wait" -lt 2 ]; do
+        sleep 1
+        jimmy_wait=$((jimmy_wait + 1))
+    done
+    if [ "$1" -eq 0 ]; then success=true; else success=false; fi
+    if [ -n "$JIMMY_STEP" ]; then step=$(jimmy_status_json "$JIMMY_STEP"); else step=null; fi
+    {
+        printf '{"success":%s,"step":%s,"exit_code":%d,"started_at":"%s","finished_at":"%s","plan":%s,"stderr":[' \
+            "$success" "$step" "$1" "$JIMMY_STATUS_START" "$(date -u +%Y-%m-%dT%H:%M:%SZ)" {plan}
+        sep=
+        tail -n {lines} "$JIMMY_STDERR" | while IFS= read -r line; do
+            printf '%s%s' "$sep" "$(jimmy_status_json "$line")"
+            sep=,
+        done
+        printf ']}\n'
+    } >{path}
+    {stderr_cleanup}
+    if mountpoint -q {root}; then
+        mkdir -p {root}/var/lib/jimmy && cp {path} {root}/var/lib/jimmy/
+    fi
+}
+trap 'jimmy_status $?' EXIT
+trap 'exit 130' INT
+trap 'exit 143' TERM"#;
+
+/// Where the resolv.conf of the target system is saved while the one of the live system is used
+/// in its place
+const RESOLV_CONF_BACKUP: &str = "/etc/resolv.conf.jimmy-backup";
+
+/// Shell code that puts a working resolv.conf of the live system in the place of the target's.
+/// When the live system's /etc/resolv.conf points at the stub of a systemd-resolved that isn't
+/// running, arch-chroot doesn't bind it, and the target is left with its own, which doesn't work
+/// either; the servers systemd-resolved forwards to are listed next to its stub. The target's own
+/// is only saved the first time, so that running it again doesn't save the copy over it
+const RESOLV_CONF_COPY: &str = r#"if { [ -e {root}/etc/resolv.conf ] || [ -L {root}/etc/resolv.conf ]; } && ! [ -e {backup} ] && ! [ -L {backup} ]; then
+    mv -f {root}/etc/resolv.conf {backup}
+fi
+if [ -s /run/systemd/resolve/resolv.conf ]; then
+    cp /run/systemd/resolve/resolv.conf {root}/etc/resolv.conf
+elif [ -s /etc/resolv.conf ]; then
+    cp -L /etc/resolv.conf {root}/etc/resolv.conf
+else
+    echo 'warning: the live system has no working resolv.conf to use inside arch-chroot' >&2
+fi"#;
+
+/// Shell code that puts back the resolv.conf that's meant to be on the target system: its own, if
+/// it was a link, or else the stub of systemd-resolved, which is enabled on it; once it's back,
+/// running it again does nothing
+const RESOLV_CONF_RESTORE: &str = r#"if [ -L {backup} ]; then
+    rm -f {root}/etc/resolv.conf
+    mv -f {backup} {root}/etc/resolv.conf
+elif [ -e {backup} ] || ! [ -L {root}/etc/resolv.conf ]; then
+    rm -f {root}/etc/resolv.conf {backup}
+    ln -s ../run/systemd/resolve/stub-resolv.conf {root}/etc/resolv.conf
+fi"#;
+
+/// Where the bootstrap tarball is downloaded to: onto the root partition of the new system, since
+/// the live system keeps its files in memory
+const TARBALL_PATH: &str = "/jimmy_bootstrap.tar";
+
+/// Shell code that mounts the filesystems of the kernel inside the system extracted from a
+/// bootstrap tarball, as pacstrap does, for the hooks that pacman runs in there; those that are
+/// mounted already, by an earlier attempt, are left as they are
+const API_FILESYSTEMS_MOUNT: &str = r#"mountpoint -q {root}/proc || mount -t proc proc {root}/proc -o nosuid,noexec,nodev
+mountpoint -q {root}/sys || mount -t sysfs sys {root}/sys -o nosuid,noexec,nodev,ro
+mountpoint -q {root}/dev || mount -t devtmpfs udev {root}/dev -o mode=0755,nosuid"#;
+
+/// Unmount what `API_FILESYSTEMS_MOUNT` mounted
+const API_FILESYSTEMS_UNMOUNT: &str = "umount -R {root}/proc {root}/sys {root}/dev";
+
+/// Where the mirrorlist of the live system is saved before it's replaced by the one of a snapshot
+const MIRRORLIST_BACKUP: &str = "/etc/pacman.d/mirrorlist.jimmy-backup";
+
+/// Shell code that defines the function installing a package at a given version from the Arch
+/// Linux Archive, `jimmy_pin NAME VERSION`; it stops the script if there's no such version. The
+/// packages are built for `x86_64` or for `any` architecture, and compressed with zstd or, until
+/// 2020, with xz
+const PIN_SETUP: &str = r#"jimmy_pin() {
+    for suffix in x86_64.pkg.tar.zst any.pkg.tar.zst x86_64.pkg.tar.xz any.pkg.tar.xz; do
+        if pacman -U --noconfirm "https://archive.archlinux.org/packages/$(printf '%.1s' "$1")/$1/$1-$2-$suffix" 2>/dev/null; then
+            return 0
+        fi
+    done
+    echo "error: there's no version $2 of $1 in the Arch Linux Archive" >&2
+    exit 1
+}"#;
+
+/// Packages that only some machines need, which can only be told apart once the script runs on
+/// them; they're installed from inside arch-chroot, once pacstrap is done
+struct RuntimeInstall
+{
+    /// The variable the script keeps in whether the packages are needed, which is empty if not
+    var: &'static str,
+    /// Shell code that succeeds on the machines that need the packages
+    condition: &'static str,
+    packages: Vec<&'static str>,
+    /// The commands that set the packages up once they're installed
+    setup: Vec<String>,
+}
+
+/// Return the commands that install the packages of every `RuntimeInstall` whose condition holds,
+/// with a single invocation of pacman, and then set them up
+fn runtime_install_cmds(installs: &[RuntimeInstall]) -> Vec<String>
+{
+    let mut cmds = vec!["jimmy_runtime_packages=".to_string()];
+    cmds.extend(installs.iter().map(|i| format!(
+        "if {}; then
+    {}=1
+    jimmy_runtime_packages=\"$jimmy_runtime_packages {}\"
+else
+    {}=
+fi",
+        i.condition, i.var, i.packages.join(" "), i.var,
+    )));
+    cmds.push(r#"if [ -n "$jimmy_runtime_packages" ] && ! pacman -S --needed --noconfirm $jimmy_runtime_packages; then
+    echo "error: could not install$jimmy_runtime_packages" >&2
+    exit 1
+fi"#.to_string());
+    cmds.extend(installs.iter().filter(|i| !i.setup.is_empty()).map(|i| format!(
+        "if [ -n \"${}\" ]; then\n    {}\nfi",
+        i.var, i.setup.join("\n    "),
+    )));
+    cmds
+}
+
+/// Shell code that succeeds if the firmware says the machine is a laptop: portable, laptop,
+/// notebook, sub notebook, tablet, convertible or detachable, among the chassis types of SMBIOS
+const LAPTOP_CHECK: &str = "grep -qxE '8|9|10|14|30|31|32' /sys/class/dmi/id/chassis_type 2>/dev/null";
+
+/// Where the outer script tells the arch-chroot script which keymap the live system uses, so that
+/// it can warn before passwords are typed with another one than the installed system's
+const LIVE_KEYMAP_PATH: &str = "/jimmy_live_keymap";
+
+/// Shell code that loads the keymap `{keymap}` on the live system, and records the one it ends up
+/// with: if it can't be loaded, the one the live system booted with
+const KEYMAP_LOAD: &str = r#"if loadkeys {keymap}; then
+    JIMMY_LIVE_KEYMAP={keymap}
+else
+    JIMMY_LIVE_KEYMAP=$(sed -n 's/^KEYMAP=//p' /etc/vconsole.conf 2>/dev/null | tr -d '"')
+    JIMMY_LIVE_KEYMAP=${JIMMY_LIVE_KEYMAP:-us}
+    echo "warning: the keymap '{keymap}' couldn't be loaded, so the live system keeps '$JIMMY_LIVE_KEYMAP'" >&2
+fi"#;
+
+/// Shell code that tells, before the passwords are asked for, which keymap they're typed with and
+/// warns if it isn't `{keymap}`, the one of the installed system. The live system's is only known
+/// when the outer script loaded it
+const KEYMAP_NOTE: &str = r#"jimmy_live_keymap=$(cat {path} 2>/dev/null) || jimmy_live_keymap=
+if [ -z "$jimmy_live_keymap" ]; then
+    echo "note: the installed system uses the '{keymap}' keymap; if the live system uses another one, passwords typed here might not work after rebooting"
+elif [ "$jimmy_live_keymap" = {keymap} ]; then
+    echo "note: passwords are typed with the '{keymap}' keymap, the one of the installed system"
+else
+    echo "warning: passwords are typed with the '$jimmy_live_keymap' keymap of the live system, but the installed system uses '{keymap}'; they might not work after rebooting" >&2
+fi"#;
+
+/// Return the mirrorlist that points pacman at the Arch Linux Archive as it was on `date`
+fn snapshot_mirrorlist(date: &str) -> String
+{
+    [
+        format!("# the Arch Linux Archive as of {}, set by jimmy; the system gets no updates until", date),
+        "# this file lists current mirrors again".to_string(),
+        format!("Server = https://archive.archlinux.org/repos/{}/$repo/os/$arch", date.replace('-', "/")),
+    ].join("\n") + "\n"
+}
+
+/// What the script says about keeping local time in the hardware clock before it does
+const LOCALTIME_CLOCK_WARNING: &str = r#"# Windows expects the hardware clock to keep local time, and Linux UTC; with local time, both
+# systems adjust it when daylight saving time starts or ends, so it can end up an hour off
+echo 'warning: the hardware clock keeps local time, for Windows; it can be an hour off after daylight saving time changes, if both systems adjust it' >&2"#;
+
+/// Shell code that lists the filesystems mounted under the mount root, such as those an earlier
+/// attempt left mounted, in `$jimmy_mounted`, the deepest ones first
+const MOUNTED_UNDER_ROOT: &str = r#"jimmy_mounted=$(findmnt -rn -o TARGET | awk '$1 == "{root}" || index($1, "{root}/") == 1' | sort -r)"#;
+
+/// Shell code that stops the script if anything is mounted under the mount root, telling how to
+/// unmount it with `{unmount}`
+const PREVIOUS_MOUNTS_ABORT: &str = r#"if [ -n "$jimmy_mounted" ]; then
+    echo 'error: these filesystems are still mounted under {root}, probably by an earlier attempt:' >&2
+    printf '    %s\n' $jimmy_mounted >&2
+    echo 'unmount them with `{unmount}`, or set `previous_mounts: unmount` for the script to do it' >&2
+    exit 1
+fi"#;
+
+/// Shell code that unmounts what's mounted under the mount root, children first
+const PREVIOUS_MOUNTS_UNMOUNT: &str = r#"if [ -n "$jimmy_mounted" ]; then
+    echo 'warning: unmounting the filesystems an earlier attempt left mounted under {root}' >&2
+    for jimmy_target in $jimmy_mounted; do
+        umount "$jimmy_target" || { echo "error: $jimmy_target could not be unmounted" >&2; exit 1; }
+    done
+fi"#;
+
+/// The line of the filesystem table of the installed system after which the fstab step writes
+/// its entries; whatever comes after it is written again when the step runs again
+const FSTAB_MARKER: &str = "# the entries below are written by the installation script of jimmy";
+
+/// Shell code that replaces what an earlier run of the fstab step wrote to the filesystem table of
+/// the installed system, if anything, with the entries of genfstab. The table is put together in
+/// another file, which then takes its place
+const FSTAB_WRITE: &str = r#"if [ -f {root}/etc/fstab ]; then
+    sed '/^{marker}$/,$d' {root}/etc/fstab >{root}/etc/fstab.jimmy
+else
+    : >{root}/etc/fstab.jimmy
+fi
+{ echo '{marker}'; genfstab -U {root}; } >>{root}/etc/fstab.jimmy || exit 1
+mv -f {root}/etc/fstab.jimmy {root}/etc/fstab || exit 1"#;
+
+/// Shell code that runs the arch-chroot script with `{cmd}`, and stops the installation script
+/// with its status if it failed, before the cleanup and the unmounting, so that the installed
+/// system can be inspected
+const CHROOT_RESULT: &str = r#"{cmd}
+jimmy_chroot_status=$?
+if [ "$jimmy_chroot_status" -ne 0 ]; then
+    echo "error: the arch-chroot script failed with status $jimmy_chroot_status; {root} is left mounted for inspection" >&2
+    exit "$jimmy_chroot_status"
+fi"#;
+
+/// Shell code that stops the script before unmounting if any of the checks of the verification
+/// step failed, so that the installed system can be inspected
+const VERIFY_RESULT: &str = r#"if [ "$jimmy_failed" -ne 0 ]; then
+    echo 'error: the installed system failed verification; {root} is left mounted for inspection' >&2
+    exit 1
+fi"#;
+
+/// What every step of the installation does and why it comes where it does, keyed by the name of
+/// the step; `jimmy explain` prints them next to the commands of each step, with `{root}` replaced
+/// by where the new system is mounted
+const STEP_DESCRIPTIONS: &[(&str, &str)] = &[
+    ("resolve disks",
+        "The disks given by a stable identifier (`by-id:...` or `wwn:...`) are looked up under \
+        /dev/disk, and the kernel names they have right now are saved in variables. This comes \
+        first, because the kernel names can change between boots, and every later command that \
+        touches one of their partitions goes through those variables. The disk images are \
+        attached to loop devices here, after they're created if `image` asks for it."),
+    ("previous mounts",
+        "The filesystems an earlier attempt left mounted under {root}, if any, are found with \
+        findmnt. Depending on `previous_mounts`, the script either stops, telling how to unmount \
+        them, or unmounts them and closes the encrypted partitions it opens, so that the disks \
+        can be partitioned again. The partitions it mounts itself are only mounted if they \
+        aren't already."),
+    ("preflight",
+        "Before anything is changed, the script checks that it's ran as root on the architecture \
+        it installs for, that every program it needs is available on the live system, offering \
+        to install the missing ones with pacman unless `offline` is set, that none of the \
+        disks it's about to partition holds the running system or, unless the script is given \
+        `--allow-install-medium`, the live medium, even once it's been copied to RAM, and that \
+        the live system was made from a release of the ISO recent enough for the features of \
+        the configuration, and for `min_iso_version`. The partitions marked `reuse: true` are \
+        checked with blkid to hold the filesystems they're declared with. It stops at the first \
+        problem, so that a failed check never leaves a half-partitioned disk behind."),
+    ("keymap",
+        "The `keymap` of the installed system is loaded on the live system first, so that the \
+        passphrases and passwords typed during the installation are laid out as they'll be \
+        after rebooting. If it can't be loaded, the live system keeps the keymap it booted with, \
+        and the arch-chroot script warns before the passwords are asked for."),
+    ("clock",
+        "The clock of the live system is synchronized over NTP. A wrong clock makes the package \
+        signatures look invalid to pacman, and ends up in the timestamps of the new filesystems."),
+    ("partitioning",
+        "Every disk gets a new GPT partition table, and the partitions are created on it in the \
+        order they're listed in, each with the partition type that goes with its format or, for \
+        the root partition, /home, /srv and /var, with the one that lets systemd find them by \
+        itself. The partition without a size takes the rest of its disk, so it has to be the \
+        last one on it. A disk holding a partition marked `reuse: true` keeps its partition table \
+        instead: the partitions before the reused ones are deleted with sfdisk, if they're there, \
+        and created again in the space they leave, while the reused ones stay as they are."),
+    ("encryption",
+        "The encrypted partitions are formatted as LUKS2 containers and opened, asking for their \
+        passphrases until they're given correctly. This has to happen before formatting, since \
+        the filesystems are created inside the opened containers, not on the partitions."),
+    ("pre-format",
+        "The `pre_format` commands of the disks are ran, each with `$DEVICE` set to the file of \
+        its disk, for whatever has to be done to a whole disk before it's used, such as \
+        `blkdiscard` or `badblocks -wsv`. They come before partitioning, since they may wipe \
+        the disk, and the script stops if one of them fails."),
+    ("formatting",
+        "A filesystem (or swap space) is created on every partition, with the extra `mkfs_args` \
+        of each, except on the partitions marked `reuse: true`, which keep theirs. The data already \
+        on the other partitions is lost at this point."),
+    ("post-format",
+        "The `post_format` commands of the partitions are ran, each with `$DEVICE` set to the \
+        file its filesystem is on (the opened container, for an encrypted partition), such as \
+        `tune2fs` or a benchmark. They come before mounting, so that the filesystems aren't in \
+        use yet, and the script stops if one of them fails."),
+    ("mounting",
+        "The partitions are mounted under {root}, where the new system is assembled, and the swap \
+        partitions are activated. The root partition is always mounted first, since the other \
+        mount points are directories on it."),
+    ("snapshot",
+        "The mirrorlist of the live system is replaced by the Arch Linux Archive as it was on the \
+        `snapshot_date`, after it's saved to /etc/pacman.d/mirrorlist.jimmy-backup. This comes \
+        right before pacstrap, which downloads the packages from the mirrors of the live system \
+        and copies its mirrorlist onto the new one."),
+    ("pacstrap",
+        "The packages are installed onto {root} with pacstrap: the base system, the kernel, the \
+        firmware, the packages the configuration needs, and the ones of `packages`. Failed downloads \
+        are retried, but signature errors aren't, since retrying doesn't fix them."),
+    ("tarball",
+        "With `bootstrap: tarball`, the base system comes from a bootstrap tarball instead of \
+        pacstrap: it's downloaded onto the root partition unless it's a local file, checked \
+        against its `sha256` or else its signature, and extracted into {root}, keeping the owners, \
+        permissions and extended attributes of its files. The mirrorlist of the live system is \
+        copied onto it, as pacstrap does."),
+    ("packages",
+        "With `bootstrap: tarball`, the packages are installed into the extracted system by the \
+        pacman of the live system, with `--root {root}`, after the filesystems of the kernel are \
+        mounted inside it for the hooks of the packages. They're the packages pacstrap would \
+        install, and so are the retries. Whatever the tarball came with is upgraded first."),
+    ("fstab",
+        "The filesystem table of the new system is written by genfstab, from what's mounted under \
+        {root}, along with the swap partitions that weren't activated. The entries go after a \
+        line of their own, past which whatever an earlier attempt wrote is replaced. It has to come after \
+        mounting, since genfstab only sees mounted filesystems, and after pacstrap, which \
+        creates {root}/etc."),
+    ("crypttab",
+        "The encrypted partitions other than the root one are listed in /etc/crypttab, by the UUID \
+        of their LUKS containers, so that they're unlocked during boot; the ones with a keyfile \
+        get it created on the root partition and added to their containers. Their entries in the \
+        filesystem table are then rewritten to use the opened containers, which is why this \
+        comes after genfstab."),
+    ("skel",
+        "The files of the `skel` directory of the live system are copied into /etc/skel on the \
+        new system, over those its packages put there, and are given to root. The users are \
+        created from /etc/skel in the arch-chroot script, so this has to come before it."),
+    ("chroot script",
+        "The commands that have to run inside the new system are written to {root}/jimmy_part2.sh: \
+        timezone, locales, hostname, network, passwords, users, initramfs and bootloader. They \
+        can't run from the live system, since they change files and services of the new one. The \
+        hash of the configuration file is saved in /var/lib/jimmy/config.hash on the new system, \
+        and the script refuses to run on a system whose hash isn't its own."),
+    ("resolv.conf",
+        "The resolv.conf of the live system, or the list of servers its systemd-resolved forwards \
+        to, is copied over the one of the new system, which is saved next to it, so that names \
+        resolve inside arch-chroot. This only happens when `keep_resolv_conf` asks for it or, by \
+        default, when the arch-chroot script downloads something, as with `package_pins`."),
+    ("configuration",
+        "The script written in the previous step is ran inside the new system, with arch-chroot \
+        or systemd-nspawn, depending on `chroot_backend`. It asks for the passwords of root and \
+        of the users. The script stops at the first command that fails, and then so does the \
+        installation script, with its status, leaving {root} mounted for inspection."),
+    ("restore resolv.conf",
+        "The resolv.conf the new system is meant to have is put back: its own, if it was a link, \
+        or else a link to the stub resolver of systemd-resolved, which is enabled on it. This \
+        comes right after the arch-chroot script, which is the only thing that needed the copy."),
+    ("cleanup",
+        "The script that was ran inside the new system is deleted, so that it doesn't stay around \
+        on the installed system."),
+    ("verification",
+        "The installed system is checked for the kernel, the initramfs, the bootloader, the \
+        mount points in the filesystem table and the users. If any check fails, the script stops \
+        here with {root} still mounted, so that the problem can be looked into."),
+    ("artifacts",
+        "The files jimmy left on the installed system to tell how it was installed are removed, \
+        as `cleanup` asks: the hash of the configuration file, the timings of the steps and the \
+        report, or all but the report. This only happens once the installed system passed \
+        verification, and is the last change made to it."),
+    ("unmount",
+        "Every filesystem under {root} is unmounted and the encrypted partitions are closed, so \
+        that everything is written to the disks before rebooting. The disk images are detached \
+        from their loop devices last."),
+];
+
+/// What running a step again does, once the script stopped in it or after it, which tells
+/// whether an installation that failed partway can be resumed from it
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Rerun
+{
+    /// Running the step again leaves the system as running it once does
+    Idempotent,
+    /// Running it again fails, or does some of it twice, but loses nothing
+    NotIdempotent,
+    /// Running it again wipes out what was done since, such as the new filesystems
+    Destructive,
+}
+
+/// What running each step again does
+const STEP_RERUNS: &[(&str, Rerun)] = &[
+    ("resolve disks", Rerun::Idempotent),
+    ("preflight", Rerun::Idempotent),
+    ("keymap", Rerun::Idempotent),
+    ("previous mounts", Rerun::Idempotent),
+    ("clock", Rerun::Idempotent),
+    ("pre-format", Rerun::Destructive),
+    ("partitioning", Rerun::Destructive),
+    ("encryption", Rerun::Destructive),
+    ("formatting", Rerun::Destructive),
+    // the commands are the configuration file's, so there's no telling
+    ("post-format", Rerun::NotIdempotent),
+    ("mounting", Rerun::Idempotent),
+    ("snapshot", Rerun::Idempotent),
+    ("pacstrap", Rerun::Idempotent),
+    // extracting the tarball again puts back the files of its packages over those of newer ones
+    ("tarball", Rerun::NotIdempotent),
+    ("packages", Rerun::Idempotent),
+    ("fstab", Rerun::Idempotent),
+    // the entries are appended to /etc/crypttab, and the keyfiles added to the containers again
+    ("crypttab", Rerun::NotIdempotent),
+    ("skel", Rerun::Idempotent),
+    ("chroot script", Rerun::Idempotent),
+    ("resolv.conf", Rerun::Idempotent),
+    ("configuration", Rerun::Idempotent),
+    ("restore resolv.conf", Rerun::Idempotent),
+    ("cleanup", Rerun::Idempotent),
+    ("verification", Rerun::Idempotent),
+    // rmdir fails once the directories are gone
+    ("artifacts", Rerun::NotIdempotent),
+    ("unmount", Rerun::Idempotent),
+];
+
+/// The phase of the installation, of those in `PHASES`, that each step belongs to; the steps that
+/// aren't in any are ran whichever phases are selected, since the others rely on them
+const STEP_PHASES: &[(&str, &str)] = &[
+    ("previous mounts", "disks"),
+    ("pre-format", "disks"),
+    ("partitioning", "disks"),
+    ("encryption", "disks"),
+    ("formatting", "disks"),
+    ("post-format", "disks"),
+    ("mounting", "disks"),
+    ("snapshot", "pacstrap"),
+    ("pacstrap", "pacstrap"),
+    ("tarball", "pacstrap"),
+    ("packages", "pacstrap"),
+    ("fstab", "fstab"),
+    ("crypttab", "fstab"),
+    ("skel", "chroot"),
+    ("chroot script", "chroot"),
+    ("resolv.conf", "chroot"),
+    ("configuration", "chroot"),
+    ("restore resolv.conf", "chroot"),
+    ("cleanup", "chroot"),
+    ("verification", "chroot"),
+    ("unmount", "unmount"),
+];
+
+/// Where the timings of the steps are saved on the target system
+const TIMINGS_PATH: &str = "/var/log/jimmy/timings.txt";
+
+/// Where the arch-chroot script writes down, one per line, what it gave up on without stopping
+/// the installation, to be shown at the end of it and put in the report
+const WARNINGS_PATH: &str = "/var/log/jimmy/warnings.txt";
+
+/// The kinds of files jimmy leaves on the installed system to tell how it was installed, which
+/// `cleanup` decides to keep or not
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Artifact
+{
+    /// The hash of the configuration file, which keeps the arch-chroot script of another
+    /// configuration file from running on the system
+    ConfigHash,
+    Timings,
+    Warnings,
+    Report,
+}
+
+impl Artifact
+{
+    /// Return whether the file is kept with the given `cleanup` policy
+    fn kept_by(&self, policy: &str) -> bool
+    {
+        match policy {
+            "keep-everything" => true,
+            "keep-report-only" => *self == Artifact::Report,
+            _ => false,
+        }
+    }
+}
+
+/// What an installation is going to do, in numbers
+#[derive(Debug)]
+pub struct Summary
+{
+    pub partitions: usize,
+    /// The disks, in the order they're partitioned
+    pub disks: Vec<String>,
+    /// What's going to be done with every disk, in the same order
+    pub disk_plans: Vec<String>,
+    pub packages: usize,
+    /// The names of the steps of the script, in order
+    pub steps: Vec<&'static str>,
+    /// A line describing every partition
+    pub layout: Vec<String>,
+}
+
+impl Summary
+{
+    /// Return the summary as a single line, e.g. `3 partitions across 1 disk, 6 packages`
+    pub fn line(&self) -> String
+    {
+        let plural = |n: usize, what: &str| format!("{} {}{}", n, what, if n == 1 { "" } else { "s" });
+        format!("{} across {}, {}",
+            plural(self.partitions, "partition"),
+            plural(self.disks.len(), "disk"),
+            plural(self.packages, "package"),
+        )
+    }
+
+    /// Return the summary with the disks, the steps and the partitions, one per line
+    pub fn details(&self) -> String
+    {
+        [
+            vec![
+                self.line(),
+                format!("disks: {}", self.disks.join(", ")),
+            ],
+            self.disk_plans.iter().map(|d| format!("    {}", d)).collect(),
+            vec![
+                format!("steps: {}", self.steps.join(", ")),
+                "partitions:".to_string(),
+            ],
+            self.layout.iter().map(|l| format!("    {}", l)).collect(),
+        ].concat().join("\n")
+    }
+}
+
+/// What identifies an installation, at the start of the plan of both the report and the status of
+/// the installation: the jimmy that generated the script, the configuration file, the machine and
+/// the steps
+#[derive(Debug, Serialize)]
+struct PlanIdentity
+{
+    /// The version of jimmy that generated the script
+    jimmy: &'static str,
+    config_hash: String,
+    hostname: String,
+    /// The names of the steps of the script, in order
+    steps: Vec<&'static str>,
+}
+
+/// What jimmy planned for an installation, as it's written to the `plan` of the report; the
+/// rest of the report is only known once the installation has run, and is filled in by the
+/// script (see `REPORT_CMDS`)
+#[derive(Debug, Serialize)]
+struct ReportPlan
+{
+    #[serde(flatten)]
+    identity: PlanIdentity,
+    timezone: String,
+    locales: Vec<String>,
+    /// The keymap of the console, `us` unless it's set
+    keymap: String,
+    arch: &'static str,
+    /// The package of the kernel
+    kernel: &'static str,
+    bootloader: &'static str,
+    /// The disks, in the order they're partitioned
+    disks: Vec<String>,
+    partitions: Vec<ReportPartition>,
+    packages: Vec<String>,
+    users: Vec<String>,
+    /// The boot entries of the installed system, with their whole kernel command line
+    boot_entries: Vec<ReportBootEntry>,
+    /// The files jimmy leaves on the installed system, once `cleanup` has removed the others
+    artifacts: Vec<String>,
+    /// What the installation sets up but nothing uses, grouped by what it is
+    unused: Unused,
+}
+
+/// A partition, as it's listed in the plan of the report
+#[derive(Debug, Serialize)]
+struct ReportPartition
+{
+    /// The disk, as it's given in the configuration file
+    disk: String,
+    /// The number of the partition on its disk, starting at 1
+    number: usize,
+    format: String,
+    /// The size, or `None` for the partition that takes the rest of its disk or is reused
+    size: Option<String>,
+    mount: Option<String>,
+    /// Whether the partition is reused as it is, rather than created and formatted anew
+    reused: bool,
+}
+
+/// A boot entry, as it's listed in the plan of the report
+#[derive(Debug, Serialize)]
+struct ReportBootEntry
+{
+    /// The title or the label of the entry
+    entry: String,
+    /// The kernel command line the entry starts the kernel with
+    cmdline: String,
+}
+
+/// Something the initramfs has to be able to do, whichever program creates it
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum InitramfsNeed
+{
+    /// Unlock the encrypted root partition
+    Encrypt,
+    /// Unlock it with the TPM2 chip
+    Tpm2,
+}
+
+impl InitramfsNeed
+{
+    /// Return the dracut module that does it
+    fn dracut_module(&self) -> &'static str
+    {
+        match self {
+            InitramfsNeed::Encrypt => "crypt",
+            InitramfsNeed::Tpm2 => "tpm2-tss",
+        }
+    }
+}
+
+/// Where the scripts ran by the pacman hooks for dracut are installed
+const DRACUT_INSTALL_PATH: &str = "/usr/local/lib/jimmy/dracut-install";
+const DRACUT_REMOVE_PATH: &str = "/usr/local/lib/jimmy/dracut-remove";
+
+/// Install the kernels given on the standard input (e.g. `usr/lib/modules/6.1.1-arch1-1/vmlinuz`)
+/// to /boot, where mkinitcpio would put them, and create their initramfs images with dracut
+const DRACUT_INSTALL_SCRIPT: &str = r#"#!/bin/sh
+while read -r line; do
+    kver=${line#usr/lib/modules/}
+    kver=${kver%/vmlinuz}
+    pkgbase=$(cat "/usr/lib/modules/$kver/pkgbase")
+    install -Dm0644 "/$line" "/boot/vmlinuz-$pkgbase"
+    dracut --force --hostonly --no-hostonly-cmdline "/boot/initramfs-$pkgbase.img" "$kver"
+    dracut --force --no-hostonly "/boot/initramfs-$pkgbase-fallback.img" "$kver"
+done
+"#;
+
+/// Remove the kernels given on the standard input from /boot, along with their initramfs images
+const DRACUT_REMOVE_SCRIPT: &str = r#"#!/bin/sh
+while read -r line; do
+    kver=${line#usr/lib/modules/}
+    kver=${kver%/vmlinuz}
+    pkgbase=$(cat "/usr/lib/modules/$kver/pkgbase")
+    rm -f "/boot/vmlinuz-$pkgbase" "/boot/initramfs-$pkgbase.img" "/boot/initramfs-$pkgbase-fallback.img"
+done
+"#;
+
+/// The pacman hooks that run the scripts above when kernels are installed, upgraded or removed;
+/// `{}` stands for the path of the script
+const DRACUT_INSTALL_HOOK: &str = "[Trigger]
+Type = Path
+Operation = Install
+Operation = Upgrade
+Target = usr/lib/modules/*/vmlinuz
+
+[Action]
+Description = Installing kernels and creating their initramfs images with dracut...
+When = PostTransaction
+Exec = {}
+NeedsTargets
+";
+const DRACUT_REMOVE_HOOK: &str = "[Trigger]
+Type = Path
+Operation = Remove
+Target = usr/lib/modules/*/vmlinuz
+
+[Action]
+Description = Removing kernels and their initramfs images...
+When = PreTransaction
+Exec = {}
+NeedsTargets
+";
+
+/// Everything that has packages installed with pacstrap, each returning its packages for a
+/// configuration; a feature that needs packages adds them here, and nowhere else
+const PACKAGE_SOURCES: &[fn(&InstallOptions) -> Vec<&str>] = &[
+    |_| vec!["base"],
+    |o| vec![o.kernel.package(o.arch)],
+    |o| match &o.firmware {
+        Firmware::Default => vec!["linux-firmware"],
+        Firmware::None => vec![],
+        Firmware::Packages(firmware) => firmware.iter().map(|p| p.as_str()).collect(),
+    },
+    |o| o.extra.split_whitespace().collect(),
+    // the other bootloaders don't need to be installed separately
+    |o| if o.bootloader == Bootloader::Grub { vec!["grub"] } else { vec![] },
+    |_| vec!["efibootmgr", "networkmanager"],
+    // systemd-cryptenroll talks to the TPM2 chip through it
+    |o| if o.partitions.iter().any(|p| p.encryption.as_ref().is_some_and(|e| e.tpm2)) { vec!["tpm2-tss"] } else { vec![] },
+    // it takes the place of mkinitcpio as the provider of `initramfs`
+    |o| if o.initramfs_generator == "dracut" { vec!["dracut"] } else { vec![] },
+    |o| if o.maintenance.paccache { vec!["pacman-contrib"] } else { vec![] },
+    |o| if secondary_esps(&o.partitions).is_empty() { vec![] } else { vec!["rsync"] },
+    |o| if o.maintenance.mirrorlist_update.is_some() { vec!["reflector"] } else { vec![] },
+    |o| o.default_editor.as_deref().and_then(editor_package).into_iter().collect(),
+    // without knowing it's a laptop, the power daemon is only installed once the script finds out
+    |o| if o.laptop == Some(true) { o.power_packages() } else { vec![] },
+    // some filesystems can't be mounted without extra tools
+    |o| o.partitions.iter().filter_map(Partition::filesystem).flat_map(|fs| fs.packages.iter().copied()).collect(),
+];
+
+/// A change made to the installed system by one of the `hardening` toggles
+#[derive(Debug)]
+enum Tweak
+{
+    /// A kernel setting, written to /etc/sysctl.d and applied on every boot
+    Sysctl(&'static str, &'static str),
+    /// A file that overrides part of the configuration of a program: its path and contents
+    DropIn(&'static str, &'static str),
+    /// A parameter of the kernel command line, given to it by the bootloader
+    KernelParam(&'static str),
+    /// An entry of the filesystem table: what's mounted, where, its type and its options
+    Fstab(&'static str, &'static str, &'static str, &'static str),
+}
+
+/// Where the kernel settings of the `hardening` toggles are written on the installed system
+const HARDENING_SYSCTL_PATH: &str = "/etc/sysctl.d/90-jimmy-hardening.conf";
+
+/// What each of the `hardening` toggles changes on the installed system. /proc is mounted with
+/// the group `proc` allowed to see every process, since systemd-logind needs to
+const HARDENING_TWEAKS: &[(&str, &[Tweak])] = &[
+    ("kernel_lockdown", &[Tweak::KernelParam("lockdown=integrity")]),
+    ("disable_coredumps", &[
+        Tweak::DropIn("/etc/systemd/coredump.conf.d/90-jimmy-hardening.conf", "[Coredump]\nStorage=none\nProcessSizeMax=0\n"),
+    ]),
+    ("restrict_dmesg", &[Tweak::Sysctl("kernel.dmesg_restrict", "1")]),
+    ("hidepid", &[
+        Tweak::Fstab("proc", "/proc", "proc", "nosuid,nodev,noexec,hidepid=invisible,gid=proc"),
+        Tweak::DropIn("/etc/systemd/system/systemd-logind.service.d/90-jimmy-hardening.conf", "[Service]\nSupplementaryGroups=proc\n"),
+    ]),
+];
+
+/// An action of the arch-chroot script that several of its parts may need, but that only has to
+/// be carried out once, after the last of them. Actions are carried out in the order they're
+/// declared in, since each of them may depend on the ones before: the GRUB configuration lists
+/// the initramfs images, the boot entries point to them, and the secondary EFI system partitions
+/// get copies of all of them
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Deferred
+{
+    RebuildInitramfs,
+    RegenerateGrubConfig,
+    UpdateEfiEntries,
+    SyncEsps,
+}
+
+impl Deferred
+{
+    const ALL: [Deferred; 4] = [Deferred::RebuildInitramfs, Deferred::RegenerateGrubConfig, Deferred::UpdateEfiEntries, Deferred::SyncEsps];
+
+    /// Return the identifier of the status message printed when the action is carried out
+    fn id(&self) -> &'static str
+    {
+        match self {
+            Deferred::RebuildInitramfs => "rebuild initramfs",
+            Deferred::RegenerateGrubConfig => "grub config",
+            Deferred::UpdateEfiEntries => "efi entries",
+            Deferred::SyncEsps => "esp sync",
+        }
+    }
+}
+
+/// A part of the arch-chroot script: its status message, its commands, and the deferred actions
+/// it needs to be carried out after it
+struct ChrootSection
+{
+    id: &'static str,
+    cmds: String,
+    deferred: Vec<Deferred>,
+}
+
+impl ChrootSection
+{
+    fn new(id: &'static str, cmds: String) -> Self
+    {
+        Self { id, cmds, deferred: Vec::new() }
+    }
+
+    /// Register an action that has to be carried out after this part
+    fn deferring(mut self, action: Deferred) -> Self
+    {
+        if !self.deferred.contains(&action) {
+            self.deferred.push(action);
+        }
+        self
+    }
+}
+
+/// A unit the installed system starts by itself, enabled by one of the parts of the arch-chroot
+/// script
+#[derive(Debug, Clone)]
+struct EnabledService
+{
+    /// The unit, as it's given to `systemctl enable`
+    unit: &'static str,
+    /// The identifier of the part of the arch-chroot script that enables it
+    section: &'static str,
+    /// Whether it's started right away as well, where there's a systemd to start it with
+    start: bool,
+    /// Whether it's only enabled if the script finds that the machine is a laptop
+    laptop_only: bool,
+}
+
+/// Pair every part of the arch-chroot script with the deferred actions that are carried out right
+/// after it. Every action that's needed is carried out exactly once: after the last part that
+/// needs it, but never before an action declared before it
+fn schedule_deferred(sections: &[ChrootSection]) -> Vec<(&ChrootSection, Vec<Deferred>)>
+{
+    let mut scheduled: Vec<(&ChrootSection, Vec<Deferred>)> = sections.iter().map(|s| (s, Vec::new())).collect();
+    let mut earliest = 0;
+    for action in Deferred::ALL {
+        if let Some(last) = sections.iter().rposition(|s| s.deferred.contains(&action)) {
+            let index = last.max(earliest);
+            scheduled[index].1.push(action);
+            earliest = index;
+        }
+    }
+    scheduled
+}
+
+/// Where the script that copies the EFI system partition onto the secondary ones is installed
+const SYNC_ESPS_PATH: &str = "/usr/local/lib/jimmy/sync-esps";
+
+/// Where the secondary EFI system partitions are listed on the installed system, one per line, by
+/// the `/dev/disk/by-partuuid` links that keep naming them when the disks are renamed
+const SECONDARY_ESPS_LIST: &str = "/etc/jimmy/secondary-esps";
+
+/// Copy the EFI system partition mounted at `{esp}` onto each of the partitions listed in `{list}`,
+/// mounting them one after the other. FAT has no owners or permissions to copy, and only keeps
+/// modification times to two seconds
+const SYNC_ESPS_SCRIPT: &str = r#"#!/bin/sh
+status=0
+dir=$(mktemp -d)
+while read -r device; do
+    if ! mount "$device" "$dir"; then
+        echo "error: could not mount the secondary EFI system partition $device" >&2
+        status=1
+        continue
+    fi
+    rsync --recursive --times --modify-window=1 --delete {esp}/ "$dir"/ || status=1
+    umount "$dir"
+done <{list}
+rmdir "$dir"
+exit $status
+"#;
+
+/// The pacman hook that runs the script above after the transactions that change what's on the
+/// EFI system partition: those of the kernels, the microcode and the initramfs or the bootloader.
+/// Hooks run in the order of their names, so it comes after the ones that rebuild the initramfs
+/// and update systemd-boot; `{}` stands for the path of the script
+const SYNC_ESPS_HOOK: &str = "[Trigger]
+Type = Path
+Operation = Install
+Operation = Upgrade
+Operation = Remove
+Target = boot/*
+Target = usr/lib/modules/*/vmlinuz
+Target = usr/lib/initcpio/*
+Target = usr/lib/dracut/*
+Target = usr/lib/systemd/boot/efi/*
+
+[Action]
+Description = Copying the EFI system partition onto the secondary ones...
+When = PostTransaction
+Exec = {}
+";
+
+/// Where the script ran by the pacman hook that lists orphaned packages is installed
+const LIST_ORPHANS_PATH: &str = "/usr/local/lib/jimmy/list-orphans";
+
+/// List the packages that were installed as dependencies, but that nothing depends on anymore
+const LIST_ORPHANS_SCRIPT: &str = r#"#!/bin/sh
+orphans=$(pacman -Qtdq)
+if [ -n "$orphans" ]; then
+    echo 'these packages are no longer needed; remove them with: pacman -Rns $(pacman -Qtdq)'
+    echo "$orphans" | sed 's/^/    /'
+fi
+"#;
+
+/// The pacman hook that updates systemd-boot on the EFI system partition when systemd is
+/// upgraded; `{}` stands for the arguments that tell bootctl where the partitions are
+const SYSTEMD_BOOT_HOOK: &str = "[Trigger]
+Type = Package
+Operation = Upgrade
+Target = systemd
+
+[Action]
+Description = Updating systemd-boot...
+When = PostTransaction
+Exec = /usr/bin/bootctl {} update
+";
+
+/// The pacman hook that runs the script above after every transaction; `{}` stands for the path
+/// of the script
+const LIST_ORPHANS_HOOK: &str = "[Trigger]
+Type = Package
+Operation = Install
+Operation = Upgrade
+Operation = Remove
+Target = *
+
+[Action]
+Description = Looking for orphaned packages...
+When = PostTransaction
+Exec = {}
+";
+
+/// Return the arguments reflector picks the fastest up-to-date mirrors with, from the given
+/// countries or, if there are none, from everywhere
+fn reflector_args(countries: &[String]) -> Vec<String>
+{
+    let mut args = vec![
+        "--protocol https".to_string(),
+        "--latest 20".to_string(),
+        "--sort rate".to_string(),
+    ];
+    if !countries.is_empty() {
+        args.push(format!("--country {}", shell_quote(&countries.join(","))));
+    }
+    args
+}
+
+/// Return what the preflight checks do about missing programs, when the packages that provide
+/// them can be installed on the live system: offer to install them
+fn missing_tools_install() -> String
+{
+    format!(r#"if [ -n "$jimmy_missing" ]; then
+    echo "missing programs:$jimmy_missing"
+{}
+{}
+    if ! pacman -Sy --noconfirm $jimmy_packages; then
+        echo "error: could not install$jimmy_packages" >&2
+        exit 1
+    fi
+fi"#,
+        indent(&require_command("pacman", "error: pacman is not available to install them; the script is meant to be ran from the Arch live environment"), 1),
+        indent(&confirm("\"install$jimmy_packages on the live system with pacman?\"", "error: cannot continue without them"), 1),
+    )
+}
+
+/// A feature of the script that only works on live systems made from a release of the ISO that's
+/// recent enough, because of the version of a program on it
+struct IsoRequirement
+{
+    /// The step that uses the feature, whose explanation tells which release it needs
+    step: &'static str,
+    /// What needs the release, as it's told when the live system is older
+    feature: &'static str,
+    /// The first release of the ISO the feature works on
+    version: &'static str,
+    /// Whether the configuration uses the feature
+    needed: fn(&InstallOptions) -> bool,
+}
+
+/// Every feature that needs a recent enough release of the ISO; each of them is defined next to
+/// the code that uses it
+const ISO_REQUIREMENTS: &[IsoRequirement] = &[
+    Partition::FDISK_TYPE_NAMES,
+    Partition::LUKS2,
+    InstallOptions::NSPAWN_RESOLV_CONF,
+];
+
+/// What the preflight checks do about missing programs with `offline: true`: stop, listing them
+const MISSING_TOOLS_OFFLINE: &str = r#"if [ -n "$jimmy_missing" ]; then
+    echo "error: missing programs:$jimmy_missing" >&2
+    echo "hint: the script is meant to be ran from the Arch live environment; elsewhere, install$jimmy_packages first" >&2
+    exit 1
+fi"#;
+
+/// Work out the size of the partition that takes the rest of the disk `{device}`, whose size wasn't
+/// declared, so that `{reserve_end}` is left free at its end: what's left of it once GPT has taken
+/// `{overhead}` MiB and the other partitions `{used}` MiB, less the `{reserved}` MiB reserved. The
+/// script stops if there's nothing left
+const RESERVE_END_CMDS: &str = r#"# the partition that takes the rest of {disk} leaves {reserve_end} free at its end
+jimmy_disk_bytes=$(blockdev --getsize64 {device}) || exit 1
+jimmy_rest_mib=$((jimmy_disk_bytes / 1048576 - {overhead} - {used} - {reserved}))
+if [ "$jimmy_rest_mib" -le 0 ]; then
+    echo "error: disk {disk} is $((jimmy_disk_bytes / 1048576))M, which leaves no space for the partition that takes the rest of it once {reserve_end} is reserved at its end" >&2
+    exit 1
+fi"#;
+
+/// Stop before anything is changed if the reused partition `{partition}`, mounted at `{mount}`,
+/// doesn't hold the `{format}` filesystem it's declared with, which blkid calls `{type}`
+const REUSE_CHECK: &str = r#"# {partition} is reused as it is, so it has to hold the {format} filesystem it's declared with
+jimmy_found=$(blkid -s TYPE -o value {partition}) || jimmy_found=
+if [ "$jimmy_found" != {type} ]; then
+    echo "error: {partition} is reused for {mount}, but blkid finds ${jimmy_found:-no filesystem} on it instead of {type}; nothing was changed" >&2
+    exit 1
+fi"#;
+
+/// Stop unless given `--allow-install-medium` if one of the disks `{disks}` holds the live medium.
+/// It's found from what archiso mounted under /run/archiso and, since with `copytoram` the medium
+/// is no longer mounted once it's been copied, from the kernel command line, which names it by
+/// `archisodevice`, `archisolabel` or `archisosearchuuid`
+const INSTALL_MEDIUM_CHECK: &str = r#"# the devices of the live medium, even if it was copied to RAM
+jimmy_medium=$(findmnt -nro SOURCE,TARGET | awk '$2 ~ /^\/run\/archiso\// { print $1 }')
+for jimmy_arg in $(cat /proc/cmdline); do
+    case "$jimmy_arg" in
+        archisodevice=UUID=*) jimmy_medium="$jimmy_medium /dev/disk/by-uuid/${jimmy_arg#archisodevice=UUID=}" ;;
+        archisodevice=LABEL=*) jimmy_medium="$jimmy_medium /dev/disk/by-label/${jimmy_arg#archisodevice=LABEL=}" ;;
+        archisodevice=*) jimmy_medium="$jimmy_medium ${jimmy_arg#archisodevice=}" ;;
+        archisolabel=*) jimmy_medium="$jimmy_medium /dev/disk/by-label/${jimmy_arg#archisolabel=}" ;;
+        archisosearchuuid=*) jimmy_medium="$jimmy_medium /dev/disk/by-uuid/${jimmy_arg#archisosearchuuid=}" ;;
+    esac
+done
+for jimmy_medium_disk in $(for jimmy_device in $jimmy_medium; do lsblk -nsrpo NAME,TYPE "$jimmy_device" 2>/dev/null; done | awk '$2 == "disk" { print $1 }'); do
+    for jimmy_disk in {disks}; do
+        if [ "$(readlink -f "$jimmy_disk")" = "$jimmy_medium_disk" ] && [ "$JIMMY_ALLOW_INSTALL_MEDIUM" != 1 ]; then
+            echo "error: $jimmy_disk holds the live medium, even if it was copied to RAM; pass --allow-install-medium to continue anyway" >&2
+            exit 1
+        fi
+    done
+done"#;
+
+/// Delete the boot entries with the label `{}`, such as those left by earlier installations, so
+/// that they don't pile up in NVRAM. `efibootmgr` lists them as `Boot0003* Arch Linux`, followed
+/// by a tab and the device path on newer versions; inactive entries have a space instead of `*`
+const EFI_ENTRY_CLEANUP: &str = r#"# remove the boot entries with the same label, so that they don't pile up
+for jimmy_entry in $(efibootmgr | JIMMY_LABEL={} awk '/^Boot[0-9A-F][0-9A-F][0-9A-F][0-9A-F][* ] / { label = substr($0, 11); sub(/\t.*/, "", label); if (label == ENVIRON["JIMMY_LABEL"]) print substr($0, 5, 4) }'); do
+    efibootmgr --bootnum "$jimmy_entry" --delete-bootnum >/dev/null
+done"#;
+
+/// Return the label of the boot entry of the secondary EFI system partition at the given index:
+/// the label of the primary one's, numbered after it
+fn secondary_esp_label(label: &str, index: usize) -> String
+{
+    format!("{} (ESP {})", label, index + 2)
+}
+
+/// Where the outer script records, relative to the root of the new system, the hash of the
+/// configuration file it was generated from
+const CONFIG_HASH_MARKER: &str = "/var/lib/jimmy/config.hash";
+
+/// Where the arch-chroot script is written on the installed system, for the time it runs
+const CHROOT_SCRIPT_PATH: &str = "/jimmy_part2.sh";
+
+/// Stop the arch-chroot script if the system was installed by a script generated from another
+/// configuration file, unless `JIMMY_IGNORE_CONFIG_HASH=1`. It runs anywhere there's no marker
+const CONFIG_HASH_CHECK: &str = r#"# stop if the system was installed from another configuration file
+jimmy_config_hash={hash}
+if [ -f {marker} ] && [ "$(cat {marker})" != "$jimmy_config_hash" ] && [ "$JIMMY_IGNORE_CONFIG_HASH" != 1 ]; then
+    echo "error: the system was installed from a configuration file with the hash $(cat {marker}), but this script was generated from one with the hash $jimmy_config_hash; set JIMMY_IGNORE_CONFIG_HASH=1 to continue anyway" >&2
+    exit 1
+fi"#;
+
+/// A part of the installation: a status message, followed by the commands that carry it out. The
+/// name is what the step is called in the timings summary, and identifies its status message and
+/// its description
+struct Step
+{
+    name: &'static str,
+    cmds: String,
+    description: &'static str,
+    rerun: Rerun,
+    /// The phase of the installation the step belongs to, if any
+    phase: Option<&'static str>,
+}
+
+impl Step
+{
+    /// Create a step, in the phase `STEP_PHASES` puts it in; panic if it has no description in
+    /// `STEP_DESCRIPTIONS`, or isn't in `STEP_RERUNS`
+    fn new(name: &'static str, cmds: String) -> Self
+    {
+        let description = STEP_DESCRIPTIONS.iter()
+            .find(|(key, _)| *key == name)
+            .map(|(_, description)| *description)
+            .filter(|description| !description.is_empty())
+            .unwrap_or_else(|| panic!("step {:?} has no description", name));
+        let rerun = STEP_RERUNS.iter()
+            .find(|(key, _)| *key == name)
+            .map(|(_, rerun)| *rerun)
+            .unwrap_or_else(|| panic!("step {:?} doesn't say what running it again does", name));
+        let phase = STEP_PHASES.iter()
+            .find(|(key, _)| *key == name)
+            .map(|(_, phase)| *phase);
+        Self { name, cmds, description, rerun, phase }
+    }
+
+    /// Return the step, running which again does `rerun` instead of what `STEP_RERUNS` says,
+    /// since the configuration file changes it
+    fn with_rerun(self, rerun: Rerun) -> Self
+    {
+        Self { rerun, ..self }
+    }
+
+    /// Return the shell code for this step, with its status message in the given language, for
+    /// the new system mounted on `mount_root`, and written the way `ctx` asks; if `timed`, also
+    /// record how long the step took. The step is named in `JIMMY_STEP` while it runs, for the
+    /// status of the installation
+    fn render(&self, timed: bool, language: Language, mount_root: &str, ctx: &RenderContext) -> String
+    {
+        let msg = format!("<-> {}", message(language, self.name).replace("{root}", mount_root));
+        let cmds = format!("JIMMY_STEP={}\n{}", shell_quote(self.name), self.cmds);
+        if timed {
+            echo_status(&msg, &format!(
+                "jimmy_step_start=$(date +%s)\n{}\njimmy_time '{}' \"$jimmy_step_start\" >>\"$JIMMY_TIMINGS\"",
+                cmds,
+                self.name,
+            ), ctx.color)
+        } else {
+            echo_status(&msg, &cmds, ctx.color)
+        }
+    }
+
+    /// Return the name, the description and the commands of this step, as plain text or Markdown,
+    /// for the new system mounted on `mount_root`; the description ends with the releases of the
+    /// ISO the features in `requirements` need
+    fn explain(&self, index: usize, markdown: bool, mount_root: &str, requirements: &[&IsoRequirement]) -> String
+    {
+        let mut description = self.description.replace("{root}", mount_root);
+        for requirement in requirements.iter().filter(|r| r.step == self.name) {
+            description += &format!(" It needs a live system made from the ISO released on {} or later, for {}.",
+                requirement.version, requirement.feature);
+        }
+        let rerun = match self.rerun {
+            Rerun::Idempotent => "Running it again leaves the system as running it once does, so a failed installation can be resumed from it.",
+            Rerun::NotIdempotent => "Running it again isn't safe: some of it would fail, or be done twice.",
+            Rerun::Destructive => "Running it again wipes out what was done since, so a failed installation can't be resumed from it, only started over.",
+        };
+        if markdown {
+            format!("## {}. {}\n\n{}\n\n{}\n\n```sh\n{}\n```\n", index, self.name, description, rerun, self.cmds)
+        } else {
+            format!("{}. {}\n\n{}\n\n{}\n\n{}\n",
+                index,
+                self.name,
+                wrap(&description, 80, "    "),
+                wrap(rerun, 80, "    "),
+                self.cmds.lines()
+                    .map(|line| format!("    {}", line).trim_end().to_string())
+                    .collect::<Vec<String>>()
+                    .join("\n"),
+            )
+        }
+    }
+}
+
+/// Render rows of cells as a table whose columns are aligned, with a header; as plain text, the
+/// columns are separated by two spaces, and as Markdown, by pipes
+fn summary_table(header: &[&str], rows: &[Vec<String>], markdown: bool) -> String
+{
+    let widths: Vec<usize> = (0..header.len())
+        .map(|i| rows.iter().map(|r| r[i].chars().count()).chain([header[i].len()]).max().unwrap())
+        .collect();
+    let line = |cells: Vec<String>| {
+        let padded = cells.iter().zip(&widths)
+            .map(|(c, w)| format!("{:<w$}", c, w = w))
+            .collect::<Vec<String>>();
+        if markdown {
+            format!("| {} |", padded.join(" | "))
+        } else {
+            padded.join("  ").trim_end().to_string()
+        }
+    };
+    let mut lines = vec![line(header.iter().map(|h| h.to_string()).collect())];
+    lines.push(if markdown {
+        format!("|{}|", widths.iter().map(|w| "-".repeat(w + 2)).collect::<Vec<String>>().join("|"))
+    } else {
+        widths.iter().map(|w| "-".repeat(*w)).collect::<Vec<String>>().join("  ")
+    });
+    lines.extend(rows.iter().map(|r| line(r.clone())));
+    lines.join("\n")
+}
+
+/// Break a text into lines no longer than `width`, each starting with `indent`
+fn wrap(text: &str, width: usize, indent: &str) -> String
+{
+    let mut lines = vec![];
+    let mut line = String::new();
+    for word in text.split_whitespace() {
+        if !line.is_empty() && indent.len() + line.len() + 1 + word.len() > width {
+            lines.push(format!("{}{}", indent, line));
+            line.clear();
+        }
+        if !line.is_empty() {
+            line.push(' ');
+        }
+        line.push_str(word);
+    }
+    lines.push(format!("{}{}", indent, line));
+    lines.join("\n")
+}
+
+impl InstallOptions
+{
+    /// Return the steps that check that the installation can go on: finding the disks given by
+    /// stable identifiers, if any, and the preflight checks
+    fn setup_steps(&self) -> Vec<Step>
+    {
+        let mut steps = vec![];
+        let resolve_disks = self.resolve_disks_cmds();
+        if !resolve_disks.is_empty() {
+            let step = Step::new("resolve disks", resolve_disks.join("\n"));
+            // the disk images it creates are created anew
+            steps.push(match self.image.as_ref().is_some_and(|i| i.create) && self.unique_disks_used().iter().any(|d| is_image_disk(d)) {
+                true => step.with_rerun(Rerun::Destructive),
+                false => step,
+            });
+        }
+        steps.push(Step::new("preflight", self.preflight_checks()));
+        steps
+    }
+
+    /// Return the timed steps of the installation, in the order they're ran in, with the
+    /// arch-chroot script written the way `ctx` asks
+    fn install_steps(&self, ctx: &RenderContext) -> Vec<Step>
+    {
+        let mut steps = vec![
+            Step::new(
+                "previous mounts",
+                self.previous_mounts_cmds(),
+            ),
+            Step::new(
+                "clock",
+                "timedatectl set-ntp true".to_string(),
+            ),
+            Step::new(
+                "partitioning",
+                self.fdisk_cmds().join("\n"),
+            ),
+            Step::new(
+                "formatting",
+                self.format_cmds(),
+            ),
+            Step::new(
+                "mounting",
+                // Always mount root partition first, and every mount point before those inside
+                // it, whatever disks they're on; swap partitions have no mount point, and are
+                // activated last
+                {
+                    let mut ps = self.map_partitions(|p, device| p.mount_cmd(device, &self.mount_root));
+                    ps.sort_by_key(|(p, _)| match p.mount.as_str() {
+                        "" => usize::MAX,
+                        mount => mount.split('/').filter(|c| !c.is_empty()).count(),
+                    });
+
+                    [
+                        map_snd(ps),
+                        map_snd(self.map_partitions(Partition::unmounted_note)),
+                    ].concat().join("\n")
+                },
+            ),
+            match self.bootstrap.as_str() {
+                "tarball" => Step::new("packages", self.pacstrap_cmds(ctx)),
+                _ => Step::new("pacstrap", self.pacstrap_cmds(ctx)),
+            },
+            Step::new(
+                "fstab",
+                [
+                    vec![FSTAB_WRITE.replace("{marker}", FSTAB_MARKER).replace("{root}", &self.mount_root)],
+                    map_snd(self.map_partitions(|p, device| p.fstab_cmd(device, &self.mount_root))),
+                ].concat().join("\n"),
+            ),
+            // The system configuration part is a bit complicated, since we first need to create a
+            // different script, put it in the mount root, run it with arch-chroot, and then delete it after
+            // we're done.
+            // Check `https://bbs.archlinux.org/viewtopic.php?id=204252`
+            Step::new(
+                "chroot script",
+                [
+                    format!("mkdir -p {}", self.target(CONFIG_HASH_MARKER.rsplit_once('/').unwrap().0)),
+                    format!("echo {} >{}", self.config_hash, self.target(CONFIG_HASH_MARKER)),
+                    heredoc_cmd(&self.target(CHROOT_SCRIPT_PATH), &self.render_chroot_script(ctx), false),
+                    format!("chmod +x {}", self.target(CHROOT_SCRIPT_PATH)),
+                ].into_iter()
+                    .chain(self.keymap.as_ref().map(|_| format!("printf '%s\\n' \"$JIMMY_LIVE_KEYMAP\" >{}", self.target(LIVE_KEYMAP_PATH))))
+                    .collect::<Vec<String>>()
+                    .join("\n"),
+            ),
+            // efibootmgr adds the boot entries again unless the old ones are deleted first
+            match self.keep_existing_entries && (self.bootloader == Bootloader::Efistub || !secondary_esps(&self.partitions).is_empty()) {
+                true => Step::new("configuration", self.configuration_cmds()).with_rerun(Rerun::NotIdempotent),
+                false => Step::new("configuration", self.configuration_cmds()),
+            },
+            Step::new(
+                "cleanup",
+                match self.keymap {
+                    Some(_) => format!("rm -f {} {}", self.target(CHROOT_SCRIPT_PATH), self.target(LIVE_KEYMAP_PATH)),
+                    None => format!("rm -f {}", self.target(CHROOT_SCRIPT_PATH)),
+                },
+            ),
+            Step::new(
+                "verification",
+                self.verification_cmds(),
+            ),
+        ];
+
+        if self.keeps_resolv_conf() {
+            let chroot = steps.iter().position(|s| s.name == "configuration").unwrap();
+            let backup = self.target(RESOLV_CONF_BACKUP);
+            steps.insert(chroot + 1, Step::new("restore resolv.conf", RESOLV_CONF_RESTORE.replace("{backup}", &backup).replace("{root}", &self.mount_root)));
+            steps.insert(chroot, Step::new("resolv.conf", RESOLV_CONF_COPY.replace("{backup}", &backup).replace("{root}", &self.mount_root)));
+        }
+        if let Some(skel) = &self.skel {
+            // the users are created by the arch-chroot script, from what's in /etc/skel by then
+            let chroot = steps.iter().position(|s| s.name == "chroot script").unwrap();
+            steps.insert(chroot, Step::new(
+                "skel",
+                format!("mkdir -p {0}\ncp -R --no-preserve=ownership {1}/. {0}/", self.target("/etc/skel"), shell_quote(skel)),
+            ));
+        }
+        if let Some(tarball) = &self.tarball {
+            let packages = steps.iter().position(|s| s.name == "packages").unwrap();
+            steps.insert(packages, Step::new("tarball", self.tarball_cmds(tarball, ctx)));
+        }
+        if let Some(date) = &self.snapshot_date {
+            // before the mirrorlist is copied onto the new system
+            let pacstrap = steps.iter().position(|s| s.name == "pacstrap" || s.name == "tarball").unwrap();
+            steps.insert(pacstrap, Step::new(
+                "snapshot",
+                [
+                    format!("[ -f {0} ] || cp /etc/pacman.d/mirrorlist {0}", MIRRORLIST_BACKUP),
+                    heredoc_cmd("/etc/pacman.d/mirrorlist", &snapshot_mirrorlist(date), false),
+                ].join("\n"),
+            ));
+        }
+        let luks = map_snd(self.map_partitions(Partition::luks_cmds));
+        if !luks.is_empty() {
+            // the encrypted partitions are opened before they're formatted
+            let partitioning = steps.iter().position(|s| s.name == "partitioning").unwrap();
+            steps.insert(partitioning + 1, Step::new("encryption", luks.join("\n")));
+        }
+        let post_format = map_snd(self.map_partitions(Partition::post_format_cmds));
+        if !post_format.is_empty() {
+            let formatting = steps.iter().position(|s| s.name == "formatting").unwrap();
+            steps.insert(formatting + 1, Step::new("post-format", post_format.join("\n")));
+        }
+        let pre_format: Vec<String> = self.unique_disks_used().iter()
+            .filter_map(|disk| Some(with_device(&disk_device(disk), self.pre_format.get(disk)?, &format!("pre_format commands of {}", disk))))
+            .collect();
+        if !pre_format.is_empty() {
+            // the commands are given the whole disk, so they come before its partition table
+            let partitioning = steps.iter().position(|s| s.name == "partitioning").unwrap();
+            steps.insert(partitioning, Step::new("pre-format", pre_format.join("\n")));
+        }
+        let crypttab = map_snd(self.map_partitions(|p, device| p.crypttab_cmds(device, &self.mount_root)));
+        if !crypttab.is_empty() {
+            // genfstab refers to the filesystems by UUID, so its entries for the encrypted
+            // partitions have to be rewritten after it has run
+            let fstab = steps.iter().position(|s| s.name == "fstab").unwrap();
+            steps.insert(fstab + 1, Step::new("crypttab", crypttab.join("\n")));
+        }
+        if let Some(keymap) = &self.keymap {
+            // before anything is typed, such as the passphrases of the encrypted partitions
+            steps.insert(0, Step::new("keymap", KEYMAP_LOAD.replace("{keymap}", keymap)));
+        }
+        steps.retain(|s| s.phase.is_none_or(|phase| self.runs_phase(phase)));
+
+        steps
+    }
+
+    /// Return whether the script carries out the given phase of the installation
+    fn runs_phase(&self, phase: &str) -> bool
+    {
+        self.phases.iter().any(|p| p == phase)
+    }
+
+    /// Return the files jimmy leaves on the installed system to tell how it was installed, with
+    /// their paths on it
+    fn artifacts(&self) -> Vec<(Artifact, String)>
+    {
+        // the marker and the warnings are written by the arch-chroot script and the step before it
+        let chroot = self.runs_phase("chroot");
+        let mut artifacts = vec![];
+        if chroot {
+            artifacts.push((Artifact::ConfigHash, CONFIG_HASH_MARKER.to_string()));
+        }
+        if self.timings {
+            artifacts.push((Artifact::Timings, TIMINGS_PATH.to_string()));
+        }
+        if chroot && self.root_password_policy == "prompt-with-fallback" {
+            artifacts.push((Artifact::Warnings, WARNINGS_PATH.to_string()));
+        }
+        if let Some(path) = &self.report {
+            artifacts.push((Artifact::Report, path.clone()));
+        }
+        artifacts
+    }
+
+    /// Return the step that removes the files jimmy left on the installed system which `cleanup`
+    /// doesn't keep, if there are any, along with the directories of jimmy they were in
+    fn artifacts_step(&self) -> Option<Step>
+    {
+        let removed: Vec<String> = self.artifacts().into_iter()
+            .filter(|(artifact, _)| !artifact.kept_by(&self.cleanup))
+            .map(|(_, path)| path)
+            .collect();
+        if removed.is_empty() {
+            return None;
+        }
+        let mut dirs: Vec<&str> = removed.iter()
+            .filter_map(|path| path.rsplit_once('/').map(|(dir, _)| dir))
+            .filter(|dir| dir.ends_with("/jimmy"))
+            .collect();
+        dirs.dedup();
+        let mut cmds = vec![format!("rm -f {}", removed.iter().map(|p| shell_quote(&self.target(p))).collect::<Vec<String>>().join(" "))];
+        if !dirs.is_empty() {
+            cmds.push(format!("rmdir --ignore-fail-on-non-empty {}", dirs.iter().map(|d| self.target(d)).collect::<Vec<String>>().join(" ")));
+        }
+        Some(Step::new("artifacts", cmds.join("\n")))
+    }
+
+    /// Return the step that unmounts the new system once it's installed, unless it's left mounted
+    fn unmount_step(&self) -> Option<Step>
+    {
+        if !self.runs_phase("unmount") {
+            return None;
+        }
+        Some(Step::new(
+            "unmount",
+            [
+                vec![format!("! mountpoint -q {0} || umount -R {0}", self.mount_root)],
+                self.close_containers_cmds(),
+                // the loop devices go last, once nothing on them is in use
+                self.unique_disks_used().iter()
+                    .filter(|d| is_image_disk(d))
+                    .map(|d| format!("losetup --detach \"${}\"", disk_variable(d).unwrap()))
+                    .collect(),
+            ].concat().join("\n"),
+        ))
+    }
+
+    /// Return the commands that close the encrypted partitions that are open
+    fn close_containers_cmds(&self) -> Vec<String>
+    {
+        self.partitions.iter()
+            .filter_map(Partition::mapper_name)
+            .map(|name| format!("! [ -e /dev/mapper/{0} ] || cryptsetup close {0}", name))
+            .collect()
+    }
+
+    /// Return the commands that deal with what an earlier attempt left mounted under the mount
+    /// root, as `previous_mounts` asks
+    fn previous_mounts_cmds(&self) -> String
+    {
+        let mut cmds = vec![MOUNTED_UNDER_ROOT.replace("{root}", &self.mount_root)];
+        match self.previous_mounts.as_str() {
+            "unmount" => {
+                cmds.push(PREVIOUS_MOUNTS_UNMOUNT.replace("{root}", &self.mount_root));
+                cmds.extend(self.close_containers_cmds());
+            },
+            _ => {
+                let unmount = std::iter::once(format!("umount -R {}", self.mount_root))
+                    .chain(self.partitions.iter().filter_map(Partition::mapper_name).map(|name| format!("cryptsetup close {}", name)))
+                    .collect::<Vec<String>>()
+                    .join(" && ");
+                cmds.push(PREVIOUS_MOUNTS_ABORT.replace("{unmount}", &unmount).replace("{root}", &self.mount_root));
+            },
+        }
+        cmds.join("\n")
+    }
+
+    /// Return what the installation is going to do, in numbers
+    pub fn summary(&self) -> Summary
+    {
+        Summary {
+            partitions: self.partitions.len(),
+            disks: self.unique_disks_used(),
+            disk_plans: self.unique_disks_used().iter()
+                .map(|d| disk_plan(d, self.disks.get(d), &self.partitions_on_disk(d)))
+                .collect(),
+            packages: self.package_list().len(),
+            steps: self.setup_steps().iter()
+                .chain(self.install_steps(&RenderContext::default()).iter())
+                .chain(self.artifacts_step().iter())
+                .chain(self.unmount_step().iter())
+                .map(|s| s.name)
+                .collect(),
+            layout: self.map_partitions(Partition::describe).into_iter().filter_map(|(_, d)| d).collect(),
+        }
+    }
+
+    /// Return every step of the installation, with what it does and why, as plain text or Markdown
+    pub fn explain(&self, markdown: bool) -> String
+    {
+        self.setup_steps().iter()
+            .chain(self.install_steps(&RenderContext::default()).iter())
+            .chain(self.artifacts_step().iter())
+            .chain(self.unmount_step().iter())
+            .enumerate()
+            .map(|(i, step)| step.explain(i + 1, markdown, &self.mount_root, &self.iso_requirements()))
+            .collect::<Vec<String>>()
+            .join("\n")
+    }
+
+    /// Return a one-page summary of the installation, as plain text or Markdown, for people who
+    /// have to approve it without reading the script: the machine's settings, the users, the
+    /// partitions, and what's installed and enabled on top of the base system
+    pub fn summarize(&self, markdown: bool) -> String
+    {
+        let plan = self.report_plan();
+        let settings = vec![
+            vec!["hostname".to_string(), plan.identity.hostname.clone()],
+            vec!["timezone".to_string(), plan.timezone.clone()],
+            vec!["locales".to_string(), plan.locales.join(", ")],
+            vec!["keymap".to_string(), plan.keymap.clone()],
+            vec!["bootloader".to_string(), plan.bootloader.to_string()],
+            vec!["kernel".to_string(), format!("{} ({})", plan.kernel, plan.arch)],
+        ];
+        let mut users = vec![vec![
+            "root".to_string(),
+            "-".to_string(),
+            "-".to_string(),
+            format!("password: {}", self.root_password_policy),
+        ]];
+        users.extend(self.users.iter().map(|u| {
+            let mut privileges = vec![];
+            // the sudo section lets the wheel group run anything
+            if u.groups.iter().any(|g| g == "wheel") {
+                privileges.push("sudo");
+            }
+            if u.home_encryption {
+                privileges.push("encrypted home");
+            }
+            vec![
+                u.name.clone(),
+                if u.groups.is_empty() { "-".to_string() } else { u.groups.join(", ") },
+                if u.shell.is_empty() { "-".to_string() } else { u.shell.clone() },
+                if privileges.is_empty() { "-".to_string() } else { privileges.join(", ") },
+            ]
+        }));
+        // what's on the partitions is only worth a column if some of it is kept
+        let any_reused = self.partitions.iter().any(|p| p.reuse);
+        let layout = plan.disks.iter()
+            .flat_map(|disk| self.partitions_on_disk(disk).into_iter().enumerate())
+            .map(|(idx, p)| [vec![
+                p.disk.clone(),
+                (idx + 1).to_string(),
+                p.format.clone(),
+                match (p.size, self.reserve_end.get(&p.disk)) {
+                    _ if p.reuse => "as it is".to_string(),
+                    (Some(size), _) => size.to_string(),
+                    (None, Some(reserved)) => format!("rest of the disk, less {}", reserved),
+                    // the space the partitions replaced before the reused ones leave
+                    (None, None) if self.partitions_on_disk(&p.disk).iter().any(|p| p.reuse) => "rest of the free space".to_string(),
+                    (None, None) => "rest of the disk".to_string(),
+                },
+                match (p.format.as_str(), p.mount.as_str()) {
+                    ("swap", _) => "swap".to_string(),
+                    (_, "") if p.esp => "secondary EFI system partition".to_string(),
+                    (_, "") if p.member_of.is_some() => format!("member of {}", p.member_of.as_deref().unwrap_or_default()),
+                    (_, "") => "-".to_string(),
+                    (_, mount) => mount.to_string(),
+                },
+                match &p.encryption {
+                    Some(e) if e.tpm2 => "LUKS, TPM2".to_string(),
+                    Some(_) => "LUKS".to_string(),
+                    None => "-".to_string(),
+                },
+            ], match (any_reused, p.reuse) {
+                (false, _) => vec![],
+                (true, true) => vec!["PRESERVED".to_string()],
+                (true, false) => vec!["erased".to_string()],
+            }].concat())
+            .collect::<Vec<Vec<String>>>();
+        let layout_columns: &[&str] = match any_reused {
+            true => &["disk", "#", "format", "size", "mount", "encryption", "data"],
+            false => &["disk", "#", "format", "size", "mount", "encryption"],
+        };
+        let packages = self.extra.split_whitespace().collect::<Vec<&str>>();
+        let mut services = self.enabled_services().into_iter()
+            .map(|s| (if s.unit.contains('.') { s.unit.to_string() } else { format!("{}.service", s.unit) }, s.laptop_only))
+            .map(|(unit, laptop_only)| if laptop_only { format!("{} (on laptops)", unit) } else { unit })
+            .collect::<Vec<String>>();
+        services.sort_unstable();
+        services.dedup();
+        let list = |items: &[String]| match (items.is_empty(), markdown) {
+            (true, _) => "none".to_string(),
+            (false, true) => items.join(", "),
+            (false, false) => wrap(&items.join(", "), 80, ""),
+        };
+
+        let mut sections = vec![
+            ("Settings", summary_table(&["setting", "value"], &settings, markdown)),
+            ("Users", summary_table(&["user", "groups", "shell", "privileges"], &users, markdown)),
+            ("Disk layout", summary_table(layout_columns, &layout, markdown)),
+            ("Notable packages", list(&packages.iter().map(|p| p.to_string()).collect::<Vec<String>>())),
+            ("Services", list(&services)),
+        ];
+        if !plan.unused.is_empty() {
+            let groups = plan.unused.groups().into_iter()
+                .map(|(what, found)| {
+                    let items = found.iter()
+                        .map(|f| format!("- {}", if markdown { f.clone() } else { wrap(f, 78, "").replace('\n', "\n  ") }))
+                        .collect::<Vec<String>>();
+                    format!("{}:\n\n{}", what, items.join("\n"))
+                })
+                .collect::<Vec<String>>();
+            sections.push(("Set up but unused", groups.join("\n\n")));
+        }
+        let title = format!("Installation of {}", self.hostname);
+        let mut summary = if markdown { format!("# {}\n", title) } else { format!("{}\n{}\n", title, "=".repeat(title.len())) };
+        for (heading, body) in sections {
+            summary += &if markdown {
+                format!("\n## {}\n\n{}\n", heading, body)
+            } else {
+                format!("\n{}\n\n{}\n", heading, body)
+            };
+        }
+        summary
+    }
+
+    /// Return the comments every script starts with, saying what generated it and from what, so
+    /// that a script pasted into a bug report tells where it came from; `what` is the kind of
+    /// script, such as `installation script`
+    fn script_header(&self, what: &str, ctx: &RenderContext) -> String
+    {
+        let mut header = vec![
+            format!("#!{}", ctx.shell.interpreter()),
+            format!("# {} automatically generated by jimmy-rs {}", what, crate::VERSION),
+            format!("# configuration format version: {}", CONFIG_VERSION),
+            format!("# bootloader: {}, kernel: {}", self.bootloader.name(), self.kernel.package(self.arch)),
+            format!("# disks: {}", self.unique_disks_used().join(", ")),
+            format!("# the system is configured with {}", self.chroot_backend),
+        ];
+        if !ctx.reproducible {
+            header.push(format!("# generated at {}", utc_timestamp(std::time::SystemTime::now())));
+        }
+        if self.phases.len() < PHASES.len() {
+            header.push(format!("# phases: {}", self.phases.join(", ")));
+        }
+        if let Some(reused) = self.reused_partitions() {
+            header.push(format!("# reused partitions, kept as they are: {}", reused));
+        }
+        for (what, found) in unused(self).groups() {
+            header.push(format!("# unused {}:", what));
+            header.extend(found.iter().map(|f| format!("#   {}", f)));
+        }
+        header.push(format!("# configuration hash: {}", self.config_hash));
+        header.join("\n")
+    }
+
+    /// Create the script that applies the settings and installs the system, written the way `ctx`
+    /// asks
+    pub fn render_shellscript(&self, ctx: &RenderContext) -> String
+    {
+        let mut script = vec![self.script_header("installation script", ctx), self.status_setup(ctx)];
+        script.extend(self.setup_steps().iter().map(|s| s.render(false, self.language, &self.mount_root, ctx)));
+        if self.timings {
+            script.push(TIMINGS_SETUP.to_string());
+        }
+        if self.report.is_some() {
+            script.push(REPORT_SETUP.to_string());
+        }
+        script.extend(self.install_steps(ctx).iter().map(|s| s.render(self.timings, self.language, &self.mount_root, ctx)));
+        if self.timings {
+            // the summary is saved on the target system, so it has to be done before unmounting
+            script.push(echo_status(
+                &self.status("timings"),
+                &TIMINGS_SUMMARY.replace("{root}", &self.mount_root),
+                ctx.color,
+            ));
+        }
+        if let Some(path) = &self.report {
+            // after the timings, which it includes, but before stopping on a failed verification,
+            // so that a failed installation gets a report too
+            script.push(echo_status(&self.status("report"), &self.report_cmds(path), ctx.color));
+        }
+        // the verification step is the last of the chroot phase
+        let chroot = self.runs_phase("chroot");
+        if chroot {
+            script.push(VERIFY_RESULT.replace("{root}", &self.mount_root));
+        }
+        let warnings = chroot && self.root_password_policy == "prompt-with-fallback";
+        if warnings {
+            // read before `cleanup` may remove them, to be shown once everything else is done
+            script.push(format!("jimmy_warnings=$(cat {} 2>/dev/null)", self.target(WARNINGS_PATH)));
+        }
+        if let Some(step) = self.artifacts_step() {
+            script.push(step.render(false, self.language, &self.mount_root, ctx));
+        }
+        match self.unmount_step() {
+            Some(step) => script.extend([
+                step.render(false, self.language, &self.mount_root, ctx),
+                done_line(&self.status("done"), ctx.color),
+            ]),
+            None => script.push(done_line(&self.status("done mounted"), ctx.color)),
+        }
+        if warnings {
+            script.push(r#"if [ -n "$jimmy_warnings" ]; then
+    printf '%s\n' "$jimmy_warnings" | sed 's/^/warning: /' >&2
+fi"#.to_string());
+        }
+        script.join("\n\n") + "\n"
+    }
+
+    /// Return the commands that check that the installation did what the configuration asked for:
+    /// the kernel and the bootloader are in place, every mountpoint is in the filesystem table,
+    /// and every user exists
+    fn verification_cmds(&self) -> String
+    {
+        let image = self.kernel.image(self.arch);
+        let initramfs = self.kernel.initramfs();
+        let mut checks = vec![
+            (format!("kernel /boot/{}", image), format!("test -f {}", self.target(&format!("/boot/{}", image)))),
+            (format!("initramfs /boot/{}", initramfs), format!("test -f {}", self.target(&format!("/boot/{}", initramfs)))),
+        ];
+        checks.push(match self.bootloader {
+            Bootloader::Grub => ("GRUB configuration /boot/grub/grub.cfg".to_string(), format!("test -f {}", self.target("/boot/grub/grub.cfg"))),
+            Bootloader::Efistub => (
+                format!("boot entry '{}'", self.boot_entry_label),
+                format!("{} | grep -qF {}", self.chroot_cmd("efibootmgr -v"), shell_quote(&self.boot_entry_label)),
+            ),
+            Bootloader::SystemdBoot => {
+                let boot = find_xbootldr(self.bootloader, &self.partitions).unwrap_or(self.esp());
+                (
+                    format!("boot entry {}/loader/entries/arch.conf", boot.mount),
+                    format!("test -f {}", self.target(&format!("{}/loader/entries/arch.conf", boot.mount))),
+                )
+            },
+        });
+        let fstab_extra = self.fstab_extra();
+        let mountpoints = self.partitions.iter()
+            .filter(|p| !p.mount.is_empty())
+            .map(|p| p.mount.as_str())
+            .chain(fstab_extra.iter().filter(|e| e.dir != "none").map(|e| e.dir.as_str()));
+        for dir in mountpoints {
+            // genfstab doesn't write trailing slashes
+            let dir = match dir.trim_end_matches('/') {
+                "" => "/",
+                d => d,
+            };
+            checks.push((
+                format!("fstab entry for {}", dir),
+                format!("awk '$2 == \"{}\" {{ found = 1 }} END {{ exit !found }}' {}", dir, self.target("/etc/fstab")),
+            ));
+        }
+        // the users with encrypted home directories are only created on the first boot
+        for user in self.users.iter().filter(|u| !u.home_encryption) {
+            checks.push((
+                format!("user {}", user.name),
+                format!("grep -q '^{}:' {}", user.name, self.target("/etc/passwd")),
+            ));
+        }
+
+        let mut cmds = vec![VERIFY_SETUP.to_string()];
+        cmds.extend(checks.iter().map(|(description, check)| {
+            format!("jimmy_verify {} {}", shell_quote(description), shell_quote(check))
+        }));
+        cmds.join("\n")
+    }
+
+    /// Return what's planned for the installation, as it's written to the plan of the report
+    fn report_plan(&self) -> ReportPlan
+    {
+        let disks = self.unique_disks_used();
+        ReportPlan {
+            identity: self.plan_identity(),
+            timezone: self.timezone.clone(),
+            locales: self.locales.clone(),
+            keymap: self.console_keymap().to_string(),
+            arch: self.arch.name(),
+            kernel: self.kernel.package(self.arch),
+            bootloader: self.bootloader.name(),
+            disks: disks.clone(),
+            partitions: disks.iter()
+                .flat_map(|disk| self.partitions_on_disk(disk).into_iter().enumerate())
+                .map(|(idx, p)| ReportPartition {
+                    disk: p.disk.clone(),
+                    number: idx + 1,
+                    format: p.format.clone(),
+                    size: p.size.map(|s| s.to_string()),
+                    mount: Some(p.mount.clone()).filter(|m| !m.is_empty()),
+                    reused: p.reuse,
+                })
+                .collect(),
+            packages: self.package_list(),
+            users: self.users.iter().map(|u| u.name.clone()).collect(),
+            boot_entries: self.boot_entries(),
+            artifacts: self.artifacts().into_iter()
+                .filter(|(artifact, _)| artifact.kept_by(&self.cleanup))
+                .map(|(_, path)| path)
+                .collect(),
+            unused: unused(self),
+        }
+    }
+
+    /// Return what's planned for the installation as JSON, in the shape of the plan of the report
+    pub fn plan(&self) -> serde_json::Value
+    {
+        serde_json::to_value(self.report_plan()).unwrap()
+    }
+
+    /// Return the commands that write the report of the installation to `path` on the target system
+    fn report_cmds(&self, path: &str) -> String
+    {
+        let disks = self.unique_disks_used();
+        let plan = self.report_plan();
+        REPORT_CMDS
+            .replace("{root}", &self.mount_root)
+            .replace("{json_string}", JSON_STRING)
+            .replace("{plan}", &shell_quote(&serde_json::to_string(&plan).unwrap()))
+            .replace("{disks}", &disks.iter().map(|d| disk_device(d)).collect::<Vec<String>>().join(" "))
+            .replace("{dir}", &shell_quote(path.rsplit_once('/').unwrap().0))
+            .replace("{path}", &shell_quote(path))
+    }
+
+    /// Return what identifies the installation in its report and in its status
+    fn plan_identity(&self) -> PlanIdentity
+    {
+        PlanIdentity {
+            jimmy: crate::VERSION,
+            config_hash: self.config_hash.clone(),
+            hostname: self.hostname.clone(),
+            steps: self.summary().steps,
+        }
+    }
+
+    /// Return the commands that write the status of the installation when the script exits; what
+    /// the script writes to stderr is kept in the log of `ctx`, if it has one
+    fn status_setup(&self, ctx: &RenderContext) -> String
+    {
+        let (stderr_setup, stderr_cleanup) = match &ctx.log_path {
+            Some(path) => (
+                format!("JIMMY_STDERR={}\nmkdir -p \"$(dirname \"$JIMMY_STDERR\")\" && : >\"$JIMMY_STDERR\"", shell_quote(path)),
+                "# the log is kept for after the installation".to_string(),
+            ),
+            None => ("JIMMY_STDERR=$(mktemp)".to_string(), "rm -f \"$JIMMY_STDERR\"".to_string()),
+        };
+        STATUS_SETUP
+            .replace("{root}", &self.mount_root)
+            .replace("{stderr_setup}", &stderr_setup)
+            .replace("{stderr_cleanup}", &stderr_cleanup)
+            .replace("{json_string}", JSON_STRING)
+            .replace("{plan}", &shell_quote(&serde_json::to_string(&self.plan_identity()).unwrap()))
+            .replace("{lines}", &STATUS_STDERR_LINES.to_string())
+            .replace("{path}", STATUS_PATH)
+    }
+
+    /// Return the features of the configuration that download something from inside the target
+    /// system, and so need it to resolve names
+    pub fn chroot_network_features(&self) -> Vec<&'static str>
+    {
+        let mut features = vec![];
+        if !self.package_pins.is_empty() {
+            features.push("package_pins");
+        }
+        if self.power != "none" && self.laptop.is_none() {
+            features.push("`power`, without `laptop`,");
+        }
+        features
+    }
+
+    /// Whether anything in the configuration suggests that the machine is shared with another
+    /// system: os-prober, an NTFS partition, or boot entries that are kept
+    pub fn hints_at_dual_boot(&self) -> bool
+    {
+        self.package_list().iter().any(|p| p == "os-prober")
+            || self.partitions.iter().any(|p| p.format == "ntfs")
+            || self.keep_existing_entries
+    }
+
+    /// Whether the resolv.conf of the live system is copied onto the target system for as long as
+    /// the arch-chroot script runs
+    fn keeps_resolv_conf(&self) -> bool
+    {
+        self.keep_resolv_conf.unwrap_or_else(|| !self.chroot_network_features().is_empty())
+    }
+
+    /// systemd-nspawn is told what to do with resolv.conf, which it's been able to since 239
+    const NSPAWN_RESOLV_CONF: IsoRequirement = IsoRequirement {
+        step: "configuration",
+        feature: "`chroot_backend: nspawn` (systemd-nspawn --resolv-conf, systemd 239)",
+        version: "2018.08.01",
+        needed: |o| o.chroot_backend == "nspawn",
+    };
+
+    /// Return the command that runs `cmd` inside the target system, with the chosen backend
+    fn chroot_cmd(&self, cmd: &str) -> String
+    {
+        match self.chroot_backend.as_str() {
+            // the host's resolv.conf is used, as with arch-chroot, unless a copy of it was put
+            // there already, and the EFI variables are made writable so that the bootloader can
+            // register itself
+            "nspawn" => format!(
+                "systemd-nspawn -D {} --as-pid2 --resolv-conf={} --bind=/sys/firmware/efi/efivars {}",
+                self.mount_root,
+                if self.keeps_resolv_conf() { "off" } else { "bind-host" },
+                cmd,
+            ),
+            _ => format!("arch-chroot {} {}", self.mount_root, cmd),
+        }
+    }
+
+    /// Return the commands that run the configuration script inside the target system
+    fn configuration_cmds(&self) -> String
+    {
+        match self.chroot_backend.as_str() {
+            "nspawn" => format!("{}\n{}",
+                CHROOT_RESULT.replace("{root}", &self.mount_root).replace("{cmd}", &self.chroot_cmd(&format!("{}/jimmy_part2.sh", self.image_env()))),
+                self.hwclock_cmd(Some(&self.target("/etc/adjtime"))),
+            ),
+            _ => CHROOT_RESULT.replace("{root}", &self.mount_root).replace("{cmd}", &self.chroot_cmd(&format!("{}./jimmy_part2.sh", self.image_env()))),
+        }
+    }
+
+    /// Return what the arch-chroot script has to be started with for it to know the loop devices
+    /// the disk images are attached to, which it can't find out by itself, since the files are
+    /// outside of the target system: `env` with their variables, if there are any
+    fn image_env(&self) -> String
+    {
+        let vars: Vec<String> = self.unique_disks_used().iter()
+            .filter(|d| is_image_disk(d))
+            .map(|d| format!("{0}_PART=\"${0}_PART\"", disk_variable(d).unwrap()))
+            .collect();
+        match vars.is_empty() {
+            true => String::new(),
+            false => format!("env {} ", vars.join(" ")),
+        }
+    }
+
+    /// Return the command that sets the hardware clock from the system clock, in UTC or in the
+    /// local time of the installed system, and records which in `adjfile`, or /etc/adjtime of the
+    /// system it's ran in
+    fn hwclock_cmd(&self, adjfile: Option<&str>) -> String
+    {
+        let adjfile = adjfile.map(|f| format!(" --adjfile={}", f)).unwrap_or_default();
+        match self.hardware_clock.as_str() {
+            // outside the target system, hwclock would go by the timezone of the live system
+            "localtime" => format!("{}
+{}hwclock --systohc --localtime{}",
+                LOCALTIME_CLOCK_WARNING,
+                if adjfile.is_empty() { String::new() } else { format!("TZ={} ", shell_quote(&self.timezone)) },
+                adjfile,
+            ),
+            _ => format!("hwclock --systohc{}", adjfile),
+        }
+    }
+
+    /// Return the scripts that run the first time the installed system boots, in order
+    fn first_boot_scripts(&self) -> Vec<FirstBootScript>
+    {
+        let mut scripts = Vec::new();
+        let tpm2: Vec<String> = self.partitions.iter()
+            .filter_map(|p| Some((p.mapper_name()?, p.encryption.as_ref().filter(|e| e.tpm2)?)))
+            .map(|(name, encryption)| format!(
+                r#"jimmy_device=$(cryptsetup status {name} | awk '$1 == "device:" {{ print $2 }}')
+if systemd-cryptenroll --tpm2-device=list | grep -q /dev/; then
+    systemd-cryptenroll --tpm2-device=auto --tpm2-pcrs={pcrs} "$jimmy_device"
+else
+    echo "warning: no TPM2 chip found; $jimmy_device is still unlocked with its passphrase only" >&2
+fi"#,
+                name = name,
+                pcrs = encryption.pcrs.iter().map(|p| p.to_string()).collect::<Vec<String>>().join("+"),
+            ))
+            .collect();
+        if !tpm2.is_empty() {
+            // enrolling needs the passphrase, and the PCRs of the installed system, not those of
+            // the live environment
+            scripts.push(FirstBootScript {
+                name: "tpm2",
+                script: tpm2.join("\n"),
+                after: &[],
+                interactive: true,
+            });
+        }
+        let homed_users: Vec<String> = self.users.iter()
+            .filter(|u| u.home_encryption)
+            .map(User::homectl_cmd)
+            .collect();
+        if !homed_users.is_empty() {
+            // systemd-homed can't run inside the arch-chroot session, so the users are created,
+            // and asked for their passwords, on the first boot
+            scripts.push(FirstBootScript {
+                name: "homed",
+                script: homed_users.join("\n"),
+                after: &["systemd-homed.service"],
+                interactive: true,
+            });
+        }
+        let user_services: Vec<String> = self.users.iter().filter_map(User::user_services_cmds).collect();
+        if !user_services.is_empty() {
+            // neither lingering nor the units of the users' systemd instances can be turned on from
+            // inside the arch-chroot session, where logind isn't running; by now, every user
+            // exists, and those with encrypted home directories can't have any of it
+            scripts.push(FirstBootScript {
+                name: "user-services",
+                script: user_services.join("\n"),
+                after: &["systemd-logind.service"],
+                interactive: false,
+            });
+        }
+        if !self.first_boot.is_empty() {
+            scripts.push(FirstBootScript {
+                name: "custom",
+                script: self.first_boot.join("\n"),
+                after: &["network-online.target"],
+                interactive: false,
+            });
+        }
+        scripts
+    }
+
+    /// Return a status message printed by the script, in the configured language
+    fn status(&self, id: &str) -> String
+    {
+        format!("<-> {}", message(self.language, id).replace("{root}", &self.mount_root))
+    }
+
+    /// Return where `path`, a path on the installed system, is on the live system while it's
+    /// installed, under `mount_root`
+    fn target(&self, path: &str) -> String
+    {
+        target_path(&self.mount_root, path)
+    }
+
+    /// Return a status message printed by the arch-chroot script, in the configured language
+    fn chroot_status(&self, id: &str) -> String
+    {
+        format!("<chroot> {}", message(self.language, id))
+    }
+
+    /// Return the commands that find out the kernel names of the disks given by stable identifiers,
+    /// and attach the disk images to loop devices, and store them in variables, along with the
+    /// prefix of their partitions
+    fn resolve_disks_cmds(&self) -> Vec<String>
+    {
+        self.unique_disks_used().iter()
+            .filter_map(|disk| {
+                let var = disk_variable(disk)?;
+                if is_image_disk(disk) {
+                    return Some(self.attach_image_cmds(disk, &var));
+                }
+                Some(format!(r#"{var}=$(readlink -e {path}) || {{ echo 'error: disk {disk} not found' >&2; exit 1; }}
+case "${var}" in
+    *[0-9]) {var}_PART="${{{var}}}p" ;;
+    *) {var}_PART="${var}" ;;
+esac"#,
+                    var = var,
+                    path = stable_disk_path(disk).unwrap(),
+                    disk = disk,
+                ))
+            })
+            .collect()
+    }
+
+    /// Return the commands that attach a disk image to a loop device, after creating it if `image`
+    /// says so, and store the device in `var`; the kernel reads its partitions as it would a
+    /// disk's, so they're at `${var}p1`, `${var}p2`...
+    fn attach_image_cmds(&self, disk: &str, var: &str) -> String
+    {
+        // validation made sure that disk images come along with `image`
+        let image = self.image.as_ref().unwrap();
+        let path = shell_quote(disk);
+        let file = match image.size.filter(|_| image.create) {
+            // a new sparse file, even if an earlier run left one behind
+            Some(size) => format!("truncate --size=0 {0} && truncate --size={1} {0} || {{ echo {2} >&2; exit 1; }}",
+                path, size.bytes, shell_quote(&format!("error: disk image {} could not be created", disk))),
+            None => format!("[ -f {} ] || {{ echo {} >&2; exit 1; }}", path, shell_quote(&format!("error: disk image {} not found", disk))),
+        };
+        format!("{file}\n{var}=$(losetup --find --show --partscan {path}) || {{ echo {error} >&2; exit 1; }}\n{var}_PART=\"${{{var}}}p\"",
+            file = file,
+            var = var,
+            path = path,
+            error = shell_quote(&format!("error: disk image {} could not be attached to a loop device", disk)),
+        )
+    }
+
+    /// Return the commands that stop the script before it touches anything, if it isn't ran as root
+    /// from an Arch live environment, or if one of the disks it would partition hosts the running
+    /// system. The latter check can be skipped by setting `JIMMY_FORCE=1` or by passing
+    /// `--i-know-what-i-am-doing` to the script. Nor does it go on if one of them holds the live
+    /// medium, unless it's given `--allow-install-medium`
+    fn preflight_checks(&self) -> String
+    {
+        let disks = self.unique_disks_used().iter()
+            .map(|d| disk_device(d))
+            .collect::<Vec<String>>()
+            .join(" ");
+        let mut checks = format!(r#"if [ "$(id -u)" -ne 0 ]; then
+    echo 'error: the script must be ran as root' >&2
+    exit 1
+fi
+if [ "$(uname -m)" != {} ]; then
+    echo "error: the script installs a system for {}, but this one is $(uname -m); generate it with \`arch: $(uname -m)\`" >&2
+    exit 1
+fi
+jimmy_check() {{
+    jimmy_package=$1
+    shift
+    for jimmy_tool in "$@"; do
+        if ! command -v "$jimmy_tool" >/dev/null; then
+            jimmy_missing="$jimmy_missing $jimmy_tool"
+            case " $jimmy_packages " in
+                *" $jimmy_package "*) ;;
+                *) jimmy_packages="$jimmy_packages $jimmy_package" ;;
+            esac
+        fi
+    done
+}}
+{}
+{}
+case " $* " in
+    *" --i-know-what-i-am-doing "*) JIMMY_FORCE=1 ;;
+esac
+case " $* " in
+    *" --allow-install-medium "*) JIMMY_ALLOW_INSTALL_MEDIUM=1 ;;
+esac
+# the disks holding the running system's root filesystem, including those under LVM, LUKS etc.
+for jimmy_root_disk in $(lsblk -nsrpo NAME,TYPE "$(findmnt -nvo SOURCE /)" 2>/dev/null | awk '$2 == "disk" {{ print $1 }}'); do
+    for jimmy_disk in {}; do
+        if [ "$(readlink -f "$jimmy_disk")" = "$jimmy_root_disk" ] && [ "$JIMMY_FORCE" != 1 ]; then
+            echo "error: $jimmy_disk hosts the running system; set JIMMY_FORCE=1 or pass --i-know-what-i-am-doing to continue anyway" >&2
+            exit 1
+        fi
+    done
+done"#,
+            self.arch.name(),
+            self.arch.name(),
+            self.required_tools().iter()
+                .map(|(package, tools)| format!("jimmy_check {} {}", package, tools.join(" ")))
+                .collect::<Vec<String>>()
+                .join("\n"),
+            if self.offline { MISSING_TOOLS_OFFLINE.to_string() } else { missing_tools_install() },
+            disks,
+        );
+        checks += &format!("\n{}", INSTALL_MEDIUM_CHECK.replace("{disks}", &disks));
+        if let Some(skel) = &self.skel {
+            checks += &format!("\nif [ ! -d {0} ]; then\n    printf 'error: the skel directory %s is not on the live system\\n' {0} >&2\n    exit 1\nfi",
+                shell_quote(skel));
+        }
+        for check in map_snd(self.map_partitions(Partition::reuse_check)) {
+            checks += &format!("\n{}", check);
+        }
+        checks + "\n" + &self.iso_check()
+    }
+
+    /// Return the features of the configuration that need a recent enough release of the ISO
+    fn iso_requirements(&self) -> Vec<&'static IsoRequirement>
+    {
+        ISO_REQUIREMENTS.iter().filter(|r| (r.needed)(self)).collect()
+    }
+
+    /// Return the commands that stop the script if the live system was made from a release of the
+    /// ISO older than the ones the features of the configuration need, or than `min_iso_version`,
+    /// listing what needs a newer one. The release is read from archiso's /version, or else from
+    /// os-release; if it's neither, the live system isn't checked, with a warning if
+    /// `min_iso_version` was given
+    fn iso_check(&self) -> String
+    {
+        let mut checks: Vec<String> = self.iso_requirements().iter()
+            .map(|r| format!("        jimmy_iso_check {} {}", r.version, shell_quote(r.feature)))
+            .collect();
+        if let Some(version) = &self.min_iso_version {
+            checks.push(format!("        jimmy_iso_check {} min_iso_version", version));
+        }
+        format!(r#"# the release of the ISO the live system was made from
+jimmy_iso=$(cat /version 2>/dev/null || sed -n 's/^IMAGE_VERSION=//p' /etc/os-release 2>/dev/null)
+jimmy_iso=$(printf '%s' "$jimmy_iso" | tr -d \"\')
+jimmy_iso_check() {{
+    if [ "$(printf '%s' "$jimmy_iso" | tr -d .)" -lt "$(printf '%s' "$1" | tr -d .)" ]; then
+        jimmy_iso_missing="$jimmy_iso_missing
+    $2: $1 or newer"
+    fi
+}}
+case "$jimmy_iso" in
+    [0-9][0-9][0-9][0-9].[0-9][0-9].[0-9][0-9])
+{}
+        if [ -n "$jimmy_iso_missing" ]; then
+            printf 'error: the live system was made from the ISO released on %s, which is too old for:%s\n' "$jimmy_iso" "$jimmy_iso_missing" >&2
+            exit 1
+        fi
+        ;;
+    *){}
+        ;;
+esac"#,
+            checks.join("
+"),
+            if self.min_iso_version.is_some() {
+                "\n        echo \"warning: the release of the live ISO is unknown, so it isn't checked against min_iso_version\" >&2"
+            } else {
+                ""
+            },
+        )
+    }
+
+    /// Return the programs the script needs, grouped by the package that provides them; only the
+    /// programs needed for this configuration are included (e.g. `mkfs.fat` only if there's a
+    /// `fat32` partition)
+    fn required_tools(&self) -> Vec<(&'static str, Vec<&'static str>)>
+    {
+        let mut tools = vec![
+            match self.bootstrap.as_str() {
+                "tarball" => ("libarchive", "bsdtar"),
+                _ => ("arch-install-scripts", "pacstrap"),
+            },
+            match self.chroot_backend.as_str() {
+                "nspawn" => ("systemd", "systemd-nspawn"),
+                _ => ("arch-install-scripts", "arch-chroot"),
+            },
+            ("arch-install-scripts", "genfstab"),
+            ("systemd", "timedatectl"),
+            ("util-linux", "fdisk"),
+            ("util-linux", "lsblk"),
+            ("util-linux", "findmnt"),
+            ("util-linux", "mountpoint"),
+        ];
+        for fs in self.partitions.iter().filter(|p| !p.reuse).filter_map(Partition::filesystem) {
+            // the name of the program, without its arguments
+            tools.push((fs.mkfs_package, fs.mkfs.split(' ').next().unwrap()));
+        }
+        if self.partitions.iter().any(|p| p.encryption.is_some()) {
+            tools.push(("cryptsetup", "cryptsetup"));
+        }
+        if self.keymap.is_some() {
+            tools.push(("kbd", "loadkeys"));
+        }
+        if let Some(tarball) = &self.tarball {
+            if tarball.is_url() {
+                tools.push(("curl", "curl"));
+            }
+            tools.push(match tarball.sha256 {
+                Some(_) => ("coreutils", "sha256sum"),
+                None => ("pacman", "pacman-key"),
+            });
+        }
+        if self.reserve_end.keys().any(|d| !self.disks.contains_key(d)) {
+            tools.push(("util-linux", "blockdev"));
+        }
+        if self.partitions.iter().any(|p| p.format == "swap" && !p.activate_swap)
+            || self.partitions.iter().any(|p| p.encryption.is_some() && p.mount != "/")
+            || self.partitions.iter().any(|p| p.reuse)
+        {
+            tools.push(("util-linux", "blkid"));
+        }
+        // the partitions replaced on a disk whose partition table is kept
+        if self.unique_disks_used().iter()
+            .map(|d| self.partitions_on_disk(d))
+            .any(|ps| ps.iter().any(|p| p.reuse) && ps.iter().any(|p| !p.reuse))
+        {
+            tools.push(("util-linux", "sfdisk"));
+        }
+
+        let mut grouped: Vec<(&str, Vec<&str>)> = Vec::new();
+        for (package, tool) in tools {
+            match grouped.iter_mut().find(|(p, _)| *p == package) {
+                Some((_, ts)) if ts.contains(&tool) => (),
+                Some((_, ts)) => ts.push(tool),
+                None => grouped.push((package, vec![tool])),
+            }
+        }
+        grouped
+    }
+
+    /// Create the script that is ran from inside the arch-chroot session to configure the system,
+    /// written the way `ctx` asks. It only touches the system it's ran in, so it can also be ran on
+    /// its own, in any root filesystem of Arch Linux
+    pub fn render_chroot_script(&self, ctx: &RenderContext) -> String
+    {
+        let mut sections = Vec::new();
+        // `genfstab` has already run by now, so these don't get overwritten; they're all appended
+        // at once, so they're already there if the first one is
+        let fstab_extra: Vec<String> = self.fstab_extra().iter().map(FstabEntry::fstab_line).collect();
+        if !fstab_extra.is_empty() {
+            sections.push(ChrootSection::new(
+                "fstab extra",
+                append_once("/etc/fstab", &fstab_extra.join("\n")),
+            ));
+        }
+        sections.extend([
+            ChrootSection::new(
+                "timezone",
+                format!(
+                    "ln -sf {} /etc/localtime{}",
+                    shell_quote(&format!("/usr/share/zoneinfo/{}", self.timezone)),
+                    // containers can't reach the hardware clock, so it's set from outside
+                    match self.chroot_backend.as_str() {
+                        "nspawn" => String::new(),
+                        _ => format!("\n{}", self.hwclock_cmd(None)),
+                    },
+                ),
+            ),
+            ChrootSection::new(
+                "locales",
+                format!("{}\n{}",
+                    self.locales_cmd(ctx).join("\n"),
+                    "locale-gen"
+                ),
+            ),
+            ChrootSection::new(
+                "hostname",
+                format!("echo '{}' >/etc/hostname\n{}",
+                    &self.hostname,
+                    self.local_hostname_cmd(),
+                ),
+            ),
+        ]);
+        let greetings = self.greeting_cmds();
+        if !greetings.is_empty() {
+            sections.push(ChrootSection::new("greetings", greetings.join("\n")));
+        }
+        sections.extend([
+            ChrootSection::new(
+                "network",
+                self.configure_networkmanager().join("\n"),
+            ),
+        ]);
+        if let Some(keymap) = &self.keymap {
+            let mut section = ChrootSection::new("console keymap", format!("echo 'KEYMAP={}' >/etc/vconsole.conf", keymap));
+            // the passphrases of the encrypted partitions are typed in the initramfs
+            if self.partitions.iter().any(|p| p.encryption.is_some()) {
+                section = section.deferring(Deferred::RebuildInitramfs);
+            }
+            sections.push(section);
+        }
+        if let Some(note) = self.keymap_note() {
+            sections.push(ChrootSection::new("password keymap", note));
+        }
+        sections.extend([
+            ChrootSection::new(
+                match self.root_password_policy.as_str() {
+                    "prompt-with-fallback" => "root password fallback",
+                    "locked" => "root password locked",
+                    "hash" => "root password hash",
+                    _ => "root password",
+                },
+                self.root_password_cmds(),
+            ),
+            ChrootSection::new(
+                "sudo",
+                "grep -qxF 'wheel ALL=(ALL) ALL' /etc/sudoers || echo 'wheel ALL=(ALL) ALL' | EDITOR='tee -a' visudo".to_string(),
+            ),
+            ChrootSection::new(
+                "users",
+                self.users.iter()
+                    .filter(|u| !u.home_encryption)
+                    .map(|u| u.to_commands().join("\n"))
+                    .collect::<Vec<String>>()
+                    .join("\n\n"),
+            ),
+        ]);
+        if self.users.iter().any(|u| u.home_encryption) {
+            sections.push(ChrootSection::new(
+                "encrypted homes",
+                self.enable_cmds("encrypted homes").join("\n"),
+            ));
+        }
+        // after the users, so that they can own some of them
+        if !self.directories.is_empty() {
+            sections.push(ChrootSection::new(
+                "directories",
+                self.directories.iter().map(Directory::install_cmd).collect::<Vec<String>>().join("\n"),
+            ));
+        }
+        if let Some(editor) = &self.default_editor {
+            sections.push(ChrootSection::new(
+                "editor",
+                append_once("/etc/environment", &format!("EDITOR={}", editor)),
+            ));
+        }
+        // the packages are pinned from the snapshot's mirrorlist, if there's one
+        if let Some(date) = &self.snapshot_date {
+            sections.push(ChrootSection::new(
+                "snapshot",
+                heredoc_cmd("/etc/pacman.d/mirrorlist", &snapshot_mirrorlist(date), false),
+            ));
+        }
+        if !self.package_pins.is_empty() {
+            sections.push(ChrootSection::new("package pins", self.package_pin_cmds().join("\n")));
+        }
+        let power = self.power_cmds();
+        if !power.is_empty() {
+            sections.push(ChrootSection::new("power", power.join("\n")));
+        }
+        sections.push(ChrootSection::new("default target", format!("systemctl set-default {}.target", self.default_target())));
+        let maintenance = self.maintenance_cmds();
+        if !maintenance.is_empty() {
+            sections.push(ChrootSection::new("maintenance", maintenance.join("\n")));
+        }
+        let hardening = self.hardening_cmds();
+        if !hardening.is_empty() {
+            sections.push(ChrootSection::new("hardening", hardening.join("\n")));
+        }
+        let initramfs = self.initramfs_cmds();
+        if !initramfs.is_empty() {
+            sections.push(ChrootSection::new("initramfs", initramfs.join("\n"))
+                .deferring(Deferred::RebuildInitramfs));
+        }
+        let first_boot = self.first_boot_scripts();
+        if !first_boot.is_empty() {
+            let cmds = [first_boot_cmds(&first_boot), self.enable_cmds("first boot")].concat();
+            sections.push(ChrootSection::new("first boot", cmds.join("\n")));
+        }
+        // the entries of the secondary EFI system partitions are created before the one of the
+        // bootloader, which is put first in the boot order
+        let secondary_esps = self.secondary_esp_cmds(ctx);
+        if !secondary_esps.is_empty() {
+            sections.push(ChrootSection::new("secondary esps", secondary_esps.join("\n")));
+        }
+        let mut bootloader = ChrootSection::new("bootloader", self.install_bootloader().join("\n"));
+        bootloader = match self.bootloader {
+            Bootloader::Grub => bootloader.deferring(Deferred::RegenerateGrubConfig),
+            Bootloader::Efistub => bootloader.deferring(Deferred::UpdateEfiEntries),
+            Bootloader::SystemdBoot => bootloader,
+        };
+        if !secondary_esps.is_empty() {
+            bootloader = bootloader.deferring(Deferred::SyncEsps);
+        }
+        sections.push(bootloader);
+
+        let mut script = vec![
+            self.script_header("arch-chroot script", ctx),
+            // the installation script only goes on if this one succeeded, so it stops at the first
+            // command that fails
+            "set -e".to_string(),
+            CONFIG_HASH_CHECK
+                .replace("{hash}", &self.config_hash)
+                .replace("{marker}", CONFIG_HASH_MARKER),
+        ];
+        script.extend(schedule_deferred(&sections).into_iter().map(|(section, actions)| {
+            let mut cmds = Vec::new();
+            // a part that's only there to register its actions has nothing to say by itself
+            if !section.cmds.is_empty() || actions.is_empty() {
+                cmds.push(echo_status(&self.chroot_status(section.id), &section.cmds, ctx.color));
+            }
+            cmds.extend(actions.iter().map(|a| echo_status(&self.chroot_status(a.id()), &self.deferred_cmds(*a, ctx), ctx.color)));
+            cmds.join("\n\n")
+        }));
+        // only reached if every command before it succeeded
+        script.push(status_line(&self.chroot_status("finished"), ctx.color));
+        script.join("\n\n") + "\n"
+    }
+
+    /// Return the commands that carry out an action that's deferred until every part of the
+    /// arch-chroot script that needs it has made its changes
+    fn deferred_cmds(&self, action: Deferred, ctx: &RenderContext) -> String
+    {
+        match action {
+            Deferred::RebuildInitramfs => match self.initramfs_generator.as_str() {
+                // the hook takes the kernels the way pacman gives them: without the leading slash
+                "dracut" => format!("ls /usr/lib/modules/*/vmlinuz | sed 's|^/||' | {}", DRACUT_INSTALL_PATH),
+                _ => "mkinitcpio -P".to_string(),
+            },
+            Deferred::RegenerateGrubConfig => "grub-mkconfig -o /boot/grub/grub.cfg".to_string(),
+            Deferred::UpdateEfiEntries => self.efistub_entry_cmd(ctx),
+            Deferred::SyncEsps => SYNC_ESPS_PATH.to_string(),
+        }
+    }
+
+    /// Return the commands that list the secondary EFI system partitions for the script that
+    /// keeps them in sync, install it along with its hook if there's one, and create their boot
+    /// entries. They start the same program as the primary one, from their own partition
+    fn secondary_esp_cmds(&self, ctx: &RenderContext) -> Vec<String>
+    {
+        let secondaries = secondary_esps(&self.partitions);
+        if secondaries.is_empty() {
+            return Vec::new();
+        }
+        let esp = self.esp();
+        let mut cmds = vec!["mkdir -p /etc/jimmy /usr/local/lib/jimmy".to_string()];
+        cmds.extend(secondaries.iter().enumerate().map(|(i, secondary)| format!(
+            "echo \"/dev/disk/by-partuuid/$(blkid -s PARTUUID -o value {})\" {}{}",
+            self.partition_file(secondary),
+            if i == 0 { ">" } else { ">>" },
+            SECONDARY_ESPS_LIST,
+        )));
+        cmds.extend([
+            heredoc_cmd(
+                SYNC_ESPS_PATH,
+                &SYNC_ESPS_SCRIPT.replace("{esp}", &esp.mount).replace("{list}", SECONDARY_ESPS_LIST),
+                false,
+            ),
+            format!("chmod +x {}", SYNC_ESPS_PATH),
+        ]);
+        if self.esp_sync_hook {
+            cmds.extend([
+                "mkdir -p /etc/pacman.d/hooks".to_string(),
+                heredoc_cmd("/etc/pacman.d/hooks/zz-jimmy-sync-esps.hook", &SYNC_ESPS_HOOK.replace("{}", SYNC_ESPS_PATH), false),
+            ]);
+        }
+        for (i, secondary) in secondaries.iter().enumerate() {
+            let label = secondary_esp_label(&self.boot_entry_label, i);
+            if !self.keep_existing_entries {
+                cmds.push(EFI_ENTRY_CLEANUP.replace("{}", &shell_quote(&label)));
+            }
+            cmds.push(self.efibootmgr_cmd(secondary, &label).render(ctx.width, 0));
+        }
+        cmds
+    }
+
+    /// Return a list of commands that get the specified bootloader up and running
+    fn install_bootloader(&self) -> Vec<String>
+    {
+        let lts = match &self.kernel {
+            Kernel::Lts => "-lts",
+            _ => "",
+        };
+        let esp = self.esp();
+
+        match self.bootloader {
+            Bootloader::Grub => {
+                let mut cmds = vec![
+                    format!("grub-install --target={} --efi-directory={} --bootloader-id=GRUB --recheck{}", self.arch.grub_target(), esp.mount,
+                        // a disk image boots on another machine than the one installing it, whose
+                        // firmware finds GRUB at the fallback path
+                        if is_image_disk(&esp.disk) { " --removable --no-nvram" } else { "" }),
+                ];
+                let params = self.grub_cmdline_linux();
+                if !params.is_empty() {
+                    // the parameters are put in front of those already there, unless they're there
+                    cmds.push(format!(
+                        "grep -qF 'GRUB_CMDLINE_LINUX=\"{0} ' /etc/default/grub || sed --in-place 's|^GRUB_CMDLINE_LINUX=\"|&{0} |' /etc/default/grub",
+                        params.join(" "),
+                    ));
+                }
+                if let Some(default) = &self.kernel_params.grub_default {
+                    cmds.push(format!(
+                        "sed --in-place 's|^GRUB_CMDLINE_LINUX_DEFAULT=.*|GRUB_CMDLINE_LINUX_DEFAULT=\"{}\"|' /etc/default/grub",
+                        default.join(" "),
+                    ));
+                }
+                cmds
+            },
+            // the boot entry is created once everything it points to is in place
+            Bootloader::Efistub => Vec::new(),
+            Bootloader::SystemdBoot => {
+                // the kernels are on the XBOOTLDR partition if there's one, and on the EFI system
+                // partition otherwise
+                let boot = find_xbootldr(self.bootloader, &self.partitions).unwrap_or(esp);
+
+                let paths = if boot.mount != esp.mount {
+                    format!("--esp-path={} --boot-path={}", esp.mount, boot.mount)
+                } else {
+                    format!("--esp-path={}", esp.mount)
+                };
+
+                vec![
+                    format!("bootctl install {}", paths),
+                    match self.systemd_boot_update.as_str() {
+                        "hook" => format!(
+                            "mkdir -p /etc/pacman.d/hooks\n{}",
+                            heredoc_cmd("/etc/pacman.d/hooks/95-systemd-boot.hook", &SYSTEMD_BOOT_HOOK.replace("{}", &paths), false),
+                        ),
+                        _ => self.enable_cmds("bootloader").join("\n"),
+                    },
+                    heredoc_cmd(
+                        &format!("{}/loader/loader.conf", esp.mount),
+                        "default arch.conf\n",
+                        false,
+                    ),
+                    format!("mkdir -p {}/loader/entries", boot.mount),
+                    heredoc_cmd(
+                        &format!("{}/loader/entries/arch.conf", boot.mount),
+                        &format!(
+                            "title Arch Linux{}\nlinux /{}\ninitrd /{}\noptions {} rw\n",
+                            if lts.is_empty() { "" } else { " LTS" },
+                            self.kernel.image(self.arch),
+                            self.kernel.initramfs(),
+                            self.kernel_cmdline(),
+                        ),
+                        false,
+                    ),
+                ]
+            },
+        }
+    }
+
+    /// Return the command that creates a boot entry with the given label on an EFI system
+    /// partition, starting the program the bootloader is started by: its own EFI executable, or
+    /// the kernel itself with efistub
+    fn efibootmgr_cmd(&self, esp: &Partition, label: &str) -> ShellCmd
+    {
+        let part_re = Regex::new(r"\d+$").unwrap();
+        let cmd = ShellCmd::new("efibootmgr")
+            .opt("--disk", &stable_disk_path(&esp.disk).unwrap_or_else(|| esp.disk.clone()))
+            .opt("--part", part_re.find(&self.partition_file(esp)).map(|s| s.as_str()).unwrap_or(""))
+            .arg("--create")
+            .opt("--label", label);
+        let cmd = match self.bootloader {
+            Bootloader::Grub => cmd.opt("--loader", &format!("\\EFI\\GRUB\\grub{}.efi", self.arch.efi_suffix())),
+            Bootloader::SystemdBoot => cmd.opt("--loader", &format!("\\EFI\\systemd\\systemd-boot{}.efi", self.arch.efi_suffix())),
+            Bootloader::Efistub => cmd
+                // e.g. /vmlinuz-linux-lts
+                .opt("--loader", &format!("/{}", self.kernel.image(self.arch)))
+                // e.g. \initramfs-linux-lts.img
+                .opt("--unicode", &format!("{} rw initrd=\\{}", self.kernel_cmdline(), self.kernel.initramfs())),
+        };
+        cmd.arg("--verbose")
+    }
+
+    /// Return the commands that create the boot entry that starts the kernel with efistub, after
+    /// deleting the ones with the same label unless `keep_existing_entries` is set
+    fn efistub_entry_cmd(&self, ctx: &RenderContext) -> String
+    {
+        let create = self.efibootmgr_cmd(self.esp(), &self.boot_entry_label).render(ctx.width, 0);
+        if self.keep_existing_entries {
+            create
+        } else {
+            format!("{}\n{}", EFI_ENTRY_CLEANUP.replace("{}", &shell_quote(&self.boot_entry_label)), create)
+        }
+    }
+
+    /// Return the changes the `hardening` toggles that are turned on make to the installed system
+    fn hardening_tweaks(&self) -> Vec<&'static Tweak>
+    {
+        self.hardening.enabled().into_iter()
+            .flat_map(|name| HARDENING_TWEAKS.iter().find(|(n, _)| *n == name).unwrap().1)
+            .collect()
+    }
+
+    /// Return the parameters of the kernel command line added by the `hardening` toggles
+    fn hardening_kernel_params(&self) -> Vec<&'static str>
+    {
+        self.hardening_tweaks().into_iter()
+            .filter_map(|t| match t {
+                Tweak::KernelParam(param) => Some(*param),
+                _ => None,
+            })
+            .collect()
+    }
+
+    /// Return the parameters of the kernel command line that come after those of the root
+    /// filesystem: those of the `hardening` toggles, then `kernel_params`, then those of the
+    /// chosen bootloader, merged with `merge_kernel_params`
+    fn extra_kernel_params(&self) -> Vec<String>
+    {
+        let hardening: Vec<String> = self.hardening_kernel_params().into_iter().map(String::from).collect();
+        merge_kernel_params(&[&hardening, &self.kernel_params.shared, &self.kernel_params.bootloader])
+    }
+
+    /// Return the whole kernel command line the bootloader gives the kernel, but for `rw` and the
+    /// initramfs
+    fn kernel_cmdline(&self) -> String
+    {
+        std::iter::once(self.kernel_root_params())
+            .chain(self.extra_kernel_params())
+            .collect::<Vec<String>>()
+            .join(" ")
+    }
+
+    /// Return the boot entries the bootloader starts the installed system with, along with the
+    /// whole kernel command line of each
+    fn boot_entries(&self) -> Vec<ReportBootEntry>
+    {
+        match self.bootloader {
+            Bootloader::Grub => {
+                // grub-mkconfig puts the root filesystem first, then GRUB_CMDLINE_LINUX, then
+                // GRUB_CMDLINE_LINUX_DEFAULT
+                let default = self.kernel_params.grub_default.clone()
+                    .unwrap_or_else(|| GRUB_CMDLINE_LINUX_DEFAULT.iter().map(|p| p.to_string()).collect());
+                let cmdline = ["root=UUID=<uuid-of-the-root-filesystem>".to_string(), "rw".to_string()].into_iter()
+                    .chain(self.grub_cmdline_linux())
+                    .chain(default)
+                    .collect::<Vec<String>>()
+                    .join(" ");
+                vec![ReportBootEntry { entry: "Arch Linux".to_string(), cmdline }]
+            },
+            Bootloader::SystemdBoot => vec![ReportBootEntry {
+                entry: match self.kernel {
+                    Kernel::Lts => "Arch Linux LTS".to_string(),
+                    _ => "Arch Linux".to_string(),
+                },
+                cmdline: format!("{} rw", self.kernel_cmdline()),
+            }],
+            Bootloader::Efistub => {
+                let cmdline = format!("{} rw initrd=\\{}", self.kernel_cmdline(), self.kernel.initramfs());
+                let secondaries = secondary_esps(&self.partitions);
+                std::iter::once(self.boot_entry_label.clone())
+                    .chain((0..secondaries.len()).map(|i| secondary_esp_label(&self.boot_entry_label, i)))
+                    .map(|entry| ReportBootEntry { entry, cmdline: cmdline.clone() })
+                    .collect()
+            },
+        }
+    }
+
+    /// Return the parameters jimmy puts at the start of `GRUB_CMDLINE_LINUX`: those of the root
+    /// filesystem that grub-mkconfig doesn't find by itself, then `extra_kernel_params`
+    fn grub_cmdline_linux(&self) -> Vec<String>
+    {
+        // grub-mkconfig finds the root filesystem by itself, but not the encrypted partition
+        // beneath it
+        self.kernel_root_params().split(' ')
+            .filter(|p| p.starts_with("cryptdevice="))
+            .map(String::from)
+            .chain(self.extra_kernel_params())
+            .collect()
+    }
+
+    /// Return the entries added to the filesystem table after genfstab has written it: those of
+    /// `fstab_extra`, then those of the `hardening` toggles
+    fn fstab_extra(&self) -> Vec<FstabEntry>
+    {
+        let mut entries = self.fstab_extra.clone();
+        entries.extend(self.hardening_tweaks().into_iter().filter_map(|t| match t {
+            Tweak::Fstab(fs, dir, fstype, options) => Some(FstabEntry {
+                fs: fs.to_string(),
+                dir: dir.to_string(),
+                fstype: fstype.to_string(),
+                options: options.to_string(),
+                dump: 0,
+                pass: 0,
+            }),
+            _ => None,
+        }));
+        entries
+    }
+
+    /// Return the commands that write the kernel settings and the drop-in files of the
+    /// `hardening` toggles
+    fn hardening_cmds(&self) -> Vec<String>
+    {
+        let tweaks = self.hardening_tweaks();
+        let mut cmds = Vec::new();
+        let sysctl: Vec<String> = tweaks.iter()
+            .filter_map(|t| match t {
+                Tweak::Sysctl(key, value) => Some(format!("{} = {}\n", key, value)),
+                _ => None,
+            })
+            .collect();
+        if !sysctl.is_empty() {
+            cmds.push(heredoc_cmd(HARDENING_SYSCTL_PATH, &sysctl.concat(), false));
+        }
+        for tweak in tweaks {
+            if let Tweak::DropIn(path, contents) = tweak {
+                cmds.push(format!("mkdir -p {}", path.rsplit_once('/').unwrap().0));
+                cmds.push(heredoc_cmd(path, contents, false));
+            }
+        }
+        cmds
+    }
+
+    /// Return the kernel parameters that tell the initramfs where the root filesystem is and, if
+    /// it's encrypted, which partition to unlock
+    fn kernel_root_params(&self) -> String
+    {
+        // validation made sure that it exists
+        let root = self.root();
+        let root_file = self.partition_file(root);
+        match root.mapper_name() {
+            None => format!("root={}", root_file),
+            Some(name) => match self.hook_flavor() {
+                HookFlavor::Busybox => format!("cryptdevice={}:{} root=/dev/mapper/{}", root_file, name, name),
+                // the initramfs started by systemd finds the partition in /etc/crypttab.initramfs
+                HookFlavor::Systemd => format!("root=/dev/mapper/{}", name),
+            },
+        }
+    }
+
+    /// Return the kind of initramfs the installed system uses; the ones made by dracut are always
+    /// started by systemd
+    fn hook_flavor(&self) -> HookFlavor
+    {
+        if self.initramfs_generator == "dracut" {
+            return HookFlavor::Systemd;
+        }
+        self.mkinitcpio_hooks.as_deref().map(hook_flavor).unwrap_or(HookFlavor::Busybox)
+    }
+
+    /// Return what the initramfs has to be able to do for this configuration
+    fn initramfs_needs(&self) -> Vec<InitramfsNeed>
+    {
+        let mut needs = Vec::new();
+        let root = self.root();
+        if let Some(encryption) = &root.encryption {
+            needs.push(InitramfsNeed::Encrypt);
+            if encryption.tpm2 {
+                needs.push(InitramfsNeed::Tpm2);
+            }
+        }
+        needs
+    }
+
+    /// Return the command that lists the encrypted root partition in the given crypttab file, for
+    /// an initramfs started by systemd to unlock it
+    fn root_crypttab_cmd(&self, crypttab: &str) -> Option<String>
+    {
+        let root = self.root();
+        let (name, encryption) = (root.mapper_name()?, root.encryption.as_ref()?);
+        Some(format!(
+            "echo \"{} UUID=$(blkid -s UUID -o value {}) none {}\" >>{}",
+            name,
+            self.partition_file(root),
+            if encryption.tpm2 { "tpm2-device=auto" } else { "luks" },
+            crypttab,
+        ))
+    }
+
+    /// Return the commands that set up the initramfs: the hooks or modules it uses and, for an
+    /// initramfs started by systemd, the encrypted root partition it unlocks
+    fn initramfs_cmds(&self) -> Vec<String>
+    {
+        if self.initramfs_generator == "dracut" {
+            return self.dracut_cmds();
+        }
+        let hooks = match &self.mkinitcpio_hooks {
+            Some(hooks) => hooks,
+            None => return Vec::new(),
+        };
+        let mut cmds = Vec::new();
+        if self.hook_flavor() == HookFlavor::Systemd {
+            cmds.extend(self.root_crypttab_cmd("/etc/crypttab.initramfs"));
+        }
+        cmds.push(format!("sed --in-place 's/^HOOKS=.*/HOOKS=({})/' /etc/mkinitcpio.conf", hooks.join(" ")));
+        cmds
+    }
+
+    /// Return the commands that set up dracut: the modules the initramfs needs, and the pacman
+    /// hooks that install the kernels and create their images, since dracut doesn't come with any.
+    /// A hostonly initramfs takes the encrypted root partition from /etc/crypttab
+    fn dracut_cmds(&self) -> Vec<String>
+    {
+        let mut cmds = Vec::new();
+        cmds.extend(self.root_crypttab_cmd("/etc/crypttab"));
+        let modules = self.initramfs_needs().iter().map(InitramfsNeed::dracut_module).collect::<Vec<&str>>();
+        if !modules.is_empty() {
+            cmds.push(heredoc_cmd(
+                "/etc/dracut.conf.d/jimmy.conf",
+                &format!("add_dracutmodules+=\" {} \"\n", modules.join(" ")),
+                false,
+            ));
+        }
+        cmds.extend([
+            "mkdir -p /etc/pacman.d/hooks /usr/local/lib/jimmy".to_string(),
+            heredoc_cmd(DRACUT_INSTALL_PATH, DRACUT_INSTALL_SCRIPT, false),
+            heredoc_cmd(DRACUT_REMOVE_PATH, DRACUT_REMOVE_SCRIPT, false),
+            format!("chmod +x {} {}", DRACUT_INSTALL_PATH, DRACUT_REMOVE_PATH),
+            heredoc_cmd("/etc/pacman.d/hooks/90-jimmy-dracut-install.hook", &DRACUT_INSTALL_HOOK.replace("{}", DRACUT_INSTALL_PATH), false),
+            heredoc_cmd("/etc/pacman.d/hooks/60-jimmy-dracut-remove.hook", &DRACUT_REMOVE_HOOK.replace("{}", DRACUT_REMOVE_PATH), false),
+        ]);
+        cmds
+    }
+
+    /// Return the keymap of the console of the installed system
+    fn console_keymap(&self) -> &str
+    {
+        self.keymap.as_deref().unwrap_or("us")
+    }
 
-impl InstallOptions
-{
-    /// Create the script that applies the settings and installs the system
-    pub fn generate_shellscript(&self) -> String
+    /// Return the commands that tell which keymap the passwords are about to be typed with, if
+    /// any are asked for
+    fn keymap_note(&self) -> Option<String>
     {
-        vec![
-            format!("{}\n{}",
-                "#!/bin/sh",
-                "# arch-chroot script automatically generated by jimmy-rs",
-            ),
-            echo_status(
-                "<-> synchronizing time with the internet...",
-                "timedatectl set-ntp true"
-            ),
-            echo_status(
-                "<-> creating partitions using fdisk...",
-                &self.fdisk_cmds().join("\n"),
-            ),
-            echo_status(
-                "<-> formatting partitions...",
-                &map_snd(self.map_partitions(Partition::mkfs_cmd)).join("\n"),
-            ),
-            echo_status(
-                "<-> mounting partitions...",
-                // Always mount root partition first
-                {
-                    let mut ps = self.map_partitions(Partition::mount_cmd);
-                    let root_part_ind = ps.iter().position(|(p,_)| p.mount == "/").unwrap_or(0);
-                    ps.swap(0, root_part_ind);
+        let prompts = matches!(self.root_password_policy.as_str(), "prompt" | "prompt-with-fallback") || !self.users.is_empty();
+        prompts.then(|| KEYMAP_NOTE.replace("{keymap}", self.console_keymap()).replace("{path}", LIVE_KEYMAP_PATH))
+    }
 
-                    &map_snd(ps).join("\n")
-                },
-            ),
-            format!("pacstrap /mnt {}", &self.packages().join(" ")),
-            echo_status(
-                "<-> generating the filesystem table...",
-                "genfstab -U /mnt >> /mnt/etc/fstab"
-            ),
-            // The system configuration part is a bit complicated, since we first need to create a
-            // different script, put it in /mnt, run it with arch-chroot, and then delete it after
-            // we're done.
-            // Check `https://bbs.archlinux.org/viewtopic.php?id=204252`
-            echo_status(
-                "<-> creating the arch-chroot script...",
-                &format!("{}\n{}{}\n{}",
-                    "cat <<END_OF_SECOND_SCRIPT > /mnt/jimmy_part2.sh",
-                    self.chroot_script(),
-                    "END_OF_SECOND_SCRIPT",
-                    "chmod +x /mnt/jimmy_part2.sh",
-                ),
-            ),
-            echo_status(
-                "<-> running arch-chroot script...",
-                "arch-chroot /mnt ./jimmy_part2.sh",
-            ),
-            echo_status(
-                "<-> cleanup: removing arch-chroot script...",
-                "rm -f /mnt/jimmy_part2.sh",
-            ),
-            echo_status(
-                "<-> cleanup: unmounting all filesystems on /mnt...",
-                "umount -R /mnt",
-            ),
-            "echo -e '\\n<-> done; you may reboot now".to_string(),
-        ].join("\n\n") + "\n"
+    /// Return the commands that set the password of root, as `root_password_policy` says. With
+    /// `prompt-with-fallback`, root is locked after `root_password_attempts` failed attempts, and
+    /// the installation goes on with a warning
+    fn root_password_cmds(&self) -> String
+    {
+        match self.root_password_policy.as_str() {
+            "prompt-with-fallback" => retry_at_most("passwd", self.root_password_attempts, &format!(
+                "passwd -l root\nmkdir -p {}\necho {} >>{}",
+                WARNINGS_PATH.rsplit_once('/').unwrap().0,
+                shell_quote(&format!("the password of root was not set after {} attempts, so root is locked; set it with passwd from the live system, in arch-chroot",
+                    self.root_password_attempts)),
+                WARNINGS_PATH,
+            )),
+            "locked" => "passwd -l root".to_string(),
+            "hash" => format!("usermod -p {} root", shell_quote(self.root_password_hash.as_deref().unwrap())),
+            _ => until_success("passwd"),
+        }
     }
 
-    /// Create the script that is ran from inside the arch-chroot session to configure the system
-    fn chroot_script(&self) -> String
+    /// Return the commands that install the pinned packages at their versions, and keep pacman
+    /// from upgrading them
+    fn package_pin_cmds(&self) -> Vec<String>
     {
-        vec![
-            format!("{}\n{}",
-                "#!/bin/sh",
-                "# arch-chroot script automatically generated by jimmy-rs",
-            ),
-            echo_status(
-                "<chroot> setting timezone...",
-                &format!(
-                    "ln -sf /usr/share/zoneinfo/{}/{} /etc/localtime\nhwclock --systohc",
-                    self.region,
-                    self.city,
-                ),
-            ),
-            echo_status(
-                "<chroot> configuring locales on target system...",
-                &format!("{}\n{}",
-                    self.locales_cmd().join("\n"),
-                    "locale-gen"
-                ),
-            ),
-            echo_status(
-                "<chroot> setting hostname...",
-                &format!("{}\n{}",
-                    format!("echo '{}' >/etc/hostname", &self.hostname),
-                    self.local_hostname_cmd(),
-                ),
-            ),
-            echo_status(
-                "<chroot> configuring networkmanager...",
-                &InstallOptions::configure_networkmanager().join("\n"),
-            ),
-            echo_status(
-                "<chroot> set password for root user (repeats until success):",
-                "while true; do if passwd; then break; fi; done",
-            ),
-            echo_status(
-                "<chroot> making the wheel group capable of using sudo...",
-                "echo 'wheel ALL=(ALL) ALL' | EDITOR='tee -a' visudo",
-            ),
-            echo_status(
-                "<chroot> Configuring users, if any...",
-                &self.users.clone().into_iter()
-                    .map(|u| u.to_commands().join("\n"))
-                    .collect::<Vec<String>>()
-                    .join("\n\n"),
-            ),
-            echo_status(
-                "<chroot> setting up bootloader...",
-                &self.install_bootloader().join("\n"),
-            ),
-            echo_status(
-                "<chroot> exiting...",
-                "exit",
-            ),
-        ].join("\n\n") + "\n"
+        let mut cmds = vec![PIN_SETUP.to_string()];
+        cmds.extend(self.package_pins.iter().map(|(name, version)| format!("jimmy_pin {} {}", name, version)));
+        cmds.push(format!(
+            "sed -i 's/^#\\?IgnorePkg *=.*$/IgnorePkg = {}/' /etc/pacman.conf",
+            self.package_pins.keys().cloned().collect::<Vec<String>>().join(" "),
+        ));
+        cmds
     }
 
-    /// Return a list of commands that get the specified bootloader up and running, or panic if the
-    /// bootloader isn't valid
-    fn install_bootloader(&self) -> Vec<String>
+    /// Return the systemd target the installed system boots into: the one of `default_target`, or
+    /// else graphical if a desktop or a display manager is installed, since it's the one display
+    /// managers start on, and multi-user otherwise
+    pub fn default_target(&self) -> &str
     {
-        match self.bootloader.as_str() {
-            "grub" =>
-                vec![
-                    "grub-install --target=x86_64-efi --bootloader-id=GRUB --recheck",
-                    "grub-mkconfig -o /boot/grub/grub.cfg",
-                ].into_iter().map(|s| s.to_string()).collect(),
-            "efistub" => {
-                let lts = match &self.kernel {
-                    Kernel::Lts => "-lts",
-                    _ => "",
-                };
-                let partitions_and_disks = self.map_partitions(Partition::get_partition_file);
-                let boot_partition = partitions_and_disks.iter()
-                    .find(|(p, _)| matches!(p.mount.as_str(), "/boot" | "/efi"))
-                    .expect("using efistub, but no boot partition was detected");
-                let part_re = Regex::new(r"\d+$").unwrap();
-                let root_partition = partitions_and_disks.iter()
-                    .find(|(p, _)| p.mount.as_str() == "/")
-                    .expect("using efistub, but no root partition was detected");
+        match &self.default_target {
+            Some(target) => target,
+            None => match self.package_list().iter().any(|p| DESKTOPS.contains(&p.as_str()) || DISPLAY_MANAGERS.contains(&p.as_str())) {
+                true => "graphical",
+                false => "multi-user",
+            },
+        }
+    }
 
-                vec![
-                    format!(
-                        "efibootmgr --disk {} --part {} --create --label \"Arch Linux{}\" --loader /vmlinuz-linux{} --unicode 'root={} rw initrd=\\initramfs-linux{}.img' --verbose",
-                        boot_partition.0.disk,
-                        part_re.find(&boot_partition.1.clone().unwrap()).map(|s| s.as_str()).unwrap_or(""),
-                        match lts { // if using LTS kernel, then put label "Arch Linux LTS"
-                            "-lts" => " LTS",
-                            _ => ""
-                        },
-                        lts, // if using LTS kernel, use /vmlinuz-linux-lts
-                        root_partition.1.clone().unwrap(), // find root partition
-                        lts, // if using LTS kernel, use \initramfs-linux-lts.img
-                    )
-                ]
-                },
-            _ => panic!("invalid bootloader"),
+    /// Return the packages of the power daemon
+    fn power_packages(&self) -> Vec<&'static str>
+    {
+        match self.power.as_str() {
+            "tlp" => vec!["tlp"],
+            "power-profiles-daemon" => vec!["power-profiles-daemon"],
+            _ => vec![],
+        }
+    }
+
+    /// Return the commands that install and enable the power daemon, on laptops only: unless
+    /// `laptop` says whether the machine is one, it's found out from its chassis type
+    fn power_cmds(&self) -> Vec<String>
+    {
+        let mut setup = self.enable_cmds("power");
+        match self.power.as_str() {
+            // TLP switches the radios on and off itself, and systemd-rfkill would undo it
+            "tlp" => setup.push("systemctl mask systemd-rfkill.service systemd-rfkill.socket".to_string()),
+            "power-profiles-daemon" => (),
+            _ => return vec![],
+        }
+        match self.laptop {
+            Some(true) => setup,
+            Some(false) => vec![],
+            None => runtime_install_cmds(&[RuntimeInstall {
+                var: "jimmy_laptop",
+                condition: LAPTOP_CHECK,
+                packages: self.power_packages(),
+                setup,
+            }]),
+        }
+    }
+
+    /// Return the commands that set up the periodic cleanups and updates turned on under
+    /// `maintenance`
+    fn maintenance_cmds(&self) -> Vec<String>
+    {
+        let mut cmds = Vec::new();
+        if self.maintenance.orphan_cleanup {
+            cmds.extend([
+                "mkdir -p /etc/pacman.d/hooks /usr/local/lib/jimmy".to_string(),
+                heredoc_cmd(LIST_ORPHANS_PATH, LIST_ORPHANS_SCRIPT, false),
+                format!("chmod +x {}", LIST_ORPHANS_PATH),
+                heredoc_cmd("/etc/pacman.d/hooks/jimmy-list-orphans.hook", &LIST_ORPHANS_HOOK.replace("{}", LIST_ORPHANS_PATH), false),
+            ]);
+        }
+        if let Some(update) = &self.maintenance.mirrorlist_update {
+            let mut args = vec!["--save /etc/pacman.d/mirrorlist".to_string()];
+            args.extend(reflector_args(&self.maintenance.mirrorlist_countries));
+            cmds.push(heredoc_cmd("/etc/xdg/reflector/reflector.conf", &(args.join("\n") + "\n"), false));
+            // the timer runs weekly unless told otherwise
+            if update != "weekly" {
+                cmds.extend([
+                    "mkdir -p /etc/systemd/system/reflector.timer.d".to_string(),
+                    heredoc_cmd(
+                        "/etc/systemd/system/reflector.timer.d/jimmy.conf",
+                        &format!("[Timer]\nOnCalendar=\nOnCalendar={}\n", update),
+                        false,
+                    ),
+                ]);
+            }
         }
+        cmds.extend(self.enable_cmds("maintenance"));
+        cmds
+    }
+
+    /// Return the path that the installed system should use for one of the partitions
+    fn partition_file(&self, partition: &Partition) -> String
+    {
+        self.map_partitions(|_, device| Some(device.stable.clone()))
+            .into_iter()
+            .find(|(p, _)| std::ptr::eq(*p, partition))
+            .and_then(|(_, file)| file)
+            .unwrap()
     }
 
     /// Return a command that creates /etc/hosts and puts local hostname information into it
     fn local_hostname_cmd(&self) -> String
     {
-        format!(
-            "cat <<END_ETC_HOSTS >/etc/hosts\n{}\nEND_ETC_HOSTS",
-            vec![
+        heredoc_cmd(
+            "/etc/hosts",
+            &[
                 "127.0.0.1\tlocalhost",
                 "::1\tlocalhost",
                 &format!("127.0.1.1\t{}", &self.hostname),
             ].join("\n"),
+            false,
         )
     }
 
+    /// Return a list of commands that write the pre-login message, the message of the day and the
+    /// name of the operating system, for those that are specified
+    fn greeting_cmds(&self) -> Vec<String>
+    {
+        let mut cmds = Vec::new();
+        if let Some(issue) = &self.issue {
+            cmds.push(heredoc_cmd("/etc/issue", issue, false));
+        }
+        if let Some(motd) = &self.motd {
+            cmds.push(heredoc_cmd("/etc/motd", motd, false));
+        }
+        if let Some(name) = &self.pretty_name {
+            // os-release values follow shell quoting rules
+            let mut quoted = String::new();
+            for c in name.chars() {
+                if matches!(c, '\\' | '"' | '$' | '`') {
+                    quoted.push('\\');
+                }
+                quoted.push(c);
+            }
+            // /etc/os-release is a symlink to the file owned by the `filesystem` package, so it's
+            // replaced with a copy instead of editing the original
+            cmds.push(format!(
+                "rm -f /etc/os-release\n{{ grep -v '^PRETTY_NAME=' /usr/lib/os-release; printf '%s\\n' {}; }} >/etc/os-release",
+                shell_quote(&format!("PRETTY_NAME=\"{}\"", quoted)),
+            ));
+        }
+        cmds
+    }
+
     /// Return a list of commands that get NetworkManager up and running. This assumes, of course,
     /// that it's installed
-    fn configure_networkmanager() -> Vec<&'static str>
+    fn configure_networkmanager(&self) -> Vec<String>
     {
-        vec![
-            "systemctl enable --now systemd-resolved",
-            "systemctl enable NetworkManager.service",
-        ]
+        self.enable_cmds("network")
+    }
+
+    /// Return the units the installed system starts by itself, in the order they're enabled in
+    /// within each part of the arch-chroot script; jimmy only enables system units, so those of
+    /// `user_services` aren't among them
+    fn enabled_services(&self) -> Vec<EnabledService>
+    {
+        let service = |unit, section| EnabledService { unit, section, start: false, laptop_only: false };
+        let mut services = Vec::new();
+        if self.bootloader == Bootloader::SystemdBoot && self.systemd_boot_update != "hook" {
+            services.push(service("systemd-boot-update.service", "bootloader"));
+        }
+        services.extend([
+            EnabledService { start: true, ..service("systemd-resolved", "network") },
+            service("NetworkManager.service", "network"),
+        ]);
+        if self.users.iter().any(|u| u.home_encryption) {
+            services.push(service("systemd-homed.service", "encrypted homes"));
+        }
+        let power = match self.power.as_str() {
+            "tlp" => Some("tlp.service"),
+            "power-profiles-daemon" => Some("power-profiles-daemon.service"),
+            _ => None,
+        };
+        if let Some(unit) = power.filter(|_| self.laptop != Some(false)) {
+            services.push(EnabledService { laptop_only: self.laptop.is_none(), ..service(unit, "power") });
+        }
+        if self.maintenance.paccache {
+            services.push(service("paccache.timer", "maintenance"));
+        }
+        if self.maintenance.mirrorlist_update.is_some() {
+            services.push(service("reflector.timer", "maintenance"));
+        }
+        if !self.first_boot_scripts().is_empty() {
+            services.push(service("jimmy-firstboot.service", "first boot"));
+        }
+        services
+    }
+
+    /// Return the commands that enable the units of `enabled_services` that the given part of the
+    /// arch-chroot script enables
+    fn enable_cmds(&self, section: &str) -> Vec<String>
+    {
+        self.enabled_services().into_iter()
+            .filter(|s| s.section == section)
+            // there's no systemd running inside the container to start services with
+            .map(|s| match s.start && self.chroot_backend != "nspawn" {
+                true => format!("systemctl enable --now {}", s.unit),
+                false => format!("systemctl enable {}", s.unit),
+            })
+            .collect()
     }
 
     /// Return a vector containing the sed command that sets (uncomments) all specified locales in
-    /// /etc/locale.gen, and the command that creates /etc/locale.conf and puts `LANG=${first of
-    /// the locales}` into it
-    fn locales_cmd(&self) -> Vec<String>
-    {
-        let mut fst = vec!["sed ".to_string()];
-        for l in self.locales.clone() {
-            fst.push(format!("    --expression 's/^#{}$/{}/' ",
-                                l,
-                                l,
-                                ));
-        }
-        fst.push("    --in-place /etc/locale.gen".to_string());
+    /// /etc/locale.gen, whose lines are a locale followed by its charset (`#en_US.UTF-8 UTF-8`),
+    /// and the command that creates /etc/locale.conf and puts `LANG=${first of the locales}` into
+    /// it
+    fn locales_cmd(&self, ctx: &RenderContext) -> Vec<String>
+    {
+        let sed = self.locales.iter()
+            .fold(ShellCmd::new("sed"), |sed, l| sed.opt("--expression", &format!("s/^#{} /{} /", l.replace('.', "\\."), l)))
+            .arg("--in-place")
+            .arg("/etc/locale.gen");
         vec![
-            fst.join("\\\n"),
+            sed.render(ctx.width, 0),
             format!("echo 'LANG={}' >/etc/locale.conf", self.locales.clone()[0]),
         ]
     }
 
-    /// Return a list of packages that need to be installed with `pacstrap` onto the new system
+    /// Return the packages that need to be installed with `pacstrap` onto the new system, in the
+    /// order `PACKAGE_SOURCES` gives them, each of them once
     fn packages(&self) -> Vec<&str>
     {
-        vec![
-            "base",
-            match self.kernel {
-                Kernel::Latest => "linux",
-                Kernel::Lts => "linux-lts",
-            },
-            "linux-firmware",
-            &self.extra,
-            if &self.bootloader != "efistub" {
-                &self.bootloader
-            } else {
-                ""
-            },
-            "efibootmgr",
-            "networkmanager",
-        ]
+        let mut packages: Vec<&str> = Vec::new();
+        for package in PACKAGE_SOURCES.iter().flat_map(|source| source(self)) {
+            if !packages.contains(&package) {
+                packages.push(package);
+            }
+        }
+        packages
+    }
+
+    /// Return every package that's installed with `pacstrap` onto the new system, sorted. The
+    /// versions given by `package_pins` are installed over them later, from inside arch-chroot
+    pub fn package_list(&self) -> Vec<String>
+    {
+        let mut packages: Vec<String> = self.packages().into_iter().map(String::from).collect();
+        packages.sort_unstable();
+        packages
     }
 
     /// Map a function `apply()` over all partitions, by associating them with their disks so that
-    /// the proper file paths are used to identify them. The result of that function is added to
-    /// the return value only if it's `Some()`
-    fn map_partitions(&self, apply: fn(&Partition, u32) -> Option<String>) -> Vec<(&Partition, Option<String>)>
+    /// the proper file paths are used to identify them; this is the only place those paths are
+    /// worked out. The result of that function is added to the return value only if it's `Some()`
+    fn map_partitions(&self, apply: impl Fn(&Partition, &BlockDevice) -> Option<String>) -> Vec<(&Partition, Option<String>)>
     {
         let disks = self.unique_disks_used();
 
-        disks.iter().map(|disk| {
+        disks.iter().flat_map(|disk| {
             let partitions = self.partitions_on_disk(disk).into_iter();
 
             partitions
                 .enumerate()
                 .map(|(idx, partition)| {
-                    (partition, apply(partition, idx as u32))
+                    (partition, apply(partition, &BlockDevice::of_partition(partition, idx as u32 + 1)))
                 })
                 .collect::<Vec<(&Partition, Option<String>)>>()
         })
-        .flatten()
         .collect::<Vec<(&Partition, Option<String>)>>()
     }
 
-    /// TODO: find a way to make this function use `map_partitions()`
-    /// Return the list of shell commands that create the partitions with `fdisk`
-    fn fdisk_cmds(&self) -> Vec<String>
+    /// Return the commands that format all partitions. With `parallel_format`, the partitions of
+    /// each disk are formatted at the same time as those of other disks, with the output of every
+    /// disk prefixed by its name
+    fn format_cmds(&self) -> String
     {
+        let mut cmds = self.map_partitions(Partition::mkfs_cmd);
+        // a btrfs filesystem with members is created on all of its devices at once
+        let members = self.map_partitions(|p, device| p.member_of.as_ref().map(|_| device.filesystem.clone()));
+        for (partition, cmd) in cmds.iter_mut().filter(|(p, _)| p.raid_profile.is_some()) {
+            if let Some(cmd) = cmd {
+                for (_, file) in members.iter().filter(|(m, _)| m.member_of.as_ref() == Some(&partition.mount)) {
+                    cmd.push(' ');
+                    cmd.push_str(file.as_deref().unwrap_or_default());
+                }
+            }
+        }
         let disks = self.unique_disks_used();
+        if !self.parallel_format || disks.len() < 2 {
+            // mkfs says why it failed by itself
+            return map_snd(cmds).iter().map(|cmd| format!("{} || exit 1", cmd)).collect::<Vec<String>>().join("\n");
+        }
+
+        let mut jobs = vec!["jimmy_format_status=$(mktemp -d)".to_string()];
+        for (i, disk) in disks.iter().enumerate() {
+            let disk_cmds: Vec<&str> = cmds.iter()
+                .filter(|(p, _)| &p.disk == disk)
+                .filter_map(|(_, c)| c.as_deref())
+                .collect();
+            // a disk whose partitions are all reused
+            if disk_cmds.is_empty() {
+                continue;
+            }
+            jobs.push(format!(
+                "{{ {{ {}; }} 2>&1; echo $? >\"$jimmy_format_status/{}\"; }} | sed {} &",
+                disk_cmds.join(" && "),
+                i,
+                shell_quote(&format!("s|^|{}: |", disk)),
+            ));
+        }
+        jobs.push(r#"wait
+jimmy_format_failed=
+for jimmy_format_job in "$jimmy_format_status"/*; do
+    [ "$(cat "$jimmy_format_job")" -eq 0 ] || jimmy_format_failed=1
+done
+rm -rf "$jimmy_format_status"
+if [ -n "$jimmy_format_failed" ]; then
+    echo 'error: formatting failed' >&2
+    exit 1
+fi"#.to_string());
+        jobs.join("\n")
+    }
+
+    /// Return the commands that install the packages with `pacstrap`, or with the pacman of the
+    /// live system into the system extracted from a bootstrap tarball. Unless `retries` is 0, a
+    /// failed attempt is retried after `retry_delay` seconds, with `--needed` so that the packages
+    /// that got installed aren't fetched again; failures caused by package signatures aren't
+    /// retried, since they'd only fail again. The script stops with the exit status of the last
+    /// attempt if none succeeds
+    fn pacstrap_cmds(&self, ctx: &RenderContext) -> String
+    {
+        let packages: Vec<String> = if ctx.reproducible {
+            self.package_list()
+        } else {
+            self.packages().into_iter().map(String::from).collect()
+        };
+        let (program, pacstrap) = match self.bootstrap.as_str() {
+            // what pacstrap does itself: the packages are kept in the cache of the new system, and
+            // the ones the tarball came with are upgraded along with them
+            "tarball" => {
+                let pacman = ShellCmd::new("pacman")
+                    .opt("--root", &self.mount_root)
+                    .opt("--cachedir", &self.target("/var/cache/pacman/pkg"))
+                    .args(["--noconfirm", "-Syu", "--needed"]);
+                let cmds = self.install_cmds("pacman", pacman, packages, ctx);
+                return [
+                    API_FILESYSTEMS_MOUNT.replace("{root}", &self.mount_root),
+                    cmds,
+                    API_FILESYSTEMS_UNMOUNT.replace("{root}", &self.mount_root),
+                ].join("\n");
+            },
+            _ => ("pacstrap", ShellCmd::new("pacstrap").arg(&self.mount_root)),
+        };
+        self.install_cmds(program, pacstrap, packages, ctx)
+    }
+
+    /// Return the commands that install `packages` with `cmd`, which is called `program` in the
+    /// messages, retrying it as `pacstrap_cmds` tells
+    fn install_cmds(&self, program: &str, cmd: ShellCmd, packages: Vec<String>, ctx: &RenderContext) -> String
+    {
+        // pacstrap gives pacman everything after the root directory
+        if self.retries == 0 {
+            return cmd.args(&self.pacstrap_args).args(packages).render(ctx.width, 0);
+        }
+        let pacstrap = match program {
+            "pacstrap" => cmd.raw("$jimmy_needed"),
+            _ => cmd,
+        }.args(&self.pacstrap_args).args(packages);
+
+        format!(r#"jimmy_pacstrap_log=$(mktemp)
+jimmy_attempt=0
+jimmy_needed=
+while true; do
+    {{ {pacstrap} 2>&1; echo $? >"$jimmy_pacstrap_log.status"; }} | tee "$jimmy_pacstrap_log"
+    jimmy_status=$(cat "$jimmy_pacstrap_log.status")
+    [ "$jimmy_status" -eq 0 ] && break
+    if grep -Eq 'signature|PGP' "$jimmy_pacstrap_log"; then
+        echo 'error: {program} failed because of package signatures, so it is not retried; try `pacman -Sy archlinux-keyring` first' >&2
+        break
+    fi
+    jimmy_attempt=$((jimmy_attempt + 1))
+    [ "$jimmy_attempt" -gt {retries} ] && break
+    echo "warning: {program} failed with exit status $jimmy_status; retrying in {delay} seconds ($jimmy_attempt of {retries})..." >&2
+    sleep {delay}
+    jimmy_needed=--needed
+done
+rm -f "$jimmy_pacstrap_log" "$jimmy_pacstrap_log.status"
+if [ "$jimmy_status" -ne 0 ]; then
+    echo 'error: {program} failed' >&2
+    exit "$jimmy_status"
+fi"#,
+            pacstrap = pacstrap.render(ctx.width, "    { ".len()),
+            program = program,
+            retries = self.retries,
+            delay = self.retry_delay,
+        )
+    }
+
+    /// Return the commands that put the base system onto the mount root from the bootstrap `tarball`:
+    /// download it unless it's a local file, check it, and extract it
+    fn tarball_cmds(&self, tarball: &Tarball, ctx: &RenderContext) -> String
+    {
+        let mut cmds = Vec::new();
+        if tarball.is_url() {
+            cmds.push(format!("jimmy_tarball={}", self.target(TARBALL_PATH)));
+            let mut downloads = vec![(tarball.source.clone(), "\"$jimmy_tarball\"")];
+            if tarball.sha256.is_none() {
+                downloads.push((format!("{}.sig", tarball.source), "\"$jimmy_tarball.sig\""));
+            }
+            for (url, output) in downloads {
+                let curl = ShellCmd::new("curl")
+                    .args(["--fail", "--location"])
+                    .opt("--retry", &self.retries.to_string())
+                    .raw(&format!("--output {}", output))
+                    .arg(&url);
+                cmds.push(or_fail(&curl.render(ctx.width, 0), &format!("error: could not download {}", url)));
+            }
+        } else {
+            cmds.push(format!("jimmy_tarball={}", shell_quote(&tarball.source)));
+            cmds.push(or_fail("[ -f \"$jimmy_tarball\" ]", &format!("error: the bootstrap tarball {} doesn't exist", tarball.source)));
+        }
+        cmds.push(match &tarball.sha256 {
+            Some(sum) => or_fail(&format!("printf '%s  %s\\n' {} \"$jimmy_tarball\" | sha256sum --check --status", sum),
+                "error: the bootstrap tarball does not have the sha256 checksum it should"),
+            None => or_fail("pacman-key --verify \"$jimmy_tarball.sig\" \"$jimmy_tarball\"", "error: the signature of the bootstrap tarball could not be verified"),
+        });
+        let mut bsdtar = ShellCmd::new("bsdtar")
+            .args(["--extract", "--preserve-permissions", "--xattrs", "--acls", "--numeric-owner"])
+            .raw("--file \"$jimmy_tarball\"")
+            .opt("--directory", &self.mount_root);
+        if tarball.strip_components() > 0 {
+            bsdtar = bsdtar.opt("--strip-components", &tarball.strip_components().to_string());
+        }
+        cmds.push(or_fail(&bsdtar.render(ctx.width, 0), "error: could not extract the bootstrap tarball"));
+        if tarball.is_url() {
+            cmds.push("rm -f \"$jimmy_tarball\" \"$jimmy_tarball.sig\"".to_string());
+        }
+        cmds.push(format!("cp /etc/pacman.d/mirrorlist {}", self.target("/etc/pacman.d/mirrorlist")));
+        cmds.join("\n")
+    }
 
+    /// Return the list of shell commands that create the partitions with `fdisk`. On a disk with
+    /// space reserved at its end, the partition that takes the rest of it is given the size that
+    /// leaves that space free: worked out now if the size of the disk was declared, or by the
+    /// script from the size of the disk otherwise, since fdisk can't do it
+    fn fdisk_cmds(&self) -> Vec<String>
+    {
         let mut cmds = Vec::new();
-        for disk in disks {
-            let partitions = self.partitions_on_disk(&disk);
-
-            let mut cmd = String::from("echo -e \"g\\n");
-            let mut i = 1;
-            while i <= partitions.len() as u32 {
-                cmd += partitions[i as usize - 1].fdisk_script_string(i).as_str();
-                i += 1;
+        for disk in self.unique_disks_used() {
+            if let Some(declared) = self.disks.get(&disk) {
+                cmds.push(format!("# {}", disk_plan(&disk, Some(declared), &self.partitions_on_disk(&disk))));
+            }
+
+            let has_rest = self.partitions_on_disk(&disk).iter().any(|p| p.size.is_none());
+            let rest = match (self.reserve_end.get(&disk).filter(|_| has_rest), self.disks.get(&disk)) {
+                (None, _) => String::new(),
+                (Some(_), Some(declared)) => format!("+{}", Size { bytes: declared.remaining_mib.unwrap() << 20 }),
+                (Some(reserved), None) => {
+                    let used_mib: u64 = self.partitions_on_disk(&disk).iter()
+                        .filter_map(|p| p.size)
+                        .map(|s| (s.bytes + (1 << 20) - 1) >> 20)
+                        .sum();
+                    cmds.push(RESERVE_END_CMDS
+                        .replace("{device}", &disk_device(&disk))
+                        .replace("{disk}", &disk)
+                        .replace("{overhead}", &GPT_OVERHEAD_MIB.to_string())
+                        .replace("{used}", &used_mib.to_string())
+                        .replace("{reserved}", &((reserved.bytes + (1 << 20) - 1) >> 20).to_string())
+                        .replace("{reserve_end}", &reserved.to_string()));
+                    "+${jimmy_rest_mib}M".to_string()
+                },
+            };
+
+            let on_disk = self.partitions_on_disk(&disk);
+            if on_disk.iter().any(|p| p.reuse) {
+                cmds.extend(kept_table_cmds(&disk, &on_disk));
+                continue;
             }
-            cmd += &format!("\\nw\" | fdisk {} &>/dev/null", disk);
-            cmds.push(cmd);
+            // g: create a new GPT partition table, and w: write it along with the partitions
+            let mut answers = vec!["g".to_string()];
+            for (idx, p) in on_disk.into_iter().enumerate() {
+                let device = BlockDevice::of_partition(p, idx as u32 + 1);
+                answers.extend(p.fdisk_answers(&device, &rest, device.number == 1));
+            }
+            answers.push("w".to_string());
+            cmds.push(feed_lines(&answers, &format!("fdisk {} >/dev/null 2>&1 || exit 1", disk_device(&disk))));
         }
         cmds
     }
 
-    /// Return the list of all unique disks used in the configuration
-    fn unique_disks_used(&self) -> Vec<String>
+    /// Return the partitions that are reused, with the filesystems they hold and where they're
+    /// mounted, or `None` if there are none
+    fn reused_partitions(&self) -> Option<String>
     {
-        let mut disks: Vec<String> = self.partitions.iter()
-            .map(|p| p.disk.clone())
-            .collect();
-        disks.sort();
-        disks.dedup();
+        let reused = map_snd(self.map_partitions(|p, device| match p.reuse {
+            true => Some(format!("{} ({}, {})", device.partition, p.format, p.mount)),
+            false => None,
+        }));
+        Some(reused.join(", ")).filter(|r| !r.is_empty())
+    }
+
+    /// Return the list of all unique disks used in the configuration, in the order their first
+    /// partitions are written in; that's the order they're partitioned and formatted in
+    pub fn unique_disks_used(&self) -> Vec<String>
+    {
+        let mut disks: Vec<String> = Vec::new();
+        for p in &self.partitions {
+            if !disks.contains(&p.disk) {
+                disks.push(p.disk.clone());
+            }
+        }
         disks
     }
 
@@ -316,98 +3820,377 @@ impl InstallOptions
     }
 }
 
+/// Return the commands that replace the new partitions of a disk holding a reused one, whose
+/// partition table is kept: the partitions with their numbers are deleted with `sfdisk`, if
+/// they're there, and created again with `fdisk` in the space they leave, before the reused ones,
+/// which stay where they are
+fn kept_table_cmds(disk: &str, partitions: &[&Partition]) -> Vec<String>
+{
+    let devices: Vec<(&Partition, BlockDevice)> = partitions.iter().enumerate()
+        .map(|(idx, p)| (*p, BlockDevice::of_partition(p, idx as u32 + 1)))
+        .collect();
+    let numbers = |reuse: bool| devices.iter()
+        .filter(|(p, _)| p.reuse == reuse)
+        .map(|(_, device)| device.number.to_string())
+        .collect::<Vec<String>>();
+    let (reused, new) = (numbers(true), numbers(false));
+
+    let kept = match reused.len() {
+        1 => "is reused as it is",
+        _ => "are reused as they are",
+    };
+    let mut cmds = vec![match new.is_empty() {
+        true => format!("# {} keeps its partition table, and {} on it {}", disk, plural("partition", &reused), kept),
+        false => format!("# {} keeps its partition table: {} on it {}, and {} replaced",
+            disk, plural("partition", &reused), kept, plural("partition", &new)),
+    }];
+    if new.is_empty() {
+        return cmds;
+    }
+    let device = disk_device(disk);
+    cmds.push(format!(r#"for jimmy_number in {}; do
+    if sfdisk --part-type {} "$jimmy_number" >/dev/null 2>&1; then
+        sfdisk --delete {} "$jimmy_number" >/dev/null || exit 1
+    fi
+done"#, new.join(" "), device, device));
+    // w: write the partition table, with the partitions replaced
+    let mut answers: Vec<String> = devices.iter()
+        .filter(|(p, _)| !p.reuse)
+        .flat_map(|(p, device)| p.fdisk_answers(device, "", false))
+        .collect();
+    answers.push("w".to_string());
+    cmds.push(feed_lines(&answers, &format!("fdisk {} >/dev/null 2>&1 || exit 1", device)));
+    cmds
+}
+
+/// Return `word` followed by the given numbers, in the plural if there's more than one of them:
+/// "partition 3", "partitions 1 and 2", "partitions 1, 2 and 4"
+fn plural(word: &str, numbers: &[String]) -> String
+{
+    match numbers {
+        [number] => format!("{} {}", word, number),
+        [rest @ .., last] => format!("{}s {} and {}", word, rest.join(", "), last),
+        [] => format!("no {}s", word),
+    }
+}
+
+/// Return what's going to be done with a disk: how its space is going to be used if its size was
+/// declared under `disks`, or that it gets a partition table without any checks otherwise
+fn disk_plan(name: &str, disk: Option<&Disk>, partitions: &[&Partition]) -> String
+{
+    let disk = match disk {
+        Some(disk) => disk,
+        None => return format!("disk {} isn't declared under `disks`, so it gets a GPT partition table without its size being checked", name),
+    };
+    let mut comment = format!("disk {} is {}, of which {} can be partitioned", name, disk.size, format_mib(disk.usable_mib));
+    let rest = partitions.iter().find(|p| p.size.is_none());
+    if let (Some(remaining), Some(p)) = (disk.remaining_mib, rest) {
+        if p.mount.is_empty() {
+            comment += &format!("; the {} partition gets the remaining {}", p.format, format_mib(remaining));
+        } else {
+            comment += &format!("; the partition mounted at {} gets the remaining {}", p.mount, format_mib(remaining));
+        }
+        comment += &format!(" (at least {} expected)", disk.min_remaining);
+    }
+    if let Some(reserved) = disk.reserve_end {
+        comment += &format!(", and {} is left free at its end", reserved);
+    }
+    comment
+}
+
 impl Partition
 {
-    /// Return the string that can be `echo`ed into `fdisk` to create this Partition
-    pub fn fdisk_script_string(&self, number: u32) -> String
+    /// Partition types are given to `fdisk` by name, which it understands since util-linux 2.36
+    const FDISK_TYPE_NAMES: IsoRequirement = IsoRequirement {
+        step: "partitioning",
+        feature: "partition types given to fdisk by name (util-linux 2.36)",
+        version: "2020.09.01",
+        needed: |_| true,
+    };
+
+    /// Return the answers to the prompts of `fdisk` that create this Partition, one per line; only
+    /// the number of the partition matters, since `fdisk` is given the disk. `rest` is the size
+    /// given if the partition takes the rest of the disk, empty for all of it, and `alone` is
+    /// whether it's the only partition on the disk once it's created
+    pub fn fdisk_answers(&self, device: &BlockDevice, rest: &str, alone: bool) -> Vec<String>
     {
-        format!(
-            // n: create new partition
-            // use partition number specified
-            // next line: default first sector
-            // use partition size specified in instance
-            // then: change the type of the partition
-            // use the partition number specified
-            // change it to the type needed for the format
-            r"n\n{}\n\n{}\nt{}\n{}\n",
-            number,
-            if self.size.is_empty() {
-                "".to_string()
-            } else {
-                format!("+{}", &self.size)
-            },
-            // The first partition is going to be selected by default
-            if number == 1 {
-                "".to_string()
-            } else {
-                format!("\\n{}", number)
-            },
-            self.fdisk_partition_type()
-        )
+        let number = device.number.to_string();
+        // n: create new partition
+        // use partition number specified
+        // next line: default first sector
+        // use partition size specified in instance
+        let mut answers = vec![
+            "n".to_string(),
+            number.clone(),
+            "".to_string(),
+            // fdisk takes sizes with the same units as the configuration file
+            self.size.map_or_else(|| rest.to_string(), |size| format!("+{}", size)),
+        ];
+        // then: change the type of the partition
+        answers.push("t".to_string());
+        // use the partition number specified; the only partition is going to be selected by
+        // default
+        if !alone {
+            answers.push(number);
+        }
+        // change it to the type needed for the format
+        answers.push(self.fdisk_partition_type().to_string());
+        answers
     }
 
     /// Return the `mkfs` command that can format this partition, or `None` if the format of the
-    /// partition wasn't recognised.
-    pub fn mkfs_cmd(&self, number: u32) -> Option<String>
-    {
-        let cmd = match self.format.as_str() {
-            "ext2" => "mkfs.ext2",
-            "ext3" => "mkfs.ext3",
-            "ext4" => "mkfs.ext4",
-            "fat32" => "mkfs.fat -F 32",
-            "swap" => "mkswap",
-            _ => ""
-        }.to_string();
-        if cmd.is_empty() { // if true, then we didn't recognise the format
-            None
-        } else {
-            Some(cmd + " " + &self.get_partition_file(number).unwrap())
+    /// partition wasn't recognised, if it's reused, or if it's a member of a btrfs filesystem,
+    /// which is formatted along with the partition it's a member of. The files of the members are
+    /// added by `format_cmds`, after the one of this partition
+    pub fn mkfs_cmd(&self, device: &BlockDevice) -> Option<String>
+    {
+        if self.reuse || self.member_of.is_some() {
+            return None;
+        }
+        self.filesystem().map(|fs| {
+            let mut cmd = vec![fs.mkfs.to_string()];
+            if let Some(profile) = &self.raid_profile {
+                cmd.push(format!("-d {} -m {}", profile, profile));
+            }
+            // user-supplied arguments go after ours, so that they take precedence
+            cmd.extend(self.mkfs_args.iter().map(|a| shell_quote(a)));
+            cmd.push(device.filesystem.clone());
+            cmd.join(" ")
+        })
+    }
+
+    /// Return the commands that stop the script if the partition is reused, but doesn't hold the
+    /// filesystem it's declared with, or `None` if it isn't reused
+    pub fn reuse_check(&self, device: &BlockDevice) -> Option<String>
+    {
+        if !self.reuse {
+            return None;
+        }
+        self.filesystem().map(|fs| REUSE_CHECK
+            .replace("{partition}", &device.partition)
+            .replace("{format}", &self.format)
+            .replace("{mount}", &self.mount)
+            .replace("{type}", fs.blkid_type))
+    }
+
+    /// Return the `post_format` commands of the partition, with `$DEVICE` set to the file of its
+    /// filesystem, or `None` if it has none
+    pub fn post_format_cmds(&self, device: &BlockDevice) -> Option<String>
+    {
+        if self.post_format.is_empty() {
+            return None;
+        }
+        Some(with_device(&device.filesystem, &self.post_format, &format!("post_format commands of {}", device.partition)))
+    }
+
+    /// Return a comment noting that the partition is left unmounted on purpose, if it's neither
+    /// swap nor mounted anywhere
+    pub fn unmounted_note(&self, device: &BlockDevice) -> Option<String>
+    {
+        if &self.format == "swap" || !self.mount.is_empty() {
+            return None;
+        }
+        if self.esp {
+            return Some(format!("# {} is a secondary EFI system partition, which is only mounted while it's kept in sync with the primary one",
+                device.partition));
         }
+        if let Some(mount) = &self.member_of {
+            return Some(format!("# {} is a member of the btrfs filesystem mounted at {}", device.partition, mount));
+        }
+        Some(format!("# {} ({}) is left unmounted, and isn't added to the filesystem table",
+            device.partition, self.format))
+    }
+
+    /// Return a line describing the partition: its file, format, size and where it's mounted
+    pub fn describe(&self, device: &BlockDevice) -> Option<String>
+    {
+        Some(format!("{}: {}, {}, {}",
+            device.partition,
+            self.format,
+            match self.size {
+                _ if self.reuse => "reused as it is".to_string(),
+                Some(size) => size.to_string(),
+                None => "rest of the disk".to_string(),
+            },
+            match (self.format.as_str(), self.mount.as_str()) {
+                ("swap", _) => "swap".to_string(),
+                (_, "") if self.esp => "secondary EFI system partition".to_string(),
+                (_, "") if self.member_of.is_some() =>
+                    format!("member of the filesystem mounted at {}", self.member_of.as_deref().unwrap_or_default()),
+                (_, "") => "unmounted".to_string(),
+                (_, mount) => format!("mounted at {}", mount),
+            },
+        ))
     }
 
-    /// Return a shell command that mounts the given partition
-    pub fn mount_cmd(&self, number: u32) -> Option<String>
+    /// Return a shell command that mounts the given partition, under the new system mounted on
+    /// `root`. Swap partitions are activated instead, with their priority if they have one;
+    /// `genfstab` picks it up from the active swap
+    pub fn mount_cmd(&self, device: &BlockDevice, root: &str) -> Option<String>
     {
         if &self.format == "swap" {
+            if !self.activate_swap {
+                return None;
+            }
+            // /proc/swaps lists the swap spaces by the file the kernel knows them by
             Some(format!(
-                "swapon {}",
-                self.get_partition_file(number).unwrap(),
+                "grep -q \"^$(readlink -f {0}) \" /proc/swaps || swapon {1}{0}",
+                device.partition,
+                match self.swap_priority {
+                    Some(priority) => format!("-p {} ", priority),
+                    None => "".to_string(),
+                },
             ))
         } else if self.mount.is_empty() {
             None
         } else {
             Some(format!(
-                "mkdir -p /mnt{} && mount {} /mnt{}",
-                self.mount,
-                self.get_partition_file(number).unwrap(),
-                self.mount,
+                "mountpoint -q {0} || {{ mkdir -p {0} && mount {1}{2} {0} || exit 1; }}",
+                target_path(root, &self.mount),
+                if self.mount_options.is_empty() {
+                    "".to_string()
+                } else {
+                    format!("-o {} ", shell_quote(&self.mount_options))
+                },
+                device.filesystem,
             ))
         }
     }
 
-    /// Return the path to the partition file (e.g. `/dev/sda1`, if provided `0`, for 0th
-    /// partition)
-    fn get_partition_file(&self, number: u32) -> Option<String>
+    /// Partitions are encrypted as LUKS2 containers, which cryptsetup creates since 2.0
+    const LUKS2: IsoRequirement = IsoRequirement {
+        step: "encryption",
+        feature: "LUKS2 containers (cryptsetup 2.0)",
+        version: "2018.02.01",
+        needed: |o| o.partitions.iter().any(|p| p.encryption.is_some()),
+    };
+
+    /// Return the commands that encrypt this partition with LUKS and open it, asking for the
+    /// passphrase until it's given correctly, or `None` if it's not encrypted
+    pub fn luks_cmds(&self, device: &BlockDevice) -> Option<String>
+    {
+        let name = self.mapper_name()?;
+        let file = &device.partition;
+        Some(format!(
+            "{}\n{}",
+            until_success(&format!("cryptsetup luksFormat --type luks2 --batch-mode --verify-passphrase {}", file)),
+            until_success(&format!("cryptsetup open {} {}", file, name)),
+        ))
+    }
+
+    /// Return the commands that make the installed system unlock this partition on boot: its
+    /// keyfile, if it has one, its entry in `/etc/crypttab`, which refers to it by UUID, and the
+    /// rewrite of its fstab entry to go through the device mapper, on the new system mounted on
+    /// `root`. `None` if the partition isn't encrypted, or if it's the root partition, which is
+    /// unlocked by the initramfs instead
+    pub fn crypttab_cmds(&self, device: &BlockDevice, root: &str) -> Option<String>
+    {
+        let name = self.mapper_name()?;
+        if self.mount == "/" {
+            return None;
+        }
+        let encryption = self.encryption.as_ref().unwrap();
+        let file = &device.partition;
+        let mut cmds = Vec::new();
+        if let Some(keyfile) = &encryption.keyfile {
+            let keyfile = target_path(root, keyfile);
+            cmds.push(format!("(umask 077 && mkdir -p \"$(dirname {0})\" && dd if=/dev/urandom of={0} bs=512 count=4 status=none)", keyfile));
+            cmds.push(format!("chmod 0 {}", keyfile));
+            cmds.push(until_success(&format!("cryptsetup luksAddKey {} {}", file, keyfile)));
+        }
+        let mut options = vec!["luks"];
+        if encryption.tpm2 {
+            options.push("tpm2-device=auto");
+        }
+        cmds.push(format!("echo \"{} UUID=$(blkid -s UUID -o value {}) {} {}\" >> {}",
+            name, file, encryption.keyfile.as_deref().unwrap_or("none"), options.join(","), target_path(root, "/etc/crypttab")));
+        cmds.push(format!("sed -i \"s|^UUID=$(blkid -s UUID -o value /dev/mapper/{})[[:space:]]|/dev/mapper/{} |\" {}",
+            name, name, target_path(root, "/etc/fstab")));
+        Some(cmds.join("\n"))
+    }
+
+    /// Return the name of the device mapper entry of this partition, if it's encrypted
+    pub fn mapper_name(&self) -> Option<String>
     {
-        let disk = self.disk.clone();
-        let n = &(number + 1).to_string();
-        // NVME naming patterns deviate from the usual
-        let re = Regex::new(r"/dev/nvme\d+n\d+").unwrap();
-        Some(if re.is_match(&disk) {
-            disk + "p" + n
-        } else {
-            disk + n
+        self.encryption.as_ref()?;
+        Some(match self.mount.as_str() {
+            "/" => "cryptroot".to_string(),
+            mount => format!("crypt{}", mount.trim_matches('/').replace('/', "_")),
         })
     }
 
+    /// Return a command that adds this partition to the fstab file of the target system, if
+    /// `genfstab` isn't going to do it. That's the case only for swap that isn't activated during
+    /// the installation. The target system is mounted on `root`
+    pub fn fstab_cmd(&self, device: &BlockDevice, root: &str) -> Option<String>
+    {
+        if &self.format != "swap" || self.activate_swap {
+            return None;
+        }
+        Some(fstab_append_cmd(
+            root,
+            &device.partition,
+            "none",
+            "swap",
+            &match self.swap_priority {
+                Some(priority) => format!("defaults,pri={}", priority),
+                None => "defaults".to_string(),
+            },
+            0,
+        ))
+    }
+
     /// Return the `fdisk` partition type that should be used with the specified format
-    fn fdisk_partition_type(&self) -> &str
+    pub fn fdisk_partition_type(&self) -> &str
     {
-        match self.format.as_str() {
-            "fat32" => "uefi", // EFI System
-            "swap" => "swap", // Linux swap
-            _ => "linux", // Linux filesystem
+        // Linux filesystem, unless the format says otherwise
+        self.fdisk_type.as_deref()
+            .or_else(|| self.filesystem().map(|fs| fs.fdisk_type))
+            .unwrap_or("linux")
+    }
+
+    /// Return the filesystem described by the partition's format, or `None` if it wasn't
+    /// recognised
+    fn filesystem(&self) -> Option<&'static Filesystem>
+    {
+        filesystem(&self.format)
+    }
+}
+
+impl FstabEntry
+{
+    /// Return the line describing this entry in an fstab file
+    fn fstab_line(&self) -> String
+    {
+        format!(
+            "{}\t{}\t{}\t{}\t{} {}",
+            self.fs,
+            self.dir,
+            self.fstype,
+            self.options,
+            self.dump,
+            self.pass,
+        )
+    }
+}
+
+impl Directory
+{
+    /// Return the command that creates the directory, along with its parents, and gives it its
+    /// mode and owner
+    pub fn install_cmd(&self) -> String
+    {
+        let mut cmd = "install -d".to_string();
+        if let Some(mode) = &self.mode {
+            cmd += &format!(" -m {}", mode);
+        }
+        if let Some(owner) = &self.owner {
+            cmd += &format!(" -o {}", shell_quote(owner));
         }
+        if let Some(group) = &self.group {
+            cmd += &format!(" -g {}", shell_quote(group));
+        }
+        format!("{} {}", cmd, shell_quote(&self.path))
     }
 }
 
@@ -416,10 +4199,11 @@ impl User
     #[allow(dead_code)]
     fn to_commands(&self) -> Vec<String>
     {
-        vec![
+        let mut cmds = vec![
             format!(
-                "useradd -m {}{}{}",
+                "id -u {0} >/dev/null 2>&1 || useradd -m {0}{1}{2}{3}",
                 &self.name,
+                if self.skip_skel { " -k /dev/null" } else { "" },
                 if ! &self.groups.is_empty() {
                     format!(" -G {}", &self.groups.join(","))
                 } else {
@@ -431,7 +4215,53 @@ impl User
                     "".to_string()
                 },
             ),
-            format!("while true; do if passwd {}; then break; fi; done", &self.name),
-        ]
+            until_success(&format!("passwd {}", &self.name)),
+        ];
+        if let Some(locale) = &self.locale {
+            // the file is created by root, so it has to be handed over to the user
+            cmds.extend([
+                format!("mkdir -p /home/{}/.config", &self.name),
+                format!("echo 'LANG={}' >/home/{}/.config/locale.conf", locale, &self.name),
+                format!("chown -R {}: /home/{}/.config", &self.name, &self.name),
+            ]);
+        }
+        cmds
+    }
+
+    /// Return the command that creates the user with an encrypted home directory, managed by
+    /// systemd-homed, asking for the password until it succeeds
+    fn homectl_cmd(&self) -> String
+    {
+        let mut cmd = format!("homectl create {} --storage=luks", &self.name);
+        if !self.groups.is_empty() {
+            cmd += &format!(" --member-of={}", self.groups.join(","));
+        }
+        if !self.shell.is_empty() {
+            cmd += &format!(" --shell={}", &self.shell);
+        }
+        if let Some(locale) = &self.locale {
+            cmd += &format!(" --language={}", locale);
+        }
+        until_success(&cmd)
+    }
+
+    /// Return the commands that make the user linger and enable the units of their systemd
+    /// instance, if there's any of it to do. The units are enabled by systemctl itself, as the
+    /// user, rather than by their instance: the first-boot service runs before users can log in,
+    /// which their instance waits for, so asking it would never return
+    fn user_services_cmds(&self) -> Option<String>
+    {
+        let mut cmds = vec![];
+        if self.linger {
+            cmds.push(format!("loginctl enable-linger {}", self.name));
+        }
+        if !self.user_services.is_empty() {
+            cmds.push(format!(
+                "runuser -u {} -- env SYSTEMCTL_INSTALL_CLIENT_SIDE=1 systemctl --user enable {}",
+                self.name,
+                self.user_services.join(" "),
+            ));
+        }
+        (!cmds.is_empty()).then(|| cmds.join("\n"))
     }
 }
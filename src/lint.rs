@@ -0,0 +1,93 @@
+use crate::data::{Bootloader, InstallOptions, DESKTOPS, DISPLAY_MANAGERS};
+
+/// A check against a practice that jimmy advises against, in a configuration that's otherwise
+/// valid. `jimmy validate --lint` runs them
+pub struct Lint
+{
+    /// The name it's reported and allowed by, which never changes
+    pub name: &'static str,
+    /// Return what's wrong with the configuration, if anything
+    check: fn(&InstallOptions) -> Option<String>,
+}
+
+/// What a lint found in a configuration
+#[derive(Debug)]
+pub struct Diagnostic
+{
+    pub lint: &'static str,
+    pub message: String,
+}
+
+/// Every lint, in the order they're ran in
+pub const LINTS: &[Lint] = &[
+    Lint { name: "no-swap", check: no_swap },
+    Lint { name: "small-esp", check: small_esp },
+    Lint { name: "ext4-root-without-noatime", check: ext4_root_without_noatime },
+    Lint { name: "sshd-without-firewall", check: sshd_without_firewall },
+    Lint { name: "desktop-without-display-manager", check: desktop_without_display_manager },
+];
+
+/// Packages that install a firewall
+const FIREWALLS: &[&str] = &["ufw", "firewalld", "nftables"];
+
+/// The smallest EFI system partition that leaves room for a second kernel, when it holds them
+const ESP_KERNELS_MIN_MIB: u64 = 512;
+
+/// Return whether `name` is the name of a lint
+pub fn is_lint(name: &str) -> bool
+{
+    LINTS.iter().any(|l| l.name == name)
+}
+
+/// Run every lint on the options, except those that are allowed, either by `allowed` or by the
+/// `allow_lints` of the configuration
+pub fn lint(options: &InstallOptions, allowed: &[String]) -> Vec<Diagnostic>
+{
+    LINTS.iter()
+        .filter(|l| !allowed.iter().chain(&options.allow_lints).any(|a| a == l.name))
+        .filter_map(|l| Some(Diagnostic { lint: l.name, message: (l.check)(options)? }))
+        .collect()
+}
+
+fn no_swap(options: &InstallOptions) -> Option<String>
+{
+    (!options.partitions.iter().any(|p| p.format == "swap"))
+        .then(|| "there's no swap partition, so the system can't hibernate, and has nothing to fall back on when it runs out of memory".to_string())
+}
+
+fn small_esp(options: &InstallOptions) -> Option<String>
+{
+    let esp = options.esp();
+    let holds_kernels = options.bootloader == Bootloader::Efistub || (options.bootloader == Bootloader::SystemdBoot && esp.mount == "/boot");
+    let size = esp.size?;
+    (holds_kernels && size.mib() < ESP_KERNELS_MIN_MIB).then(|| format!(
+        "the EFI system partition ({}) holds the kernels, and under {}M it has little room for another kernel or a bigger initramfs",
+        size, ESP_KERNELS_MIN_MIB,
+    ))
+}
+
+fn ext4_root_without_noatime(options: &InstallOptions) -> Option<String>
+{
+    let root = options.root();
+    (root.format == "ext4" && !root.mount_options.split(',').any(|o| o == "noatime"))
+        .then(|| "the root filesystem is ext4 without `noatime`, so reading files also writes to it; add it to its `mount_options`".to_string())
+}
+
+fn sshd_without_firewall(options: &InstallOptions) -> Option<String>
+{
+    let packages = options.package_list();
+    (packages.iter().any(|p| p == "openssh") && !packages.iter().any(|p| FIREWALLS.contains(&p.as_str()))).then(|| format!(
+        "openssh is installed, and sshd accepts passwords unless it's told otherwise, but there's no firewall ({})",
+        FIREWALLS.join(", "),
+    ))
+}
+
+fn desktop_without_display_manager(options: &InstallOptions) -> Option<String>
+{
+    let packages = options.package_list();
+    let desktop = packages.iter().find(|p| DESKTOPS.contains(&p.as_str()))?;
+    (!packages.iter().any(|p| DISPLAY_MANAGERS.contains(&p.as_str()))).then(|| format!(
+        "{} is installed, but no display manager ({}) is, so the installed system starts on a console",
+        desktop, DISPLAY_MANAGERS.join(", "),
+    ))
+}
@@ -0,0 +1,169 @@
+use std::cell::RefCell;
+use std::sync::Mutex;
+use std::sync::atomic::{AtomicU8, AtomicUsize, Ordering};
+
+/// How much jimmy itself prints to stderr while it reads a configuration file, apart from errors
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Verbosity
+{
+    /// Only errors
+    Quiet,
+    /// Warnings, and a one-line summary of the installation
+    Normal,
+    /// Warnings, and a summary of every step of the installation
+    Verbose,
+}
+
+static VERBOSITY: AtomicU8 = AtomicU8::new(Verbosity::Normal as u8);
+
+/// Set how much jimmy prints from now on
+pub fn set_verbosity(verbosity: Verbosity)
+{
+    VERBOSITY.store(verbosity as u8, Ordering::Relaxed);
+}
+
+/// Return how much jimmy prints
+pub fn verbosity() -> Verbosity
+{
+    match VERBOSITY.load(Ordering::Relaxed) {
+        0 => Verbosity::Quiet,
+        1 => Verbosity::Normal,
+        _ => Verbosity::Verbose,
+    }
+}
+
+/// Print a warning about the configuration file to stderr, unless jimmy was told to be quiet or
+/// the warnings are collected (see `collect_warnings`)
+macro_rules! warning {
+    ($($arg:tt)*) => {
+        $crate::log::warn(format!($($arg)*))
+    };
+}
+pub(crate) use warning;
+
+/// A part of the path to a property of the configuration file
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum PathSegment
+{
+    /// A property of a mapping
+    Key(String),
+    /// An item of a list, starting at 0
+    Index(usize),
+}
+
+/// A warning or an error, along with the path of the property it was given about, as far as it's
+/// known
+#[derive(Debug, Clone)]
+pub struct Logged
+{
+    pub message: String,
+    pub context: Vec<PathSegment>,
+}
+
+/// The warnings given so far, while they're collected instead of printed
+static COLLECTED: Mutex<Option<Vec<Logged>>> = Mutex::new(None);
+
+thread_local! {
+    /// The path of the property whose value is being read
+    static CONTEXT: RefCell<Vec<PathSegment>> = const { RefCell::new(Vec::new()) };
+}
+
+/// How many warnings were given so far, printed or not
+static WARNED: AtomicUsize = AtomicUsize::new(0);
+
+/// Print a warning, or collect it; use `warning!` instead
+pub fn warn(message: String)
+{
+    WARNED.fetch_add(1, Ordering::Relaxed);
+    if let Some(warnings) = COLLECTED.lock().unwrap().as_mut() {
+        warnings.push(Logged::here(message));
+    } else if verbosity() >= Verbosity::Normal {
+        eprintln!("warning: {}", message);
+    }
+}
+
+/// Return how many warnings were given so far, whether they were printed, collected or neither
+pub fn warning_count() -> usize
+{
+    WARNED.load(Ordering::Relaxed)
+}
+
+/// Collect the warnings from now on instead of printing them, whatever the verbosity
+pub fn collect_warnings()
+{
+    *COLLECTED.lock().unwrap() = Some(Vec::new());
+}
+
+/// Return the warnings collected since `collect_warnings`, and print them again from now on
+pub fn collected_warnings() -> Vec<Logged>
+{
+    COLLECTED.lock().unwrap().take().unwrap_or_default()
+}
+
+/// Run `f` as reading the property at `path`, under the one that's being read
+pub fn within<T>(path: &[PathSegment], f: impl FnOnce() -> T) -> T
+{
+    let depth = CONTEXT.with(|c| {
+        let mut c = c.borrow_mut();
+        c.extend_from_slice(path);
+        c.len() - path.len()
+    });
+    let result = f();
+    CONTEXT.with(|c| c.borrow_mut().truncate(depth));
+    result
+}
+
+/// Return the path of the property that's being read
+pub fn context() -> Vec<PathSegment>
+{
+    CONTEXT.with(|c| c.borrow().clone())
+}
+
+/// The errors found in a configuration file, along with the paths of the properties they're
+/// about; the file is refused if there's any
+pub type Errors = Vec<Logged>;
+
+impl Logged
+{
+    /// Return a message about the property that's being read
+    pub fn here(message: String) -> Self
+    {
+        Self { message, context: context() }
+    }
+}
+
+/// Return the errors a configuration file is refused for, made of the given one
+macro_rules! refusal {
+    ($($arg:tt)*) => {
+        vec![$crate::log::Logged::here(format!($($arg)*))]
+    };
+}
+pub(crate) use refusal;
+
+/// Refuse the configuration file, returning the given error from the function that reads it. Use
+/// `error!` instead when what comes next can be read without it
+macro_rules! refuse {
+    ($($arg:tt)*) => {
+        return Err($crate::log::refusal!($($arg)*))
+    };
+}
+pub(crate) use refuse;
+
+/// Add an error about the configuration file to `errors`, and keep on reading it to find the
+/// others; it's refused once it's read
+macro_rules! error {
+    ($errors:expr, $($arg:tt)*) => {
+        $errors.push($crate::log::Logged::here(format!($($arg)*)))
+    };
+}
+pub(crate) use error;
+
+/// Print a note about what jimmy is doing to stderr, unless jimmy was told to be quiet
+macro_rules! info {
+    ($($arg:tt)*) => {
+        if $crate::log::verbosity() >= $crate::log::Verbosity::Normal {
+            eprintln!("{}", format_args!($($arg)*));
+        }
+    };
+}
+pub(crate) use info;
@@ -1,9 +1,26 @@
 use std::process::exit;
-use clap::{App, Arg};
+use clap::{App, Arg, ValueHint};
+use clap_complete::Shell;
 
+mod api;
+mod config;
 mod data;
+mod doctor;
 mod install;
+use install::{RenderContext, ShellDialect, DEFAULT_WIDTH};
+mod lint;
+mod log;
+mod messages;
+mod migrate;
+mod shellgen;
+mod template;
+mod unused;
 use data::*;
+use log::{Verbosity, info, refusal, warning};
+
+/// The version of jimmy, along with the commit and the date it was built from, as written in the
+/// headers of the generated scripts
+pub const VERSION: &str = env!("JIMMY_VERSION");
 
 /// Determine if the given path exists *and* is a file
 fn is_file(path: &str) -> bool
@@ -17,36 +34,473 @@ fn read_file(path: &str) -> Result<String, std::io::Error>
     std::fs::read_to_string(path)
 }
 
-fn main() -> Result<(), std::io::Error>
+/// Read the configuration file at the given path, bringing it up to date with the current version
+/// of the format, and warning about the deprecated properties it uses. Exit if the path isn't a
+/// file, or if it can't be brought up to date
+fn read_config(path: &str) -> Result<serde_yaml::Value, std::io::Error>
+{
+    if !is_file(path) {
+        eprintln!("error: provided path is not a file");
+        exit(1);
+    }
+
+    Ok(or_exit(migrate_config(serde_yaml::from_str(&read_file(path)?).unwrap())))
+}
+
+/// Bring a configuration up to date with the current version of the format, warning about the
+/// deprecated properties it uses
+fn migrate_config(mut config: serde_yaml::Value) -> Result<serde_yaml::Value, log::Errors>
+{
+    for d in migrate::migrate(&mut config)? {
+        warning!("'{}' is deprecated since version {} of the format; use '{}' instead",
+            d.field, d.since, d.replacement);
+    }
+    Ok(config)
+}
+
+/// Turn a configuration that's up to date with the format into the installation options
+fn options_from_config(mut config: serde_yaml::Value, allow_missing_env: bool) -> Result<InstallOptions, log::Errors>
+{
+    template::expand(&mut config, allow_missing_env)?;
+    let hash = config_hash(&config);
+    let parsed: ParsedInstallOptions = serde_yaml::from_value(config).map_err(|e| refusal!("{}", e))?;
+    Ok(InstallOptions {
+        config_hash: hash,
+        ..InstallOptions::try_from(parsed)?
+    })
+}
+
+/// Return what was read from a configuration file. Exit, printing every error that was found, if
+/// it has any
+fn or_exit<T>(read: Result<T, log::Errors>) -> T
+{
+    read.unwrap_or_else(|errors| {
+        for error in errors {
+            eprintln!("error: {}", error.message);
+        }
+        exit(1);
+    })
+}
+
+/// Read the configuration file at the given path and turn it into the installation options. Exit,
+/// printing every error that was found, if it has any
+fn load_options(path: &str, allow_missing_env: bool) -> Result<InstallOptions, std::io::Error>
+{
+    let config = read_config(path)?;
+    Ok(or_exit(options_from_config(config, allow_missing_env)))
+}
+
+/// Return how the scripts are to be written, from the flags given on the command line. Exit if
+/// the log isn't given as an absolute path, since the script may be ran from anywhere, or if the
+/// width isn't a number
+fn render_context(args: &clap::ArgMatches) -> RenderContext
+{
+    let log_path = args.value_of("LOG").map(String::from);
+    if let Some(path) = &log_path {
+        if !path.starts_with('/') || path.ends_with('/') || path.contains(['\n', '\0']) {
+            eprintln!("error: invalid --log: \"{}\" (expected the absolute path of a file, such as /var/log/jimmy.log)", path);
+            exit(1);
+        }
+    }
+    RenderContext {
+        reproducible: args.is_present("flag_reproducible"),
+        color: args.is_present("flag_color"),
+        log_path,
+        shell: match args.value_of("SHELL_DIALECT") {
+            Some("bash") => ShellDialect::Bash,
+            _ => ShellDialect::Sh,
+        },
+        width: match args.value_of("WIDTH") {
+            None => DEFAULT_WIDTH,
+            Some(width) => width.parse().unwrap_or_else(|_| {
+                eprintln!("error: invalid --width: \"{}\" (expected a number of columns, or 0 not to wrap the commands)", width);
+                exit(1);
+            }),
+        },
+    }
+}
+
+/// Return the path of the configuration file to use when none is given, reporting which one it
+/// is. Exit, listing the places that were searched, if there's none
+fn find_config() -> String
+{
+    match config::find_config(&config::SearchEnv::current()) {
+        Ok(path) => {
+            info!("using the configuration file {}", path.display());
+            path.display().to_string()
+        },
+        Err(searched) => {
+            eprintln!("error: no configuration file given, and none found at any of:");
+            for path in searched {
+                eprintln!("    {}", path.display());
+            }
+            exit(1);
+        },
+    }
+}
+
+/// Write the sample configuration file to where the user's configuration file goes, unless
+/// there's already one. Exit if there's no such place
+fn install_sample() -> Result<(), std::io::Error>
+{
+    let path = match config::user_config_path(&config::SearchEnv::current()) {
+        Some(path) => path,
+        None => {
+            eprintln!("error: neither XDG_CONFIG_HOME nor HOME is set to an absolute path");
+            exit(1);
+        },
+    };
+    if path.exists() {
+        eprintln!("error: {} already exists; remove it first to replace it with the sample", path.display());
+        exit(1);
+    }
+    std::fs::create_dir_all(path.parent().unwrap())?;
+    std::fs::write(&path, sample_input_file())?;
+    info!("wrote the sample configuration file to {}", path.display());
+    Ok(())
+}
+
+/// Return the flags that set how the scripts are written, which the commands printing them take
+fn render_args() -> [Arg<'static>; 5]
+{
+    [
+        Arg::new("flag_reproducible")
+            .long("--reproducible")
+            .help("generates the same script every time for the same input file"),
+        Arg::new("flag_color")
+            .long("--color")
+            .help("prints the status messages of the scripts in bold"),
+        Arg::new("LOG")
+            .long("--log")
+            .takes_value(true)
+            .value_name("PATH")
+            .help("keeps what the installation script writes to stderr in the given file"),
+        Arg::new("SHELL_DIALECT")
+            .long("--shell")
+            .takes_value(true)
+            .possible_values(ShellDialect::ALL.iter().map(ShellDialect::name))
+            .help("sets the shell the scripts are written for"),
+        Arg::new("WIDTH")
+            .long("--width")
+            .takes_value(true)
+            .value_name("COLUMNS")
+            .help("wraps the long commands of the scripts to the given width; 0 leaves them on one line"),
+    ]
+}
+
+/// Return the flag of the commands that expand the templates of a configuration file
+fn allow_missing_env_arg() -> Arg<'static>
+{
+    Arg::new("flag_allow_missing_env")
+        .long("--allow-missing-env")
+        .help("expands references to unset environment variables to empty strings")
+}
+
+/// Return the flags that set how much jimmy prints, which the commands reading a configuration
+/// file take
+fn verbosity_args() -> [Arg<'static>; 2]
+{
+    [
+        Arg::new("flag_quiet")
+            .short('q')
+            .long("--quiet")
+            .conflicts_with("flag_verbose")
+            .help("prints only errors"),
+        Arg::new("flag_verbose")
+            .short('v')
+            .long("--verbose")
+            .help("prints every step and partition of the installation"),
+    ]
+}
+
+/// Set how much jimmy prints from the flags of `verbosity_args`
+fn set_verbosity(args: &clap::ArgMatches)
+{
+    log::set_verbosity(if args.is_present("flag_quiet") {
+        Verbosity::Quiet
+    } else if args.is_present("flag_verbose") {
+        Verbosity::Verbose
+    } else {
+        Verbosity::Normal
+    });
+}
+
+/// Return the command line interface
+fn cli() -> App<'static>
 {
-    let cli_args = App::new(env!("CARGO_PKG_NAME"))
-        .version(env!("CARGO_PKG_VERSION"))
+    App::new(env!("CARGO_PKG_NAME"))
+        .version(VERSION)
         .author("xylous <xylous.e@gmail.com>")
         .about("Arch installer using YAML files")
+        // the flags of the installation script don't apply to the other commands
+        .args_conflicts_with_subcommands(true)
         .arg(Arg::new("FILE")
             .short('f')
             .long("--file")
             .takes_value(true)
+            .value_hint(ValueHint::FilePath)
             .help("sets the input file"))
+        .args(render_args())
+        .arg(allow_missing_env_arg())
+        .args(verbosity_args())
+        .arg(Arg::new("flag_no_chroot")
+            .long("--no-chroot")
+            .help("stops after the filesystem table, without configuring the system inside arch-chroot"))
+        .arg(Arg::new("flag_keep_mounted")
+            .long("--keep-mounted")
+            .help("leaves the new system mounted on its mount root (/mnt by default) once it's installed"))
         .arg(Arg::new("flag_sample_file")
             .short('s')
             .long("--sample")
             .help("prints a sample file to stdout"))
-        .get_matches();
+        .arg(Arg::new("flag_install")
+            .long("--install")
+            .requires("flag_sample_file")
+            .help("writes the sample file to $XDG_CONFIG_HOME/jimmy/config.yaml instead"))
+        .subcommand(App::new("migrate")
+            .about("rewrites a YAML file to follow the current version of the format")
+            .arg(Arg::new("FILE")
+                .required(true)
+                .value_hint(ValueHint::FilePath)
+                .help("the file to rewrite; note that comments are lost"))
+            .args(verbosity_args()))
+        .subcommand(App::new("validate")
+            .about("checks a YAML file without generating the script, optionally against the lints of best practices")
+            .arg(Arg::new("FILE")
+                .required(true)
+                .value_hint(ValueHint::FilePath)
+                .help("the file to check"))
+            .arg(Arg::new("flag_lint")
+                .long("--lint")
+                .help("also runs the lints, which advise against valid but unwise choices"))
+            .arg(Arg::new("LINT")
+                .long("--allow")
+                .takes_value(true)
+                .multiple_occurrences(true)
+                .requires("flag_lint")
+                .help("doesn't run the given lint; can be repeated"))
+            .arg(Arg::new("flag_deny_warnings")
+                .long("--deny-warnings")
+                .help("fails if there's any warning, or anything the lints found"))
+            .arg(allow_missing_env_arg())
+            .args(verbosity_args()))
+        .subcommand(App::new("explain")
+            .about("prints every step of the script a YAML file generates, with what it does and why")
+            .arg(Arg::new("FILE")
+                .required(true)
+                .value_hint(ValueHint::FilePath)
+                .help("the file to explain"))
+            .arg(Arg::new("flag_markdown")
+                .long("--markdown")
+                .help("prints the explanation as Markdown"))
+            .arg(allow_missing_env_arg())
+            .args(verbosity_args()))
+        .subcommand(App::new("summarize")
+            .about("prints a one-page summary of the installation a YAML file describes, for reviewing it")
+            .arg(Arg::new("FILE")
+                .required(true)
+                .value_hint(ValueHint::FilePath)
+                .help("the file to summarize"))
+            .arg(Arg::new("flag_markdown")
+                .long("--markdown")
+                .help("prints the summary as Markdown"))
+            .arg(allow_missing_env_arg())
+            .args(verbosity_args()))
+        .subcommand(App::new("chroot-script")
+            .about("prints only the script that configures the system from inside arch-chroot")
+            .arg(Arg::new("FILE")
+                .required(true)
+                .value_hint(ValueHint::FilePath)
+                .help("the file to generate the script from"))
+            .args(render_args())
+            .arg(allow_missing_env_arg())
+            .args(verbosity_args()))
+        .subcommand(App::new("packages")
+            .about("prints every package a YAML file has installed with pacstrap, sorted")
+            .arg(Arg::new("FILE")
+                .required(true)
+                .value_hint(ValueHint::FilePath)
+                .help("the file to list the packages of"))
+            .arg(Arg::new("flag_json")
+                .long("--json")
+                .help("prints the list as JSON"))
+            .arg(allow_missing_env_arg())
+            .args(verbosity_args()))
+        .subcommand(App::new("capabilities")
+            .about("lists the supported formats, bootloaders, kernels etc.")
+            .arg(Arg::new("flag_json")
+                .long("--json")
+                .help("prints the list as JSON")))
+        .subcommand(App::new("doctor")
+            .about("prints what jimmy finds out about this machine: disks, firmware, processor etc.")
+            .arg(Arg::new("flag_draft")
+                .long("--draft")
+                .help("prints a YAML file for this machine instead, to start from"))
+            .arg(Arg::new("ROOT")
+                .long("--root")
+                .takes_value(true)
+                .value_hint(ValueHint::DirPath)
+                .help("reads /proc, /sys and /etc under this directory instead of /")))
+        .subcommand(App::new("api")
+            .about("reads a configuration as JSON on stdin, and writes a single JSON document on stdout, for programs that drive jimmy")
+            .subcommand_required(true)
+            .subcommand(App::new("validate")
+                .about("checks the configuration, with its warnings and errors")
+                .arg(allow_missing_env_arg()))
+            .subcommand(App::new("plan")
+                .about("checks the configuration, along with what the installation plans to do")
+                .arg(allow_missing_env_arg())))
+        .subcommand(App::new("completions")
+            .about("prints the completions of jimmy for a shell")
+            .arg(Arg::new("SHELL")
+                .required(true)
+                .possible_values(["bash", "zsh", "fish"])
+                .help("the shell to complete for")))
+}
 
-    if cli_args.is_present("FILE") {
-        let path = cli_args.value_of("FILE").unwrap();
-        if !is_file(path) {
-            eprintln!("error: provided path is not a file");
+fn main() -> Result<(), std::io::Error>
+{
+    let cli_args = cli().get_matches();
+
+    if let Some(sub_args) = cli_args.subcommand_matches("migrate") {
+        set_verbosity(sub_args);
+        let path = sub_args.value_of("FILE").unwrap();
+        let config = read_config(path)?;
+        std::fs::write(path, serde_yaml::to_string(&config).unwrap())?;
+    } else if let Some(sub_args) = cli_args.subcommand_matches("validate") {
+        set_verbosity(sub_args);
+        let allowed: Vec<String> = sub_args.values_of("LINT").map(|v| v.map(String::from).collect()).unwrap_or_default();
+        if let Some(name) = allowed.iter().find(|l| !lint::is_lint(l)) {
+            eprintln!("error: there's no lint '{}'; the lints are: {}", name, lint::LINTS.iter().map(|l| l.name).collect::<Vec<&str>>().join(", "));
             exit(1);
         }
-
-        let contents = read_file(path)?;
-        let parsed: ParsedInstallOptions = serde_yaml::from_str(&contents).unwrap();
-        let proper = InstallOptions::from(parsed);
-        print!("{}", proper.generate_shellscript());
-    } else if cli_args.is_present("flag_sample_file") {
-        print!("{}", sample_input_file());
+        let options = load_options(
+            sub_args.value_of("FILE").unwrap(),
+            sub_args.is_present("flag_allow_missing_env"),
+        )?;
+        let diagnostics = if sub_args.is_present("flag_lint") { lint::lint(&options, &allowed) } else { vec![] };
+        for diagnostic in &diagnostics {
+            eprintln!("lint {}: {}", diagnostic.lint, diagnostic.message);
+        }
+        if sub_args.is_present("flag_deny_warnings") && (log::warning_count() > 0 || !diagnostics.is_empty()) {
+            eprintln!("error: the configuration isn't free of warnings, and --deny-warnings was given");
+            exit(1);
+        }
+    } else if let Some(sub_args) = cli_args.subcommand_matches("explain") {
+        set_verbosity(sub_args);
+        let options = load_options(
+            sub_args.value_of("FILE").unwrap(),
+            sub_args.is_present("flag_allow_missing_env"),
+        )?;
+        print!("{}", options.explain(sub_args.is_present("flag_markdown")));
+    } else if let Some(sub_args) = cli_args.subcommand_matches("summarize") {
+        set_verbosity(sub_args);
+        let options = load_options(
+            sub_args.value_of("FILE").unwrap(),
+            sub_args.is_present("flag_allow_missing_env"),
+        )?;
+        print!("{}", options.summarize(sub_args.is_present("flag_markdown")));
+    } else if let Some(sub_args) = cli_args.subcommand_matches("chroot-script") {
+        set_verbosity(sub_args);
+        let options = load_options(
+            sub_args.value_of("FILE").unwrap(),
+            sub_args.is_present("flag_allow_missing_env"),
+        )?;
+        if !options.phases.iter().any(|p| p == "chroot") {
+            eprintln!("error: the configuration file leaves out the phase chroot, so there's no arch-chroot script");
+            exit(1);
+        }
+        print!("{}", options.render_chroot_script(&render_context(sub_args)));
+    } else if let Some(sub_args) = cli_args.subcommand_matches("packages") {
+        set_verbosity(sub_args);
+        let options = load_options(
+            sub_args.value_of("FILE").unwrap(),
+            sub_args.is_present("flag_allow_missing_env"),
+        )?;
+        if sub_args.is_present("flag_json") {
+            println!("{}", serde_json::to_string_pretty(&options.package_list()).unwrap());
+        } else {
+            for package in options.package_list() {
+                println!("{}", package);
+            }
+        }
+    } else if let Some(sub_args) = cli_args.subcommand_matches("capabilities") {
+        if sub_args.is_present("flag_json") {
+            println!("{}", serde_json::to_string_pretty(&capabilities()).unwrap());
+        } else {
+            println!("formats: {}", FILESYSTEMS.iter().map(|fs| fs.format).collect::<Vec<&str>>().join(", "));
+            println!("bootloaders: {}", Bootloader::ALL.iter().map(Bootloader::name).collect::<Vec<&str>>().join(", "));
+            println!("chroot backends: {}", CHROOT_BACKENDS.join(", "));
+            println!("initramfs generators: {}", INITRAMFS_GENERATORS.join(", "));
+            println!("kernels: {}", Kernel::ALL.iter().map(Kernel::name).collect::<Vec<&str>>().join(", "));
+            println!("architectures: {}", Architecture::ALL.iter().map(Architecture::name).collect::<Vec<&str>>().join(", "));
+            println!("network backends: {}", NETWORK_BACKENDS.join(", "));
+            println!("power daemons: {}", POWER_DAEMONS.join(", "));
+            println!("previous mounts: {}", PREVIOUS_MOUNTS.join(", "));
+            println!("default targets: {}", DEFAULT_TARGETS.join(", "));
+            println!("bootstraps: {}", BOOTSTRAPS.join(", "));
+            println!("languages: {}", Language::ALL.iter().map(Language::code).collect::<Vec<&str>>().join(", "));
+            println!("partition types: {}", PARTITION_TYPES.iter().map(|(name, _)| *name).collect::<Vec<&str>>().join(", "));
+            println!("lints: {}", lint::LINTS.iter().map(|l| l.name).collect::<Vec<&str>>().join(", "));
+        }
+    } else if let Some(sub_args) = cli_args.subcommand_matches("doctor") {
+        let machine = doctor::Machine::detect(std::path::Path::new(sub_args.value_of("ROOT").unwrap_or("/")));
+        if sub_args.is_present("flag_draft") {
+            print!("{}", machine.draft());
+        } else {
+            print!("{}", machine.report());
+        }
+    } else if let Some(sub_args) = cli_args.subcommand_matches("api") {
+        let (command, command_args) = sub_args.subcommand().unwrap();
+        let mut input = String::new();
+        std::io::Read::read_to_string(&mut std::io::stdin(), &mut input)?;
+        let response = api::respond(
+            &input,
+            command == "plan",
+            command_args.is_present("flag_allow_missing_env"),
+        );
+        println!("{}", serde_json::to_string_pretty(&response).unwrap());
+        if response["valid"] != true {
+            exit(1);
+        }
+    } else if let Some(sub_args) = cli_args.subcommand_matches("completions") {
+        let shell = match sub_args.value_of("SHELL").unwrap() {
+            "bash" => Shell::Bash,
+            "zsh" => Shell::Zsh,
+            _ => Shell::Fish,
+        };
+        clap_complete::generate(shell, &mut cli(), env!("CARGO_PKG_NAME"), &mut std::io::stdout());
+    } else if cli_args.is_present("flag_sample_file") && !cli_args.is_present("FILE") {
+        if cli_args.is_present("flag_install") {
+            install_sample()?;
+        } else {
+            print!("{}", sample_input_file());
+        }
+    } else {
+        set_verbosity(&cli_args);
+        let path = match cli_args.value_of("FILE") {
+            Some(path) => path.to_string(),
+            None => find_config(),
+        };
+        let mut proper = load_options(
+            &path,
+            cli_args.is_present("flag_allow_missing_env"),
+        )?;
+        // the flags take phases away from those the configuration file selects
+        for (flag, phase) in [("flag_no_chroot", "chroot"), ("flag_keep_mounted", "unmount")] {
+            if cli_args.is_present(flag) {
+                proper.phases.retain(|p| p != phase);
+            }
+        }
+        or_exit(validate_phases(&proper.phases));
+        print!("{}", proper.render_shellscript(&render_context(&cli_args)));
+        // the script goes to stdout, so the summary goes with the warnings
+        let summary = proper.summary();
+        match log::verbosity() {
+            Verbosity::Quiet => (),
+            Verbosity::Normal => eprintln!("{}", summary.line()),
+            Verbosity::Verbose => eprintln!("{}", summary.details()),
+        }
     }
 
     Ok(())
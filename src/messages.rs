@@ -0,0 +1,322 @@
+use crate::data::Language;
+
+/// The status messages printed by the generated scripts, keyed by identifier, in the order of
+/// `Language::ALL`. They're printed inside single quotes, so they mustn't contain any. `{root}`
+/// is where the new system is mounted, which the scripts fill in
+const CATALOG: &[(&str, [&str; 3])] = &[
+    ("resolve disks", [
+        "finding disks given by stable identifiers, and attaching disk images...",
+        "buscando los discos indicados por identificadores estables, y conectando las imágenes de disco...",
+        "suche die Festplatten anhand stabiler Kennungen und binde Festplattenabbilder ein...",
+    ]),
+    ("preflight", [
+        "checking whether it is safe to install...",
+        "comprobando si es seguro instalar...",
+        "prüfe, ob die Installation sicher ist...",
+    ]),
+    ("keymap", [
+        "loading the keymap...",
+        "cargando la distribución del teclado...",
+        "lade die Tastaturbelegung...",
+    ]),
+    ("previous mounts", [
+        "looking for filesystems left mounted under {root}...",
+        "buscando sistemas de archivos que sigan montados en {root}...",
+        "suche nach Dateisystemen, die noch unter {root} eingehängt sind...",
+    ]),
+    ("clock", [
+        "synchronizing time with the internet...",
+        "sincronizando la hora con internet...",
+        "synchronisiere die Uhrzeit mit dem Internet...",
+    ]),
+    ("pre-format", [
+        "running the commands given for the disks...",
+        "ejecutando los comandos dados para los discos...",
+        "führe die Befehle für die Festplatten aus...",
+    ]),
+    ("partitioning", [
+        "creating partitions using fdisk...",
+        "creando particiones con fdisk...",
+        "erstelle Partitionen mit fdisk...",
+    ]),
+    ("encryption", [
+        "encrypting partitions with LUKS...",
+        "cifrando particiones con LUKS...",
+        "verschlüssele Partitionen mit LUKS...",
+    ]),
+    ("formatting", [
+        "formatting partitions...",
+        "formateando particiones...",
+        "formatiere Partitionen...",
+    ]),
+    ("post-format", [
+        "running the commands given for the partitions...",
+        "ejecutando los comandos dados para las particiones...",
+        "führe die Befehle für die Partitionen aus...",
+    ]),
+    ("mounting", [
+        "mounting partitions...",
+        "montando particiones...",
+        "hänge Partitionen ein...",
+    ]),
+    ("pacstrap", [
+        "installing packages with pacstrap...",
+        "instalando paquetes con pacstrap...",
+        "installiere Pakete mit pacstrap...",
+    ]),
+    ("tarball", [
+        "extracting the bootstrap tarball...",
+        "extrayendo el tarball de arranque...",
+        "entpacke das Bootstrap-Tarball...",
+    ]),
+    ("packages", [
+        "installing packages with pacman...",
+        "instalando paquetes con pacman...",
+        "installiere Pakete mit pacman...",
+    ]),
+    ("fstab", [
+        "generating the filesystem table...",
+        "generando la tabla de sistemas de archivos...",
+        "erzeuge die Dateisystemtabelle...",
+    ]),
+    ("crypttab", [
+        "setting up the unlocking of encrypted partitions on boot...",
+        "configurando el desbloqueo de las particiones cifradas al arrancar...",
+        "richte das Entsperren verschlüsselter Partitionen beim Start ein...",
+    ]),
+    ("chroot script", [
+        "creating the arch-chroot script...",
+        "creando el script de arch-chroot...",
+        "erstelle das arch-chroot-Skript...",
+    ]),
+    ("configuration", [
+        "running arch-chroot script...",
+        "ejecutando el script de arch-chroot...",
+        "führe das arch-chroot-Skript aus...",
+    ]),
+    ("cleanup", [
+        "cleanup: removing arch-chroot script...",
+        "limpieza: eliminando el script de arch-chroot...",
+        "Aufräumen: entferne das arch-chroot-Skript...",
+    ]),
+    ("verification", [
+        "checking the installed system...",
+        "comprobando el sistema instalado...",
+        "prüfe das installierte System...",
+    ]),
+    ("timings", [
+        "installation timings:",
+        "tiempos de la instalación:",
+        "Dauer der Installationsschritte:",
+    ]),
+    ("report", [
+        "writing the installation report...",
+        "escribiendo el informe de la instalación...",
+        "schreibe den Installationsbericht...",
+    ]),
+    ("artifacts", [
+        "cleanup: removing the files jimmy left on the installed system...",
+        "limpieza: eliminando los archivos que jimmy dejó en el sistema instalado...",
+        "Aufräumen: entferne die Dateien, die jimmy auf dem installierten System hinterlassen hat...",
+    ]),
+    ("unmount", [
+        "cleanup: unmounting all filesystems on {root}...",
+        "limpieza: desmontando todos los sistemas de archivos en {root}...",
+        "Aufräumen: hänge alle Dateisysteme unter {root} aus...",
+    ]),
+    ("done", [
+        "done; you may reboot now",
+        "listo; ya puede reiniciar",
+        "fertig; Sie können jetzt neu starten",
+    ]),
+    ("done mounted", [
+        "done; the new system is left mounted on {root}",
+        "listo; el nuevo sistema queda montado en {root}",
+        "fertig; das neue System bleibt unter {root} eingehängt",
+    ]),
+    ("fstab extra", [
+        "adding extra entries to the filesystem table...",
+        "añadiendo entradas adicionales a la tabla de sistemas de archivos...",
+        "füge weitere Einträge zur Dateisystemtabelle hinzu...",
+    ]),
+    ("timezone", [
+        "setting timezone...",
+        "configurando la zona horaria...",
+        "stelle die Zeitzone ein...",
+    ]),
+    ("locales", [
+        "configuring locales on target system...",
+        "configurando los locales del sistema de destino...",
+        "konfiguriere die Locales des Zielsystems...",
+    ]),
+    ("hostname", [
+        "setting hostname...",
+        "configurando el nombre del equipo...",
+        "setze den Hostnamen...",
+    ]),
+    ("greetings", [
+        "setting up greetings...",
+        "configurando los mensajes de bienvenida...",
+        "richte die Begrüßungen ein...",
+    ]),
+    ("network", [
+        "configuring networkmanager...",
+        "configurando networkmanager...",
+        "konfiguriere networkmanager...",
+    ]),
+    ("console keymap", [
+        "setting the keymap of the console...",
+        "configurando la distribución del teclado de la consola...",
+        "setze die Tastaturbelegung der Konsole...",
+    ]),
+    ("password keymap", [
+        "checking the keymap the passwords are typed with...",
+        "comprobando la distribución del teclado con la que se escriben las contraseñas...",
+        "prüfe die Tastaturbelegung, mit der die Passwörter eingegeben werden...",
+    ]),
+    ("root password", [
+        "set password for root user (repeats until success):",
+        "establezca la contraseña del usuario root (se repite hasta que funcione):",
+        "Passwort für den root-Benutzer festlegen (wird bis zum Erfolg wiederholt):",
+    ]),
+    ("root password fallback", [
+        "set password for root user (root is locked after a few failed attempts):",
+        "establezca la contraseña del usuario root (root se bloquea tras unos intentos fallidos):",
+        "Passwort für den root-Benutzer festlegen (root wird nach einigen Fehlversuchen gesperrt):",
+    ]),
+    ("root password locked", [
+        "locking the root user...",
+        "bloqueando el usuario root...",
+        "sperre den root-Benutzer...",
+    ]),
+    ("root password hash", [
+        "setting the password of the root user...",
+        "estableciendo la contraseña del usuario root...",
+        "setze das Passwort des root-Benutzers...",
+    ]),
+    ("sudo", [
+        "making the wheel group capable of using sudo...",
+        "permitiendo que el grupo wheel use sudo...",
+        "erlaube der Gruppe wheel, sudo zu benutzen...",
+    ]),
+    ("users", [
+        "Configuring users, if any...",
+        "configurando los usuarios, si los hay...",
+        "konfiguriere die Benutzer, falls vorhanden...",
+    ]),
+    ("encrypted homes", [
+        "enabling systemd-homed for the users with encrypted home directories...",
+        "activando systemd-homed para los usuarios con directorios personales cifrados...",
+        "aktiviere systemd-homed für die Benutzer mit verschlüsselten Home-Verzeichnissen...",
+    ]),
+    ("editor", [
+        "setting the default editor...",
+        "configurando el editor predeterminado...",
+        "setze den Standard-Editor...",
+    ]),
+    ("directories", [
+        "creating directories...",
+        "creando directorios...",
+        "erstelle Verzeichnisse...",
+    ]),
+    ("snapshot", [
+        "using the mirrorlist of the Arch Linux Archive snapshot...",
+        "usando la lista de réplicas de la instantánea del Arch Linux Archive...",
+        "verwende die Spiegelliste des Arch-Linux-Archive-Schnappschusses...",
+    ]),
+    ("resolv.conf", [
+        "copying the resolv.conf of the live system, for arch-chroot...",
+        "copiando el resolv.conf del sistema en vivo, para arch-chroot...",
+        "kopiere die resolv.conf des Live-Systems für arch-chroot...",
+    ]),
+    ("restore resolv.conf", [
+        "restoring the resolv.conf of the installed system...",
+        "restaurando el resolv.conf del sistema instalado...",
+        "stelle die resolv.conf des installierten Systems wieder her...",
+    ]),
+    ("skel", [
+        "copying the files every new user starts with to /etc/skel...",
+        "copiando a /etc/skel los archivos con los que empieza cada usuario nuevo...",
+        "kopiere die Dateien, mit denen jeder neue Benutzer beginnt, nach /etc/skel...",
+    ]),
+    ("package pins", [
+        "installing the pinned versions of packages...",
+        "instalando las versiones fijadas de los paquetes...",
+        "installiere die festgelegten Paketversionen...",
+    ]),
+    ("power", [
+        "setting up the power management of laptops...",
+        "configurando la gestión de energía de los portátiles...",
+        "richte die Energieverwaltung von Laptops ein...",
+    ]),
+    ("default target", [
+        "setting the target the system boots into...",
+        "estableciendo el target con el que arranca el sistema...",
+        "setze das Target, mit dem das System startet...",
+    ]),
+    ("maintenance", [
+        "setting up the periodic maintenance of the system...",
+        "configurando el mantenimiento periódico del sistema...",
+        "richte die regelmäßige Wartung des Systems ein...",
+    ]),
+    ("hardening", [
+        "applying the hardening measures...",
+        "aplicando las medidas de endurecimiento...",
+        "wende die Härtungsmaßnahmen an...",
+    ]),
+    ("initramfs", [
+        "setting up the initramfs...",
+        "configurando el initramfs...",
+        "richte das initramfs ein...",
+    ]),
+    ("first boot", [
+        "setting up the scripts that run on the first boot...",
+        "preparando los scripts que se ejecutan en el primer arranque...",
+        "richte die Skripte ein, die beim ersten Start laufen...",
+    ]),
+    ("secondary esps", [
+        "adding the boot entries of the secondary EFI system partitions...",
+        "añadiendo las entradas de arranque de las particiones de sistema EFI secundarias...",
+        "füge die Booteinträge der sekundären EFI-Systempartitionen hinzu...",
+    ]),
+    ("bootloader", [
+        "setting up bootloader...",
+        "configurando el gestor de arranque...",
+        "richte den Bootloader ein...",
+    ]),
+    ("rebuild initramfs", [
+        "rebuilding the initramfs...",
+        "regenerando el initramfs...",
+        "erzeuge das initramfs neu...",
+    ]),
+    ("grub config", [
+        "generating the GRUB configuration...",
+        "generando la configuración de GRUB...",
+        "erzeuge die GRUB-Konfiguration...",
+    ]),
+    ("efi entries", [
+        "creating the boot entry...",
+        "creando la entrada de arranque...",
+        "erstelle den Booteintrag...",
+    ]),
+    ("esp sync", [
+        "copying the EFI system partition onto the secondary ones...",
+        "copiando la partición de sistema EFI en las secundarias...",
+        "kopiere die EFI-Systempartition auf die sekundären...",
+    ]),
+    ("finished", [
+        "the system is configured",
+        "el sistema está configurado",
+        "das System ist konfiguriert",
+    ]),
+];
+
+/// Return the status message with the given identifier, in the given language
+pub fn message(language: Language, id: &str) -> &'static str
+{
+    let index = Language::ALL.iter().position(|l| *l == language).unwrap();
+    CATALOG.iter()
+        .find(|(key, _)| *key == id)
+        .map(|(_, translations)| translations[index])
+        .unwrap_or_else(|| panic!("no status message with the identifier {:?}", id))
+}
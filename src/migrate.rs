@@ -0,0 +1,128 @@
+use serde_yaml::{Mapping, Value};
+use crate::data::CONFIG_VERSION;
+use crate::log::{Errors, refusal, refuse};
+
+/// A property that's still accepted, but that has been replaced by another one
+#[derive(Debug)]
+pub struct Deprecation
+{
+    /// The name of the old property
+    pub field: &'static str,
+    /// What should be used instead
+    pub replacement: &'static str,
+    /// The version of the configuration file format that stopped using the property
+    pub since: u32,
+}
+
+/// A property of an older version of the configuration file format, along with the function that
+/// turns it into its replacement
+struct LegacyField
+{
+    deprecation: Deprecation,
+    migrate: fn(&mut Mapping, Value) -> Result<(), Errors>,
+}
+
+/// Every property that has been replaced since jimmy started versioning its configuration files
+const LEGACY_FIELDS: &[LegacyField] = &[
+    LegacyField {
+        deprecation: Deprecation { field: "username", replacement: "users", since: 1 },
+        migrate: migrate_username,
+    },
+    LegacyField {
+        deprecation: Deprecation { field: "extra", replacement: "packages", since: 2 },
+        migrate: migrate_extra,
+    },
+    LegacyField {
+        deprecation: Deprecation { field: "region", replacement: "timezone", since: 2 },
+        migrate: migrate_region,
+    },
+    // after `region`, whose timezone already has the city in it
+    LegacyField {
+        deprecation: Deprecation { field: "city", replacement: "timezone", since: 2 },
+        migrate: migrate_city,
+    },
+];
+
+/// Turn the single `username` into an entry of the `users` list
+fn migrate_username(config: &mut Mapping, username: Value) -> Result<(), Errors>
+{
+    let users = config
+        .entry(Value::from("users"))
+        .or_insert_with(|| Value::Sequence(Vec::new()));
+    if let Value::Sequence(users) = users {
+        let mut user = Mapping::new();
+        user.insert(Value::from("name"), username);
+        users.push(Value::Mapping(user));
+    }
+    Ok(())
+}
+
+/// Rename `extra` to `packages`, which takes the same string or list of packages
+fn migrate_extra(config: &mut Mapping, extra: Value) -> Result<(), Errors>
+{
+    config.insert(Value::from("packages"), extra);
+    Ok(())
+}
+
+/// Turn the `region` into the `timezone`, along with the `city` if there's one: `Europe/London`
+fn migrate_region(config: &mut Mapping, region: Value) -> Result<(), Errors>
+{
+    let region = region.as_str()
+        .ok_or_else(|| refusal!("`region` must be a string, not {:?}", region))?
+        .trim_matches('/')
+        .to_string();
+    if region.is_empty() {
+        return Ok(());
+    }
+    let timezone = match config.get(&Value::from("city")).map(|c| c.as_str().map(|c| c.trim_matches('/'))) {
+        Some(Some("")) | None => region,
+        Some(Some(city)) => format!("{}/{}", region, city),
+        Some(None) => refuse!("`city` must be a string, not {:?}", config[&Value::from("city")]),
+    };
+    config.insert(Value::from("timezone"), Value::from(timezone));
+    Ok(())
+}
+
+/// Check that the `city` went into the `timezone` along with a `region`
+fn migrate_city(config: &mut Mapping, city: Value) -> Result<(), Errors>
+{
+    let city = city.as_str().map(|c| c.trim_matches('/')).filter(|c| !c.is_empty());
+    if let Some(city) = city.filter(|_| !config.contains_key(&Value::from("timezone"))) {
+        refuse!("city '{}' is given without a region; replace it with `timezone`, e.g. `timezone: Europe/London`", city)
+    }
+    Ok(())
+}
+
+/// Rewrite a parsed configuration file so that it follows the current version of the format,
+/// returning the deprecated properties that had to be changed. Refuse if the file has a version
+/// this version of jimmy doesn't understand
+pub fn migrate(config: &mut Value) -> Result<Vec<&'static Deprecation>, Errors>
+{
+    let config = match config {
+        Value::Mapping(m) => m,
+        _ => refuse!("the configuration file must be a mapping of properties"),
+    };
+    let version = match config.get(&Value::from("version")) {
+        None => 0,
+        Some(v) => v.as_u64()
+            .ok_or_else(|| refusal!("`version` must be a number, not {:?}", v))? as u32,
+    };
+    if version > CONFIG_VERSION {
+        refuse!("the configuration file is for version {} of the format, but this version of jimmy only understands up to version {}; upgrade jimmy",
+            version, CONFIG_VERSION);
+    }
+
+    let mut used = Vec::new();
+    for legacy in LEGACY_FIELDS {
+        // files written for newer versions of the format can't use older properties
+        if version >= legacy.deprecation.since {
+            continue;
+        }
+        if let Some(value) = config.remove(&Value::from(legacy.deprecation.field)) {
+            (legacy.migrate)(config, value)?;
+            used.push(&legacy.deprecation);
+        }
+    }
+    config.insert(Value::from("version"), Value::from(CONFIG_VERSION));
+    Ok(used)
+}
@@ -0,0 +1,206 @@
+/// Quote a word so that the shell passes it to a command as-is. Words made only of characters the
+/// shell doesn't treat specially are left alone, for readability
+pub fn shell_quote(word: &str) -> String
+{
+    let is_safe = |c: char| c.is_ascii_alphanumeric() || "-_.,:/=+@%".contains(c);
+    if !word.is_empty() && word.chars().all(is_safe) {
+        word.to_string()
+    } else {
+        format!("'{}'", word.replace('\'', "'\\''"))
+    }
+}
+
+/// Return a command that writes `contents` to the file at `path`, or appends them to it if
+/// `append` is true. A quoted heredoc is used, so the shell doesn't expand anything in `contents`
+pub fn heredoc_cmd(path: &str, contents: &str, append: bool) -> String
+{
+    // the delimiter can't appear on a line by itself, or it would end the heredoc early
+    let mut delimiter = "END_OF_FILE".to_string();
+    while contents.lines().any(|l| l == delimiter) {
+        delimiter.push('_');
+    }
+    // the heredoc always ends with a newline
+    let contents = contents.strip_suffix('\n').unwrap_or(contents);
+    format!(
+        "cat <<'{}' {}{}\n{}\n{}",
+        delimiter,
+        if append { ">>" } else { ">" },
+        path,
+        contents,
+        delimiter,
+    )
+}
+
+/// A command built from its arguments, which are only quoted once it's rendered. The arguments are
+/// kept in groups, such as an option along with its value, that stay on the same line when the
+/// command is wrapped
+#[derive(Debug, Clone)]
+pub struct ShellCmd
+{
+    groups: Vec<Vec<String>>,
+}
+
+impl ShellCmd
+{
+    pub fn new(program: &str) -> Self
+    {
+        Self { groups: vec![vec![shell_quote(program)]] }
+    }
+
+    /// Add an argument, quoted as needed
+    pub fn arg(mut self, arg: &str) -> Self
+    {
+        self.groups.push(vec![shell_quote(arg)]);
+        self
+    }
+
+    /// Add every one of the arguments, quoted as needed
+    pub fn args<S: AsRef<str>>(mut self, args: impl IntoIterator<Item = S>) -> Self
+    {
+        self.groups.extend(args.into_iter().map(|a| vec![shell_quote(a.as_ref())]));
+        self
+    }
+
+    /// Add an option and its value, quoted as needed, which are never wrapped apart
+    pub fn opt(mut self, option: &str, value: &str) -> Self
+    {
+        self.groups.push(vec![shell_quote(option), shell_quote(value)]);
+        self
+    }
+
+    /// Add shell code that's already written as the shell should read it, such as the expansion of
+    /// a variable
+    pub fn raw(mut self, code: &str) -> Self
+    {
+        self.groups.push(vec![code.to_string()]);
+        self
+    }
+
+    /// Return the command on a single line
+    pub fn line(&self) -> String
+    {
+        self.groups.iter().map(|g| g.join(" ")).collect::<Vec<String>>().join(" ")
+    }
+
+    /// Return the command, wrapped between groups of arguments with `\` so that its lines fit in
+    /// `width` columns, or on a single line if `width` is 0. The command starts at `column`, and
+    /// its other lines are indented one level further than the code around it is; a group that
+    /// doesn't fit on a line by itself is given one anyway, since quoted values are never wrapped
+    pub fn render(&self, width: usize, column: usize) -> String
+    {
+        let line = self.line();
+        if width == 0 || column + line.len() <= width {
+            return line;
+        }
+        let indent = " ".repeat(column / 4 * 4 + 4);
+        let mut lines = vec![self.groups[0].join(" ")];
+        let mut used = column + lines[0].len();
+        for group in &self.groups[1..] {
+            let group = group.join(" ");
+            // the space and the backslash of the continuation take two more columns
+            if used + 1 + group.len() + 2 > width {
+                lines.push(format!("{}{}", indent, group));
+                used = indent.len() + group.len();
+            } else {
+                let last = lines.last_mut().unwrap();
+                last.push(' ');
+                last.push_str(&group);
+                used += 1 + group.len();
+            }
+        }
+        lines.join(" \\\n")
+    }
+}
+
+/// Indent every line of `code` that isn't empty by `levels` levels, for code that goes inside a
+/// block. It can't hold here-documents, whose lines would be changed along with the rest
+pub fn indent(code: &str, levels: usize) -> String
+{
+    let indent = " ".repeat(levels * 4);
+    code.lines()
+        .map(|l| if l.is_empty() { l.to_string() } else { format!("{}{}", indent, l) })
+        .collect::<Vec<String>>()
+        .join("\n")
+}
+
+/// Return the code that stops the script with `message` on stderr if `condition`, a command,
+/// succeeds
+pub fn fail_if(condition: &str, message: &str) -> String
+{
+    format!("if {}; then\n    echo {} >&2\n    exit 1\nfi", condition, shell_quote(message))
+}
+
+/// Return the code that stops the script with `message` on stderr unless `cmd` succeeds. The `||`
+/// goes on a line of its own, since `cmd` is usually a long one
+pub fn or_fail(cmd: &str, message: &str) -> String
+{
+    format!("{} \\\n    || {{ echo {} >&2; exit 1; }}", cmd, shell_quote(message))
+}
+
+/// Return the code that stops the script with `message` on stderr if `program` can't be found
+pub fn require_command(program: &str, message: &str) -> String
+{
+    fail_if(&format!("! command -v {} >/dev/null", shell_quote(program)), message)
+}
+
+/// Return the code that asks `question` and stops the script with `refused` on stderr unless it's
+/// answered yes, which is the default. `question` is a word of shell code, so that it can expand
+/// variables
+pub fn confirm(question: &str, refused: &str) -> String
+{
+    format!(r#"printf '%s [Y/n] ' {}
+read -r jimmy_answer
+case "$jimmy_answer" in
+    [nN]*)
+        echo {} >&2
+        exit 1
+        ;;
+esac"#,
+        question,
+        shell_quote(refused),
+    )
+}
+
+/// Return the loop that runs `cmd` until it succeeds, such as a prompt for a passphrase that's
+/// asked again when it's mistyped
+pub fn until_success(cmd: &str) -> String
+{
+    format!("while true; do if {}; then break; fi; done", cmd)
+}
+
+/// Return the loop that runs `cmd` until it succeeds, at most `attempts` times; `give_up` runs
+/// after the last one failed, and the script goes on after it
+pub fn retry_at_most(cmd: &str, attempts: u32, give_up: &str) -> String
+{
+    format!(r#"jimmy_attempt=0
+until {}; do
+    jimmy_attempt=$((jimmy_attempt + 1))
+    if [ "$jimmy_attempt" -ge {} ]; then
+{}
+        break
+    fi
+done"#,
+        cmd,
+        attempts,
+        indent(give_up, 2),
+    )
+}
+
+/// Return a command that appends `contents` to the file at `path`, unless their first line is
+/// already one of its lines, so that running it again doesn't add them twice
+pub fn append_once(path: &str, contents: &str) -> String
+{
+    let first = contents.lines().next().unwrap_or_default();
+    format!("grep -qxF {} {} || {}", shell_quote(first), path, heredoc_cmd(path, contents, true))
+}
+
+/// Return the pipeline that gives `cmd` the `lines` on its stdin, one after the other, such as the
+/// answers to its prompts. They're written by printf, since only some shells' echo reads `\n` as
+/// a new line, and in double quotes, so that they can expand variables; that's why they can't
+/// hold any of `"`, `\`, `%` and `` ` ``
+pub fn feed_lines<S: AsRef<str>>(lines: &[S], cmd: &str) -> String
+{
+    let lines: Vec<&str> = lines.iter().map(|l| l.as_ref()).collect();
+    assert!(!lines.iter().any(|l| l.contains(['"', '\\', '%', '`'])), "cannot feed {:?} through printf", lines);
+    format!("printf \"{}\\n\" | {}", lines.join("\\n"), cmd)
+}
@@ -0,0 +1,100 @@
+use serde_yaml::{Mapping, Value};
+use crate::log::{Errors, refusal, refuse, warning};
+
+/// Expand the references inside every string of a parsed configuration file:
+/// - `${env:VAR}` becomes the value of the environment variable `VAR`
+/// - `${property}` becomes the value of a top-level property, such as `${hostname}`
+/// - `$${` becomes a literal `${`
+///
+/// Refuse references to unknown properties or, unless `allow_missing_env` is set, to unset
+/// environment variables; with it, they expand to nothing
+pub fn expand(config: &mut Value, allow_missing_env: bool) -> Result<(), Errors>
+{
+    let properties = match config {
+        Value::Mapping(m) => m.clone(),
+        _ => return Ok(()),
+    };
+    expand_value(config, &properties, allow_missing_env)
+}
+
+/// Expand the references inside every string of a value, recursively
+fn expand_value(value: &mut Value, properties: &Mapping, allow_missing_env: bool) -> Result<(), Errors>
+{
+    match value {
+        Value::String(s) => *s = expand_str(s, properties, allow_missing_env, &mut Vec::new())?,
+        Value::Sequence(seq) => seq
+            .iter_mut()
+            .try_for_each(|v| expand_value(v, properties, allow_missing_env))?,
+        Value::Mapping(m) => m
+            .iter_mut()
+            .try_for_each(|(_, v)| expand_value(v, properties, allow_missing_env))?,
+        _ => (),
+    }
+    Ok(())
+}
+
+/// Expand the references inside a single string; `resolving` holds the properties whose values
+/// are being expanded, to catch properties that refer to themselves
+fn expand_str(s: &str, properties: &Mapping, allow_missing_env: bool, resolving: &mut Vec<String>) -> Result<String, Errors>
+{
+    let mut expanded = String::new();
+    let mut rest = s;
+    while let Some(start) = rest.find('$') {
+        expanded.push_str(&rest[..start]);
+        rest = &rest[start..];
+        if let Some(escaped) = rest.strip_prefix("$${") {
+            expanded.push_str("${");
+            rest = escaped;
+            continue;
+        }
+        let reference = match rest.strip_prefix("${") {
+            Some(r) => r,
+            None => {
+                expanded.push('$');
+                rest = &rest[1..];
+                continue;
+            },
+        };
+        let end = reference
+            .find('}')
+            .ok_or_else(|| refusal!("unterminated reference in {:?}; close it with '}}'", s))?;
+        expanded.push_str(&resolve(&reference[..end], properties, allow_missing_env, resolving)?);
+        rest = &reference[end + 1..];
+    }
+    expanded.push_str(rest);
+    Ok(expanded)
+}
+
+/// Return the value a reference (the part between `${` and `}`) stands for
+fn resolve(name: &str, properties: &Mapping, allow_missing_env: bool, resolving: &mut Vec<String>) -> Result<String, Errors>
+{
+    if let Some(var) = name.strip_prefix("env:") {
+        return match std::env::var(var) {
+            Ok(value) => Ok(value),
+            Err(_) if allow_missing_env => {
+                warning!("environment variable '{}' is not set; using an empty string", var);
+                Ok(String::new())
+            },
+            Err(_) => refuse!("environment variable '{}' is not set; set it, or pass --allow-missing-env to use an empty string",
+                var),
+        };
+    }
+
+    let value = properties
+        .get(&Value::from(name))
+        .ok_or_else(|| refusal!("unknown variable '${{{}}}'; use '${{env:{}}}' for environment variables", name, name))?;
+    match value {
+        Value::String(s) => {
+            if resolving.iter().any(|r| r == name) {
+                refuse!("variable '${{{}}}' refers to itself: {} -> {}", name, resolving.join(" -> "), name);
+            }
+            resolving.push(name.to_string());
+            let value = expand_str(s, properties, allow_missing_env, resolving)?;
+            resolving.pop();
+            Ok(value)
+        },
+        Value::Number(n) => Ok(n.to_string()),
+        Value::Bool(b) => Ok(b.to_string()),
+        _ => refuse!("variable '${{{}}}' refers to a property that isn't a single value", name),
+    }
+}
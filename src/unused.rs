@@ -0,0 +1,70 @@
+use serde::Serialize;
+use crate::data::{InstallOptions, PARTITION_TYPES};
+
+/// What the installation sets up, but that nothing uses once it's done. It's found by looking at
+/// the whole plan at once, since whether something is used depends on the rest of it
+#[derive(Debug, Default, Serialize)]
+pub struct Unused
+{
+    /// The partitions that are formatted, but that are neither mounted, nor swap, nor marked
+    /// `unmounted: true`, nor kept in sync with or a member of another partition
+    pub partitions: Vec<String>,
+    /// The disks declared under `disks` that no partition is on
+    pub disks: Vec<String>,
+    /// The partitions of the EFI system type, which the firmware may boot from, that the
+    /// bootloader isn't installed on
+    pub esps: Vec<String>,
+}
+
+impl Unused
+{
+    /// Return whether nothing is unused
+    pub fn is_empty(&self) -> bool
+    {
+        self.partitions.is_empty() && self.disks.is_empty() && self.esps.is_empty()
+    }
+
+    /// Return the groups of what's unused, with what they're about, leaving out the empty ones
+    pub fn groups(&self) -> Vec<(&'static str, &[String])>
+    {
+        [
+            ("partitions", self.partitions.as_slice()),
+            ("disks", self.disks.as_slice()),
+            ("EFI system partitions", self.esps.as_slice()),
+        ].into_iter().filter(|(_, found)| !found.is_empty()).collect()
+    }
+
+    /// Return what's unused, one group after the other
+    pub fn messages(&self) -> impl Iterator<Item = &String>
+    {
+        self.partitions.iter().chain(&self.disks).chain(&self.esps)
+    }
+}
+
+/// Return what the installation sets up but doesn't use
+pub fn unused(options: &InstallOptions) -> Unused
+{
+    let partitions = &options.partitions;
+    let esp_guid = PARTITION_TYPES.iter().find(|(name, _)| *name == "esp").map(|(_, guid)| *guid).unwrap();
+
+    Unused {
+        partitions: partitions.iter()
+            .filter(|p| p.mount.is_empty() && p.format != "swap" && !p.unmounted && !p.esp && p.member_of.is_none())
+            .map(|p| format!("{} partition on {} has no mount point; add `mount:`, or `unmounted: true` if it's meant to be left unmounted",
+                p.format, p.disk))
+            .collect(),
+        disks: options.disks.keys()
+            .filter(|disk| !partitions.iter().any(|p| &p.disk == *disk))
+            .map(|disk| format!("disk {} is declared under `disks`, but there are no partitions on it; remove it, or fix the `disk` of its partitions", disk))
+            .collect(),
+        // the primary EFI system partition is the one mounted at /efi or /boot, and when it's the
+        // former, the partition mounted at /boot holds the kernels; the secondary ones are marked
+        esps: partitions.iter()
+            .filter(|p| [esp_guid, "uefi"].contains(&p.fdisk_partition_type()))
+            .filter(|p| !p.esp && !p.reuse && p.mount != "/boot" && p.mount != "/efi")
+            .map(|p| format!("the {} partition {} has the type of an EFI system partition, which the firmware may boot from, but the bootloader isn't installed on it; \
+                give it another `type_guid`, or mark it `esp: true` without a mount point to keep it in sync with the primary one",
+                p.format, if p.mount.is_empty() { format!("on {}", p.disk) } else { format!("mounted at {}", p.mount) }))
+            .collect(),
+    }
+}
@@ -0,0 +1,58 @@
+//! Checks `activate_swap: false`: the swap created but not activated during the installation, and
+//! the entry of the filesystem table jimmy appends for it, since genfstab only sees active swap
+
+mod common;
+
+/// Generate the script from the sample configuration file, with a swap partition with the given
+/// lines added after its boot partition and `extra_lines` appended
+fn generated(swap: &str, extra_lines: &str) -> String
+{
+    let partition = format!("    size: 500M\n  - swap:\n    format: swap\n    size: 4G\n{}", swap);
+    common::script(common::generate(&["--file"], &[("    size: 500M\n", &partition)], extra_lines))
+}
+
+/// Return the lines of the script that append an entry for /dev/sda2 to the filesystem table
+fn appended(script: &str) -> Vec<&str>
+{
+    script.lines().filter(|l| l.contains("blkid -s UUID -o value /dev/sda2")).collect()
+}
+
+const ENTRY: &str = "echo \"UUID=$(blkid -s UUID -o value /dev/sda2)\tnone\tswap\tdefaults\t0 0\" >> /mnt/etc/fstab";
+
+#[test]
+fn not_activated()
+{
+    let script = generated("    activate_swap: false\n", "");
+    assert!(script.contains("\nmkswap /dev/sda2 || exit 1\n"));
+    assert!(!script.contains("swapon"));
+    assert_eq!(appended(&script), [ENTRY]);
+
+    // the setting of the whole file, and the one of the partition that takes precedence over it
+    assert_eq!(appended(&generated("", "activate_swap: false\n")), [ENTRY]);
+    let script = generated("    activate_swap: true\n", "activate_swap: false\n");
+    assert!(script.contains("swapon /dev/sda2"));
+    assert!(appended(&script).is_empty());
+}
+
+#[test]
+fn activated()
+{
+    let script = generated("", "");
+    assert!(script.contains("\ngrep -q \"^$(readlink -f /dev/sda2) \" /proc/swaps || swapon /dev/sda2\n"));
+    assert!(appended(&script).is_empty());
+}
+
+#[test]
+fn entry()
+{
+    // the priority goes in the options, and the table is the one of the new system wherever it's
+    // mounted
+    let script = generated("    activate_swap: false\n    swap_priority: 10\n", "mount_root: /target\n");
+    assert_eq!(appended(&script),
+        ["echo \"UUID=$(blkid -s UUID -o value /dev/sda2)\tnone\tswap\tdefaults,pri=10\t0 0\" >> /target/etc/fstab"]);
+
+    // the UUID is the one blkid finds when the script runs
+    let code = format!("{} && cat \"$DIR/fstab\"", ENTRY.replace("/mnt/etc/", "$DIR/"));
+    assert_eq!(common::sh(&code, &[("blkid", "[ \"$*\" = '-s UUID -o value /dev/sda2' ] && echo 5678")], ""),
+        (true, "UUID=5678\tnone\tswap\tdefaults\t0 0\n".to_string(), String::new()));
+}
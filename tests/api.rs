@@ -0,0 +1,153 @@
+//! Checks `jimmy api`: whatever it's given on stdin, it writes a single JSON document of the
+//! documented shape on stdout, with the plan of a valid configuration, or the errors of one that
+//! isn't, along with the warnings and the properties they're about
+
+use std::io::Write;
+use std::process::{Command, Stdio};
+
+mod common;
+
+/// Run `jimmy api` with the given subcommand and input, and return its exit status along with the
+/// document it wrote, checking that there's nothing else on stdout
+fn api(command: &str, input: &str) -> (bool, serde_json::Value)
+{
+    let mut child = Command::new(env!("CARGO_BIN_EXE_jimmy"))
+        .args(["api", command])
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .unwrap();
+    child.stdin.take().unwrap().write_all(input.as_bytes()).unwrap();
+    let output = child.wait_with_output().unwrap();
+    let stdout = String::from_utf8(output.stdout).unwrap();
+    let response: serde_json::Value = serde_json::from_str(&stdout).unwrap_or_else(|e| panic!("{}: {:?}", e, stdout));
+    assert_eq!(response["api_version"], 1);
+    let mut keys: Vec<&String> = response.as_object().unwrap().keys().collect();
+    keys.sort();
+    let expected: &[&str] = if command == "plan" {
+        &["api_version", "errors", "plan", "valid", "warnings"]
+    } else {
+        &["api_version", "errors", "valid", "warnings"]
+    };
+    assert_eq!(keys, expected);
+    for problem in response["warnings"].as_array().unwrap().iter().chain(response["errors"].as_array().unwrap()) {
+        assert!(problem["message"].is_string());
+        assert!(problem["path"].is_string() || problem["path"].is_null());
+        assert_eq!(problem.as_object().unwrap().len(), 2);
+    }
+    assert_eq!(response["valid"], output.status.success());
+    (output.status.success(), response)
+}
+
+/// Return the sample configuration file as JSON
+fn sample() -> serde_json::Value
+{
+    serde_yaml::from_str(&common::sample()).unwrap()
+}
+
+#[test]
+fn valid()
+{
+    let (valid, response) = api("plan", &sample().to_string());
+    assert!(valid);
+    assert_eq!(response["errors"], serde_json::json!([]));
+    let plan = &response["plan"];
+    assert_eq!(plan["hostname"], "archlinux");
+    assert_eq!(plan["keymap"], "us");
+    assert_eq!(plan["disks"], serde_json::json!(["/dev/sda"]));
+    assert_eq!(plan["partitions"][1]["mount"], "/");
+    assert!(plan["steps"].as_array().unwrap().contains(&serde_json::json!("pacstrap")));
+    assert!(plan["packages"].as_array().unwrap().contains(&serde_json::json!("vim")));
+
+    let (valid, response) = api("validate", &sample().to_string());
+    assert!(valid);
+    assert_eq!(response["warnings"], serde_json::json!([]));
+}
+
+#[test]
+fn errors()
+{
+    let mut config = sample();
+    config["partitions"][1]["raid_profile"] = serde_json::json!("raid1");
+    let (valid, response) = api("plan", &config.to_string());
+    assert!(!valid);
+    assert_eq!(response["plan"], serde_json::Value::Null);
+    assert_eq!(response["errors"], serde_json::json!([{
+        "message": "raid_profile is only used with `format: btrfs`, not 'ext4'",
+        "path": "partitions[1].raid_profile",
+    }]));
+
+    let mut config = sample();
+    config["cleanup"] = serde_json::json!("everything");
+    let (_, response) = api("validate", &config.to_string());
+    assert_eq!(response["errors"][0]["path"], "cleanup");
+
+    let mut config = sample();
+    config["disks"] = serde_json::json!({ "/dev/sda": { "min_remaining": "1G" } });
+    let (_, response) = api("validate", &config.to_string());
+    assert_eq!(response["errors"][0]["path"], "disks[\"/dev/sda\"]");
+
+    // an error that isn't about a single property has no path
+    let mut config = sample();
+    config.as_object_mut().unwrap().remove("bootloader");
+    let (_, response) = api("validate", &config.to_string());
+    assert_eq!(response["errors"], serde_json::json!([{ "message": "no bootloader specified", "path": null }]));
+}
+
+#[test]
+fn several_errors()
+{
+    // the properties that don't stop jimmy from reading the rest are all reported, up to the first
+    // one that does
+    let mut config = sample();
+    config["hardware_clock"] = serde_json::json!("sundial");
+    config["power"] = serde_json::json!("tlpp");
+    config["cleanup"] = serde_json::json!("everything");
+    config["root_password_policy"] = serde_json::json!("ask");
+    let (valid, response) = api("validate", &config.to_string());
+    assert!(!valid);
+    let paths: Vec<&serde_json::Value> = response["errors"].as_array().unwrap().iter().map(|e| &e["path"]).collect();
+    assert_eq!(paths, ["hardware_clock", "power", "cleanup", "root_password_policy"]);
+    assert_eq!(response["errors"][1]["message"], "invalid power: \"tlpp\" (expected one of: tlp, power-profiles-daemon, none)");
+
+    // and they're all printed by the other subcommands
+    let yaml = common::config(&[], "power: tlpp\ncleanup: everything\n");
+    let stderr = common::refusal(common::jimmy(&["validate"], &yaml));
+    assert!(stderr.contains("error: invalid power: \"tlpp\""), "{}", stderr);
+    assert!(stderr.contains("error: invalid cleanup: \"everything\""), "{}", stderr);
+}
+
+#[test]
+fn warnings()
+{
+    let mut config = sample();
+    config["partitions"][1]["format"] = serde_json::json!("");
+    config["version"] = serde_json::json!(0);
+    config["username"] = serde_json::json!("archie");
+    let (valid, response) = api("validate", &config.to_string());
+    assert!(valid);
+    let warnings = response["warnings"].as_array().unwrap();
+    assert!(warnings.contains(&serde_json::json!({
+        "message": "partition format not specified; defaulting to 'ext4'",
+        "path": "partitions[1]",
+    })));
+    assert!(warnings.contains(&serde_json::json!({
+        "message": "'username' is deprecated since version 1 of the format; use 'users' instead",
+        "path": null,
+    })));
+}
+
+#[test]
+fn malformed()
+{
+    for input in ["", "{", "[1, 2]", "\"partitions\"", "{\"partitions\": 3}", "{\"hostname\": \"${nothing}\"}"] {
+        for command in ["validate", "plan"] {
+            let (valid, response) = api(command, input);
+            assert!(!valid, "{:?}", input);
+            assert_eq!(response["errors"].as_array().unwrap().len(), 1, "{:?}", input);
+        }
+    }
+    let (_, response) = api("validate", "{");
+    assert!(response["errors"][0]["message"].as_str().unwrap().starts_with("the input isn't valid JSON: "));
+}
@@ -0,0 +1,73 @@
+//! Checks the files the commands of each partition use, on disks whose partitions are named in
+//! different ways, and for partitions reached through the device mapper
+
+mod common;
+
+/// Return the script generated from the sample configuration file, with both partitions on `disk`,
+/// systemd-boot, which is given the root partition, and the given lines added to the root
+/// partition, along with what `--verbose` says about it
+fn generate(disk: &str, root_lines: &str) -> (String, String)
+{
+    let output = common::generate(&["--verbose", "--file"], &[
+        ("\ndisk: /dev/sda\n", format!("\ndisk: {}\n", disk).as_str()),
+        ("    mount: /\n", format!("    mount: /\n{}", root_lines).as_str()),
+        ("bootloader: grub\n", "bootloader: systemd-boot\n"),
+    ], "");
+    let stderr = String::from_utf8(output.stderr.clone()).unwrap();
+    (common::script(output), stderr)
+}
+
+/// Return the lines of the script that start with `prefix`
+fn lines<'a>(script: &'a str, prefix: &str) -> Vec<&'a str>
+{
+    script.lines().filter(|l| l.starts_with(prefix)).collect()
+}
+
+#[test]
+fn plain_disk()
+{
+    let (script, stderr) = generate("/dev/sda", "");
+    assert_eq!(lines(&script, "mkfs."), ["mkfs.fat -F 32 /dev/sda1 || exit 1", "mkfs.ext4 /dev/sda2 || exit 1"]);
+    assert!(script.contains("\nmountpoint -q /mnt/ || { mkdir -p /mnt/ && mount /dev/sda2 /mnt/ || exit 1; }\nmountpoint -q /mnt/boot || { mkdir -p /mnt/boot && mount /dev/sda1 /mnt/boot || exit 1; }\n"));
+    assert!(script.contains("\noptions root=/dev/sda2 rw"));
+    assert!(stderr.contains("    /dev/sda2: ext4, rest of the disk, mounted at /\n"));
+}
+
+#[test]
+fn nvme_disk()
+{
+    let (script, stderr) = generate("/dev/nvme0n1", "");
+    assert_eq!(lines(&script, "mkfs."), ["mkfs.fat -F 32 /dev/nvme0n1p1 || exit 1", "mkfs.ext4 /dev/nvme0n1p2 || exit 1"]);
+    assert!(script.contains("\nmountpoint -q /mnt/ || { mkdir -p /mnt/ && mount /dev/nvme0n1p2 /mnt/ || exit 1; }\n"));
+    assert!(stderr.contains("    /dev/nvme0n1p1: fat32, 500M, mounted at /boot\n"));
+}
+
+#[test]
+fn software_raid()
+{
+    let (script, _) = generate("/dev/md0", "");
+    assert_eq!(lines(&script, "mkfs."), ["mkfs.fat -F 32 /dev/md0p1 || exit 1", "mkfs.ext4 /dev/md0p2 || exit 1"]);
+    assert!(script.contains("| fdisk /dev/md0 "));
+}
+
+#[test]
+fn device_mapper()
+{
+    let (script, stderr) = generate("/dev/nvme0n1", "    encryption: {}\n");
+    // the partition is encrypted, and the filesystem goes on the device mapper entry
+    assert!(script.contains("cryptsetup luksFormat --type luks2 --batch-mode --verify-passphrase /dev/nvme0n1p2;"));
+    assert!(script.contains("cryptsetup open /dev/nvme0n1p2 cryptroot;"));
+    assert_eq!(lines(&script, "mkfs.ext4"), ["mkfs.ext4 /dev/mapper/cryptroot || exit 1"]);
+    assert!(script.contains("\nmountpoint -q /mnt/ || { mkdir -p /mnt/ && mount /dev/mapper/cryptroot /mnt/ || exit 1; }\n"));
+    // the partition itself is what's described
+    assert!(stderr.contains("    /dev/nvme0n1p2: ext4, rest of the disk, mounted at /\n"));
+}
+
+#[test]
+fn stable_identifier()
+{
+    let (script, _) = generate("by-id:nvme-DISK", "");
+    assert_eq!(lines(&script, "mkfs."), ["mkfs.fat -F 32 ${JIMMY_DISK_by_id_nvme_DISK_PART}1 || exit 1", "mkfs.ext4 ${JIMMY_DISK_by_id_nvme_DISK_PART}2 || exit 1"]);
+    // the installed system keeps using the stable identifier
+    assert!(script.contains("\noptions root=/dev/disk/by-id/nvme-DISK-part2 rw"));
+}
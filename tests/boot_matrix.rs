@@ -0,0 +1,116 @@
+//! Checks the combinations of bootloaders and filesystems that are known not to boot, which are
+//! refused, or to boot unreliably, which are warned about and refused with `strict: true`, along
+//! with combinations next to them that boot
+
+use std::process::Output;
+
+mod common;
+
+/// Generate the script from the sample configuration file, with the given lines appended
+fn generate(extra_lines: &str) -> Output
+{
+    common::generate(&["--file"], &[], extra_lines)
+}
+
+/// An EFI system partition, mounted at `/boot` unless the kernels go on another partition
+fn esp(mount: &str) -> String
+{
+    format!("partitions:\n  - esp:\n    format: fat32\n    size: 500M\n    mount: {}\n", mount)
+}
+
+/// A partition holding the kernels, mounted at `/boot`
+fn boot(format: &str, encrypted: bool) -> String
+{
+    let encryption = if encrypted { "    encryption:\n      passphrase: prompt\n" } else { "" };
+    format!("  - boot:\n    format: {}\n    size: 1G\n    mount: /boot\n{}", format, encryption)
+}
+
+/// The root partition, with `extra` properties
+fn root(format: &str, extra: &str) -> String
+{
+    format!("  - root:\n    format: {}\n    mount: /\n{}", format, extra)
+}
+
+/// A btrfs root filesystem spread over two disks
+const RAID1: &str = "    disk: /dev/sda\n    raid_profile: raid1\n    members:\n      - disk: /dev/sdb\n";
+
+/// Busybox hooks, without the `btrfs` hook
+const BUSYBOX_HOOKS: &str = "mkinitcpio_hooks: [ base, udev, autodetect, modconf, block, filesystems, fsck ]\n";
+
+#[test]
+fn broken()
+{
+    for bootloader in ["grub", "systemd-boot", "efistub"] {
+        for format in ["fat32", "ntfs", "exfat"] {
+            let lines = format!("bootloader: {}\n{}{}", bootloader, esp("/boot"), root(format, ""));
+            let output = generate(&lines);
+            assert!(!output.status.success(), "{}", lines);
+            assert!(String::from_utf8_lossy(&output.stderr).contains(&format!(
+                "`bootloader: {}` can't boot this system: the root filesystem would be {}, which has no owners, permissions or symbolic links, \
+                so the base system can't run from it; format the partition mounted at / as ext4 or btrfs", bootloader, format)),
+                "{}", String::from_utf8_lossy(&output.stderr));
+        }
+    }
+
+    let lines = format!("{}{}{}", esp("/efi"), boot("ext4", true), root("ext4", "    encryption:\n      passphrase: prompt\n"));
+    let output = generate(&lines);
+    assert!(!output.status.success());
+    assert!(String::from_utf8_lossy(&output.stderr).contains("`bootloader: grub` can't boot this system: the kernels would be on the encrypted \
+        partition mounted at /boot, and GRUB can't unlock the LUKS2 containers cryptsetup creates; put /boot on an unencrypted ext4 or fat32 partition"));
+    // the same, with /boot left unencrypted
+    assert!(generate(&format!("{}{}{}", esp("/efi"), boot("ext4", false), root("ext4", "    encryption:\n      passphrase: prompt\n")))
+        .status.success());
+
+    let output = generate(&format!("bootloader: systemd-boot\n{}{}{}", esp("/boot"), root("btrfs", RAID1), BUSYBOX_HOOKS));
+    assert!(!output.status.success());
+    assert!(String::from_utf8_lossy(&output.stderr).contains("the root filesystem is spread over several devices, which an initramfs started by \
+        busybox only finds all of with the 'btrfs' hook; add it to mkinitcpio_hooks, after 'udev'"));
+    let hooks = BUSYBOX_HOOKS.replace("udev,", "udev, btrfs,");
+    assert!(generate(&format!("bootloader: systemd-boot\n{}{}{}", esp("/boot"), root("btrfs", RAID1), hooks)).status.success());
+}
+
+#[test]
+fn risky()
+{
+    for (lines, message) in [
+        (
+            format!("{}{}", esp("/efi"), root("btrfs", RAID1)),
+            "`bootloader: grub` may not boot this system reliably: GRUB would read the kernels from the btrfs filesystem mounted at /, which is \
+            spread over several devices, and can't once one of them is missing; put /boot on a partition of its own, such as the EFI system partition",
+        ),
+        (
+            format!("{}{}{}", esp("/efi"), boot("ntfs", false), root("ext4", "")),
+            "`bootloader: grub` may not boot this system reliably: the kernels would be on the ntfs partition mounted at /boot, which is meant for \
+            sharing files with Windows, and which GRUB and the updates of the kernel are hardly ever used with; format the partition mounted at /boot as ext4 or fat32",
+        ),
+        (
+            format!("{}{}{}", esp("/efi"), boot("exfat", false), root("ext4", "")),
+            "`bootloader: grub` may not boot this system reliably: the kernels would be on the exfat partition mounted at /boot",
+        ),
+    ] {
+        let output = generate(&lines);
+        assert!(output.status.success(), "{}: {}", lines, String::from_utf8_lossy(&output.stderr));
+        assert!(String::from_utf8_lossy(&output.stderr).contains(&format!("warning: {}", message)), "{}", String::from_utf8_lossy(&output.stderr));
+
+        let output = generate(&format!("strict: true\n{}", lines));
+        assert!(!output.status.success(), "{}", lines);
+        assert!(String::from_utf8_lossy(&output.stderr).contains(message));
+    }
+}
+
+#[test]
+fn boots()
+{
+    for lines in [
+        // the kernels are on the EFI system partition, which is on a single disk
+        format!("{}{}", esp("/boot"), root("btrfs", RAID1)),
+        format!("bootloader: systemd-boot\n{}{}", esp("/boot"), root("btrfs", RAID1)),
+        format!("{}{}{}", esp("/efi"), boot("ext4", false), root("btrfs", "")),
+        format!("{}{}", esp("/efi"), root("btrfs", "")),
+        format!("bootloader: efistub\n{}{}", esp("/boot"), root("ext4", "    encryption:\n      passphrase: prompt\n")),
+    ] {
+        let output = generate(&lines);
+        assert!(output.status.success(), "{}: {}", lines, String::from_utf8_lossy(&output.stderr));
+        assert!(!String::from_utf8_lossy(&output.stderr).contains("boot this system"), "{}", String::from_utf8_lossy(&output.stderr));
+    }
+}
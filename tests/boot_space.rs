@@ -0,0 +1,114 @@
+//! Checks the room the kernels need on the partition mounted at /boot, over the kernels, the
+//! bootloaders and the sizes of the partition, and the bootloaders that can't do without one
+
+mod common;
+
+/// What jimmy makes of a configuration file
+#[derive(Debug, PartialEq)]
+enum Outcome
+{
+    Fine,
+    Warned,
+    Refused,
+}
+
+/// Run jimmy on the sample configuration file with the given kernel and packages, and the given
+/// partitions, and return what it made of it, along with what it said
+fn outcome(kernel: &str, packages: &str, bootloader: &str, partitions: &str) -> (Outcome, String)
+{
+    let output = common::generate(&["--file"], &[
+        ("kernel: latest\n", &format!("kernel: {}\n", kernel)),
+        ("packages: vim\n", &format!("packages: {}\n", packages)),
+        ("bootloader: grub\n", &format!("bootloader: {}\n", bootloader)),
+    ], &format!("partitions:\n{}", partitions));
+    let stderr = String::from_utf8(output.stderr).unwrap();
+    let outcome = match (output.status.success(), stderr.contains("has little room to spare for the kernel")) {
+        (false, _) => Outcome::Refused,
+        (true, true) => Outcome::Warned,
+        (true, false) => Outcome::Fine,
+    };
+    (outcome, stderr)
+}
+
+/// The EFI system partition, mounted at `mount`, with the given size
+fn esp(mount: &str, size: &str) -> String
+{
+    format!("  - esp:\n    format: fat32\n    mount: {}\n    size: {}\n", mount, size)
+}
+
+/// The root partition, with a size so that /boot can take the rest of the disk
+const ROOT: &str = "  - root:\n    format: ext4\n    mount: /\n    size: 20G\n";
+
+/// The kernels and packages of the matrix, with the room their files take in /boot, in MiB
+const KERNELS: &[(&str, &str, u64)] = &[
+    ("latest", "vim", 150),
+    ("lts", "vim", 130),
+    // a second kernel, with its own initramfs images
+    ("latest", "vim linux-lts", 280),
+    ("lts", "vim intel-ucode amd-ucode", 150),
+];
+
+/// What jimmy should make of a /boot partition of `size` MiB holding files of `required` MiB:
+/// `mkinitcpio` needs half as much again while it rebuilds the images
+fn expected(size: u64, required: u64) -> Outcome
+{
+    if size < required {
+        Outcome::Refused
+    } else if size < required + required / 2 {
+        Outcome::Warned
+    } else {
+        Outcome::Fine
+    }
+}
+
+#[test]
+fn separate_boot_partition()
+{
+    // the EFI system partition is mounted at /efi, so /boot can be of any size
+    for (bootloader, format) in [("grub", "ext4"), ("systemd-boot", "fat32")] {
+        for (kernel, packages, required) in KERNELS {
+            for size in [required - 1, *required, required + required / 2 - 1, required + required / 2, 1024] {
+                let boot = format!("  - boot:\n    format: {}\n    mount: /boot\n    size: {}M\n", format, size);
+                let (outcome, stderr) = outcome(kernel, packages, bootloader, &format!("{}{}{}", esp("/efi", "500M"), ROOT, boot));
+                assert_eq!(outcome, expected(size, *required), "{} {} {} {}M: {}", bootloader, kernel, packages, size, stderr);
+                if outcome == Outcome::Refused {
+                    assert!(stderr.contains(&format!("the partition mounted at /boot is too small for the kernel (about {}M with its initramfs images and microcode); \
+                        change its `size` from '{}M' to at least '{}M'", required, size, required)), "{}", stderr);
+                }
+            }
+            // the rest of the disk is taken to be enough
+            let boot = format!("  - boot:\n    format: {}\n    mount: /boot\n", format);
+            let (outcome, stderr) = outcome(kernel, packages, bootloader, &format!("{}{}{}", esp("/efi", "500M"), ROOT, boot));
+            assert_eq!(outcome, Outcome::Fine, "{} {} {}: {}", bootloader, kernel, packages, stderr);
+        }
+    }
+}
+
+#[test]
+fn esp_mounted_at_boot()
+{
+    // the EFI system partition can't be smaller than 260M anyway
+    for bootloader in ["grub", "systemd-boot", "efistub"] {
+        for (kernel, packages, required) in KERNELS {
+            for size in [260, 300, 419, 420] {
+                let (outcome, stderr) = outcome(kernel, packages, bootloader, &format!("{}{}", esp("/boot", &format!("{}M", size)), ROOT));
+                assert_eq!(outcome, expected(size, *required), "{} {} {} {}M: {}", bootloader, kernel, packages, size, stderr);
+            }
+        }
+    }
+}
+
+#[test]
+fn no_boot_partition()
+{
+    // GRUB reads the kernels from the root partition; the others can't
+    let partitions = format!("{}{}", esp("/efi", "500M"), ROOT);
+    assert_eq!(outcome("latest", "vim", "grub", &partitions).0, Outcome::Fine);
+    let (outcome, stderr) = outcome("latest", "vim", "efistub", &partitions);
+    assert_eq!(outcome, Outcome::Refused);
+    assert!(stderr.contains("efistub loads the kernels straight from the EFI system partition, but they'd be on the root partition; \
+        mount the EFI system partition at /boot instead"), "{}", stderr);
+    let (outcome, stderr) = self::outcome("latest", "vim", "systemd-boot", &partitions);
+    assert_eq!(outcome, Outcome::Refused);
+    assert!(stderr.contains("systemd-boot with the EFI system partition mounted at /efi requires an XBOOTLDR partition for the kernels"), "{}", stderr);
+}
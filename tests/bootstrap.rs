@@ -0,0 +1,231 @@
+//! Checks `bootstrap`: the steps of the script with pacstrap and with a bootstrap tarball, which
+//! only differ in how the packages get onto /mnt, and the commands that download, check and
+//! extract the tarball, which are ran with sh on a local file, with bsdtar, curl and pacman-key
+//! replaced by programs recording what they're asked to do
+
+use std::os::unix::fs::PermissionsExt;
+use std::process::{Command, Output};
+
+mod common;
+
+/// Run jimmy with `args` on the sample configuration file, with the given lines appended
+fn generate(args: &[&str], extra_lines: &str) -> Output
+{
+    common::generate(args, &[], extra_lines)
+}
+
+/// Return the script, checking that jimmy succeeded
+fn generated(extra_lines: &str) -> String
+{
+    common::script(generate(&["--file"], extra_lines))
+}
+
+/// Return the names of the steps of the script, in the order they're ran in
+fn steps(script: &str) -> Vec<&str>
+{
+    script.lines()
+        .filter_map(|l| l.strip_prefix("JIMMY_STEP="))
+        .map(|name| name.trim_matches('\''))
+        .filter(|name| !name.is_empty())
+        .collect()
+}
+
+/// Return the commands of the step called `name`
+fn step<'a>(script: &'a str, name: &str) -> &'a str
+{
+    let start = script.find(&format!("\nJIMMY_STEP={}\n", name)).unwrap() + format!("\nJIMMY_STEP={}\n", name).len();
+    let end = start + script[start..].find(&format!("\njimmy_time '{}'", name)).unwrap();
+    &script[start..end]
+}
+
+/// Return the packages `program` installs in the loop that retries it
+fn packages<'a>(script: &'a str, program: &str) -> Vec<&'a str>
+{
+    let start = script.find(&format!("{{ {} ", program)).unwrap() + 2;
+    let end = start + script[start..].find(" 2>&1;").unwrap();
+    // the options, their values, which are all paths, and the variable that adds `--needed`
+    script[start..end].split_whitespace().skip(1)
+        .filter(|a| *a != "\\" && !a.starts_with(['-', '/', '$']))
+        .collect()
+}
+
+/// Run `code` with sh, with /mnt replaced by an empty directory and the mirrorlist of the live
+/// system by a file of its own; bsdtar, curl and pacman-key append what they're asked to do to a
+/// log, pacman-key failing if `bad_signature`. Return whether the code succeeded, its stderr and
+/// the log
+fn run(code: &str, bad_signature: bool) -> (bool, String, String)
+{
+    let dir = common::temp_path("dir");
+    std::fs::create_dir_all(dir.join("bin")).unwrap();
+    let programs = [
+        ("bsdtar", "echo \"bsdtar $*\" >>\"$LOG\"\nmkdir -p \"$MNT/etc/pacman.d\"".to_string()),
+        ("curl", "echo \"curl $*\" >>\"$LOG\"".to_string()),
+        ("pacman-key", format!("echo \"pacman-key $*\" >>\"$LOG\"\nexit {}", if bad_signature { 1 } else { 0 })),
+    ];
+    for (program, body) in programs {
+        std::fs::write(dir.join("bin").join(program), format!("#!/bin/sh\n{}\n", body)).unwrap();
+        std::fs::set_permissions(dir.join("bin").join(program), std::fs::Permissions::from_mode(0o755)).unwrap();
+    }
+    std::fs::write(dir.join("tarball"), "the root filesystem\n").unwrap();
+    std::fs::write(dir.join("mirrorlist"), "Server = https://mirror.example/$repo/os/$arch\n").unwrap();
+    let code = code
+        .replace("cp /etc/pacman.d/mirrorlist", &format!("cp {}/mirrorlist", dir.display()))
+        .replace("/mnt", &dir.join("mnt").display().to_string())
+        .replace("/local/tarball", &dir.join("tarball").display().to_string());
+    let output = Command::new("sh").args(["-c", &code])
+        .env("PATH", format!("{}:{}", dir.join("bin").display(), std::env::var("PATH").unwrap()))
+        .env("LOG", dir.join("log"))
+        .env("MNT", dir.join("mnt"))
+        .output()
+        .unwrap();
+    let log = std::fs::read_to_string(dir.join("log")).unwrap_or_default()
+        .replace(&dir.display().to_string(), "");
+    if output.status.success() {
+        assert_eq!(std::fs::read_to_string(dir.join("mnt/etc/pacman.d/mirrorlist")).unwrap(), "Server = https://mirror.example/$repo/os/$arch\n");
+    }
+    std::fs::remove_dir_all(&dir).unwrap();
+    (output.status.success(), String::from_utf8(output.stderr).unwrap(), log)
+}
+
+/// Return the SHA-256 checksum of `contents`
+fn sha256(contents: &str) -> String
+{
+    let path = common::temp_path("checksum");
+    std::fs::write(&path, contents).unwrap();
+    let output = Command::new("sha256sum").arg(&path).output().unwrap();
+    std::fs::remove_file(&path).unwrap();
+    String::from_utf8(output.stdout).unwrap().split_whitespace().next().unwrap().to_string()
+}
+
+/// The steps of the sample configuration file, with the ones that put the packages onto /mnt
+/// left out
+const BEFORE: &[&str] = &["preflight", "previous mounts", "clock", "partitioning", "formatting", "mounting"];
+const AFTER: &[&str] = &["fstab", "chroot script", "configuration", "cleanup", "verification", "unmount"];
+
+#[test]
+fn pacstrap()
+{
+    for lines in ["", "bootstrap: pacstrap\n"] {
+        let script = generated(lines);
+        assert_eq!(steps(&script), [BEFORE, &["pacstrap"], AFTER].concat());
+        assert!(script.contains("\njimmy_check arch-install-scripts pacstrap arch-chroot genfstab\n"));
+        assert!(!script.contains("bsdtar") && !script.contains("pacman --root"));
+    }
+    let output = generate(&["--file"], "tarball:\n  source: /local/tarball\n");
+    assert!(output.status.success());
+    assert!(String::from_utf8_lossy(&output.stderr).contains("warning: tarball is only used with `bootstrap: tarball`; it's going to be ignored"));
+}
+
+#[test]
+fn tarball()
+{
+    let pacstrap = generated("");
+    let script = generated("bootstrap: tarball\n");
+    assert_eq!(steps(&script), [BEFORE, &["tarball", "packages"], AFTER].concat());
+    assert!(script.contains("\njimmy_check libarchive bsdtar\n") && script.contains("\njimmy_check pacman pacman-key\n"));
+    assert!(!script.contains("\npacstrap "));
+
+    // the same packages, and everything after them is the same
+    assert_eq!(packages(&script, "pacman"), packages(&pacstrap, "pacstrap"));
+    for name in ["fstab", "configuration", "cleanup", "verification"] {
+        assert_eq!(step(&script, name), step(&pacstrap, name), "{}", name);
+    }
+
+    let packages_step = step(&script, "packages");
+    assert!(packages_step.starts_with("mountpoint -q /mnt/proc || mount -t proc proc /mnt/proc -o nosuid,noexec,nodev\n\
+        mountpoint -q /mnt/sys || mount -t sysfs sys /mnt/sys -o nosuid,noexec,nodev,ro\n\
+        mountpoint -q /mnt/dev || mount -t devtmpfs udev /mnt/dev -o mode=0755,nosuid\n\
+        jimmy_pacstrap_log=$(mktemp)\n"));
+    assert!(packages_step.contains("\n    { pacman --root /mnt --cachedir /mnt/var/cache/pacman/pkg --noconfirm -Syu \\\n        --needed base "));
+    assert!(packages_step.contains("\n    echo 'error: pacman failed' >&2\n"));
+    assert!(packages_step.ends_with("\nfi\numount -R /mnt/proc /mnt/sys /mnt/dev"));
+
+    // the snapshot's mirrorlist is the one copied onto the new system
+    let script = generated("bootstrap: tarball\nsnapshot_date: 2024-01-15\n");
+    assert_eq!(steps(&script), [BEFORE, &["snapshot", "tarball", "packages"], AFTER].concat());
+    // the phases are the same
+    let script = generated("bootstrap: tarball\nphases: [ disks, unmount ]\n");
+    assert_eq!(steps(&script), [BEFORE, &["unmount"]].concat());
+}
+
+#[test]
+fn downloaded()
+{
+    let script = generated("bootstrap: tarball\n");
+    let code = step(&script, "tarball");
+    assert_eq!(code, "jimmy_tarball=/mnt/jimmy_bootstrap.tar\n\
+        curl --fail --location --retry 3 --output \"$jimmy_tarball\" \\\n    \
+        https://geo.mirror.pkgbuild.com/iso/latest/archlinux-bootstrap-x86_64.tar.zst \\\n    \
+        || { echo 'error: could not download https://geo.mirror.pkgbuild.com/iso/latest/archlinux-bootstrap-x86_64.tar.zst' >&2; exit 1; }\n\
+        curl --fail --location --retry 3 --output \"$jimmy_tarball.sig\" \\\n    \
+        https://geo.mirror.pkgbuild.com/iso/latest/archlinux-bootstrap-x86_64.tar.zst.sig \\\n    \
+        || { echo 'error: could not download https://geo.mirror.pkgbuild.com/iso/latest/archlinux-bootstrap-x86_64.tar.zst.sig' >&2; exit 1; }\n\
+        pacman-key --verify \"$jimmy_tarball.sig\" \"$jimmy_tarball\" \\\n    \
+        || { echo 'error: the signature of the bootstrap tarball could not be verified' >&2; exit 1; }\n\
+        bsdtar --extract --preserve-permissions --xattrs --acls --numeric-owner \\\n    \
+        --file \"$jimmy_tarball\" --directory /mnt --strip-components 1 \\\n    \
+        || { echo 'error: could not extract the bootstrap tarball' >&2; exit 1; }\n\
+        rm -f \"$jimmy_tarball\" \"$jimmy_tarball.sig\"\n\
+        cp /etc/pacman.d/mirrorlist /mnt/etc/pacman.d/mirrorlist");
+    assert!(script.contains("\njimmy_check curl curl\n"));
+
+    let (success, stderr, log) = run(code, false);
+    assert!(success, "{}", stderr);
+    assert_eq!(log, "curl --fail --location --retry 3 --output /mnt/jimmy_bootstrap.tar https://geo.mirror.pkgbuild.com/iso/latest/archlinux-bootstrap-x86_64.tar.zst\n\
+        curl --fail --location --retry 3 --output /mnt/jimmy_bootstrap.tar.sig https://geo.mirror.pkgbuild.com/iso/latest/archlinux-bootstrap-x86_64.tar.zst.sig\n\
+        pacman-key --verify /mnt/jimmy_bootstrap.tar.sig /mnt/jimmy_bootstrap.tar\n\
+        bsdtar --extract --preserve-permissions --xattrs --acls --numeric-owner --file /mnt/jimmy_bootstrap.tar --directory /mnt --strip-components 1\n");
+
+    // nothing is extracted from a tarball whose signature doesn't match
+    let (success, stderr, log) = run(code, true);
+    assert!(!success);
+    assert_eq!(stderr, "error: the signature of the bootstrap tarball could not be verified\n");
+    assert!(!log.contains("bsdtar"));
+
+    // Arch Linux ARM's tarball is the root filesystem itself, and its signature can't be checked
+    let lines = format!("bootstrap: tarball\narch: aarch64\nbootloader: grub\ntarball:\n  sha256: {}\n", sha256(""));
+    let script = String::from_utf8(generate(&["--file"], &lines).stdout).unwrap();
+    let code = step(&script, "tarball");
+    assert!(code.contains(" https://os.archlinuxarm.org/os/ArchLinuxARM-aarch64-latest.tar.gz \\\n"));
+    assert!(code.contains("--file \"$jimmy_tarball\" --directory /mnt \\\n"));
+    assert!(!code.contains("pacman-key"));
+}
+
+#[test]
+fn local()
+{
+    let lines = format!("bootstrap: tarball\ntarball:\n  source: /local/tarball\n  sha256: {}\n", sha256("the root filesystem\n").to_uppercase());
+    let script = generated(&lines);
+    let code = step(&script, "tarball");
+    assert!(code.starts_with("jimmy_tarball=/local/tarball\n[ -f \"$jimmy_tarball\" ] \\\n    \
+        || { echo 'error: the bootstrap tarball /local/tarball doesn'\\''t exist' >&2; exit 1; }\n"));
+    assert!(!code.contains("curl") && !code.contains("rm -f") && !code.contains("--strip-components"));
+    assert!(script.contains("\njimmy_check coreutils sha256sum\n") && !script.contains("jimmy_check curl"));
+    let (success, stderr, log) = run(code, false);
+    assert!(success, "{}", stderr);
+    assert_eq!(log, "bsdtar --extract --preserve-permissions --xattrs --acls --numeric-owner --file /tarball --directory /mnt\n");
+
+    let script = generated(&lines.replace(&sha256("the root filesystem\n").to_uppercase(), &sha256("another one\n")));
+    let (success, stderr, log) = run(step(&script, "tarball"), false);
+    assert_eq!((success, stderr.as_str(), log.as_str()), (false, "error: the bootstrap tarball does not have the sha256 checksum it should\n", ""));
+
+    let script = generated("bootstrap: tarball\ntarball:\n  source: /local/missing\n");
+    let (success, stderr, _) = run(step(&script, "tarball"), false);
+    assert_eq!((success, stderr.as_str()), (false, "error: the bootstrap tarball /local/missing doesn't exist\n"));
+}
+
+#[test]
+fn invalid()
+{
+    for (lines, message) in [
+        ("bootstrap: debootstrap\n", "invalid bootstrap: \"debootstrap\" (expected one of: pacstrap, tarball)"),
+        ("bootstrap: tarball\ntarball:\n  source: archlinux-bootstrap-x86_64.tar.zst\n",
+            "invalid source of tarball: \"archlinux-bootstrap-x86_64.tar.zst\" (expected a URL starting with http:// or https://, or an absolute path)"),
+        ("bootstrap: tarball\ntarball:\n  sha256: 0123abc\n", "invalid sha256 of tarball: \"0123abc\" (expected 64 hexadecimal digits)"),
+        ("bootstrap: tarball\narch: aarch64\n", "the tarball of aarch64 needs a `sha256` under `tarball`"),
+    ] {
+        let output = generate(&["--file"], lines);
+        assert!(!output.status.success(), "{}", lines);
+        assert!(String::from_utf8_lossy(&output.stderr).contains(message), "{}", String::from_utf8_lossy(&output.stderr));
+    }
+}
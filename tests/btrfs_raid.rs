@@ -0,0 +1,76 @@
+//! Checks btrfs filesystems spread over several disks: the single `mkfs.btrfs` that creates them
+//! on all of their devices, the members left out of the mounting step, and what's refused
+
+use std::process::Output;
+
+mod common;
+
+/// The root partition of the sample, as a btrfs filesystem mirrored onto /dev/sdb
+const RAID1: &str = "    format: btrfs\n    mount: /\n    raid_profile: raid1\n    members:\n      - disk: /dev/sdb\n";
+
+/// Generate the script from the sample configuration file, with the lines of its root partition
+/// replaced by `root`
+fn generate(root: &str) -> Output
+{
+    common::generate(&["--file"], &[("    format: ext4\n    mount: /\n", root)], "")
+}
+
+/// Return the script, checking that jimmy succeeded
+fn generated(root: &str) -> String
+{
+    common::script(generate(root))
+}
+
+/// Return what jimmy complains about when it refuses the configuration file
+fn refusal(root: &str) -> String
+{
+    common::refusal(generate(root))
+}
+
+/// Return the lines of the script that start with `prefix`
+fn lines<'a>(script: &'a str, prefix: &str) -> Vec<&'a str>
+{
+    script.lines().filter(|l| l.starts_with(prefix)).collect()
+}
+
+#[test]
+fn one_mkfs_for_every_device()
+{
+    let script = generated(RAID1);
+    assert_eq!(lines(&script, "mkfs."), ["mkfs.fat -F 32 /dev/sda1 || exit 1", "mkfs.btrfs -d raid1 -m raid1 /dev/sda2 /dev/sdb1 || exit 1"]);
+    // the member is still partitioned
+    assert!(script.contains("| fdisk /dev/sdb "));
+
+    let third = RAID1.replace("raid1", "raid10").replace("/dev/sdb", "/dev/sdb\n        size: 100G\n      - disk: /dev/sdc");
+    let script = generated(&format!("{}    mkfs_args: --label root\n", third));
+    assert_eq!(lines(&script, "mkfs.btrfs"), ["mkfs.btrfs -d raid10 -m raid10 --label root /dev/sda2 /dev/sdb1 /dev/sdc1 || exit 1"]);
+}
+
+#[test]
+fn only_the_first_device_is_mounted()
+{
+    let script = generated(RAID1);
+    let start = script.find("echo '<-> mounting partitions...'").unwrap();
+    let mounting = &script[start..start + script[start..].find("\n\n").unwrap()];
+    assert_eq!(lines(mounting, "mountpoint -q /mnt"), ["mountpoint -q /mnt/ || { mkdir -p /mnt/ && mount /dev/sda2 /mnt/ || exit 1; }", "mountpoint -q /mnt/boot || { mkdir -p /mnt/boot && mount /dev/sda1 /mnt/boot || exit 1; }"]);
+    assert!(mounting.contains("\n# /dev/sdb1 is a member of the btrfs filesystem mounted at /\n"));
+    assert!(!script.contains("/dev/sdb1 /mnt"));
+}
+
+#[test]
+fn validation()
+{
+    assert!(refusal(&RAID1.replace("/dev/sdb\n", "/dev/sdb\n        mount: /data\n"))
+        .contains("the member on /dev/sdb of the btrfs filesystem mounted at / has `mount`, but members are formatted and mounted along with the filesystem; remove it"));
+    assert!(refusal(&RAID1.replace("/dev/sdb\n", "/dev/sdb\n        format: btrfs\n")).contains("has `format`"));
+    assert!(refusal(&RAID1.replace("/dev/sdb", "/dev/sda"))
+        .contains("the btrfs filesystem mounted at / has more than one device on /dev/sda; put each member on a disk of its own"));
+    assert!(refusal("    format: btrfs\n    mount: /\n    raid_profile: raid1\n")
+        .contains("`raid_profile: raid1` needs at least 2 devices, but the btrfs filesystem mounted at / has 1; add `members` on other disks"));
+    assert!(refusal(&RAID1.replace("raid1", "raid5"))
+        .contains("invalid raid_profile: \"raid5\" (expected one of: single, raid1, raid10)"));
+    assert!(refusal(&RAID1.replace("btrfs", "ext4"))
+        .contains("members are only used with `format: btrfs`, not 'ext4'"));
+    assert!(refusal(&RAID1.replace("    raid_profile: raid1\n", ""))
+        .contains("the btrfs filesystem mounted at / has members, but no `raid_profile`"));
+}
@@ -0,0 +1,113 @@
+//! Checks that `jimmy capabilities --json` lists what jimmy accepts: the values of each property
+//! jimmy gives as expected when it refuses one, and what each of the others does to the script
+
+use std::process::{Command, Output};
+
+mod common;
+
+/// Return what `jimmy capabilities --json` prints
+fn capabilities() -> serde_json::Value
+{
+    let output = Command::new(env!("CARGO_BIN_EXE_jimmy")).args(["capabilities", "--json"]).output().unwrap();
+    serde_json::from_slice(&output.stdout).unwrap()
+}
+
+/// Return the names listed under `key`, as strings or as the `name` of objects
+fn names(capabilities: &serde_json::Value, key: &str) -> Vec<String>
+{
+    capabilities[key].as_array().unwrap_or_else(|| panic!("no {}", key)).iter()
+        .map(|v| v.as_str().unwrap_or_else(|| v["name"].as_str().unwrap()).to_string())
+        .collect()
+}
+
+/// Return the script jimmy made with `--reproducible`, without the hash of the configuration file
+fn body(output: Output) -> String
+{
+    common::without_hash(&common::script(output))
+}
+
+#[test]
+fn expected_values()
+{
+    let capabilities = capabilities();
+    for (key, replacements, extra_lines) in [
+        ("bootloaders", &[("bootloader: grub\n", "bootloader: bogus\n")][..], ""),
+        ("architectures", &[], "arch: bogus\n"),
+        ("chroot_backends", &[], "chroot_backend: bogus\n"),
+        ("initramfs_generators", &[], "initramfs_generator: bogus\n"),
+        ("cleanup_policies", &[], "cleanup: bogus\n"),
+        ("hardware_clocks", &[], "hardware_clock: bogus\n"),
+        ("power_daemons", &[], "power: bogus\n"),
+        ("previous_mounts", &[], "previous_mounts: bogus\n"),
+        ("default_targets", &[], "default_target: bogus\n"),
+        ("bootstraps", &[], "bootstrap: bogus\n"),
+        ("lints", &[], "allow_lints: [ bogus ]\n"),
+        ("root_password_policies", &[], "root_password_policy: bogus\n"),
+        ("systemd_boot_updates", &[("bootloader: grub\n", "bootloader: systemd-boot\n")], "systemd_boot_update: bogus\n"),
+        ("mirrorlist_updates", &[], "maintenance:\n  mirrorlist_update: bogus\n"),
+        ("raid_profiles", &[("    format: ext4\n", "    format: btrfs\n    raid_profile: bogus\n")], ""),
+    ] {
+        let stderr = common::refusal(common::generate(&["--file"], replacements, extra_lines));
+        let from = stderr.find("(expected one of: ").unwrap_or_else(|| panic!("{}: {}", key, stderr)) + "(expected one of: ".len();
+        let expected: Vec<&str> = stderr[from..][..stderr[from..].find(')').unwrap()].split(", ").collect();
+        assert_eq!(names(&capabilities, key), expected, "{}", key);
+    }
+}
+
+#[test]
+fn formats()
+{
+    let capabilities = capabilities();
+    for format in capabilities["formats"].as_array().unwrap() {
+        let name = format["name"].as_str().unwrap();
+        let mount = if name == "swap" { "" } else { "    mount: /data\n" };
+        let partition = format!("    size: 500M\n  - data:\n    format: {}\n    size: 4G\n{}", name, mount);
+        let script = common::script(common::generate(&["--file"], &[("    size: 500M\n", &partition)], ""));
+        assert!(script.contains(&format!("\n{} /dev/sda2 || exit 1\n", format["mkfs"].as_str().unwrap())), "{}", name);
+    }
+}
+
+#[test]
+fn partition_types()
+{
+    let capabilities = capabilities();
+    for partition_type in capabilities["partition_types"].as_array().unwrap() {
+        let name = partition_type["name"].as_str().unwrap();
+        let partition = format!("    size: 500M\n  - data:\n    format: ext4\n    mount: /data\n    size: 4G\n    type_guid: {}\n", name);
+        let script = common::script(common::generate(&["--file"], &[("    size: 500M\n", &partition)], ""));
+        assert!(script.contains(&format!("\\nt\\n2\\n{}\\n", partition_type["guid"].as_str().unwrap())), "{}", name);
+    }
+}
+
+#[test]
+fn kernels_and_network_backends()
+{
+    let capabilities = capabilities();
+    let packages = |kernel: &str| common::script(common::generate(&["packages"], &[("kernel: latest\n", &format!("kernel: {}\n", kernel))], ""));
+    let lists: Vec<String> = names(&capabilities, "kernels").iter().map(|k| packages(k)).collect();
+    for (i, list) in lists.iter().enumerate() {
+        assert!(lists[..i].iter().all(|l| l != list), "{}", list);
+        for backend in names(&capabilities, "network_backends") {
+            assert!(list.lines().any(|p| p == backend), "{}", backend);
+        }
+    }
+}
+
+#[test]
+fn languages_and_hardening_toggles()
+{
+    let capabilities = capabilities();
+    let output = |language: &str| common::generate(&["--reproducible", "--file"], &[], &format!("language: {}\nstrict: true\n", language));
+    let english = body(output("en"));
+    for language in names(&capabilities, "languages") {
+        let output = output(&language);
+        assert!(!String::from_utf8_lossy(&output.stderr).contains("no status messages"), "{}", language);
+        assert_eq!(body(output) == english, language == "en", "{}", language);
+    }
+
+    let chroot_script = |hardening: &str| body(common::generate(&["chroot-script", "--reproducible"], &[], hardening));
+    let none = chroot_script("");
+    for toggle in names(&capabilities, "hardening_toggles") {
+        assert_ne!(chroot_script(&format!("hardening:\n  {}: true\n", toggle)), none, "{}", toggle);
+    }
+}
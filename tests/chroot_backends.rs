@@ -0,0 +1,88 @@
+//! Checks what changes between the scripts made with `chroot_backend: arch-chroot` and with
+//! `chroot_backend: nspawn`: how the arch-chroot script is started, the steps the container can't
+//! do by itself, and nothing else
+
+mod common;
+
+/// Return the script jimmy makes with `--reproducible` and `args` from the sample configuration
+/// file with the given backend and lines appended, without the hash of the file
+fn script(args: &[&str], backend: &str, extra_lines: &str) -> String
+{
+    let args = common::with_flags(&["--reproducible"], args);
+    common::without_hash(&common::script(common::generate(&args, &[], &format!("chroot_backend: {}\n{}", backend, extra_lines))))
+}
+
+/// Return the lines of the script made with nspawn that aren't in the one made with arch-chroot,
+/// and the other way around, in order
+fn differences(args: &[&str], extra_lines: &str) -> (Vec<String>, Vec<String>)
+{
+    let arch_chroot = script(args, "arch-chroot", extra_lines);
+    let nspawn = script(args, "nspawn", extra_lines);
+    let only_in = |a: &str, b: &str| {
+        let mut others: Vec<&str> = b.lines().collect();
+        a.lines()
+            .filter(|l| match others.iter().position(|o| o == l) {
+                Some(i) => {
+                    others.remove(i);
+                    false
+                },
+                None => true,
+            })
+            .map(String::from)
+            .collect::<Vec<String>>()
+    };
+    (only_in(&nspawn, &arch_chroot), only_in(&arch_chroot, &nspawn))
+}
+
+#[test]
+fn installation_script()
+{
+    let (nspawn, arch_chroot) = differences(&["--file"], "");
+    assert_eq!(nspawn, [
+        "# the system is configured with nspawn",
+        "jimmy_check arch-install-scripts pacstrap genfstab",
+        "jimmy_check systemd systemd-nspawn timedatectl",
+        "        jimmy_iso_check 2018.08.01 '`chroot_backend: nspawn` (systemd-nspawn --resolv-conf, systemd 239)'",
+        "# the system is configured with nspawn",
+        "systemctl enable systemd-resolved",
+        "systemd-nspawn -D /mnt --as-pid2 --resolv-conf=bind-host --bind=/sys/firmware/efi/efivars /jimmy_part2.sh",
+        // the container can't reach the hardware clock, so it's set from outside once the script ran
+        "hwclock --systohc --adjfile=/mnt/etc/adjtime",
+    ]);
+    assert_eq!(arch_chroot, [
+        "# the system is configured with arch-chroot",
+        "jimmy_check arch-install-scripts pacstrap arch-chroot genfstab",
+        "jimmy_check systemd timedatectl",
+        "# the system is configured with arch-chroot",
+        "hwclock --systohc",
+        // inside the container, there's no systemd to start the service with
+        "systemctl enable --now systemd-resolved",
+        "arch-chroot /mnt ./jimmy_part2.sh",
+    ]);
+}
+
+#[test]
+fn chroot_script()
+{
+    let (nspawn, arch_chroot) = differences(&["chroot-script"], "");
+    assert_eq!(nspawn, ["# the system is configured with nspawn", "systemctl enable systemd-resolved"]);
+    assert_eq!(arch_chroot, ["# the system is configured with arch-chroot", "hwclock --systohc", "systemctl enable --now systemd-resolved"]);
+}
+
+#[test]
+fn resolv_conf()
+{
+    // the copy of resolv.conf the arch-chroot script needs is kept, instead of the host's one
+    let script = script(&["--file"], "nspawn", "keep_resolv_conf: true\n");
+    assert!(script.contains("\nsystemd-nspawn -D /mnt --as-pid2 --resolv-conf=off --bind=/sys/firmware/efi/efivars /jimmy_part2.sh\n"));
+}
+
+#[test]
+fn verification()
+{
+    // what's checked inside the new system once it's installed is ran with the backend too
+    let (nspawn, arch_chroot) = differences(&["--file"], "bootloader: efistub\n");
+    let verify = |lines: &[String]| lines.iter().find(|l| l.starts_with("jimmy_verify 'boot entry")).cloned().unwrap();
+    assert_eq!(verify(&nspawn), "jimmy_verify 'boot entry '\\''Arch Linux'\\''' 'systemd-nspawn -D /mnt --as-pid2 --resolv-conf=bind-host --bind=/sys/firmware/efi/efivars efibootmgr -v | grep -qF '\\''Arch Linux'\\'''");
+    assert_eq!(verify(&arch_chroot), "jimmy_verify 'boot entry '\\''Arch Linux'\\''' 'arch-chroot /mnt efibootmgr -v | grep -qF '\\''Arch Linux'\\'''");
+}
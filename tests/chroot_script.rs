@@ -0,0 +1,106 @@
+//! Runs a generated arch-chroot script inside a real root filesystem of Arch Linux, and checks
+//! that it configured the system. It needs such a root filesystem, e.g. the extracted
+//! `archlinux-bootstrap-x86_64.tar.zst`, given by `JIMMY_E2E_ROOTFS`, along with `unshare` and
+//! `script` from util-linux, so it's ignored by default:
+//!
+//! ```
+//! JIMMY_E2E_ROOTFS=/path/to/root.x86_64 cargo test -- --ignored
+//! ```
+//!
+//! The root filesystem is copied before the script is ran, so it can be reused.
+
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::process::{Command, Stdio};
+
+/// The configuration file the script is generated from
+const CONFIG: &str = "examples/valid--simple.yaml";
+
+/// Return whether a program can be found in `PATH`
+fn has_program(name: &str) -> bool
+{
+    Command::new("sh")
+        .args(["-c", &format!("command -v {} >/dev/null", name)])
+        .status()
+        .is_ok_and(|s| s.success())
+}
+
+/// Return the root filesystem to run the script in, or `None` if the test can't run on this
+/// machine
+fn prerequisites() -> Option<PathBuf>
+{
+    let rootfs = match std::env::var_os("JIMMY_E2E_ROOTFS") {
+        Some(path) => PathBuf::from(path),
+        None => {
+            eprintln!("skipping: JIMMY_E2E_ROOTFS isn't set");
+            return None;
+        },
+    };
+    if !rootfs.join("etc/arch-release").is_file() {
+        eprintln!("skipping: {} isn't a root filesystem of Arch Linux", rootfs.display());
+        return None;
+    }
+    if let Some(missing) = ["unshare", "script", "cp"].iter().find(|p| !has_program(p)) {
+        eprintln!("skipping: {} isn't installed", missing);
+        return None;
+    }
+    Some(rootfs)
+}
+
+/// Copy the root filesystem to a new temporary directory, returning the path to the copy
+fn copy_rootfs(rootfs: &Path) -> PathBuf
+{
+    let copy = std::env::temp_dir().join(format!("jimmy-e2e-{}", std::process::id()));
+    let status = Command::new("cp")
+        .arg("-a")
+        .arg(rootfs)
+        .arg(&copy)
+        .status()
+        .unwrap();
+    assert!(status.success(), "couldn't copy {} to {}", rootfs.display(), copy.display());
+    copy
+}
+
+#[test]
+#[ignore]
+fn chroot_script_configures_the_system()
+{
+    let rootfs = match prerequisites() {
+        Some(rootfs) => rootfs,
+        None => return,
+    };
+    let output = Command::new(env!("CARGO_BIN_EXE_jimmy"))
+        .args(["chroot-script", CONFIG])
+        .output()
+        .unwrap();
+    assert!(output.status.success(), "jimmy failed: {}", String::from_utf8_lossy(&output.stderr));
+
+    let root = copy_rootfs(&rootfs);
+    std::fs::write(root.join("jimmy_part2.sh"), &output.stdout).unwrap();
+    // `passwd` only reads passwords from a terminal, so the script runs inside one made by
+    // `script`, which passes it whatever is written to its standard input
+    let mut child = Command::new("script")
+        .args(["--quiet", "--return", "--command"])
+        .arg(format!("unshare --map-root-user chroot {} /bin/sh /jimmy_part2.sh", root.display()))
+        .arg("/dev/null")
+        .stdin(Stdio::piped())
+        .spawn()
+        .unwrap();
+    child.stdin.take().unwrap().write_all("jimmy\n".repeat(16).as_bytes()).unwrap();
+    child.wait().unwrap();
+
+    let read = |path: &str| std::fs::read_to_string(root.join(path))
+        .unwrap_or_else(|e| panic!("couldn't read /{}: {}", path, e));
+    assert_eq!(
+        std::fs::read_link(root.join("etc/localtime")).unwrap(),
+        PathBuf::from("/usr/share/zoneinfo/Europe/London"),
+    );
+    assert!(read("etc/locale.conf").contains("LANG=en_US.UTF-8"));
+    assert_eq!(read("etc/hostname"), "archlinux\n");
+    assert!(read("etc/hosts").contains("127.0.1.1\tarchlinux"));
+    for unit in ["multi-user.target.wants/NetworkManager.service", "sysinit.target.wants/systemd-resolved.service"] {
+        let link = root.join("etc/systemd/system").join(unit);
+        assert!(link.is_symlink(), "{} isn't enabled", unit);
+    }
+    std::fs::remove_dir_all(&root).unwrap();
+}
@@ -0,0 +1,96 @@
+//! Checks how a failure of the arch-chroot script reaches the installation script: the arch-chroot
+//! script stops at the first command that fails and only says it's done at the end, and the
+//! installation script checks its status and stops before the cleanup, the unmounting and the
+//! "done" message. The commands that run it are ran with sh, with arch-chroot replaced by a program
+//! exiting with a given status
+
+use std::os::unix::fs::PermissionsExt;
+use std::process::Command;
+
+mod common;
+
+/// Run jimmy with `args` on the sample configuration file, with the given lines appended, and
+/// return what it printed, checking that it succeeded
+fn generated(args: &[&str], extra_lines: &str) -> String
+{
+    common::script(common::generate(args, &[], extra_lines))
+}
+
+/// Return the commands of the step of the installation script that runs the arch-chroot script
+fn configuration(script: &str) -> &str
+{
+    let start = script.find("\nJIMMY_STEP=configuration\n").unwrap() + "\nJIMMY_STEP=configuration\n".len();
+    let end = start + script[start..].find("\njimmy_time 'configuration'").unwrap();
+    &script[start..end]
+}
+
+/// Run `code` with sh, with arch-chroot exiting with `status`, and return the status `code` exited
+/// with, along with its stdout and stderr
+fn run(code: &str, status: i32) -> (i32, String, String)
+{
+    let dir = common::temp_path("dir");
+    std::fs::create_dir_all(&dir).unwrap();
+    std::fs::write(dir.join("arch-chroot"), format!("#!/bin/sh\nexit {}\n", status)).unwrap();
+    std::fs::set_permissions(dir.join("arch-chroot"), std::fs::Permissions::from_mode(0o755)).unwrap();
+    let output = Command::new("sh").args(["-c", code])
+        .env("PATH", format!("{}:{}", dir.display(), std::env::var("PATH").unwrap()))
+        .output()
+        .unwrap();
+    std::fs::remove_dir_all(&dir).unwrap();
+    (output.status.code().unwrap(), String::from_utf8(output.stdout).unwrap(), String::from_utf8(output.stderr).unwrap())
+}
+
+/// The check of the status of the arch-chroot script, after the command that runs it
+const CHECK: &str = "\njimmy_chroot_status=$?\nif [ \"$jimmy_chroot_status\" -ne 0 ]; then\n    \
+    echo \"error: the arch-chroot script failed with status $jimmy_chroot_status; /mnt is left mounted for inspection\" >&2\n    \
+    exit \"$jimmy_chroot_status\"\nfi";
+
+#[test]
+fn checked()
+{
+    let script = generated(&["--file"], "");
+    assert_eq!(configuration(&script), format!("arch-chroot /mnt ./jimmy_part2.sh{}", CHECK));
+    // the cleanup, the unmounting and the "done" message all come after it
+    let start = script.find("\nJIMMY_STEP=configuration\n").unwrap();
+    for later in ["\nrm -f /mnt/jimmy_part2.sh\n", "\n! mountpoint -q /mnt || umount -R /mnt\n", "\nprintf '\\n%s\\n' '<-> done; you may reboot now'\n"] {
+        assert!(script[start..].contains(later), "{}", later);
+    }
+
+    // the hardware clock is only set from outside once the script succeeded
+    let script = generated(&["--file"], "chroot_backend: nspawn\n");
+    let cmds = configuration(&script);
+    assert!(cmds.starts_with("systemd-nspawn -D /mnt "));
+    assert!(cmds.contains(&format!(" /jimmy_part2.sh{}\nhwclock ", CHECK)));
+}
+
+#[test]
+fn failed()
+{
+    // what follows the step stands for the rest of the script
+    let code = format!("{}\necho 'the rest of the script'", configuration(&generated(&["--file"], "")));
+    assert_eq!(run(&code, 3), (
+        3,
+        String::new(),
+        "error: the arch-chroot script failed with status 3; /mnt is left mounted for inspection\n".to_string(),
+    ));
+    assert_eq!(run(&code, 0), (0, "the rest of the script\n".to_string(), String::new()));
+}
+
+#[test]
+fn chroot_script()
+{
+    let script = generated(&["chroot-script"], "keymap: de-latin1\n");
+    let header_end = script.find("\n\n").unwrap();
+    assert!(script[header_end..].starts_with("\n\nset -e\n\n# stop if the system was installed from another configuration file\n"));
+    assert!(script.ends_with("\n\necho '<chroot> the system is configured'\n"));
+    assert!(!script.lines().any(|l| l == "exit"));
+
+    // the note on the keymap doesn't stop the script when the live system's keymap isn't known
+    let start = script.find("jimmy_live_keymap=$(").unwrap();
+    let end = start + script[start..].find("\nfi\n").unwrap() + "\nfi\n".len();
+    let missing = common::temp_path("missing");
+    let code = format!("set -e\n{}echo 'the rest of the script'", script[start..end].replace("/jimmy_live_keymap", &missing.display().to_string()));
+    let (status, stdout, _) = run(&code, 0);
+    assert_eq!(status, 0);
+    assert!(stdout.ends_with("\nthe rest of the script\n"), "{}", stdout);
+}
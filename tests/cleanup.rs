@@ -0,0 +1,100 @@
+//! Checks which of the files jimmy leaves on the installed system are still there after the
+//! installation, with each `cleanup` policy. The commands that remove them are ran on a copy of
+//! /mnt in a temporary directory, in which every one of those files was created
+
+use std::process::Command;
+
+mod common;
+
+/// The files jimmy may leave on the installed system, relative to its root
+const ARTIFACTS: &[&str] = &["var/lib/jimmy/config.hash", "var/log/jimmy/timings.txt", "root/jimmy-report.json"];
+
+/// Return the script generated from the example with a report, with the given replacements made
+/// to it
+fn script(replacements: &[(&str, &str)]) -> String
+{
+    let mut yaml = std::fs::read_to_string("examples/valid--report.yaml").unwrap();
+    for (from, to) in replacements {
+        yaml = yaml.replace(from, to);
+    }
+    common::script(common::jimmy(&["--file"], &yaml))
+}
+
+/// Run the step that removes the files, if there's one, on a fake installed system that has all
+/// of them, and return the ones left, along with the directories of jimmy
+fn files_left(script: &str) -> Vec<String>
+{
+    let root = common::temp_path("dir");
+    for artifact in ARTIFACTS {
+        std::fs::create_dir_all(root.join(artifact).parent().unwrap()).unwrap();
+        std::fs::write(root.join(artifact), "").unwrap();
+    }
+    let marker = "cleanup: removing the files jimmy left on the installed system...'\n";
+    if let Some(start) = script.find(marker).map(|i| i + marker.len()) {
+        let cmds = &script[start..start + script[start..].find("\n\n").unwrap()];
+        let cmds = cmds.replace("/mnt", &root.display().to_string());
+        assert!(Command::new("sh").args(["-c", &cmds]).status().unwrap().success());
+    }
+    let mut left: Vec<String> = ["var/lib/jimmy", "var/log/jimmy"].iter().chain(ARTIFACTS)
+        .filter(|path| root.join(path).exists())
+        .map(|path| path.to_string())
+        .collect();
+    left.sort();
+    std::fs::remove_dir_all(&root).unwrap();
+    left
+}
+
+/// Return the files that the plan in the report says are left on the installed system
+fn planned_artifacts(script: &str) -> Vec<String>
+{
+    let line = script.lines().find(|l| l.trim_start().starts_with("printf '{\"plan\":%s")).expect("no report in the script");
+    let (_, plan) = line.split_once("' '").unwrap();
+    let plan: serde_json::Value = serde_json::from_str(plan.trim_end_matches('\'')).unwrap();
+    plan["artifacts"].as_array().unwrap().iter().map(|a| a.as_str().unwrap().to_string()).collect()
+}
+
+#[test]
+fn keep_everything()
+{
+    let script = script(&[("cleanup: keep-report-only\n", "")]);
+    assert!(!script.contains("rmdir --ignore-fail-on-non-empty"));
+    assert_eq!(files_left(&script), [
+        "root/jimmy-report.json", "var/lib/jimmy", "var/lib/jimmy/config.hash", "var/log/jimmy", "var/log/jimmy/timings.txt",
+    ]);
+    assert_eq!(planned_artifacts(&script), ["/var/lib/jimmy/config.hash", "/var/log/jimmy/timings.txt", "/root/jimmy-report.json"]);
+}
+
+#[test]
+fn keep_report_only()
+{
+    let script = script(&[]);
+    assert_eq!(files_left(&script), ["root/jimmy-report.json"]);
+    assert_eq!(planned_artifacts(&script), ["/root/jimmy-report.json"]);
+    // the files are only removed once the installed system passed verification
+    let removal = script.find("\nrm -f /mnt/var/lib/jimmy/config.hash /mnt/var/log/jimmy/timings.txt\n").unwrap();
+    assert!(script.find("if [ \"$jimmy_failed\" -ne 0 ]").unwrap() < removal);
+    assert!(removal < script.find("\n! mountpoint -q /mnt || umount -R /mnt\n").unwrap());
+}
+
+#[test]
+fn remove_all()
+{
+    // a report would be removed as soon as it's written, so there's none
+    let script = script(&[
+        ("cleanup: keep-report-only\n", "cleanup: remove-all\n"),
+        ("report: /root/jimmy-report.json\n", ""),
+    ]);
+    // the report on the fake system isn't one of jimmy's, so it's left alone
+    assert_eq!(files_left(&script), ["root/jimmy-report.json"]);
+}
+
+#[test]
+fn remove_all_without_timings()
+{
+    let script = script(&[
+        ("cleanup: keep-report-only\n", "cleanup: remove-all\ntimings: false\n"),
+        ("report: /root/jimmy-report.json\n", ""),
+    ]);
+    assert!(script.contains("\nrm -f /mnt/var/lib/jimmy/config.hash\nrmdir --ignore-fail-on-non-empty /mnt/var/lib/jimmy\n"));
+    assert_eq!(files_left(&script), ["root/jimmy-report.json", "var/log/jimmy", "var/log/jimmy/timings.txt"]);
+}
@@ -0,0 +1,128 @@
+//! What the integration tests share: running jimmy on the sample configuration file, changed the
+//! way each of them needs, and running the shell code it generates with sh, with the programs it
+//! calls replaced by fake ones. Every test file uses only some of it
+#![allow(dead_code)]
+
+use std::io::Write;
+use std::os::unix::fs::PermissionsExt;
+use std::path::PathBuf;
+use std::process::{Command, Output, Stdio};
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+/// How many temporary paths this test binary asked for so far
+static TEMP_PATHS: AtomicUsize = AtomicUsize::new(0);
+
+/// Return a path in the temporary directory that no other test uses, ending with `what`
+pub fn temp_path(what: &str) -> PathBuf
+{
+    let n = TEMP_PATHS.fetch_add(1, Ordering::Relaxed);
+    std::env::temp_dir().join(format!("jimmy-test-{}-{}-{}", std::process::id(), n, what))
+}
+
+/// Return the sample configuration file
+pub fn sample() -> String
+{
+    String::from_utf8(Command::new(env!("CARGO_BIN_EXE_jimmy")).arg("--sample").output().unwrap().stdout).unwrap()
+}
+
+/// Return the sample configuration file, with each `(from, to)` replaced once and the given lines
+/// appended
+pub fn config(replacements: &[(&str, &str)], extra_lines: &str) -> String
+{
+    let mut sample = sample();
+    for (from, to) in replacements {
+        assert!(sample.contains(from), "the sample has no {:?}", from);
+        sample = sample.replacen(from, to, 1);
+    }
+    format!("{}\n{}", sample, extra_lines)
+}
+
+/// Return the arguments that run the command of `command` with the given flags: they follow the
+/// name of a subcommand, and come before `--file`, which the path of the configuration file is
+/// given to last
+pub fn with_flags<'a>(flags: &[&'a str], command: &[&'a str]) -> Vec<&'a str>
+{
+    match command.split_first() {
+        Some((name, rest)) if !name.starts_with('-') => [&[*name], flags, rest].concat(),
+        _ => [flags, command].concat(),
+    }
+}
+
+/// Run jimmy with `args` on a configuration file holding `config`
+pub fn jimmy(args: &[&str], config: &str) -> Output
+{
+    let path = temp_path("config.yaml");
+    std::fs::write(&path, config).unwrap();
+    let output = Command::new(env!("CARGO_BIN_EXE_jimmy")).args(args).arg(&path).output().unwrap();
+    std::fs::remove_file(&path).unwrap();
+    output
+}
+
+/// Run jimmy with `args` on the sample configuration file, changed as `config` does
+pub fn generate(args: &[&str], replacements: &[(&str, &str)], extra_lines: &str) -> Output
+{
+    jimmy(args, &config(replacements, extra_lines))
+}
+
+/// Return what jimmy printed, checking that it succeeded
+pub fn script(output: Output) -> String
+{
+    assert!(output.status.success(), "jimmy failed: {}", String::from_utf8_lossy(&output.stderr));
+    String::from_utf8(output.stdout).unwrap()
+}
+
+/// Return a script with the hash of the configuration file it's made from replaced by `<hash>`, so
+/// that it can be compared with the one of another file
+pub fn without_hash(script: &str) -> String
+{
+    let hash = script.lines().find_map(|l| l.strip_prefix("# configuration hash: ")).expect("the script has no hash");
+    script.replace(hash, "<hash>")
+}
+
+/// Return what jimmy printed to stderr, checking that it refused the configuration
+pub fn refusal(output: Output) -> String
+{
+    assert!(!output.status.success(), "jimmy succeeded: {}", String::from_utf8_lossy(&output.stdout));
+    String::from_utf8(output.stderr).unwrap()
+}
+
+/// Return what `jimmy api plan` answers about a configuration file, given to it as JSON
+pub fn api_plan(config: &str) -> serde_json::Value
+{
+    let config: serde_json::Value = serde_yaml::from_str(config).unwrap();
+    let mut child = Command::new(env!("CARGO_BIN_EXE_jimmy"))
+        .args(["api", "plan"])
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .unwrap();
+    child.stdin.take().unwrap().write_all(config.to_string().as_bytes()).unwrap();
+    serde_json::from_slice(&child.wait_with_output().unwrap().stdout).unwrap()
+}
+
+/// Run `code` with sh in a directory of its own, with the programs given as `(name, body)` found
+/// before the others and `input` on its stdin, and return whether it succeeded along with its
+/// stdout and stderr. `$DIR` names the directory in the programs and in `code`
+pub fn sh(code: &str, programs: &[(&str, &str)], input: &str) -> (bool, String, String)
+{
+    let dir = temp_path("sh");
+    std::fs::create_dir_all(dir.join("bin")).unwrap();
+    for (program, body) in programs {
+        let path = dir.join("bin").join(program);
+        std::fs::write(&path, format!("#!/bin/sh\n{}\n", body)).unwrap();
+        std::fs::set_permissions(&path, std::fs::Permissions::from_mode(0o755)).unwrap();
+    }
+    let mut child = Command::new("sh").args(["-c", code])
+        .env("PATH", format!("{}:{}", dir.join("bin").display(), std::env::var("PATH").unwrap()))
+        .env("DIR", &dir)
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .unwrap();
+    let _ = child.stdin.take().unwrap().write_all(input.as_bytes());
+    let output = child.wait_with_output().unwrap();
+    std::fs::remove_dir_all(&dir).unwrap();
+    (output.status.success(), String::from_utf8(output.stdout).unwrap(), String::from_utf8(output.stderr).unwrap())
+}
@@ -0,0 +1,96 @@
+//! Checks that the arch-chroot script refuses to run on a system installed from another
+//! configuration file. Only the check at the start of the script is ran, against a marker file in
+//! a temporary directory, so that nothing else is touched
+
+use std::process::{Command, Output};
+
+mod common;
+
+/// The configuration file the scripts are generated from
+const CONFIG: &str = "examples/valid--simple.yaml";
+
+/// Return the standard output of jimmy, ran with the given arguments
+fn jimmy(args: &[&str]) -> String
+{
+    common::script(Command::new(env!("CARGO_BIN_EXE_jimmy")).args(args).output().unwrap())
+}
+
+/// Return the hash the script records for the configuration file
+fn config_hash(script: &str) -> String
+{
+    script.lines()
+        .find_map(|l| l.strip_prefix("# configuration hash: "))
+        .expect("the script doesn't record the configuration hash")
+        .to_string()
+}
+
+/// Run the start of the arch-chroot script, up to its first status message, with the given
+/// contents in the marker file, if any, and the given extra environment variables. It prints
+/// `reached` if the script would've gone on
+fn run_check(marker: Option<&str>, envs: &[(&str, &str)]) -> Output
+{
+    let script = jimmy(&["chroot-script", CONFIG]);
+    let dir = common::temp_path("dir");
+    std::fs::create_dir_all(&dir).unwrap();
+    let path = dir.join("config.hash");
+    if let Some(marker) = marker {
+        std::fs::write(&path, format!("{}\n", marker)).unwrap();
+    }
+    let check = script.lines()
+        .take_while(|l| !l.starts_with("echo '"))
+        .collect::<Vec<&str>>()
+        .join("\n")
+        .replace("/var/lib/jimmy/config.hash", path.to_str().unwrap());
+    let output = Command::new("sh")
+        .args(["-c", &format!("{}\necho reached", check)])
+        .env_remove("JIMMY_IGNORE_CONFIG_HASH")
+        .envs(envs.iter().copied())
+        .output()
+        .unwrap();
+    std::fs::remove_dir_all(&dir).unwrap();
+    output
+}
+
+#[test]
+fn both_scripts_record_the_same_hash()
+{
+    let script = jimmy(&["--file", CONFIG]);
+    let hash = config_hash(&script);
+    assert!(script.contains(&format!("echo {} >/mnt/var/lib/jimmy/config.hash\n", hash)));
+    assert_eq!(config_hash(&jimmy(&["chroot-script", CONFIG])), hash);
+}
+
+#[test]
+fn matching_hash_continues()
+{
+    let hash = config_hash(&jimmy(&["chroot-script", CONFIG]));
+    let output = run_check(Some(&hash), &[]);
+    assert!(output.status.success());
+    assert_eq!(String::from_utf8(output.stdout).unwrap(), "reached\n");
+}
+
+#[test]
+fn missing_marker_continues()
+{
+    let output = run_check(None, &[]);
+    assert!(output.status.success());
+    assert_eq!(String::from_utf8(output.stdout).unwrap(), "reached\n");
+}
+
+#[test]
+fn mismatched_hash_aborts()
+{
+    let output = run_check(Some("0123456789abcdef"), &[]);
+    assert_eq!(output.status.code(), Some(1));
+    assert_eq!(String::from_utf8(output.stdout).unwrap(), "");
+    let stderr = String::from_utf8(output.stderr).unwrap();
+    assert!(stderr.contains("installed from a configuration file with the hash 0123456789abcdef"), "{}", stderr);
+}
+
+#[test]
+fn mismatched_hash_can_be_ignored()
+{
+    let output = run_check(Some("0123456789abcdef"), &[("JIMMY_IGNORE_CONFIG_HASH", "1")]);
+    assert!(output.status.success());
+    assert_eq!(String::from_utf8(output.stdout).unwrap(), "reached\n");
+}
@@ -0,0 +1,115 @@
+//! Checks where jimmy looks for a configuration file when it isn't given one, and where
+//! `--sample --install` writes the sample. Every test runs jimmy in a temporary directory of its
+//! own, which also serves as `HOME`
+
+use std::path::{Path, PathBuf};
+use std::process::{Command, Output};
+
+mod common;
+
+/// Return a new, empty temporary directory
+fn temp_dir() -> PathBuf
+{
+    let dir = common::temp_path("dir");
+    std::fs::create_dir_all(&dir).unwrap();
+    dir
+}
+
+/// Write the sample configuration file to the given path, with its hostname replaced
+fn write_config(path: &Path, hostname: &str)
+{
+    std::fs::create_dir_all(path.parent().unwrap()).unwrap();
+    std::fs::write(path, common::config(&[("hostname: archlinux", &format!("hostname: {}", hostname))], "")).unwrap();
+}
+
+/// Run jimmy with the given arguments in `dir`, with `dir` as `HOME` and the given
+/// `XDG_CONFIG_HOME`, if any
+fn jimmy(dir: &Path, xdg_config_home: Option<&Path>, args: &[&str]) -> Output
+{
+    let mut cmd = Command::new(env!("CARGO_BIN_EXE_jimmy"));
+    cmd.args(args).current_dir(dir).env("HOME", dir).env_remove("XDG_CONFIG_HOME");
+    if let Some(config_home) = xdg_config_home {
+        cmd.env("XDG_CONFIG_HOME", config_home);
+    }
+    cmd.output().unwrap()
+}
+
+/// Return the hostname set by the generated script
+fn hostname(output: &Output) -> String
+{
+    assert!(output.status.success(), "jimmy failed: {}", String::from_utf8_lossy(&output.stderr));
+    let script = String::from_utf8_lossy(&output.stdout);
+    let line = script.lines().find(|l| l.ends_with(">/etc/hostname")).expect("no hostname in the script");
+    line.split('\'').nth(1).unwrap().to_string()
+}
+
+#[test]
+fn current_directory_comes_first()
+{
+    let dir = temp_dir();
+    let config_home = dir.join("xdg");
+    write_config(&dir.join("jimmy.yaml"), "fromcwd");
+    write_config(&config_home.join("jimmy/config.yaml"), "fromxdg");
+    let output = jimmy(&dir, Some(&config_home), &[]);
+    assert_eq!(hostname(&output), "fromcwd");
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(stderr.contains(&format!("using the configuration file {}\n", dir.join("jimmy.yaml").display())), "{}", stderr);
+    std::fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn xdg_config_home_comes_second()
+{
+    let dir = temp_dir();
+    let config_home = dir.join("xdg");
+    write_config(&config_home.join("jimmy/config.yaml"), "fromxdg");
+    write_config(&dir.join(".config/jimmy/config.yaml"), "fromhome");
+    assert_eq!(hostname(&jimmy(&dir, Some(&config_home), &[])), "fromxdg");
+    std::fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn home_config_without_xdg_config_home()
+{
+    let dir = temp_dir();
+    write_config(&dir.join(".config/jimmy/config.yaml"), "fromhome");
+    assert_eq!(hostname(&jimmy(&dir, None, &[])), "fromhome");
+    // a relative XDG_CONFIG_HOME is ignored
+    assert_eq!(hostname(&jimmy(&dir, Some(Path::new("relative")), &[])), "fromhome");
+    std::fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn no_config_lists_the_searched_paths()
+{
+    if Path::new("/etc/jimmy/config.yaml").exists() {
+        eprintln!("skipping: /etc/jimmy/config.yaml exists on this machine");
+        return;
+    }
+    let dir = temp_dir();
+    let output = jimmy(&dir, None, &[]);
+    assert_eq!(output.status.code(), Some(1));
+    assert_eq!(
+        String::from_utf8_lossy(&output.stderr),
+        format!(
+            "error: no configuration file given, and none found at any of:\n    {}\n    {}\n    /etc/jimmy/config.yaml\n",
+            dir.join("jimmy.yaml").display(),
+            dir.join(".config/jimmy/config.yaml").display(),
+        ),
+    );
+    std::fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn sample_install_writes_to_xdg_config_home()
+{
+    let dir = temp_dir();
+    let config_home = dir.join("xdg");
+    let output = jimmy(&dir, Some(&config_home), &["--sample", "--install"]);
+    assert!(output.status.success(), "jimmy failed: {}", String::from_utf8_lossy(&output.stderr));
+    assert_eq!(hostname(&jimmy(&dir, Some(&config_home), &[])), "archlinux");
+    // an existing file isn't overwritten
+    let output = jimmy(&dir, Some(&config_home), &["--sample", "--install"]);
+    assert_eq!(output.status.code(), Some(1));
+    std::fs::remove_dir_all(&dir).unwrap();
+}
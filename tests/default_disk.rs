@@ -0,0 +1,55 @@
+//! Checks the top-level `disk`, which every partition that doesn't have a `disk` of its own is
+//! made on
+
+use std::process::Output;
+
+mod common;
+
+/// Generate the script from the sample configuration file, with the hashes of the configuration
+/// files left out, after every replacement was made to it
+fn generate(replacements: &[(&str, &str)]) -> Output
+{
+    common::generate(&["--reproducible", "--file"], replacements, "")
+}
+
+/// Return the script jimmy generated, without the hash of the configuration file it came from
+fn script(output: Output) -> String
+{
+    let hash = regex::Regex::new("[0-9a-f]{16}").unwrap();
+    common::script(output).lines()
+        .map(|l| if l.contains("hash") { hash.replace_all(l, "HASH").to_string() } else { l.to_string() })
+        .collect::<Vec<String>>()
+        .join("\n")
+}
+
+#[test]
+fn same_script_as_with_a_disk_on_every_partition()
+{
+    let short = script(generate(&[]));
+    let long = script(generate(&[
+        ("\ndisk: /dev/sda\n", "\n"),
+        ("    mount: /boot\n", "    mount: /boot\n    disk: /dev/sda\n"),
+        ("    mount: /\n", "    mount: /\n    disk: /dev/sda\n"),
+    ]));
+    assert_eq!(short, long);
+    assert!(short.contains("| fdisk /dev/sda"));
+}
+
+#[test]
+fn partitions_can_override_it()
+{
+    let script = script(generate(&[("    mount: /\n", "    mount: /\n    disk: /dev/sdb\n")]));
+    let fdisk: Vec<&str> = script.lines()
+        .filter_map(|l| l.split_once("| fdisk ").map(|(_, rest)| rest.split(' ').next().unwrap()))
+        .collect();
+    assert_eq!(fdisk, ["/dev/sda", "/dev/sdb"]);
+}
+
+#[test]
+fn a_disk_is_needed_somewhere()
+{
+    let output = generate(&[("\ndisk: /dev/sda\n", "\n")]);
+    assert!(!output.status.success());
+    assert!(String::from_utf8_lossy(&output.stderr)
+        .contains("partition disk not specified, and there's no `disk` for every partition"));
+}
@@ -0,0 +1,83 @@
+//! Checks the systemd target the installed system boots into: the one given by `default_target`,
+//! or else the one that goes with the desktops and display managers being installed, and the
+//! warning about a display manager that would never start
+
+use std::process::Output;
+
+mod common;
+
+/// Generate the arch-chroot script from the sample configuration file, with the given lines
+/// appended
+fn generate(extra_lines: &str) -> Output
+{
+    common::generate(&["chroot-script"], &[], extra_lines)
+}
+
+/// Return the target the script sets as the default, along with the warnings, checking that jimmy
+/// succeeded
+fn default_target(extra_lines: &str) -> (String, String)
+{
+    let output = generate(extra_lines);
+    let stderr = String::from_utf8(output.stderr.clone()).unwrap();
+    let script = common::script(output);
+    let cmds: Vec<&str> = script.lines().filter(|l| l.starts_with("systemctl set-default ")).collect();
+    assert_eq!(cmds.len(), 1, "{}", extra_lines);
+    (cmds[0].trim_start_matches("systemctl set-default ").to_string(), stderr)
+}
+
+const WARNING: &str = "warning: `default_target: multi-user`, but the display manager";
+
+#[test]
+fn derived()
+{
+    for (lines, target) in [
+        ("", "multi-user.target"),
+        ("packages: [ vim, openssh ]\n", "multi-user.target"),
+        ("packages: [ plasma ]\n", "graphical.target"),
+        ("packages: [ gnome, gdm ]\n", "graphical.target"),
+        ("packages: [ xorg-server, lightdm ]\n", "graphical.target"),
+        ("packages: [ sway, greetd ]\n", "graphical.target"),
+    ] {
+        let (found, stderr) = default_target(lines);
+        assert_eq!(found, target, "{}", lines);
+        assert!(!stderr.contains(WARNING), "{}", stderr);
+    }
+}
+
+#[test]
+fn explicit()
+{
+    for (lines, target) in [
+        ("default_target: graphical\n", "graphical.target"),
+        ("default_target: graphical.target\n", "graphical.target"),
+        ("default_target: multi-user\npackages: [ plasma ]\n", "multi-user.target"),
+        ("default_target: rescue\n", "rescue.target"),
+        ("default_target: rescue\npackages: [ gnome, gdm ]\n", "rescue.target"),
+    ] {
+        let (found, stderr) = default_target(lines);
+        assert_eq!(found, target, "{}", lines);
+        assert!(!stderr.contains(WARNING), "{}", stderr);
+    }
+}
+
+#[test]
+fn display_manager_on_multi_user()
+{
+    for (lines, manager) in [("default_target: multi-user\npackages: [ plasma, sddm ]\n", "sddm"), ("default_target: multi-user.target\npackages: [ ly ]\n", "ly")] {
+        let (found, stderr) = default_target(lines);
+        assert_eq!(found, "multi-user.target");
+        assert!(stderr.contains(&format!("{} {} is installed, and it only starts on graphical.target; use `default_target: graphical`", WARNING, manager)), "{}", stderr);
+    }
+}
+
+#[test]
+fn invalid()
+{
+    for target in ["emergency", "graphical.service", "\"\""] {
+        let output = generate(&format!("default_target: {}\n", target));
+        assert!(!output.status.success(), "{}", target);
+        assert!(String::from_utf8_lossy(&output.stderr).contains("(expected one of: graphical, multi-user, rescue)"), "{}", target);
+    }
+    let output = generate("default_target: emergency\n");
+    assert!(String::from_utf8_lossy(&output.stderr).contains("invalid default_target: \"emergency\" (expected one of: graphical, multi-user, rescue)"));
+}
@@ -0,0 +1,79 @@
+//! Checks the actions of the arch-chroot script that are deferred until every part that needs them
+//! is done: each is carried out once, after the last part that needs it, and the GRUB
+//! configuration is generated after both the edits of /etc/default/grub and the initramfs it
+//! lists
+
+mod common;
+
+/// Hooks that need the initramfs to be rebuilt once they're set, with plymouth drawing the splash
+/// screen in it
+const HOOKS: &str = "mkinitcpio_hooks: [ base, udev, plymouth, autodetect, microcode, modconf, kms, keyboard, keymap, consolefont, block, encrypt, filesystems, fsck ]\n";
+
+/// Return the arch-chroot script of the sample configuration file booted with `bootloader`, with
+/// an encrypted root partition and `keymap: de`, which both need the initramfs rebuilt, and the
+/// given lines appended
+fn chroot_script(bootloader: &str, extra_lines: &str) -> String
+{
+    common::script(common::generate(&["chroot-script"], &[
+        ("bootloader: grub\n", &format!("bootloader: {}\n", bootloader)),
+        ("packages: vim\n", "packages: vim plymouth\n"),
+        ("    mount: /\n", "    mount: /\n    encryption: {}\n"),
+    ], &format!("keymap: de\nkernel_params: [ quiet, splash ]\n{}", extra_lines)))
+}
+
+/// Return the numbers of the lines of `script` that contain `text`
+fn lines(script: &str, text: &str) -> Vec<usize>
+{
+    script.lines().enumerate().filter(|(_, l)| l.contains(text)).map(|(i, _)| i).collect()
+}
+
+#[test]
+fn grub()
+{
+    let script = chroot_script("grub", HOOKS);
+    let rebuild = lines(&script, "mkinitcpio -P");
+    let mkconfig = lines(&script, "grub-mkconfig");
+    assert_eq!(rebuild.len(), 1, "{}", script);
+    assert_eq!(mkconfig.len(), 1, "{}", script);
+    // after both parts that need the initramfs rebuilt
+    assert!(lines(&script, "KEYMAP=de")[0] < rebuild[0]);
+    assert!(lines(&script, "HOOKS=(base udev plymouth ")[0] < rebuild[0]);
+    // the GRUB configuration lists the rebuilt images, with the parameters of /etc/default/grub
+    assert!(rebuild[0] < mkconfig[0]);
+    let edits = lines(&script, "/etc/default/grub");
+    assert!(!edits.is_empty());
+    assert!(edits.iter().all(|e| *e < mkconfig[0]), "{}", script);
+    assert!(script.contains("quiet splash"));
+    assert!(script.trim_end().ends_with("grub-mkconfig -o /boot/grub/grub.cfg\n\necho '<chroot> the system is configured'"), "{}", script);
+}
+
+#[test]
+fn efistub()
+{
+    // the boot entry loads the rebuilt images
+    let script = chroot_script("efistub", HOOKS);
+    let rebuild = lines(&script, "mkinitcpio -P");
+    let entry = lines(&script, "efibootmgr --disk /dev/sda --part 1 --create");
+    assert_eq!((rebuild.len(), entry.len()), (1, 1), "{}", script);
+    assert!(rebuild[0] < entry[0]);
+    assert!(script.contains("\necho '<chroot> rebuilding the initramfs...'\nmkinitcpio -P\n\necho '<chroot> creating the boot entry...'\n"), "{}", script);
+    assert!(!script.contains("grub-mkconfig"));
+}
+
+#[test]
+fn only_when_needed()
+{
+    // with the encrypted root partition, the default hooks are set, and the images rebuilt once
+    let script = chroot_script("grub", "");
+    assert_eq!(lines(&script, "mkinitcpio -P").len(), 1);
+    assert!(lines(&script, "HOOKS=(base systemd ")[0] < lines(&script, "mkinitcpio -P")[0]);
+
+    // the keymap only changes the images if passphrases are typed in them
+    let script = common::script(common::generate(&["chroot-script"], &[], "keymap: de\n"));
+    assert!(script.contains("KEYMAP=de") && lines(&script, "mkinitcpio").is_empty());
+
+    // pacstrap builds the images of the sample, which has nothing that changes them
+    let script = common::script(common::generate(&["chroot-script"], &[], ""));
+    assert!(lines(&script, "mkinitcpio").is_empty());
+    assert_eq!(lines(&script, "grub-mkconfig").len(), 1);
+}
@@ -0,0 +1,119 @@
+//! Checks the `pre_format` commands of the disks and the `post_format` commands of the partitions:
+//! the steps they're ran in, the `$DEVICE` they're given, and how they're ran, with sh
+
+use std::process::{Command, Output};
+
+mod common;
+
+/// Generate the script from the sample configuration file, with the given lines added to its root
+/// partition and appended to it
+fn generate(root_lines: &str, extra_lines: &str) -> Output
+{
+    common::generate(&["--file"], &[("    mount: /\n", &format!("    mount: /\n{}", root_lines))], extra_lines)
+}
+
+/// Return the script, checking that jimmy succeeded
+fn script(root_lines: &str, extra_lines: &str) -> String
+{
+    common::script(generate(root_lines, extra_lines))
+}
+
+/// Return the names of the steps of the script, in order
+fn steps(script: &str) -> Vec<&str>
+{
+    script.lines().filter_map(|l| l.strip_prefix("JIMMY_STEP=")).map(|s| s.trim_matches('\'')).collect()
+}
+
+/// Return the commands of a step, without its status message and the lines that time it
+fn step<'a>(script: &'a str, name: &str) -> &'a str
+{
+    let start = script.find(&format!("JIMMY_STEP={}\n", name)).unwrap();
+    let body = script[start..].split_once('\n').unwrap().1;
+    &body[..body.find("\njimmy_time ").unwrap()]
+}
+
+const PRE_FORMAT: &str = "disks:\n  /dev/sda:\n    pre_format:\n      - blkdiscard -f \"$DEVICE\"\n";
+const POST_FORMAT: &str = "    post_format:\n      - tune2fs -m 1 \"$DEVICE\"\n";
+
+#[test]
+fn steps_come_in_order()
+{
+    let script = script(POST_FORMAT, PRE_FORMAT);
+    let names = steps(&script);
+    let at = |name: &str| names.iter().position(|s| *s == name).unwrap();
+    assert_eq!(at("pre-format") + 1, at("partitioning"));
+    assert_eq!(at("formatting") + 1, at("post-format"));
+    assert_eq!(at("post-format") + 1, at("mounting"));
+    assert!(script.contains("\necho '<-> running the commands given for the disks...'\n"));
+
+    // the encrypted partitions are opened, then formatted, before the commands are ran
+    let script = self::script(&format!("{}    encryption: {{}}\n", POST_FORMAT), "");
+    let names = steps(&script);
+    let at = |name: &str| names.iter().position(|s| *s == name).unwrap();
+    assert_eq!([at("partitioning") + 1, at("encryption") + 1, at("formatting") + 1], [at("encryption"), at("formatting"), at("post-format")]);
+    assert!(!names.contains(&"pre-format"));
+    assert!(step(&script, "post-format").starts_with("(\nDEVICE=/dev/mapper/cryptroot\nexport DEVICE\n"));
+
+    let script = self::script("", "");
+    assert!(!script.contains("JIMMY_STEP=pre-format") && !script.contains("JIMMY_STEP=post-format"));
+}
+
+#[test]
+fn device()
+{
+    let script = script(POST_FORMAT, PRE_FORMAT);
+    assert_eq!(step(&script, "pre-format"), "(\nDEVICE=/dev/sda\nexport DEVICE\nset -e\nblkdiscard -f \"$DEVICE\"\n)\n\
+        if [ $? -ne 0 ]; then\n    echo 'error: the pre_format commands of /dev/sda failed' >&2\n    exit 1\nfi");
+    assert!(step(&script, "post-format").starts_with("(\nDEVICE=/dev/sda2\n"));
+
+    let stable = |s: &str| s.replace("/dev/sda", "by-id:nvme-DISK");
+    let script = self::script(POST_FORMAT, &format!("{}{}", stable(PRE_FORMAT), stable("disk: /dev/sda\n")));
+    assert!(step(&script, "pre-format").starts_with("(\nDEVICE=$JIMMY_DISK_by_id_nvme_DISK\n"));
+    assert!(step(&script, "post-format").starts_with("(\nDEVICE=${JIMMY_DISK_by_id_nvme_DISK_PART}2\n"));
+}
+
+#[test]
+fn ran_with_the_device()
+{
+    let cmds = "      - 'printf \"%s|\" \"$DEVICE\" >>\"$JIMMY_TEST_OUT\"'\n      - sh -c 'printf \"%s|\" \"$DEVICE\"' >>\"$JIMMY_TEST_OUT\"\n      - |\n        cat <<'EOF' >>\"$JIMMY_TEST_OUT\"\n        $DEVICE 'as written'\n        EOF\n";
+    let script = script(&format!("    post_format:\n{}", cmds), "");
+    let out = common::temp_path("out");
+    let run = |code: &str| {
+        let _ = std::fs::remove_file(&out);
+        let output = Command::new("sh").args(["-c", code]).env("JIMMY_TEST_OUT", &out).output().unwrap();
+        (output, std::fs::read_to_string(&out).unwrap_or_default())
+    };
+    let (output, written) = run(&format!("{}\necho \"left: $DEVICE\"", step(&script, "post-format")));
+    assert!(output.status.success());
+    assert_eq!(written, "/dev/sda2|/dev/sda2|$DEVICE 'as written'\n");
+    // the variable doesn't leak into the rest of the script
+    assert_eq!(String::from_utf8(output.stdout).unwrap(), "left: \n");
+
+    // the first command that fails stops them, and the script
+    let script = self::script("    post_format:\n      - \"false\"\n      - echo ran >>\"$JIMMY_TEST_OUT\"\n", "");
+    let (output, written) = run(&format!("{}\necho after", step(&script, "post-format")));
+    assert_eq!(output.status.code(), Some(1));
+    assert_eq!(written, "");
+    assert_eq!(String::from_utf8(output.stderr).unwrap(), "error: the post_format commands of /dev/sda2 failed\n");
+    assert_eq!(String::from_utf8(output.stdout).unwrap(), "");
+    let _ = std::fs::remove_file(&out);
+}
+
+#[test]
+fn disks()
+{
+    // a disk declared only for its commands doesn't need a size
+    let script = script("", PRE_FORMAT);
+    assert!(!script.contains("# disk /dev/sda: "));
+    assert!(script.contains("JIMMY_STEP=pre-format"));
+
+    let output = generate("", &PRE_FORMAT.replace("/dev/sda", "/dev/sdb"));
+    assert!(output.status.success());
+    assert!(String::from_utf8_lossy(&output.stderr)
+        .contains("warning: disk /dev/sdb has `pre_format` commands, but there are no partitions on it; they're going to be ignored"));
+    assert!(!String::from_utf8(output.stdout).unwrap().contains("JIMMY_STEP=pre-format"));
+
+    let output = generate("    format: btrfs\n    raid_profile: raid1\n    members:\n      - disk: /dev/sdb\n        post_format: [ \"true\" ]\n", "");
+    assert!(!output.status.success());
+    assert!(String::from_utf8_lossy(&output.stderr).contains("has `post_format`, but members are formatted and mounted along with the filesystem"));
+}
@@ -0,0 +1,80 @@
+//! Checks the directories created on the installed system: how their paths and modes are read and
+//! checked, and where they're created in the arch-chroot script
+
+use std::process::Output;
+
+mod common;
+
+/// Generate the arch-chroot script from the sample configuration file, with the given
+/// `directories` appended
+fn chroot_script(directories: &str) -> Output
+{
+    common::generate(&["chroot-script"], &[], &format!("directories:\n{}", directories))
+}
+
+/// Return the command that creates the first directory
+fn install_cmd(directories: &str) -> String
+{
+    common::script(chroot_script(directories)).lines()
+        .find(|l| l.starts_with("install -d"))
+        .expect("no directory is created")
+        .to_string()
+}
+
+/// Return what jimmy complains about when it refuses the directories
+fn refusal(directories: &str) -> String
+{
+    common::refusal(chroot_script(directories))
+}
+
+#[test]
+fn created_after_the_users()
+{
+    let script = common::script(chroot_script("  - path: /srv/data\n    owner: archie\n    group: wheel\n    mode: \"750\"\n"));
+    let install = script.find("\ninstall -d -m 0750 -o archie -g wheel /srv/data\n").expect("no install in the script");
+    assert!(script.find("\nid -u archie >/dev/null 2>&1 || useradd -m archie ").unwrap() < install);
+}
+
+#[test]
+fn paths_are_normalized()
+{
+    assert_eq!(install_cmd("  - path: //srv///data/\n"), "install -d /srv/data");
+    assert_eq!(install_cmd("  - path: \"/srv/my data\"\n"), "install -d '/srv/my data'");
+}
+
+#[test]
+fn bad_paths_are_refused()
+{
+    assert!(refusal("  - path: srv/data\n").contains("directory path is a relative path: \"srv/data\""));
+    assert!(refusal("  - path: /srv/../etc\n").contains("directory path must not have `.` or `..` in it"));
+    assert!(refusal("  - path: //\n").contains("directory path can't be the root directory"));
+    assert!(refusal("  - path: /srv\n  - path: /srv/\n").contains("directory /srv is listed more than once"));
+}
+
+#[test]
+fn modes_are_octal_digits()
+{
+    assert_eq!(install_cmd("  - path: /persist\n    mode: \"0700\"\n"), "install -d -m 0700 /persist");
+    assert_eq!(install_cmd("  - path: /persist\n    mode: 755\n"), "install -d -m 0755 /persist");
+    assert_eq!(install_cmd("  - path: /persist\n    mode: \"1777\"\n"), "install -d -m 1777 /persist");
+    assert_eq!(install_cmd("  - path: /persist\n    mode: \"0o750\"\n"), "install -d -m 0750 /persist");
+    assert_eq!(install_cmd("  - path: /persist\n    mode: 0750\n"), "install -d -m 0750 /persist");
+}
+
+#[test]
+fn bad_modes_are_refused()
+{
+    for mode in ["\"0790\"", "75", "\"07555\"", "rwx"] {
+        let stderr = refusal(&format!("  - path: /persist\n    mode: {}\n", mode));
+        assert!(stderr.contains("invalid mode for /persist"), "{}", stderr);
+    }
+}
+
+#[test]
+fn owners()
+{
+    let output = chroot_script("  - path: /srv/http\n    owner: http\n");
+    assert!(output.status.success());
+    assert!(String::from_utf8_lossy(&output.stderr).contains("directory /srv/http is owned by 'http', who isn't one of the `users`"));
+    assert!(refusal("  - path: /srv\n    owner: \"a b\"\n").contains("invalid owner or group of directory /srv: \"a b\""));
+}
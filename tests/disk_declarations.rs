@@ -0,0 +1,51 @@
+//! Checks how the disks declared under `disks` are reconciled with those the partitions are on:
+//! declared disks without partitions, and partitions on disks that aren't declared
+
+mod common;
+
+/// Generate the script from the sample configuration file, with its root partition on
+/// /dev/sdb and the given lines appended, and return whether jimmy succeeded along with what
+/// `--verbose` printed
+fn generate(extra_lines: &str) -> (bool, String)
+{
+    let output = common::generate(&["--verbose", "--file"], &[("    mount: /\n", "    mount: /\n    disk: /dev/sdb\n")], extra_lines);
+    (output.status.success(), String::from_utf8(output.stderr).unwrap())
+}
+
+const UNPARTITIONED: &str = "disk /dev/sdc is declared under `disks`, but there are no partitions on it; \
+    remove it, or fix the `disk` of its partitions";
+
+#[test]
+fn every_disk_is_planned()
+{
+    let (success, stderr) = generate("disks:\n  /dev/sda:\n    size: 64GB\n");
+    assert!(success, "{}", stderr);
+    assert!(stderr.contains("\ndisks: /dev/sda, /dev/sdb\n\
+        \x20   disk /dev/sda is 64GB, of which 59.6G can be partitioned\n\
+        \x20   disk /dev/sdb isn't declared under `disks`, so it gets a GPT partition table without its size being checked\n\
+        steps: "), "{}", stderr);
+}
+
+#[test]
+fn the_rest_of_a_declared_disk()
+{
+    let (success, stderr) = generate("disks:\n  /dev/sdb:\n    size: 64GB\n    min_remaining: 20G\n");
+    assert!(success, "{}", stderr);
+    assert!(stderr.contains("\n    disk /dev/sdb is 64GB, of which 59.6G can be partitioned; \
+        the partition mounted at / gets the remaining 59.6G (at least 20G expected)\n"), "{}", stderr);
+    assert!(stderr.contains("\n    disk /dev/sda isn't declared under `disks`"));
+}
+
+#[test]
+fn declared_disks_without_partitions()
+{
+    let lines = "disks:\n  /dev/sdc:\n    size: 64GB\n";
+    let (success, stderr) = generate(lines);
+    assert!(success, "{}", stderr);
+    assert!(stderr.contains(&format!("warning: {}\n", UNPARTITIONED)), "{}", stderr);
+    assert!(stderr.contains("\ndisks: /dev/sda, /dev/sdb\n"));
+
+    let (success, stderr) = generate(&format!("{}strict: true\n", lines));
+    assert!(!success);
+    assert!(stderr.contains(UNPARTITIONED), "{}", stderr);
+}
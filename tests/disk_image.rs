@@ -0,0 +1,185 @@
+//! Checks the installations onto disk image files: the partitions are all reached through the loop
+//! device the image is attached to, from the live system and from inside arch-chroot, the image is
+//! detached at the end, and the configurations that would touch real disks or boot through the
+//! loop device are refused. The commands attaching the image are ran with sh, with losetup and
+//! truncate replaced by programs recording what they're asked to do
+
+use std::os::unix::fs::PermissionsExt;
+use std::process::{Command, Output, Stdio};
+
+mod common;
+
+/// Run jimmy with `args` on the sample configuration file, with its disk replaced by `disk` and
+/// the given lines appended
+fn generate(args: &[&str], disk: &str, extra_lines: &str) -> Output
+{
+    common::generate(args, &[("\ndisk: /dev/sda\n", &format!("\ndisk: {}\n", disk))], extra_lines)
+}
+
+/// Return what jimmy printed, checking that it succeeded
+fn generated(args: &[&str], disk: &str, extra_lines: &str) -> String
+{
+    common::script(generate(args, disk, extra_lines))
+}
+
+/// Return the commands of the step of the script named `step`, without its status message
+fn step<'a>(script: &'a str, step: &str) -> &'a str
+{
+    let start = script.find(&format!("\nJIMMY_STEP={}\n", step)).unwrap() + 1;
+    let start = start + script[start..].find('\n').unwrap() + 1;
+    let end = start + script[start..].find("\n\n").unwrap_or(script.len() - start);
+    &script[start..end]
+}
+
+/// Run `code` with sh in a temporary directory, where losetup prints `/dev/loop7` and truncate
+/// does nothing but append what they're asked to do to a log; `image` is created there first if
+/// it's given. Return the log, and what the code printed or, if it failed, the error it gave
+fn run(code: &str, image: Option<&str>) -> (String, Result<String, String>)
+{
+    let dir = common::temp_path("dir");
+    std::fs::create_dir_all(dir.join("bin")).unwrap();
+    for (program, output) in [("losetup", "echo /dev/loop7\n"), ("truncate", "")] {
+        let path = dir.join("bin").join(program);
+        std::fs::write(&path, format!("#!/bin/sh\necho \"{} $*\" >>\"$LOG\"\n{}", program, output)).unwrap();
+        std::fs::set_permissions(&path, std::fs::Permissions::from_mode(0o755)).unwrap();
+    }
+    if let Some(image) = image {
+        std::fs::write(dir.join(image), "").unwrap();
+    }
+    let output = Command::new("sh").args(["-c", code])
+        .current_dir(&dir)
+        .env("PATH", format!("{}:{}", dir.join("bin").display(), std::env::var("PATH").unwrap()))
+        .env("LOG", dir.join("log"))
+        .stdin(Stdio::null())
+        .output()
+        .unwrap();
+    let log = std::fs::read_to_string(dir.join("log")).unwrap_or_default();
+    std::fs::remove_dir_all(&dir).unwrap();
+    let result = match output.status.success() {
+        true => Ok(String::from_utf8(output.stdout).unwrap()),
+        false => Err(String::from_utf8(output.stderr).unwrap()),
+    };
+    (log, result)
+}
+
+const IMAGE: &str = "image:\n  size: 20G\n  create: true\n";
+
+/// The variables that hold the loop device of ./test.img, and the prefix of its partitions
+const VAR: &str = "JIMMY_DISK_test_img";
+
+#[test]
+fn attached()
+{
+    let script = generated(&["--file"], "./test.img", IMAGE);
+    let resolve = step(&script, "'resolve disks'");
+    assert_eq!(resolve, format!("truncate --size=0 test.img && truncate --size=21474836480 test.img \
+        || {{ echo 'error: disk image test.img could not be created' >&2; exit 1; }}\n\
+        {0}=$(losetup --find --show --partscan test.img) || {{ echo 'error: disk image test.img could not be attached to a loop device' >&2; exit 1; }}\n\
+        {0}_PART=\"${{{0}}}p\"", VAR));
+
+    let echo = format!("{}\necho \"${}\" \"${}_PART\"1", resolve, VAR, VAR);
+    assert_eq!(run(&echo, None), (
+        "truncate --size=0 test.img\ntruncate --size=21474836480 test.img\nlosetup --find --show --partscan test.img\n".to_string(),
+        Ok("/dev/loop7 /dev/loop7p1\n".to_string()),
+    ));
+
+    // an image that isn't created has to be there already
+    let script = generated(&["--file"], "./test.img", "image:\n  size: 20G\n");
+    let resolve = step(&script, "'resolve disks'");
+    assert!(resolve.starts_with("[ -f test.img ] || { echo 'error: disk image test.img not found' >&2; exit 1; }\n"));
+    assert_eq!(run(resolve, Some("test.img")).0, "losetup --find --show --partscan test.img\n");
+    assert_eq!(run(resolve, None), (String::new(), Err("error: disk image test.img not found\n".to_string())));
+}
+
+#[test]
+fn partitions()
+{
+    // every command that touches a partition, on the live system and inside arch-chroot, goes
+    // through the loop device, and the initramfs finds the encrypted root partition by UUID
+    let lines = format!("{}partitions:\n  - esp:\n    format: fat32\n    size: 500M\n    mount: /boot\n  \
+        - root:\n    format: ext4\n    mount: /\n    encryption:\n      passphrase: prompt\n", IMAGE);
+    let script = generated(&["--width", "0", "--file"], "./test.img", &lines);
+    assert!(!script.contains("test.img1") && !script.contains("test.img2") && !script.contains("/dev/sda"));
+    assert!(script.contains(&format!("\nmkfs.fat -F 32 ${{{}_PART}}1 || exit 1\n", VAR)));
+    assert!(script.contains(&format!("cryptsetup open ${{{}_PART}}2", VAR)));
+    assert!(script.contains(&format!("\nmount ${{{}_PART}}1 /mnt/boot", VAR)) || script.contains(&format!("&& mount ${{{}_PART}}1 /mnt/boot", VAR)));
+    assert!(script.contains(&format!("blkid -s UUID -o value ${{{}_PART}}2", VAR)));
+    // the arch-chroot script is given the variables it uses
+    assert!(script.contains(&format!("\narch-chroot /mnt env {0}_PART=\"${0}_PART\" ./jimmy_part2.sh\n", VAR)));
+    let nspawn = generated(&["--file"], "./test.img", &format!("{}chroot_backend: nspawn\n", lines));
+    assert!(nspawn.contains(&format!(" env {0}_PART=\"${0}_PART\" /jimmy_part2.sh\n", VAR)));
+    // GRUB doesn't register itself in the firmware of the machine installing the image
+    assert!(script.contains("grub-install --target=x86_64-efi --efi-directory=/boot --bootloader-id=GRUB --recheck --removable --no-nvram\n"));
+
+    assert!(step(&script, "unmount").ends_with(&format!("cryptsetup close cryptroot\nlosetup --detach \"${}\"", VAR)));
+    // nothing changes for real disks
+    let script = generated(&["--file"], "/dev/sda", "");
+    assert!(!script.contains("losetup") && !script.contains("--removable") && script.contains("\narch-chroot /mnt ./jimmy_part2.sh\n"));
+}
+
+#[test]
+fn alongside_real_disks()
+{
+    let lines = "partitions:\n  - esp:\n    format: fat32\n    size: 500M\n    mount: /boot\n  \
+        - root:\n    format: ext4\n    mount: /\n  - data:\n    disk: TARGET\n    format: ext4\n    mount: /srv\n";
+    for target in ["/dev/sdb", "by-id:ata-SAMSUNG_SSD", "/var/tmp/data.img"] {
+        let lines = lines.replace("TARGET", target);
+        let output = generate(&["--file"], "./test.img", &format!("{}{}", IMAGE, lines));
+        if target.ends_with(".img") {
+            assert!(output.status.success());
+            continue;
+        }
+        assert!(!output.status.success(), "{}", target);
+        assert!(String::from_utf8_lossy(&output.stderr).contains(&format!("the disk image test.img would be installed along with the real disk {}; \
+            set `allow_real_disks: true` under `image` if that's what's meant", target)), "{}", String::from_utf8_lossy(&output.stderr));
+
+        let script = generated(&["--file"], "./test.img", &format!("{}  allow_real_disks: true\n{}", IMAGE, lines));
+        // the real disk keeps its own files, and isn't handed to the arch-chroot script
+        let data = match target {
+            "/dev/sdb" => "/dev/sdb1".to_string(),
+            _ => "${JIMMY_DISK_by_id_ata_SAMSUNG_SSD_PART}1".to_string(),
+        };
+        assert!(script.contains(&format!("\nmkfs.ext4 {} || exit 1\n", data)), "{}", target);
+        assert!(script.contains(&format!("\nmkfs.ext4 ${{{}_PART}}2 || exit 1\n", VAR)));
+        assert!(script.contains(&format!("\narch-chroot /mnt env {0}_PART=\"${0}_PART\" ./jimmy_part2.sh\n", VAR)));
+        assert_eq!(script.matches("losetup --detach").count(), 1);
+    }
+    // two images are attached and detached each
+    let script = generated(&["--file"], "./test.img", &format!("{}{}", IMAGE, lines.replace("TARGET", "/var/tmp/data.img")));
+    assert!(script.contains("losetup --detach \"$JIMMY_DISK_test_img\"\nlosetup --detach \"$JIMMY_DISK__var_tmp_data_img\""));
+    assert!(script.contains("\ntruncate --size=0 /var/tmp/data.img && "));
+}
+
+#[test]
+fn invalid()
+{
+    for (disk, lines, message) in [
+        ("./test.img", "", "disk \"test.img\" isn't a device under /dev; use e.g. \"/dev/sda\", or set `image` to install onto a disk image file, \
+            e.g. `image: { size: 20G, create: true }`"),
+        ("/var/tmp/test.img", "", "disk \"/var/tmp/test.img\" isn't a device under /dev"),
+        ("./test.img", "image:\n  create: true\n", "`image` has `create: true`, but no `size` to create the files with"),
+        ("./test.img", "image:\n  size: big\n", "invalid size of image: \"big\" (expected e.g. '20G')"),
+        ("./test.img", "image:\n  size: 400M\n", "the partitions on disk test.img need"),
+        ("./test.img", "image:\n  size: 20G\ndisks:\n  test.img:\n    size: 30G\n",
+            "disk test.img is declared under `disks` with a size of 30G, but the size of `image` is 20G; remove one of the two"),
+        ("./test.img", &format!("{}bootloader: systemd-boot\n", IMAGE), "`bootloader: systemd-boot` can't boot from a disk image: its boot entry would find \
+            the root partition by the loop device it's on while installing; use `bootloader: grub`, which finds it by UUID"),
+        ("./test.img", &format!("{}bootloader: efistub\n", IMAGE), "`bootloader: efistub` can't boot from a disk image"),
+        ("./test.img", &format!("{}mkinitcpio_hooks: [ base, udev, autodetect, modconf, block, encrypt, filesystems, fsck ]\n\
+            partitions:\n  - esp:\n    format: fat32\n    size: 500M\n    mount: /boot\n  \
+            - root:\n    format: ext4\n    mount: /\n    encryption:\n      passphrase: prompt\n", IMAGE),
+            "the encrypted root partition on test.img would be unlocked by an initramfs started by busybox, which finds it by the loop device it's on while installing; \
+            use the 'systemd' and 'sd-encrypt' hooks in mkinitcpio_hooks"),
+        ("./test.img", &format!("{}partitions:\n  - esp:\n    format: fat32\n    size: 500M\n    mount: /efi\n  \
+            - backup:\n    format: fat32\n    size: 500M\n    esp: true\n  - root:\n    format: ext4\n    mount: /\n", IMAGE),
+            "the secondary EFI system partition on test.img would get a boot entry in the firmware of the machine installing the disk image"),
+    ] {
+        let output = generate(&["--file"], disk, lines);
+        assert!(!output.status.success(), "{}", lines);
+        assert!(String::from_utf8_lossy(&output.stderr).contains(message), "{}", String::from_utf8_lossy(&output.stderr));
+    }
+
+    let output = generate(&["--file"], "/dev/sda", IMAGE);
+    assert!(output.status.success());
+    assert!(String::from_utf8_lossy(&output.stderr).contains("warning: `image` is set, but none of the disks is a file; it's going to be ignored"));
+}
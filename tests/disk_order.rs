@@ -0,0 +1,53 @@
+//! Checks that disks are partitioned, formatted and listed in the order they're written in the
+//! configuration file, and that the order of the disks doesn't change the order of the mounts
+
+use std::process::Output;
+
+mod common;
+
+/// Generate the script from the sample configuration file, with its boot partition on `boot_disk`
+/// and its root partition on `root_disk`, after the other replacements were made to it
+fn generate(boot_disk: &str, root_disk: &str, replacements: &[(&str, &str)]) -> Output
+{
+    let (boot, root) = (format!("\ndisk: {}\n", boot_disk), format!("    mount: /\n    disk: {}\n", root_disk));
+    let mut all = vec![("\ndisk: /dev/sda\n", boot.as_str()), ("    mount: /\n", root.as_str())];
+    all.extend_from_slice(replacements);
+    let output = common::generate(&["--verbose", "--file"], &all, "");
+    assert!(output.status.success(), "jimmy failed: {}", String::from_utf8_lossy(&output.stderr));
+    output
+}
+
+/// Return the lines of the script that run `prefix`, with the prefix removed
+fn runs<'a>(output: &'a Output, prefix: &str) -> Vec<&'a str>
+{
+    std::str::from_utf8(&output.stdout).unwrap().lines()
+        .filter_map(|l| l.split_once(prefix).map(|(_, rest)| rest))
+        .collect()
+}
+
+#[test]
+fn in_the_order_they_are_written()
+{
+    for (first, second) in [("/dev/sda", "/dev/sdb"), ("/dev/sdb", "/dev/sda")] {
+        let output = generate(first, second, &[]);
+        let fdisk: Vec<&str> = runs(&output, "| fdisk ").iter().map(|r| r.split(' ').next().unwrap()).collect();
+        assert_eq!(fdisk, [first, second]);
+        assert_eq!(runs(&output, "mkfs.fat -F 32 "), [format!("{}1 || exit 1", first)]);
+        assert_eq!(runs(&output, "mkfs.ext4 "), [format!("{}1 || exit 1", second)]);
+        assert!(String::from_utf8_lossy(&output.stderr).contains(&format!("\ndisks: {}, {}\n", first, second)));
+    }
+}
+
+#[test]
+fn mount_points_come_before_those_inside_them()
+{
+    // /home/data is on the disk that's partitioned first, and /home on the one that's last
+    let output = generate("/dev/sdb", "/dev/sda", &[
+        ("    size: 500M\n", "    size: 500M\n  - data:\n    format: ext4\n    mount: /home/data\n    size: 10G\n"),
+        ("    # on the disk\n", "    # on the disk\n  - home:\n    format: ext4\n    mount: /home\n    disk: /dev/sdc\n"),
+    ]);
+    assert_eq!(runs(&output, "| fdisk ").iter().map(|r| r.split(' ').next().unwrap()).collect::<Vec<&str>>(),
+        ["/dev/sdb", "/dev/sda", "/dev/sdc"]);
+    let mounts: Vec<&str> = runs(&output, "|| { mkdir -p ").iter().map(|r| r.split(' ').next().unwrap()).collect();
+    assert_eq!(mounts, ["/mnt/", "/mnt/boot", "/mnt/home", "/mnt/home/data"]);
+}
@@ -0,0 +1,85 @@
+//! Checks how the disks of the configuration file are normalized: the slashes and `.` taken out
+//! of their paths, partitions refused in place of whole disks, whatever the naming scheme of the
+//! disk, and relative paths refused unless they're disk images
+
+mod common;
+
+/// Run jimmy on the sample configuration file with the given disk
+fn generate(disk: &str) -> std::process::Output
+{
+    common::generate(&["--file"], &[("\ndisk: /dev/sda\n", &format!("\ndisk: \"{}\"\n", disk))], "")
+}
+
+/// Return the disk fdisk is run on in the script of the sample configuration file, with the given
+/// disk
+fn partitioned(disk: &str) -> String
+{
+    let script = common::script(generate(disk));
+    script.lines().find_map(|l| l.split_once("| fdisk ")).unwrap().1.split(' ').next().unwrap().to_string()
+}
+
+#[test]
+fn normalized()
+{
+    for (disk, normalized) in [
+        ("/dev/sda", "/dev/sda"),
+        ("/dev/sda/", "/dev/sda"),
+        ("//dev//sda", "/dev/sda"),
+        ("/dev/./sda/.", "/dev/sda"),
+        ("/dev/nvme0n1", "/dev/nvme0n1"),
+        ("/dev/mmcblk0", "/dev/mmcblk0"),
+        // disks are only partitions if what comes before the number is the name of a whole disk
+        ("/dev/sdab", "/dev/sdab"),
+        ("/dev/nvme0n1p", "/dev/nvme0n1p"),
+        ("/dev/disk/by-id/ata-DISK", "/dev/disk/by-id/ata-DISK"),
+        ("/dev/disk/by-id/ata-DISK-part", "/dev/disk/by-id/ata-DISK-part"),
+    ] {
+        assert_eq!(partitioned(disk), normalized, "{}", disk);
+    }
+}
+
+#[test]
+fn partitions()
+{
+    for (partition, disk) in [
+        // numbered right after the disk's name
+        ("/dev/sda1", "/dev/sda"),
+        ("/dev/sdab12", "/dev/sdab"),
+        ("/dev/vdb2", "/dev/vdb"),
+        ("/dev/xvda3", "/dev/xvda"),
+        ("/dev/hdc1", "/dev/hdc"),
+        ("//dev//sda1/", "/dev/sda"),
+        // with a `p` after a name that ends in a digit
+        ("/dev/nvme0n1p1", "/dev/nvme0n1"),
+        ("/dev/nvme10n2p15", "/dev/nvme10n2"),
+        ("/dev/mmcblk0p2", "/dev/mmcblk0"),
+        ("/dev/loop7p1", "/dev/loop7"),
+        ("/dev/md127p1", "/dev/md127"),
+        // with `-partN` after the links udev makes
+        ("/dev/disk/by-id/ata-DISK-part1", "/dev/disk/by-id/ata-DISK"),
+        ("/dev/disk/by-path/pci-0000:00:1f.2-ata-1-part3", "/dev/disk/by-path/pci-0000:00:1f.2-ata-1"),
+    ] {
+        let stderr = common::refusal(generate(partition));
+        assert!(stderr.contains(&format!("disk \"{}\" is a partition; use the whole disk: \"{}\"", partition, disk)), "{}", stderr);
+    }
+
+    let stderr = common::refusal(generate("by-id:ata-DISK-part2"));
+    assert!(stderr.contains("disk \"by-id:ata-DISK-part2\" is a partition; use the identifier of the whole disk: \"by-id:ata-DISK\""), "{}", stderr);
+}
+
+#[test]
+fn refused()
+{
+    for disk in ["sda", "dev/sda", "./sda"] {
+        let stderr = common::refusal(generate(disk));
+        assert!(stderr.contains("isn't a device under /dev; use e.g. \"/dev/sda\", or set `image` to install onto a disk image file"), "{}: {}", disk, stderr);
+    }
+    for disk in ["/", "//", "/./"] {
+        let stderr = common::refusal(generate(disk));
+        assert!(stderr.contains(&format!("invalid disk: \"{}\"", disk)), "{}: {}", disk, stderr);
+    }
+    for disk in ["by-id:", "by-id:ata/DISK"] {
+        let stderr = common::refusal(generate(disk));
+        assert!(stderr.contains(&format!("invalid disk identifier: \"{}\"", disk)), "{}: {}", disk, stderr);
+    }
+}
@@ -0,0 +1,98 @@
+//! Checks that a disk written in more than one way in the configuration file is partitioned only
+//! once. The devices are looked up in a temporary directory given with `JIMMY_DEVICE_ROOT`, which
+//! holds a copy of the parts of /dev that matter
+
+use std::path::{Path, PathBuf};
+use std::process::{Command, Output};
+
+mod common;
+
+/// Create a temporary directory with a `dev/sda`, and an `ata-DISK` and a `wwn-` link to it under
+/// `dev/disk/by-id`, like udev makes them, and return it
+fn device_root() -> PathBuf
+{
+    let root = common::temp_path("root");
+    std::fs::create_dir_all(root.join("dev/disk/by-id")).unwrap();
+    std::fs::write(root.join("dev/sda"), "").unwrap();
+    std::os::unix::fs::symlink("../../sda", root.join("dev/disk/by-id/ata-DISK")).unwrap();
+    std::os::unix::fs::symlink("../../sda", root.join("dev/disk/by-id/wwn-0x5000c500a1b2c3d4")).unwrap();
+    root
+}
+
+/// Return the sample configuration file, with the boot partition on the default disk, which is
+/// `boot_disk`, and the root partition on a disk of its own
+fn config(boot_disk: &str, root_disk: &str) -> String
+{
+    common::config(&[
+        ("\ndisk: /dev/sda\n", &format!("\ndisk: {}\n", boot_disk)),
+        ("    mount: /\n", &format!("    mount: /\n    disk: {}\n", root_disk)),
+    ], "")
+}
+
+/// Generate the script from the given configuration file, looking up devices under `root`
+fn generate(root: &Path, config: &str) -> Output
+{
+    let path = root.join("config.yaml");
+    std::fs::write(&path, config).unwrap();
+    Command::new(env!("CARGO_BIN_EXE_jimmy")).arg("--file").arg(&path).env("JIMMY_DEVICE_ROOT", root).output().unwrap()
+}
+
+/// Return the devices `fdisk` is run on, in order
+fn fdisk_runs(output: &Output) -> Vec<String>
+{
+    assert!(output.status.success(), "jimmy failed: {}", String::from_utf8_lossy(&output.stderr));
+    String::from_utf8_lossy(&output.stdout).lines()
+        .filter_map(|l| l.split_once("| fdisk ").map(|(_, rest)| rest.split(' ').next().unwrap().to_string()))
+        .collect()
+}
+
+#[test]
+fn trailing_slash()
+{
+    let root = device_root();
+    let output = generate(&root, &config("/dev/sda", "/dev/sda/"));
+    assert_eq!(fdisk_runs(&output), ["/dev/sda"]);
+    std::fs::remove_dir_all(&root).unwrap();
+}
+
+#[test]
+fn symlink_to_the_same_device()
+{
+    let root = device_root();
+    let output = generate(&root, &config("/dev/sda", "by-id:ata-DISK"));
+    assert_eq!(fdisk_runs(&output), ["/dev/sda"]);
+    assert!(String::from_utf8_lossy(&output.stderr).contains(
+        "warning: disks /dev/sda and by-id:ata-DISK are the same device (/dev/sda); its partitions are all going to be made on /dev/sda"
+    ));
+    std::fs::remove_dir_all(&root).unwrap();
+}
+
+#[test]
+fn world_wide_names_ignore_case()
+{
+    let root = device_root();
+    let output = generate(&root, &config("wwn:0x5000C500A1B2C3D4", "wwn:0x5000c500a1b2c3d4"));
+    assert_eq!(fdisk_runs(&output), ["$JIMMY_DISK_wwn_0x5000c500a1b2c3d4"]);
+    std::fs::remove_dir_all(&root).unwrap();
+}
+
+#[test]
+fn two_partitions_taking_the_rest_are_refused()
+{
+    let root = device_root();
+    let output = generate(&root, &config("by-id:ata-DISK", "/dev/sda").replacen("    size: 500M\n", "", 1));
+    assert!(!output.status.success());
+    assert!(String::from_utf8_lossy(&output.stderr).contains(
+        "disks by-id:ata-DISK and /dev/sda are the same device (/dev/sda), and both have a partition without a `size`"
+    ));
+    std::fs::remove_dir_all(&root).unwrap();
+}
+
+#[test]
+fn devices_not_on_this_machine_are_kept_apart()
+{
+    let root = device_root();
+    let output = generate(&root, &config("/dev/sda", "by-id:ata-OTHER"));
+    assert_eq!(fdisk_runs(&output), ["/dev/sda", "$JIMMY_DISK_by_id_ata_OTHER"]);
+    std::fs::remove_dir_all(&root).unwrap();
+}
@@ -0,0 +1,128 @@
+//! Checks what `jimmy doctor` finds out about a machine, from a copy of its /proc, /sys and /etc
+//! made in a temporary directory and given with `--root`
+
+use std::path::{Path, PathBuf};
+use std::process::Command;
+
+mod common;
+
+/// The start of /proc/cpuinfo on a virtual machine with an Intel processor
+const CPUINFO_INTEL_VM: &str = "processor\t: 0
+vendor_id\t: GenuineIntel
+model name\t: Intel(R) Core(TM) i7-8565U CPU @ 1.80GHz
+flags\t\t: fpu vme de pse tsc msr pae mce cx8 apic sep hypervisor lahf_lm
+";
+
+/// The start of /proc/cpuinfo on a 64-bit ARM board
+const CPUINFO_ARM: &str = "processor\t: 0
+BogoMIPS\t: 108.00
+Features\t: fp asimd evtstrm crc32 cpuid
+CPU implementer\t: 0x41
+CPU architecture: 8
+";
+
+/// Write the given files, relative to a new temporary directory, and return the latter
+fn machine(files: &[(&str, &str)]) -> PathBuf
+{
+    let root = common::temp_path("root");
+    for (path, contents) in files {
+        let path = root.join(path);
+        std::fs::create_dir_all(path.parent().unwrap()).unwrap();
+        std::fs::write(path, contents).unwrap();
+    }
+    root
+}
+
+/// Run `jimmy doctor` on the machine under `root`, with the given extra arguments
+fn doctor(root: &Path, args: &[&str]) -> String
+{
+    let output = Command::new(env!("CARGO_BIN_EXE_jimmy"))
+        .arg("doctor")
+        .arg("--root")
+        .arg(root)
+        .args(args)
+        .output()
+        .unwrap();
+    assert!(output.status.success(), "jimmy failed: {}", String::from_utf8_lossy(&output.stderr));
+    String::from_utf8(output.stdout).unwrap()
+}
+
+#[test]
+fn reports_a_qemu_machine()
+{
+    let root = machine(&[
+        ("proc/cpuinfo", CPUINFO_INTEL_VM),
+        ("sys/firmware/efi/fw_platform_size", "64\n"),
+        ("sys/class/dmi/id/sys_vendor", "QEMU\n"),
+        ("sys/class/dmi/id/product_name", "Standard PC (Q35 + ICH9, 2009)\n"),
+        ("etc/vconsole.conf", "KEYMAP=de-latin1\n"),
+        // 128 GiB and 16 GiB, in 512-byte sectors
+        ("sys/block/sda/size", "268435456\n"),
+        ("sys/block/sda/removable", "0\n"),
+        ("sys/block/sda/device/model", "QEMU HARDDISK   \n"),
+        ("sys/block/sdb/size", "33554432\n"),
+        ("sys/block/sdb/removable", "1\n"),
+        ("sys/block/loop0/size", "1638400\n"),
+        ("sys/block/sr0/size", "2097152\n"),
+    ]);
+    std::os::unix::fs::symlink("../usr/share/zoneinfo/Europe/Berlin", root.join("etc/localtime")).unwrap();
+    assert_eq!(doctor(&root, &[]), "architecture: x86_64
+firmware: UEFI
+cpu: Intel (microcode: intel-ucode)
+virtualization: qemu
+keymap: de-latin1
+timezone: Europe/Berlin
+disks:
+    /dev/sda: 128G, QEMU HARDDISK
+    /dev/sdb: 16G, removable
+");
+
+    let draft = doctor(&root, &["--draft"]);
+    assert!(draft.contains("\npackages: intel-ucode qemu-guest-agent\nfirmware_packages: none\n"), "{}", draft);
+    assert!(draft.contains("\ntimezone: Europe/Berlin\n"), "{}", draft);
+    assert!(draft.contains("\n  - en_US.UTF-8\nkeymap: de-latin1\n"), "{}", draft);
+    assert!(draft.contains("  #   /dev/sda: 128G, QEMU HARDDISK\n  #   /dev/sdb: 16G, removable\n"), "{}", draft);
+    // the removable disk is probably the live medium
+    assert!(draft.contains("    disk: /dev/sda\n") && !draft.contains("    disk: /dev/sdb\n"), "{}", draft);
+    assert!(!draft.contains("BIOS"), "{}", draft);
+    std::fs::remove_dir_all(&root).unwrap();
+}
+
+#[test]
+fn drafts_for_an_arm_board_booted_with_bios()
+{
+    let root = machine(&[
+        ("proc/cpuinfo", CPUINFO_ARM),
+        ("sys/block/mmcblk0/size", "62333952\n"),
+        ("sys/block/mmcblk0/removable", "0\n"),
+    ]);
+    std::fs::create_dir_all(root.join("etc")).unwrap();
+    std::os::unix::fs::symlink("/usr/share/zoneinfo/UTC", root.join("etc/localtime")).unwrap();
+    let draft = doctor(&root, &["--draft"]);
+    assert!(draft.contains("\narch: aarch64\n"), "{}", draft);
+    assert!(draft.contains("# warning: the machine booted with BIOS"), "{}", draft);
+    assert!(!draft.contains("ucode") && !draft.contains("firmware_packages"), "{}", draft);
+    assert!(draft.contains("\ntimezone: UTC\n\n"), "{}", draft);
+    assert!(draft.contains("    disk: /dev/mmcblk0\n"), "{}", draft);
+    std::fs::remove_dir_all(&root).unwrap();
+}
+
+#[test]
+fn draft_of_a_uefi_machine_is_a_valid_configuration()
+{
+    let root = machine(&[
+        ("proc/cpuinfo", "processor\t: 0\nvendor_id\t: AuthenticAMD\nflags\t\t: fpu vme\n"),
+        ("sys/firmware/efi/fw_platform_size", "64\n"),
+        ("sys/block/nvme0n1/size", "1000215216\n"),
+        ("sys/block/nvme0n1/removable", "0\n"),
+    ]);
+    std::fs::create_dir_all(root.join("etc")).unwrap();
+    std::os::unix::fs::symlink("/usr/share/zoneinfo/Europe/London", root.join("etc/localtime")).unwrap();
+    let draft = doctor(&root, &["--draft"]);
+    assert!(draft.contains("\npackages: amd-ucode\n"), "{}", draft);
+    let path = root.join("config.yaml");
+    std::fs::write(&path, &draft).unwrap();
+    let output = Command::new(env!("CARGO_BIN_EXE_jimmy")).arg("--file").arg(&path).output().unwrap();
+    assert!(output.status.success(), "jimmy failed on the draft: {}", String::from_utf8_lossy(&output.stderr));
+    std::fs::remove_dir_all(&root).unwrap();
+}
@@ -0,0 +1,76 @@
+//! Checks the commands that delete the boot entries with the same label as the one created for
+//! efistub. The deletion loop is ran against a fake `efibootmgr`, which lists a few entries and
+//! records the ones it's asked to delete
+
+use std::process::Command;
+
+mod common;
+
+/// What the fake `efibootmgr` lists: the default label on an active and on an inactive entry, and
+/// labels that only start with it
+const LISTING: &str = "BootCurrent: 0001
+Timeout: 1 seconds
+BootOrder: 0001,0000,0002,0003
+Boot0000* Windows Boot Manager\tHD(1,GPT,0e3c2a6f-1f57-4c8e-9a3b-5c1b2f0e1d2a,0x800,0x100000)/\\EFI\\Microsoft\\Boot\\bootmgfw.efi
+Boot0001* Arch Linux\tHD(1,GPT,5b6a7c8d-0e1f-4a2b-8c3d-4e5f6a7b8c9d,0x800,0x100000)/\\vmlinuz-linux
+Boot0002  Arch Linux
+Boot0003* Arch Linux LTS\tHD(1,GPT,5b6a7c8d-0e1f-4a2b-8c3d-4e5f6a7b8c9d,0x800,0x100000)/\\vmlinuz-linux-lts
+Boot0004* Arch Linux (workstation)
+";
+
+/// Return the arch-chroot script generated from a configuration file
+fn chroot_script(config: &str) -> String
+{
+    common::script(Command::new(env!("CARGO_BIN_EXE_jimmy")).args(["chroot-script", "--width", "0", config]).output().unwrap())
+}
+
+/// Return the loop that deletes the boot entries, from its comment to its `done`
+fn cleanup_loop(script: &str) -> Option<String>
+{
+    let start = script.find("# remove the boot entries with the same label")?;
+    let end = start + script[start..].find("\ndone\n")? + "\ndone".len();
+    Some(script[start..end].to_string())
+}
+
+/// Run the deletion loop with the fake `efibootmgr`, returning the entries it deleted
+fn deleted_entries(cleanup: &str) -> Vec<String>
+{
+    let efibootmgr = format!("if [ $# -eq 0 ]; then\n    cat <<'EOF'\n{}EOF\nelse\n    echo \"$*\" >>\"$DIR/calls\"\nfi", LISTING);
+    let code = format!("{}\njimmy_status=$?\ncat \"$DIR/calls\" 2>/dev/null\nexit $jimmy_status", cleanup);
+    let (success, calls, _) = common::sh(&code, &[("efibootmgr", &efibootmgr)], "");
+    assert!(success);
+    calls.lines().map(String::from).collect()
+}
+
+#[test]
+fn deletes_entries_with_the_default_label()
+{
+    let script = chroot_script("examples/valid--bootloader_efistub.yaml");
+    let cleanup = cleanup_loop(&script).expect("no deletion loop in the script");
+    assert!(cleanup.contains("JIMMY_LABEL='Arch Linux' awk"));
+    assert_eq!(
+        deleted_entries(&cleanup),
+        ["--bootnum 0001 --delete-bootnum", "--bootnum 0002 --delete-bootnum"],
+    );
+    // the new entry is created after the old ones are gone
+    assert!(script.find(&cleanup).unwrap() < script.find("--create --label 'Arch Linux' ").unwrap());
+}
+
+#[test]
+fn deletes_entries_with_a_label_of_its_own()
+{
+    let yaml = std::fs::read_to_string("examples/valid--boot_entry_label.yaml").unwrap()
+        .replace("keep_existing_entries: true", "keep_existing_entries: false");
+    let script = common::script(common::jimmy(&["chroot-script"], &yaml));
+
+    let cleanup = cleanup_loop(&script).expect("no deletion loop in the script");
+    assert_eq!(deleted_entries(&cleanup), ["--bootnum 0004 --delete-bootnum"]);
+}
+
+#[test]
+fn keeps_existing_entries()
+{
+    let script = chroot_script("examples/valid--boot_entry_label.yaml");
+    assert_eq!(cleanup_loop(&script), None);
+    assert!(script.contains("--create --label 'Arch Linux (workstation)' "));
+}
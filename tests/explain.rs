@@ -0,0 +1,51 @@
+//! Checks that `jimmy explain` describes every step the installation script runs, for each of the
+//! valid examples
+
+use std::process::Command;
+
+/// Run jimmy with `args` on the example `path`, and return what it printed, checking that it
+/// succeeded
+fn jimmy(args: &[&str], path: &str) -> String
+{
+    let output = Command::new(env!("CARGO_BIN_EXE_jimmy"))
+        .args(args)
+        .arg(path)
+        .env("JIMMY_HOSTNAME", "workstation")
+        .output()
+        .unwrap();
+    assert!(output.status.success(), "{}: {}", path, String::from_utf8_lossy(&output.stderr));
+    String::from_utf8(output.stdout).unwrap()
+}
+
+/// Return the steps the installation script records in its status, in order
+fn script_steps(script: &str) -> Vec<String>
+{
+    let steps = script.split_once("\"steps\":[").unwrap().1.split_once(']').unwrap().0;
+    steps.split(',').map(|s| s.trim_matches('"').to_string()).collect()
+}
+
+#[test]
+fn every_step_is_described()
+{
+    let examples = std::fs::read_dir("examples").unwrap()
+        .map(|e| e.unwrap().path().to_str().unwrap().to_string())
+        .filter(|p| p.starts_with("examples/valid--") && p.ends_with(".yaml"));
+    let mut checked = 0;
+    for path in examples {
+        let steps = script_steps(&jimmy(&["--file"], &path));
+        let explanation = jimmy(&["explain", "--markdown"], &path);
+        // the first section has no newline before it
+        let sections: Vec<&str> = std::iter::once(explanation.strip_prefix("## ").unwrap())
+            .chain(explanation.split("\n## ").skip(1))
+            .collect();
+        assert_eq!(sections.len(), steps.len(), "{}", path);
+        for (i, (section, step)) in sections.iter().zip(steps.iter()).enumerate() {
+            let mut paragraphs = section.split("\n\n");
+            assert_eq!(paragraphs.next().unwrap(), format!("{}. {}", i + 1, step), "{}", path);
+            let description = paragraphs.next().unwrap_or_default();
+            assert!(!description.trim().is_empty() && !description.starts_with("```"), "{}: {:?} has no description", path, step);
+        }
+        checked += 1;
+    }
+    assert!(checked > 50, "{}", checked);
+}
@@ -0,0 +1,70 @@
+//! Checks that `packages` only takes the names of packages, whichever way it's written, and that
+//! the flags for pacman go through `pacstrap_args`
+
+mod common;
+
+/// Generate the script from the sample configuration file, with the given `packages` and lines
+/// appended, and return whether jimmy succeeded along with its stdout and stderr
+fn generate(extra: &str, extra_lines: &str) -> (bool, String, String)
+{
+    let output = common::generate(&["--file"], &[("packages: vim\n", &format!("packages: {}\n", extra))], extra_lines);
+    (output.status.success(), String::from_utf8(output.stdout).unwrap(), String::from_utf8(output.stderr).unwrap())
+}
+
+/// Return what jimmy complains about when it refuses the given `packages`
+fn refusal(extra: &str) -> String
+{
+    let (success, _, stderr) = generate(extra, "");
+    assert!(!success, "{} was accepted", extra);
+    stderr
+}
+
+#[test]
+fn flags_are_refused()
+{
+    let stderr = refusal("\"--ignore linux vim\"");
+    assert!(stderr.contains("`packages` only lists packages, but it has the pacman flag '--ignore'; \
+        give it to `pacstrap_args` instead, along with 'linux' if it's the flag's argument"), "{}", stderr);
+    let stderr = refusal("[ vim, --needed ]");
+    assert!(stderr.contains("it has the pacman flag '--needed'; give it to `pacstrap_args` instead\n"), "{}", stderr);
+}
+
+#[test]
+fn malformed_names_are_refused()
+{
+    for (extra, package) in [
+        ("Vim", "Vim"),
+        ("\"vim;reboot\"", "vim;reboot"),
+        ("[ \"$(reboot)\" ]", "$(reboot)"),
+        ("core/linux", "core/linux"),
+        (".hidden", ".hidden"),
+    ] {
+        let stderr = refusal(extra);
+        assert!(stderr.contains(&format!("invalid package name in packages: \"{}\" (expected lowercase letters, digits and @._+-)", package)),
+            "{}: {}", extra, stderr);
+    }
+}
+
+#[test]
+fn versions_are_warned_about()
+{
+    let (success, _, stderr) = generate("linux-lts 6.6.1", "");
+    assert!(success, "{}", stderr);
+    assert!(stderr.contains("warning: '6.6.1' in `packages` looks like a version rather than a package; pin packages at a version with package_pins"));
+    let (_, _, stderr) = generate("[ 7zip, 0ad, python3.12 ]", "");
+    assert!(!stderr.contains("looks like a version"), "{}", stderr);
+}
+
+#[test]
+fn pacstrap_args()
+{
+    let (success, script, stderr) = generate("vim", "pacstrap_args: [ --ignore, linux, --overwrite, \"/usr/lib/*\" ]\n");
+    assert!(success, "{}", stderr);
+    assert!(script.contains("pacstrap /mnt $jimmy_needed --ignore linux --overwrite '/usr/lib/*' base "), "{}", script);
+    let (success, script, _) = generate("vim", "pacstrap_args: [ --noprogressbar ]\nretries: 0\n");
+    assert!(success);
+    assert!(script.contains("\npacstrap /mnt --noprogressbar base "));
+    let (success, _, stderr) = generate("vim", "pacstrap_args: [ \"\" ]\n");
+    assert!(!success);
+    assert!(stderr.contains("pacstrap_args must be single lines, and not empty: \"\""));
+}
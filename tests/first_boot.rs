@@ -0,0 +1,173 @@
+//! Checks the first-boot service: the unit file, and the runner, ran with sh on the scripts the
+//! arch-chroot script installs, with the programs they run replaced by ones recording what they're
+//! given: the order the scripts run in, what's left after one of them fails, and the service
+//! disabling itself once they all succeed
+
+mod common;
+
+/// The users of the sample replaced by one with an encrypted home directory, created by homed
+const HOMED: &str = "users:\n  - homed:\n    name: encrypted\n    home_encryption: true\n";
+
+/// The commands of the configuration file, ran after the users are created
+const CUSTOM: &str = "first_boot:\n  - timedatectl set-ntp true\n";
+
+/// Return the arch-chroot script of the sample configuration file, with its users replaced by
+/// `users` if it's given, and the given lines appended
+fn chroot_script(users: Option<&str>, extra_lines: &str) -> String
+{
+    let sample = common::sample();
+    let start = sample.find("users:\n").unwrap();
+    let end = start + sample[start..].find("\n\n").unwrap() + 1;
+    let replacements = users.map(|u| vec![(&sample[start..end], u)]).unwrap_or_default();
+    common::script(common::generate(&["chroot-script"], &replacements, extra_lines))
+}
+
+/// Return the commands that install the first-boot service
+fn first_boot_cmds(script: &str) -> &str
+{
+    let from = script.find("\nmkdir -p /usr/local/lib/jimmy/firstboot.d\n").unwrap() + 1;
+    let end = "\nsystemctl enable jimmy-firstboot.service\n";
+    &script[from..from + script[from..].find(end).unwrap() + end.len()]
+}
+
+/// Return the unit file of the first-boot service
+fn unit(script: &str) -> &str
+{
+    let start = "cat <<'END_OF_FILE' >/etc/systemd/system/jimmy-firstboot.service\n";
+    let from = script.find(start).unwrap() + start.len();
+    &script[from..from + script[from..].find("END_OF_FILE\n").unwrap()]
+}
+
+/// Install the first-boot service of `script` under `$DIR` and run it `runs` times, with the given
+/// programs, and return whether the last run succeeded, the calls the programs recorded in
+/// `$DIR/calls`, the first-boot scripts left, and what the runs wrote to stderr
+fn boot(script: &str, programs: &[(&str, &str)], runs: usize) -> (bool, String, String, String)
+{
+    let install = first_boot_cmds(script)
+        .replace("/usr/local/lib/jimmy", "$DIR/lib")
+        .replace("/etc/systemd/system", "$DIR");
+    let code = format!("{}\n{}\necho \"status $?\"\nls \"$DIR/lib/firstboot.d\"\necho calls:\ncat \"$DIR/calls\"",
+        install, vec!["\"$DIR/lib/firstboot\" >/dev/null"; runs].join("\n"));
+    let mut programs = programs.to_vec();
+    programs.push(("systemctl", "echo \"systemctl $*\" >>\"$DIR/calls\""));
+    let (success, stdout, stderr) = common::sh(&code, &programs, "");
+    assert!(success, "{}", stderr);
+    let (status, rest) = stdout.strip_prefix("status ").unwrap().split_once('\n').unwrap();
+    let (left, calls) = rest.split_once("calls:\n").unwrap();
+    (status == "0", calls.to_string(), left.to_string(), stderr)
+}
+
+#[test]
+fn unit_file()
+{
+    // nothing asks questions, so the output only goes to the journal
+    assert_eq!(unit(&chroot_script(None, CUSTOM)), "[Unit]\n\
+        Description=Finish the installation done by jimmy\n\
+        Wants=network-online.target\n\
+        After=network-online.target\n\
+        Before=systemd-user-sessions.service getty@tty1.service\n\
+        \n\
+        [Service]\n\
+        Type=oneshot\n\
+        ExecStart=/usr/local/lib/jimmy/firstboot\n\
+        StandardOutput=journal\n\
+        \n\
+        [Install]\n\
+        WantedBy=multi-user.target\n");
+
+    // homectl asks for the password of the user on tty1, before anyone can log in there
+    assert_eq!(unit(&chroot_script(Some(HOMED), CUSTOM)), "[Unit]\n\
+        Description=Finish the installation done by jimmy\n\
+        Wants=systemd-homed.service network-online.target\n\
+        After=systemd-homed.service network-online.target\n\
+        Before=systemd-user-sessions.service getty@tty1.service\n\
+        \n\
+        [Service]\n\
+        Type=oneshot\n\
+        ExecStart=/usr/local/lib/jimmy/firstboot\n\
+        StandardInput=tty\n\
+        TTYPath=/dev/tty1\n\
+        StandardOutput=journal+console\n\
+        \n\
+        [Install]\n\
+        WantedBy=multi-user.target\n");
+
+    assert!(!chroot_script(None, "").contains("firstboot"));
+}
+
+#[test]
+fn homed()
+{
+    let script = chroot_script(Some(HOMED), "");
+    assert!(script.contains("\ncat <<'END_OF_FILE' >/usr/local/lib/jimmy/firstboot.d/10-homed.sh\n#!/bin/sh\n\
+        # first-boot script automatically generated by jimmy-rs\nset -e\n\n\
+        while true; do if homectl create encrypted --storage=luks; then break; fi; done\nEND_OF_FILE\n"));
+    // the user isn't created in the arch-chroot session
+    assert!(!script.lines().any(|l| l.starts_with("useradd ") && l.ends_with(" encrypted")));
+
+    // homectl is asked again until the passwords it's given match
+    let homectl = "echo \"homectl $*\" >>\"$DIR/calls\"\n[ \"$(grep -c homectl \"$DIR/calls\")\" -ge 2 ]";
+    let (success, calls, left, stderr) = boot(&script, &[("homectl", homectl)], 1);
+    assert!(success, "{}", stderr);
+    assert_eq!(calls, "systemctl enable jimmy-firstboot.service\n\
+        homectl create encrypted --storage=luks\n\
+        homectl create encrypted --storage=luks\n\
+        systemctl disable jimmy-firstboot.service\n");
+    assert_eq!(left, "");
+}
+
+#[test]
+fn in_order()
+{
+    let script = chroot_script(Some(HOMED), CUSTOM);
+    let homectl = "echo \"homectl $*\" >>\"$DIR/calls\"";
+    let timedatectl = "echo \"timedatectl $*\" >>\"$DIR/calls\"";
+    let (success, calls, left, stderr) = boot(&script, &[("homectl", homectl), ("timedatectl", timedatectl)], 1);
+    assert!(success, "{}", stderr);
+    assert_eq!(calls, "systemctl enable jimmy-firstboot.service\n\
+        homectl create encrypted --storage=luks\n\
+        timedatectl set-ntp true\n\
+        systemctl disable jimmy-firstboot.service\n");
+    // the scripts that succeeded are removed
+    assert_eq!(left, "");
+    assert_eq!(stderr, "");
+}
+
+#[test]
+fn failure()
+{
+    let script = chroot_script(Some(HOMED), CUSTOM);
+    let homectl = "echo \"homectl $*\" >>\"$DIR/calls\"";
+    let timedatectl = "echo \"timedatectl $*\" >>\"$DIR/calls\"\nexit 1";
+    let (success, calls, left, stderr) = boot(&script, &[("homectl", homectl), ("timedatectl", timedatectl)], 1);
+    assert!(!success);
+    // the service stays enabled, with only the script that failed left to run on the next boot
+    assert_eq!(calls, "systemctl enable jimmy-firstboot.service\n\
+        homectl create encrypted --storage=luks\n\
+        timedatectl set-ntp true\n");
+    assert_eq!(left, "20-custom.sh\n");
+    assert!(stderr.ends_with("/lib/firstboot.d/20-custom.sh failed; it is going to run again on the next boot\n"), "{}", stderr);
+
+    // which is tried again, and the service disabled once it succeeds
+    let timedatectl = "echo \"timedatectl $*\" >>\"$DIR/calls\"\n[ \"$(grep -c timedatectl \"$DIR/calls\")\" -ge 2 ]";
+    let (success, calls, left, _) = boot(&script, &[("homectl", homectl), ("timedatectl", timedatectl)], 2);
+    assert!(success);
+    assert_eq!(calls, "systemctl enable jimmy-firstboot.service\n\
+        homectl create encrypted --storage=luks\n\
+        timedatectl set-ntp true\n\
+        timedatectl set-ntp true\n\
+        systemctl disable jimmy-firstboot.service\n");
+    assert_eq!(left, "");
+}
+
+#[test]
+fn stops_at_the_first_failing_command()
+{
+    // each script is ran with `set -e`
+    let script = chroot_script(None, "first_boot:\n  - timedatectl set-ntp true\n  - timedatectl set-timezone UTC\n");
+    let timedatectl = "echo \"timedatectl $*\" >>\"$DIR/calls\"\nexit 1";
+    let (success, calls, left, _) = boot(&script, &[("timedatectl", timedatectl)], 1);
+    assert!(!success);
+    assert_eq!(calls, "systemctl enable jimmy-firstboot.service\ntimedatectl set-ntp true\n");
+    assert_eq!(left, "10-custom.sh\n");
+}
@@ -0,0 +1,58 @@
+//! Checks `issue`, `motd` and `pretty_name`: the files the chroot script writes with them, which
+//! have to hold exactly what the configuration file gives, escapes of agetty and characters the
+//! shell would expand included
+
+mod common;
+
+const GREETING: &str = "issue: |\n  Arch Linux \\r (\\l)\n  \\S{PRETTY_NAME} on \\n, $HOME `date`\n\n\
+    motd: |\n  Welcome back\n  \\\\ \"quoted\" \\$PATH\n\
+    pretty_name: 'Example \"Corp\" $OS \\ `Linux`'\n";
+
+/// Return the chroot script of the sample configuration file with the given lines appended
+fn chroot_script(extra_lines: &str) -> String
+{
+    common::script(common::generate(&["chroot-script"], &[], extra_lines))
+}
+
+/// Return the command of `script` that writes `path` with a heredoc
+fn heredoc<'a>(script: &'a str, path: &str) -> &'a str
+{
+    let from = script.find(&format!("cat <<'END_OF_FILE' >{}\n", path)).unwrap();
+    let end = "\nEND_OF_FILE";
+    let to = from + script[from..].find(end).unwrap() + end.len();
+    &script[from..to]
+}
+
+#[test]
+fn written_untouched()
+{
+    let script = chroot_script(GREETING);
+    for (path, contents) in [
+        ("/etc/issue", "Arch Linux \\r (\\l)\n\\S{PRETTY_NAME} on \\n, $HOME `date`\n"),
+        ("/etc/motd", "Welcome back\n\\\\ \"quoted\" \\$PATH\n"),
+    ] {
+        let code = format!("{}\ncat \"$DIR/file\"", heredoc(&script, path).replace(path, "\"$DIR/file\""));
+        assert_eq!(common::sh(&code, &[], ""), (true, contents.to_string(), String::new()), "{}", path);
+    }
+}
+
+#[test]
+fn pretty_name()
+{
+    let script = chroot_script(GREETING);
+    let from = script.find("rm -f /etc/os-release\n").unwrap();
+    let to = from + script[from..].find(">/etc/os-release\n").unwrap() + ">/etc/os-release".len();
+    // os-release is read with the quoting rules of the shell, so sourcing it gives the name back
+    let code = format!("printf 'NAME=\"Arch Linux\"\\nPRETTY_NAME=\"Arch Linux\"\\n' >\"$DIR/original\"\n{}\n. \"$DIR/os-release\"\necho \"$NAME|$PRETTY_NAME\"",
+        script[from..to].replace("/usr/lib/os-release", "\"$DIR/original\"").replace("/etc/os-release", "\"$DIR/os-release\""));
+    assert_eq!(common::sh(&code, &[], ""), (true, "Arch Linux|Example \"Corp\" $OS \\ `Linux`\n".to_string(), String::new()));
+}
+
+#[test]
+fn absent()
+{
+    let script = chroot_script("");
+    for file in ["/etc/issue", "/etc/motd", "/etc/os-release"] {
+        assert!(!script.contains(file), "{}", file);
+    }
+}
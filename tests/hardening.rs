@@ -0,0 +1,84 @@
+//! Checks where each of the `hardening` toggles lands in the arch-chroot script: the kernel
+//! settings, a drop-in file, the kernel command line or the filesystem table
+
+use std::process::Output;
+
+mod common;
+
+/// Generate the arch-chroot script from the sample configuration file, with the given lines of it
+/// replaced, the given hardening toggles turned on and the given lines appended
+fn generate(replacements: &[(&str, &str)], toggles: &[&str], extra_lines: &str) -> Output
+{
+    let hardening: String = toggles.iter().map(|t| format!("  {}: true\n", t)).collect();
+    common::generate(&["chroot-script"], replacements, &format!("hardening:\n{}{}", hardening, extra_lines))
+}
+
+/// Return the arch-chroot script, checking that it was generated
+fn script(bootloader: &str, toggles: &[&str]) -> String
+{
+    common::script(generate(&[("bootloader: grub\n", &format!("bootloader: {}\n", bootloader))], toggles, ""))
+}
+
+/// What's appended to the filesystem table for `hidepid`
+const PROC_ENTRY: &str = "proc\t/proc\tproc\tnosuid,nodev,noexec,hidepid=invisible,gid=proc\t0 0";
+
+#[test]
+fn nothing_by_default()
+{
+    let script = script("grub", &[]);
+    assert!(!script.contains("hardening"));
+    assert!(!script.contains("lockdown=") && !script.contains("/proc\tproc"));
+}
+
+#[test]
+fn kernel_lockdown_goes_on_the_command_line()
+{
+    let grub = script("grub", &["kernel_lockdown"]);
+    assert!(grub.contains("\ngrep -qF 'GRUB_CMDLINE_LINUX=\"lockdown=integrity ' /etc/default/grub || \
+        sed --in-place 's|^GRUB_CMDLINE_LINUX=\"|&lockdown=integrity |' /etc/default/grub\n"));
+    assert!(!grub.contains("hardening measures"));
+    let systemd_boot = script("systemd-boot", &["kernel_lockdown"]);
+    assert!(systemd_boot.contains("\noptions root=/dev/sda2 lockdown=integrity rw\n"), "{}", systemd_boot);
+}
+
+#[test]
+fn disable_coredumps_is_a_drop_in()
+{
+    let script = script("grub", &["disable_coredumps"]);
+    assert!(script.contains("\nmkdir -p /etc/systemd/coredump.conf.d\n\
+        cat <<'END_OF_FILE' >/etc/systemd/coredump.conf.d/90-jimmy-hardening.conf\n[Coredump]\nStorage=none\nProcessSizeMax=0\nEND_OF_FILE\n"));
+    assert!(!script.contains("sysctl.d"));
+}
+
+#[test]
+fn restrict_dmesg_is_a_kernel_setting()
+{
+    let script = script("grub", &["restrict_dmesg"]);
+    assert!(script.contains("\ncat <<'END_OF_FILE' >/etc/sysctl.d/90-jimmy-hardening.conf\nkernel.dmesg_restrict = 1\nEND_OF_FILE\n"));
+    assert!(!script.contains("GRUB_CMDLINE_LINUX"));
+}
+
+#[test]
+fn hidepid_is_an_fstab_entry()
+{
+    let script = script("grub", &["hidepid"]);
+    assert!(script.contains(&format!("\ngrep -qxF '{0}' /etc/fstab || cat <<'END_OF_FILE' >>/etc/fstab\n{0}\nEND_OF_FILE\n", PROC_ENTRY)));
+    // systemd-logind has to see every process
+    assert!(script.contains("\nmkdir -p /etc/systemd/system/systemd-logind.service.d\n"));
+    assert!(script.contains("\n[Service]\nSupplementaryGroups=proc\n"));
+
+    let output = generate(&[], &["hidepid"], "fstab_extra:\n  - raw: proc /proc proc defaults\n");
+    assert!(!output.status.success());
+    assert!(String::from_utf8_lossy(&output.stderr)
+        .contains("`hidepid` under `hardening` writes the fstab entry for /proc, and fstab_extra has one already"));
+}
+
+#[test]
+fn conflicts_with_unsigned_modules()
+{
+    let output = generate(&[("packages: vim\n", "packages: nvidia nvidia-utils\n")], &["kernel_lockdown"], "");
+    assert!(output.status.success());
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(stderr.contains("package 'nvidia' conflicts with `kernel_lockdown` under `hardening`"), "{}", stderr);
+    assert!(!stderr.contains("'nvidia-utils'"));
+}
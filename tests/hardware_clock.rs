@@ -0,0 +1,62 @@
+//! Checks `hardware_clock`: the command that sets the hardware clock with each value and each
+//! chroot backend, and the warning about local time without anything else hinting at Windows
+
+use std::process::Output;
+
+mod common;
+
+/// Generate the script from the sample configuration file, with the given lines appended
+fn generate(extra_lines: &str) -> Output
+{
+    common::generate(&["--file"], &[], extra_lines)
+}
+
+/// Return the lines of the script that mention hwclock, checking that jimmy succeeded
+fn hwclock_lines(extra_lines: &str) -> Vec<String>
+{
+    common::script(generate(extra_lines)).lines().filter(|l| l.contains("hwclock")).map(str::to_string).collect()
+}
+
+const WARNING: &str = "warning: `hardware_clock: localtime` is only useful when the machine is shared with Windows";
+
+#[test]
+fn utc()
+{
+    assert_eq!(hwclock_lines(""), ["hwclock --systohc"]);
+    assert_eq!(hwclock_lines("hardware_clock: utc\n"), ["hwclock --systohc"]);
+    assert_eq!(hwclock_lines("chroot_backend: nspawn\n"), ["hwclock --systohc --adjfile=/mnt/etc/adjtime"]);
+}
+
+#[test]
+fn localtime()
+{
+    let lines = "hardware_clock: localtime\npackages: vim os-prober\n";
+    assert_eq!(hwclock_lines(lines), ["hwclock --systohc --localtime"]);
+    // outside the target system, hwclock is given its timezone
+    assert_eq!(hwclock_lines(&format!("{}chroot_backend: nspawn\n", lines)),
+        ["TZ=Europe/London hwclock --systohc --localtime --adjfile=/mnt/etc/adjtime"]);
+
+    let output = generate(lines);
+    assert!(!String::from_utf8_lossy(&output.stderr).contains(WARNING));
+    let script = String::from_utf8(output.stdout).unwrap();
+    assert!(script.contains("\necho 'warning: the hardware clock keeps local time, for Windows; it can be an hour off after daylight saving time changes, if both systems adjust it' >&2\nhwclock --systohc --localtime\n"));
+}
+
+#[test]
+fn localtime_without_windows()
+{
+    let output = generate("hardware_clock: localtime\n");
+    assert!(output.status.success());
+    assert!(String::from_utf8_lossy(&output.stderr).contains(WARNING));
+    let output = generate("hardware_clock: localtime\nbootloader: efistub\nkeep_existing_entries: true\n");
+    assert!(output.status.success(), "jimmy failed: {}", String::from_utf8_lossy(&output.stderr));
+    assert!(!String::from_utf8_lossy(&output.stderr).contains(WARNING));
+}
+
+#[test]
+fn invalid()
+{
+    let output = generate("hardware_clock: local\n");
+    assert!(!output.status.success());
+    assert!(String::from_utf8_lossy(&output.stderr).contains("invalid hardware_clock: \"local\" (expected one of: utc, localtime)"));
+}
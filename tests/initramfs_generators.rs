@@ -0,0 +1,100 @@
+//! Checks mkinitcpio and dracut over the same features of the configuration file: what each is
+//! told to put in the initramfs, the crypttab the encrypted root partition is unlocked from, the
+//! packages, and that the images are created once, after everything that changes them
+
+mod common;
+
+/// Return the installation script and the arch-chroot script of the sample configuration file,
+/// with the given initramfs generator and lines added to its root partition, the commands of both
+/// on one line each
+fn scripts(generator: &str, root: &str) -> (String, String)
+{
+    let generate = |command: &str| common::script(common::generate(
+        &common::with_flags(&["--width", "0"], &[command]),
+        &[("    mount: /\n", &format!("    mount: /\n{}", root))],
+        &format!("initramfs_generator: {}\n", generator),
+    ));
+    (generate("--file"), generate("chroot-script"))
+}
+
+/// Return the part of the arch-chroot script that sets up the initramfs, and rebuilds it, if any
+fn initramfs(chroot_script: &str) -> Vec<&str>
+{
+    chroot_script.lines()
+        .skip_while(|l| *l != "echo '<chroot> setting up the initramfs...'")
+        .take_while(|l| !l.starts_with("echo '<chroot> setting up the scripts") && !l.starts_with("echo '<chroot> setting up bootloader"))
+        .filter(|l| !l.is_empty())
+        .collect()
+}
+
+/// The root partition encrypted, and unlocked with the TPM2 chip
+const ENCRYPTED: &str = "    encryption: {}\n";
+const TPM2: &str = "    encryption: { tpm2: true }\n";
+const SYSTEMD_HOOKS: &str = "sed --in-place 's/^HOOKS=.*/HOOKS=(base systemd autodetect microcode modconf kms keyboard sd-vconsole block sd-encrypt filesystems fsck)/' /etc/mkinitcpio.conf";
+const DRACUT_REBUILD: &str = "ls /usr/lib/modules/*/vmlinuz | sed 's|^/||' | /usr/local/lib/jimmy/dracut-install";
+
+#[test]
+fn same_features()
+{
+    for (root, unlock, modules) in [("", None, None), (ENCRYPTED, Some("luks"), Some("crypt")), (TPM2, Some("tpm2-device=auto"), Some("crypt tpm2-tss"))] {
+        let crypttab = |path: &str| unlock.map(|u| format!("echo \"cryptroot UUID=$(blkid -s UUID -o value /dev/sda2) none {}\" >>{}", u, path));
+
+        let (_, mkinitcpio) = scripts("mkinitcpio", root);
+        let lines = initramfs(&mkinitcpio);
+        match &crypttab("/etc/crypttab.initramfs") {
+            // the images pacstrap made already do without the encrypted root partition
+            None => assert!(lines.is_empty(), "{:?}", lines),
+            Some(crypttab) => assert_eq!(lines, [
+                "echo '<chroot> setting up the initramfs...'",
+                crypttab,
+                SYSTEMD_HOOKS,
+                "echo '<chroot> rebuilding the initramfs...'",
+                "mkinitcpio -P",
+            ]),
+        }
+
+        let (_, dracut) = scripts("dracut", root);
+        let lines = initramfs(&dracut);
+        let mut expected = crypttab("/etc/crypttab").into_iter().collect::<Vec<String>>();
+        if let Some(modules) = modules {
+            expected.extend(["cat <<'END_OF_FILE' >/etc/dracut.conf.d/jimmy.conf".to_string(), format!("add_dracutmodules+=\" {} \"", modules), "END_OF_FILE".to_string()]);
+        }
+        assert_eq!(lines[1..expected.len() + 1], expected, "{:?}", root);
+        // dracut has no pacman hooks of its own, so they're installed whatever the features, and
+        // the images created by hand once they are
+        assert_eq!(lines[expected.len() + 1], "mkdir -p /etc/pacman.d/hooks /usr/local/lib/jimmy");
+        assert_eq!(lines[lines.len() - 2..], ["echo '<chroot> rebuilding the initramfs...'", DRACUT_REBUILD]);
+        assert!(!dracut.contains("mkinitcpio") && !dracut.contains("crypttab.initramfs"));
+        assert!(!mkinitcpio.contains("dracut"));
+    }
+}
+
+#[test]
+fn packages()
+{
+    for (root, tpm2) in [("", ""), (TPM2, " tpm2-tss")] {
+        let (mkinitcpio, _) = scripts("mkinitcpio", root);
+        let (dracut, _) = scripts("dracut", root);
+        assert!(mkinitcpio.contains(&format!(" networkmanager{} 2>&1;", tpm2)), "{}", mkinitcpio);
+        assert!(dracut.contains(&format!(" networkmanager{} dracut 2>&1;", tpm2)), "{}", dracut);
+    }
+}
+
+#[test]
+fn dracut_install()
+{
+    // the script the pacman hook runs, with the kernel pacman just installed
+    let (_, script) = scripts("dracut", "");
+    let start = "cat <<'END_OF_FILE' >/usr/local/lib/jimmy/dracut-install\n";
+    let from = script.find(start).unwrap() + start.len();
+    let install = &script[from..from + script[from..].find("END_OF_FILE\n").unwrap()];
+    let code = format!("mkdir -p \"$DIR/usr/lib/modules/6.1.1-arch1-1\"\necho linux-lts >\"$DIR/usr/lib/modules/6.1.1-arch1-1/pkgbase\"\n\
+        echo usr/lib/modules/6.1.1-arch1-1/vmlinuz | {{\n{}}}\ncat \"$DIR/calls\"",
+        install.replace("\"/usr/lib/modules/", "\"$DIR/usr/lib/modules/").replace("\"/$line\"", "\"$DIR/$line\""));
+    let record = |program: &str| format!("echo \"{} $*\" | sed \"s|$DIR||g\" >>\"$DIR/calls\"", program);
+    let (success, stdout, stderr) = common::sh(&code, &[("install", &record("install")), ("dracut", &record("dracut"))], "");
+    assert!(success, "{}", stderr);
+    assert_eq!(stdout, "install -Dm0644 /usr/lib/modules/6.1.1-arch1-1/vmlinuz /boot/vmlinuz-linux-lts\n\
+        dracut --force --hostonly --no-hostonly-cmdline /boot/initramfs-linux-lts.img 6.1.1-arch1-1\n\
+        dracut --force --no-hostonly /boot/initramfs-linux-lts-fallback.img 6.1.1-arch1-1\n");
+}
@@ -0,0 +1,97 @@
+//! Checks that the script refuses to partition the disk holding the live medium, whether it's still
+//! mounted or was copied to RAM. The check is ran with findmnt and lsblk replaced by programs that
+//! print outputs saved from live systems, and with /proc/cmdline moved into a temporary directory
+
+mod common;
+
+/// Return the check of the script generated from the sample configuration file, with its disk
+/// replaced by `disk`, from its comment to its last `done`
+fn medium_check(disk: &str) -> String
+{
+    let script = common::script(common::generate(&["--file"], &[("\ndisk: /dev/sda\n", &format!("\ndisk: {}\n", disk))], ""));
+    let start = script.find("# the devices of the live medium, even if it was copied to RAM\n").unwrap();
+    let end = start + script[start..].find("\n    done\ndone\n").unwrap() + "\n    done\ndone\n".len();
+    script[start..end].to_string()
+}
+
+/// A live system booted from a USB stick the ISO was written to, as /dev/sdb: `findmnt -nro
+/// SOURCE,TARGET`, the kernel command line, and `lsblk -nsrpo NAME,TYPE` of the devices it names
+const USB: (&str, &str, &[(&str, &str)]) = (
+    "/dev/sdb1 /run/archiso/bootmnt\n/dev/loop0 /run/archiso/airootfs\nairootfs /\n",
+    "BOOT_IMAGE=/arch/boot/x86_64/vmlinuz-linux archisobasedir=arch archisosearchuuid=2024-05-01-17-23-53-00\n",
+    &[
+        ("/dev/sdb1", "/dev/sdb1 part\n/dev/sdb disk\n"),
+        ("/dev/loop0", "/dev/loop0 loop\n"),
+        ("/dev/disk/by-uuid/2024-05-01-17-23-53-00", "/dev/sdb disk\n"),
+    ],
+);
+
+/// The same system booted with `copytoram`: the medium isn't mounted anymore
+const COPYTORAM: (&str, &str, &[(&str, &str)]) = (
+    "/dev/loop0 /run/archiso/airootfs\nairootfs /\n",
+    "BOOT_IMAGE=/arch/boot/x86_64/vmlinuz-linux archisobasedir=arch archisolabel=ARCH_202405 copytoram\n",
+    &[
+        ("/dev/loop0", "/dev/loop0 loop\n"),
+        ("/dev/disk/by-label/ARCH_202405", "/dev/sdb1 part\n/dev/sdb disk\n"),
+    ],
+);
+
+/// Run `check` on the given live system with the given arguments, and return whether it let the
+/// script go on along with what it wrote to stderr
+fn run(check: &str, live: (&str, &str, &[(&str, &str)]), args: &[&str]) -> (bool, String)
+{
+    let (findmnt, cmdline, lsblk) = live;
+    let cases: String = lsblk.iter()
+        .map(|(device, output)| format!("    {}) printf '%s' '{}' ;;\n", device, output))
+        .collect();
+    let findmnt = format!("printf '%s' '{}'", findmnt);
+    let lsblk = format!("case \"$3\" in\n{}    *) echo \"lsblk: $3: not a block device\" >&2; exit 32 ;;\nesac", cases);
+    let code = format!("set -- {}\nprintf '%s' '{}' >\"$DIR/cmdline\"\n\
+        case \" $* \" in\n    *\" --allow-install-medium \"*) JIMMY_ALLOW_INSTALL_MEDIUM=1 ;;\nesac\n{}echo passed",
+        args.join(" "), cmdline, check.replace("/proc/cmdline", "\"$DIR/cmdline\""));
+    let (_, stdout, stderr) = common::sh(&code, &[("findmnt", &findmnt), ("lsblk", &lsblk)], "");
+    (stdout == "passed\n", stderr)
+}
+
+#[test]
+fn medium_is_refused()
+{
+    let check = medium_check("/dev/sdb");
+    for (live, name) in [(USB, "usb"), (COPYTORAM, "copytoram")] {
+        let (passed, stderr) = run(&check, live, &[]);
+        assert!(!passed, "{}", name);
+        assert_eq!(stderr, "error: /dev/sdb holds the live medium, even if it was copied to RAM; pass --allow-install-medium to continue anyway\n");
+    }
+}
+
+#[test]
+fn other_disks_are_not()
+{
+    let check = medium_check("/dev/sda");
+    assert_eq!(run(&check, USB, &[]), (true, String::new()));
+    assert_eq!(run(&check, COPYTORAM, &[]), (true, String::new()));
+    // nor is anything, if the live medium can't be found
+    assert_eq!(run(&medium_check("/dev/sdb"), ("airootfs /\n", "quiet\n", &[]), &[]), (true, String::new()));
+}
+
+#[test]
+fn overridden()
+{
+    let check = medium_check("/dev/sdb");
+    assert_eq!(run(&check, COPYTORAM, &["--allow-install-medium"]), (true, String::new()));
+    assert!(!run(&check, COPYTORAM, &["--i-know-what-i-am-doing"]).0);
+}
+
+#[test]
+fn archisodevice()
+{
+    let check = medium_check("/dev/sdb");
+    for (argument, device) in [
+        ("archisodevice=UUID=1234-ABCD", "/dev/disk/by-uuid/1234-ABCD"),
+        ("archisodevice=LABEL=ARCH_202405", "/dev/disk/by-label/ARCH_202405"),
+        ("archisodevice=/dev/sdb1", "/dev/sdb1"),
+    ] {
+        let live = ("", argument, &[(device, "/dev/sdb1 part\n/dev/sdb disk\n")][..]);
+        assert!(!run(&check, live, &[]).0, "{}", argument);
+    }
+}
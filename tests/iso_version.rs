@@ -0,0 +1,99 @@
+//! Checks the release of the ISO the script asks of the live system, for the features of the
+//! configuration and `min_iso_version`. The check is ran with /version and /etc/os-release moved
+//! into a temporary directory
+
+use std::process::{Command, Output};
+
+mod common;
+
+/// Generate the script from the sample configuration file, with the given lines appended
+fn generate(extra_lines: &str) -> Output
+{
+    common::generate(&["--file"], &[], extra_lines)
+}
+
+/// Return the check of the script generated with the given lines, from its comment to `esac`
+fn iso_check(extra_lines: &str) -> String
+{
+    let script = common::script(generate(extra_lines));
+    let start = script.find("# the release of the ISO the live system was made from\n").unwrap();
+    let end = start + script[start..].find("\nesac\n").unwrap() + "\nesac\n".len();
+    script[start..end].to_string()
+}
+
+/// Run `check` on a live system with the given /version and /etc/os-release, and return whether
+/// it let the script go on along with what it wrote to stderr
+fn run(check: &str, version: Option<&str>, os_release: &str) -> (bool, String)
+{
+    let dir = common::temp_path("dir");
+    std::fs::create_dir_all(&dir).unwrap();
+    if let Some(version) = version {
+        std::fs::write(dir.join("version"), format!("{}\n", version)).unwrap();
+    }
+    std::fs::write(dir.join("os-release"), os_release).unwrap();
+    let check = check
+        .replace("/version", &dir.join("version").display().to_string())
+        .replace("/etc/os-release", &dir.join("os-release").display().to_string());
+    let output = Command::new("sh").args(["-c", &format!("{}\necho passed", check)]).output().unwrap();
+    std::fs::remove_dir_all(&dir).unwrap();
+    (String::from_utf8(output.stdout).unwrap() == "passed\n", String::from_utf8(output.stderr).unwrap())
+}
+
+#[test]
+fn features_need_their_release()
+{
+    let check = iso_check("");
+    assert!(check.contains("\n        jimmy_iso_check 2020.09.01 'partition types given to fdisk by name (util-linux 2.36)'\n"));
+    assert!(!check.contains("LUKS2"));
+    assert_eq!(run(&check, Some("2020.09.01"), ""), (true, String::new()));
+
+    let check = iso_check("chroot_backend: nspawn\n");
+    let (passed, stderr) = run(&check, Some("2018.06.01"), "");
+    assert!(!passed);
+    assert_eq!(stderr, "error: the live system was made from the ISO released on 2018.06.01, which is too old for:\n    \
+        partition types given to fdisk by name (util-linux 2.36): 2020.09.01 or newer\n    \
+        `chroot_backend: nspawn` (systemd-nspawn --resolv-conf, systemd 239): 2018.08.01 or newer\n");
+}
+
+#[test]
+fn min_iso_version()
+{
+    let check = iso_check("min_iso_version: 2023.01.01\n");
+    let (passed, stderr) = run(&check, Some("2022.12.01"), "");
+    assert!(!passed);
+    assert!(stderr.ends_with(":\n    min_iso_version: 2023.01.01 or newer\n"), "{}", stderr);
+    assert!(run(&check, Some("2024.05.01"), "").0);
+    // the release is also found in os-release, quoted or not
+    assert!(run(&check, None, "NAME=\"Arch Linux\"\nIMAGE_VERSION=\"2024.05.01\"\n").0);
+    assert!(!run(&check, None, "IMAGE_VERSION=2022.12.01\n").0);
+}
+
+#[test]
+fn unknown_release()
+{
+    assert_eq!(run(&iso_check(""), None, "NAME=\"Arch Linux\"\n"), (true, String::new()));
+    let (passed, stderr) = run(&iso_check("min_iso_version: 2023.01.01\n"), None, "");
+    assert!(passed);
+    assert_eq!(stderr, "warning: the release of the live ISO is unknown, so it isn't checked against min_iso_version\n");
+}
+
+#[test]
+fn explained()
+{
+    let jimmy = env!("CARGO_BIN_EXE_jimmy");
+    let output = Command::new(jimmy).args(["explain", "--markdown", "examples/valid--encrypted_root_busybox.yaml"]).output().unwrap();
+    let explanation = String::from_utf8(output.stdout).unwrap();
+    let encryption = explanation.split("\n## ").find(|s| s.contains(". encryption\n")).unwrap();
+    assert!(encryption.contains(" It needs a live system made from the ISO released on 2018.02.01 or later, for LUKS2 containers (cryptsetup 2.0).\n"));
+}
+
+#[test]
+fn invalid()
+{
+    for version in ["2023-01-01", "2023.13.01", "latest"] {
+        let output = generate(&format!("min_iso_version: {}\n", version));
+        assert!(!output.status.success());
+        assert!(String::from_utf8_lossy(&output.stderr)
+            .contains(&format!("invalid min_iso_version: \"{}\" (expected a release of the ISO such as 2024.01.01)", version)));
+    }
+}
@@ -0,0 +1,118 @@
+//! Checks the kernel command line of every bootloader: how `kernel_params`, the parameters of the
+//! chosen bootloader and those of the `hardening` toggles are merged, where each ends up in the
+//! script, and the boot entries of the plan, with their whole command line
+
+use std::process::Output;
+
+mod common;
+
+/// Generate the script from the sample configuration file, with the given lines appended
+fn generate(extra_lines: &str) -> Output
+{
+    common::generate(&["--width", "0", "--file"], &[], extra_lines)
+}
+
+/// Return the sample configuration file, with the given lines appended
+fn config(extra_lines: &str) -> String
+{
+    common::config(&[], extra_lines)
+}
+
+/// Return the script, checking that jimmy succeeded
+fn generated(extra_lines: &str) -> String
+{
+    common::script(generate(extra_lines))
+}
+
+/// Return the boot entries of the plan, as `(entry, cmdline)`
+fn boot_entries(extra_lines: &str) -> Vec<(String, String)>
+{
+    let response = common::api_plan(&config(extra_lines));
+    assert_eq!(response["valid"], true, "{}", response["errors"]);
+    response["plan"]["boot_entries"].as_array().unwrap().iter()
+        .map(|e| (e["entry"].as_str().unwrap().to_string(), e["cmdline"].as_str().unwrap().to_string()))
+        .collect()
+}
+
+/// Parameters for every bootloader: the one of `hardening` is replaced by the shared one, whose
+/// `console=` and `quiet` are replaced by those of the bootloader
+const SHARED: &str = "hardening:\n  kernel_lockdown: true\nkernel_params: quiet console=tty0 lockdown=none quiet\n";
+
+#[test]
+fn efistub()
+{
+    let lines = format!("{}bootloader: efistub\nefistub:\n  extra_params: [ \"console=ttyS0,115200\", console=tty1, quiet ]\n", SHARED);
+    let script = generated(&lines);
+    let cmdline = "root=/dev/sda2 lockdown=none console=ttyS0,115200 console=tty1 quiet rw initrd=\\initramfs-linux.img";
+    assert!(script.contains(&format!(" --loader /vmlinuz-linux --unicode '{}' --verbose\n", cmdline)));
+    assert_eq!(boot_entries(&lines), [("Arch Linux".to_string(), cmdline.to_string())]);
+}
+
+#[test]
+fn systemd_boot()
+{
+    let lines = format!("{}bootloader: systemd-boot\nsystemd_boot:\n  extra_params: splash\n", SHARED);
+    let script = generated(&lines);
+    assert!(script.contains("\noptions root=/dev/sda2 quiet console=tty0 lockdown=none splash rw\n"));
+    assert_eq!(boot_entries(&lines), [("Arch Linux".to_string(), "root=/dev/sda2 quiet console=tty0 lockdown=none splash rw".to_string())]);
+}
+
+#[test]
+fn grub()
+{
+    let lines = format!("{}grub:\n  cmdline_linux: [ nomodeset ]\n  cmdline_linux_default: loglevel=4\n", SHARED);
+    let script = generated(&lines);
+    assert!(script.contains("\ngrep -qF 'GRUB_CMDLINE_LINUX=\"quiet console=tty0 lockdown=none nomodeset ' /etc/default/grub || \
+        sed --in-place 's|^GRUB_CMDLINE_LINUX=\"|&quiet console=tty0 lockdown=none nomodeset |' /etc/default/grub\n"));
+    assert!(script.contains("\nsed --in-place 's|^GRUB_CMDLINE_LINUX_DEFAULT=.*|GRUB_CMDLINE_LINUX_DEFAULT=\"loglevel=4\"|' /etc/default/grub\n"));
+    assert_eq!(boot_entries(&lines), [(
+        "Arch Linux".to_string(),
+        "root=UUID=<uuid-of-the-root-filesystem> rw quiet console=tty0 lockdown=none nomodeset loglevel=4".to_string(),
+    )]);
+
+    // GRUB_CMDLINE_LINUX_DEFAULT is left alone unless it's given, and can be emptied
+    let script = generated("");
+    assert!(!script.contains("GRUB_CMDLINE_LINUX"));
+    assert_eq!(boot_entries("")[0].1, "root=UUID=<uuid-of-the-root-filesystem> rw loglevel=3 quiet");
+    assert!(generated("grub:\n  cmdline_linux_default: []\n").contains("|GRUB_CMDLINE_LINUX_DEFAULT=\"\"|"));
+}
+
+#[test]
+fn other_bootloaders_are_ignored()
+{
+    let lines = "bootloader: systemd-boot\ngrub:\n  cmdline_linux: nomodeset\nefistub:\n  extra_params: quiet\n";
+    let output = generate(lines);
+    assert!(output.status.success());
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(stderr.contains("warning: grub is only used with `bootloader: grub`; it's going to be ignored"));
+    assert!(stderr.contains("warning: efistub is only used with `bootloader: efistub`; it's going to be ignored"));
+    assert!(String::from_utf8(output.stdout).unwrap().contains("\noptions root=/dev/sda2 rw\n"));
+}
+
+#[test]
+fn secondary_esps()
+{
+    let lines = "bootloader: efistub\nkernel_params: quiet\npartitions:\n  - esp:\n    format: fat32\n    size: 500M\n    mount: /boot\n  \
+        - esp2:\n    disk: /dev/sdb\n    format: fat32\n    size: 500M\n    esp: true\n  - root:\n    format: ext4\n    mount: /\n";
+    let cmdline = "root=/dev/sda2 quiet rw initrd=\\initramfs-linux.img".to_string();
+    assert_eq!(boot_entries(lines), [
+        ("Arch Linux".to_string(), cmdline.clone()),
+        ("Arch Linux (ESP 2)".to_string(), cmdline),
+    ]);
+}
+
+#[test]
+fn invalid()
+{
+    for (lines, message) in [
+        ("kernel_params: [ \"quiet'\" ]\n", "invalid kernel_params: \"quiet'\" (expected a kernel parameter, such as quiet or console=ttyS0,115200"),
+        ("grub:\n  cmdline_linux: a|b\n", "invalid grub.cmdline_linux: \"a|b\""),
+        ("efistub:\n  extra_params: [ \"two words\" ]\n", "invalid efistub.extra_params: \"two words\""),
+        ("kernel_params: root=/dev/sdb2\n", "kernel_params can't have root=/dev/sdb2: jimmy sets the parameters root, cryptdevice, rw, ro, initrd by itself"),
+        ("systemd_boot:\n  extra_params: ro\n", "systemd_boot.extra_params can't have ro: "),
+    ] {
+        let output = generate(lines);
+        assert!(!output.status.success(), "{}", lines);
+        assert!(String::from_utf8_lossy(&output.stderr).contains(message), "{}", String::from_utf8_lossy(&output.stderr));
+    }
+}
@@ -0,0 +1,108 @@
+//! Checks `keymap`: it's loaded on the live system and set on the installed one, and the keymap
+//! the live system ends up with is handed to the arch-chroot script, which tells it before the
+//! passwords are asked for. The commands are ran with sh, with loadkeys replaced and the files
+//! they read and write moved into a temporary directory
+
+use std::process::Output;
+
+mod common;
+
+/// Generate the script from the sample configuration file, with the given lines appended
+fn generate(extra_lines: &str) -> Output
+{
+    common::generate(&["--file"], &[], extra_lines)
+}
+
+/// Return the script, checking that jimmy succeeded
+fn generated(extra_lines: &str) -> String
+{
+    common::script(generate(extra_lines))
+}
+
+/// Return the lines of `script` from the one starting with `first` to the next one that's `last`
+fn lines<'a>(script: &'a str, first: &str, last: &str) -> &'a str
+{
+    let start = script.find(&format!("\n{}", first)).unwrap() + 1;
+    let end = start + script[start..].find(&format!("\n{}\n", last)).unwrap() + last.len() + 2;
+    &script[start..end]
+}
+
+/// Run what the script does about the keymap, from loading it on a live system whose loadkeys
+/// succeeds or not, to the note before the passwords, and return what it wrote to stdout and to
+/// stderr
+fn run(script: &str, loads: bool) -> (String, String)
+{
+    let mut code = "printf 'KEYMAP=\"fr\"\\n' >\"$DIR/vconsole.conf\"\n".to_string();
+    if script.contains("\nJIMMY_STEP=keymap\n") {
+        code += lines(script, "if loadkeys ", "fi");
+        code += script.lines().find(|l| l.starts_with("printf '%s\\n' \"$JIMMY_LIVE_KEYMAP\" >")).unwrap();
+        code += "\n";
+    }
+    code += lines(script, "jimmy_live_keymap=", "fi");
+    let code = code
+        .replace("/mnt/jimmy_live_keymap", "\"$DIR/live_keymap\"")
+        .replace("/jimmy_live_keymap", "\"$DIR/live_keymap\"")
+        .replace("/etc/vconsole.conf", "\"$DIR/vconsole.conf\"");
+    let (success, stdout, stderr) = common::sh(&code, &[("loadkeys", if loads { "" } else { "exit 1" })], "");
+    assert!(success);
+    (stdout, stderr)
+}
+
+#[test]
+fn matching_keymap()
+{
+    let script = generated("keymap: de-latin1\n");
+    let steps: Vec<&str> = script.lines().filter_map(|l| l.strip_prefix("JIMMY_STEP=")).filter(|s| !s.is_empty()).collect();
+    assert_eq!(steps[..3], ["preflight", "keymap", "'previous mounts'"]);
+    assert!(script.contains("\necho 'KEYMAP=de-latin1' >/etc/vconsole.conf\n"));
+    assert!(script.contains("\nrm -f /mnt/jimmy_part2.sh /mnt/jimmy_live_keymap\n"));
+    assert_eq!(run(&script, true), ("note: passwords are typed with the 'de-latin1' keymap, the one of the installed system\n".to_string(), String::new()));
+}
+
+#[test]
+fn mismatching_keymap()
+{
+    let script = generated("keymap: de-latin1\n");
+    let (stdout, stderr) = run(&script, false);
+    assert_eq!(stdout, "");
+    assert_eq!(stderr, "warning: the keymap 'de-latin1' couldn't be loaded, so the live system keeps 'fr'\n\
+        warning: passwords are typed with the 'fr' keymap of the live system, but the installed system uses 'de-latin1'; they might not work after rebooting\n");
+}
+
+#[test]
+fn default_keymap()
+{
+    let script = generated("");
+    assert!(!script.contains("loadkeys") && !script.contains("vconsole.conf"));
+    assert!(script.contains("\nrm -f /mnt/jimmy_part2.sh\n"));
+    // the live system's keymap isn't known, so the note can only tell the installed system's
+    assert_eq!(run(&script, true).0,
+        "note: the installed system uses the 'us' keymap; if the live system uses another one, passwords typed here might not work after rebooting\n");
+
+    // nothing's typed, so there's no note
+    let script = generated("root_password_policy: locked\nusers: []\n");
+    assert!(!script.contains("jimmy_live_keymap"));
+}
+
+#[test]
+fn initramfs()
+{
+    // the passphrase of the encrypted root is typed in the initramfs, which has to know the keymap
+    let script = generated("keymap: de-latin1\n");
+    assert!(!script.contains("<chroot> rebuilding the initramfs..."));
+    let example = std::fs::read_to_string("examples/valid--encrypted_root_busybox.yaml").unwrap();
+    let script = common::script(common::jimmy(&["--file"], &format!("{}\nkeymap: de-latin1\n", example)));
+    let vconsole = script.find("\necho 'KEYMAP=de-latin1' >/etc/vconsole.conf\n").unwrap();
+    assert!(script[vconsole..].contains("\necho '<chroot> rebuilding the initramfs...'\n"));
+}
+
+#[test]
+fn invalid()
+{
+    for keymap in ["de latin1", "'us'", "-us"] {
+        let output = generate(&format!("keymap: \"{}\"\n", keymap.replace('"', "\\\"")));
+        assert!(!output.status.success());
+        assert!(String::from_utf8_lossy(&output.stderr)
+            .contains(&format!("invalid keymap: \"{}\" (expected the name of a keymap such as de-latin1, as listed by `localectl list-keymaps`)", keymap)));
+    }
+}
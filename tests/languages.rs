@@ -0,0 +1,44 @@
+//! Checks `language`: the status messages of both scripts are all translated, and nothing else in
+//! them changes
+
+mod common;
+
+/// What turns on the steps whose messages the sample doesn't print
+const STEPS: &str = "timings: true\nreport: /root/install-report.txt\nfstab_extra:\n  - fs: tmpfs\n    dir: /tmp\n    type: tmpfs\n    options: defaults\n";
+
+/// Return the script jimmy makes with `--reproducible` and `args` from the sample configuration
+/// file with `STEPS` and the given language, without the hash of the file
+fn script(args: &[&str], language: &str) -> String
+{
+    let args = common::with_flags(&["--reproducible"], args);
+    common::without_hash(&common::script(common::generate(&args, &[], &format!("{}language: {}\n", STEPS, language))))
+}
+
+/// Return whether a line of a script prints a status message
+fn is_status(line: &str) -> bool
+{
+    line.contains("<->") || line.contains("<chroot>")
+}
+
+#[test]
+fn translated()
+{
+    for args in [&["--file"][..], &["chroot-script"]] {
+        let english = script(args, "en");
+        let statuses = english.lines().filter(|l| is_status(l)).count();
+        assert!(statuses > 10, "{:?}: {}", args, statuses);
+        for language in ["es", "de"] {
+            let translated = script(args, language);
+            // every status line is in the same place, and none of them is left in English
+            assert_eq!(english.lines().count(), translated.lines().count(), "{:?} {}", args, language);
+            for (en, other) in english.lines().zip(translated.lines()) {
+                assert_eq!(is_status(en), is_status(other), "{:?} {}: {} / {}", args, language, en, other);
+                if is_status(en) {
+                    assert_ne!(en, other, "{:?} {}", args, language);
+                } else {
+                    assert_eq!(en, other, "{:?} {}", args, language);
+                }
+            }
+        }
+    }
+}
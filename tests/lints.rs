@@ -0,0 +1,117 @@
+//! Checks `jimmy validate`, and each of the lints it runs with `--lint`: what they find, how
+//! they're allowed from the command line and from the configuration file, and `--deny-warnings`
+
+use std::process::Output;
+
+mod common;
+
+/// Run `jimmy validate` with the given arguments on the sample configuration file, with its root
+/// partition's lines replaced by `root` and the given lines appended
+fn validate(root: &str, extra_lines: &str, args: &[&str]) -> Output
+{
+    common::generate(&[&["validate"], args].concat(), &[("    format: ext4\n    mount: /\n", root)], extra_lines)
+}
+
+/// Return the names of the lints that found something, checking that jimmy succeeded
+fn lints(root: &str, extra_lines: &str) -> Vec<String>
+{
+    let output = validate(root, extra_lines, &["--lint"]);
+    assert!(output.status.success(), "jimmy failed: {}", String::from_utf8_lossy(&output.stderr));
+    String::from_utf8(output.stderr).unwrap().lines()
+        .filter_map(|l| l.strip_prefix("lint "))
+        .map(|l| l.split_once(':').unwrap().0.to_string())
+        .collect()
+}
+
+const ROOT: &str = "    format: ext4\n    mount: /\n";
+/// A root partition none of the lints find anything about, along with a swap partition
+const FINE: &str = "    format: ext4\n    mount: /\n    mount_options: noatime\n  - swap:\n    format: swap\n    size: 4G\n";
+
+#[test]
+fn validate_only_lints_when_asked()
+{
+    let output = validate(ROOT, "", &[]);
+    assert!(output.status.success());
+    assert_eq!(String::from_utf8(output.stdout).unwrap(), "");
+    assert!(!String::from_utf8(output.stderr).unwrap().contains("lint "));
+    assert_eq!(lints(FINE, ""), Vec::<String>::new());
+
+    let output = validate("    format: ext4\n    mount: /\n    raid_profile: raid1\n", "", &["--lint"]);
+    assert!(!output.status.success());
+}
+
+#[test]
+fn no_swap()
+{
+    assert_eq!(lints(ROOT, ""), ["no-swap", "ext4-root-without-noatime"]);
+    assert!(!lints(FINE, "").contains(&"no-swap".to_string()));
+}
+
+#[test]
+fn small_esp()
+{
+    // the EFI system partition of the sample is 500M and mounted at /boot, but GRUB doesn't
+    // keep the kernels on it
+    assert_eq!(lints(FINE, "bootloader: efistub\n"), ["small-esp"]);
+    assert_eq!(lints(FINE, "bootloader: systemd-boot\n"), ["small-esp"]);
+    assert_eq!(lints(FINE, ""), Vec::<String>::new());
+    let output = validate(FINE, "bootloader: efistub\n", &["--lint"]);
+    assert!(String::from_utf8(output.stderr).unwrap()
+        .contains("lint small-esp: the EFI system partition (500M) holds the kernels, and under 512M it has little room for another kernel or a bigger initramfs\n"));
+}
+
+#[test]
+fn ext4_root_without_noatime()
+{
+    let swap = "  - swap:\n    format: swap\n    size: 4G\n";
+    assert_eq!(lints(&format!("{}{}", ROOT, swap), ""), ["ext4-root-without-noatime"]);
+    assert_eq!(lints(&format!("{}    mount_options: relatime\n{}", ROOT, swap), ""), ["ext4-root-without-noatime"]);
+    // the options given for every ext4 filesystem count
+    assert_eq!(lints(&format!("{}{}", ROOT, swap), "default_mount_options:\n  ext4: noatime,commit=60\n"), Vec::<String>::new());
+    assert_eq!(lints(&format!("    format: btrfs\n    mount: /\n{}", swap), ""), Vec::<String>::new());
+}
+
+#[test]
+fn sshd_without_firewall()
+{
+    assert_eq!(lints(FINE, "packages: vim openssh\n"), ["sshd-without-firewall"]);
+    assert_eq!(lints(FINE, "packages: vim openssh nftables\n"), Vec::<String>::new());
+}
+
+#[test]
+fn desktop_without_display_manager()
+{
+    let output = validate(FINE, "packages: vim plasma-meta\n", &["--lint"]);
+    assert!(String::from_utf8(output.stderr).unwrap()
+        .contains("lint desktop-without-display-manager: plasma-meta is installed, but no display manager (sddm, gdm, lightdm, lxdm, ly, greetd) is, so the installed system starts on a console\n"));
+    assert_eq!(lints(FINE, "packages: vim plasma-meta sddm\n"), Vec::<String>::new());
+}
+
+#[test]
+fn allowed()
+{
+    let output = validate(ROOT, "", &["--lint", "--allow", "no-swap", "--allow", "ext4-root-without-noatime"]);
+    assert!(!String::from_utf8(output.stderr).unwrap().contains("lint "));
+    assert_eq!(lints(ROOT, "allow_lints: [ no-swap ]\n"), ["ext4-root-without-noatime"]);
+
+    let output = validate(ROOT, "", &["--lint", "--allow", "swap"]);
+    assert!(!output.status.success());
+    assert!(String::from_utf8(output.stderr).unwrap().starts_with("error: there's no lint 'swap'; the lints are: no-swap, small-esp, "));
+    let output = validate(ROOT, "allow_lints: [ swap ]\n", &["--lint"]);
+    assert!(!output.status.success());
+    assert!(String::from_utf8_lossy(&output.stderr).contains("invalid allow_lints: \"swap\" (expected one of: no-swap, small-esp, "));
+}
+
+#[test]
+fn deny_warnings()
+{
+    let output = validate(ROOT, "", &["--lint", "--deny-warnings"]);
+    assert_eq!(output.status.code(), Some(1));
+    assert!(String::from_utf8(output.stderr).unwrap()
+        .ends_with("error: the configuration isn't free of warnings, and --deny-warnings was given\n"));
+    assert!(validate(FINE, "", &["--lint", "--deny-warnings"]).status.success());
+    assert!(validate(ROOT, "", &["--deny-warnings"]).status.success());
+    // jimmy's own warnings count too
+    assert_eq!(validate(FINE, "hardware_clock: localtime\n", &["--deny-warnings"]).status.code(), Some(1));
+    assert_eq!(validate(FINE, "hardware_clock: localtime\n", &["--deny-warnings", "--quiet"]).status.code(), Some(1));
+}
@@ -0,0 +1,75 @@
+//! Checks the programs the installation script looks for on the live system, by the package that
+//! provides them, for a few configurations that need different ones, and what it does about the
+//! missing ones with `offline`
+
+mod common;
+
+/// Return the packages and programs the installation script of the sample configuration file
+/// checks for, with the given lines replaced and appended
+fn checked(replacements: &[(&str, &str)], extra_lines: &str) -> Vec<String>
+{
+    let script = common::script(common::generate(&["--file"], replacements, extra_lines));
+    script.lines().filter_map(|l| l.strip_prefix("jimmy_check ")).map(String::from).collect()
+}
+
+const ROOT: &str = "    format: ext4\n    mount: /\n";
+const HOOKS: &str = "mkinitcpio_hooks: [ base, udev, autodetect, microcode, modconf, kms, keyboard, keymap, consolefont, block, encrypt, filesystems, fsck ]\n";
+
+#[test]
+fn sample()
+{
+    assert_eq!(checked(&[], ""), [
+        "arch-install-scripts pacstrap arch-chroot genfstab",
+        "systemd timedatectl",
+        "util-linux fdisk lsblk findmnt mountpoint",
+        "dosfstools mkfs.fat",
+        "e2fsprogs mkfs.ext4",
+    ]);
+}
+
+#[test]
+fn filesystems()
+{
+    // each program is checked once, along with the others of its package
+    let partitions = "    format: btrfs\n    mount: /\n    size: 20G\n\
+        \x20 - swap:\n    format: swap\n    size: 4G\n    activate_swap: false\n\
+        \x20 - data:\n    format: exfat\n    mount: /data\n    size: 10G\n\
+        \x20 - home:\n    format: btrfs\n    mount: /home\n";
+    assert_eq!(checked(&[(ROOT, partitions)], "keymap: de\n"), [
+        "arch-install-scripts pacstrap arch-chroot genfstab",
+        "systemd timedatectl",
+        // swap that isn't activated is found by its UUID
+        "util-linux fdisk lsblk findmnt mountpoint mkswap blkid",
+        "dosfstools mkfs.fat",
+        "btrfs-progs mkfs.btrfs",
+        "exfatprogs mkfs.exfat",
+        "kbd loadkeys",
+    ]);
+}
+
+#[test]
+fn encryption_and_nspawn()
+{
+    let root = "    format: ext4\n    mount: /\n    encryption: {}\n";
+    assert_eq!(checked(&[(ROOT, root)], &format!("{}chroot_backend: nspawn\n", HOOKS)), [
+        "arch-install-scripts pacstrap genfstab",
+        "systemd systemd-nspawn timedatectl",
+        "util-linux fdisk lsblk findmnt mountpoint",
+        "dosfstools mkfs.fat",
+        "e2fsprogs mkfs.ext4",
+        "cryptsetup cryptsetup",
+    ]);
+}
+
+#[test]
+fn offline()
+{
+    // nothing can be installed, so the script stops with the list of what's missing
+    let script = common::script(common::generate(&["--file"], &[], "offline: true\n"));
+    assert!(script.contains("\nif [ -n \"$jimmy_missing\" ]; then\n    echo \"error: missing programs:$jimmy_missing\" >&2\n"), "{}", script);
+    assert!(!script.contains("pacman -Sy --noconfirm"));
+    assert_eq!(checked(&[], "offline: true\n"), checked(&[], ""));
+
+    let script = common::script(common::generate(&["--file"], &[], ""));
+    assert!(script.contains("pacman -Sy --noconfirm $jimmy_packages"));
+}
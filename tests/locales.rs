@@ -0,0 +1,123 @@
+//! Checks how the locales are read: the shorthands that are spelled the way /etc/locale.gen spells
+//! them, the locales that are taken as they are, those that aren't of its form, which only
+//! `strict` refuses, and those that are refused outright. The sed command uncommenting them is ran
+//! on a copy of the lines of /etc/locale.gen
+
+use std::process::{Command, Output};
+
+mod common;
+
+/// Generate the arch-chroot script from the sample configuration file, with its locales replaced
+/// by `locales` and the given lines appended
+fn generate(locales: &str, extra_lines: &str) -> Output
+{
+    common::generate(&["chroot-script", "--width", "0"], &[("locales:\n  - en_US.UTF-8\n", &format!("locales: {}\n", locales))], extra_lines)
+}
+
+/// Return the locales uncommented in /etc/locale.gen, along with the warnings, checking that jimmy
+/// succeeded
+fn generated(locales: &str) -> (Vec<String>, String)
+{
+    let output = generate(locales, "");
+    assert!(output.status.success(), "jimmy failed on {}: {}", locales, String::from_utf8_lossy(&output.stderr));
+    let script = String::from_utf8(output.stdout).unwrap();
+    let locales = script.split("--expression 's/^#").skip(1)
+        .map(|l| l.split_once(' ').unwrap().0.replace('\\', ""))
+        .collect();
+    (locales, String::from_utf8(output.stderr).unwrap())
+}
+
+/// Some of the lines of /etc/locale.gen
+const LOCALE_GEN: &str = "#  en_US.UTF-8 UTF-8\n\
+    #ca_ES.UTF-8@valencia UTF-8\n\
+    #de_DE.UTF-8 UTF-8\n\
+    #de_DE ISO-8859-1\n\
+    #de_DE@euro ISO-8859-15\n\
+    #en_US.UTF-8 UTF-8\n\
+    #en_US ISO-8859-1\n\
+    #sr_RS@latin UTF-8\n";
+
+#[test]
+fn normalized()
+{
+    for (locale, normalized, warning) in [
+        ("en_US", "en_US.UTF-8", "warning: locales has 'en_US', which has no charset; using 'en_US.UTF-8'"),
+        ("en_US.utf8", "en_US.UTF-8", "warning: locales has 'en_US.utf8', which /etc/locale.gen spells 'en_US.UTF-8'; using that"),
+        ("de_DE.utf-8", "de_DE.UTF-8", "/etc/locale.gen spells 'de_DE.UTF-8'"),
+        ("de_DE.UTF8", "de_DE.UTF-8", "/etc/locale.gen spells 'de_DE.UTF-8'"),
+        ("sr_RS@latin", "sr_RS.UTF-8@latin", "which has no charset; using 'sr_RS.UTF-8@latin'"),
+        ("ca_ES.Utf8@valencia", "ca_ES.UTF-8@valencia", "/etc/locale.gen spells 'ca_ES.UTF-8@valencia'"),
+    ] {
+        let (locales, stderr) = generated(locale);
+        assert_eq!(locales, [normalized], "{}", locale);
+        assert!(stderr.contains(warning), "{}: {}", locale, stderr);
+    }
+}
+
+#[test]
+fn accepted()
+{
+    for locale in ["en_US.UTF-8", "C.UTF-8", "de_DE.ISO-8859-1", "de_DE.ISO-8859-15@euro", "ast_ES.UTF-8", "ca_ES.UTF-8@valencia"] {
+        let (locales, stderr) = generated(locale);
+        assert_eq!(locales, [locale]);
+        assert!(!stderr.contains("locales has"), "{}: {}", locale, stderr);
+    }
+    // the same locale, once normalized, is generated once
+    assert_eq!(generated("[ en_US, en_US.UTF-8, de_DE.utf8 ]").0, ["en_US.UTF-8", "de_DE.UTF-8"]);
+}
+
+#[test]
+fn unusual_form()
+{
+    for (locale, normalized) in [("english.UTF-8", "english.UTF-8"), ("en_us", "en_us.UTF-8"), ("EN_US.UTF-8", "EN_US.UTF-8"), ("en_US.utf16", "en_US.utf16")] {
+        let message = format!("locales has '{}', which isn't of the form ll_CC.CHARSET[@modifier], such as en_US.UTF-8 or sr_RS.UTF-8@latin, \
+            so locale-gen won't find it in /etc/locale.gen", normalized);
+        let (locales, stderr) = generated(locale);
+        assert_eq!(locales, [normalized]);
+        assert!(stderr.contains(&format!("warning: {}", message)), "{}: {}", locale, stderr);
+
+        let output = generate(locale, "strict: true\n");
+        assert!(!output.status.success(), "{}", locale);
+        assert!(String::from_utf8_lossy(&output.stderr).contains(&message), "{}", String::from_utf8_lossy(&output.stderr));
+    }
+}
+
+#[test]
+fn rejected()
+{
+    for locale in ["\"en_US.UTF-8'\"", "\"en_US/UTF-8\"", "\"en_US.UTF-8;\"", "\"en_US.UTF-8$\""] {
+        let output = generate(locale, "");
+        assert!(!output.status.success(), "{}", locale);
+        assert!(String::from_utf8_lossy(&output.stderr).contains("(expected a locale such as en_US.UTF-8)"), "{}", locale);
+    }
+}
+
+#[test]
+fn users()
+{
+    let users = "users:\n  - first:\n    name: archie\n    locale: de_DE\n";
+    let output = generate("[ en_US.UTF-8, de_DE.utf8 ]", users);
+    assert!(output.status.success(), "jimmy failed: {}", String::from_utf8_lossy(&output.stderr));
+    assert!(String::from_utf8_lossy(&output.stderr).contains("warning: the locale of user 'archie' has 'de_DE', which has no charset; using 'de_DE.UTF-8'"));
+    assert!(String::from_utf8(output.stdout).unwrap().contains("echo 'LANG=de_DE.UTF-8' >/home/archie/.config/locale.conf"));
+}
+
+#[test]
+fn locale_gen()
+{
+    let output = generate("[ en_US, ca_ES.UTF-8@valencia ]", "");
+    assert!(output.status.success());
+    let script = String::from_utf8(output.stdout).unwrap();
+    let start = script.find("sed --expression").unwrap();
+    let end = start + script[start..].find("/etc/locale.gen").unwrap();
+    let path = common::temp_path("locale.gen");
+    std::fs::write(&path, LOCALE_GEN).unwrap();
+    let status = Command::new("sh").args(["-c", &format!("{}{}", &script[start..end], path.display())]).status().unwrap();
+    assert!(status.success());
+    let uncommented: Vec<String> = std::fs::read_to_string(&path).unwrap().lines()
+        .filter(|l| !l.starts_with('#'))
+        .map(String::from)
+        .collect();
+    std::fs::remove_file(&path).unwrap();
+    assert_eq!(uncommented, ["ca_ES.UTF-8@valencia UTF-8", "en_US.UTF-8 UTF-8"]);
+}
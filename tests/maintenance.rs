@@ -0,0 +1,77 @@
+//! Checks what each of the options under `maintenance` adds to the scripts: the packages
+//! installed with pacstrap, the units enabled and the files written on the installed system
+
+mod common;
+
+/// Return the full script and the arch-chroot script generated from the sample configuration
+/// file, with the given `maintenance` block appended
+fn scripts(maintenance: &str) -> (String, String)
+{
+    let config = common::config(&[], maintenance);
+    let run = |args: &[&str]| common::script(common::jimmy(&common::with_flags(&["--width", "0"], args), &config));
+    (run(&["--file"]), run(&["chroot-script"]))
+}
+
+/// Return the packages installed with pacstrap
+fn packages(script: &str) -> Vec<String>
+{
+    let line = script.lines().find(|l| l.contains("pacstrap /mnt")).expect("no pacstrap in the script");
+    let (_, args) = line.split_once("pacstrap /mnt $jimmy_needed ").unwrap();
+    args.split(" 2>&1").next().unwrap().split(' ').map(String::from).collect()
+}
+
+#[test]
+fn everything_is_off_by_default()
+{
+    let (script, chroot) = scripts("");
+    assert!(!chroot.contains("periodic maintenance"));
+    for package in ["pacman-contrib", "reflector"] {
+        assert!(!packages(&script).iter().any(|p| p == package), "{} is installed", package);
+    }
+}
+
+#[test]
+fn paccache()
+{
+    let (script, chroot) = scripts("maintenance:\n  paccache: true\n");
+    assert!(packages(&script).iter().any(|p| p == "pacman-contrib"));
+    assert!(chroot.contains("\nsystemctl enable paccache.timer\n"));
+    assert!(!chroot.contains("list-orphans") && !chroot.contains("reflector"));
+}
+
+#[test]
+fn orphan_cleanup()
+{
+    let (script, chroot) = scripts("maintenance:\n  orphan_cleanup: true\n");
+    assert!(!packages(&script).iter().any(|p| p == "pacman-contrib" || p == "reflector"));
+    assert!(chroot.contains("cat <<'END_OF_FILE' >/usr/local/lib/jimmy/list-orphans\n#!/bin/sh\norphans=$(pacman -Qtdq)\n"));
+    assert!(chroot.contains("\nchmod +x /usr/local/lib/jimmy/list-orphans\n"));
+    assert!(chroot.contains(">/etc/pacman.d/hooks/jimmy-list-orphans.hook\n[Trigger]\nType = Package\n"));
+    assert!(chroot.contains("When = PostTransaction\nExec = /usr/local/lib/jimmy/list-orphans\n"));
+}
+
+#[test]
+fn weekly_mirrorlist_update()
+{
+    let (script, chroot) = scripts("maintenance:\n  mirrorlist_update: weekly\n");
+    assert!(packages(&script).iter().any(|p| p == "reflector"));
+    assert!(chroot.contains(
+        "cat <<'END_OF_FILE' >/etc/xdg/reflector/reflector.conf\n\
+        --save /etc/pacman.d/mirrorlist\n--protocol https\n--latest 20\n--sort rate\nEND_OF_FILE\n"
+    ));
+    // the timer of the package already runs weekly
+    assert!(!chroot.contains("reflector.timer.d"));
+    assert!(chroot.contains("\nsystemctl enable reflector.timer\n"));
+}
+
+#[test]
+fn monthly_mirrorlist_update_from_some_countries()
+{
+    let maintenance = "maintenance:\n  mirrorlist_update: monthly\n  mirrorlist_countries: [ United States, CA ]\n";
+    let (_, chroot) = scripts(maintenance);
+    assert!(chroot.contains("--sort rate\n--country 'United States,CA'\nEND_OF_FILE\n"));
+    assert!(chroot.contains(
+        "cat <<'END_OF_FILE' >/etc/systemd/system/reflector.timer.d/jimmy.conf\n\
+        [Timer]\nOnCalendar=\nOnCalendar=monthly\nEND_OF_FILE\n"
+    ));
+}
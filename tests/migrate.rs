@@ -0,0 +1,109 @@
+//! Checks the versions of the format: the properties of older versions, which are still accepted
+//! with a warning and which `jimmy migrate` rewrites, and the files of newer versions, which are
+//! refused
+
+use std::process::{Command, Output};
+
+mod common;
+
+/// The sample configuration file, written with the properties of the version before the current
+/// one, without the lines it has in common with it
+const VERSION_1: &str = "version: 1\nextra: vim\nregion: Europe\ncity: London\n";
+
+/// The sample configuration file with its version and the properties replaced since the first
+/// version of the format replaced by `old`
+fn legacy(old: &str) -> String
+{
+    common::config(&[("version: 2\n", ""), ("packages: vim\n", ""), ("timezone: Europe/London\n", "")], old)
+}
+
+/// Run `jimmy migrate` on a file holding `config`, and return what it printed along with the file
+/// it left
+fn migrate(config: &str) -> (Output, String)
+{
+    let path = common::temp_path("config.yaml");
+    std::fs::write(&path, config).unwrap();
+    let output = Command::new(env!("CARGO_BIN_EXE_jimmy")).arg("migrate").arg(&path).output().unwrap();
+    let migrated = std::fs::read_to_string(&path).unwrap();
+    std::fs::remove_file(&path).unwrap();
+    (output, migrated)
+}
+
+/// Return the value of `key` in a YAML file
+fn property(config: &str, key: &str) -> serde_yaml::Value
+{
+    serde_yaml::from_str::<serde_yaml::Value>(config).unwrap()[key].clone()
+}
+
+#[test]
+fn legacy_properties_are_rewritten()
+{
+    let (output, migrated) = migrate(&legacy(VERSION_1));
+    assert!(output.status.success(), "{}", String::from_utf8_lossy(&output.stderr));
+    let stderr = String::from_utf8(output.stderr).unwrap();
+    for (field, replacement) in [("extra", "packages"), ("region", "timezone"), ("city", "timezone")] {
+        assert!(stderr.contains(&format!("warning: '{}' is deprecated since version 2 of the format; use '{}' instead\n", field, replacement)), "{}", stderr);
+        assert_eq!(property(&migrated, field), serde_yaml::Value::Null, "{}", field);
+    }
+    assert_eq!(property(&migrated, "version"), serde_yaml::Value::from(2));
+    assert_eq!(property(&migrated, "packages"), serde_yaml::Value::from("vim"));
+    assert_eq!(property(&migrated, "timezone"), serde_yaml::Value::from("Europe/London"));
+
+    // the file follows the current version, so there's nothing left to migrate, and it's the
+    // sample again
+    let (output, again) = migrate(&migrated);
+    assert!(output.status.success() && output.stderr.is_empty(), "{}", String::from_utf8_lossy(&output.stderr));
+    assert_eq!(again, migrated);
+    let sample: serde_yaml::Value = serde_yaml::from_str(&common::sample()).unwrap();
+    assert_eq!(serde_yaml::from_str::<serde_yaml::Value>(&migrated).unwrap(), sample);
+}
+
+#[test]
+fn files_without_a_version()
+{
+    let (output, migrated) = migrate(&legacy("username: archie\nextra: [ vim, git ]\n"));
+    assert!(String::from_utf8_lossy(&output.stderr).contains("warning: 'username' is deprecated since version 1 of the format; use 'users' instead\n"));
+    let names: Vec<String> = property(&migrated, "users").as_sequence().unwrap().iter().map(|u| u["name"].as_str().unwrap().to_string()).collect();
+    assert_eq!(names, ["archie", "archie"]);
+    assert_eq!(property(&migrated, "packages"), serde_yaml::from_str::<serde_yaml::Value>("[ vim, git ]").unwrap());
+    assert_eq!(property(&migrated, "timezone"), serde_yaml::Value::Null);
+
+    // the properties a file's own version has are left to it
+    let (output, migrated) = migrate(&legacy("version: 1\nusername: archie\n"));
+    assert!(!String::from_utf8_lossy(&output.stderr).contains("'username'"));
+    assert_eq!(property(&migrated, "username"), serde_yaml::Value::from("archie"));
+}
+
+#[test]
+fn timezones()
+{
+    for (old, timezone) in [
+        ("region: Europe\ncity: London\n", "Europe/London"),
+        ("region: Europe/\ncity: /London\n", "Europe/London"),
+        ("region: America\ncity: Argentina/Buenos_Aires\n", "America/Argentina/Buenos_Aires"),
+        ("region: Japan\n", "Japan"),
+        ("region: Japan\ncity: \"\"\n", "Japan"),
+    ] {
+        assert_eq!(property(&migrate(&legacy(old)).1, "timezone"), serde_yaml::Value::from(timezone), "{}", old);
+        let script = common::script(common::jimmy(&["chroot-script"], &legacy(old)));
+        assert!(script.contains(&format!("\nln -sf /usr/share/zoneinfo/{} /etc/localtime\n", timezone)), "{}", old);
+    }
+
+    let stderr = common::refusal(common::jimmy(&["--file"], &legacy("city: London\n")));
+    assert!(stderr.contains("city 'London' is given without a region; replace it with `timezone`, e.g. `timezone: Europe/London`"), "{}", stderr);
+}
+
+#[test]
+fn newer_versions_are_refused()
+{
+    let message = "the configuration file is for version 3 of the format, but this version of jimmy only understands up to version 2; upgrade jimmy";
+    let config = common::config(&[("version: 2\n", "version: 3\n")], "");
+    assert!(common::refusal(common::jimmy(&["--file"], &config)).contains(message));
+    // the file is left as it is
+    let (output, migrated) = migrate(&config);
+    assert!(common::refusal(output).contains(message));
+    assert_eq!(migrated, config);
+
+    let config = common::config(&[("version: 2\n", "version: two\n")], "");
+    assert!(common::refusal(common::jimmy(&["--file"], &config)).contains("`version` must be a number, not String(\"two\")"));
+}
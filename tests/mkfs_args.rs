@@ -0,0 +1,45 @@
+//! Checks `mkfs_args`: where the extra arguments of a partition go in the command that formats
+//! it, and the ones that are refused
+
+mod common;
+
+/// Generate the script from the sample configuration file, with `lines` added to the partition
+/// whose lines start with `partition`
+fn generate(partition: &str, lines: &str) -> std::process::Output
+{
+    common::generate(&["--file"], &[(partition, &format!("{}{}", partition, lines))], "")
+}
+
+const BOOT: &str = "    format: fat32\n    mount: /boot\n";
+const ROOT: &str = "    format: ext4\n    mount: /\n";
+
+/// Return the line of the script that starts with `prefix`
+fn line(script: &str, prefix: &str) -> String
+{
+    script.lines().find(|l| l.starts_with(prefix)).unwrap().to_string()
+}
+
+#[test]
+fn after_the_flags_of_jimmy()
+{
+    let script = common::script(generate(ROOT, "    mkfs_args: -L root -O ^has_journal\n"));
+    assert_eq!(line(&script, "mkfs.ext4 "), "mkfs.ext4 -L root -O '^has_journal' /dev/sda2 || exit 1");
+
+    // mkfs.fat is always given -F 32 first
+    let script = common::script(generate(BOOT, "    mkfs_args: [ -n, EFI BOOT ]\n"));
+    assert_eq!(line(&script, "mkfs.fat "), "mkfs.fat -F 32 -n 'EFI BOOT' /dev/sda1 || exit 1");
+
+    // none at all
+    let script = common::script(generate(ROOT, ""));
+    assert_eq!(line(&script, "mkfs.ext4 "), "mkfs.ext4 /dev/sda2 || exit 1");
+}
+
+#[test]
+fn newlines_and_nul_characters()
+{
+    // YAML and the message of jimmy escape them the same way
+    for arg in ["\"-L\\nroot\"", "\"-L\\0root\""] {
+        let stderr = common::refusal(generate(ROOT, &format!("    mkfs_args: [ {} ]\n", arg)));
+        assert!(stderr.contains(&format!("mkfs argument contains a newline or a NUL character: {}", arg)), "{}", stderr);
+    }
+}
@@ -0,0 +1,60 @@
+//! Checks how `default_mount_options` are merged with the `mount_options` of each partition: option
+//! by option, the partition's own ones winning, options with values included
+
+mod common;
+
+/// Return the options the root partition of the sample is mounted with, once it has the given
+/// format and `mount_options`, with the given `default_mount_options` appended
+fn root_options(format: &str, options: Option<&str>, defaults: &str) -> Option<String>
+{
+    let root = format!("    format: {}\n    mount: /\n{}", format, options.map(|o| format!("    mount_options: {}\n", o)).unwrap_or_default());
+    let extra_lines = if defaults.is_empty() { String::new() } else { format!("default_mount_options:\n{}", defaults) };
+    let script = common::script(common::generate(&["--file"], &[("    format: ext4\n    mount: /\n", &root)], &extra_lines));
+    let line = script.lines().find(|l| l.ends_with(" /dev/sda2 /mnt/ || exit 1; }")).unwrap();
+    line.split_once("mount -o ").map(|(_, rest)| rest.trim_end_matches(" /dev/sda2 /mnt/ || exit 1; }").to_string())
+}
+
+#[test]
+fn merged()
+{
+    for (format, options, defaults, merged) in [
+        // either of them alone
+        ("ext4", None, "  ext4: noatime\n", Some("noatime")),
+        ("ext4", Some("noatime,commit=60"), "", Some("noatime,commit=60")),
+        // without defaults to merge them with, a partition's options are passed as they are
+        ("ext4", Some("commit=30,commit=60"), "", Some("commit=30,commit=60")),
+        ("ext4", None, "", None),
+        // the defaults of other formats don't apply
+        ("ext4", None, "  btrfs: noatime,compress=zstd\n", None),
+        // the options of the partition are added after the defaults
+        ("ext4", Some("commit=60"), "  ext4: noatime\n", Some("noatime,commit=60")),
+        // and they override those of the same name in place, whatever their values
+        ("btrfs", Some("compress=zstd:3"), "  btrfs: noatime,compress=zstd,space_cache=v2\n", Some("noatime,compress=zstd:3,space_cache=v2")),
+        ("btrfs", Some("subvol=@root"), "  btrfs: subvol=@,noatime\n", Some("subvol=@root,noatime")),
+        ("btrfs", Some("compress"), "  btrfs: compress=zstd\n", Some("compress")),
+        ("btrfs", Some("compress=lzo"), "  btrfs: compress\n", Some("compress=lzo")),
+        // an option given twice is kept once, with its last value
+        ("ext4", Some("noatime,noatime"), "  ext4: noatime\n", Some("noatime")),
+        ("ext4", Some("commit=30,commit=60"), "  ext4: noatime\n", Some("noatime,commit=60")),
+        ("ext4", None, "  ext4: commit=30,noatime,commit=60\n", Some("commit=60,noatime")),
+        // only the names, before the first `=`, are compared, so a value can have one of its own
+        ("ext4", Some("x-systemd.after=a=b"), "  ext4: x-systemd.after=c\n", Some("x-systemd.after=a=b")),
+        // the names have to be the same, so opposites are both kept, and mount takes the last
+        ("ext4", Some("ro"), "  ext4: rw\n", Some("rw,ro")),
+        // empty options are left out
+        ("ext4", Some("noatime,,commit=60,"), "  ext4: \",errors=remount-ro\"\n", Some("errors=remount-ro,noatime,commit=60")),
+    ] {
+        assert_eq!(root_options(format, options, defaults).as_deref(), merged, "{:?} and {:?}", options, defaults);
+    }
+}
+
+#[test]
+fn refused()
+{
+    let stderr = common::refusal(common::generate(&["--file"], &[], "default_mount_options:\n  ext4: noatime commit=60\n"));
+    assert!(stderr.contains("mount options can't contain whitespace: \"noatime commit=60\""), "{}", stderr);
+
+    let output = common::generate(&["--file"], &[], "default_mount_options:\n  ext5: noatime\n");
+    assert!(output.status.success());
+    assert!(String::from_utf8_lossy(&output.stderr).contains("warning: default mount options given for unknown format 'ext5'; they're going to be ignored"));
+}
@@ -0,0 +1,92 @@
+//! Checks `mount_root`: the directory the new system is mounted on while it's installed, which
+//! every command of the scripts and every step of `jimmy explain` goes through instead of /mnt,
+//! along with the paths it's refused for
+
+use std::process::Output;
+
+mod common;
+
+/// Run jimmy with `args` on the sample configuration file, with the given lines appended
+fn generate(args: &[&str], extra_lines: &str) -> Output
+{
+    common::generate(args, &[], extra_lines)
+}
+
+/// Return what jimmy printed, checking that it succeeded
+fn generated(args: &[&str], extra_lines: &str) -> String
+{
+    common::script(generate(args, extra_lines))
+}
+
+/// The options whose commands go through the mount root, each given along with `mount_root`
+const VARIANTS: &[(&str, &str)] = &[
+    ("", "sample"),
+    ("bootstrap: tarball\n", "tarball"),
+    ("chroot_backend: nspawn\n", "nspawn"),
+    ("previous_mounts: unmount\n", "previous-mounts"),
+    ("keep_resolv_conf: true\n", "resolv-conf"),
+    ("report: /root/jimmy-report.json\ncleanup: keep-report-only\n", "report"),
+    ("skel: /root/skel\nkeymap: de-latin1\n", "skel"),
+];
+
+#[test]
+fn no_mnt_left()
+{
+    for (lines, name) in VARIANTS {
+        let extra_lines = format!("{}mount_root: /target\n", lines);
+        for args in [&["--file"][..], &["chroot-script"], &["explain"], &["explain", "--markdown"]] {
+            let output = generated(args, &extra_lines);
+            assert!(!output.contains("/mnt"), "{} {:?}: {}", name, args, output.lines().find(|l| l.contains("/mnt")).unwrap());
+        }
+        let script = generated(&["--file"], &extra_lines);
+        assert!(script.contains("\nmountpoint -q /target/ || { mkdir -p /target/ && mount "), "{}", name);
+        assert!(script.contains("\nrm -f /target/jimmy_part2.sh"), "{}", name);
+        assert!(script.contains("\n! mountpoint -q /target || umount -R /target\n"), "{}", name);
+    }
+
+    let script = generated(&["--file"], "mount_root: /target\n");
+    for cmd in [" genfstab -U /target; } >>/target/etc/fstab.jimmy || exit 1\n", "\narch-chroot /target ./jimmy_part2.sh\n", "{ pacstrap /target $jimmy_needed "] {
+        assert!(script.contains(cmd), "{}", cmd);
+    }
+}
+
+/// Return the script made with `--reproducible`, without the hash of the configuration file it's
+/// made from
+fn reproducible(extra_lines: &str) -> String
+{
+    common::without_hash(&generated(&["--reproducible", "--file"], extra_lines))
+}
+
+#[test]
+fn default()
+{
+    // /mnt, unless it's set, and the same script with it set to /mnt
+    let script = reproducible("");
+    assert!(script.contains("\narch-chroot /mnt ./jimmy_part2.sh\n"));
+    assert_eq!(reproducible("mount_root: /mnt\n"), script);
+}
+
+#[test]
+fn trailing_slashes()
+{
+    assert_eq!(
+        reproducible("mount_root: /srv/target//\n"),
+        reproducible("mount_root: /srv/target\n"),
+    );
+}
+
+#[test]
+fn invalid()
+{
+    for root in ["target", "/", "\"\"", "/srv/my target", "/srv/../mnt", "./target", "/srv/$HOME"] {
+        let output = generate(&["--file"], &format!("mount_root: {}\n", root));
+        assert!(!output.status.success(), "{}", root);
+        assert!(String::from_utf8_lossy(&output.stderr).contains("(expected an absolute path other than /, of letters, digits, '.', '_' and '-', such as /mnt or /target)"), "{}", root);
+    }
+    for (root, dir) in [("/dev", "/dev"), ("/proc/target", "/proc"), ("/sys/fs/target", "/sys")] {
+        let output = generate(&["--file"], &format!("mount_root: {}\n", root));
+        assert!(!output.status.success(), "{}", root);
+        assert!(String::from_utf8_lossy(&output.stderr)
+            .contains(&format!("invalid mount_root: \"{}\" (expected a directory outside of {}, whose filesystems are the kernel's)", root, dir)), "{}", root);
+    }
+}
@@ -0,0 +1,44 @@
+//! Checks what the mounting step does with a partition added to the sample configuration file:
+//! mount it, activate it as swap, or leave it alone
+
+mod common;
+
+/// Return the lines of the mounting step about a partition of the given format and mount point,
+/// added after the boot partition with the given lines
+fn mounting(format: &str, mount: Option<&str>, lines: &str) -> Vec<String>
+{
+    let partition = format!("    size: 500M\n  - extra:\n    format: {}\n    size: 4G\n{}{}",
+        format, mount.map(|m| format!("    mount: {}\n", m)).unwrap_or_default(), lines);
+    let script = common::script(common::generate(&["--file"], &[("    size: 500M\n", &partition)], "strict: true\n"));
+    script.lines()
+        .skip_while(|l| *l != "JIMMY_STEP=mounting")
+        .take_while(|l| !l.is_empty())
+        .filter(|l| l.contains("/dev/sda2"))
+        .map(String::from)
+        .collect()
+}
+
+#[test]
+fn mounting_step()
+{
+    for (format, mount, lines, expected) in [
+        ("ext4", Some("/srv"), "", Some("mountpoint -q /mnt/srv || { mkdir -p /mnt/srv && mount /dev/sda2 /mnt/srv || exit 1; }")),
+        ("ext4", Some("/srv"), "    mount_options: noatime\n", Some("mountpoint -q /mnt/srv || { mkdir -p /mnt/srv && mount -o noatime /dev/sda2 /mnt/srv || exit 1; }")),
+        ("swap", None, "", Some("grep -q \"^$(readlink -f /dev/sda2) \" /proc/swaps || swapon /dev/sda2")),
+        ("swap", None, "    swap_priority: 10\n", Some("grep -q \"^$(readlink -f /dev/sda2) \" /proc/swaps || swapon -p 10 /dev/sda2")),
+        // nothing mounts swap that isn't activated, nor partitions left unmounted on purpose, which
+        // the script says it leaves alone
+        ("swap", None, "    activate_swap: false\n", None),
+        ("ext4", None, "    unmounted: true\n", Some("# /dev/sda2 (ext4) is left unmounted, and isn't added to the filesystem table")),
+    ] {
+        assert_eq!(mounting(format, mount, lines), expected.into_iter().collect::<Vec<&str>>(), "{} {:?} {:?}", format, mount, lines);
+    }
+}
+
+#[test]
+fn unmounted_in_the_plan()
+{
+    let partition = "    size: 500M\n  - extra:\n    format: ext4\n    size: 4G\n    unmounted: true\n";
+    let output = common::generate(&["--verbose", "--file"], &[("    size: 500M\n", partition)], "");
+    assert!(String::from_utf8_lossy(&output.stderr).contains("\n    /dev/sda2: ext4, 4G, unmounted\n"));
+}
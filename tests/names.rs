@@ -0,0 +1,87 @@
+//! Checks the ways a list of names can be written in the configuration file: as a sequence, or as
+//! a string of names separated by commas or whitespace. The groups of a user, the locales and the
+//! extra packages are all read the same way
+
+use std::process::Output;
+
+mod common;
+
+/// Generate the full script and the arch-chroot script from the sample configuration file, with
+/// `replace` replaced by `with`
+fn generate(replace: &str, with: &str) -> (Output, Output)
+{
+    let config = common::config(&[(replace, with)], "");
+    (common::jimmy(&["--file"], &config), common::jimmy(&["chroot-script"], &config))
+}
+
+/// Return the groups given to `useradd` for archie, the user of the sample
+fn groups(groups: &str) -> String
+{
+    let (_, chroot) = generate("    groups: [ wheel ]\n", &format!("    groups: {}\n", groups));
+    let script = common::script(chroot);
+    let line = script.lines().find(|l| l.contains("|| useradd ")).expect("no useradd in the script");
+    line.split_once(" -G ").map(|(_, rest)| rest.split(' ').next().unwrap().to_string()).unwrap_or_default()
+}
+
+#[test]
+fn groups_as_a_sequence()
+{
+    assert_eq!(groups("[ wheel, video ]"), "wheel,video");
+    assert_eq!(groups("\n      - wheel\n      - video"), "wheel,video");
+}
+
+#[test]
+fn groups_as_a_comma_separated_string()
+{
+    assert_eq!(groups("wheel,video"), "wheel,video");
+    assert_eq!(groups("wheel, video,"), "wheel,video");
+    assert_eq!(groups("\"wheel,,video\""), "wheel,video");
+}
+
+#[test]
+fn groups_as_a_whitespace_separated_string()
+{
+    assert_eq!(groups("wheel video  audio"), "wheel,video,audio");
+}
+
+#[test]
+fn items_of_a_sequence_are_split_too()
+{
+    assert_eq!(groups("[ \"wheel,video\", \"\", audio ]"), "wheel,video,audio");
+}
+
+#[test]
+fn empty_groups()
+{
+    assert_eq!(groups(""), "");
+    assert_eq!(groups("\"\""), "");
+    assert_eq!(groups("[]"), "");
+}
+
+#[test]
+fn invalid_groups_are_explained()
+{
+    let (full, _) = generate("    groups: [ wheel ]\n", "    groups: { wheel: true }\n");
+    assert!(!full.status.success());
+    assert!(String::from_utf8_lossy(&full.stderr)
+        .contains("expected a list of names, or a string of names separated by commas or spaces"));
+}
+
+#[test]
+fn locales_as_a_string()
+{
+    let (_, chroot) = generate("locales:\n  - en_US.UTF-8\n", "locales: en_US.UTF-8, de_DE.UTF-8\n");
+    let script = String::from_utf8(chroot.stdout).unwrap();
+    assert!(script.contains("--expression 's/^#en_US\\.UTF-8 /en_US.UTF-8 /' "));
+    assert!(script.contains("--expression 's/^#de_DE\\.UTF-8 /de_DE.UTF-8 /' "));
+    assert!(script.contains("echo 'LANG=en_US.UTF-8' >/etc/locale.conf"));
+}
+
+#[test]
+fn extra_as_a_sequence()
+{
+    let (full, _) = generate("packages: vim\n", "packages: [ vim, git ]\n");
+    assert!(full.status.success(), "jimmy failed: {}", String::from_utf8_lossy(&full.stderr));
+    let script = String::from_utf8(full.stdout).unwrap();
+    assert!(script.contains(" linux-firmware vim git "), "{}", script);
+}
@@ -0,0 +1,100 @@
+//! Checks the `packages` that contradict the rest of the configuration file: which ones are known,
+//! the names that end with `*` in the list of conflicts, which match every package starting with
+//! the rest, and the warning they're given, which `strict: true` turns into an error
+
+mod common;
+
+/// Run jimmy on the sample configuration file with the given `packages`, the given lines replaced
+/// and the given lines appended, and return whether it succeeded along with what it said
+fn generate(packages: &str, replacements: &[(&str, &str)], extra_lines: &str) -> (bool, String)
+{
+    let mut replacements = replacements.to_vec();
+    let packages = format!("packages: {}\n", packages);
+    replacements.push(("packages: vim\n", &packages));
+    let output = common::generate(&["--file"], &replacements, extra_lines);
+    (output.status.success(), String::from_utf8(output.stderr).unwrap())
+}
+
+/// Return the conflicts jimmy warns about
+fn conflicts(packages: &str, replacements: &[(&str, &str)], extra_lines: &str) -> Vec<String>
+{
+    let (success, stderr) = generate(packages, replacements, extra_lines);
+    assert!(success, "{}", stderr);
+    stderr.lines()
+        .filter_map(|l| l.strip_prefix("warning: package "))
+        .map(String::from)
+        .collect()
+}
+
+const NETWORK: &str = "both manage the network interfaces and fight over them; remove the package";
+const SECOND_KERNEL: &str = "a second kernel gets installed, but the bootloader only boots the one given by `kernel`";
+const LTS: (&str, &str) = ("kernel: latest\n", "kernel: lts\n");
+
+#[test]
+fn known_conflicts()
+{
+    for (packages, replacements, extra_lines, expected) in [
+        ("netctl", &[][..], "", format!("'netctl' conflicts with the networkmanager network backend: {}", NETWORK)),
+        ("dhcpcd", &[], "", "'dhcpcd' conflicts with the networkmanager network backend: NetworkManager runs its own DHCP client, \
+            and the two fight over the leases if dhcpcd is enabled; remove the package".to_string()),
+        ("grub", &[("bootloader: grub\n", "bootloader: efistub\n")], "", "'grub' conflicts with `bootloader: efistub`: \
+            GRUB gets installed but is never set up; remove the package, or use `bootloader: grub`".to_string()),
+        ("refind", &[], "", "'refind' conflicts with `bootloader: grub`: jimmy only sets up one bootloader; remove the package".to_string()),
+        ("linux", &[LTS], "", format!("'linux' conflicts with `kernel: lts`: {}", SECOND_KERNEL)),
+        ("linux-lts", &[], "", format!("'linux-lts' conflicts with `kernel: latest`: {}", SECOND_KERNEL)),
+        ("nvidia-lts", &[], "", "'nvidia-lts' conflicts with `kernel: latest`: \
+            the package only has modules for the `linux-lts` kernel; use nvidia or nvidia-dkms".to_string()),
+        ("linux-firmware-whence", &[], "firmware_packages: none\n", "'linux-firmware-whence' conflicts with `firmware_packages: none`: \
+            firmware gets installed anyway; remove the package, or list it in `firmware_packages`".to_string()),
+        ("auto-cpufreq", &[], "power: tlp\n", "'auto-cpufreq' conflicts with `power: tlp`: \
+            both change the same power settings and undo each other's; remove the package".to_string()),
+    ] {
+        assert_eq!(conflicts(&format!("vim {}", packages), replacements, extra_lines), [expected], "{}", packages);
+    }
+
+    // the same packages, without what they'd conflict with
+    for (packages, replacements, extra_lines) in [
+        ("grub", &[][..], ""),
+        ("linux", &[], ""),
+        ("linux-lts nvidia-lts", &[LTS], ""),
+        ("linux-firmware-whence", &[], ""),
+        ("auto-cpufreq", &[], "power: none\n"),
+    ] {
+        assert!(conflicts(packages, replacements, extra_lines).is_empty(), "{}", packages);
+    }
+}
+
+#[test]
+fn prefixes()
+{
+    // `connman*` and `wicd*`, but not `netctl`, match the packages that start with them
+    for package in ["connman", "connman-gtk", "wicd", "wicd-gtk"] {
+        assert_eq!(conflicts(package, &[], ""), [format!("'{}' conflicts with the networkmanager network backend: {}", package, NETWORK)]);
+    }
+    for package in ["netctl-git", "cmst-connman", "xwicd"] {
+        assert!(conflicts(package, &[], "").is_empty(), "{}", package);
+    }
+}
+
+#[test]
+fn several_conflicts()
+{
+    // a package can conflict with several properties, and each of them is warned about
+    let warnings = conflicts("nvidia dhcpcd", &[LTS], "hardening:\n  kernel_lockdown: true\n");
+    assert_eq!(warnings.len(), 3, "{:?}", warnings);
+    assert!(warnings[0].starts_with("'nvidia' conflicts with `kernel: lts`: "));
+    assert!(warnings[1].starts_with("'nvidia' conflicts with `kernel_lockdown` under `hardening`: "));
+    assert!(warnings[2].starts_with("'dhcpcd' conflicts with the networkmanager network backend: "));
+}
+
+#[test]
+fn strict()
+{
+    let (success, stderr) = generate("vim netctl", &[], "strict: true\n");
+    assert!(!success);
+    assert!(stderr.contains(&format!("'netctl' conflicts with the networkmanager network backend: {}", NETWORK)), "{}", stderr);
+    assert!(!stderr.contains("warning: package "), "{}", stderr);
+
+    let (success, stderr) = generate("vim git", &[], "strict: true\n");
+    assert!(success, "{}", stderr);
+}
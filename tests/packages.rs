@@ -0,0 +1,72 @@
+//! Checks that `jimmy packages` lists exactly what pacstrap installs, for a configuration that
+//! has packages added by most of the features that add any
+
+mod common;
+
+/// The sample configuration file, with features that add packages of their own: an encrypted
+/// root partition unlocked with the TPM2 chip, dracut, an ntfs partition, the maintenance, an
+/// editor and firmware, with `packages` some of which are added by them too
+fn config() -> String
+{
+    common::config(&[
+        ("packages: vim\n", "packages: vim,git ntfs-3g vim\n"),
+        ("kernel: latest\n", "kernel: lts\n"),
+        ("    mount: /\n", "    mount: /\n    encryption:\n      tpm2: true\n"),
+        ("    size: 500M\n", "    size: 500M\n  - shared:\n    format: ntfs\n    mount: /shared\n    size: 20G\n"),
+    ], "\
+initramfs_generator: dracut
+default_editor: nano
+firmware_packages: linux-firmware-intel sof-firmware
+maintenance:
+  paccache: true
+  mirrorlist_update: weekly
+")
+}
+
+/// Run jimmy with the given arguments on the configuration file, and return its stdout
+fn jimmy(args: &[&str]) -> String
+{
+    common::script(common::jimmy(args, &config()))
+}
+
+/// Return the packages given to pacstrap in the script, which retries it with `$jimmy_needed`
+fn pacstrap(script: &str) -> Vec<String>
+{
+    let line = script.lines().find_map(|l| l.split_once("pacstrap /mnt $jimmy_needed ")).unwrap().1;
+    line.split_once(" 2>&1").unwrap().0.split(' ').map(String::from).collect()
+}
+
+#[test]
+fn what_pacstrap_installs()
+{
+    let list: Vec<String> = jimmy(&["packages"]).lines().map(String::from).collect();
+    let script = jimmy(&["--width", "0", "--file"]);
+    let json = jimmy(&["packages", "--json"]);
+
+    let mut installed = pacstrap(&script);
+    assert_eq!(installed.iter().filter(|p| *p == "vim").count(), 1, "{:?}", installed);
+    installed.sort();
+    assert_eq!(list, installed);
+    assert_eq!(serde_json::from_str::<Vec<String>>(&json).unwrap(), list);
+    for package in [
+        "base", "linux-lts", "linux-firmware-intel", "sof-firmware", "git", "ntfs-3g", "vim", "grub", "efibootmgr",
+        "networkmanager", "tpm2-tss", "dracut", "pacman-contrib", "reflector", "nano",
+    ] {
+        assert!(list.iter().any(|p| p == package), "{} isn't listed", package);
+    }
+    assert!(!list.iter().any(|p| p == "linux" || p == "linux-firmware"));
+}
+
+#[test]
+fn sorted_once_each()
+{
+    let list: Vec<String> = jimmy(&["packages"]).lines().map(String::from).collect();
+    let script = jimmy(&["--reproducible", "--width", "0", "--file"]);
+
+    let mut sorted = list.clone();
+    sorted.sort();
+    sorted.dedup();
+    assert_eq!(list, sorted);
+    // reproducible scripts give pacstrap the packages in the same order
+    assert_eq!(pacstrap(&script), list);
+}
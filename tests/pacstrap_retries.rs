@@ -0,0 +1,77 @@
+//! Checks the loop that retries pacstrap, ran with sh and a pacstrap that fails a given number of
+//! times: the exit status it stops with, the failures it doesn't retry, and `--needed` being given
+//! from the second attempt on
+
+mod common;
+
+/// Return the loop that retries pacstrap in the script of the sample configuration file, with the
+/// given `retries` and no delay
+fn retry_loop(retries: u32) -> String
+{
+    let script = common::script(common::generate(&["--file"], &[], &format!("retries: {}\nretry_delay: 0\n", retries)));
+    let from = script.find("\njimmy_pacstrap_log=$(mktemp)\n").unwrap() + 1;
+    let to = from + script[from..].find("\n    exit \"$jimmy_status\"\nfi\n").unwrap() + "\n    exit \"$jimmy_status\"\nfi\n".len();
+    script[from..to].to_string()
+}
+
+/// Run the loop with a pacstrap that prints `output` and exits with `status` the first `failures`
+/// times it's called, and return the exit status the loop stopped with, the arguments pacstrap
+/// was given before the packages each time, and what the loop wrote to stderr
+fn run(retries: u32, failures: u32, status: u32, output: &str) -> (i32, Vec<String>, String)
+{
+    let pacstrap = format!(r#"echo "$2" >>"$DIR/calls"
+if [ "$(wc -l <"$DIR/calls")" -le {} ]; then
+    echo '{}'
+    exit {}
+fi"#, failures, output, status);
+    let code = format!("( {} )\necho \"status $?\"\ncat \"$DIR/calls\"", retry_loop(retries));
+    let (_, stdout, stderr) = common::sh(&code, &[("pacstrap", &pacstrap)], "");
+    let mut lines = stdout.lines().skip_while(|l| !l.starts_with("status "));
+    let status = lines.next().unwrap().trim_start_matches("status ").parse().unwrap();
+    (status, lines.map(String::from).collect(), stderr)
+}
+
+#[test]
+fn retried()
+{
+    // the first attempt installs everything, and the next ones only what it didn't get to
+    let (status, calls, stderr) = run(3, 2, 1, "error: failed retrieving file");
+    assert_eq!(status, 0);
+    assert_eq!(calls, ["base", "--needed", "--needed"]);
+    assert_eq!(stderr, "warning: pacstrap failed with exit status 1; retrying in 0 seconds (1 of 3)...\n\
+        warning: pacstrap failed with exit status 1; retrying in 0 seconds (2 of 3)...\n");
+
+    let (status, calls, stderr) = run(3, 0, 1, "");
+    assert_eq!((status, calls, stderr), (0, vec!["base".to_string()], String::new()));
+}
+
+#[test]
+fn exit_status()
+{
+    // the one of the last attempt is kept, even though pacstrap's output goes through tee
+    let (status, calls, stderr) = run(2, 10, 42, "error: failed retrieving file");
+    assert_eq!(status, 42);
+    assert_eq!(calls, ["base", "--needed", "--needed"]);
+    assert!(stderr.ends_with("(2 of 2)...\nerror: pacstrap failed\n"), "{}", stderr);
+}
+
+#[test]
+fn signatures()
+{
+    // failing again wouldn't help, so the script stops at the first attempt
+    for output in ["error: linux: signature from \"someone\" is unknown trust", "error: GPGME error: PGP key not found"] {
+        let (status, calls, stderr) = run(3, 10, 1, output);
+        assert_eq!(status, 1, "{}", output);
+        assert_eq!(calls, ["base"], "{}", output);
+        assert_eq!(stderr, "error: pacstrap failed because of package signatures, so it is not retried; \
+            try `pacman -Sy archlinux-keyring` first\nerror: pacstrap failed\n", "{}", output);
+    }
+}
+
+#[test]
+fn not_retried()
+{
+    let script = common::script(common::generate(&["--file"], &[], "retries: 0\n"));
+    assert!(!script.contains("jimmy_needed") && !script.contains("jimmy_pacstrap_log"));
+    assert!(script.contains("\npacstrap /mnt base "));
+}
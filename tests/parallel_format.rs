@@ -0,0 +1,102 @@
+//! Checks `parallel_format: true`: the jobs that format the partitions of each disk at the same time
+//! as the others, what they print, and the script stopping when one of them fails
+
+mod common;
+
+/// A partition on a second disk, added after the boot partition of the sample
+const DATA: &str = "    size: 500M\n  - data:\n    format: ext4\n    mount: /data\n    disk: /dev/sdb\n";
+
+/// Return the script of the sample configuration file made with `--reproducible`, without the hash
+/// of the file, with the given lines replaced and appended
+fn body(replacements: &[(&str, &str)], extra_lines: &str) -> String
+{
+    common::without_hash(&common::script(common::generate(&["--reproducible", "--file"], replacements, extra_lines)))
+}
+
+/// Return the shell code that formats the partitions in parallel
+fn jobs(script: &str) -> &str
+{
+    let from = script.find("jimmy_format_status=$(mktemp -d)\n").unwrap();
+    let end = "echo 'error: formatting failed' >&2\n    exit 1\nfi\n";
+    &script[from..from + script[from..].find(end).unwrap() + end.len()]
+}
+
+#[test]
+fn one_disk()
+{
+    let script = body(&[], "parallel_format: true\n");
+    assert_eq!(script, body(&[], "parallel_format: false\n"));
+    assert!(script.contains("\nmkfs.fat -F 32 /dev/sda1 || exit 1\nmkfs.ext4 /dev/sda2 || exit 1\n"));
+    assert!(!script.contains("jimmy_format_status"));
+}
+
+#[test]
+fn several_disks()
+{
+    let script = body(&[("    size: 500M\n", DATA)], "parallel_format: true\n");
+    assert_eq!(jobs(&script), "jimmy_format_status=$(mktemp -d)
+{ { mkfs.fat -F 32 /dev/sda1 && mkfs.ext4 /dev/sda2; } 2>&1; echo $? >\"$jimmy_format_status/0\"; } | sed 's|^|/dev/sda: |' &
+{ { mkfs.ext4 /dev/sdb1; } 2>&1; echo $? >\"$jimmy_format_status/1\"; } | sed 's|^|/dev/sdb: |' &
+wait
+jimmy_format_failed=
+for jimmy_format_job in \"$jimmy_format_status\"/*; do
+    [ \"$(cat \"$jimmy_format_job\")\" -eq 0 ] || jimmy_format_failed=1
+done
+rm -rf \"$jimmy_format_status\"
+if [ -n \"$jimmy_format_failed\" ]; then
+    echo 'error: formatting failed' >&2
+    exit 1
+fi
+");
+    // without it, the disks are formatted one after the other
+    let script = body(&[("    size: 500M\n", DATA)], "");
+    assert!(script.contains("\nmkfs.fat -F 32 /dev/sda1 || exit 1\nmkfs.ext4 /dev/sda2 || exit 1\nmkfs.ext4 /dev/sdb1 || exit 1\n"));
+}
+
+#[test]
+fn failing_job()
+{
+    let script = body(&[("    size: 500M\n", DATA)], "parallel_format: true\n");
+    let code = format!("{}echo 'the rest of the script'", jobs(&script));
+    // mkfs.ext4 fails on /dev/sdb1, or on every partition
+    let mkfs = |failing: &str| format!("echo \"formatting $*\"\ncase \"$*\" in {}) echo \"cannot format $*\" >&2; exit 1 ;; esac", failing);
+    let run = |failing: &str| {
+        let (success, stdout, stderr) = common::sh(&code, &[("mkfs.fat", &mkfs(failing)), ("mkfs.ext4", &mkfs(failing))], "");
+        let mut lines: Vec<String> = stdout.lines().map(String::from).collect();
+        // the jobs print at the same time
+        lines.sort();
+        (success, lines, stderr)
+    };
+
+    assert_eq!(run("nothing"), (true, vec![
+        "/dev/sda: formatting -F 32 /dev/sda1".to_string(),
+        "/dev/sda: formatting /dev/sda2".to_string(),
+        "/dev/sdb: formatting /dev/sdb1".to_string(),
+        "the rest of the script".to_string(),
+    ], String::new()));
+    assert_eq!(run("/dev/sdb1"), (false, vec![
+        "/dev/sda: formatting -F 32 /dev/sda1".to_string(),
+        "/dev/sda: formatting /dev/sda2".to_string(),
+        "/dev/sdb: cannot format /dev/sdb1".to_string(),
+        "/dev/sdb: formatting /dev/sdb1".to_string(),
+    ], "error: formatting failed\n".to_string()));
+    // the partitions after the one that failed on the same disk are left alone
+    assert_eq!(run("*/dev/sda1"), (false, vec![
+        "/dev/sda: cannot format -F 32 /dev/sda1".to_string(),
+        "/dev/sda: formatting -F 32 /dev/sda1".to_string(),
+        "/dev/sdb: formatting /dev/sdb1".to_string(),
+    ], "error: formatting failed\n".to_string()));
+    // a variable of the same name left by the environment doesn't make it fail
+    let (success, _, _) = common::sh(&format!("jimmy_format_failed=1\n{}", code), &[("mkfs.fat", &mkfs("nothing")), ("mkfs.ext4", &mkfs("nothing"))], "");
+    assert!(success);
+
+    // one after the other, the script stops at the first one that fails
+    let script = body(&[("    size: 500M\n", DATA)], "");
+    let from = script.find("\nmkfs.fat ").unwrap();
+    let code = format!("{}\necho 'the rest of the script'", &script[from..from + script[from..].find("\nmkfs.ext4 /dev/sdb1").unwrap()]);
+    assert_eq!(common::sh(&code, &[("mkfs.fat", &mkfs("*/dev/sda1")), ("mkfs.ext4", &mkfs("*/dev/sda1"))], ""), (
+        false,
+        "formatting -F 32 /dev/sda1\n".to_string(),
+        "cannot format -F 32 /dev/sda1\n".to_string(),
+    ));
+}
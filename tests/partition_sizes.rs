@@ -0,0 +1,86 @@
+//! Checks how the sizes of partitions are read and given to fdisk: every size is written back
+//! with the largest unit it's a whole number of, reading that back gives the same size, and sizes
+//! that can't be understood are refused
+
+use std::process::Output;
+
+mod common;
+
+/// Bytes in every unit a size can be written with
+const UNITS: &[(&str, u64)] = &[
+    ("K", 1 << 10), ("KiB", 1 << 10), ("M", 1 << 20), ("MiB", 1 << 20),
+    ("G", 1 << 30), ("GiB", 1 << 30), ("T", 1 << 40), ("TiB", 1 << 40),
+    ("KB", 1_000), ("MB", 1_000_000), ("GB", 1_000_000_000), ("TB", 1_000_000_000_000),
+];
+
+/// Generate the script from the sample configuration file, with a partition of the given size
+/// after the others
+fn generate(size: &str) -> Output
+{
+    common::generate(&["--file"], &[], &format!("  - data:\n    format: ext4\n    mount: /data\n    size: \"{}\"\n", size))
+}
+
+/// Return the size fdisk is given for the partition of the given size, without its `+`
+fn fdisk_size(size: &str) -> String
+{
+    let script = common::script(generate(size));
+    let line = script.lines().find(|l| l.ends_with("| fdisk /dev/sda >/dev/null 2>&1 || exit 1")).unwrap();
+    // the first partition with a size is the EFI system partition
+    line.split("\\n").filter_map(|l| l.strip_prefix('+')).nth(1).unwrap().to_string()
+}
+
+/// Return the bytes of a size
+fn bytes(size: &str) -> u64
+{
+    let split = size.find(|c: char| !c.is_ascii_digit()).unwrap();
+    let (_, unit) = UNITS.iter().find(|(name, _)| *name == &size[split..]).unwrap();
+    size[..split].parse::<u64>().unwrap() * unit
+}
+
+#[test]
+fn round_trips()
+{
+    for number in [1, 3, 500, 1000, 1023, 1024, 1536, 3000, 4096, 1_000_000] {
+        for (unit, _) in UNITS {
+            let size = format!("{}{}", number, unit);
+            let rendered = fdisk_size(&size);
+            assert_eq!(bytes(&rendered), bytes(&size), "{} was given to fdisk as {}", size, rendered);
+            // no bigger unit holds it exactly
+            let unit_bytes = bytes(&rendered) / rendered.trim_end_matches(char::is_alphabetic).parse::<u64>().unwrap();
+            assert!(!UNITS.iter().any(|(_, b)| *b > unit_bytes && bytes(&size).is_multiple_of(*b)), "{} was given to fdisk as {}", size, rendered);
+            assert_eq!(fdisk_size(&rendered), rendered);
+        }
+    }
+}
+
+#[test]
+fn largest_exact_unit()
+{
+    for (size, rendered) in [
+        ("500M", "500M"), ("1024M", "1G"), ("1GiB", "1G"), ("2048KiB", "2M"), ("1536M", "1536M"),
+        ("2GB", "2GB"), ("1000MB", "1GB"), ("256GB", "256GB"), ("1500KB", "1500KB"), ("8000K", "8000K"),
+    ] {
+        assert_eq!(fdisk_size(size), rendered, "{}", size);
+    }
+}
+
+#[test]
+fn rest_of_the_disk()
+{
+    // the root partition of the sample takes the rest of the disk, so fdisk is given no size
+    let output = generate("1G");
+    let script = String::from_utf8(output.stdout).unwrap();
+    assert!(script.contains("\"g\\nn\\n1\\n\\n+500M\\nt\\nuefi\\nn\\n2\\n\\n\\nt\\n2\\n"));
+}
+
+#[test]
+fn invalid()
+{
+    for size in ["500", "500m", "1.5G", "G", "-1G", "50%", "99999999999T"] {
+        let output = generate(size);
+        assert!(!output.status.success(), "{}", size);
+        assert!(String::from_utf8_lossy(&output.stderr)
+            .contains(&format!("invalid size: \"{}\" (expected a number followed by one of: T, TiB, TB, G, GiB, GB, M, MiB, MB, K, KiB, KB)", size)),
+            "{}", size);
+    }
+}
@@ -0,0 +1,113 @@
+//! Checks the partition types fdisk is given: `type_guid` by name or as a GUID, the types of the
+//! Discoverable Partitions Specification given to the partitions mounted where it says, and
+//! `discoverable_partitions: false`
+
+mod common;
+
+/// The root partition of the sample, with a size so that other partitions can follow it
+const ROOT: &str = "    format: ext4\n    mount: /\n    size: 20G\n";
+
+/// Generate the script from the sample configuration file, with `partitions` after its root
+/// partition and the given lines appended
+fn generate(partitions: &str, extra_lines: &str) -> std::process::Output
+{
+    common::generate(&["--file"], &[("    format: ext4\n    mount: /\n", &format!("{}{}", ROOT, partitions))], extra_lines)
+}
+
+/// Return the types fdisk gives to the partitions, by number, in the script of the sample
+/// configuration file with `partitions` after its root partition and the given lines appended
+fn types(partitions: &str, extra_lines: &str) -> Vec<(usize, String)>
+{
+    let script = common::script(generate(partitions, extra_lines));
+    let answers = script.lines().find_map(|l| l.strip_prefix("printf \"")).unwrap().split_once('"').unwrap().0;
+    let answers: Vec<&str> = answers.split("\\n").collect();
+    let mut types = Vec::new();
+    for (i, _) in answers.iter().enumerate().filter(|(_, a)| **a == "t") {
+        // fdisk doesn't ask which partition when there's only one
+        match answers[i + 1].parse() {
+            Ok(number) => types.push((number, answers[i + 2].to_string())),
+            Err(_) => types.push((1, answers[i + 1].to_string())),
+        }
+    }
+    types
+}
+
+/// A partition of the given format mounted at `mount`, with the given lines
+fn partition(format: &str, mount: &str, lines: &str) -> String
+{
+    format!("  - other:\n    format: {}\n    mount: {}\n    size: 1G\n{}", format, mount, lines)
+}
+
+const ROOT_X86_64: &str = "4F68BCE3-E8CD-4DB1-96E7-FBCAF984B709";
+const LINUX: &str = "0FC63DAF-8483-4772-8E79-3D69D8477DE4";
+
+#[test]
+fn names_and_guids()
+{
+    for (type_guid, guid) in [
+        ("esp", "C12A7328-F81F-11D2-BA4B-00A0C93EC93B"),
+        ("xbootldr", "BC13C2FF-59E6-4262-A352-B275FD6F7172"),
+        ("linux", LINUX),
+        ("linux-home", "933AC7E1-2EB4-4F13-B844-0E14E2AEF915"),
+        ("linux-srv", "3B8F8425-20E0-4F3B-907F-1A25A76F98E8"),
+        ("linux-var", "4D21B016-B534-45C2-A9FB-5C16E091FD2D"),
+        ("linux-root-x86-64", ROOT_X86_64),
+        ("linux-root-arm64", "B921B045-1DF0-41C3-AF44-4C6F280D3FAE"),
+        // GUIDs are passed on in uppercase, whatever case they're written in
+        ("0fc63daf-8483-4772-8e79-3d69d8477de4", LINUX),
+        ("0Fc63dAf-8483-4772-8E79-3d69D8477dE4", LINUX),
+        ("9E1A2D38-C612-4316-AA26-8B49521E5A8B", "9E1A2D38-C612-4316-AA26-8B49521E5A8B"),
+    ] {
+        let format = if ["esp", "xbootldr"].contains(&type_guid) { "fat32" } else { "ext4" };
+        let types = types(&partition(format, "/data", &format!("    type_guid: {}\n", type_guid)), "");
+        assert_eq!(types[2], (3, guid.to_string()), "{}", type_guid);
+    }
+    let swap = types("  - swap:\n    format: swap\n    type_guid: swap\n", "");
+    assert_eq!(swap[2], (3, "0657FD6D-A4AB-43C4-84E5-0933C84B4F4F".to_string()));
+}
+
+#[test]
+fn invalid_type_guid()
+{
+    for type_guid in ["linux-root", "0FC63DAF-8483-4772-8E79", "0FC63DAF84834772 8E793D69D8477DE4", "0FC63DAF-8483-4772-8E79-3D69D8477DEG"] {
+        let stderr = common::refusal(generate(&partition("ext4", "/data", &format!("    type_guid: \"{}\"\n", type_guid)), ""));
+        assert!(stderr.contains(&format!("invalid type_guid: \"{}\" (expected a GUID, or one of: esp, xbootldr, swap, linux, linux-home, \
+            linux-srv, linux-var, linux-root-x86-64, linux-root-arm64)", type_guid)), "{}", stderr);
+    }
+}
+
+/// The same partitions, one for each place the specification has a type for, and one it doesn't
+const DISCOVERABLE: &str = "  - home:\n    format: ext4\n    mount: /home\n    size: 10G\n\
+    \x20 - srv:\n    format: xfs\n    mount: /srv\n    size: 10G\n\
+    \x20 - var:\n    format: btrfs\n    mount: /var\n    size: 10G\n\
+    \x20 - data:\n    format: ext4\n    mount: /data\n";
+
+#[test]
+fn discoverable()
+{
+    assert_eq!(types(DISCOVERABLE, ""), [
+        (1, "uefi".to_string()),
+        (2, ROOT_X86_64.to_string()),
+        (3, "933AC7E1-2EB4-4F13-B844-0E14E2AEF915".to_string()),
+        (4, "3B8F8425-20E0-4F3B-907F-1A25A76F98E8".to_string()),
+        (5, "4D21B016-B534-45C2-A9FB-5C16E091FD2D".to_string()),
+        (6, "linux".to_string()),
+    ]);
+
+    // a `type_guid` given by hand takes precedence
+    let types = types(&partition("ext4", "/home", "    type_guid: linux\n"), "");
+    assert_eq!(types[2], (3, LINUX.to_string()));
+
+    // filesystems other than Linux ones can't be discovered, and keep the type of their format
+    let types = self::types(&partition("exfat", "/srv", ""), "");
+    assert_eq!(types[2], (3, "EBD0A0A2-B9E5-4433-87C0-68B6B72699C7".to_string()));
+}
+
+#[test]
+fn not_discoverable()
+{
+    // the Linux partitions all get the generic type of their format
+    let mut expected = vec![(1, "uefi".to_string())];
+    expected.extend((2..=6).map(|n| (n, "linux".to_string())));
+    assert_eq!(types(DISCOVERABLE, "discoverable_partitions: false\n"), expected);
+}
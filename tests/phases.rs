@@ -0,0 +1,123 @@
+//! Checks the phases of the installation the script can be limited to, with `phases` or with
+//! `--no-chroot` and `--keep-mounted`: the exact steps of the script in each mode, what it does
+//! once they're done, and the selections that are refused
+
+use std::process::Output;
+
+mod common;
+
+/// Generate the script with `args` from the sample configuration file, with the given lines
+/// appended
+fn generate(args: &[&str], extra_lines: &str) -> Output
+{
+    common::generate(args, &[], extra_lines)
+}
+
+/// Return the script, checking that jimmy succeeded
+fn generated(args: &[&str], extra_lines: &str) -> String
+{
+    common::script(generate(args, extra_lines))
+}
+
+/// Return the names of the steps of the script, in the order they're ran in
+fn steps(script: &str) -> Vec<&str>
+{
+    script.lines()
+        .filter_map(|l| l.strip_prefix("JIMMY_STEP="))
+        .map(|name| name.trim_matches('\''))
+        .filter(|name| !name.is_empty())
+        .collect()
+}
+
+/// The steps of the sample configuration file up to its filesystem table
+const UP_TO_FSTAB: &[&str] = &["preflight", "previous mounts", "clock", "partitioning", "formatting", "mounting", "pacstrap", "fstab"];
+
+/// The steps of the sample configuration file that configure the installed system
+const CHROOT: &[&str] = &["chroot script", "configuration", "cleanup", "verification"];
+
+#[test]
+fn every_phase()
+{
+    let script = generated(&["--file"], "");
+    assert_eq!(steps(&script), [UP_TO_FSTAB, CHROOT, &["unmount"]].concat());
+    assert!(!script.contains("# phases:"));
+    assert!(script.ends_with("\nprintf '\\n%s\\n' '<-> done; you may reboot now'\n"));
+}
+
+#[test]
+fn no_chroot()
+{
+    let script = generated(&["--no-chroot", "--file"], "");
+    assert_eq!(steps(&script), [UP_TO_FSTAB, &["unmount"]].concat());
+    assert!(script.contains("\n# phases: disks, pacstrap, fstab, unmount\n"));
+    // nothing is left to verify, and nothing of the arch-chroot script is written
+    assert!(!script.contains("jimmy_failed"));
+    assert!(!script.contains("jimmy_part2.sh"));
+    assert!(!script.contains("config.hash"));
+
+    // the same as the configuration file selecting the phases, in whichever order
+    let config = generated(&["--file"], "phases: unmount, fstab, pacstrap, disks\n");
+    assert_eq!(steps(&config), steps(&script));
+    assert!(config.contains("\n# phases: disks, pacstrap, fstab, unmount\n"));
+}
+
+#[test]
+fn keep_mounted()
+{
+    let script = generated(&["--no-chroot", "--keep-mounted", "--file"], "");
+    assert_eq!(steps(&script), UP_TO_FSTAB);
+    assert!(script.contains("\n# phases: disks, pacstrap, fstab\n"));
+    assert!(!script.contains("|| umount -R /mnt"));
+    assert!(script.ends_with("\nprintf '\\n%s\\n' '<-> done; the new system is left mounted on /mnt'\n"));
+
+    let script = generated(&["--keep-mounted", "--file"], "");
+    assert_eq!(steps(&script), [UP_TO_FSTAB, CHROOT].concat());
+    assert!(script.contains("\nif [ \"$jimmy_failed\" -ne 0 ]; then\n"));
+}
+
+#[test]
+fn disks_only()
+{
+    let lines = "partitions:\n  - esp:\n    format: fat32\n    size: 500M\n    mount: /boot\n  \
+        - root:\n    format: ext4\n    mount: /\n    encryption:\n      passphrase: prompt\n";
+    let script = generated(&["--file"], &format!("{}phases: [ disks ]\n", lines));
+    assert_eq!(steps(&script), ["preflight", "previous mounts", "clock", "partitioning", "encryption", "formatting", "mounting"]);
+    // the encrypted partitions are left open along with the filesystems
+    assert!(!script.contains("|| cryptsetup close"));
+    assert!(generated(&["--file"], lines).contains("|| cryptsetup close"));
+}
+
+#[test]
+fn timings_and_report()
+{
+    // they're written on the new system whichever phases are selected, since it's mounted by then
+    let script = generated(&["--no-chroot", "--keep-mounted", "--file"], "timings: true\nreport: /var/log/jimmy/report.json\n");
+    assert!(script.contains("\njimmy_time 'fstab' \"$jimmy_step_start\" >>\"$JIMMY_TIMINGS\"\n"));
+    assert!(script.contains("/mnt/var/log/jimmy/timings.txt"));
+    assert!(script.contains("\"$JIMMY_VERIFIED\""));
+}
+
+#[test]
+fn chroot_script()
+{
+    let output = generate(&["chroot-script"], "phases: disks, pacstrap, fstab\n");
+    assert!(!output.status.success());
+    assert!(String::from_utf8_lossy(&output.stderr)
+        .contains("error: the configuration file leaves out the phase chroot, so there's no arch-chroot script"));
+}
+
+#[test]
+fn invalid()
+{
+    for (lines, message) in [
+        ("phases: [ disks, configure ]\n", "invalid phases: \"configure\" (expected one of: disks, pacstrap, fstab, chroot, unmount)"),
+        ("phases: []\n", "no phases of the installation are selected, so the script would do nothing"),
+        ("phases: disks, chroot\n", "the phase chroot needs the phase fstab, which isn't selected"),
+        ("phases: disks, fstab\n", "the phase fstab needs the phase pacstrap, which isn't selected"),
+        ("phases: pacstrap, fstab, chroot, unmount\n", "the phase pacstrap needs the phase disks, which isn't selected"),
+    ] {
+        let output = generate(&["--file"], lines);
+        assert!(!output.status.success(), "{}", lines);
+        assert!(String::from_utf8_lossy(&output.stderr).contains(message), "{}", String::from_utf8_lossy(&output.stderr));
+    }
+}
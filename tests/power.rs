@@ -0,0 +1,159 @@
+//! Checks `power` and `laptop`: the power daemon installed straight away on laptops, left out
+//! elsewhere, and installed by the arch-chroot script once it finds out from the chassis type,
+//! which is ran with sh on fake chassis types, with pacman and systemctl replaced by programs
+//! recording what they're asked to do, and what the summary says about the daemon
+
+use std::os::unix::fs::PermissionsExt;
+use std::process::{Command, Output, Stdio};
+
+mod common;
+
+/// Run jimmy with `args` on the sample configuration file, with the given lines appended
+fn generate(args: &[&str], extra_lines: &str) -> Output
+{
+    common::generate(args, &[], extra_lines)
+}
+
+/// Return what jimmy printed, checking that it succeeded
+fn generated(args: &[&str], extra_lines: &str) -> String
+{
+    common::script(generate(args, extra_lines))
+}
+
+/// Return the commands of the arch-chroot script that set up the power daemon, if there are any
+fn power_cmds(script: &str) -> Option<&str>
+{
+    let start = script.find("echo '<chroot> setting up the power management of laptops...'\n")?;
+    let end = start + script[start..].find("\n\necho '<chroot>").unwrap();
+    Some(&script[start..end])
+}
+
+/// Run `code` with sh on a machine whose chassis type is `chassis`, if any; pacman and systemctl
+/// append what they're asked to do to a log, which is returned along with whether the code
+/// succeeded
+fn run(code: &str, chassis: Option<&str>, pacman_fails: bool) -> (bool, String)
+{
+    let dir = common::temp_path("dir");
+    std::fs::create_dir_all(&dir).unwrap();
+    let exit = if pacman_fails { 1 } else { 0 };
+    for (program, status) in [("pacman", exit), ("systemctl", 0)] {
+        std::fs::write(dir.join(program), format!("#!/bin/sh\necho \"{} $*\" >>\"$LOG\"\nexit {}\n", program, status)).unwrap();
+        std::fs::set_permissions(dir.join(program), std::fs::Permissions::from_mode(0o755)).unwrap();
+    }
+    if let Some(chassis) = chassis {
+        std::fs::write(dir.join("chassis_type"), format!("{}\n", chassis)).unwrap();
+    }
+    let code = code.replace("/sys/class/dmi/id/chassis_type", &dir.join("chassis_type").display().to_string());
+    let status = Command::new("sh").args(["-c", &code])
+        .env("PATH", format!("{}:{}", dir.display(), std::env::var("PATH").unwrap()))
+        .env("LOG", dir.join("log"))
+        .stdout(Stdio::null())
+        .status()
+        .unwrap();
+    let log = std::fs::read_to_string(dir.join("log")).unwrap_or_default();
+    std::fs::remove_dir_all(&dir).unwrap();
+    (status.success(), log)
+}
+
+#[test]
+fn laptop()
+{
+    let lines = "power: tlp\nlaptop: true\n";
+    assert!(generated(&["packages"], lines).lines().any(|p| p == "tlp"));
+    let script = generated(&["chroot-script"], lines);
+    assert_eq!(power_cmds(&script).unwrap(), "echo '<chroot> setting up the power management of laptops...'\n\
+        systemctl enable tlp.service\n\
+        systemctl mask systemd-rfkill.service systemd-rfkill.socket");
+    assert!(!script.contains("chassis_type"));
+
+    let script = generated(&["chroot-script"], "power: power-profiles-daemon\nlaptop: true\n");
+    assert!(power_cmds(&script).unwrap().ends_with("\nsystemctl enable power-profiles-daemon.service"));
+}
+
+#[test]
+fn not_a_laptop()
+{
+    for lines in ["power: tlp\nlaptop: false\n", "power: none\n", ""] {
+        assert!(power_cmds(&generated(&["chroot-script"], lines)).is_none(), "{}", lines);
+        assert!(!generated(&["packages"], lines).lines().any(|p| p == "tlp"));
+    }
+}
+
+#[test]
+fn detected()
+{
+    for lines in ["power: tlp\n", "power: tlp\nlaptop: auto\n"] {
+        assert!(!generated(&["packages"], lines).lines().any(|p| p == "tlp"));
+        let script = generated(&["chroot-script"], lines);
+        let cmds = power_cmds(&script).unwrap();
+        for chassis in ["8", "9", "10", "14", "30", "31", "32"] {
+            assert_eq!(run(cmds, Some(chassis), false), (true, "pacman -S --needed --noconfirm tlp\n\
+                systemctl enable tlp.service\n\
+                systemctl mask systemd-rfkill.service systemd-rfkill.socket\n".to_string()), "{}", chassis);
+        }
+        // a desktop, a server, and a machine whose firmware doesn't say
+        for chassis in [Some("3"), Some("17"), Some("100"), None] {
+            assert_eq!(run(cmds, chassis, false), (true, String::new()), "{:?}", chassis);
+        }
+        assert_eq!(run(cmds, Some("10"), true), (false, "pacman -S --needed --noconfirm tlp\n".to_string()));
+    }
+    // the packages are downloaded from inside arch-chroot
+    let output = generate(&["--file"], "power: power-profiles-daemon\nkeep_resolv_conf: false\n");
+    assert!(String::from_utf8_lossy(&output.stderr)
+        .contains("warning: `keep_resolv_conf: false`, but `power`, without `laptop`, needs the network inside arch-chroot"));
+}
+
+#[test]
+fn summarized()
+{
+    // the daemon is only said to be enabled on every machine if it's known to be a laptop
+    for (lines, services) in [
+        ("power: tlp\nlaptop: true\n", "NetworkManager.service, systemd-resolved.service, tlp.service"),
+        ("power: tlp\n", "NetworkManager.service, systemd-resolved.service, tlp.service (on laptops)"),
+        ("power: tlp\nlaptop: false\n", "NetworkManager.service, systemd-resolved.service"),
+    ] {
+        let summary = generated(&["summarize", "--markdown"], lines);
+        assert!(summary.contains(&format!("\n## Services\n\n{}\n", services)), "{}: {}", lines, summary);
+    }
+}
+
+#[test]
+fn conflicts()
+{
+    for (lines, message) in [
+        ("power: power-profiles-daemon\npackages: tlp\n", "package 'tlp' conflicts with `power: power-profiles-daemon`: \
+            both change the same power settings and undo each other's; remove the package, or use `power: tlp`"),
+        ("power: tlp\npackages: power-profiles-daemon\n", "package 'power-profiles-daemon' conflicts with `power: tlp`"),
+        ("power: tlp\npackages: gnome\n", "package 'gnome' conflicts with `power: tlp`: GNOME comes with power-profiles-daemon, \
+            which undoes the power settings of TLP; use `power: power-profiles-daemon`"),
+        ("power: power-profiles-daemon\npackages: laptop-mode-tools\n", "package 'laptop-mode-tools' conflicts with `power: power-profiles-daemon`"),
+    ] {
+        let output = generate(&["--file"], lines);
+        assert!(output.status.success());
+        assert!(String::from_utf8_lossy(&output.stderr).contains(&format!("warning: {}", message)), "{}", String::from_utf8_lossy(&output.stderr));
+        let output = generate(&["--file"], &format!("strict: true\n{}", lines));
+        assert!(!output.status.success());
+        assert!(String::from_utf8_lossy(&output.stderr).contains(message));
+    }
+    for lines in ["packages: tlp\n", "power: tlp\npackages: tlp\n", "power: power-profiles-daemon\npackages: gnome\n"] {
+        let stderr = String::from_utf8(generate(&["--file"], lines).stderr).unwrap();
+        assert!(!stderr.contains("conflicts with"), "{}: {}", lines, stderr);
+    }
+}
+
+#[test]
+fn invalid()
+{
+    for (lines, message) in [
+        ("power: thermald\n", "invalid power: \"thermald\" (expected one of: tlp, power-profiles-daemon, none)"),
+        ("power: tlp\nlaptop: maybe\n", "invalid laptop: \"maybe\" (expected one of: auto, true, false)"),
+    ] {
+        let output = generate(&["--file"], lines);
+        assert!(!output.status.success(), "{}", lines);
+        assert!(String::from_utf8_lossy(&output.stderr).contains(message), "{}", String::from_utf8_lossy(&output.stderr));
+    }
+    let output = generate(&["--file"], "laptop: true\n");
+    assert!(output.status.success());
+    assert!(String::from_utf8_lossy(&output.stderr)
+        .contains("warning: laptop is only used along with `power`, which is `none`; it's going to be ignored"));
+}
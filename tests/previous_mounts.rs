@@ -0,0 +1,108 @@
+//! Checks what the script does with the filesystems an earlier attempt left mounted under /mnt,
+//! with each `previous_mounts` policy: the commands it's written with, and what they do when ran
+//! with sh, with findmnt listing made-up mounts and umount and cryptsetup recording what they're
+//! asked to do
+
+use std::os::unix::fs::PermissionsExt;
+use std::process::{Command, Output};
+
+mod common;
+
+/// Run jimmy on the sample configuration file, with the given lines appended
+fn generate(extra_lines: &str) -> Output
+{
+    common::generate(&["--file"], &[], extra_lines)
+}
+
+/// Return the commands of the step that looks for what's mounted under /mnt, checking that
+/// jimmy succeeded
+fn previous_mounts(extra_lines: &str) -> String
+{
+    let script = common::script(generate(extra_lines));
+    let start = script.find("\nJIMMY_STEP='previous mounts'\n").unwrap() + "\nJIMMY_STEP='previous mounts'\n".len();
+    let end = start + script[start..].find("\njimmy_time 'previous mounts'").unwrap();
+    script[start..end].to_string()
+}
+
+/// Run `code` with sh, with findmnt listing the targets in `mounted`; umount and cryptsetup
+/// append what they're asked to do to a log. Return whether the code succeeded, its stderr and
+/// the log
+fn run(code: &str, mounted: &[&str]) -> (bool, String, String)
+{
+    let dir = common::temp_path("dir");
+    std::fs::create_dir_all(&dir).unwrap();
+    let programs = [
+        ("findmnt", format!("printf '%s\\n' / /run/archiso {}", mounted.join(" "))),
+        ("umount", "echo \"umount $*\" >>\"$LOG\"".to_string()),
+        ("cryptsetup", "echo \"cryptsetup $*\" >>\"$LOG\"".to_string()),
+    ];
+    for (program, body) in programs {
+        std::fs::write(dir.join(program), format!("#!/bin/sh\n{}\n", body)).unwrap();
+        std::fs::set_permissions(dir.join(program), std::fs::Permissions::from_mode(0o755)).unwrap();
+    }
+    // the encrypted partition is still open
+    let code = code.replace("/dev/mapper/", &format!("{}/", dir.display()));
+    std::fs::write(dir.join("cryptroot"), "").unwrap();
+    let output = Command::new("sh").args(["-c", &code])
+        .env("PATH", format!("{}:{}", dir.display(), std::env::var("PATH").unwrap()))
+        .env("LOG", dir.join("log"))
+        .output()
+        .unwrap();
+    let log = std::fs::read_to_string(dir.join("log")).unwrap_or_default();
+    std::fs::remove_dir_all(&dir).unwrap();
+    (output.status.success(), String::from_utf8(output.stderr).unwrap(), log)
+}
+
+const ENCRYPTED: &str = "partitions:\n  - esp:\n    format: fat32\n    size: 500M\n    mount: /boot\n  \
+    - root:\n    format: ext4\n    mount: /\n    encryption:\n      passphrase: prompt\n";
+
+/// The shell code that lists what's mounted under /mnt
+const MOUNTED: &str = "jimmy_mounted=$(findmnt -rn -o TARGET | awk '$1 == \"/mnt\" || index($1, \"/mnt/\") == 1' | sort -r)\n";
+
+#[test]
+fn abort()
+{
+    for lines in ["", "previous_mounts: abort\n"] {
+        assert_eq!(previous_mounts(lines), format!("{}if [ -n \"$jimmy_mounted\" ]; then\n    \
+            echo 'error: these filesystems are still mounted under /mnt, probably by an earlier attempt:' >&2\n    \
+            printf '    %s\\n' $jimmy_mounted >&2\n    \
+            echo 'unmount them with `umount -R /mnt`, or set `previous_mounts: unmount` for the script to do it' >&2\n    \
+            exit 1\nfi", MOUNTED));
+    }
+    let code = previous_mounts(ENCRYPTED);
+    assert!(code.contains("unmount them with `umount -R /mnt && cryptsetup close cryptroot`"));
+
+    assert_eq!(run(&code, &["/mnt", "/mnt/boot"]), (false, "error: these filesystems are still mounted under /mnt, probably by an earlier attempt:\n    \
+        /mnt/boot\n    /mnt\nunmount them with `umount -R /mnt && cryptsetup close cryptroot`, or set `previous_mounts: unmount` for the script to do it\n".to_string(), String::new()));
+    // only what's under /mnt counts
+    assert_eq!(run(&code, &["/mnt2", "/media/mnt"]), (true, String::new(), String::new()));
+}
+
+#[test]
+fn unmount()
+{
+    let code = previous_mounts(&format!("previous_mounts: unmount\n{}", ENCRYPTED));
+    assert_eq!(code, format!("{}if [ -n \"$jimmy_mounted\" ]; then\n    \
+        echo 'warning: unmounting the filesystems an earlier attempt left mounted under /mnt' >&2\n    \
+        for jimmy_target in $jimmy_mounted; do\n        \
+        umount \"$jimmy_target\" || {{ echo \"error: $jimmy_target could not be unmounted\" >&2; exit 1; }}\n    \
+        done\nfi\n\
+        ! [ -e /dev/mapper/cryptroot ] || cryptsetup close cryptroot", MOUNTED));
+
+    // the deepest mounts go first, and whatever happened to be mounted under /mnt too
+    assert_eq!(run(&code, &["/mnt", "/mnt/boot", "/mnt/home", "/mnt/var/cache"]), (
+        true,
+        "warning: unmounting the filesystems an earlier attempt left mounted under /mnt\n".to_string(),
+        "umount /mnt/var/cache\numount /mnt/home\numount /mnt/boot\numount /mnt\ncryptsetup close cryptroot\n".to_string(),
+    ));
+    // the encrypted partition is closed even when nothing was mounted on it yet
+    assert_eq!(run(&code, &[]), (true, String::new(), "cryptsetup close cryptroot\n".to_string()));
+}
+
+#[test]
+fn invalid()
+{
+    let output = generate("previous_mounts: ignore\n");
+    assert!(!output.status.success());
+    assert!(String::from_utf8_lossy(&output.stderr).contains("invalid previous_mounts: \"ignore\" (expected one of: abort, unmount)"));
+}
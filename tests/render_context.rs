@@ -0,0 +1,83 @@
+//! Checks the way the scripts are asked to be written: each of `--shell`, `--color` and `--log`
+//! only changes the lines it's about
+
+mod common;
+
+/// Generate the script with `--reproducible` and the given flags, then the command, from the
+/// sample configuration file with the given lines appended, checking that jimmy succeeded
+fn generated(args: &[&str], extra_lines: &str) -> String
+{
+    let (command, flags) = args.split_last().unwrap();
+    common::script(common::generate(&common::with_flags(&[&["--reproducible"], flags].concat(), &[command]), &[], extra_lines))
+}
+
+/// Return the lines that differ between two scripts with the same number of lines, as
+/// `(line of the first, line of the second)`
+fn changed<'a>(first: &'a str, second: &'a str) -> Vec<(&'a str, &'a str)>
+{
+    assert_eq!(first.lines().count(), second.lines().count());
+    first.lines().zip(second.lines()).filter(|(a, b)| a != b).collect()
+}
+
+/// The lines a context that keeps the log in /var/log/jimmy.log adds, in place of the temporary
+/// file
+const LOG_SETUP: &str = "JIMMY_STDERR=/var/log/jimmy.log\nmkdir -p \"$(dirname \"$JIMMY_STDERR\")\" && : >\"$JIMMY_STDERR\"\n";
+
+#[test]
+fn shell()
+{
+    // the installation script writes the arch-chroot script, with its own `#!` line
+    for (command, scripts) in [("--file", 2), ("chroot-script", 1)] {
+        let sh = generated(&[command], "");
+        assert_eq!(sh, generated(&["--shell", "sh", command], ""));
+        let bash = generated(&["--shell", "bash", command], "");
+        assert_eq!(changed(&sh, &bash), vec![("#!/bin/sh", "#!/bin/bash"); scripts]);
+    }
+}
+
+#[test]
+fn color()
+{
+    for command in ["--file", "chroot-script"] {
+        let plain = generated(&[command], "timings: true\n");
+        let color = generated(&["--color", command], "timings: true\n");
+        let changed = changed(&plain, &color);
+        assert!(changed.len() > 5);
+        for (plain, color) in changed {
+            let message = plain.trim_start().strip_prefix("printf '\\n%s\\n' '").or_else(|| plain.trim_start().strip_prefix("echo '")).unwrap();
+            assert!(color.ends_with(&format!("%s\\033[0m\\n' '{}", message)), "{} -> {}", plain, color);
+        }
+    }
+    let color = generated(&["--color", "--file"], "");
+    assert!(color.ends_with("\nprintf '\\n\\033[1m%s\\033[0m\\n' '<-> done; you may reboot now'\n"));
+}
+
+#[test]
+fn log()
+{
+    let temporary = generated(&["--file"], "");
+    let log = generated(&["--log", "/var/log/jimmy.log", "--file"], "");
+    assert_eq!(log, temporary
+        .replace("JIMMY_STDERR=$(mktemp)\n", LOG_SETUP)
+        .replace("    rm -f \"$JIMMY_STDERR\"\n", "    # the log is kept for after the installation\n"));
+    // the arch-chroot script doesn't write the status
+    assert_eq!(generated(&["--log", "/var/log/jimmy.log", "chroot-script"], ""), generated(&["chroot-script"], ""));
+
+    assert!(common::refusal(common::generate(&["--log", "jimmy.log", "--file"], &[], ""))
+        .contains("error: invalid --log: \"jimmy.log\" (expected the absolute path of a file, such as /var/log/jimmy.log)"));
+}
+
+#[test]
+fn only_for_the_scripts()
+{
+    // the commands that don't print a script don't take the flags that write one
+    for args in [&["explain", "--width", "0"][..], &["packages", "--reproducible"], &["validate", "--color"]] {
+        assert!(!common::generate(args, &[], "").status.success(), "{:?}", args);
+    }
+    // nor are those of the installation script given to the subcommands
+    assert!(!common::generate(&["--width", "0", "chroot-script"], &[], "").status.success());
+    // how much is printed is set by every command that reads the configuration file
+    let warned = |args: &[&str]| String::from_utf8(common::generate(args, &[("locales:\n  - en_US.UTF-8\n", "locales: []\n")], "").stderr).unwrap();
+    assert!(warned(&["validate"]).contains("warning: locales not specified"));
+    assert_eq!(warned(&["validate", "--quiet"]), "");
+}
@@ -0,0 +1,103 @@
+//! Checks the report of the installation: the commands that write it are ran with /mnt and /sys
+//! moved into a temporary directory, after a fake installation left its timings, its warnings and
+//! the results of its verification there, and with a fake `lsblk`
+
+use std::os::unix::fs::PermissionsExt;
+use std::path::Path;
+use std::process::Command;
+
+mod common;
+
+/// What the fake `lsblk` prints, in the layout of the real one
+const LSBLK: &str = r#"{
+   "blockdevices": [
+      {
+         "path": "/dev/sda",
+         "model": "Samsung SSD 870",
+         "serial": "S5SXNG0R123456",
+         "wwn": "0x5002538f4123abcd",
+         "size": 500107862016
+      }
+   ]
+}"#;
+
+/// Return the script generated from a configuration file
+fn script(config: &Path) -> String
+{
+    common::script(Command::new(env!("CARGO_BIN_EXE_jimmy")).arg("--file").arg(config).output().unwrap())
+}
+
+/// Return the commands that write the report, from the definition of `jimmy_json` to the removal
+/// of the results of the verification
+fn report_cmds(script: &str) -> Option<String>
+{
+    let start = script.find("jimmy_json() {")?;
+    let end = start + script[start..].find("rm -f \"$JIMMY_VERIFIED\"")?;
+    Some(script[start..end].to_string())
+}
+
+#[test]
+fn report_has_the_plan_timings_verification_and_hardware()
+{
+    let script = script(Path::new("examples/valid--report.yaml"));
+    assert!(script.contains("\nJIMMY_VERIFIED=$(mktemp)\n"));
+    // the report includes the total time, and is written even if the verification failed
+    let report = script.find("writing the installation report").unwrap();
+    assert!(script.find("jimmy_time 'total'").unwrap() < report);
+    assert!(report < script.find("if [ \"$jimmy_failed\" -ne 0 ]").unwrap());
+
+    let dir = common::temp_path("dir");
+    for subdir in ["mnt/var/log/jimmy", "sys/class/dmi/id", "bin"] {
+        std::fs::create_dir_all(dir.join(subdir)).unwrap();
+    }
+    std::fs::write(dir.join("mnt/var/log/jimmy/timings.txt"), "preflight        2s\npacstrap         3m25s\nchroot script    0s\ntotal            4m1s\n").unwrap();
+    std::fs::write(dir.join("mnt/var/log/jimmy/warnings.txt"), "the password of root was not set, so root is \"locked\"\n").unwrap();
+    std::fs::write(dir.join("verified"), "PASS\tkernel /boot/vmlinuz-linux\nFAIL\tuser \"archie\"\n").unwrap();
+    std::fs::write(dir.join("sys/class/dmi/id/product_name"), "ThinkPad X1\n").unwrap();
+    let lsblk = dir.join("bin/lsblk");
+    std::fs::write(&lsblk, format!("#!/bin/sh\ncat <<'EOF'\n{}\nEOF\n", LSBLK)).unwrap();
+    std::fs::set_permissions(&lsblk, std::fs::Permissions::from_mode(0o755)).unwrap();
+
+    let cmds = report_cmds(&script).expect("no report in the script")
+        .replace("/mnt", &dir.join("mnt").display().to_string())
+        .replace("/sys", &dir.join("sys").display().to_string());
+    let path = format!("{}:{}", dir.join("bin").display(), std::env::var("PATH").unwrap_or_default());
+    let status = Command::new("sh")
+        .args(["-c", &cmds])
+        .env("PATH", path)
+        .env("JIMMY_VERIFIED", dir.join("verified"))
+        .status()
+        .unwrap();
+    assert!(status.success());
+
+    let report = std::fs::read_to_string(dir.join("mnt/root/jimmy-report.json")).unwrap();
+    let report: serde_json::Value = serde_json::from_str(&report).unwrap();
+    let plan = &report["plan"];
+    assert_eq!(plan["hostname"], "archlinux");
+    assert_eq!(plan["timezone"], "Europe/London");
+    assert_eq!(plan["kernel"], "linux");
+    assert_eq!(plan["partitions"][0], serde_json::json!({
+        "disk": "/dev/sda", "number": 1, "format": "fat32", "size": "500M", "mount": "/boot", "reused": false,
+    }));
+    assert_eq!(plan["partitions"][1]["size"], serde_json::Value::Null);
+    assert!(plan["packages"].as_array().unwrap().contains(&serde_json::json!("grub")));
+    assert_eq!(report["durations"], serde_json::json!({
+        "preflight": 2, "pacstrap": 205, "chroot script": 0, "total": 241,
+    }));
+    assert_eq!(report["verification"], serde_json::json!([
+        { "check": "kernel /boot/vmlinuz-linux", "passed": true },
+        { "check": "user \"archie\"", "passed": false },
+    ]));
+    assert_eq!(report["warnings"], serde_json::json!(["the password of root was not set, so root is \"locked\""]));
+    assert_eq!(report["hardware"]["product_name"], "ThinkPad X1");
+    assert_eq!(report["hardware"]["disks"][0]["serial"], "S5SXNG0R123456");
+    std::fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn no_report_by_default()
+{
+    let script = script(Path::new("examples/valid--maintenance.yaml"));
+    assert!(report_cmds(&script).is_none());
+    assert!(!script.contains("JIMMY_VERIFIED=$(mktemp)"));
+}
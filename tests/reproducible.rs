@@ -0,0 +1,53 @@
+//! Checks `--reproducible`: the same configuration file and flags give the same bytes, and the
+//! configuration hash the headers record changes with what the file configures
+
+mod common;
+
+/// Generate the script with `--reproducible` and `args` from the sample configuration file, with
+/// the given lines replaced and appended, checking that jimmy succeeded
+fn generated(args: &[&str], replacements: &[(&str, &str)], extra_lines: &str) -> String
+{
+    common::script(common::generate(&common::with_flags(&["--reproducible"], args), replacements, extra_lines))
+}
+
+/// Return the hash the header of the script records for the configuration file
+fn config_hash(script: &str) -> &str
+{
+    script.lines().find_map(|l| l.strip_prefix("# configuration hash: ")).unwrap()
+}
+
+#[test]
+fn reproducible()
+{
+    for args in [&["--file"][..], &["chroot-script"], &["--color", "--shell", "bash", "--log", "/var/log/jimmy.log", "--file"]] {
+        let first = generated(args, &[], "");
+        assert_eq!(first, generated(args, &[], ""));
+        assert!(!first.contains("# generated at"));
+    }
+}
+
+#[test]
+fn config_hash_follows_the_config()
+{
+    let sample = generated(&["--file"], &[], "");
+    let hash = config_hash(&sample);
+    assert_eq!(hash.len(), 16);
+    assert!(hash.bytes().all(|b| b.is_ascii_hexdigit()));
+    // both scripts record the same one
+    assert_eq!(config_hash(&generated(&["chroot-script"], &[], "")), hash);
+
+    for (replacements, extra_lines) in [
+        (&[("packages: vim\n", "packages: vim git\n")][..], ""),
+        (&[("timezone: Europe/London\n", "timezone: Europe/Paris\n")], ""),
+        (&[("    size: 500M\n", "    size: 1G\n")], ""),
+        (&[("hostname: archlinux\n", "hostname: workstation\n")], ""),
+        (&[], "keymap: de\n"),
+    ] {
+        let changed = generated(&["--file"], replacements, extra_lines);
+        assert_ne!(config_hash(&changed), hash, "{:?} {:?}", replacements, extra_lines);
+        assert_eq!(config_hash(&generated(&["chroot-script"], replacements, extra_lines)), config_hash(&changed));
+    }
+
+    // what's hashed is the configuration, not the text of the file
+    assert_eq!(config_hash(&generated(&["--file"], &[], "# a comment\n\n")), hash);
+}
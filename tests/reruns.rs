@@ -0,0 +1,164 @@
+//! Checks what running the steps of the script again does: the commands guarded so that a second
+//! run changes nothing, which are ran twice with fake programs recording what they're asked to
+//! do, and what `jimmy explain` says about every step
+
+use std::os::unix::fs::PermissionsExt;
+use std::process::{Command, Output};
+
+mod common;
+
+/// Generate the script, or run `jimmy explain` with `args`, on the sample configuration file with
+/// the given lines appended
+fn generate(args: &[&str], extra_lines: &str) -> Output
+{
+    let output = common::generate(args, &[], extra_lines);
+    assert!(output.status.success(), "jimmy failed: {}", String::from_utf8_lossy(&output.stderr));
+    output
+}
+
+/// Return the script
+fn script(extra_lines: &str) -> String
+{
+    String::from_utf8(generate(&["--file"], extra_lines).stdout).unwrap()
+}
+
+/// Return the commands of a step of the script whose name is a single word, up to the end of the
+/// step, or to where it's timed
+fn step<'a>(script: &'a str, name: &str) -> &'a str
+{
+    let start = script.find(&format!("JIMMY_STEP={}\n", name)).unwrap();
+    let end = start + script[start..].find("\n\n").unwrap();
+    let step = &script[start..end];
+    step.split_once(&format!("\njimmy_time '{}'", name)).map(|(cmds, _)| cmds).unwrap_or(step)
+}
+
+/// Return what `jimmy explain` says about running every step again, as `(step, sentence)`
+fn reruns(extra_lines: &str) -> Vec<(String, String)>
+{
+    let explanation = String::from_utf8(generate(&["explain", "--markdown"], extra_lines).stdout).unwrap();
+    explanation.split("## ").skip(1)
+        .map(|s| {
+            let (title, rest) = s.split_once('\n').unwrap();
+            let paragraphs: Vec<&str> = rest.split("\n\n").collect();
+            (title.split_once(". ").unwrap().1.to_string(), paragraphs[1].to_string())
+        })
+        .collect()
+}
+
+/// Run `code` twice with sh, with the given fake programs first in PATH; each of them is a shell
+/// script, ran with `$STATE` set to a directory they can keep what they did in. Return what
+/// `$STATE/log` has after both runs
+fn run_twice(code: &str, programs: &[(&str, &str)]) -> String
+{
+    let dir = common::temp_path("dir");
+    std::fs::create_dir_all(dir.join("bin")).unwrap();
+    std::fs::create_dir_all(dir.join("state")).unwrap();
+    for (program, body) in programs {
+        std::fs::write(dir.join("bin").join(program), format!("#!/bin/sh\n{}\n", body)).unwrap();
+        std::fs::set_permissions(dir.join("bin").join(program), std::fs::Permissions::from_mode(0o755)).unwrap();
+    }
+    for _ in 0..2 {
+        let status = Command::new("sh").args(["-e", "-c", code])
+            .env("PATH", format!("{}:{}", dir.join("bin").display(), std::env::var("PATH").unwrap()))
+            .env("STATE", dir.join("state"))
+            .status()
+            .unwrap();
+        assert!(status.success());
+    }
+    let log = std::fs::read_to_string(dir.join("state/log")).unwrap_or_default();
+    std::fs::remove_dir_all(&dir).unwrap();
+    log
+}
+
+#[test]
+fn mounting()
+{
+    let script = script("");
+    let mounting = step(&script, "mounting");
+    assert!(mounting.contains("\nmountpoint -q /mnt/ || { mkdir -p /mnt/ && mount /dev/sda2 /mnt/ || exit 1; }\n\
+        mountpoint -q /mnt/boot || { mkdir -p /mnt/boot && mount /dev/sda1 /mnt/boot || exit 1; }"));
+    let log = run_twice(mounting, &[
+        ("mkdir", ":"),
+        ("mount", "echo \"mount $*\" >>\"$STATE/log\"; touch \"$STATE/$(echo \"$2\" | tr / _)\""),
+        ("mountpoint", "[ -e \"$STATE/$(echo \"$2\" | tr / _)\" ]"),
+    ]);
+    assert_eq!(log, "mount /dev/sda2 /mnt/\nmount /dev/sda1 /mnt/boot\n");
+}
+
+#[test]
+fn fstab()
+{
+    let script = script("  - swap:\n    format: swap\n    size: 4G\n    activate_swap: false\n");
+    let fstab = step(&script, "fstab");
+    // the filesystem table pacstrap puts there is kept, and what comes after the marker is
+    // written again, the swap entry along with the others; the table is copied to the log at the
+    // end of each run, so only the second one is left
+    let code = format!("[ -f \"$STATE/fstab\" ] || echo '# Static information about the filesystems.' >\"$STATE/fstab\"\n{}\ncp \"$STATE/fstab\" \"$STATE/log\"",
+        fstab.replace("/mnt/etc/", "$STATE/"));
+    let log = run_twice(&code, &[
+        ("genfstab", "echo \"UUID=1234 / ext4 rw 0 1\""),
+        ("blkid", "echo 5678"),
+    ]);
+    assert_eq!(log, "# Static information about the filesystems.\n\
+        # the entries below are written by the installation script of jimmy\n\
+        UUID=1234 / ext4 rw 0 1\n\
+        UUID=5678\tnone\tswap\tdefaults\t0 0\n");
+}
+
+#[test]
+fn swap()
+{
+    let script = script("  - swap:\n    format: swap\n    size: 4G\n    swap_priority: 10\n");
+    // the kernel lists the swap spaces by the file it knows them by, e.g. /dev/dm-0 for
+    // /dev/mapper/cryptswap
+    assert!(step(&script, "mounting").ends_with("\ngrep -q \"^$(readlink -f /dev/sda3) \" /proc/swaps || swapon -p 10 /dev/sda3"));
+}
+
+#[test]
+fn users()
+{
+    let configuration = String::from_utf8(generate(&["chroot-script"], "").stdout).unwrap();
+    let useradd = configuration.lines().find(|l| l.contains("useradd")).unwrap();
+    assert_eq!(useradd, "id -u archie >/dev/null 2>&1 || useradd -m archie -G wheel -s /bin/bash");
+    let log = run_twice(useradd, &[
+        ("id", "grep -qx \"$2\" \"$STATE/users\""),
+        ("useradd", "echo \"useradd $*\" >>\"$STATE/log\"; echo \"$2\" >>\"$STATE/users\""),
+    ]);
+    assert_eq!(log, "useradd -m archie -G wheel -s /bin/bash\n");
+    assert!(configuration.contains("\ngrep -qxF 'wheel ALL=(ALL) ALL' /etc/sudoers || echo 'wheel ALL=(ALL) ALL' | EDITOR='tee -a' visudo\n"));
+}
+
+#[test]
+fn unmount()
+{
+    let script = script("");
+    let unmount = step(&script, "unmount");
+    assert!(unmount.ends_with("\n! mountpoint -q /mnt || umount -R /mnt"));
+    let log = run_twice(unmount, &[
+        ("mountpoint", "! [ -e \"$STATE/unmounted\" ]"),
+        ("umount", "echo \"umount $*\" >>\"$STATE/log\"; touch \"$STATE/unmounted\""),
+    ]);
+    assert_eq!(log, "umount -R /mnt\n");
+}
+
+#[test]
+fn explained()
+{
+    let sample = reruns("");
+    let rerun = |name: &str| sample.iter().find(|(step, _)| step == name).unwrap().1.clone();
+    let idempotent = "Running it again leaves the system as running it once does, so a failed installation can be resumed from it.";
+    let destructive = "Running it again wipes out what was done since, so a failed installation can't be resumed from it, only started over.";
+    let not_idempotent = "Running it again isn't safe: some of it would fail, or be done twice.";
+    for name in ["preflight", "previous mounts", "mounting", "pacstrap", "fstab", "chroot script", "configuration", "verification", "unmount"] {
+        assert_eq!(rerun(name), idempotent, "{}", name);
+    }
+    assert_eq!(rerun("partitioning"), destructive);
+    assert_eq!(rerun("formatting"), destructive);
+    assert!(sample.iter().all(|(_, r)| [idempotent, destructive, not_idempotent].contains(&r.as_str())));
+
+    // efibootmgr creates the boot entry again when the old ones are kept
+    let efistub = reruns("bootloader: efistub\nkeep_existing_entries: true\n");
+    assert_eq!(efistub.iter().find(|(step, _)| step == "configuration").unwrap().1, not_idempotent);
+    let efistub = reruns("bootloader: efistub\n");
+    assert_eq!(efistub.iter().find(|(step, _)| step == "configuration").unwrap().1, idempotent);
+}
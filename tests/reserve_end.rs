@@ -0,0 +1,106 @@
+//! Checks `reserve_end`: the size the partition that takes the rest of a disk is given, worked out
+//! by jimmy when the size of the disk is declared, and by the script otherwise, which is ran with
+//! sh and blockdev replaced by a program that prints the size of a disk
+
+use std::os::unix::fs::PermissionsExt;
+use std::process::{Command, Output};
+
+mod common;
+
+/// Generate the script from the sample configuration file, with the given lines appended
+fn generate(extra_lines: &str) -> Output
+{
+    common::generate(&["--file"], &[], extra_lines)
+}
+
+/// Return the script, checking that jimmy succeeded
+fn generated(extra_lines: &str) -> String
+{
+    common::script(generate(extra_lines))
+}
+
+/// Return the line of the script that partitions /dev/sda
+fn fdisk_line(script: &str) -> &str
+{
+    script.lines().find(|l| l.ends_with("| fdisk /dev/sda >/dev/null 2>&1 || exit 1")).unwrap()
+}
+
+/// Run the commands of the script that work out the size of the rest of the disk, from their
+/// comment to the fdisk command, on a disk of `bytes`, and return whether they succeeded along
+/// with what they printed
+fn run(script: &str, bytes: u64) -> (bool, String, String)
+{
+    let start = script.find("# the partition that takes the rest of /dev/sda").unwrap();
+    let end = start + script[start..].find("\nfi\n").unwrap() + "\nfi\n".len();
+    let dir = common::temp_path("dir");
+    std::fs::create_dir_all(&dir).unwrap();
+    std::fs::write(dir.join("blockdev"), format!("#!/bin/sh\n[ \"$1 $2\" = '--getsize64 /dev/sda' ] && echo {}\n", bytes)).unwrap();
+    std::fs::set_permissions(dir.join("blockdev"), std::fs::Permissions::from_mode(0o755)).unwrap();
+    let code = format!("{}echo \"$jimmy_rest_mib\"", &script[start..end]);
+    let output = Command::new("sh").args(["-c", &code])
+        .env("PATH", format!("{}:{}", dir.display(), std::env::var("PATH").unwrap()))
+        .output()
+        .unwrap();
+    std::fs::remove_dir_all(&dir).unwrap();
+    (output.status.success(), String::from_utf8(output.stdout).unwrap(), String::from_utf8(output.stderr).unwrap())
+}
+
+#[test]
+fn declared_disk()
+{
+    let script = generated("disks:\n  /dev/sda:\n    size: 64GB\n    reserve_end: 8G\n");
+    // 61033M can be partitioned, of which the EFI system partition takes 500M
+    assert!(fdisk_line(&script).contains("\\nn\\n2\\n\\n+52341M\\n"), "{}", fdisk_line(&script));
+    assert!(script.contains("\n# disk /dev/sda is 64GB, of which 59.6G can be partitioned; \
+        the partition mounted at / gets the remaining 51.1G (at least 8G expected), and 8G is left free at its end\n"));
+    assert!(!script.contains("blockdev"));
+}
+
+#[test]
+fn runtime()
+{
+    let script = generated("disks:\n  /dev/sda:\n    reserve_end: 10G\n");
+    assert!(fdisk_line(&script).contains("\\nn\\n2\\n\\n+${jimmy_rest_mib}M\\n"));
+    assert!(script.contains("\njimmy_check util-linux fdisk lsblk findmnt mountpoint blockdev\n"));
+    // a 64GB disk
+    assert_eq!(run(&script, 64_000_000_000), (true, "50293\n".to_string(), String::new()));
+    let (success, _, stderr) = run(&script, 8 << 30);
+    assert!(!success);
+    assert_eq!(stderr, "error: disk /dev/sda is 8192M, which leaves no space for the partition that takes the rest of it once 10G is reserved at its end\n");
+}
+
+#[test]
+fn without_reserve_end()
+{
+    let script = generated("");
+    assert!(fdisk_line(&script).contains("\\nn\\n2\\n\\n\\nt\\n2\\n"));
+    assert!(!script.contains("jimmy_rest_mib"));
+}
+
+#[test]
+fn too_large()
+{
+    let output = generate("disks:\n  /dev/sda:\n    size: 16G\n    reserve_end: 16G\n");
+    assert!(!output.status.success());
+    assert!(String::from_utf8_lossy(&output.stderr).contains("the partitions on disk /dev/sda need 500M, and 16G is reserved at its end, \
+        but only 16.0G of its 16G can be partitioned; make them smaller, or reserve less"));
+
+    let output = generate("disks:\n  /dev/sda:\n    reserve_end: ten gigs\n");
+    assert!(!output.status.success());
+    assert!(String::from_utf8_lossy(&output.stderr).contains("invalid reserve_end for disk /dev/sda: \"ten gigs\" (expected e.g. '10G')"));
+}
+
+#[test]
+fn no_effect()
+{
+    let output = generate("disks:\n  /dev/sdc:\n    reserve_end: 10G\n");
+    assert!(output.status.success());
+    assert!(String::from_utf8_lossy(&output.stderr)
+        .contains("warning: disk /dev/sdc has `reserve_end`, but there are no partitions on it; it's going to be ignored"));
+
+    let output = common::generate(&["--file"], &[("    mount: /\n", "    mount: /\n    size: 40G\n")], "disks:\n  /dev/sda:\n    reserve_end: 10G\n");
+    assert!(output.status.success());
+    assert!(String::from_utf8_lossy(&output.stderr)
+        .contains("warning: disk /dev/sda has `reserve_end`, but none of its partitions takes the rest of it, so the end of the disk is left free anyway"));
+    assert!(!String::from_utf8(output.stdout).unwrap().contains("jimmy_rest_mib"));
+}
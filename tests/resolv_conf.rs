@@ -0,0 +1,108 @@
+//! Checks when the resolv.conf of the live system is copied onto the target system for the
+//! arch-chroot script, and that the target's own is put back after it. The commands of both steps
+//! are ran with the paths of the live and target systems moved into a temporary directory
+
+use std::process::Command;
+
+mod common;
+
+/// What the sample configuration file needs to download something from inside arch-chroot
+const PINS: &str = "package_pins:\n  linux: 6.6.1.arch1-1\n";
+
+/// Generate the script from the sample configuration file, with the given lines appended, and
+/// return it along with what `--verbose` printed
+fn generate(extra_lines: &str) -> (String, String)
+{
+    let output = common::generate(&["--verbose", "--file"], &[], extra_lines);
+    let stderr = String::from_utf8(output.stderr.clone()).unwrap();
+    (common::script(output), stderr)
+}
+
+/// Return the commands of the step whose status message starts with `status`
+fn step_cmds(script: &str, status: &str) -> String
+{
+    let start = script.find(&format!("\necho '<-> {}", status)).unwrap();
+    let cmds = script[start..].split_once("jimmy_step_start=$(date +%s)\n").unwrap().1;
+    cmds[..cmds.find("\njimmy_time '").unwrap()].to_string()
+}
+
+/// Run the given commands with /mnt, /etc and /run moved into `dir`
+fn run(dir: &std::path::Path, cmds: &str)
+{
+    let root = dir.display().to_string();
+    let cmds = cmds
+        .replace("/mnt/", &format!("{}/mnt/", root))
+        .replace(" /etc/", &format!(" {}/etc/", root))
+        .replace(" /run/", &format!(" {}/run/", root));
+    assert!(Command::new("sh").args(["-c", &cmds]).status().unwrap().success(), "{}", cmds);
+}
+
+/// Create the live and target systems in a new temporary directory
+fn systems() -> std::path::PathBuf
+{
+    let dir = common::temp_path("dir");
+    for d in ["mnt/etc", "etc", "run/systemd/resolve"] {
+        std::fs::create_dir_all(dir.join(d)).unwrap();
+    }
+    dir
+}
+
+#[test]
+fn only_when_the_chroot_needs_the_network()
+{
+    let (script, stderr) = generate("");
+    assert!(!stderr.contains("resolv.conf"), "{}", stderr);
+    assert!(!script.contains("resolv.conf.jimmy-backup"));
+    let (script, _) = generate("chroot_backend: nspawn\n");
+    assert!(script.contains(" --resolv-conf=bind-host "));
+
+    let (_, stderr) = generate(PINS);
+    assert!(stderr.contains("chroot script, resolv.conf, configuration, restore resolv.conf, cleanup"), "{}", stderr);
+    let (script, _) = generate(&format!("{}chroot_backend: nspawn\n", PINS));
+    // the copy isn't replaced by what systemd-nspawn would bind
+    assert!(script.contains(" --resolv-conf=off "));
+}
+
+#[test]
+fn keep_resolv_conf_overrides_the_default()
+{
+    let (_, stderr) = generate("keep_resolv_conf: true\n");
+    assert!(stderr.contains(", resolv.conf, configuration, restore resolv.conf,"), "{}", stderr);
+    let (script, stderr) = generate(&format!("{}keep_resolv_conf: false\n", PINS));
+    assert!(stderr.contains("warning: `keep_resolv_conf: false`, but package_pins needs the network inside arch-chroot"));
+    assert!(!script.contains("resolv.conf.jimmy-backup"));
+}
+
+#[test]
+fn a_linked_resolv_conf_is_put_back()
+{
+    let (script, _) = generate(PINS);
+    let dir = systems();
+    std::fs::write(dir.join("run/systemd/resolve/resolv.conf"), "nameserver 192.0.2.1\n").unwrap();
+    std::os::unix::fs::symlink("/run/systemd/resolve/stub-resolv.conf", dir.join("etc/resolv.conf")).unwrap();
+    std::os::unix::fs::symlink("/somewhere/else", dir.join("mnt/etc/resolv.conf")).unwrap();
+
+    run(&dir, &step_cmds(&script, "copying the resolv.conf"));
+    // the servers the stub forwards to are used, not the stub that may not be running
+    assert_eq!(std::fs::read_to_string(dir.join("mnt/etc/resolv.conf")).unwrap(), "nameserver 192.0.2.1\n");
+    run(&dir, &step_cmds(&script, "restoring the resolv.conf"));
+    assert_eq!(std::fs::read_link(dir.join("mnt/etc/resolv.conf")).unwrap().to_str(), Some("/somewhere/else"));
+    assert!(!dir.join("mnt/etc/resolv.conf.jimmy-backup").exists());
+    std::fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn otherwise_the_stub_of_systemd_resolved_is_linked()
+{
+    let (script, _) = generate(PINS);
+    let dir = systems();
+    std::fs::write(dir.join("etc/resolv.conf"), "nameserver 192.0.2.2\n").unwrap();
+    std::fs::write(dir.join("mnt/etc/resolv.conf"), "").unwrap();
+
+    run(&dir, &step_cmds(&script, "copying the resolv.conf"));
+    assert_eq!(std::fs::read_to_string(dir.join("mnt/etc/resolv.conf")).unwrap(), "nameserver 192.0.2.2\n");
+    run(&dir, &step_cmds(&script, "restoring the resolv.conf"));
+    assert_eq!(std::fs::read_link(dir.join("mnt/etc/resolv.conf")).unwrap().to_str(), Some("../run/systemd/resolve/stub-resolv.conf"));
+    assert_eq!(std::fs::read_dir(dir.join("mnt/etc")).unwrap().count(), 1);
+    std::fs::remove_dir_all(&dir).unwrap();
+}
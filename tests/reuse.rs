@@ -0,0 +1,149 @@
+//! Checks `reuse: true`: the partitions kept as they are across an installation, which the script
+//! checks with blkid before it changes anything, leaves out of partitioning and formatting, and
+//! mounts like the others, along with what's refused with them and how the plan and the header
+//! of the script point them out
+
+use std::process::Output;
+
+mod common;
+
+/// The configuration file of a reinstallation, with the given partitions after its boot and root
+/// partitions on /dev/sda and the given lines appended
+fn config(partitions: &str, extra_lines: &str) -> String
+{
+    format!("hostname: archlinux\nbootloader: grub\ndisk: /dev/sda\npartitions:\n  \
+        - boot:\n    format: fat32\n    mount: /boot\n    size: 500M\n  \
+        - root:\n    format: ext4\n    mount: /\n    size: 40G\n{}{}", partitions, extra_lines)
+}
+
+/// Run jimmy with `args` on the configuration file made by `config`
+fn generate(args: &[&str], partitions: &str, extra_lines: &str) -> Output
+{
+    common::jimmy(args, &config(partitions, extra_lines))
+}
+
+/// Return what jimmy printed, checking that it succeeded
+fn generated(args: &[&str], partitions: &str, extra_lines: &str) -> String
+{
+    common::script(generate(args, partitions, extra_lines))
+}
+
+const HOME: &str = "  - home:\n    format: ext4\n    mount: /home\n    reuse: true\n";
+
+const CHECK: &str = "# /dev/sda3 is reused as it is, so it has to hold the ext4 filesystem it's declared with
+jimmy_found=$(blkid -s TYPE -o value /dev/sda3) || jimmy_found=
+if [ \"$jimmy_found\" != ext4 ]; then
+    echo \"error: /dev/sda3 is reused for /home, but blkid finds ${jimmy_found:-no filesystem} on it instead of ext4; nothing was changed\" >&2
+    exit 1
+fi
+";
+
+#[test]
+fn verification()
+{
+    let script = generated(&["--file"], HOME, "");
+    assert!(script.contains(&format!("\n{}", CHECK)));
+    assert!(script.find(CHECK).unwrap() < script.find("\nprintf \"n\\n1\\n").unwrap());
+
+    // blkid finds the declared filesystem, another one, or none at all
+    let code = format!("{}echo 'the rest of the script'", CHECK);
+    assert_eq!(common::sh(&code, &[("blkid", "echo ext4")], ""), (true, "the rest of the script\n".to_string(), String::new()));
+    assert_eq!(common::sh(&code, &[("blkid", "echo xfs")], ""), (
+        false,
+        String::new(),
+        "error: /dev/sda3 is reused for /home, but blkid finds xfs on it instead of ext4; nothing was changed\n".to_string(),
+    ));
+    assert_eq!(common::sh(&code, &[("blkid", "exit 2")], ""), (
+        false,
+        String::new(),
+        "error: /dev/sda3 is reused for /home, but blkid finds no filesystem on it instead of ext4; nothing was changed\n".to_string(),
+    ));
+
+    // the type blkid gives a FAT32 filesystem
+    let script = generated(&["--file"], "  - shared:\n    format: fat32\n    mount: /shared\n    reuse: true\n", "");
+    assert!(script.contains("\nif [ \"$jimmy_found\" != vfat ]; then\n"));
+}
+
+#[test]
+fn partitioning()
+{
+    let script = generated(&["--file"], HOME, "");
+    assert!(script.contains("\n# /dev/sda keeps its partition table: partition 3 on it is reused as it is, and partitions 1 and 2 replaced
+for jimmy_number in 1 2; do
+    if sfdisk --part-type /dev/sda \"$jimmy_number\" >/dev/null 2>&1; then
+        sfdisk --delete /dev/sda \"$jimmy_number\" >/dev/null || exit 1
+    fi
+done
+printf \"n\\n1\\n\\n+500M\\nt\\n1\\nuefi\\nn\\n2\\n\\n+40G\\nt\\n2\\n4F68BCE3-E8CD-4DB1-96E7-FBCAF984B709\\nw\\n\" | fdisk /dev/sda >/dev/null 2>&1 || exit 1\n"));
+    assert!(!script.contains("\"g\\n"));
+    assert!(!script.contains("/dev/sda3\n") && !script.contains("mkfs.ext4 /dev/sda3"));
+    assert!(script.contains("mount /dev/sda3 /mnt/home"));
+    assert!(script.contains("\njimmy_check util-linux fdisk lsblk findmnt mountpoint blkid sfdisk\n"));
+
+    // only the partitions that are there are deleted, and fdisk is given what creates them again
+    let sfdisk = "echo \"sfdisk $*\" >>\"$DIR/log\"\n[ \"$1\" != --part-type ] || [ \"$3\" = 1 ]";
+    let fdisk = "echo \"fdisk $*\" >>\"$DIR/log\"\ncat >>\"$DIR/log\"";
+    let from = script.find("\nfor jimmy_number in").unwrap() + 1;
+    let end = "| fdisk /dev/sda >/dev/null 2>&1 || exit 1\n";
+    let to = from + script[from..].find(end).unwrap() + end.len();
+    let code = format!("{}cat \"$DIR/log\"", &script[from..to]);
+    assert_eq!(common::sh(&code, &[("sfdisk", sfdisk), ("fdisk", fdisk)], ""), (
+        true,
+        "sfdisk --part-type /dev/sda 1\nsfdisk --delete /dev/sda 1\nsfdisk --part-type /dev/sda 2\nfdisk /dev/sda\n\
+            n\n1\n\n+500M\nt\n1\nuefi\nn\n2\n\n+40G\nt\n2\n4F68BCE3-E8CD-4DB1-96E7-FBCAF984B709\nw\n".to_string(),
+        String::new(),
+    ));
+
+    // a disk whose partitions are all reused is left alone
+    let script = generated(&["--file"], &format!("{}  - data:\n    format: btrfs\n    mount: /data\n    disk: /dev/sdb\n    reuse: true\n", HOME),
+        "parallel_format: true\n");
+    assert!(script.contains("\n# /dev/sdb keeps its partition table, and partition 1 on it is reused as it is\n"));
+    assert!(!script.contains("fdisk /dev/sdb") && !script.contains("/dev/sdb: "));
+}
+
+#[test]
+fn plan()
+{
+    let response = common::api_plan(&config(HOME, ""));
+    let reused: Vec<&serde_json::Value> = response["plan"]["partitions"].as_array().unwrap().iter().map(|p| &p["reused"]).collect();
+    assert_eq!(reused, [false, false, true]);
+
+    let summary = generated(&["summarize"], HOME, "");
+    assert!(summary.contains("\n/dev/sda  3  ext4    as it is  /home  -           PRESERVED\n"), "{}", summary);
+    assert!(summary.contains("\n/dev/sda  1  fat32   500M      /boot  -           erased\n"), "{}", summary);
+    assert!(!generated(&["summarize"], "", "").contains("PRESERVED"));
+
+    for args in [["--file"], ["chroot-script"]] {
+        let script = generated(&args, HOME, "");
+        let header: Vec<&str> = script.lines().take_while(|l| !l.is_empty()).collect();
+        assert!(header.contains(&"# reused partitions, kept as they are: /dev/sda3 (ext4, /home)"), "{:?}", header);
+    }
+    assert!(!generated(&["--file"], "", "").contains("# reused partitions"));
+}
+
+#[test]
+fn invalid()
+{
+    for (partitions, extra_lines, message) in [
+        ("  - home:\n    format: ext4\n    mount: /home\n    size: 200G\n    reuse: true\n", "",
+            "a partition marked `reuse: true` can't be given a `size`, since it's kept as it is; remove it"),
+        ("  - home:\n    format: ext4\n    reuse: true\n", "",
+            "a partition marked `reuse: true` needs a mount point, since it's kept for the new system to use it"),
+        ("  - home:\n    format: ext4\n    mount: /home\n    mkfs_args: [ -L, home ]\n    reuse: true\n", "",
+            "the partition mounted at /home is reused, so it's neither formatted nor changed; remove `mkfs_args`"),
+        ("  - home:\n    format: xfs\n    mount: /home\n    reuse: true\n", "",
+            "invalid format for the reused partition mounted at /home: \"xfs\" (expected one of: ext2, ext3, ext4, fat32, btrfs, ntfs, exfat)"),
+        (&format!("{}  - data:\n    format: ext4\n    mount: /data\n    size: 8G\n", HOME), "",
+            "partition 4 on /dev/sda is new, but it comes after partition 3, which is reused; on a disk whose partition table is kept, the new partitions come first"),
+        (HOME, "disks:\n  /dev/sda:\n    size: 256G\n",
+            "disk /dev/sda is declared under `disks`, but partition 3 on it is reused, so its partition table is kept and the space left on it isn't known; remove it from `disks`"),
+    ] {
+        let output = generate(&["--file"], partitions, extra_lines);
+        assert!(!output.status.success(), "{}", message);
+        assert!(String::from_utf8_lossy(&output.stderr).contains(message), "{}: {}", message, String::from_utf8_lossy(&output.stderr));
+    }
+
+    // the new system is installed on the root partition
+    let root = config("", "").replace("    mount: /\n    size: 40G\n", "    mount: /\n    reuse: true\n");
+    assert!(common::refusal(common::jimmy(&["--file"], &root)).contains("the root partition can't be reused, since the new system is installed on it; remove `reuse: true`"));
+}
@@ -0,0 +1,136 @@
+//! Checks how the password of root is set with each `root_password_policy`. The commands of the
+//! fallback are ran against a fake `passwd`, which records how it's called, with the file of the
+//! warnings moved into a temporary directory
+
+use std::os::unix::fs::PermissionsExt;
+use std::process::{Command, Output};
+
+mod common;
+
+/// A hash of a password, in the format `openssl passwd -6` makes them in
+const HASH: &str = "$6$q2CtoUxKNqRcEjlu$WN9K9Pl4ZBrvoh9/VX.JPUrMEJ0VkILFjtHLLPw6CfCqfnL5V.OLOMp6bN1y2HQp7BMOkVqDEsZ/3aOnMwoYw/";
+
+/// Generate the full script and the arch-chroot script from the sample configuration file, with
+/// the given lines appended
+fn generate(extra_lines: &str) -> (Output, Output)
+{
+    let config = common::config(&[], extra_lines);
+    (common::jimmy(&["--file"], &config), common::jimmy(&["chroot-script"], &config))
+}
+
+/// Return the commands that set the password of root in the arch-chroot script
+fn root_password_cmds(extra_lines: &str) -> String
+{
+    let script = common::script(generate(extra_lines).1);
+    let start = script.find("echo '<chroot> making the wheel group").unwrap();
+    let section = script[..start].trim_end();
+    let begin = section.rfind("\n\necho '<chroot> ").unwrap() + 2;
+    section[begin..].split_once('\n').unwrap().1.to_string()
+}
+
+/// Return what jimmy complains about when it refuses the configuration file
+fn refusal(extra_lines: &str) -> String
+{
+    common::refusal(generate(extra_lines).0)
+}
+
+/// Run the commands of the fallback with a fake `passwd` that fails the given number of times,
+/// and return how it was called and the warnings left behind
+fn run_fallback(cmds: &str, failures: u32) -> (String, String)
+{
+    let dir = common::temp_path("dir");
+    std::fs::create_dir_all(dir.join("bin")).unwrap();
+    let fake = dir.join("bin/passwd");
+    std::fs::write(&fake, format!(
+        "#!/bin/sh\necho passwd $* >>{dir}/calls\n[ $# -ne 0 ] && exit 0\n[ \"$(grep -cx passwd {dir}/calls)\" -gt {} ]\n",
+        failures,
+        dir = dir.display(),
+    )).unwrap();
+    std::fs::set_permissions(&fake, std::fs::Permissions::from_mode(0o755)).unwrap();
+    let cmds = cmds.replace("/var/log/jimmy", &dir.join("log").display().to_string());
+    let path = format!("{}:{}", dir.join("bin").display(), std::env::var("PATH").unwrap_or_default());
+    assert!(Command::new("sh").args(["-c", &cmds]).env("PATH", path).status().unwrap().success());
+    let results = (
+        std::fs::read_to_string(dir.join("calls")).unwrap(),
+        std::fs::read_to_string(dir.join("log/warnings.txt")).unwrap_or_default(),
+    );
+    std::fs::remove_dir_all(&dir).unwrap();
+    results
+}
+
+#[test]
+fn prompt_by_default()
+{
+    assert_eq!(root_password_cmds(""), "while true; do if passwd; then break; fi; done");
+    let script = common::script(generate("").0);
+    assert!(!script.contains("jimmy_warnings"));
+    assert!(!script.contains("warnings.txt\n"));
+}
+
+#[test]
+fn fallback_locks_root_after_the_attempts()
+{
+    let cmds = root_password_cmds("root_password_policy: prompt-with-fallback\nroot_password_attempts: 2\n");
+    let (calls, warnings) = run_fallback(&cmds, 5);
+    assert_eq!(calls, "passwd\npasswd\npasswd -l root\n");
+    assert_eq!(warnings, "the password of root was not set after 2 attempts, so root is locked; set it with passwd from the live system, in arch-chroot\n");
+
+    let (calls, warnings) = run_fallback(&cmds, 1);
+    assert_eq!(calls, "passwd\npasswd\n");
+    assert_eq!(warnings, "");
+}
+
+#[test]
+fn fallback_warnings_are_shown_at_the_end()
+{
+    let script = common::script(generate("root_password_policy: prompt-with-fallback\ncleanup: remove-all\n").0);
+    let read = script.find("\njimmy_warnings=$(cat /mnt/var/log/jimmy/warnings.txt 2>/dev/null)\n").unwrap();
+    let removal = script.find("/mnt/var/log/jimmy/warnings.txt\nrmdir").expect("the warnings aren't removed");
+    let shown = script.find("printf '%s\\n' \"$jimmy_warnings\" | sed 's/^/warning: /' >&2").unwrap();
+    assert!(read < removal && removal < shown);
+    assert!(script.find("\n! mountpoint -q /mnt || umount -R /mnt\n").unwrap() < shown);
+    assert_eq!(root_password_cmds("root_password_policy: prompt-with-fallback\n").matches("-ge 3 ]").count(), 1);
+}
+
+#[test]
+fn locked()
+{
+    let (full, chroot) = generate("root_password_policy: locked\n");
+    assert!(!String::from_utf8_lossy(&full.stderr).contains("nobody can log in"));
+    assert!(common::script(chroot).contains("\necho '<chroot> locking the root user...'\npasswd -l root\n"));
+    let (full, _) = generate("root_password_policy: locked\nusers: []\n");
+    assert!(String::from_utf8_lossy(&full.stderr)
+        .contains("root is locked and there are no `users`, so nobody can log in to the installed system"));
+}
+
+#[test]
+fn hash()
+{
+    let expected = format!("usermod -p '{}' root", HASH);
+    let given = format!("root_password_hash: \"{}\"\n", HASH);
+    assert_eq!(root_password_cmds(&given), expected);
+    assert_eq!(root_password_cmds(&format!("root_password_policy: hash\n{}", given)), expected);
+}
+
+#[test]
+fn hash_and_policy_must_agree()
+{
+    assert!(refusal("root_password_policy: hash\n")
+        .contains("`root_password_policy: hash` needs a `root_password_hash`"));
+    assert!(refusal(&format!("root_password_policy: locked\nroot_password_hash: \"{}\"\n", HASH))
+        .contains("root_password_hash is only used with `root_password_policy: hash`, not `locked`"));
+    let stderr = refusal("root_password_hash: hunter2\n");
+    assert!(stderr.contains("invalid root_password_hash"));
+    assert!(!stderr.contains("hunter2"));
+    assert!(refusal("root_password_policy: ask\n")
+        .contains("invalid root_password_policy: \"ask\" (expected one of: prompt, prompt-with-fallback, locked, hash)"));
+}
+
+#[test]
+fn attempts_only_go_with_the_fallback()
+{
+    let (full, _) = generate("root_password_attempts: 5\n");
+    assert!(String::from_utf8_lossy(&full.stderr).contains("root_password_attempts is only used with `root_password_policy: prompt-with-fallback`"));
+    assert!(refusal("root_password_policy: prompt-with-fallback\nroot_password_attempts: 0\n")
+        .contains("root_password_attempts must be at least 1"));
+}
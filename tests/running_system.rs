@@ -0,0 +1,56 @@
+//! Checks the check at the top of the script that stops it when one of the disks it partitions
+//! hosts the running system: the disks lsblk finds under the source of `/`, whatever sits between
+//! them, and the ways of going on anyway
+
+mod common;
+
+/// `lsblk -s` on a root filesystem on the second partition of /dev/sda
+const PARTITION: &str = "/dev/sda2 part\n/dev/sda disk";
+
+/// `lsblk -s` on a root filesystem on LUKS on a logical volume spread over /dev/sdb and /dev/sda
+const STACKED: &str = "/dev/mapper/root crypt\n/dev/mapper/vg-root lvm\n/dev/sdb1 part\n/dev/sdb disk\n/dev/sda3 part\n/dev/sda disk";
+
+/// Return the shell code of the check, from the script of the sample configuration file
+fn check() -> String
+{
+    let script = common::script(common::generate(&["--file"], &[], ""));
+    let from = script.find("case \" $* \" in\n    *\" --i-know-what-i-am-doing \"*").unwrap();
+    let end = "\n    done\ndone\n";
+    let to = script[from..].find(end).unwrap() + from + end.len();
+    script[from..to].to_string()
+}
+
+/// Run the check with the given arguments and environment, with `lsblk -s` answering `lsblk` on
+/// the source findmnt gives for `/`, and return whether the script would go on and what it printed
+fn run(args: &str, env: &str, source: &str, lsblk: &str) -> (bool, String)
+{
+    let code = format!("set -- {}\n{}{}echo go on", args, env, check());
+    let findmnt = format!("[ \"$*\" = '-nvo SOURCE /' ] && echo {}", source);
+    let lsblk = format!("[ \"$1 $2\" = '-nsrpo NAME,TYPE' ] && [ \"$3\" = {} ] && printf '%s\\n' '{}'", source, lsblk.replace('\n', "' '"));
+    let (success, stdout, stderr) = common::sh(&code, &[("findmnt", &findmnt), ("lsblk", &lsblk), ("readlink", "echo \"$2\"")], "");
+    (success && stdout == "go on\n", stderr)
+}
+
+const ERROR: &str = "error: /dev/sda hosts the running system; set JIMMY_FORCE=1 or pass --i-know-what-i-am-doing to continue anyway\n";
+
+#[test]
+fn parentage()
+{
+    assert_eq!(run("", "", "/dev/sda2", PARTITION), (false, ERROR.to_string()));
+    // whatever is stacked between the filesystem and the disks, any of them is enough
+    assert_eq!(run("", "", "/dev/mapper/root", STACKED), (false, ERROR.to_string()));
+    assert_eq!(run("", "", "/dev/mapper/root", &STACKED.replace("/dev/sda", "/dev/sdc")), (true, String::new()));
+    assert_eq!(run("", "", "/dev/nvme0n1p2", "/dev/nvme0n1p2 part\n/dev/nvme0n1 disk"), (true, String::new()));
+    // the live environment, whose root filesystem isn't on a disk
+    assert_eq!(run("", "", "airootfs", "airootfs loop"), (true, String::new()));
+    assert_eq!(run("", "", "overlay", ""), (true, String::new()));
+}
+
+#[test]
+fn going_on_anyway()
+{
+    assert_eq!(run("--i-know-what-i-am-doing", "", "/dev/sda2", PARTITION), (true, String::new()));
+    assert_eq!(run("--allow-install-medium --i-know-what-i-am-doing", "", "/dev/sda2", PARTITION), (true, String::new()));
+    assert_eq!(run("", "JIMMY_FORCE=1\n", "/dev/sda2", PARTITION), (true, String::new()));
+    assert_eq!(run("--i-know-what-i-am", "JIMMY_FORCE=0\n", "/dev/sda2", PARTITION), (false, ERROR.to_string()));
+}
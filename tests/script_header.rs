@@ -0,0 +1,59 @@
+//! Checks the comments both scripts start with, which tell what generated them and from what
+
+mod common;
+
+/// The lines every header has, whether the script is reproducible or not
+const FIELDS: &[&str] = &[
+    "# configuration format version: 2",
+    "# bootloader: systemd-boot, kernel: linux-lts",
+    "# disks: /dev/sdb, /dev/sda",
+    "# the system is configured with arch-chroot",
+];
+
+/// Return the headers of the full script and of the arch-chroot script, generated with the given
+/// extra arguments from the sample configuration file, put on two disks
+fn headers(args: &[&str]) -> (Vec<String>, Vec<String>)
+{
+    let config = common::config(&[
+        ("\ndisk: /dev/sda\n", "\ndisk: /dev/sdb\n"),
+        ("    mount: /\n", "    mount: /\n    disk: /dev/sda\n"),
+        ("bootloader: grub\n", "bootloader: systemd-boot\n"),
+        ("kernel: latest\n", "kernel: lts\n"),
+    ], "");
+    let header = |command: &str| {
+        common::script(common::jimmy(&common::with_flags(args, &[command]), &config)).lines()
+            .take_while(|l| !l.is_empty())
+            .map(String::from)
+            .collect::<Vec<String>>()
+    };
+    (header("--file"), header("chroot-script"))
+}
+
+/// Check that the header has every field exactly once, and the timestamp only if `timestamp`
+fn check(header: &[String], what: &str, timestamp: bool)
+{
+    assert_eq!(header[0], "#!/bin/sh");
+    assert!(header[1].starts_with(&format!("# {} automatically generated by jimmy-rs ", what)), "{:?}", header);
+    for field in FIELDS.iter().chain(["# configuration hash: "].iter()) {
+        assert_eq!(header.iter().filter(|l| l.starts_with(field)).count(), 1, "{:?} in {:?}", field, header);
+    }
+    assert_eq!(header.iter().filter(|l| l.starts_with("# generated at ")).count(), timestamp as usize, "{:?}", header);
+    assert!(header.iter().all(|l| l.starts_with('#')));
+}
+
+#[test]
+fn both_scripts_have_every_field_once()
+{
+    let (full, chroot) = headers(&[]);
+    check(&full, "installation script", true);
+    check(&chroot, "arch-chroot script", true);
+}
+
+#[test]
+fn reproducible_scripts_have_no_timestamp()
+{
+    let (full, chroot) = headers(&["--reproducible"]);
+    check(&full, "installation script", false);
+    check(&chroot, "arch-chroot script", false);
+    assert_eq!(full[2..], chroot[2..]);
+}
@@ -0,0 +1,160 @@
+//! Checks the EFI system partitions that are kept in sync with the primary one: their boot entries,
+//! when they're copied onto, and the script and pacman hook that do it. The script is ran against
+//! fake `mount`, `umount` and `rsync`, which record how they're called
+
+use std::os::unix::fs::PermissionsExt;
+use std::process::{Command, Output, Stdio};
+
+mod common;
+
+/// A secondary EFI system partition on /dev/sdb, as big as the primary one of the sample
+const SECONDARY: &str = "  - mirror:\n    format: fat32\n    esp: true\n    size: 500M\n    disk: /dev/sdb\n";
+
+/// Generate the arch-chroot script from the sample configuration file, with the given partitions
+/// added after its boot partition and the other replacements made to it
+fn generate(partitions: &str, replacements: &[(&str, &str)]) -> Output
+{
+    let partitions = format!("    size: 500M\n{}", partitions);
+    let mut all = vec![("    size: 500M\n", partitions.as_str())];
+    all.extend_from_slice(replacements);
+    common::generate(&["chroot-script", "--width", "0"], &all, "")
+}
+
+/// Return the arch-chroot script, checking that jimmy succeeded
+fn chroot_script(partitions: &str, replacements: &[(&str, &str)]) -> String
+{
+    common::script(generate(partitions, replacements))
+}
+
+/// Return what jimmy complains about when it refuses the configuration file
+fn refusal(partitions: &str, replacements: &[(&str, &str)]) -> String
+{
+    common::refusal(generate(partitions, replacements))
+}
+
+/// Return the lines of the script that create boot entries
+fn entries(script: &str) -> Vec<&str>
+{
+    script.lines().filter(|l| l.starts_with("efibootmgr --disk ")).collect()
+}
+
+/// Return the part of the script between the status message with `message` and the next one
+fn section<'a>(script: &'a str, message: &str) -> &'a str
+{
+    let start = script.find(&format!("echo '<chroot> {}'\n", message)).unwrap();
+    let end = script[start..].find("\n\necho '<chroot> ").map(|e| start + e).unwrap_or(script.len());
+    &script[start..end]
+}
+
+#[test]
+fn boot_entries()
+{
+    let script = chroot_script(SECONDARY, &[("bootloader: grub\n", "bootloader: systemd-boot\n")]);
+    assert_eq!(entries(&script), [
+        "efibootmgr --disk /dev/sdb --part 1 --create --label 'Arch Linux (ESP 2)' --loader '\\EFI\\systemd\\systemd-bootx64.efi' --verbose",
+    ]);
+    assert!(script.contains("JIMMY_LABEL='Arch Linux (ESP 2)' awk"));
+    // the bootloader's own entry is created last, so that it comes first in the boot order
+    assert!(script.find("efibootmgr --disk /dev/sdb").unwrap() < script.find("\nbootctl install ").unwrap());
+
+    let third = SECONDARY.replace("mirror", "third").replace("/dev/sdb", "/dev/sdc");
+    let script = chroot_script(&format!("{}{}", SECONDARY, third), &[
+        ("bootloader: grub\n", "bootloader: efistub\nboot_entry_label: Arch\nkeep_existing_entries: true\n"),
+    ]);
+    assert_eq!(entries(&script), [
+        "efibootmgr --disk /dev/sdb --part 1 --create --label 'Arch (ESP 2)' --loader /vmlinuz-linux --unicode 'root=/dev/sda2 rw initrd=\\initramfs-linux.img' --verbose",
+        "efibootmgr --disk /dev/sdc --part 1 --create --label 'Arch (ESP 3)' --loader /vmlinuz-linux --unicode 'root=/dev/sda2 rw initrd=\\initramfs-linux.img' --verbose",
+        "efibootmgr --disk /dev/sda --part 1 --create --label Arch --loader /vmlinuz-linux --unicode 'root=/dev/sda2 rw initrd=\\initramfs-linux.img' --verbose",
+    ]);
+    assert!(!script.contains("JIMMY_LABEL="));
+    assert!(section(&script, "adding the boot entries of the secondary EFI system partitions...").contains(
+        "echo \"/dev/disk/by-partuuid/$(blkid -s PARTUUID -o value /dev/sdb1)\" >/etc/jimmy/secondary-esps\n\
+        echo \"/dev/disk/by-partuuid/$(blkid -s PARTUUID -o value /dev/sdc1)\" >>/etc/jimmy/secondary-esps\n"));
+}
+
+#[test]
+fn synced_after_everything_else()
+{
+    let script = chroot_script(SECONDARY, &[]);
+    assert!(script.contains("--label 'Arch Linux (ESP 2)' --loader '\\EFI\\GRUB\\grubx64.efi' --verbose\n"));
+    let sync = script.find("echo '<chroot> copying the EFI system partition onto the secondary ones...'\n/usr/local/lib/jimmy/sync-esps\n").unwrap();
+    assert!(script.find("\ngrub-mkconfig -o /boot/grub/grub.cfg\n").unwrap() < sync);
+    assert!(script[sync..].lines().filter(|l| l.starts_with("echo '<chroot> ")).count() == 2);
+    assert!(!script.contains("/etc/pacman.d/hooks/zz-jimmy-sync-esps.hook"));
+
+    let script = chroot_script(SECONDARY, &[("bootloader: grub\n", "bootloader: efistub\n")]);
+    let sync = script.find("\n/usr/local/lib/jimmy/sync-esps\n").unwrap();
+    assert!(script.find("efibootmgr --disk /dev/sda ").unwrap() < sync);
+}
+
+#[test]
+fn hook()
+{
+    let script = chroot_script(SECONDARY, &[("bootloader: grub\n", "bootloader: grub\nesp_sync_hook: true\n")]);
+    let hook = section(&script, "adding the boot entries of the secondary EFI system partitions...");
+    assert!(hook.contains("\nmkdir -p /etc/pacman.d/hooks\ncat <<'END_OF_FILE' >/etc/pacman.d/hooks/zz-jimmy-sync-esps.hook\n[Trigger]\nType = Path\n"));
+    assert!(hook.contains("\nTarget = usr/lib/modules/*/vmlinuz\n"));
+    assert!(hook.contains("\nWhen = PostTransaction\nExec = /usr/local/lib/jimmy/sync-esps\nEND_OF_FILE\n"));
+
+    let output = generate("", &[("bootloader: grub\n", "bootloader: grub\nesp_sync_hook: true\n")]);
+    assert!(String::from_utf8_lossy(&output.stderr).contains("warning: esp_sync_hook is only used with secondary EFI system partitions"));
+    assert!(!String::from_utf8(output.stdout).unwrap().contains("sync-esps"));
+}
+
+#[test]
+fn sync_script()
+{
+    let script = chroot_script(SECONDARY, &[]);
+    let start = script.find("cat <<'END_OF_FILE' >/usr/local/lib/jimmy/sync-esps\n").unwrap();
+    let body = script[start..].split_once('\n').unwrap().1.split_once("END_OF_FILE\n").unwrap().0;
+
+    let dir = common::temp_path("run");
+    std::fs::create_dir_all(dir.join("bin")).unwrap();
+    for (program, behavior) in [("mount", "[ \"$1\" != /dev/bad ]"), ("umount", "true"), ("rsync", "true")] {
+        let fake = dir.join("bin").join(program);
+        std::fs::write(&fake, format!("#!/bin/sh\necho {} \"$@\" | sed 's|/tmp/tmp[.][A-Za-z0-9]*|TMP|g' >>{}/calls\n{}\n", program, dir.display(), behavior)).unwrap();
+        std::fs::set_permissions(&fake, std::fs::Permissions::from_mode(0o755)).unwrap();
+    }
+    let list = dir.join("list");
+    let body = body.replace("/etc/jimmy/secondary-esps", &list.display().to_string());
+    let path = format!("{}:{}", dir.join("bin").display(), std::env::var("PATH").unwrap_or_default());
+    let run = |devices: &str| {
+        std::fs::write(&list, devices).unwrap();
+        let _ = std::fs::remove_file(dir.join("calls"));
+        let status = Command::new("sh").args(["-c", &body]).env("PATH", &path).env("TMPDIR", "/tmp").stderr(Stdio::null()).status().unwrap();
+        (status.success(), std::fs::read_to_string(dir.join("calls")).unwrap())
+    };
+
+    let (success, calls) = run("/dev/disk/by-partuuid/one\n/dev/disk/by-partuuid/two\n");
+    assert!(success);
+    assert_eq!(calls, "mount /dev/disk/by-partuuid/one TMP\n\
+        rsync --recursive --times --modify-window=1 --delete /boot/ TMP/\n\
+        umount TMP\n\
+        mount /dev/disk/by-partuuid/two TMP\n\
+        rsync --recursive --times --modify-window=1 --delete /boot/ TMP/\n\
+        umount TMP\n");
+    // a partition that can't be mounted doesn't keep the others from being copied onto
+    let (success, calls) = run("/dev/bad\n/dev/disk/by-partuuid/two\n");
+    assert!(!success);
+    assert_eq!(calls.lines().filter(|l| l.starts_with("rsync ")).count(), 1);
+    std::fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn validation()
+{
+    assert!(refusal(&SECONDARY.replace("fat32", "ext4"), &[])
+        .contains("a partition marked `esp: true` must be formatted as 'fat32', not 'ext4'"));
+    assert!(refusal(&SECONDARY.replace("    esp: true\n", "    esp: true\n    mount: /efi\n"), &[("    mount: /boot\n", "    mount: /boot\n    esp: true\n")])
+        .contains("the partition mounted at /boot is marked `esp: true`, but the primary EFI system partition is the one mounted at /efi"));
+    assert!(refusal(&SECONDARY.replace("500M", "300M"), &[])
+        .contains("the secondary EFI system partition on /dev/sdb is smaller than the primary one; change its `size` from '300M' to at least '500M'"));
+    assert!(refusal(SECONDARY, &[("bootloader: grub\n", "bootloader: grub\nchroot_backend: nspawn\n")])
+        .contains("use `chroot_backend: arch-chroot`"));
+
+    let output = generate(&SECONDARY.replace("    disk: /dev/sdb\n", ""), &[]);
+    assert!(output.status.success());
+    let stderr = String::from_utf8(output.stderr).unwrap();
+    assert!(stderr.contains("warning: the secondary EFI system partition on /dev/sda is on the same disk as the primary one"), "{}", stderr);
+    assert!(stderr.contains("warning: GRUB on the secondary EFI system partitions reads its configuration from the primary one"));
+}
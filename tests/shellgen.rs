@@ -0,0 +1,270 @@
+//! Checks the shell code the scripts are made of that's written in a single place, rather than
+//! by each feature: that both scripts parse with `sh -n` whatever the configuration uses, that
+//! each of the loops, checks, prompts, heredocs and pipelines comes out as it should and parses on
+//! its own, and that they behave as they should when they're ran with sh, which is dash here,
+//! with the commands they run replaced by programs recording what they're given
+
+use std::process::{Command, Stdio};
+use std::io::Write;
+
+mod common;
+
+/// Run jimmy with `args` on the sample configuration file, with the given lines appended, and
+/// return what it printed, checking that it succeeded
+fn generated(args: &[&str], extra_lines: &str) -> String
+{
+    common::script(common::generate(args, &[], extra_lines))
+}
+
+/// Return the code from the line starting with `start` to the first line after it that's `end`
+fn block<'a>(script: &'a str, start: &str, end: &str) -> &'a str
+{
+    let from = script.find(&format!("\n{}", start)).unwrap() + 1;
+    let to = from + script[from..].find(&format!("\n{}\n", end)).unwrap() + end.len() + 2;
+    &script[from..to]
+}
+
+/// Check that `code` parses with `sh -n`
+fn assert_parses(code: &str)
+{
+    let mut child = Command::new("sh").arg("-n").stdin(Stdio::piped()).stderr(Stdio::piped()).spawn().unwrap();
+    child.stdin.take().unwrap().write_all(code.as_bytes()).unwrap();
+    let output = child.wait_with_output().unwrap();
+    assert!(output.status.success(), "{}: {}", code, String::from_utf8_lossy(&output.stderr));
+}
+
+/// A program that records that it was called, with its arguments, and fails the first `failures`
+/// times it is, for the commands that are ran again until they succeed
+fn failing(program: &str, failures: u32) -> String
+{
+    format!("echo {} \"$@\" >>\"$DIR/calls\"\n[ \"$(wc -l <\"$DIR/calls\")\" -gt {} ]", program, failures)
+}
+
+/// The configurations that use every piece of the shell code between them
+const VARIANTS: &[(&str, &str)] = &[
+    ("", "sample"),
+    ("root_password_policy: prompt-with-fallback\n", "fallback"),
+    ("default_editor: nvim\nfstab_extra:\n  - raw: tmpfs /scratch tmpfs defaults 0 0\n", "appended"),
+    ("bootstrap: tarball\n", "tarball"),
+    ("bootstrap: tarball\ntarball:\n  source: /root/bootstrap.tar.zst\n", "local-tarball"),
+    ("disks:\n  /dev/sda:\n    reserve_end: 10G\n", "reserve-end"),
+];
+
+#[test]
+fn syntax()
+{
+    for (lines, name) in VARIANTS {
+        for args in [&["--file"][..], &["chroot-script"], &["--shell", "bash", "--file"]] {
+            let script = generated(args, lines);
+            let shell = if args.contains(&"bash") { "bash" } else { "sh" };
+            let mut child = Command::new(shell).arg("-n").stdin(Stdio::piped()).stderr(Stdio::piped()).spawn().unwrap();
+            child.stdin.take().unwrap().write_all(script.as_bytes()).unwrap();
+            let output = child.wait_with_output().unwrap();
+            assert!(output.status.success(), "{} {:?}: {}", name, args, String::from_utf8_lossy(&output.stderr));
+        }
+    }
+}
+
+#[test]
+fn golden()
+{
+    let script = generated(&["--file"], "default_editor: nvim\nfstab_extra:\n  - raw: tmpfs /scratch tmpfs defaults 0 0\n");
+    for code in [
+        "\nprintf \"g\\nn\\n1\\n\\n+500M\\nt\\nuefi\\nn\\n2\\n\\n\\nt\\n2\\n4F68BCE3-E8CD-4DB1-96E7-FBCAF984B709\\nw\\n\" | fdisk /dev/sda >/dev/null 2>&1 || exit 1\n",
+        "\n    if ! command -v pacman >/dev/null; then\n        echo 'error: pacman is not available to install them; \
+            the script is meant to be ran from the Arch live environment' >&2\n        exit 1\n    fi\n",
+        "\n    printf '%s [Y/n] ' \"install$jimmy_packages on the live system with pacman?\"\n    read -r jimmy_answer\n    \
+            case \"$jimmy_answer\" in\n        [nN]*)\n            echo 'error: cannot continue without them' >&2\n            exit 1\n            ;;\n    esac\n",
+        "\ngrep -qxF 'tmpfs\t/scratch\ttmpfs\tdefaults\t0 0' /etc/fstab || cat <<'END_OF_FILE' >>/etc/fstab\ntmpfs\t/scratch\ttmpfs\tdefaults\t0 0\nEND_OF_FILE\n",
+        "\ngrep -qxF EDITOR=nvim /etc/environment || cat <<'END_OF_FILE' >>/etc/environment\nEDITOR=nvim\nEND_OF_FILE\n",
+        "\ncat <<'END_OF_FILE' >/etc/hosts\n127.0.0.1\tlocalhost\n::1\tlocalhost\n127.0.1.1\tarchlinux\nEND_OF_FILE\n",
+        "\nwhile true; do if passwd archie; then break; fi; done\n",
+        "\nprintf '\\n%s\\n' '<-> done; you may reboot now'\n",
+    ] {
+        assert!(script.contains(code), "{}", code);
+    }
+    assert!(!script.contains("echo -e") && !script.contains("&>"));
+}
+
+#[test]
+fn fdisk_answers()
+{
+    // what fdisk reads, one answer per line, with the size worked out by the script expanded
+    let fdisk = "cat >\"$DIR/answers\"\necho \"$1\" >>\"$DIR/answers\"";
+    for (lines, size) in [("", ""), ("disks:\n  /dev/sda:\n    reserve_end: 10G\n", "+50293M")] {
+        let script = generated(&["--file"], lines);
+        let cmd = script.lines().find(|l| l.ends_with("| fdisk /dev/sda >/dev/null 2>&1 || exit 1")).unwrap();
+        let code = format!("jimmy_rest_mib=50293\n{}\ncat \"$DIR/answers\"", cmd);
+        assert_eq!(common::sh(&code, &[("fdisk", fdisk)], ""), (
+            true,
+            format!("g\nn\n1\n\n+500M\nt\nuefi\nn\n2\n\n{}\nt\n2\n4F68BCE3-E8CD-4DB1-96E7-FBCAF984B709\nw\n/dev/sda\n", size),
+            String::new(),
+        ));
+    }
+}
+
+#[test]
+fn confirm()
+{
+    let script = generated(&["--file"], "");
+    let code = format!("jimmy_missing=' mkfs.btrfs'\njimmy_packages=' btrfs-progs'\n{}echo 'the rest of the script'",
+        block(&script, "if [ -n \"$jimmy_missing\" ]; then", "fi"));
+    let pacman = "echo \"pacman $*\"";
+    for (answer, name) in [("\n", "default"), ("y\n", "yes"), ("", "no-input")] {
+        assert_eq!(common::sh(&code, &[("pacman", pacman)], answer), (
+            true,
+            "missing programs: mkfs.btrfs\ninstall btrfs-progs on the live system with pacman? [Y/n] pacman -Sy --noconfirm btrfs-progs\nthe rest of the script\n".to_string(),
+            String::new(),
+        ), "{}", name);
+    }
+    for answer in ["n\n", "No\n"] {
+        assert_eq!(common::sh(&code, &[("pacman", pacman)], answer), (
+            false,
+            "missing programs: mkfs.btrfs\ninstall btrfs-progs on the live system with pacman? [Y/n] ".to_string(),
+            "error: cannot continue without them\n".to_string(),
+        ), "{}", answer);
+    }
+}
+
+#[test]
+fn append_once()
+{
+    let script = generated(&["chroot-script"], "default_editor: nvim\n");
+    let cmd = block(&script, "grep -qxF EDITOR=nvim /etc/environment", "END_OF_FILE").replace("/etc/environment", "\"$DIR/environment\"");
+    let code = format!("echo 'LANG=C' >\"$DIR/environment\"\n{0}{0}cat \"$DIR/environment\"", cmd);
+    assert_eq!(common::sh(&code, &[], ""), (true, "LANG=C\nEDITOR=nvim\n".to_string(), String::new()));
+}
+
+#[test]
+fn until_success()
+{
+    let script = generated(&["chroot-script"], "");
+    let code = script.lines().find(|l| l.starts_with("while true; do if passwd;")).unwrap();
+    assert_eq!(code, "while true; do if passwd; then break; fi; done");
+    assert_parses(code);
+    assert_eq!(common::sh(&format!("{}\ncat \"$DIR/calls\"", code), &[("passwd", &failing("passwd", 2))], ""),
+        (true, "passwd\npasswd\npasswd\n".to_string(), String::new()));
+}
+
+#[test]
+fn retry_at_most()
+{
+    let script = generated(&["chroot-script"], "root_password_policy: prompt-with-fallback\nroot_password_attempts: 2\n");
+    let code = block(&script, "jimmy_attempt=0", "done");
+    assert_eq!(code, "jimmy_attempt=0\n\
+        until passwd; do\n    \
+            jimmy_attempt=$((jimmy_attempt + 1))\n    \
+            if [ \"$jimmy_attempt\" -ge 2 ]; then\n        \
+                passwd -l root\n        \
+                mkdir -p /var/log/jimmy\n        \
+                echo 'the password of root was not set after 2 attempts, so root is locked; set it with passwd from the live system, in arch-chroot' >>/var/log/jimmy/warnings.txt\n        \
+                break\n    \
+            fi\n\
+        done\n");
+    assert_parses(code);
+
+    // the loop gives up after the last attempt, and the script goes on after it either way
+    let code = format!("{}echo 'the rest of the script'\ncat \"$DIR/calls\" \"$DIR/log/warnings.txt\" 2>/dev/null",
+        code.replace("/var/log/jimmy", "$DIR/log"));
+    let (_, stdout, _) = common::sh(&code, &[("passwd", &failing("passwd", 10))], "");
+    assert_eq!(stdout, "the rest of the script\npasswd\npasswd\npasswd -l root\n\
+        the password of root was not set after 2 attempts, so root is locked; set it with passwd from the live system, in arch-chroot\n");
+    let (_, stdout, _) = common::sh(&code, &[("passwd", &failing("passwd", 1))], "");
+    assert_eq!(stdout, "the rest of the script\npasswd\npasswd\n");
+
+    for attempts in [1, 3] {
+        let script = generated(&["chroot-script"], &format!("root_password_policy: prompt-with-fallback\nroot_password_attempts: {}\n", attempts));
+        let code = format!("{}cat \"$DIR/calls\"", block(&script, "jimmy_attempt=0", "done").replace("/var/log/jimmy", "$DIR/log"));
+        let (_, stdout, _) = common::sh(&code, &[("passwd", &failing("passwd", 10))], "");
+        assert_eq!(stdout, format!("{}passwd -l root\n", "passwd\n".repeat(attempts)), "{}", attempts);
+    }
+}
+
+#[test]
+fn require_command()
+{
+    // the check is made with `fail_if`
+    let script = generated(&["--file"], "");
+    let code = block(&script, "    if ! command -v pacman >/dev/null; then", "    fi");
+    assert_eq!(code, "    if ! command -v pacman >/dev/null; then\n        \
+        echo 'error: pacman is not available to install them; the script is meant to be ran from the Arch live environment' >&2\n        \
+        exit 1\n    \
+        fi\n");
+    assert_parses(code);
+
+    let code = format!("PATH=\"$DIR/bin\"\n{}echo 'the rest of the script'", code);
+    assert_eq!(common::sh(&code, &[("pacman", "")], ""), (true, "the rest of the script\n".to_string(), String::new()));
+    assert_eq!(common::sh(&code, &[], ""), (
+        false,
+        String::new(),
+        "error: pacman is not available to install them; the script is meant to be ran from the Arch live environment\n".to_string(),
+    ));
+}
+
+#[test]
+fn or_fail()
+{
+    let script = generated(&["--file"], "bootstrap: tarball\ntarball:\n  source: /root/bootstrap.tar.zst\n");
+    let code = block(&script, "[ -f \"$jimmy_tarball\" ] \\", "    || { echo 'error: the bootstrap tarball /root/bootstrap.tar.zst doesn'\\''t exist' >&2; exit 1; }");
+    assert_eq!(code, "[ -f \"$jimmy_tarball\" ] \\\n    || { echo 'error: the bootstrap tarball /root/bootstrap.tar.zst doesn'\\''t exist' >&2; exit 1; }\n");
+    assert_parses(code);
+
+    let code = format!("jimmy_tarball=\"$DIR/tarball\"\n{}echo 'the rest of the script'", code);
+    assert_eq!(common::sh(&format!(": >\"$DIR/tarball\"\n{}", code), &[], ""), (true, "the rest of the script\n".to_string(), String::new()));
+    assert_eq!(common::sh(&code, &[], ""),
+        (false, String::new(), "error: the bootstrap tarball /root/bootstrap.tar.zst doesn't exist\n".to_string()));
+}
+
+#[test]
+fn indent()
+{
+    // what's inside the block is a level further in, and blocks inside it two levels
+    let script = generated(&["--file"], "");
+    let code = block(&script, "if [ -n \"$jimmy_missing\" ]; then", "fi");
+    assert_parses(code);
+    let lines: Vec<&str> = code.lines().collect();
+    for line in &lines[1..lines.len() - 1] {
+        assert!(line.starts_with("    ") && !line.trim().is_empty(), "{:?}", line);
+    }
+    for line in ["        echo 'error: pacman is not available to install them; the script is meant to be ran from the Arch live environment' >&2",
+        "        [nN]*)", "            exit 1", "            ;;", "    esac"] {
+        assert!(lines.contains(&line), "{}", line);
+    }
+}
+
+#[test]
+fn shell_cmd()
+{
+    // the arguments are quoted once, whether the command is on a single line or wrapped between
+    // an option and its value
+    let lines = "locales: [en_US.UTF-8, de_DE.UTF-8]\n";
+    let single = generated(&["chroot-script", "--width", "0"], lines);
+    let single = single.lines().find(|l| l.starts_with("sed ")).unwrap();
+    assert_eq!(single, "sed --expression 's/^#en_US\\.UTF-8 /en_US.UTF-8 /' --expression 's/^#de_DE\\.UTF-8 /de_DE.UTF-8 /' --in-place /etc/locale.gen");
+    let script = generated(&["chroot-script", "--width", "60"], lines);
+    let wrapped = block(&script, "sed ", "    --in-place /etc/locale.gen");
+    assert_eq!(wrapped, "sed --expression 's/^#en_US\\.UTF-8 /en_US.UTF-8 /' \\\n    \
+        --expression 's/^#de_DE\\.UTF-8 /de_DE.UTF-8 /' \\\n    \
+        --in-place /etc/locale.gen\n");
+    let sed = "for arg in \"$@\"; do echo \"$arg\"; done";
+    for code in [single, wrapped] {
+        assert_parses(code);
+        assert_eq!(common::sh(code, &[("sed", sed)], ""), (
+            true,
+            "--expression\ns/^#en_US\\.UTF-8 /en_US.UTF-8 /\n--expression\ns/^#de_DE\\.UTF-8 /de_DE.UTF-8 /\n--in-place\n/etc/locale.gen\n".to_string(),
+            String::new(),
+        ));
+    }
+}
+
+#[test]
+fn heredoc_delimiter()
+{
+    // a line that's the delimiter would end the heredoc early, so another one is picked
+    let script = generated(&["chroot-script"], "motd: \"before\\nEND_OF_FILE\\nEND_OF_FILE_\\nafter\\n\"\n");
+    let code = block(&script, "cat <<'END_OF_FILE__' >/etc/motd", "END_OF_FILE__");
+    assert_eq!(code, "cat <<'END_OF_FILE__' >/etc/motd\nbefore\nEND_OF_FILE\nEND_OF_FILE_\nafter\nEND_OF_FILE__\n");
+    assert_parses(code);
+    let code = format!("{}cat \"$DIR/motd\"", code.replace("/etc/motd", "\"$DIR/motd\""));
+    assert_eq!(common::sh(&code, &[], ""), (true, "before\nEND_OF_FILE\nEND_OF_FILE_\nafter\n".to_string(), String::new()));
+}
@@ -0,0 +1,61 @@
+//! Checks that the `skel` directory is copied into /etc/skel before the users are created, and
+//! that users with `skip_skel` are created without it
+
+mod common;
+
+/// Generate the script from the sample configuration file, with the given replacements made to it
+/// and the given lines appended, and return what jimmy printed on stdout and stderr
+fn generate(replacements: &[(&str, &str)], extra_lines: &str) -> (bool, String, String)
+{
+    let output = common::generate(&["--verbose", "--file"], replacements, extra_lines);
+    (output.status.success(), String::from_utf8(output.stdout).unwrap(), String::from_utf8(output.stderr).unwrap())
+}
+
+#[test]
+fn copied_before_the_users_are_created()
+{
+    let (success, script, stderr) = generate(&[], "skel: /root/skel/\n");
+    assert!(success, "jimmy failed: {}", stderr);
+    assert!(stderr.contains("fstab, skel, chroot script, configuration"), "{}", stderr);
+    assert!(script.contains("\nmkdir -p /mnt/etc/skel\ncp -R --no-preserve=ownership /root/skel/. /mnt/etc/skel/\n"));
+    // the script stops before touching the disks if the directory isn't there
+    let check = script.find("\nif [ ! -d /root/skel ]; then\n").unwrap();
+    assert!(check < script.find("| fdisk ").unwrap());
+}
+
+#[test]
+fn nothing_is_copied_by_default()
+{
+    let (success, script, stderr) = generate(&[], "");
+    assert!(success, "jimmy failed: {}", stderr);
+    assert!(!stderr.contains("skel"));
+    assert!(!script.contains("/etc/skel"));
+    assert!(!script.contains(" -k /dev/null"));
+}
+
+#[test]
+fn skip_skel()
+{
+    let (success, script, stderr) = generate(&[("    groups: [ wheel ]\n", "    groups: [ wheel ]\n    skip_skel: true\n")],
+        "skel: /root/skel\n");
+    assert!(success, "jimmy failed: {}", stderr);
+    assert!(script.contains("\nid -u archie >/dev/null 2>&1 || useradd -m archie -k /dev/null "), "{}", script);
+}
+
+#[test]
+fn refused()
+{
+    for (skel, message) in [
+        ("skel", "skel must be the absolute path of a directory on the live system: \"skel\""),
+        ("//", "skel can't be the root directory of the live system"),
+    ] {
+        let (success, _, stderr) = generate(&[], &format!("skel: {}\n", skel));
+        assert!(!success);
+        assert!(stderr.contains(message), "{}", stderr);
+    }
+    let (success, _, stderr) = generate(&[
+        ("    groups: [ wheel ]\n", "    groups: [ wheel ]\n    skip_skel: true\n    home_encryption: true\n"),
+    ], "");
+    assert!(!success);
+    assert!(stderr.contains("user 'archie' has both skip_skel and home_encryption"), "{}", stderr);
+}
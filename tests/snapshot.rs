@@ -0,0 +1,111 @@
+//! Checks the installations from a snapshot of the Arch Linux Archive (`snapshot_date`) and the
+//! packages pinned at a version of their own (`package_pins`); the function that installs the
+//! pinned packages is ran against a fake `pacman`, which records the packages it's given
+
+use std::os::unix::fs::PermissionsExt;
+use std::process::{Command, Output};
+
+mod common;
+
+/// Generate the full script and the arch-chroot script from the sample configuration file, with
+/// the given lines appended
+fn generate(extra_lines: &str) -> (Output, Output)
+{
+    let config = common::config(&[], extra_lines);
+    (common::jimmy(&["--file"], &config), common::jimmy(&["chroot-script"], &config))
+}
+
+/// Return what jimmy complains about when it refuses the configuration file
+fn refusal(extra_lines: &str) -> String
+{
+    common::refusal(generate(extra_lines).0)
+}
+
+const MIRRORLIST: &str = "cat <<'END_OF_FILE' >/etc/pacman.d/mirrorlist
+# the Arch Linux Archive as of 2024-02-29, set by jimmy; the system gets no updates until
+# this file lists current mirrors again
+Server = https://archive.archlinux.org/repos/2024/02/29/$repo/os/$arch
+END_OF_FILE
+";
+
+#[test]
+fn snapshot_mirrorlist_on_the_live_and_installed_systems()
+{
+    let (full, chroot) = generate("snapshot_date: 2024-02-29\n");
+    let stderr = String::from_utf8_lossy(&full.stderr).to_string();
+    assert!(stderr.contains("warning: the installed system gets its packages from the Arch Linux Archive as of 2024-02-29"));
+    let script = common::script(full);
+    let backup = script.find("[ -f /etc/pacman.d/mirrorlist.jimmy-backup ] || cp /etc/pacman.d/mirrorlist /etc/pacman.d/mirrorlist.jimmy-backup\n")
+        .expect("the mirrorlist of the live system isn't saved");
+    let live = script.find(MIRRORLIST).expect("no mirrorlist for the live system");
+    assert!(backup < live && live < script.find("pacstrap /mnt").unwrap());
+    assert!(common::script(chroot).contains(MIRRORLIST));
+}
+
+#[test]
+fn no_snapshot_by_default()
+{
+    let (full, chroot) = generate("");
+    assert!(!common::script(full).contains("archive.archlinux.org"));
+    assert!(!common::script(chroot).contains("IgnorePkg"));
+}
+
+#[test]
+fn invalid_dates_are_refused()
+{
+    for date in ["2023-02-29", "2024-04-31", "2024-13-01", "2024/11/01", "24-11-01"] {
+        let stderr = refusal(&format!("snapshot_date: {}\n", date));
+        assert!(stderr.contains(&format!("invalid snapshot_date: \"{}\" (expected a date such as 2024-11-01)", date)), "{}", stderr);
+    }
+}
+
+#[test]
+fn reflector_and_arm_are_refused()
+{
+    assert!(refusal("snapshot_date: 2024-11-01\nmaintenance:\n  mirrorlist_update: daily\n")
+        .contains("snapshot_date and mirrorlist_update can't be used together"));
+    let arm = refusal("snapshot_date: 2024-11-01\narch: aarch64\n");
+    assert!(arm.contains("snapshot_date and package_pins aren't supported on aarch64"), "{}", arm);
+}
+
+#[test]
+fn pins_are_installed_and_ignored()
+{
+    let (_, chroot) = generate("package_pins:\n  openssl: 3.4.0-1\n  ffmpeg: \"2:6.1-1\"\n");
+    let script = common::script(chroot);
+    assert!(script.contains("\njimmy_pin ffmpeg 2:6.1-1\njimmy_pin openssl 3.4.0-1\n"));
+    assert!(script.contains("\nsed -i 's/^#\\?IgnorePkg *=.*$/IgnorePkg = ffmpeg openssl/' /etc/pacman.conf\n"));
+
+    // the fake pacman only has packages built for any architecture
+    let dir = common::temp_path("pacman");
+    std::fs::create_dir_all(&dir).unwrap();
+    let fake = dir.join("pacman");
+    std::fs::write(&fake, format!(
+        "#!/bin/sh\necho \"$3\" >>{}/calls\ncase \"$3\" in *-any.pkg.tar.zst) exit 0;; *) exit 1;; esac\n",
+        dir.display(),
+    )).unwrap();
+    std::fs::set_permissions(&fake, std::fs::Permissions::from_mode(0o755)).unwrap();
+    let start = script.find("jimmy_pin() {").unwrap();
+    let setup = &script[start..start + script[start..].find("\n}\n").unwrap() + 2];
+    let path = format!("{}:{}", dir.display(), std::env::var("PATH").unwrap_or_default());
+    let status = Command::new("sh")
+        .args(["-c", &format!("{}\njimmy_pin ffmpeg 2:6.1-1", setup)])
+        .env("PATH", path)
+        .status()
+        .unwrap();
+    assert!(status.success());
+    assert_eq!(std::fs::read_to_string(dir.join("calls")).unwrap(), "\
+https://archive.archlinux.org/packages/f/ffmpeg/ffmpeg-2:6.1-1-x86_64.pkg.tar.zst
+https://archive.archlinux.org/packages/f/ffmpeg/ffmpeg-2:6.1-1-any.pkg.tar.zst
+");
+    std::fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn invalid_pins_are_refused()
+{
+    assert!(refusal("package_pins:\n  openssl: \"3.4.0\"\n")
+        .contains("invalid version for package 'openssl' in package_pins: \"3.4.0\""));
+    assert!(refusal("package_pins:\n  Open SSL: 3.4.0-1\n")
+        .contains("invalid package name in package_pins: \"Open SSL\""));
+}
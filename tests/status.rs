@@ -0,0 +1,146 @@
+//! Checks the status the script writes when it exits, however it ended. The code that sets it up
+//! is ran with the status file and /mnt moved into a temporary directory, followed by commands
+//! that end the way a step would, and with a fake `mountpoint`
+
+use std::os::unix::fs::PermissionsExt;
+use std::process::{Command, Output};
+
+mod common;
+
+/// Every field of the status, in the order they're written in
+const FIELDS: &[&str] = &["success", "step", "exit_code", "started_at", "finished_at", "plan", "stderr"];
+
+/// The fields of the plan of the report that are in the plan of the status
+const PLAN_FIELDS: &[&str] = &["jimmy", "config_hash", "hostname", "steps"];
+
+/// Return the script generated from the example with a report
+fn script() -> String
+{
+    common::script(Command::new(env!("CARGO_BIN_EXE_jimmy")).arg("--file").arg("examples/valid--report.yaml").output().unwrap())
+}
+
+/// Return the code that sets up the status, from its comment to the last trap
+fn status_setup(script: &str) -> &str
+{
+    let start = script.find("# write how the installation ended").unwrap();
+    let end = start + script[start..].find("trap 'exit 143' TERM").unwrap();
+    &script[start..end]
+}
+
+/// Run the code that sets up the status followed by `cmds`, with /mnt mounted or not, and return
+/// how the script ended along with the status it wrote and the copy of it on the new system
+fn run(cmds: &str, mounted: bool) -> (Output, serde_json::Value, Option<serde_json::Value>)
+{
+    let dir = common::temp_path("dir");
+    std::fs::create_dir_all(dir.join("bin")).unwrap();
+    let fake = dir.join("bin/mountpoint");
+    std::fs::write(&fake, format!("#!/bin/sh\nexit {}\n", if mounted { 0 } else { 1 })).unwrap();
+    std::fs::set_permissions(&fake, std::fs::Permissions::from_mode(0o755)).unwrap();
+    let code = format!("{}\n{}\n", status_setup(&script()), cmds)
+        .replace("/tmp/jimmy-status.json", &dir.join("jimmy-status.json").display().to_string())
+        .replace("/mnt/", &format!("{}/mnt/", dir.display()));
+    let path = format!("{}:{}", dir.join("bin").display(), std::env::var("PATH").unwrap_or_default());
+    let output = Command::new("sh").args(["-c", &code]).env("PATH", path).output().unwrap();
+    let read = |path: std::path::PathBuf| std::fs::read_to_string(path).ok().map(|s| serde_json::from_str(&s).unwrap());
+    let status = read(dir.join("jimmy-status.json")).expect("no status was written");
+    let copy = read(dir.join("mnt/var/lib/jimmy/jimmy-status.json"));
+    std::fs::remove_dir_all(&dir).unwrap();
+    (output, status, copy)
+}
+
+#[test]
+fn the_trap_writes_every_field()
+{
+    let script = script();
+    let setup = status_setup(&script);
+    for field in FIELDS {
+        assert_eq!(setup.matches(&format!("\"{}\":", field)).count(), 1, "{}", field);
+    }
+    // the status comes before anything that can fail
+    assert!(script.find("# write how the installation ended").unwrap() < script.find("echo '<-> ").unwrap());
+    assert!(setup.contains("\ntrap 'jimmy_status $?' EXIT\n"));
+}
+
+#[test]
+fn every_step_is_named()
+{
+    let script = script();
+    let (_, status, _) = run("true", false);
+    let steps: Vec<&str> = script.lines()
+        .filter_map(|l| l.strip_prefix("JIMMY_STEP="))
+        .filter(|s| !s.is_empty())
+        .map(|s| s.trim_matches('\''))
+        .collect();
+    assert_eq!(serde_json::json!(steps), status["plan"]["steps"]);
+}
+
+#[test]
+fn failure()
+{
+    let (output, status, copy) = run(
+        "JIMMY_STEP='chroot script'\nfor i in $(seq 60); do echo \"line $i\" >&2; done\necho 'error: \"/jimmy_part2.sh\" failed' >&2\nexit 3",
+        true,
+    );
+    assert_eq!(output.status.code(), Some(3));
+    // stderr is still shown while it's saved
+    assert!(String::from_utf8(output.stderr).unwrap().ends_with("line 60\nerror: \"/jimmy_part2.sh\" failed\n"));
+    assert_eq!(status["success"], false);
+    assert_eq!(status["step"], "chroot script");
+    assert_eq!(status["exit_code"], 3);
+    let stderr = status["stderr"].as_array().unwrap();
+    assert_eq!(stderr.len(), 50);
+    assert_eq!(stderr[0], "line 12");
+    assert_eq!(stderr[49], "error: \"/jimmy_part2.sh\" failed");
+    for field in ["started_at", "finished_at"] {
+        let time = status[field].as_str().unwrap();
+        assert!(time.len() == 20 && time.ends_with('Z') && time.as_bytes()[10] == b'T', "{}: {}", field, time);
+    }
+    assert_eq!(copy, Some(status));
+}
+
+#[test]
+fn success()
+{
+    let (output, status, copy) = run("JIMMY_STEP=unmount\necho 'done'", false);
+    assert!(output.status.success());
+    assert_eq!(status["success"], true);
+    assert_eq!(status["step"], "unmount");
+    assert_eq!(status["exit_code"], 0);
+    assert_eq!(status["stderr"], serde_json::json!([]));
+    // /mnt isn't mounted anymore once the installation is done
+    assert_eq!(copy, None);
+
+    let (_, status, _) = run("exit 1", false);
+    assert_eq!(status["step"], serde_json::Value::Null);
+}
+
+#[test]
+fn a_failed_mount_stops_the_script()
+{
+    // the steps that don't stop the script by themselves when they fail would have it go on, and
+    // end as if it succeeded
+    let script = script();
+    let start = script.find("JIMMY_STEP=mounting\n").unwrap();
+    let end = start + script[start..].find("jimmy_time 'mounting'").unwrap();
+    let cmds = format!("mount() {{ echo \"mount: $2: special device $1 does not exist\" >&2; return 32; }}\n{}echo 'unreachable'", &script[start..end]);
+    let (output, status, _) = run(&cmds, false);
+    assert_eq!(output.status.code(), Some(1));
+    assert!(!String::from_utf8(output.stdout).unwrap().contains("unreachable"));
+    assert_eq!(status["success"], false);
+    assert_eq!(status["step"], "mounting");
+    // POSIX sleep only takes whole seconds
+    assert!(!status_setup(&script).contains("sleep 0."));
+}
+
+#[test]
+fn the_plan_is_a_subset_of_the_report()
+{
+    let script = script();
+    let line = script.lines().find(|l| l.trim_start().starts_with("printf '{\"plan\":%s,\"durations\"")).unwrap();
+    let report: serde_json::Value = serde_json::from_str(line.split_once("' '").unwrap().1.trim_end_matches('\'')).unwrap();
+    let (_, status, _) = run("true", false);
+    assert_eq!(status["plan"].as_object().unwrap().len(), PLAN_FIELDS.len());
+    for field in PLAN_FIELDS {
+        assert_eq!(status["plan"][field], report[field], "{}", field);
+    }
+}
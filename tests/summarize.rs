@@ -0,0 +1,67 @@
+//! Checks `jimmy summarize` against the summaries saved in tests/summarize, as plain text and as
+//! Markdown, for the sample configuration file, for a configuration that uses more of jimmy, for
+//! one that sets up what it doesn't use, and for one that reuses a partition
+
+use std::process::Command;
+
+/// Summarize the configuration file at `path`, checking that jimmy succeeded
+fn summarize(path: &std::path::Path, markdown: bool) -> String
+{
+    let mut command = Command::new(env!("CARGO_BIN_EXE_jimmy"));
+    command.arg("summarize").arg(path);
+    if markdown {
+        command.arg("--markdown");
+    }
+    let output = command.output().unwrap();
+    assert!(output.status.success(), "jimmy failed: {}", String::from_utf8_lossy(&output.stderr));
+    String::from_utf8(output.stdout).unwrap()
+}
+
+/// Check the summaries of the configuration file at `path` against tests/summarize/`name`.txt
+/// and tests/summarize/`name`.md
+fn check(path: &std::path::Path, name: &str)
+{
+    for (markdown, extension) in [(false, "txt"), (true, "md")] {
+        let expected = std::fs::read_to_string(format!("tests/summarize/{}.{}", name, extension)).unwrap();
+        assert_eq!(summarize(path, markdown), expected, "{}.{}", name, extension);
+    }
+}
+
+#[test]
+fn sample()
+{
+    let jimmy = env!("CARGO_BIN_EXE_jimmy");
+    let path = std::env::temp_dir().join(format!("jimmy-summarize-{}.yaml", std::process::id()));
+    std::fs::write(&path, Command::new(jimmy).arg("--sample").output().unwrap().stdout).unwrap();
+    check(&path, "sample");
+    std::fs::remove_file(&path).unwrap();
+}
+
+#[test]
+fn complex()
+{
+    check(std::path::Path::new("tests/summarize/complex.yaml"), "complex");
+}
+
+#[test]
+fn unused()
+{
+    check(std::path::Path::new("tests/summarize/unused.yaml"), "unused");
+}
+
+#[test]
+fn reuse()
+{
+    check(std::path::Path::new("tests/summarize/reuse.yaml"), "reuse");
+}
+
+#[test]
+fn invalid()
+{
+    let path = std::env::temp_dir().join(format!("jimmy-summarize-{}-invalid.yaml", std::process::id()));
+    std::fs::write(&path, "hostname: archlinux\nbootloader: lilo\n").unwrap();
+    let output = Command::new(env!("CARGO_BIN_EXE_jimmy")).arg("summarize").arg(&path).output().unwrap();
+    std::fs::remove_file(&path).unwrap();
+    assert!(!output.status.success());
+    assert_eq!(output.stdout, b"");
+}
@@ -0,0 +1,40 @@
+//! Checks what jimmy prints to stderr about the sample configuration file while it generates a
+//! script from it
+
+use std::process::Output;
+
+mod common;
+
+/// Generate a script from the sample configuration file, with the given extra arguments
+fn generate_from_sample(args: &[&str]) -> Output
+{
+    let output = common::generate(&[args, &["--file"]].concat(), &[], "");
+    assert!(output.status.success(), "jimmy failed: {}", String::from_utf8_lossy(&output.stderr));
+    output
+}
+
+#[test]
+fn summary_line()
+{
+    let output = generate_from_sample(&[]);
+    let stderr = String::from_utf8(output.stderr).unwrap();
+    assert_eq!(stderr.lines().last(), Some("2 partitions across 1 disk, 7 packages"));
+}
+
+#[test]
+fn quiet_prints_nothing()
+{
+    let output = generate_from_sample(&["--quiet"]);
+    assert_eq!(String::from_utf8(output.stderr).unwrap(), "");
+}
+
+#[test]
+fn verbose_lists_steps_and_partitions()
+{
+    let output = generate_from_sample(&["--verbose"]);
+    let stderr = String::from_utf8(output.stderr).unwrap();
+    assert!(stderr.contains("2 partitions across 1 disk, 7 packages\n"));
+    assert!(stderr.contains("steps: preflight, previous mounts, clock, partitioning,"));
+    assert!(stderr.contains("    /dev/sda1: fat32, 500M, mounted at /boot\n"));
+    assert!(stderr.contains("    /dev/sda2: ext4, rest of the disk, mounted at /\n"));
+}
@@ -0,0 +1,64 @@
+//! Checks every combination of a swap or mountable format with a mount point or without one, on a
+//! partition added to the sample configuration file
+
+use std::process::Output;
+
+mod common;
+
+/// Generate the script from the sample configuration file, with a partition of the given format
+/// and mount point added after its boot partition
+fn generate(format: &str, mount: Option<&str>, strict: bool) -> Output
+{
+    let partition = format!("    size: 500M\n  - extra:\n    format: {}\n    size: 4G\n{}",
+        format, mount.map(|m| format!("    mount: {}\n", m)).unwrap_or_default());
+    common::generate(&["--file"], &[("    size: 500M\n", &partition)], &format!("strict: {}\n", strict))
+}
+
+const SWAP_MOUNTED: &str = "partition 2 under `partitions` (swap on /dev/sda) has `mount: /swap`, but swap isn't mounted; \
+    remove the mount point, or use a format that can be mounted";
+
+#[test]
+fn swap_with_a_mount_point()
+{
+    let output = generate("swap", Some("/swap"), false);
+    assert!(output.status.success());
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(stderr.contains(&format!("warning: {}; it's going to be ignored", SWAP_MOUNTED)), "{}", stderr);
+    let script = String::from_utf8(output.stdout).unwrap();
+    assert!(script.contains("\ngrep -q \"^$(readlink -f /dev/sda2) \" /proc/swaps || swapon /dev/sda2\n"));
+    assert!(!script.contains("/mnt/swap"));
+
+    let output = generate("swap", Some("/swap"), true);
+    assert!(!output.status.success());
+    assert!(String::from_utf8_lossy(&output.stderr).contains(SWAP_MOUNTED));
+}
+
+#[test]
+fn swap_without_a_mount_point()
+{
+    for mount in [None, Some("\"\"")] {
+        let output = generate("swap", mount, true);
+        assert!(output.status.success(), "jimmy failed: {}", String::from_utf8_lossy(&output.stderr));
+        assert!(!String::from_utf8_lossy(&output.stderr).contains("warning: partition 2"));
+    }
+}
+
+#[test]
+fn mountable_format_with_a_mount_point()
+{
+    let output = generate("ext4", Some("/srv"), true);
+    assert!(output.status.success(), "jimmy failed: {}", String::from_utf8_lossy(&output.stderr));
+    assert!(String::from_utf8(output.stdout).unwrap().contains("\nmountpoint -q /mnt/srv || { mkdir -p /mnt/srv && mount /dev/sda2 /mnt/srv || exit 1; }\n"));
+}
+
+#[test]
+fn mountable_format_without_a_mount_point()
+{
+    let message = "ext4 partition on /dev/sda has no mount point; add `mount:`, or `unmounted: true` if it's meant to be left unmounted";
+    let output = generate("ext4", None, false);
+    assert!(output.status.success(), "jimmy failed: {}", String::from_utf8_lossy(&output.stderr));
+    assert!(String::from_utf8_lossy(&output.stderr).contains(&format!("warning: {}", message)));
+    let output = generate("ext4", None, true);
+    assert!(!output.status.success());
+    assert!(String::from_utf8_lossy(&output.stderr).contains(message));
+}
@@ -0,0 +1,59 @@
+//! Checks how systemd-boot is kept up to date on the EFI system partition with each
+//! `systemd_boot_update`, from the arch-chroot script
+
+use std::process::Output;
+
+mod common;
+
+/// Generate the arch-chroot script from the sample configuration file, with the given bootloader
+/// and lines appended
+fn generate(bootloader: &str, extra_lines: &str) -> Output
+{
+    common::generate(&["chroot-script"], &[("bootloader: grub\n", &format!("bootloader: {}\n", bootloader))], extra_lines)
+}
+
+/// Return the arch-chroot script, checking that it was generated
+fn script(bootloader: &str, extra_lines: &str) -> String
+{
+    common::script(generate(bootloader, extra_lines))
+}
+
+const HOOK: &str = "cat <<'END_OF_FILE' >/etc/pacman.d/hooks/95-systemd-boot.hook\n";
+
+#[test]
+fn service_by_default()
+{
+    let script = script("systemd-boot", "");
+    assert!(script.contains("\nbootctl install --esp-path=/boot\nsystemctl enable systemd-boot-update.service\n"));
+    assert!(!script.contains(HOOK));
+    assert!(self::script("systemd-boot", "systemd_boot_update: service\n")
+        .contains("\nsystemctl enable systemd-boot-update.service\n"));
+}
+
+#[test]
+fn hook()
+{
+    let script = script("systemd-boot", "systemd_boot_update: hook\n");
+    assert!(!script.contains("systemd-boot-update.service"));
+    let start = script.find(HOOK).expect("there's no hook") + HOOK.len();
+    let hook = &script[start..start + script[start..].find("END_OF_FILE\n").unwrap()];
+    assert!(hook.contains("[Trigger]\nType = Package\nOperation = Upgrade\nTarget = systemd\n"), "{}", hook);
+    assert!(hook.contains("\nWhen = PostTransaction\nExec = /usr/bin/bootctl --esp-path=/boot update\n"), "{}", hook);
+    assert!(script[..start].ends_with(&format!("\nmkdir -p /etc/pacman.d/hooks\n{}", HOOK)));
+}
+
+#[test]
+fn only_with_systemd_boot()
+{
+    let output = generate("grub", "systemd_boot_update: hook\n");
+    assert!(output.status.success());
+    assert!(String::from_utf8_lossy(&output.stderr)
+        .contains("systemd_boot_update is only used with `bootloader: systemd-boot`; it's going to be ignored"));
+    let script = String::from_utf8(output.stdout).unwrap();
+    assert!(!script.contains("systemd-boot-update") && !script.contains(HOOK));
+
+    let output = generate("systemd-boot", "systemd_boot_update: pacman\n");
+    assert!(!output.status.success());
+    assert!(String::from_utf8_lossy(&output.stderr)
+        .contains("invalid systemd_boot_update: \"pacman\" (expected one of: service, hook)"));
+}
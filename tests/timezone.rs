@@ -0,0 +1,60 @@
+//! Checks the command that sets the timezone of the installed system, with and without
+//! `timezone` in the configuration file
+
+use std::process::Output;
+
+mod common;
+
+/// Generate the arch-chroot script from the sample configuration file, with its `timezone`
+/// replaced by the given lines
+fn chroot_script(timezone: &str) -> Output
+{
+    common::generate(&["chroot-script"], &[("timezone: Europe/London\n", timezone)], "")
+}
+
+/// Return the line of the script that links /etc/localtime
+fn localtime_cmd(output: &Output) -> String
+{
+    assert!(output.status.success(), "jimmy failed: {}", String::from_utf8_lossy(&output.stderr));
+    String::from_utf8_lossy(&output.stdout).lines()
+        .find(|l| l.ends_with(" /etc/localtime"))
+        .expect("the script doesn't set the timezone")
+        .to_string()
+}
+
+#[test]
+fn region_and_city()
+{
+    let output = chroot_script("timezone: America/Argentina/Buenos_Aires\n");
+    assert_eq!(localtime_cmd(&output), "ln -sf /usr/share/zoneinfo/America/Argentina/Buenos_Aires /etc/localtime");
+}
+
+#[test]
+fn region_only()
+{
+    let output = chroot_script("timezone: Japan\n");
+    assert_eq!(localtime_cmd(&output), "ln -sf /usr/share/zoneinfo/Japan /etc/localtime");
+}
+
+#[test]
+fn no_timezone_defaults_to_utc()
+{
+    let output = chroot_script("");
+    assert_eq!(localtime_cmd(&output), "ln -sf /usr/share/zoneinfo/UTC /etc/localtime");
+    assert!(String::from_utf8_lossy(&output.stderr).contains("warning: timezone not specified; defaulting to 'UTC'"));
+}
+
+#[test]
+fn slashes_are_normalized()
+{
+    let output = chroot_script("timezone: /Europe/London/\n");
+    assert_eq!(localtime_cmd(&output), "ln -sf /usr/share/zoneinfo/Europe/London /etc/localtime");
+}
+
+#[test]
+fn directory_is_refused()
+{
+    let output = chroot_script("timezone: Europe\n");
+    assert!(!output.status.success());
+    assert!(String::from_utf8_lossy(&output.stderr).contains("invalid timezone: \"Europe\""));
+}
@@ -0,0 +1,123 @@
+//! Checks what's found to be set up but unused once the whole installation is planned: partitions
+//! that are formatted but never mounted, declared disks without partitions, and partitions of the
+//! EFI system type that the bootloader isn't installed on. They're warnings, or errors with
+//! `strict: true`, and they're listed in the plan, the summary and the header of the script
+
+use std::process::Output;
+
+mod common;
+
+/// Return the sample configuration file, with the given partitions added after its boot
+/// partition and the given lines appended
+fn config(partitions: &str, extra_lines: &str) -> String
+{
+    common::config(&[("    size: 500M\n", &format!("    size: 500M\n{}", partitions))], extra_lines)
+}
+
+/// Run jimmy with `args` on the sample configuration file, changed as `config` does
+fn generate(args: &[&str], partitions: &str, extra_lines: &str) -> Output
+{
+    common::jimmy(args, &config(partitions, extra_lines))
+}
+
+/// Return the warnings jimmy gives while generating the script, checking that it succeeded
+fn warnings(partitions: &str, extra_lines: &str) -> Vec<String>
+{
+    let output = generate(&["--file"], partitions, extra_lines);
+    assert!(output.status.success(), "jimmy failed: {}", String::from_utf8_lossy(&output.stderr));
+    String::from_utf8(output.stderr).unwrap().lines()
+        .filter_map(|l| l.strip_prefix("warning: "))
+        .map(String::from)
+        .collect()
+}
+
+const UNMOUNTED: &str = "  - data:\n    format: xfs\n    size: 8G\n    disk: /dev/sdb\n";
+const UNMOUNTED_FOUND: &str = "xfs partition on /dev/sdb has no mount point; add `mount:`, or `unmounted: true` if it's meant to be left unmounted";
+const EMPTY_DISK: &str = "disks:\n  /dev/sdc:\n    size: 64GB\n";
+const EMPTY_DISK_FOUND: &str = "disk /dev/sdc is declared under `disks`, but there are no partitions on it; remove it, or fix the `disk` of its partitions";
+const SHARED: &str = "  - shared:\n    format: fat32\n    mount: /shared\n    size: 2G\n";
+const SHARED_FOUND: &str = "the fat32 partition mounted at /shared has the type of an EFI system partition, which the firmware may boot from, \
+    but the bootloader isn't installed on it; give it another `type_guid`, or mark it `esp: true` without a mount point to keep it in sync with the primary one";
+
+#[test]
+fn partitions()
+{
+    assert_eq!(warnings(UNMOUNTED, ""), [UNMOUNTED_FOUND]);
+    // left unmounted on purpose, swap, kept in sync with the primary EFI system partition, or
+    // formatted along with the filesystem they're a member of
+    for (partitions, name) in [
+        ("  - data:\n    format: xfs\n    size: 8G\n    disk: /dev/sdb\n    unmounted: true\n", "marked"),
+        ("  - swap:\n    format: swap\n    size: 4G\n", "swap"),
+        ("  - mirror:\n    format: fat32\n    esp: true\n    size: 500M\n    disk: /dev/sdb\n", "secondary"),
+        ("  - pool:\n    format: btrfs\n    mount: /srv\n    size: 8G\n    raid_profile: raid1\n    members:\n      - disk: /dev/sdb\n        size: 8G\n", "member"),
+    ] {
+        let found = warnings(partitions, "");
+        assert!(!found.iter().any(|w| w.contains("has no mount point")), "{}: {:?}", name, found);
+    }
+}
+
+#[test]
+fn efi_system_partitions()
+{
+    assert_eq!(warnings(SHARED, ""), [SHARED_FOUND]);
+    let found = warnings("  - shared:\n    format: fat32\n    size: 2G\n    disk: /dev/sdb\n    unmounted: true\n", "");
+    assert!(found.iter().any(|w| w.starts_with("the fat32 partition on /dev/sdb has the type of an EFI system partition")), "{:?}", found);
+    // another type, or the kernels of systemd-boot next to an EFI system partition mounted at /efi
+    for (partitions, extra_lines, name) in [
+        ("  - shared:\n    format: fat32\n    mount: /shared\n    size: 2G\n    type_guid: linux\n", "", "typed"),
+        ("  - kernels:\n    format: fat32\n    mount: /efi\n    size: 500M\n", "bootloader: systemd-boot\n", "xbootldr"),
+    ] {
+        let found = warnings(partitions, extra_lines);
+        assert!(!found.iter().any(|w| w.contains("EFI system partition")), "{}: {:?}", name, found);
+    }
+}
+
+#[test]
+fn strict()
+{
+    for (partitions, extra_lines, found, name) in [
+        (UNMOUNTED, "", UNMOUNTED_FOUND, "unmounted"),
+        ("", EMPTY_DISK, EMPTY_DISK_FOUND, "disk"),
+        (SHARED, "", SHARED_FOUND, "shared"),
+    ] {
+        let output = generate(&["--file"], partitions, &format!("{}strict: true\n", extra_lines));
+        assert!(!output.status.success(), "{}", name);
+        assert!(String::from_utf8_lossy(&output.stderr).contains(found), "{}", name);
+    }
+}
+
+#[test]
+fn plan()
+{
+    let response = common::api_plan(&config(&format!("{}{}", UNMOUNTED, SHARED), EMPTY_DISK));
+    assert_eq!(response["valid"], true);
+    assert_eq!(response["plan"]["unused"], serde_json::json!({
+        "partitions": [UNMOUNTED_FOUND],
+        "disks": [EMPTY_DISK_FOUND],
+        "esps": [SHARED_FOUND],
+    }));
+    assert_eq!(response["warnings"].as_array().unwrap().len(), 3);
+    assert_eq!(common::api_plan(&config("", ""))["plan"]["unused"], serde_json::json!({ "partitions": [], "disks": [], "esps": [] }));
+}
+
+#[test]
+fn header()
+{
+    for args in [["--file"], ["chroot-script"]] {
+        let output = generate(&args, &format!("{}{}", UNMOUNTED, SHARED), EMPTY_DISK);
+        let script = String::from_utf8(output.stdout).unwrap();
+        let header: Vec<&str> = script.lines().take_while(|l| !l.is_empty()).collect();
+        let start = header.iter().position(|l| *l == "# unused partitions:").expect("no unused partitions in the header");
+        assert_eq!(header[start..start + 6], [
+            "# unused partitions:".to_string(),
+            format!("#   {}", UNMOUNTED_FOUND),
+            "# unused disks:".to_string(),
+            format!("#   {}", EMPTY_DISK_FOUND),
+            "# unused EFI system partitions:".to_string(),
+            format!("#   {}", SHARED_FOUND),
+        ]);
+
+        let output = generate(&args, "", "");
+        assert!(!String::from_utf8(output.stdout).unwrap().contains("\n# unused "));
+    }
+}
@@ -0,0 +1,83 @@
+//! Checks `linger` and `user_services`: the first-boot script that turns them on, where it goes
+//! among the other first-boot scripts, and the users that can't have them
+
+use std::process::Output;
+
+mod common;
+
+/// Generate the script from the sample configuration file, with its users replaced by `users`
+/// and the given lines appended
+fn generate(users: &str, extra_lines: &str) -> Output
+{
+    let sample = common::sample();
+    let start = sample.find("users:\n").unwrap();
+    let end = start + sample[start..].find("\n\n").unwrap() + 1;
+    common::generate(&["--file"], &[(&sample[start..end], users)], extra_lines)
+}
+
+/// Return the script, checking that jimmy succeeded
+fn generated(users: &str, extra_lines: &str) -> String
+{
+    common::script(generate(users, extra_lines))
+}
+
+/// Return the paths of the first-boot scripts, in the order they run in
+fn first_boot_scripts(script: &str) -> Vec<&str>
+{
+    script.lines()
+        .filter_map(|l| l.strip_prefix("cat <<'END_OF_FILE' >/usr/local/lib/jimmy/firstboot.d/"))
+        .collect()
+}
+
+const USERS: &str = "users:\n\
+    \x20 - containers:\n    name: archie\n    groups: [ wheel ]\n    linger: true\n    user_services: podman.socket, podman-auto-update.timer\n\
+    \x20 - sync:\n    name: eihcra\n    user_services: [ syncthing.service ]\n\
+    \x20 - plain:\n    name: guest\n";
+
+#[test]
+fn first_boot_script()
+{
+    let script = generated(USERS, "");
+    assert_eq!(first_boot_scripts(&script), ["10-user-services.sh"]);
+    assert!(script.contains("\nset -e\n\n\
+        loginctl enable-linger archie\n\
+        runuser -u archie -- env SYSTEMCTL_INSTALL_CLIENT_SIDE=1 systemctl --user enable podman.socket podman-auto-update.timer\n\
+        runuser -u eihcra -- env SYSTEMCTL_INSTALL_CLIENT_SIDE=1 systemctl --user enable syncthing.service\n\
+        END_OF_FILE\n"));
+    assert!(script.contains("\nWants=systemd-logind.service\nAfter=systemd-logind.service\n"));
+    // the script goes in the configuration of the installed system, after the users are created
+    let users = script.find("useradd -m").unwrap();
+    assert!(script[users..].contains("10-user-services.sh"));
+    assert!(!script[..users].contains("10-user-services.sh"));
+}
+
+#[test]
+fn ordering()
+{
+    let users = format!("{}  - homed:\n    name: encrypted\n    home_encryption: true\n", USERS);
+    let script = generated(&users, "first_boot:\n  - timedatectl set-ntp true\n");
+    // after the users with encrypted home directories are created, and before the commands of
+    // the configuration file
+    assert_eq!(first_boot_scripts(&script), ["10-homed.sh", "20-user-services.sh", "30-custom.sh"]);
+}
+
+#[test]
+fn nothing_to_do()
+{
+    let script = generated("users:\n  - plain:\n    name: archie\n    linger: false\n    user_services: []\n", "");
+    assert!(!script.contains("firstboot"));
+}
+
+#[test]
+fn invalid()
+{
+    let output = generate("users:\n  - a:\n    name: archie\n    user_services: [ podman ]\n", "");
+    assert!(!output.status.success());
+    assert!(String::from_utf8_lossy(&output.stderr).contains(
+        "invalid user_services of user 'archie': \"podman\" (expected the name of a unit, such as podman.socket or backup.timer)"));
+
+    let output = generate("users:\n  - a:\n    name: archie\n    home_encryption: true\n    linger: true\n", "");
+    assert!(!output.status.success());
+    assert!(String::from_utf8_lossy(&output.stderr).contains(
+        "user 'archie' has `linger` or `user_services`, but their home directory is encrypted, and systemd-homed only unlocks it when they log in"));
+}
@@ -0,0 +1,136 @@
+//! Checks how the long commands of the scripts are wrapped: the exact lines of pacstrap, sed and
+//! efibootmgr at a few widths, that quoted values are never split, and that the shell gives the
+//! wrapped commands the same arguments as the ones on a single line, which is checked by running
+//! them with sh and the programs replaced by one that prints its arguments
+
+use std::os::unix::fs::PermissionsExt;
+use std::process::{Command, Output};
+
+mod common;
+
+/// Run jimmy with `args` on the sample configuration file, with the given lines appended
+fn generate(args: &[&str], extra_lines: &str) -> Output
+{
+    common::generate(args, &[], extra_lines)
+}
+
+/// Return the script, checking that jimmy succeeded
+fn generated(args: &[&str], extra_lines: &str) -> String
+{
+    common::script(generate(args, extra_lines))
+}
+
+/// Return the command of the script that starts with `program`, along with its continuation lines
+fn command<'a>(script: &'a str, program: &str) -> &'a str
+{
+    let start = script.find(&format!("\n{} ", program)).unwrap() + 1;
+    let mut end = start;
+    for line in script[start..].split_inclusive('\n') {
+        end += line.len();
+        if !line.ends_with(" \\\n") {
+            break;
+        }
+    }
+    script[start..end].trim_end()
+}
+
+/// Run `cmd` with sh, with `program` printing each of its arguments on a line, and return them
+fn arguments(cmd: &str, program: &str) -> Vec<String>
+{
+    let dir = common::temp_path("dir");
+    std::fs::create_dir_all(&dir).unwrap();
+    std::fs::write(dir.join(program), "#!/bin/sh\nprintf '%s\\n' \"$@\"\n").unwrap();
+    std::fs::set_permissions(dir.join(program), std::fs::Permissions::from_mode(0o755)).unwrap();
+    let output = Command::new("sh").args(["-c", cmd])
+        .env("PATH", format!("{}:{}", dir.display(), std::env::var("PATH").unwrap()))
+        .output()
+        .unwrap();
+    std::fs::remove_dir_all(&dir).unwrap();
+    assert!(output.status.success());
+    String::from_utf8(output.stdout).unwrap().lines().map(String::from).collect()
+}
+
+const PACKAGES: &str = "retries: 0\npackages: [ vim, git, base-devel, man-db, man-pages, openssh, htop, firefox, ttf-dejavu ]\n";
+
+const LOCALES: &str = "locales: [ en_US.UTF-8, de_DE.UTF-8, fr_FR.UTF-8, sr_RS.UTF-8@latin ]\n";
+
+const EFISTUB: &str = "bootloader: efistub\nkernel_params: [ quiet, splash, \"rd.luks.options=timeout=10s\", loglevel=3 ]\n\
+    boot_entry_label: Arch Linux (it's mine)\n";
+
+#[test]
+fn pacstrap()
+{
+    let script = generated(&["--width", "60", "--file"], PACKAGES);
+    assert_eq!(command(&script, "pacstrap"), "pacstrap /mnt base linux linux-firmware vim git base-devel \\\n    \
+        man-db man-pages openssh htop firefox ttf-dejavu grub \\\n    \
+        efibootmgr networkmanager");
+    assert!(command(&script, "pacstrap").lines().all(|l| l.len() <= 60));
+
+    // the default width
+    let script = generated(&["--file"], PACKAGES);
+    assert_eq!(command(&script, "pacstrap"), "pacstrap /mnt base linux linux-firmware vim git base-devel man-db man-pages \\\n    \
+        openssh htop firefox ttf-dejavu grub efibootmgr networkmanager");
+
+    // inside the loop that retries it, the command is indented along with the loop
+    let script = generated(&["--file"], &PACKAGES.replace("retries: 0\n", ""));
+    assert!(script.contains("\n    { pacstrap /mnt $jimmy_needed base linux linux-firmware vim git base-devel \\\n        \
+        man-db man-pages openssh htop firefox ttf-dejavu grub efibootmgr \\\n        \
+        networkmanager 2>&1; "));
+}
+
+#[test]
+fn locales()
+{
+    let script = generated(&["chroot-script", "--width", "60"], LOCALES);
+    assert_eq!(command(&script, "sed"), "sed --expression 's/^#en_US\\.UTF-8 /en_US.UTF-8 /' \\\n    \
+        --expression 's/^#de_DE\\.UTF-8 /de_DE.UTF-8 /' \\\n    \
+        --expression 's/^#fr_FR\\.UTF-8 /fr_FR.UTF-8 /' \\\n    \
+        --expression 's/^#sr_RS\\.UTF-8@latin /sr_RS.UTF-8@latin /' \\\n    \
+        --in-place /etc/locale.gen");
+    // a single locale fits on a line
+    assert_eq!(command(&generated(&["chroot-script"], ""), "sed"), "sed --expression 's/^#en_US\\.UTF-8 /en_US.UTF-8 /' --in-place /etc/locale.gen");
+}
+
+#[test]
+fn efibootmgr()
+{
+    let script = generated(&["chroot-script"], EFISTUB);
+    assert_eq!(command(&script, "efibootmgr"), "efibootmgr --disk /dev/sda --part 1 --create \\\n    \
+        --label 'Arch Linux (it'\\''s mine)' --loader /vmlinuz-linux \\\n    \
+        --unicode 'root=/dev/sda2 quiet splash rd.luks.options=timeout=10s loglevel=3 rw initrd=\\initramfs-linux.img' \\\n    \
+        --verbose");
+    // the value that doesn't fit in 40 columns gets a line of its own, without being split
+    let script = generated(&["chroot-script", "--width", "40"], EFISTUB);
+    assert_eq!(command(&script, "efibootmgr"), "efibootmgr --disk /dev/sda --part 1 \\\n    \
+        --create \\\n    \
+        --label 'Arch Linux (it'\\''s mine)' \\\n    \
+        --loader /vmlinuz-linux \\\n    \
+        --unicode 'root=/dev/sda2 quiet splash rd.luks.options=timeout=10s loglevel=3 rw initrd=\\initramfs-linux.img' \\\n    \
+        --verbose");
+}
+
+#[test]
+fn same_arguments()
+{
+    for (command_args, lines, program) in [(&["--file"][..], PACKAGES, "pacstrap"), (&["chroot-script"], LOCALES, "sed"), (&["chroot-script"], EFISTUB, "efibootmgr")] {
+        let unwrapped = generated(&common::with_flags(&["--width", "0"], command_args), lines);
+        let unwrapped = command(&unwrapped, program);
+        assert!(!unwrapped.contains('\n'));
+        let expected = arguments(unwrapped, program);
+        for width in ["20", "40", "60", "80", "100"] {
+            let wrapped = generated(&common::with_flags(&["--width", width], command_args), lines);
+            assert_eq!(arguments(command(&wrapped, program), program), expected, "{} at {}", program, width);
+        }
+    }
+    assert!(arguments(command(&generated(&["chroot-script"], EFISTUB), "efibootmgr"), "efibootmgr")
+        .contains(&"Arch Linux (it's mine)".to_string()));
+}
+
+#[test]
+fn invalid_width()
+{
+    let output = generate(&["--width", "wide", "--file"], "");
+    assert!(!output.status.success());
+    assert!(String::from_utf8_lossy(&output.stderr)
+        .contains("error: invalid --width: \"wide\" (expected a number of columns, or 0 not to wrap the commands)"));
+}
@@ -0,0 +1,86 @@
+//! Checks systemd-boot with the EFI system partition mounted at /efi and an XBOOTLDR partition at
+//! /boot: the type of the latter, the paths bootctl is given, and where the boot entry and the
+//! loader configuration are written and verified
+
+mod common;
+
+/// The EFI system partition of the sample mounted at /efi, followed by an XBOOTLDR partition of
+/// the given format mounted at /boot
+fn split(format: &str) -> String
+{
+    format!("    format: fat32\n    mount: /efi\n    size: 500M\n  - kernels:\n    format: {}\n    mount: /boot\n    size: 1G\n", format)
+}
+
+/// Generate a script of the sample configuration file booted with systemd-boot, with the sample's
+/// boot partition replaced by `boot` and the given lines appended
+fn generate(args: &[&str], boot: &str, extra_lines: &str) -> std::process::Output
+{
+    common::generate(args, &[
+        ("bootloader: grub\n", "bootloader: systemd-boot\n"),
+        ("    format: fat32\n    mount: /boot\n    size: 500M\n", boot),
+    ], extra_lines)
+}
+
+/// Return the lines of the chroot script that set up the bootloader
+fn bootloader_lines(boot: &str, extra_lines: &str) -> Vec<String>
+{
+    let script = common::script(generate(&["chroot-script"], boot, extra_lines));
+    script.lines()
+        .skip_while(|l| !l.ends_with("setting up bootloader...'"))
+        .skip(1)
+        .take_while(|l| !l.is_empty())
+        .map(String::from)
+        .collect()
+}
+
+#[test]
+fn entries_on_the_xbootldr_partition()
+{
+    assert_eq!(bootloader_lines(&split("fat32"), ""), [
+        "bootctl install --esp-path=/efi --boot-path=/boot",
+        "systemctl enable systemd-boot-update.service",
+        "cat <<'END_OF_FILE' >/efi/loader/loader.conf",
+        "default arch.conf",
+        "END_OF_FILE",
+        "mkdir -p /boot/loader/entries",
+        "cat <<'END_OF_FILE' >/boot/loader/entries/arch.conf",
+        "title Arch Linux",
+        "linux /vmlinuz-linux",
+        "initrd /initramfs-linux.img",
+        "options root=/dev/sda3 rw",
+        "END_OF_FILE",
+    ]);
+
+    let script = common::script(generate(&["--file"], &split("fat32"), ""));
+    assert!(script.contains("\\nt\\n2\\nBC13C2FF-59E6-4262-A352-B275FD6F7172\\n"));
+    assert!(script.contains("\njimmy_verify 'boot entry /boot/loader/entries/arch.conf' 'test -f /mnt/boot/loader/entries/arch.conf'\n"));
+    // pacman puts the kernels on /boot by itself, so nothing copies them
+    assert!(!script.contains("/efi/vmlinuz") && !script.contains("sync-esps"));
+}
+
+#[test]
+fn hook()
+{
+    let script = common::script(generate(&["chroot-script"], &split("fat32"), "systemd_boot_update: hook\n"));
+    assert!(script.contains("\nExec = /usr/bin/bootctl --esp-path=/efi --boot-path=/boot update\n"), "{}", script);
+}
+
+#[test]
+fn esp_only()
+{
+    let lines = bootloader_lines("    format: fat32\n    mount: /boot\n    size: 500M\n", "");
+    assert_eq!(lines[0], "bootctl install --esp-path=/boot");
+    assert!(lines.contains(&"cat <<'END_OF_FILE' >/boot/loader/loader.conf".to_string()));
+    assert!(lines.contains(&"cat <<'END_OF_FILE' >/boot/loader/entries/arch.conf".to_string()));
+}
+
+#[test]
+fn invalid()
+{
+    let stderr = common::refusal(generate(&["--file"], "    format: fat32\n    mount: /efi\n    size: 500M\n", ""));
+    assert!(stderr.contains("systemd-boot with the EFI system partition mounted at /efi requires an XBOOTLDR partition for the kernels; \
+        add a `fat32` partition with `mount: /boot`"), "{}", stderr);
+    let stderr = common::refusal(generate(&["--file"], &split("ext4"), ""));
+    assert!(stderr.contains("systemd-boot requires the XBOOTLDR partition mounted at /boot to be readable by the firmware; \
+        change its `format` from 'ext4' to 'fat32'"), "{}", stderr);
+}